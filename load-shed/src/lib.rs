@@ -0,0 +1,287 @@
+//! Load measurements shared by the services' load-shedding middleware.
+//!
+//! Tracks the number of in-flight requests and an exponentially weighted moving
+//! average of request latency. When either crosses its configured threshold the
+//! service is considered saturated, and low-priority requests should be
+//! rejected instead of queueing behind interactive traffic. The average decays
+//! towards zero while no request completes, so a service that stopped
+//! admitting requests because it was slow recovers once the slowness is past.
+//!
+//! Clients mark their requests with the `X-Priority` header:
+//!
+//! - `high` / `interactive` - never shed
+//! - `normal` (or no header) - never shed
+//! - `low` / `batch` - shed while the service is saturated
+//!
+//! The HTTP side, turning a shed request into a response, lives in each
+//! service's `load_shed` module.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Header clients use to declare the priority of a request.
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+/// Weight of the newest sample in the latency moving average.
+const EWMA_ALPHA: f64 = 0.2;
+
+/// Time without completed requests over which the latency average halves.
+const EWMA_HALF_LIFE: Duration = Duration::from_secs(10);
+
+/// Priority of an incoming request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Batch or background work that may be shed under load.
+    Low,
+    /// Default priority for requests without a priority header.
+    Normal,
+    /// Latency-sensitive interactive traffic.
+    High,
+}
+
+impl Priority {
+    /// Parses a priority header value, falling back to `Normal` for unknown values.
+    pub fn from_header(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            Some("low") | Some("batch") => Priority::Low,
+            Some("high") | Some("interactive") => Priority::High,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// Thresholds controlling when the service counts as saturated.
+#[derive(Debug, Clone)]
+pub struct LoadShedConfig {
+    /// In-flight request count at which the service is saturated.
+    pub max_in_flight: usize,
+    /// Average latency above which the service is saturated.
+    pub latency_threshold: Duration,
+    /// Value sent in the `Retry-After` header of shed responses, in seconds.
+    pub retry_after_secs: u64,
+}
+
+impl LoadShedConfig {
+    /// Loads thresholds from the environment.
+    ///
+    /// # Environment Variables
+    ///
+    /// * `LOAD_SHED_MAX_IN_FLIGHT` - In-flight requests before shedding (default: 128)
+    /// * `LOAD_SHED_LATENCY_MS` - Average latency before shedding (default: 10000)
+    /// * `LOAD_SHED_RETRY_AFTER_SECS` - `Retry-After` value for shed requests (default: 5)
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_in_flight: var("LOAD_SHED_MAX_IN_FLIGHT", 128) as usize,
+            latency_threshold: Duration::from_millis(var("LOAD_SHED_LATENCY_MS", 10_000)),
+            retry_after_secs: var("LOAD_SHED_RETRY_AFTER_SECS", 5),
+        }
+    }
+}
+
+/// Latency moving average and when it was last updated.
+#[derive(Debug, Clone, Copy)]
+struct Ewma {
+    micros: f64,
+    at: Instant,
+}
+
+impl Ewma {
+    /// The average as of `now`, decayed for the time since the last sample.
+    fn at(&self, now: Instant) -> f64 {
+        let idle = now.saturating_duration_since(self.at);
+        self.micros * 0.5f64.powf(idle.as_secs_f64() / EWMA_HALF_LIFE.as_secs_f64())
+    }
+}
+
+/// Shared load measurements used by the middleware.
+#[derive(Debug)]
+pub struct LoadShedder {
+    /// Thresholds, swappable at runtime by a config reload.
+    config: RwLock<LoadShedConfig>,
+    in_flight: AtomicUsize,
+    /// `None` until the first request completes.
+    latency: Mutex<Option<Ewma>>,
+}
+
+impl LoadShedder {
+    /// Creates a shedder with no recorded load.
+    pub fn new(config: LoadShedConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            in_flight: AtomicUsize::new(0),
+            latency: Mutex::new(None),
+        }
+    }
+
+    /// The thresholds currently in effect.
+    pub fn config(&self) -> LoadShedConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replaces the thresholds; in-flight requests and load measurements are kept.
+    pub fn set_config(&self, config: LoadShedConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Number of requests currently being processed.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Moving average of recent request latency.
+    pub fn average_latency(&self) -> Duration {
+        self.average_latency_at(Instant::now())
+    }
+
+    /// Whether the service is currently over either threshold.
+    pub fn is_saturated(&self) -> bool {
+        self.is_saturated_at(Instant::now())
+    }
+
+    /// Whether a request with the given priority should be rejected right now.
+    pub fn should_shed(&self, priority: Priority) -> bool {
+        priority == Priority::Low && self.is_saturated()
+    }
+
+    /// Runs an admitted request, counting it as in flight until it finishes or
+    /// is dropped, and recording its latency once it finishes.
+    pub async fn track<F: Future>(&self, request: F) -> F::Output {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let _guard = InFlightGuard(&self.in_flight);
+        let start = Instant::now();
+        let output = request.await;
+        self.record_latency(start.elapsed(), Instant::now());
+        output
+    }
+
+    fn average_latency_at(&self, now: Instant) -> Duration {
+        let average = self
+            .latency
+            .lock()
+            .unwrap()
+            .map_or(0.0, |ewma| ewma.at(now));
+        Duration::from_micros(average as u64)
+    }
+
+    fn is_saturated_at(&self, now: Instant) -> bool {
+        let config = self.config.read().unwrap();
+        self.in_flight() >= config.max_in_flight
+            || self.average_latency_at(now) > config.latency_threshold
+    }
+
+    fn record_latency(&self, latency: Duration, now: Instant) {
+        let sample = latency.as_micros() as f64;
+        let mut average = self.latency.lock().unwrap();
+        let micros = match *average {
+            Some(ewma) => ewma.at(now) * (1.0 - EWMA_ALPHA) + sample * EWMA_ALPHA,
+            None => sample,
+        };
+        *average = Some(Ewma { micros, at: now });
+    }
+}
+
+/// Decrements the in-flight counter even if the request future is dropped.
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shedder(max_in_flight: usize) -> LoadShedder {
+        LoadShedder::new(LoadShedConfig {
+            max_in_flight,
+            latency_threshold: Duration::from_millis(500),
+            retry_after_secs: 1,
+        })
+    }
+
+    #[test]
+    fn parses_priority_header() {
+        assert_eq!(Priority::from_header(Some("batch")), Priority::Low);
+        assert_eq!(Priority::from_header(Some(" Interactive ")), Priority::High);
+        assert_eq!(Priority::from_header(Some("whatever")), Priority::Normal);
+        assert_eq!(Priority::from_header(None), Priority::Normal);
+    }
+
+    #[test]
+    fn sheds_only_low_priority_when_saturated() {
+        let s = shedder(2);
+        assert!(!s.should_shed(Priority::Low));
+
+        s.in_flight.store(2, Ordering::Relaxed);
+        assert!(s.should_shed(Priority::Low));
+        assert!(!s.should_shed(Priority::Normal));
+        assert!(!s.should_shed(Priority::High));
+    }
+
+    #[test]
+    fn slow_responses_saturate() {
+        let s = shedder(100);
+        let now = Instant::now();
+        s.record_latency(Duration::from_secs(2), now);
+        assert!(s.is_saturated_at(now));
+        for _ in 0..20 {
+            s.record_latency(Duration::from_millis(10), now);
+        }
+        assert!(!s.is_saturated_at(now));
+    }
+
+    #[test]
+    fn saturation_wears_off_without_traffic() {
+        // Only low-priority traffic arrives and all of it is shed, so no new
+        // samples come in; the slow spike must not shed it forever.
+        let s = shedder(100);
+        let now = Instant::now();
+        s.record_latency(Duration::from_secs(4), now);
+        assert!(s.is_saturated_at(now));
+        assert!(s.is_saturated_at(now + EWMA_HALF_LIFE));
+        assert!(!s.is_saturated_at(now + EWMA_HALF_LIFE * 4));
+
+        // A fast request after the pause blends with the decayed average.
+        s.record_latency(Duration::from_millis(10), now + EWMA_HALF_LIFE * 4);
+        assert!(s.average_latency_at(now + EWMA_HALF_LIFE * 4) < Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn tracks_requests_while_in_flight() {
+        let s = shedder(1);
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let request = s.track(async {
+            rx.await.unwrap();
+            7
+        });
+        tokio::pin!(request);
+        assert!(poll_once(&mut request).is_none());
+        assert_eq!(s.in_flight(), 1);
+        assert!(s.should_shed(Priority::Low));
+
+        tx.send(()).unwrap();
+        assert_eq!(request.await, 7);
+        assert_eq!(s.in_flight(), 0);
+        assert!(!s.should_shed(Priority::Low));
+    }
+
+    /// Polls `future` once.
+    fn poll_once<F: Future + Unpin>(future: &mut F) -> Option<F::Output> {
+        let waker = std::task::Waker::noop();
+        let mut cx = std::task::Context::from_waker(waker);
+        match std::pin::Pin::new(future).poll(&mut cx) {
+            std::task::Poll::Ready(output) => Some(output),
+            std::task::Poll::Pending => None,
+        }
+    }
+}
@@ -22,13 +22,15 @@ use crate::models::{
 };
 use axum::{
     Json,
-    extract::{Multipart, State},
+    extract::{Multipart, State, multipart::Field},
     http::StatusCode,
 };
-use std::path::PathBuf;
+use futures_util::StreamExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
 /// Retrieves a list of all available AI agents from the MCP server.
@@ -231,80 +233,7 @@ pub async fn handle_text_input(
 
     tracing::info!("Calling ElevenLabs TTS API for agent's reply");
 
-    // ElevenLabs TTS API - using default voice "Rachel" (21m00Tcm4TlvDq8ikWAM)
-    let tts_url = "https://api.elevenlabs.io/v1/text-to-speech/21m00Tcm4TlvDq8ikWAM";
-    
-    let tts_payload = serde_json::json!({
-        "text": agent_reply_text,
-        "model_id": "eleven_multilingual_v2",
-        "output_format": "mp3_44100_128"
-    });
-
-    let tts_response = state
-        .http_client
-        .post(tts_url)
-        .header("xi-api-key", &state.elevenlabs_api_key)
-        .header("Content-Type", "application/json")
-        .json(&tts_payload)
-        .send()
-        .await;
-
-    let audio_bytes = match tts_response {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.bytes().await {
-                    Ok(bytes) => bytes.to_vec(),
-                    Err(e) => {
-                        tracing::error!("Failed to read TTS audio bytes: {:?}", e);
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json("Failed to read TTS audio".to_string()),
-                        ));
-                    }
-                }
-            } else {
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                tracing::error!("ElevenLabs TTS API error {}: {}", status, error_text);
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(format!("Error from TTS service: {}", error_text)),
-                ));
-            }
-        }
-        Err(e) => {
-            tracing::error!("Failed to call ElevenLabs TTS API: {:?}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json("Failed to call TTS service".to_string()),
-            ));
-        }
-    };
-
-    let filename = format!("{}.mp3", Uuid::new_v4());
-    let filepath = PathBuf::from(&state.audio_dir).join(&filename);
-
-    match File::create(&filepath).await {
-        Ok(mut file) => {
-            if let Err(e) = file.write_all(&audio_bytes).await {
-                tracing::error!("Failed to write audio file: {:?}", e);
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json("Failed to save audio file".to_string()),
-                ));
-            }
-        }
-        Err(e) => {
-            tracing::error!("Failed to create audio file: {:?}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json("Failed to create audio file".to_string()),
-            ));
-        }
-    }
-
-    let audio_url = format!("/public/audio/{}", filename);
-    tracing::info!("Audio saved to: {}", audio_url);
+    let audio_url = synthesize_to_file(&state, &agent_reply_text).await?;
 
     let final_reply = AgentReplyResponse {
         reply_text: agent_reply_text,
@@ -371,22 +300,44 @@ pub async fn handle_audio_input(
 ) -> Result<(StatusCode, Json<AgentReplyResponse>), (StatusCode, Json<String>)> {
     tracing::info!("Handler called: handle_audio_input (REAL)");
 
-    let mut audio_data: Option<Vec<u8>> = None;
+    let mut audio_upload: Option<SpooledUpload> = None;
     let mut agent_id: Option<String> = None;
     let mut filename: Option<String> = None;
-    
-    while let Some(field) = multipart.next_field().await.unwrap() {
+    let mut content_type: Option<String> = None;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!("Failed to read multipart body: {:?}", e);
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json("Malformed multipart body".to_string()),
+                ));
+            }
+        };
         let name = field.name().unwrap_or("unknown").to_string();
         if name == "audio_file" {
             filename = field.file_name().map(|s| s.to_string());
-            audio_data = Some(field.bytes().await.unwrap().to_vec());
+            content_type = field.content_type().map(|s| s.to_string());
+            audio_upload = Some(spool_field(&state, field).await?);
         } else if name == "agent_id" {
-            agent_id = Some(field.text().await.unwrap());
+            agent_id = match field.text().await {
+                Ok(text) => Some(text),
+                Err(e) => {
+                    tracing::error!("Failed to read agent_id field: {:?}", e);
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        Json("Malformed 'agent_id' field".to_string()),
+                    ));
+                }
+            };
         }
     }
-    
-    let (audio_data, agent_id) = match (audio_data, agent_id) {
-        (Some(data), Some(id)) => (data, id),
+
+    let (audio_upload, agent_id) = match (audio_upload, agent_id) {
+        (Some(upload), Some(id)) => (upload, id),
         _ => {
             return Err((
                 StatusCode::BAD_REQUEST,
@@ -394,7 +345,11 @@ pub async fn handle_audio_input(
             ));
         }
     };
-    tracing::info!("Got agent_id: {} and audio file", agent_id);
+    tracing::info!(
+        "Got agent_id: {} and audio file ({} bytes)",
+        agent_id,
+        audio_upload.len
+    );
 
     tracing::info!("Calling ElevenLabs Speech-to-Text API...");
 
@@ -402,12 +357,36 @@ pub async fn handle_audio_input(
 
     // ElevenLabs STT API
     let stt_url = "https://api.elevenlabs.io/v1/speech-to-text";
-    
+
+    // Stream the spooled upload instead of holding the whole file in memory
+    let spooled_file = match File::open(&audio_upload.path).await {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Failed to reopen spooled upload: {:?}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json("Failed to read uploaded audio".to_string()),
+            ));
+        }
+    };
+    let audio_body = reqwest::Body::wrap_stream(ReaderStream::with_capacity(
+        spooled_file,
+        state.upload_chunk_size,
+    ));
+    let audio_part = reqwest::multipart::Part::stream_with_length(audio_body, audio_upload.len)
+        .file_name(original_filename);
+    let audio_part = match audio_part.mime_str(content_type.as_deref().unwrap_or("audio/mpeg")) {
+        Ok(part) => part,
+        Err(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json("Invalid audio content type".to_string()),
+            ));
+        }
+    };
+
     let form = reqwest::multipart::Form::new()
-        .part("file", reqwest::multipart::Part::bytes(audio_data.clone())
-            .file_name(original_filename)
-            .mime_str("audio/mpeg").unwrap()
-        )
+        .part("file", audio_part)
         .text("model_id", "scribe_v1")
         .text("language_code", "eng")
         .text("tag_audio_events", "true");
@@ -420,6 +399,9 @@ pub async fn handle_audio_input(
         .send()
         .await;
 
+    // The transcription request has consumed the upload; remove it from disk
+    drop(audio_upload);
+
     let user_text = match stt_response {
         Ok(response) => {
             if response.status().is_success() {
@@ -516,11 +498,116 @@ pub async fn handle_audio_input(
 
     tracing::info!("Calling ElevenLabs TTS API for agent's reply...");
 
+    let audio_url = synthesize_to_file(&state, &agent_reply_text).await?;
+
+    let final_reply = AgentReplyResponse {
+        reply_text: agent_reply_text,
+        audio_url,
+    };
+    Ok((StatusCode::CREATED, Json(final_reply)))
+}
+
+/// An uploaded file spooled to a temporary location on disk.
+///
+/// The file is removed when this value is dropped.
+struct SpooledUpload {
+    path: PathBuf,
+    len: u64,
+}
+
+impl Drop for SpooledUpload {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!("Failed to remove spooled upload {:?}: {:?}", self.path, e);
+        }
+    }
+}
+
+/// Streams a multipart field to a temporary file, chunk by chunk.
+///
+/// Only one chunk of the upload is held in memory at a time, so large audio
+/// files do not have to fit in RAM. Uploads are spooled to the system temp
+/// directory rather than `AUDIO_DIR`, which is publicly served.
+///
+/// # Errors
+///
+/// Returns `PAYLOAD_TOO_LARGE` if the upload exceeds `MAX_UPLOAD_BYTES`,
+/// `BAD_REQUEST` if the multipart stream is malformed, and
+/// `INTERNAL_SERVER_ERROR` if the temporary file cannot be written.
+async fn spool_field(
+    state: &AppState,
+    mut field: Field<'_>,
+) -> Result<SpooledUpload, (StatusCode, Json<String>)> {
+    let path = std::env::temp_dir().join(format!("mcp-api-upload-{}", Uuid::new_v4()));
+    let file = File::create(&path).await.map_err(|e| {
+        tracing::error!("Failed to create spool file: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json("Failed to store uploaded audio".to_string()),
+        )
+    })?;
+    // From here on the guard removes the file on every early return
+    let mut upload = SpooledUpload { path, len: 0 };
+    let mut writer = BufWriter::with_capacity(state.upload_chunk_size, file);
+
+    loop {
+        let chunk = match field.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!("Failed to read audio upload: {:?}", e);
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json("Failed to read uploaded audio".to_string()),
+                ));
+            }
+        };
+        upload.len += chunk.len() as u64;
+        if upload.len > state.max_upload_bytes {
+            tracing::warn!(
+                "Rejecting audio upload larger than {} bytes",
+                state.max_upload_bytes
+            );
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(format!(
+                    "Audio file exceeds the {} byte limit",
+                    state.max_upload_bytes
+                )),
+            ));
+        }
+        if let Err(e) = writer.write_all(&chunk).await {
+            tracing::error!("Failed to write spool file: {:?}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json("Failed to store uploaded audio".to_string()),
+            ));
+        }
+    }
+
+    if let Err(e) = writer.flush().await {
+        tracing::error!("Failed to flush spool file: {:?}", e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json("Failed to store uploaded audio".to_string()),
+        ));
+    }
+    Ok(upload)
+}
+
+/// Converts text to speech via ElevenLabs and streams the audio into `AUDIO_DIR`.
+///
+/// Returns the public URL of the saved file. The response body is written to
+/// disk as it arrives instead of being buffered in full.
+async fn synthesize_to_file(
+    state: &AppState,
+    text: &str,
+) -> Result<String, (StatusCode, Json<String>)> {
     // ElevenLabs TTS API - using default voice "Rachel" (21m00Tcm4TlvDq8ikWAM)
     let tts_url = "https://api.elevenlabs.io/v1/text-to-speech/21m00Tcm4TlvDq8ikWAM";
-    
+
     let tts_payload = serde_json::json!({
-        "text": agent_reply_text,
+        "text": text,
         "model_id": "eleven_multilingual_v2",
         "output_format": "mp3_44100_128"
     });
@@ -534,28 +621,16 @@ pub async fn handle_audio_input(
         .send()
         .await;
 
-    let audio_bytes = match tts_response {
+    let response = match tts_response {
+        Ok(response) if response.status().is_success() => response,
         Ok(response) => {
-            if response.status().is_success() {
-                match response.bytes().await {
-                    Ok(bytes) => bytes.to_vec(),
-                    Err(e) => {
-                        tracing::error!("Failed to read TTS audio bytes: {:?}", e);
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json("Failed to read TTS audio".to_string()),
-                        ));
-                    }
-                }
-            } else {
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                tracing::error!("ElevenLabs TTS API error {}: {}", status, error_text);
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(format!("Error from TTS service: {}", error_text)),
-                ));
-            }
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            tracing::error!("ElevenLabs TTS API error {}: {}", status, error_text);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Error from TTS service: {}", error_text)),
+            ));
         }
         Err(e) => {
             tracing::error!("Failed to call ElevenLabs TTS API: {:?}", e);
@@ -566,34 +641,46 @@ pub async fn handle_audio_input(
         }
     };
 
-    let output_filename = format!("{}.mp3", Uuid::new_v4());
-    let filepath = PathBuf::from(&state.audio_dir).join(&output_filename);
+    let filename = format!("{}.mp3", Uuid::new_v4());
+    let filepath = PathBuf::from(&state.audio_dir).join(&filename);
 
-    match File::create(&filepath).await {
-        Ok(mut file) => {
-            if let Err(e) = file.write_all(&audio_bytes).await {
-                tracing::error!("Failed to write audio file: {:?}", e);
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json("Failed to save audio file".to_string()),
-                ));
-            }
-        }
-        Err(e) => {
-            tracing::error!("Failed to create audio file: {:?}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json("Failed to create audio file".to_string()),
-            ));
-        }
+    if let Err(message) = stream_to_file(state, response, &filepath).await {
+        // Don't leave a truncated file behind in the public directory
+        let _ = tokio::fs::remove_file(&filepath).await;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(message)));
     }
 
-    let audio_url = format!("/public/audio/{}", output_filename);
+    let audio_url = format!("/public/audio/{}", filename);
     tracing::info!("Audio saved to: {}", audio_url);
+    Ok(audio_url)
+}
 
-    let final_reply = AgentReplyResponse {
-        reply_text: agent_reply_text,
-        audio_url,
-    };
-    Ok((StatusCode::CREATED, Json(final_reply)))
+/// Writes a response body to `path` chunk by chunk.
+async fn stream_to_file(
+    state: &AppState,
+    response: reqwest::Response,
+    path: &Path,
+) -> Result<(), String> {
+    let file = File::create(path).await.map_err(|e| {
+        tracing::error!("Failed to create audio file: {:?}", e);
+        "Failed to create audio file".to_string()
+    })?;
+    let mut writer = BufWriter::with_capacity(state.upload_chunk_size, file);
+    let mut body = response.bytes_stream();
+
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| {
+            tracing::error!("Failed to read TTS audio bytes: {:?}", e);
+            "Failed to read TTS audio".to_string()
+        })?;
+        writer.write_all(&chunk).await.map_err(|e| {
+            tracing::error!("Failed to write audio file: {:?}", e);
+            "Failed to save audio file".to_string()
+        })?;
+    }
+
+    writer.flush().await.map_err(|e| {
+        tracing::error!("Failed to write audio file: {:?}", e);
+        "Failed to save audio file".to_string()
+    })
 }
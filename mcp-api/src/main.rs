@@ -20,6 +20,7 @@
 
 use axum::{
     Router,
+    extract::DefaultBodyLimit,
     response::IntoResponse,
     routing::{get, post},
 };
@@ -45,6 +46,10 @@ struct AppState {
     elevenlabs_api_key: String,
     /// Directory path where generated audio files are stored.
     audio_dir: String,
+    /// Buffer size used when streaming uploads and audio to and from disk.
+    upload_chunk_size: usize,
+    /// Largest accepted audio upload, in bytes.
+    max_upload_bytes: u64,
 }
 
 /// Main entry point for the MCP API server.
@@ -74,12 +79,24 @@ async fn main() {
     let audio_dir = std::env::var("AUDIO_DIR").unwrap_or_else(|_| "public/audio".to_string());
     std::fs::create_dir_all(&audio_dir).expect("Failed to create audio directory");
 
+    let upload_chunk_size = std::env::var("UPLOAD_CHUNK_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&size| size > 0)
+        .unwrap_or(64 * 1024);
+    let max_upload_bytes = std::env::var("MAX_UPLOAD_BYTES")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(512 * 1024 * 1024);
+
     let shared_client = Client::new();
     
     let app_state = Arc::new(AppState {
         http_client: shared_client,
         elevenlabs_api_key,
         audio_dir: audio_dir.clone(),
+        upload_chunk_size,
+        max_upload_bytes,
     });
 
     let cors = CorsLayer::new()
@@ -91,7 +108,11 @@ async fn main() {
         .route("/health", get(health_check))
         .route("/agents", get(handlers::get_agents_list))
         .route("/input/text", post(handlers::handle_text_input))
-        .route("/input/audio", post(handlers::handle_audio_input))
+        .route(
+            "/input/audio",
+            // Uploads are streamed to disk and size-checked by the handler itself
+            post(handlers::handle_audio_input).layer(DefaultBodyLimit::disable()),
+        )
         .nest_service("/public", ServeDir::new("public"))
         .layer(cors)
         .with_state(app_state);
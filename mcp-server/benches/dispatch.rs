@@ -0,0 +1,196 @@
+//! Benchmarks for the JSON-RPC dispatch hot path.
+//!
+//! Covers envelope (de)serialization, provider request building for both
+//! backends, and the `list_agents` handler end to end.
+//!
+//! Run with `cargo bench`; compare against a saved baseline with
+//! `cargo bench -- --save-baseline main` / `--baseline main`.
+
+use axum::extract::State;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use mcp_server::agents::AgentStore;
+use mcp_server::handlers::handle_jsonrpc;
+use mcp_server::i18n::{Locale, RequestLocale};
+use mcp_server::load_shed::{LoadShedConfig, LoadShedder};
+use mcp_server::models::*;
+use mcp_server::providers::{gemini::build_gemini_request, groq::build_groq_request};
+use mcp_server::sessions::MemorySessionStore;
+use mcp_server::AppState;
+use std::sync::Arc;
+
+/// A realistic `process_text` request body with `history_len` prior messages.
+fn process_text_body(history_len: usize) -> String {
+    let history: Vec<Message> = sample_history(history_len);
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "process_text",
+        "params": {
+            "agent_id": "agent_002",
+            "user_text": "Explain how an ERC-721 mint transaction works.",
+            "conversation_history": history,
+        },
+        "id": 1
+    })
+    .to_string()
+}
+
+fn sample_history(len: usize) -> Vec<Message> {
+    (0..len)
+        .map(|i| Message {
+            role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+            content: format!("Message {} about wallets, gas fees and smart contracts.", i),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn bench_envelope(c: &mut Criterion) {
+    let mut group = c.benchmark_group("jsonrpc_envelope");
+
+    for history_len in [0, 10, 50] {
+        let body = process_text_body(history_len);
+        group.bench_with_input(
+            BenchmarkId::new("deserialize_request", history_len),
+            &body,
+            |b, body| {
+                b.iter(|| {
+                    let request: JsonRpcRequest<serde_json::Value> =
+                        serde_json::from_str(black_box(body)).unwrap();
+                    let params: ProcessTextParams =
+                        serde_json::from_value(request.params.unwrap()).unwrap();
+                    black_box(params)
+                })
+            },
+        );
+    }
+
+    let response = JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        result: Some(
+            serde_json::to_value(ProcessTextResult {
+                agent_id: "agent_002".to_string(),
+                reply_text: "An ERC-721 mint calls the contract's mint function...".repeat(20),
+                tool_calls: Vec::new(),
+                metadata: ProcessingMetadata {
+                    provider: "groq".to_string(),
+                    model: "llama-3.3-70b-versatile".to_string(),
+                    tokens_used: Some(512),
+                    cost_usd: Some(0.0004),
+                    processing_time_ms: 840,
+                    finish_reason: None,
+                    confidence: None,
+                    seed: None,
+                    cache: None,
+                    correlation_id: None,
+                    sources: Vec::new(),
+                    attachments: Vec::new(),
+                    language: None,
+                    prompt_version: None,
+                    no_store: false,
+                },
+            })
+            .unwrap(),
+        ),
+        error: None,
+        id: serde_json::json!(1),
+    };
+    group.bench_function("serialize_response", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&response)).unwrap())
+    });
+
+    group.finish();
+}
+
+fn bench_request_building(c: &mut Criterion) {
+    let agent = AgentStore::default().get("agent_002").unwrap();
+    let mut group = c.benchmark_group("provider_request");
+
+    for history_len in [0, 10, 50] {
+        let history = sample_history(history_len);
+        group.bench_with_input(BenchmarkId::new("gemini", history_len), &history, |b, h| {
+            b.iter(|| {
+                let request = build_gemini_request(
+                    &agent,
+                    "What is a rollup?".to_string(),
+                    Some(h.clone()),
+                    &Default::default(),
+                    Vec::new(),
+                );
+                serde_json::to_vec(&request).unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("groq", history_len), &history, |b, h| {
+            b.iter(|| {
+                let request = build_groq_request(
+                    &agent,
+                    &agent.model,
+                    "What is a rollup?".to_string(),
+                    Some(h.clone()),
+                    &Default::default(),
+                    &[],
+                );
+                serde_json::to_vec(&request).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let state = Arc::new(AppState {
+        http_client: reqwest::Client::new().into(),
+        providers: Default::default(),
+        agents: Default::default(),
+        admin_token: None,
+        jwt: None,
+        oidc: None,
+        reporter: Default::default(),
+        shedder: Arc::new(LoadShedder::new(LoadShedConfig::from_env())),
+        sessions: Arc::new(MemorySessionStore::default()),
+        history: Default::default(),
+        limits: Default::default(),
+        server_tools: Default::default(),
+        in_flight: Default::default(),
+        jobs: Default::default(),
+        cache: Default::default(),
+        coalescer: Default::default(),
+        quotas: Default::default(),
+        accounting: Default::default(),
+        guardrails: Default::default(),
+        plugins: Default::default(),
+        audit: None,
+        access_log: Default::default(),
+        metrics: Default::default(),
+        knowledge: Default::default(),
+        memories: Default::default(),
+        evals: Default::default(),
+        speech: Default::default(),
+    });
+
+    c.bench_function("dispatch/list_agents", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let request: JsonRpcRequest<serde_json::Value> = serde_json::from_str(
+                r#"{"jsonrpc":"2.0","method":"list_agents","params":{},"id":1}"#,
+            )
+            .unwrap();
+            handle_jsonrpc(
+                State(state.clone()),
+                RequestLocale(Locale::En),
+                Default::default(),
+                None,
+                axum::Json(request),
+            )
+            .await
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_envelope,
+    bench_request_building,
+    bench_dispatch
+);
+criterion_main!(benches);
@@ -0,0 +1,103 @@
+//! Load-test harness for a running MCP server.
+//!
+//! Fires JSON-RPC requests at the server with a fixed concurrency and prints
+//! latency percentiles and error counts. `list_agents` exercises the dispatch
+//! path without spending provider quota; `process_text` goes end to end.
+//!
+//! ```text
+//! cargo run --release --example load_test -- [url] [requests] [concurrency] [method]
+//! cargo run --release --example load_test -- http://localhost:3000 1000 50 list_agents
+//! ```
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let url = args
+        .next()
+        .unwrap_or_else(|| "http://localhost:3000".to_string());
+    let requests: usize = args.next().and_then(|s| s.parse().ok()).unwrap_or(200);
+    let concurrency: usize = args.next().and_then(|s| s.parse().ok()).unwrap_or(20);
+    let method = args.next().unwrap_or_else(|| "list_agents".to_string());
+
+    let body = match method.as_str() {
+        "process_text" => serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "process_text",
+            "params": { "agent_id": "agent_001", "user_text": "Say hello in five words." },
+            "id": 1
+        }),
+        _ => serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": {},
+            "id": 1
+        }),
+    };
+
+    println!(
+        "Sending {} `{}` requests to {} with concurrency {}",
+        requests, method, url, concurrency
+    );
+
+    let client = reqwest::Client::new();
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
+    let started = Instant::now();
+
+    for _ in 0..requests {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let client = client.clone();
+        let url = url.clone();
+        let body = body.clone();
+        tasks.spawn(async move {
+            let _permit = permit;
+            let request_start = Instant::now();
+            let outcome = client.post(&url).json(&body).send().await;
+            let ok = match outcome {
+                Ok(response) if response.status().is_success() => response
+                    .json::<serde_json::Value>()
+                    .await
+                    .map(|json| json.get("error").is_none())
+                    .unwrap_or(false),
+                _ => false,
+            };
+            (request_start.elapsed(), ok)
+        });
+    }
+
+    let mut latencies = Vec::with_capacity(requests);
+    let mut errors = 0usize;
+    while let Some(joined) = tasks.join_next().await {
+        let (latency, ok) = joined.expect("load test task panicked");
+        if !ok {
+            errors += 1;
+        }
+        latencies.push(latency);
+    }
+    let elapsed = started.elapsed();
+
+    latencies.sort();
+    println!("Completed in {:.2?}", elapsed);
+    println!(
+        "Throughput: {:.1} req/s",
+        requests as f64 / elapsed.as_secs_f64()
+    );
+    println!("Errors: {} / {}", errors, requests);
+    for p in [50.0, 90.0, 99.0, 100.0] {
+        println!("p{:<5} {:.2?}", p, percentile(&latencies, p));
+    }
+}
+
+/// Returns the `p`th percentile of an already sorted slice.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
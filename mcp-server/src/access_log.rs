@@ -0,0 +1,215 @@
+//! One structured log line per JSON-RPC call.
+//!
+//! Every call with an id, over HTTP or stdio, is logged at `info` under the
+//! `mcp_server::access` target once it has been answered:
+//!
+//! ```text
+//! INFO request{correlation_id=5f0c...}: mcp_server::access: call method="process_text" agent="agent_001" client="acme:alice" status="ok" error_code=- latency_ms=412 tokens=87 params={"agent_id":"agent_001","user_text":"[27 chars]"}
+//! ```
+//!
+//! What users wrote never reaches the log by default: the values of fields
+//! such as `user_text`, `history`, `messages` and `audio` are replaced with
+//! their size, wherever they are in the params, and fields named like
+//! secrets are masked as in [error reports](crate::error_report).
+//! `ACCESS_LOG_BODIES=true` logs those fields in full, still masked by the
+//! [redaction](crate::redact) patterns of the logs; it is meant for debugging,
+//! not production, and calls made with `no_store` are left out of it.
+//! `RUST_LOG=mcp_server::access=off` silences the access log
+//! as well.
+//!
+//! # Environment Variables
+//!
+//! * `ACCESS_LOG` - Optional. `false` turns the access log off (default:
+//!   `true`)
+//! * `ACCESS_LOG_BODIES` - Optional. `true` logs user text and history in
+//!   full (default: `false`)
+
+use crate::error_report::is_secret_field;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Fields holding what users wrote or sent, logged with their size only.
+pub const OMITTED_FIELDS: [&str; 12] = [
+    "user_text",
+    "text",
+    "history",
+    "messages",
+    "content",
+    "system_prompt",
+    "audio",
+    "data",
+    "documents",
+    "fact",
+    "query",
+    "expected",
+];
+
+/// What is logged of JSON-RPC calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessLog {
+    enabled: bool,
+    bodies: bool,
+}
+
+/// One call, as it is logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessEntry<'a> {
+    /// JSON-RPC method
+    pub method: &'a str,
+    /// Agent the call named, if any
+    pub agent_id: Option<&'a str>,
+    /// Quota key of the caller, if authenticated
+    pub client_id: Option<&'a str>,
+    /// Whether the call failed, with a JSON-RPC error or a tool error
+    pub failed: bool,
+    /// Code of the JSON-RPC error, if any
+    pub error_code: Option<i32>,
+    /// How long the call took
+    pub latency: Duration,
+    /// Tokens the call used, if it reported them
+    pub tokens: Option<u64>,
+    /// Params from [`AccessLog::params`]
+    pub params: Option<Value>,
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self::new(true, false)
+    }
+}
+
+impl AccessLog {
+    /// An access log, which logs nothing unless `enabled`, and user text and
+    /// history in full with `bodies`.
+    pub fn new(enabled: bool, bodies: bool) -> Self {
+        Self { enabled, bodies }
+    }
+
+    /// Reads `ACCESS_LOG` and `ACCESS_LOG_BODIES`.
+    pub fn from_env() -> Self {
+        let flag = |name: &str, default: bool| match std::env::var(name)
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Ok("false") | Ok("0") => false,
+            Ok("true") | Ok("1") => true,
+            _ => default,
+        };
+        Self::new(flag("ACCESS_LOG", true), flag("ACCESS_LOG_BODIES", false))
+    }
+
+    /// Whether calls are logged.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether user text and history are logged in full.
+    pub fn logs_bodies(&self) -> bool {
+        self.enabled && self.bodies
+    }
+
+    /// The params of a call as they are logged; `None` when nothing is.
+    pub fn params(&self, params: Option<&Value>) -> Option<Value> {
+        let mut params = params.filter(|_| self.enabled)?.clone();
+        // Tool calls carry it among their arguments
+        let no_store = [
+            params.get("no_store"),
+            params.pointer("/arguments/no_store"),
+        ]
+        .contains(&Some(&Value::Bool(true)));
+        omit(&mut params, self.bodies && !no_store);
+        Some(params)
+    }
+
+    /// Logs a call that has been answered.
+    pub fn record(&self, entry: AccessEntry<'_>) {
+        if !self.enabled {
+            return;
+        }
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        let params = entry.params.unwrap_or_default();
+        tracing::info!(
+            target: "mcp_server::access",
+            method = entry.method,
+            agent = entry.agent_id.unwrap_or("-"),
+            client = entry.client_id.unwrap_or("-"),
+            status = if entry.failed { "error" } else { "ok" },
+            error_code = %optional(entry.error_code.map(|code| code.to_string())),
+            latency_ms = entry.latency.as_millis() as u64,
+            tokens = %optional(entry.tokens.map(|tokens| tokens.to_string())),
+            params = %params,
+            "call"
+        );
+    }
+}
+
+/// Masks the secrets in `value` and, unless `bodies`, replaces the fields
+/// users wrote with their size.
+fn omit(value: &mut Value, bodies: bool) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|item| omit(item, bodies)),
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                if is_secret_field(name) && !field.is_null() {
+                    *field = json!("[REDACTED]");
+                } else if !bodies && OMITTED_FIELDS.contains(&name.as_str()) {
+                    *field = size(field);
+                } else {
+                    omit(field, bodies);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// What is logged instead of `value`.
+fn size(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        Value::String(text) => json!(format!("[{} chars]", text.chars().count())),
+        Value::Array(items) => json!(format!("[{} items]", items.len())),
+        _ => json!("[omitted]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn omits_user_text_unless_bodies_are_logged() {
+        let params = json!({
+            "agent_id": "agent_001",
+            "user_text": "my seed is ...",
+            "history": [{ "role": "user", "content": "gm" }],
+            "temperature": 0.2,
+            "arguments": { "user_text": "gm", "api_key": "sk-1" },
+        });
+        let logged = AccessLog::default().params(Some(&params)).unwrap();
+        assert_eq!(
+            logged,
+            json!({
+                "agent_id": "agent_001",
+                "user_text": "[14 chars]",
+                "history": "[1 items]",
+                "temperature": 0.2,
+                "arguments": { "user_text": "[2 chars]", "api_key": "[REDACTED]" },
+            })
+        );
+
+        let debug = AccessLog::new(true, true).params(Some(&params)).unwrap();
+        assert_eq!(debug["user_text"], "my seed is ...");
+        assert_eq!(debug["history"], params["history"]);
+        assert_eq!(debug["arguments"]["api_key"], "[REDACTED]");
+        let private = json!({ "user_text": "my seed is ...", "no_store": true });
+        let logged = AccessLog::new(true, true).params(Some(&private)).unwrap();
+        assert_eq!(logged["user_text"], "[14 chars]");
+        let tool = json!({ "arguments": { "user_text": "my seed is ...", "no_store": true } });
+        let logged = AccessLog::new(true, true).params(Some(&tool)).unwrap();
+        assert_eq!(logged["arguments"]["user_text"], "[14 chars]");
+
+        assert_eq!(AccessLog::new(false, true).params(Some(&params)), None);
+        assert!(!AccessLog::new(false, true).logs_bodies());
+    }
+}
@@ -0,0 +1,648 @@
+//! Cost accounting.
+//!
+//! Every provider call is priced from a per-model table of USD prices per
+//! million prompt and completion tokens, and the estimate is returned as
+//! `cost_usd` in the reply's metadata. [`Accounting`] also adds up requests,
+//! tokens and cost per caller (the [quota key](crate::quota::quota_key)) and
+//! per agent, so operators can see where the provider bill comes from.
+//!
+//! The built-in table ([`builtin_prices`]) lists the Groq and Gemini models
+//! at their published on-demand prices; the `[prices]` section of the config
+//! file adds models or corrects prices, and is applied on reload:
+//!
+//! ```toml
+//! [prices]
+//! "llama-3.3-70b-versatile" = { input_per_mtok = 0.59, output_per_mtok = 0.79 }
+//! ```
+//!
+//! Calls to models missing from the table, or whose provider doesn't report
+//! token counts, have no cost. When a provider reports only the total, every
+//! token is priced as output, which errs on the high side. Replies served
+//! from the response cache cost nothing.
+//!
+//! Each request's [`Outcome`] is also kept in hourly buckets per agent, with
+//! its latency and whether it failed, for the admin `usage_report` method
+//! ([`Accounting::report`]). So are the provider calls made for it
+//! ([`Accounting::record_call`]), whose latency and token throughput show a
+//! degrading model apart from slow tools or retrieval. Buckets older than
+//! [`RETENTION_HOURS`] are dropped, and at most [`MAX_LATENCY_SAMPLES`]
+//! latencies of each kind are kept per bucket. Everything lives in memory and
+//! starts over on restart.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Hours of per-agent buckets kept for `usage_report`.
+pub const RETENTION_HOURS: i64 = 31 * 24;
+
+/// Latencies kept per bucket; later ones overwrite the oldest.
+pub const MAX_LATENCY_SAMPLES: usize = 4096;
+
+const HOUR_SECS: i64 = 60 * 60;
+
+/// Price of a model in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    /// Per million prompt tokens
+    pub input_per_mtok: f64,
+    /// Per million completion tokens
+    pub output_per_mtok: f64,
+}
+
+impl ModelPrice {
+    const fn new(input_per_mtok: f64, output_per_mtok: f64) -> Self {
+        Self {
+            input_per_mtok,
+            output_per_mtok,
+        }
+    }
+
+    /// Cost of a call that used `tokens` in all, `prompt_tokens` of them in
+    /// the prompt.
+    pub fn cost(&self, prompt_tokens: Option<u32>, tokens: u32) -> f64 {
+        let prompt = prompt_tokens.unwrap_or(0).min(tokens);
+        let completion = tokens - prompt;
+        (f64::from(prompt) * self.input_per_mtok + f64::from(completion) * self.output_per_mtok)
+            / 1_000_000.0
+    }
+}
+
+/// Prices of the models the built-in providers serve.
+pub fn builtin_prices() -> HashMap<String, ModelPrice> {
+    [
+        ("llama-3.3-70b-versatile", ModelPrice::new(0.59, 0.79)),
+        ("llama-3.1-8b-instant", ModelPrice::new(0.05, 0.08)),
+        (
+            "meta-llama/llama-4-scout-17b-16e-instruct",
+            ModelPrice::new(0.11, 0.34),
+        ),
+        (
+            "meta-llama/llama-4-maverick-17b-128e-instruct",
+            ModelPrice::new(0.20, 0.60),
+        ),
+        ("openai/gpt-oss-120b", ModelPrice::new(0.15, 0.75)),
+        ("openai/gpt-oss-20b", ModelPrice::new(0.10, 0.50)),
+        ("qwen/qwen3-32b", ModelPrice::new(0.29, 0.59)),
+        ("gemma2-9b-it", ModelPrice::new(0.20, 0.20)),
+        ("gemini-2.5-pro", ModelPrice::new(1.25, 10.0)),
+        ("gemini-2.5-flash", ModelPrice::new(0.30, 2.50)),
+        ("gemini-2.5-flash-lite", ModelPrice::new(0.10, 0.40)),
+        ("gemini-2.0-flash", ModelPrice::new(0.10, 0.40)),
+        ("gemini-2.0-flash-lite", ModelPrice::new(0.075, 0.30)),
+        ("gemini-1.5-pro", ModelPrice::new(1.25, 5.0)),
+        ("gemini-1.5-flash", ModelPrice::new(0.075, 0.30)),
+    ]
+    .into_iter()
+    .map(|(model, price)| (model.to_string(), price))
+    .collect()
+}
+
+/// What one agent request came to.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Outcome {
+    /// Tokens billed
+    pub tokens: u32,
+    /// Estimated cost in USD
+    pub cost_usd: f64,
+    /// Time taken to answer or fail
+    pub latency: Duration,
+    /// Whether the request failed
+    pub failed: bool,
+}
+
+/// Requests, tokens and cost added up for a caller or an agent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Totals {
+    pub requests: u64,
+    pub errors: u64,
+    pub tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl Totals {
+    fn add(&mut self, outcome: &Outcome) {
+        self.requests += 1;
+        self.errors += u64::from(outcome.failed);
+        self.tokens += u64::from(outcome.tokens);
+        self.cost_usd += outcome.cost_usd;
+    }
+}
+
+/// The provider calls of an agent's requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Calls {
+    calls: u64,
+    tokens: u64,
+    /// Time spent in them
+    millis: u64,
+}
+
+/// An hour of one agent's requests.
+#[derive(Debug, Default)]
+struct Bucket {
+    totals: Totals,
+    /// Latencies in milliseconds, a ring once full
+    latencies_ms: Vec<u64>,
+    calls: Calls,
+    /// Provider call latencies in milliseconds, a ring once full
+    call_latencies_ms: Vec<u64>,
+}
+
+impl Bucket {
+    fn add(&mut self, outcome: &Outcome) {
+        let latency = outcome.latency.as_millis() as u64;
+        sample(&mut self.latencies_ms, self.totals.requests, latency);
+        self.totals.add(outcome);
+    }
+
+    fn add_call(&mut self, latency: Duration, tokens: u32) {
+        let latency = latency.as_millis() as u64;
+        sample(&mut self.call_latencies_ms, self.calls.calls, latency);
+        self.calls.calls += 1;
+        self.calls.tokens += u64::from(tokens);
+        self.calls.millis += latency;
+    }
+}
+
+/// Keeps the `count`th latency of a bucket in `samples`.
+fn sample(samples: &mut Vec<u64>, count: u64, latency: u64) {
+    if samples.len() < MAX_LATENCY_SAMPLES {
+        samples.push(latency);
+    } else {
+        samples[(count as usize) % MAX_LATENCY_SAMPLES] = latency;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    by_key: HashMap<String, Totals>,
+    by_agent: HashMap<String, Totals>,
+    /// By hour (Unix seconds at its start) and agent ID
+    buckets: BTreeMap<(i64, String), Bucket>,
+}
+
+/// Width of the time buckets of a usage report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketWidth {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl BucketWidth {
+    fn secs(self) -> i64 {
+        match self {
+            Self::Hour => HOUR_SECS,
+            Self::Day => 24 * HOUR_SECS,
+        }
+    }
+}
+
+/// Parameters of the admin `usage_report` method.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UsageReportParams {
+    /// Start of the report, RFC 3339; defaults to 24 hours before `to`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// End of the report, RFC 3339; defaults to now
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Width of each bucket
+    #[serde(default)]
+    pub bucket: BucketWidth,
+    /// Only report this agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+}
+
+/// Latency percentiles in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `samples`, which get sorted.
+    fn of(samples: &mut [u64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let rank = |p: f64| {
+            let index = (p * samples.len() as f64).ceil() as usize;
+            samples[index.clamp(1, samples.len()) - 1]
+        };
+        Some(Self {
+            p50: rank(0.50),
+            p90: rank(0.90),
+            p99: rank(0.99),
+        })
+    }
+}
+
+/// One agent's usage in one time bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRow {
+    pub agent_id: String,
+    /// Start of the bucket, RFC 3339
+    pub bucket_start: String,
+    pub requests: u64,
+    pub errors: u64,
+    /// Failed share of the requests, from 0 to 1
+    pub error_rate: f64,
+    pub tokens: u64,
+    pub cost_usd: f64,
+    /// Absent if no latency was sampled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<Percentiles>,
+    /// Provider calls made, fallbacks and tool rounds included
+    pub provider_calls: u64,
+    /// Latency of the provider calls alone; absent without calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_latency_ms: Option<Percentiles>,
+    /// Tokens per second of provider call time; absent without calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_per_sec: Option<f64>,
+}
+
+/// Result of the `usage_report` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageReportResult {
+    /// Start of the first bucket, RFC 3339
+    pub from: String,
+    /// End of the report, RFC 3339
+    pub to: String,
+    pub bucket: BucketWidth,
+    /// Rows by bucket, then agent ID; buckets without requests are left out
+    pub rows: Vec<UsageRow>,
+}
+
+/// Report buckets merged from hourly ones.
+#[derive(Debug, Default)]
+struct Merged {
+    totals: Totals,
+    latencies: Vec<u64>,
+    calls: Calls,
+    call_latencies: Vec<u64>,
+}
+
+/// Prices calls and keeps the running totals.
+///
+/// Cheap to clone; clones share the prices and totals.
+#[derive(Debug, Clone)]
+pub struct Accounting {
+    prices: Arc<RwLock<HashMap<String, ModelPrice>>>,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl Default for Accounting {
+    /// Accounting with the [built-in prices](builtin_prices).
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
+}
+
+impl Accounting {
+    /// Creates accounting with the built-in prices, overridden by `prices`.
+    pub fn new(prices: HashMap<String, ModelPrice>) -> Self {
+        let accounting = Self {
+            prices: Arc::default(),
+            ledger: Arc::default(),
+        };
+        accounting.reprice(prices);
+        accounting
+    }
+
+    /// Swaps in new price overrides, e.g. after a config reload, keeping the
+    /// totals.
+    pub fn reprice(&self, prices: HashMap<String, ModelPrice>) {
+        let mut table = builtin_prices();
+        table.extend(prices);
+        *self.prices.write().unwrap() = table;
+    }
+
+    /// The price of `model`, if it has one.
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices.read().unwrap().get(model).copied()
+    }
+
+    /// Estimated cost of a call to `model`, or `None` if the model has no
+    /// price or the call's tokens weren't reported.
+    pub fn cost(
+        &self,
+        model: &str,
+        prompt_tokens: Option<u32>,
+        tokens: Option<u32>,
+    ) -> Option<f64> {
+        let price = *self.prices.read().unwrap().get(model)?;
+        Some(price.cost(prompt_tokens, tokens?))
+    }
+
+    /// Adds a request to `agent_id`'s totals and current bucket and, for an
+    /// identified caller, to the totals of its quota `key`.
+    pub fn record(&self, key: Option<&str>, agent_id: &str, outcome: Outcome) {
+        self.record_at(key, agent_id, outcome, Utc::now());
+    }
+
+    fn record_at(&self, key: Option<&str>, agent_id: &str, outcome: Outcome, now: DateTime<Utc>) {
+        let hour = now.timestamp().div_euclid(HOUR_SECS) * HOUR_SECS;
+        let mut ledger = self.ledger.lock().unwrap();
+        if let Some(key) = key {
+            ledger
+                .by_key
+                .entry(key.to_string())
+                .or_default()
+                .add(&outcome);
+        }
+        ledger
+            .by_agent
+            .entry(agent_id.to_string())
+            .or_default()
+            .add(&outcome);
+        ledger
+            .buckets
+            .entry((hour, agent_id.to_string()))
+            .or_default()
+            .add(&outcome);
+        let cutoff = hour - RETENTION_HOURS * HOUR_SECS;
+        ledger.buckets = ledger.buckets.split_off(&(cutoff, String::new()));
+    }
+
+    /// Adds a provider call made for `agent_id`, which took `latency` and
+    /// used `tokens`, to the agent's current bucket.
+    pub fn record_call(&self, agent_id: &str, latency: Duration, tokens: Option<u32>) {
+        self.record_call_at(agent_id, latency, tokens, Utc::now());
+    }
+
+    fn record_call_at(
+        &self,
+        agent_id: &str,
+        latency: Duration,
+        tokens: Option<u32>,
+        now: DateTime<Utc>,
+    ) {
+        let hour = now.timestamp().div_euclid(HOUR_SECS) * HOUR_SECS;
+        self.ledger
+            .lock()
+            .unwrap()
+            .buckets
+            .entry((hour, agent_id.to_string()))
+            .or_default()
+            .add_call(latency, tokens.unwrap_or(0));
+    }
+
+    /// Usage per agent and bucket between `from` and `to`.
+    ///
+    /// Buckets are aligned to UTC hours or days; the first one is the one
+    /// holding `from`. Fails on a malformed or reversed time range.
+    pub fn report(&self, params: &UsageReportParams) -> Result<UsageReportResult, String> {
+        self.report_at(params, Utc::now())
+    }
+
+    fn report_at(
+        &self,
+        params: &UsageReportParams,
+        now: DateTime<Utc>,
+    ) -> Result<UsageReportResult, String> {
+        let parse = |name: &str, value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|value| {
+                    DateTime::parse_from_rfc3339(value)
+                        .map(|time| time.with_timezone(&Utc))
+                        .map_err(|e| format!("{} must be an RFC 3339 time: {}", name, e))
+                })
+                .transpose()
+        };
+        let to = parse("to", &params.to)?.unwrap_or(now);
+        let from = parse("from", &params.from)?.unwrap_or(to - chrono::Duration::hours(24));
+        if from > to {
+            return Err("from must not be after to".to_string());
+        }
+        let width = params.bucket.secs();
+        let first = from.timestamp().div_euclid(width) * width;
+        let end = to.timestamp();
+
+        // Merge the hourly buckets into the report's buckets
+        let mut merged: BTreeMap<(i64, &str), Merged> = BTreeMap::new();
+        let ledger = self.ledger.lock().unwrap();
+        for ((hour, agent_id), bucket) in ledger.buckets.range((first, String::new())..) {
+            if *hour > end {
+                break;
+            }
+            if params.agent_id.as_ref().is_some_and(|id| id != agent_id) {
+                continue;
+            }
+            let start = hour.div_euclid(width) * width;
+            let merged = merged.entry((start, agent_id)).or_default();
+            let totals = &mut merged.totals;
+            totals.requests += bucket.totals.requests;
+            totals.errors += bucket.totals.errors;
+            totals.tokens += bucket.totals.tokens;
+            totals.cost_usd += bucket.totals.cost_usd;
+            merged.latencies.extend_from_slice(&bucket.latencies_ms);
+            merged.calls.calls += bucket.calls.calls;
+            merged.calls.tokens += bucket.calls.tokens;
+            merged.calls.millis += bucket.calls.millis;
+            merged
+                .call_latencies
+                .extend_from_slice(&bucket.call_latencies_ms);
+        }
+
+        let rfc3339 = |secs: i64| Utc.timestamp_opt(secs, 0).unwrap().to_rfc3339();
+        let rows = merged
+            .into_iter()
+            .map(|((start, agent_id), mut merged)| UsageRow {
+                agent_id: agent_id.to_string(),
+                bucket_start: rfc3339(start),
+                requests: merged.totals.requests,
+                errors: merged.totals.errors,
+                error_rate: merged.totals.errors as f64 / merged.totals.requests.max(1) as f64,
+                tokens: merged.totals.tokens,
+                cost_usd: merged.totals.cost_usd,
+                latency_ms: Percentiles::of(&mut merged.latencies),
+                provider_calls: merged.calls.calls,
+                provider_latency_ms: Percentiles::of(&mut merged.call_latencies),
+                tokens_per_sec: (merged.calls.calls > 0).then(|| {
+                    merged.calls.tokens as f64 * 1000.0 / merged.calls.millis.max(1) as f64
+                }),
+            })
+            .collect();
+        Ok(UsageReportResult {
+            from: rfc3339(first),
+            to: to.to_rfc3339(),
+            bucket: params.bucket,
+            rows,
+        })
+    }
+
+    /// Totals per quota key.
+    pub fn by_key(&self) -> HashMap<String, Totals> {
+        self.ledger.lock().unwrap().by_key.clone()
+    }
+
+    /// Totals per agent ID.
+    pub fn by_agent(&self) -> HashMap<String, Totals> {
+        self.ledger.lock().unwrap().by_agent.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_prompt_and_completion_tokens_separately() {
+        let accounting = Accounting::default();
+        let cost = accounting
+            .cost("llama-3.3-70b-versatile", Some(600_000), Some(1_000_000))
+            .unwrap();
+        assert!((cost - (0.6 * 0.59 + 0.4 * 0.79)).abs() < 1e-9, "{}", cost);
+        // Without the split, every token is priced as output
+        let cost = accounting
+            .cost("llama-3.3-70b-versatile", None, Some(1_000_000))
+            .unwrap();
+        assert!((cost - 0.79).abs() < 1e-9, "{}", cost);
+        assert!(accounting.cost("unknown-model", None, Some(10)).is_none());
+        assert!(accounting
+            .cost("llama-3.3-70b-versatile", None, None)
+            .is_none());
+
+        accounting.reprice(HashMap::from([(
+            "unknown-model".to_string(),
+            ModelPrice::new(1.0, 2.0),
+        )]));
+        assert!(accounting.cost("unknown-model", None, Some(10)).is_some());
+    }
+
+    fn outcome(tokens: u32, cost_usd: f64) -> Outcome {
+        Outcome {
+            tokens,
+            cost_usd,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn totals_add_up_per_key_and_agent() {
+        let accounting = Accounting::default();
+        accounting.record(Some("acme/user-1"), "agent_001", outcome(100, 0.25));
+        accounting.record(Some("acme/user-1"), "agent_002", outcome(50, 0.5));
+        accounting.record(None, "agent_001", outcome(10, 0.0));
+
+        let by_key = accounting.by_key();
+        assert_eq!(by_key.len(), 1);
+        assert_eq!(
+            by_key["acme/user-1"],
+            Totals {
+                requests: 2,
+                errors: 0,
+                tokens: 150,
+                cost_usd: 0.75,
+            }
+        );
+        assert_eq!(accounting.by_agent()["agent_001"].requests, 2);
+        assert_eq!(accounting.by_agent()["agent_001"].tokens, 110);
+    }
+
+    #[test]
+    fn reports_usage_by_agent_and_bucket() {
+        let accounting = Accounting::default();
+        let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+        for (i, time) in [
+            "2026-03-01T09:10:00Z",
+            "2026-03-01T09:50:00Z",
+            "2026-03-01T11:00:00Z",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let outcome = Outcome {
+                tokens: 100,
+                cost_usd: 0.01,
+                latency: Duration::from_millis(100 * (i as u64 + 1)),
+                failed: i == 1,
+            };
+            accounting.record_at(None, "agent_001", outcome, at(time));
+        }
+        accounting.record_at(
+            None,
+            "agent_002",
+            outcome(5, 0.0),
+            at("2026-03-01T09:30:00Z"),
+        );
+        for (millis, tokens) in [(500, Some(100)), (1500, Some(200)), (2000, None)] {
+            let latency = Duration::from_millis(millis);
+            accounting.record_call_at("agent_001", latency, tokens, at("2026-03-01T09:20:00Z"));
+        }
+
+        let params = UsageReportParams {
+            from: Some("2026-03-01T09:30:00Z".to_string()),
+            to: Some("2026-03-01T12:00:00Z".to_string()),
+            ..Default::default()
+        };
+        let report = accounting
+            .report_at(&params, at("2026-03-02T00:00:00Z"))
+            .unwrap();
+        assert_eq!(report.from, "2026-03-01T09:00:00+00:00");
+        let rows: Vec<_> = report
+            .rows
+            .iter()
+            .map(|row| {
+                (
+                    row.bucket_start.as_str(),
+                    row.agent_id.as_str(),
+                    row.requests,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("2026-03-01T09:00:00+00:00", "agent_001", 2),
+                ("2026-03-01T09:00:00+00:00", "agent_002", 1),
+                ("2026-03-01T11:00:00+00:00", "agent_001", 1),
+            ]
+        );
+        assert_eq!(report.rows[0].error_rate, 0.5);
+        assert_eq!(
+            report.rows[0].latency_ms,
+            Some(Percentiles {
+                p50: 100,
+                p90: 200,
+                p99: 200,
+            })
+        );
+        assert_eq!(report.rows[0].provider_calls, 3);
+        assert_eq!(report.rows[0].provider_latency_ms.unwrap().p50, 1500);
+        assert_eq!(report.rows[0].tokens_per_sec, Some(75.0));
+        assert_eq!(report.rows[1].tokens_per_sec, None);
+
+        let daily = UsageReportParams {
+            bucket: BucketWidth::Day,
+            agent_id: Some("agent_001".to_string()),
+            ..params
+        };
+        let report = accounting
+            .report_at(&daily, at("2026-03-02T00:00:00Z"))
+            .unwrap();
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].bucket_start, "2026-03-01T00:00:00+00:00");
+        assert_eq!((report.rows[0].requests, report.rows[0].tokens), (3, 300));
+
+        let reversed = UsageReportParams {
+            from: Some("2026-03-02T00:00:00Z".to_string()),
+            to: Some("2026-03-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert!(accounting.report(&reversed).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! SQLite persistence for agents changed at runtime.
+//!
+//! With `AGENT_DB` set, every `create_agent`, `update_agent` and
+//! `delete_agent` call is written to an `agents` table in that SQLite file.
+//! On startup and on every config reload the stored rows are laid over the
+//! built-in and file-defined agents (see [`AgentDb::overlay`]), so runtime
+//! changes survive restarts. Deleting an agent stores a tombstone, which also
+//! keeps deleted built-in agents from coming back.
+//!
+//! The schema is versioned with `PRAGMA user_version` and upgraded by
+//! [`MIGRATIONS`] when the database is opened.
+//!
+//! # Environment Variables
+//!
+//! * `AGENT_DB` - Optional. Path of the SQLite file; created if missing
+
+use crate::agents::AgentRegistry;
+use crate::models::Agent;
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Schema migrations, applied in order. Entry `n` upgrades the schema from
+/// version `n` to `n + 1`; never edit an entry once released, add a new one.
+pub const MIGRATIONS: &[&str] = &[
+    // 1: agents created, updated or deleted through the admin methods
+    "CREATE TABLE agents (
+        id            TEXT PRIMARY KEY NOT NULL,
+        name          TEXT NOT NULL,
+        description   TEXT NOT NULL,
+        capabilities  TEXT NOT NULL,
+        model         TEXT NOT NULL,
+        system_prompt TEXT NOT NULL,
+        deleted       INTEGER NOT NULL DEFAULT 0,
+        updated_at    TEXT NOT NULL
+    );",
+    // 2: per-agent default stop sequences, as a JSON array
+    "ALTER TABLE agents ADD COLUMN stop TEXT NOT NULL DEFAULT '[]';",
+    // 3: per-agent provider fallbacks, as a JSON array
+    "ALTER TABLE agents ADD COLUMN fallbacks TEXT NOT NULL DEFAULT '[]';",
+    // 4: tenant owning the agent, NULL for shared agents
+    "ALTER TABLE agents ADD COLUMN tenant TEXT;",
+    // 5: whether user input is wrapped in delimiters
+    "ALTER TABLE agents ADD COLUMN delimit_user_input INTEGER NOT NULL DEFAULT 0;",
+    // 6: knowledge base bound to the agent, NULL for none
+    "ALTER TABLE agents ADD COLUMN knowledge_base TEXT;",
+    // 7: per-agent default sampling settings, NULL for the provider's
+    "ALTER TABLE agents ADD COLUMN temperature REAL;
+     ALTER TABLE agents ADD COLUMN max_tokens INTEGER;
+     ALTER TABLE agents ADD COLUMN top_p REAL;",
+    // 8: name of the live system prompt, and the other versions as a JSON array
+    "ALTER TABLE agents ADD COLUMN prompt_version TEXT;
+     ALTER TABLE agents ADD COLUMN prompt_versions TEXT NOT NULL DEFAULT '[]';",
+    // 9: models requests may override the agent's with, as a JSON array
+    "ALTER TABLE agents ADD COLUMN allowed_models TEXT NOT NULL DEFAULT '[]';",
+    // 10: whether system messages of the history are sent
+    "ALTER TABLE agents ADD COLUMN allow_system_messages INTEGER NOT NULL DEFAULT 0;",
+    // 11: the agent's Rhai scripts, as a JSON object
+    "ALTER TABLE agents ADD COLUMN scripts TEXT NOT NULL DEFAULT '{}';",
+];
+
+/// A SQLite database of runtime agent changes.
+///
+/// Calls block on SQLite; they are only made by the rarely used admin methods
+/// and by reloads.
+#[derive(Debug)]
+pub struct AgentDb {
+    conn: Mutex<Connection>,
+}
+
+impl AgentDb {
+    /// Opens (or creates) the database at `path` and migrates it.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path)
+            .map_err(|e| format!("Failed to open agent database {}: {}", path.display(), e))?;
+        Self::with_connection(conn)
+    }
+
+    /// Opens the database named by `AGENT_DB`, if set.
+    pub fn from_env() -> Result<Option<Arc<Self>>, String> {
+        match std::env::var_os("AGENT_DB").filter(|p| !p.is_empty()) {
+            Some(path) => Self::open(Path::new(&path)).map(|db| Some(Arc::new(db))),
+            None => Ok(None),
+        }
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self, String> {
+        migrate(&mut conn, MIGRATIONS, "Agent database")
+            .map_err(|e| format!("Failed to migrate agent database: {}", e))?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Stores `agent`, replacing any earlier row for its ID.
+    pub fn save(&self, agent: &Agent) -> Result<(), String> {
+        self.write(agent, false)
+    }
+
+    /// Records that `agent` was deleted.
+    pub fn delete(&self, agent: &Agent) -> Result<(), String> {
+        self.write(agent, true)
+    }
+
+    fn write(&self, agent: &Agent, deleted: bool) -> Result<(), String> {
+        let capabilities = serde_json::to_string(&agent.capabilities).unwrap();
+        let stop = serde_json::to_string(&agent.stop).unwrap();
+        let fallbacks = serde_json::to_string(&agent.fallbacks).unwrap();
+        let prompt_versions = serde_json::to_string(&agent.prompt_versions).unwrap();
+        let allowed_models = serde_json::to_string(&agent.allowed_models).unwrap();
+        let scripts = serde_json::to_string(&agent.scripts).unwrap();
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO agents
+                     (id, name, description, capabilities, model, system_prompt, stop,
+                      fallbacks, tenant, delimit_user_input, knowledge_base, deleted,
+                      updated_at, temperature, max_tokens, top_p, prompt_version,
+                      prompt_versions, allowed_models, allow_system_messages, scripts)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                         ?17, ?18, ?19, ?20, ?21)
+                 ON CONFLICT (id) DO UPDATE SET
+                     name = excluded.name,
+                     description = excluded.description,
+                     capabilities = excluded.capabilities,
+                     model = excluded.model,
+                     system_prompt = excluded.system_prompt,
+                     stop = excluded.stop,
+                     fallbacks = excluded.fallbacks,
+                     tenant = excluded.tenant,
+                     delimit_user_input = excluded.delimit_user_input,
+                     knowledge_base = excluded.knowledge_base,
+                     deleted = excluded.deleted,
+                     updated_at = excluded.updated_at,
+                     temperature = excluded.temperature,
+                     max_tokens = excluded.max_tokens,
+                     top_p = excluded.top_p,
+                     prompt_version = excluded.prompt_version,
+                     prompt_versions = excluded.prompt_versions,
+                     allowed_models = excluded.allowed_models,
+                     allow_system_messages = excluded.allow_system_messages,
+                     scripts = excluded.scripts",
+                params![
+                    agent.id,
+                    agent.name,
+                    agent.description,
+                    capabilities,
+                    agent.model,
+                    agent.system_prompt,
+                    stop,
+                    fallbacks,
+                    agent.tenant,
+                    agent.delimit_user_input,
+                    agent.knowledge_base,
+                    deleted,
+                    chrono::Utc::now().to_rfc3339(),
+                    agent.temperature,
+                    agent.max_tokens,
+                    agent.top_p,
+                    agent.prompt_version,
+                    prompt_versions,
+                    allowed_models,
+                    agent.allow_system_messages,
+                    scripts,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save agent {}: {}", agent.id, e))
+    }
+
+    /// Applies the stored changes to `base`, the built-in and file-defined agents.
+    ///
+    /// Stored agents replace those with the same ID in place, new ones are
+    /// appended in the order they were first saved, and deleted ones are
+    /// removed.
+    pub fn overlay(&self, base: &AgentRegistry) -> Result<AgentRegistry, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, description, capabilities, model, system_prompt, deleted, stop,
+                        fallbacks, tenant, delimit_user_input, knowledge_base, temperature,
+                        max_tokens, top_p, prompt_version, prompt_versions, allowed_models,
+                        allow_system_messages, scripts
+                 FROM agents ORDER BY rowid",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                let capabilities: String = row.get(3)?;
+                let stop: String = row.get(7)?;
+                let fallbacks: String = row.get(8)?;
+                let prompt_versions: String = row.get(16)?;
+                let allowed_models: String = row.get(17)?;
+                let scripts: String = row.get(19)?;
+                let agent = Agent {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    description: row.get(2)?,
+                    capabilities: serde_json::from_str(&capabilities).unwrap_or_default(),
+                    model: row.get(4)?,
+                    system_prompt: row.get(5)?,
+                    prompt_version: row.get(15)?,
+                    prompt_versions: serde_json::from_str(&prompt_versions).unwrap_or_default(),
+                    stop: serde_json::from_str(&stop).unwrap_or_default(),
+                    temperature: row.get(12)?,
+                    max_tokens: row.get(13)?,
+                    top_p: row.get(14)?,
+                    fallbacks: serde_json::from_str(&fallbacks).unwrap_or_default(),
+                    tenant: row.get(9)?,
+                    delimit_user_input: row.get(10)?,
+                    allow_system_messages: row.get(18)?,
+                    knowledge_base: row.get(11)?,
+                    allowed_models: serde_json::from_str(&allowed_models).unwrap_or_default(),
+                    scripts: serde_json::from_str(&scripts).unwrap_or_default(),
+                };
+                Ok((agent, row.get::<_, bool>(6)?))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load agents: {}", e))?;
+
+        let deleted: HashSet<String> = rows
+            .iter()
+            .filter(|(_, deleted)| *deleted)
+            .map(|(agent, _)| agent.id.clone())
+            .collect();
+        let stored = rows
+            .into_iter()
+            .filter(|(_, deleted)| !deleted)
+            .map(|(agent, _)| Arc::new(agent));
+        let agents = base
+            .list()
+            .iter()
+            .cloned()
+            .chain(stored)
+            .filter(|agent| !deleted.contains(&agent.id));
+        Ok(AgentRegistry::from_shared(agents))
+    }
+}
+
+/// Brings the schema of the `database` in `conn` up to the latest of its
+/// `migrations`.
+pub(crate) fn migrate(
+    conn: &mut Connection,
+    migrations: &[&str],
+    database: &str,
+) -> Result<(), String> {
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let version = usize::try_from(version).unwrap_or(usize::MAX);
+    if version > migrations.len() {
+        return Err(format!(
+            "schema version {} is newer than this server supports ({})",
+            version,
+            migrations.len()
+        ));
+    }
+    for (i, migration) in migrations.iter().enumerate().skip(version) {
+        let apply = |conn: &mut Connection| {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i as i64 + 1)?;
+            tx.commit()
+        };
+        apply(conn).map_err(|e| format!("version {}: {}", i + 1, e))?;
+        tracing::info!("{} migrated to version {}", database, i + 1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::{builtin_agents, AgentStore};
+    use crate::models::ProviderFallback;
+    use crate::prompt_versions::PromptVersion;
+
+    #[test]
+    fn stored_changes_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("agents-{}.db", uuid::Uuid::new_v4()));
+        let base = || AgentRegistry::new(builtin_agents());
+
+        let store = AgentStore::with_db(Arc::new(AgentDb::open(&path).unwrap()));
+        store.replace(base()).unwrap();
+        let mut agent = builtin_agents()[0].clone();
+        agent.id = "agent_005".to_string();
+        agent.stop = vec!["END".to_string()];
+        agent.temperature = Some(0.3);
+        agent.max_tokens = Some(256);
+        agent.prompt_version = Some("v1".to_string());
+        agent.prompt_versions = vec![PromptVersion {
+            version: "v2".to_string(),
+            system_prompt: "Be brief.".to_string(),
+            weight: 10,
+        }];
+        agent.fallbacks = vec![ProviderFallback {
+            provider: "gemini".to_string(),
+            model: None,
+        }];
+        agent.tenant = Some("acme".to_string());
+        agent.allowed_models = vec!["llama-3.1-8b-instant".to_string()];
+        agent.allow_system_messages = true;
+        store.create(agent).unwrap().unwrap();
+        store
+            .update("agent_001", |a| a.name = "Renamed".to_string())
+            .unwrap()
+            .unwrap();
+        store.delete("agent_002").unwrap().unwrap();
+        drop(store);
+
+        // A restart merges the stored rows over the built-in agents again
+        let db = AgentDb::open(&path).unwrap();
+        let registry = db.overlay(&base()).unwrap();
+        let ids: Vec<_> = registry.list().iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["agent_001", "agent_003", "agent_004", "agent_005"]);
+        assert_eq!(registry.list()[0].name, "Renamed");
+        assert_eq!(
+            registry.get("agent_005").unwrap().capabilities,
+            builtin_agents()[0].capabilities
+        );
+        assert_eq!(registry.get("agent_005").unwrap().stop, ["END"]);
+        assert_eq!(registry.get("agent_005").unwrap().temperature, Some(0.3));
+        assert_eq!(registry.get("agent_005").unwrap().max_tokens, Some(256));
+        assert_eq!(registry.get("agent_005").unwrap().top_p, None);
+        assert_eq!(
+            registry.get("agent_005").unwrap().prompt_version.as_deref(),
+            Some("v1")
+        );
+        assert_eq!(
+            registry.get("agent_005").unwrap().prompt_versions[0].weight,
+            10
+        );
+        assert_eq!(
+            registry.get("agent_005").unwrap().fallbacks[0].provider,
+            "gemini"
+        );
+        assert_eq!(
+            registry.get("agent_005").unwrap().tenant.as_deref(),
+            Some("acme")
+        );
+        assert_eq!(
+            registry.get("agent_005").unwrap().allowed_models,
+            ["llama-3.1-8b-instant"]
+        );
+        assert!(registry.get("agent_005").unwrap().allow_system_messages);
+
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -2,34 +2,404 @@
 //!
 //! This module provides the configuration and metadata for all available AI agents.
 //! Each agent has a unique ID, capabilities, and system prompt that defines its behavior.
+//!
+//! Agents live in an immutable [`AgentRegistry`], so listing or looking up
+//! agents never re-allocates the (long) prompt strings. The server keeps the
+//! current registry in an [`AgentStore`] in its shared state; config reloads
+//! and the admin methods swap in a new registry, and requests already holding
+//! an agent keep using it. With an [`AgentDb`], admin changes are persisted
+//! and laid over every registry the store is given.
+//!
+//! An agent with a `tenant` belongs to that tenant: requests are served from
+//! the [view](AgentStore::visible_to) of their caller's tenant, which holds
+//! the shared agents and the tenant's own, so products sharing a server never
+//! see one another's agents. Agent IDs stay unique across tenants.
+//!
+//! [`AgentRegistry::find`] serves `find_agents`, which picks agents by
+//! capabilities and ranks them by how well their name, description and
+//! capabilities match a free-text query.
 
+use crate::agent_db::AgentDb;
 use crate::models::Agent;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// An indexed, immutable set of agents.
+///
+/// Agents are stored behind `Arc` so handlers can hold on to one for the
+/// duration of a request without cloning it.
+#[derive(Debug, Default)]
+pub struct AgentRegistry {
+    /// Agents in display order.
+    agents: Vec<Arc<Agent>>,
+    /// Index from agent ID into `agents`.
+    by_id: HashMap<String, Arc<Agent>>,
+}
+
+impl AgentRegistry {
+    /// Builds a registry from a list of agents.
+    ///
+    /// If two agents share an ID, the later one wins but keeps the position of
+    /// the first.
+    pub fn new(agents: Vec<Agent>) -> Self {
+        Self::from_shared(agents.into_iter().map(Arc::new))
+    }
+
+    /// Builds a registry from agents that are already shared.
+    pub(crate) fn from_shared(agents: impl IntoIterator<Item = Arc<Agent>>) -> Self {
+        let mut registry = Self::default();
+        for agent in agents {
+            match registry.by_id.insert(agent.id.clone(), agent.clone()) {
+                Some(previous) => {
+                    let slot = registry
+                        .agents
+                        .iter_mut()
+                        .find(|a| Arc::ptr_eq(a, &previous))
+                        .expect("indexed agent must be listed");
+                    *slot = agent;
+                }
+                None => registry.agents.push(agent),
+            }
+        }
+        registry
+    }
+
+    /// All agents, in display order.
+    pub fn list(&self) -> &[Arc<Agent>] {
+        &self.agents
+    }
+
+    /// Looks up an agent by ID.
+    pub fn get(&self, agent_id: &str) -> Option<Arc<Agent>> {
+        self.by_id.get(agent_id).cloned()
+    }
+
+    /// The agents having every capability of `capabilities`, ignoring case,
+    /// and matching at least one word of `query`, best matches first.
+    ///
+    /// A word matches an agent's capabilities (worth 3), the words of its
+    /// name (2) or of its description (1); words of three letters or more
+    /// also match as prefixes, so `crypt` finds `cryptocurrency`. Common
+    /// words such as `that` are ignored, and a query of only those matches
+    /// every agent. Ties keep display order.
+    pub fn find(&self, capabilities: &[String], query: Option<&str>) -> Vec<Arc<Agent>> {
+        let terms = words(query.unwrap_or_default())
+            .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+            .collect::<Vec<_>>();
+        let mut found: Vec<(u32, &Arc<Agent>)> = self
+            .agents
+            .iter()
+            .filter(|agent| {
+                capabilities.iter().all(|wanted| {
+                    agent
+                        .capabilities
+                        .iter()
+                        .any(|capability| capability.eq_ignore_ascii_case(wanted.trim()))
+                })
+            })
+            .map(|agent| (relevance(agent, &terms), agent))
+            .filter(|(score, _)| terms.is_empty() || *score > 0)
+            .collect();
+        // Stable, so ties keep display order
+        found.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        found.into_iter().map(|(_, agent)| agent.clone()).collect()
+    }
+
+    /// The agents callers of `tenant` can see: the shared ones and the
+    /// tenant's own. Callers without a tenant only see shared agents.
+    pub fn visible_to(&self, tenant: Option<&str>) -> AgentRegistry {
+        let visible = self
+            .agents
+            .iter()
+            .filter(|agent| agent.tenant.is_none() || agent.tenant.as_deref() == tenant)
+            .cloned();
+        Self::from_shared(visible)
+    }
+
+    /// Number of registered agents.
+    pub fn len(&self) -> usize {
+        self.agents.len()
+    }
+
+    /// Whether the registry has no agents.
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+}
+
+/// Words of a query left out of [`AgentRegistry::find`]'s matching.
+const STOP_WORDS: &[&str] = &[
+    "a",
+    "an",
+    "and",
+    "any",
+    "agent",
+    "agents",
+    "are",
+    "can",
+    "do",
+    "does",
+    "find",
+    "for",
+    "i",
+    "in",
+    "is",
+    "me",
+    "need",
+    "of",
+    "on",
+    "one",
+    "or",
+    "something",
+    "that",
+    "the",
+    "to",
+    "what",
+    "which",
+    "who",
+    "with",
+];
 
-/// Returns the list of all available AI agents.
+/// The lowercase words of `text`, split on anything but letters and digits.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// How well `agent` matches the query `terms`, see [`AgentRegistry::find`].
+fn relevance(agent: &Agent, terms: &[String]) -> u32 {
+    let matches = |term: &str, word: &str| {
+        word == term || (term.chars().count() >= 3 && word.starts_with(term))
+    };
+    let capabilities: Vec<String> = agent.capabilities.iter().flat_map(|c| words(c)).collect();
+    let name: Vec<String> = words(&agent.name).collect();
+    let description: Vec<String> = words(&agent.description).collect();
+    terms
+        .iter()
+        .map(|term| {
+            let found = |words: &[String]| words.iter().any(|word| matches(term, word));
+            if found(&capabilities) {
+                3
+            } else if found(&name) {
+                2
+            } else if found(&description) {
+                1
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// Checks that `agent_id` can name an agent.
+///
+/// IDs double as MCP tool names, so they are limited to 64 ASCII letters,
+/// digits, `_` and `-`, and must not clash with the `list_agents` tool.
+///
+/// ```
+/// # use mcp_server::agents::validate_agent_id;
+/// assert!(validate_agent_id("agent_005").is_ok());
+/// assert!(validate_agent_id("nft writer").is_err());
+/// ```
+pub fn validate_agent_id(agent_id: &str) -> Result<(), String> {
+    let valid_chars = agent_id
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    if agent_id.is_empty() || agent_id.len() > 64 || !valid_chars {
+        return Err(format!(
+            "agent ID {:?} must be 1-64 letters, digits, '_' or '-'",
+            agent_id
+        ));
+    }
+    if agent_id == crate::tools::LIST_AGENTS_TOOL {
+        return Err(format!("agent ID {} is reserved", agent_id));
+    }
+    Ok(())
+}
+
+/// The live agent registry, shared by the handlers and the config reloader.
+///
+/// Cheap to clone; clones see the same registry. Writers replace the whole
+/// registry, so readers take a [`snapshot`](Self::snapshot) and never block
+/// on a request in progress.
+#[derive(Debug, Clone)]
+pub struct AgentStore {
+    current: Arc<RwLock<Arc<AgentRegistry>>>,
+    db: Option<Arc<AgentDb>>,
+}
+
+impl Default for AgentStore {
+    /// A store holding the [built-in agents](builtin_agents).
+    fn default() -> Self {
+        Self::new(AgentRegistry::new(builtin_agents()))
+    }
+}
+
+impl AgentStore {
+    /// Creates a store holding `registry`.
+    pub fn new(registry: AgentRegistry) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(registry))),
+            db: None,
+        }
+    }
+
+    /// Creates a store that persists changes to `db`.
+    ///
+    /// It starts empty; [`replace`](Self::replace) it with the file-defined
+    /// agents to load the stored ones.
+    pub fn with_db(db: Arc<AgentDb>) -> Self {
+        Self {
+            db: Some(db),
+            ..Self::new(AgentRegistry::default())
+        }
+    }
+
+    /// The current registry.
+    pub fn snapshot(&self) -> Arc<AgentRegistry> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Replaces the whole registry, e.g. after a config reload.
+    ///
+    /// Changes stored in the database, if any, are applied on top; fails,
+    /// keeping the current registry, if they cannot be read.
+    pub fn replace(&self, registry: AgentRegistry) -> Result<(), String> {
+        let registry = match &self.db {
+            Some(db) => db.overlay(&registry)?,
+            None => registry,
+        };
+        *self.current.write().unwrap() = Arc::new(registry);
+        Ok(())
+    }
+
+    /// The current registry as seen by callers of `tenant`; see
+    /// [`AgentRegistry::visible_to`].
+    ///
+    /// Deployments without tenant-owned agents get the registry itself.
+    pub fn visible_to(&self, tenant: Option<&str>) -> Arc<AgentRegistry> {
+        let registry = self.snapshot();
+        if registry.list().iter().all(|agent| agent.tenant.is_none()) {
+            return registry;
+        }
+        Arc::new(registry.visible_to(tenant))
+    }
+
+    /// All agents, in display order.
+    ///
+    /// Only the `Arc`s are cloned.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use mcp_server::agents::AgentStore;
+    /// let agents = AgentStore::default().list();
+    /// for agent in agents {
+    ///     println!("{}: {}", agent.id, agent.name);
+    /// }
+    /// ```
+    pub fn list(&self) -> Vec<Arc<Agent>> {
+        self.snapshot().list().to_vec()
+    }
+
+    /// Looks up an agent by ID.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use mcp_server::agents::AgentStore;
+    /// if let Some(agent) = AgentStore::default().get("agent_002") {
+    ///     println!("Found agent: {}", agent.name);
+    /// }
+    /// ```
+    pub fn get(&self, agent_id: &str) -> Option<Arc<Agent>> {
+        self.snapshot().get(agent_id)
+    }
+
+    /// Adds a new agent at the end of the list.
+    ///
+    /// Returns `Ok(None)`, leaving the registry unchanged, if the ID is taken,
+    /// and fails if the agent cannot be persisted.
+    pub fn create(&self, agent: Agent) -> Result<Option<Arc<Agent>>, String> {
+        let mut current = self.current.write().unwrap();
+        if current.get(&agent.id).is_some() {
+            return Ok(None);
+        }
+        self.persist(|db| db.save(&agent))?;
+        let agent = Arc::new(agent);
+        let agents = current.list().iter().cloned().chain([agent.clone()]);
+        *current = Arc::new(AgentRegistry::from_shared(agents));
+        Ok(Some(agent))
+    }
+
+    /// Changes an existing agent in place, keeping its position.
+    ///
+    /// `update` gets a copy of the agent; its ID is restored afterwards.
+    /// Returns `Ok(None)` if there is no such agent, and fails if the change
+    /// cannot be persisted.
+    pub fn update(
+        &self,
+        agent_id: &str,
+        update: impl FnOnce(&mut Agent),
+    ) -> Result<Option<Arc<Agent>>, String> {
+        let mut current = self.current.write().unwrap();
+        let Some(agent) = current.get(agent_id) else {
+            return Ok(None);
+        };
+        let mut agent = Agent::clone(&agent);
+        update(&mut agent);
+        agent.id = agent_id.to_string();
+        self.persist(|db| db.save(&agent))?;
+        let agent = Arc::new(agent);
+        // A later agent with the same ID replaces the old one in its slot
+        let agents = current.list().iter().cloned().chain([agent.clone()]);
+        *current = Arc::new(AgentRegistry::from_shared(agents));
+        Ok(Some(agent))
+    }
+
+    /// Removes an agent, returning it, or `Ok(None)` if there is no such
+    /// agent. Fails if the deletion cannot be persisted.
+    pub fn delete(&self, agent_id: &str) -> Result<Option<Arc<Agent>>, String> {
+        let mut current = self.current.write().unwrap();
+        let Some(agent) = current.get(agent_id) else {
+            return Ok(None);
+        };
+        self.persist(|db| db.delete(&agent))?;
+        let agents = current
+            .list()
+            .iter()
+            .filter(|a| !Arc::ptr_eq(a, &agent))
+            .cloned();
+        *current = Arc::new(AgentRegistry::from_shared(agents));
+        Ok(Some(agent))
+    }
+
+    /// Runs `write` against the database, if there is one.
+    fn persist(&self, write: impl FnOnce(&AgentDb) -> Result<(), String>) -> Result<(), String> {
+        self.db.as_deref().map_or(Ok(()), write)
+    }
+}
+
+/// Returns the definitions of the agents built into the server.
 ///
 /// Each agent has a unique ID, name, description, capabilities, and system prompt.
-/// The system prompt defines the agent's behavior and expertise area.
+/// The system prompt defines the agent's behavior and expertise area, and
+/// default sampling settings suit its replies. These seed the [`AgentStore`].
 ///
 /// # Available Agents
 ///
 /// - `agent_001` - General Assistant (general-purpose)
 /// - `agent_002` - Web3 Expert (blockchain, crypto, DeFi)
-/// - `agent_003` - Voice Specialist (conversational, voice-optimized)
-/// - `agent_004` - Code Assistant (programming, debugging)
+/// - `agent_003` - Voice Specialist (conversational, voice-optimized; short
+///   replies at temperature 0.6)
+/// - `agent_004` - Code Assistant (programming, debugging; long replies at
+///   temperature 0.2)
 ///
 /// # Returns
 ///
-/// A vector of `Agent` structs representing all available agents.
-///
-/// # Example
-///
-/// ```rust
-/// let agents = get_agents();
-/// for agent in agents {
-///     println!("{}: {}", agent.id, agent.name);
-/// }
-/// ```
-pub fn get_agents() -> Vec<Agent> {
+/// A vector of `Agent` structs representing all built-in agents.
+pub fn builtin_agents() -> Vec<Agent> {
     vec![
         Agent {
             id: "agent_001".to_string(),
@@ -42,6 +412,19 @@ pub fn get_agents() -> Vec<Agent> {
             ],
             model: "mixtral-8x7b-32768".to_string(),
             system_prompt: "You are a helpful, friendly, and knowledgeable AI assistant. Provide clear, accurate, and concise responses.".to_string(),
+            prompt_version: None,
+            prompt_versions: Vec::new(),
+            stop: Vec::new(),
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+            fallbacks: Vec::new(),
+            tenant: None,
+            delimit_user_input: false,
+            allow_system_messages: false,
+            knowledge_base: None,
+            allowed_models: Vec::new(),
+            scripts: Default::default(),
         },
         Agent {
             id: "agent_002".to_string(),
@@ -55,6 +438,19 @@ pub fn get_agents() -> Vec<Agent> {
             ],
             model: "mixtral-8x7b-32768".to_string(),
             system_prompt: "You are a Web3 and blockchain expert. Help users understand cryptocurrency, NFTs, smart contracts, DeFi, and related technologies. Provide accurate technical information and practical guidance.".to_string(),
+            prompt_version: None,
+            prompt_versions: Vec::new(),
+            stop: Vec::new(),
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+            fallbacks: Vec::new(),
+            tenant: None,
+            delimit_user_input: false,
+            allow_system_messages: false,
+            knowledge_base: Some("web3".to_string()),
+            allowed_models: Vec::new(),
+            scripts: Default::default(),
         },
         Agent {
             id: "agent_003".to_string(),
@@ -67,6 +463,19 @@ pub fn get_agents() -> Vec<Agent> {
             ],
             model: "mixtral-8x7b-32768".to_string(),
             system_prompt: "You are an AI assistant optimized for voice interactions. Respond in a natural, conversational tone suitable for speech. Keep responses concise and easy to understand when spoken aloud.".to_string(),
+            prompt_version: None,
+            prompt_versions: Vec::new(),
+            stop: Vec::new(),
+            temperature: Some(0.6),
+            max_tokens: Some(300),
+            top_p: None,
+            fallbacks: Vec::new(),
+            tenant: None,
+            delimit_user_input: false,
+            allow_system_messages: false,
+            knowledge_base: None,
+            allowed_models: Vec::new(),
+            scripts: Default::default(),
         },
         Agent {
             id: "agent_004".to_string(),
@@ -79,27 +488,130 @@ pub fn get_agents() -> Vec<Agent> {
             ],
             model: "mixtral-8x7b-32768".to_string(),
             system_prompt: "You are an expert programming assistant. Help users with code, debugging, architecture, and technical decisions. Provide clear explanations and working code examples.".to_string(),
+            prompt_version: None,
+            prompt_versions: Vec::new(),
+            stop: Vec::new(),
+            temperature: Some(0.2),
+            max_tokens: Some(2048),
+            top_p: None,
+            fallbacks: Vec::new(),
+            tenant: None,
+            delimit_user_input: false,
+            allow_system_messages: false,
+            knowledge_base: None,
+            allowed_models: Vec::new(),
+            scripts: Default::default(),
         },
     ]
 }
 
-/// Finds an agent by ID.
-///
-/// # Arguments
-///
-/// * `agent_id` - The unique identifier of the agent to find
-///
-/// # Returns
-///
-/// `Some(Agent)` if found, `None` otherwise
-///
-/// # Example
-///
-/// ```rust
-/// if let Some(agent) = find_agent_by_id("agent_002") {
-///     println!("Found agent: {}", agent.name);
-/// }
-/// ```
-pub fn find_agent_by_id(agent_id: &str) -> Option<Agent> {
-    get_agents().into_iter().find(|a| a.id == agent_id)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_indexes_builtin_agents() {
+        let registry = AgentStore::default().snapshot();
+        assert_eq!(registry.len(), builtin_agents().len());
+        let agent = registry.get("agent_002").expect("agent_002 is built in");
+        assert_eq!(agent.name, "Web3 Expert");
+        // Lookups share the registry's allocation
+        assert!(Arc::ptr_eq(&agent, &registry.get("agent_002").unwrap()));
+        assert!(registry.get("agent_999").is_none());
+    }
+
+    #[test]
+    fn duplicate_ids_keep_first_position() {
+        let mut agents = builtin_agents();
+        let mut replacement = agents[0].clone();
+        replacement.name = "Replacement".to_string();
+        agents.push(replacement);
+
+        let registry = AgentRegistry::new(agents);
+        assert_eq!(registry.len(), 4);
+        assert_eq!(registry.list()[0].name, "Replacement");
+        assert_eq!(registry.get("agent_001").unwrap().name, "Replacement");
+    }
+
+    #[test]
+    fn store_creates_updates_and_deletes() {
+        let store = AgentStore::default();
+        let before = store.snapshot();
+        let mut agent = builtin_agents()[0].clone();
+        assert!(store.create(agent.clone()).unwrap().is_none());
+
+        agent.id = "agent_005".to_string();
+        store.create(agent).unwrap().unwrap();
+        let updated = store
+            .update("agent_001", |a| {
+                a.name = "Renamed".to_string();
+                a.id = "ignored".to_string();
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.id, "agent_001");
+        assert!(store.update("agent_999", |_| {}).unwrap().is_none());
+        store.delete("agent_002").unwrap().unwrap();
+        assert!(store.delete("agent_002").unwrap().is_none());
+
+        let ids: Vec<_> = store.list().iter().map(|a| a.id.clone()).collect();
+        assert_eq!(ids, ["agent_001", "agent_003", "agent_004", "agent_005"]);
+        assert_eq!(store.list()[0].name, "Renamed");
+        // Earlier snapshots are unaffected
+        assert_eq!(before.len(), builtin_agents().len());
+        assert_eq!(before.list()[0].name, "General Assistant");
+    }
+
+    #[test]
+    fn finds_agents_by_capability_and_query() {
+        let registry = AgentRegistry::new(builtin_agents());
+        let ids = |agents: Vec<Arc<Agent>>| {
+            agents
+                .iter()
+                .map(|agent| agent.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(registry.find(&[], Some("something that does web3 + voice"))),
+            ["agent_002", "agent_003"]
+        );
+        assert_eq!(
+            ids(registry.find(&["Conversation".into()], None)),
+            ["agent_001", "agent_003"]
+        );
+        assert_eq!(
+            ids(registry.find(&["conversation".into()], Some("audio"))),
+            ["agent_003"]
+        );
+        // Prefixes match, and capabilities outrank descriptions
+        assert_eq!(ids(registry.find(&[], Some("crypt"))), ["agent_002"]);
+        assert_eq!(
+            ids(registry.find(&[], Some("technical assistant"))),
+            ["agent_004", "agent_001"]
+        );
+        assert_eq!(registry.find(&[], Some("the")).len(), registry.len());
+        assert!(registry
+            .find(&["web3".into(), "voice".into()], None)
+            .is_empty());
+    }
+
+    #[test]
+    fn tenants_only_see_shared_and_own_agents() {
+        let store = AgentStore::default();
+        let shared = store.visible_to(Some("acme"));
+        assert!(Arc::ptr_eq(&shared, &store.snapshot()));
+
+        for (id, tenant) in [("acme_bot", "acme"), ("globex_bot", "globex")] {
+            let mut agent = builtin_agents()[0].clone();
+            agent.id = id.to_string();
+            agent.tenant = Some(tenant.to_string());
+            store.create(agent).unwrap().unwrap();
+        }
+        let acme = store.visible_to(Some("acme"));
+        assert_eq!(acme.len(), builtin_agents().len() + 1);
+        assert!(acme.get("acme_bot").is_some());
+        assert!(acme.get("globex_bot").is_none());
+        let untenanted = store.visible_to(None);
+        assert_eq!(untenanted.len(), builtin_agents().len());
+    }
 }
@@ -0,0 +1,294 @@
+//! Files attached to `process_text` requests, such as source files for the
+//! Code Assistant to review.
+//!
+//! Each entry of `attachments` has a `name` and either the file's `text` or
+//! its base64 `data`:
+//!
+//! ```json
+//! { "agent_id": "agent_004", "user_text": "Is this contract safe to deploy?",
+//!   "attachments": [{ "name": "Vault.sol", "text": "pragma solidity ^0.8.20; ..." },
+//!                   { "name": "audit.pdf", "data": "JVBERi0xLjcK..." }] }
+//! ```
+//!
+//! Text files and PDFs are accepted, at most [`MAX_ATTACHMENTS`] per request
+//! and [`MAX_ATTACHMENT_BYTES`] each. The type comes from `mime_type`, or
+//! else from the content: PDFs by their header, anything else must be UTF-8
+//! text. Only the text of [PDFs](pdf) is read, and each file is cut to its
+//! first [`MAX_ATTACHMENT_CHARS`] characters.
+//!
+//! The extracted text of each file is put before `user_text`, in an
+//! `<attachment>` element naming it, and the result's `metadata.attachments`
+//! describes what was read. Session transcripts record the text with the
+//! attachments, so later turns can still refer to them.
+
+pub mod pdf;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// Most files one request carries.
+pub const MAX_ATTACHMENTS: usize = 5;
+
+/// Largest file, in bytes once decoded.
+pub const MAX_ATTACHMENT_BYTES: usize = 2 * 1024 * 1024;
+
+/// Characters of text kept from each file; the rest is cut off.
+pub const MAX_ATTACHMENT_CHARS: usize = 50_000;
+
+/// Longest file name, in characters.
+const MAX_NAME_CHARS: usize = 255;
+
+/// `application/` types read as text, besides `text/*`.
+const TEXT_APPLICATION_TYPES: &[&str] = &[
+    "application/json",
+    "application/xml",
+    "application/javascript",
+    "application/typescript",
+    "application/x-yaml",
+    "application/yaml",
+    "application/toml",
+    "application/x-sh",
+    "application/sql",
+];
+
+/// A file as given in the params: its text or base64 bytes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AttachmentInput {
+    /// File name shown to the model, e.g. `src/lib.rs`
+    pub name: String,
+    /// Text of the file; give either this or `data`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Base64 bytes of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Optional MIME type, `application/pdf` or a text type; detected when
+    /// omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// What was read from an attached file, reported in the result metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentInfo {
+    /// File name as given
+    pub name: String,
+    /// Type the file was read as
+    pub mime_type: String,
+    /// Size of the file in bytes
+    pub bytes: usize,
+    /// Characters of text added to the prompt
+    pub chars: usize,
+    /// Whether the text was cut at [`MAX_ATTACHMENT_CHARS`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// The text of an attached file, ready to go into the prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    /// What was read
+    pub info: AttachmentInfo,
+    /// Text extracted from the file
+    pub text: String,
+}
+
+/// How the contents of a file are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Pdf,
+}
+
+/// Checks `attachments` and extracts their text.
+///
+/// Fails with a message naming the first file that can't be read: a bad
+/// name, neither or both of `text` and `data`, a file too large, an
+/// unsupported type or a PDF without extractable text.
+pub fn extract_all(attachments: Vec<AttachmentInput>) -> Result<Vec<Attachment>, String> {
+    if attachments.len() > MAX_ATTACHMENTS {
+        return Err(format!(
+            "attachments accepts at most {} files, got {}",
+            MAX_ATTACHMENTS,
+            attachments.len()
+        ));
+    }
+    attachments
+        .into_iter()
+        .enumerate()
+        .map(|(i, attachment)| {
+            extract(attachment).map_err(|e| format!("attachments[{}]: {}", i, e))
+        })
+        .collect()
+}
+
+fn extract(attachment: AttachmentInput) -> Result<Attachment, String> {
+    let AttachmentInput {
+        name,
+        text,
+        data,
+        mime_type,
+    } = attachment;
+    validate_name(&name)?;
+    let bytes = match (text, data) {
+        (Some(text), None) => text.into_bytes(),
+        (None, Some(data)) => {
+            let data: String = data.split_whitespace().collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| format!("data is not valid base64: {}", e))?
+        }
+        _ => return Err("give either text or data".into()),
+    };
+    let size = bytes.len();
+    if size > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "files are limited to {} bytes",
+            MAX_ATTACHMENT_BYTES
+        ));
+    }
+
+    let kind = match mime_type.as_deref() {
+        Some("application/pdf") => Kind::Pdf,
+        Some(mime_type)
+            if mime_type.starts_with("text/") || TEXT_APPLICATION_TYPES.contains(&mime_type) =>
+        {
+            Kind::Text
+        }
+        Some(mime_type) => {
+            return Err(format!(
+                "unsupported type {}; attach text files or PDFs",
+                mime_type
+            ))
+        }
+        None if bytes.starts_with(b"%PDF-") => Kind::Pdf,
+        None => Kind::Text,
+    };
+    let text = match kind {
+        Kind::Pdf => pdf::extract_text(&bytes)?,
+        Kind::Text => match String::from_utf8(bytes) {
+            Ok(text) if !text.contains('\0') => text,
+            _ => return Err("is neither UTF-8 text nor a PDF".into()),
+        },
+    };
+    let mime_type = mime_type.unwrap_or_else(|| {
+        match kind {
+            Kind::Pdf => "application/pdf",
+            Kind::Text => "text/plain",
+        }
+        .to_string()
+    });
+
+    let (text, truncated) = match text.char_indices().nth(MAX_ATTACHMENT_CHARS) {
+        Some((end, _)) => (text[..end].to_string(), true),
+        None => (text, false),
+    };
+    Ok(Attachment {
+        info: AttachmentInfo {
+            name,
+            mime_type,
+            bytes: size,
+            chars: text.chars().count(),
+            truncated,
+        },
+        text,
+    })
+}
+
+/// Checks that `name` can go in the `name` attribute of an `<attachment>`.
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!(
+            "name must have between 1 and {} characters",
+            MAX_NAME_CHARS
+        ));
+    }
+    if name
+        .chars()
+        .any(|c| c.is_control() || matches!(c, '"' | '<' | '>'))
+    {
+        return Err(format!(
+            "name {:?} must not contain control characters, quotes or angle brackets",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// `user_text` preceded by the text of each of `attachments`, in
+/// `<attachment>` elements.
+///
+/// ```
+/// # use mcp_server::attachments::{extract_all, with_attachments, AttachmentInput};
+/// let attachments = extract_all(vec![AttachmentInput {
+///     name: "Vault.sol".into(),
+///     text: Some("contract Vault {}".into()),
+///     data: None,
+///     mime_type: None,
+/// }])
+/// .unwrap();
+/// assert_eq!(
+///     with_attachments(&attachments, "Review this."),
+///     "<attachment name=\"Vault.sol\">\ncontract Vault {}\n</attachment>\n\nReview this."
+/// );
+/// ```
+pub fn with_attachments(attachments: &[Attachment], user_text: &str) -> String {
+    let mut text = String::new();
+    for attachment in attachments {
+        // A file can't close its own element early
+        let contents = attachment.text.replace("</attachment", "&lt;/attachment");
+        text.push_str(&format!(
+            "<attachment name=\"{}\">\n{}\n</attachment>\n\n",
+            attachment.info.name,
+            contents.trim_end()
+        ));
+    }
+    text.push_str(user_text);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, text: Option<&str>, data: Option<&str>) -> AttachmentInput {
+        AttachmentInput {
+            name: name.into(),
+            text: text.map(str::to_string),
+            data: data.map(str::to_string),
+            mime_type: None,
+        }
+    }
+
+    #[test]
+    fn extracts_and_limits_attachments() {
+        let long = "x".repeat(MAX_ATTACHMENT_CHARS + 10);
+        let encoded = base64::engine::general_purpose::STANDARD.encode("fn main() {}");
+        let attachments = extract_all(vec![
+            file("main.rs", None, Some(&encoded)),
+            file("notes.txt", Some(&long), None),
+        ])
+        .unwrap();
+        assert_eq!(attachments[0].text, "fn main() {}");
+        assert_eq!(attachments[0].info.mime_type, "text/plain");
+        assert_eq!(attachments[0].info.bytes, 12);
+        assert!(!attachments[0].info.truncated);
+        assert_eq!(attachments[1].info.chars, MAX_ATTACHMENT_CHARS);
+        assert!(attachments[1].info.truncated);
+
+        let binary = base64::engine::general_purpose::STANDARD.encode(b"\xff\xfe\0\x01");
+        assert!(extract_all(vec![file("a.bin", None, Some(&binary))]).is_err());
+        assert!(extract_all(vec![file("a.txt", Some("a"), Some(&encoded))]).is_err());
+        assert!(extract_all(vec![file("a\"b.txt", Some("a"), None)]).is_err());
+        let image = AttachmentInput {
+            mime_type: Some("image/png".into()),
+            ..file("a.png", Some("a"), None)
+        };
+        assert!(extract_all(vec![image]).is_err());
+        assert!(extract_all(vec![file("a.txt", Some("a"), None); MAX_ATTACHMENTS + 1]).is_err());
+
+        let escaped = extract_all(vec![file("a.txt", Some("</attachment>"), None)]).unwrap();
+        assert!(!with_attachments(&escaped, "").contains("\n</attachment>\n</attachment>"));
+    }
+}
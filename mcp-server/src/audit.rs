@@ -0,0 +1,420 @@
+//! Append-only audit log of JSON-RPC calls.
+//!
+//! With `AUDIT_DB` set, every JSON-RPC call (notifications aside) is recorded
+//! in an `audit_log` table in that SQLite file: when it was made, by which
+//! client, the method, the agent it named, the tokens it used, whether it
+//! succeeded, how long it took, its [correlation ID](crate::correlation) and
+//! the [version](crate::prompt_versions) of the agent's prompt that answered.
+//! That settles billing disputes and helps investigate abuse. Triggers reject
+//! any `UPDATE` or `DELETE` of the table, and records are written off the
+//! request path so a slow disk doesn't delay responses.
+//!
+//! The client is the caller's [quota key](crate::quota::quota_key): its
+//! token's subject, prefixed with its tenant. Unauthenticated calls have
+//! none.
+//!
+//! Operators read the log with the `audit_log` method, newest records first:
+//!
+//! ```json
+//! {
+//!   "jsonrpc": "2.0",
+//!   "method": "audit_log",
+//!   "params": { "client_id": "acme/user-1", "from": "2025-01-01T00:00:00Z", "limit": 2 },
+//!   "id": 1
+//! }
+//! ```
+//!
+//! ```json
+//! {
+//!   "records": [
+//!     {
+//!       "id": 812,
+//!       "timestamp": "2025-01-02T09:30:12.481Z",
+//!       "client_id": "acme/user-1",
+//!       "method": "process_text",
+//!       "agent_id": "agent_002",
+//!       "tokens": 412,
+//!       "outcome": "ok",
+//!       "duration_ms": 934,
+//!       "prompt_version": "v2",
+//!       "correlation_id": "5f0c2b7e9a414c6c8d1e2f3a4b5c6d7e"
+//!     }
+//!   ],
+//!   "next_before_id": 812
+//! }
+//! ```
+//!
+//! Pass `next_before_id` back as `before_id` to page through older records;
+//! it is absent once there are none.
+//!
+//! # Environment Variables
+//!
+//! * `AUDIT_DB` - Optional. Path of the SQLite file; created if missing
+
+use crate::agent_db::migrate;
+use crate::error::ServerError;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Schema migrations, applied in order, as in [`crate::agent_db::MIGRATIONS`].
+pub const MIGRATIONS: &[&str] = &[
+    // 1: one row per call, never changed or deleted
+    "CREATE TABLE audit_log (
+        id             INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp      TEXT NOT NULL,
+        client_id      TEXT,
+        method         TEXT NOT NULL,
+        agent_id       TEXT,
+        tokens         INTEGER,
+        outcome        TEXT NOT NULL,
+        error_code     INTEGER,
+        duration_ms    INTEGER NOT NULL,
+        correlation_id TEXT
+    );
+    CREATE INDEX audit_log_client ON audit_log (client_id, id);
+    CREATE INDEX audit_log_correlation ON audit_log (correlation_id);
+    CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+    CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;",
+    // 2: version of the agent's system prompt that answered
+    "ALTER TABLE audit_log ADD COLUMN prompt_version TEXT;",
+];
+
+/// Records returned by `audit_log` when the params set no `limit`.
+pub const DEFAULT_LIMIT: u32 = 100;
+
+/// Most records one `audit_log` call returns.
+pub const MAX_LIMIT: u32 = 1000;
+
+/// Whether a call succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Ok,
+    Error,
+}
+
+impl AuditOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuditOutcome::Ok => "ok",
+            AuditOutcome::Error => "error",
+        }
+    }
+}
+
+/// One recorded call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the log, assigned when the record is stored
+    pub id: i64,
+    /// When the call was received, RFC 3339 in UTC
+    pub timestamp: String,
+    /// Quota key of the caller, if authenticated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// JSON-RPC method
+    pub method: String,
+    /// Agent named by the params, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    /// Tokens the call used, if it reported them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<u64>,
+    pub outcome: AuditOutcome,
+    /// JSON-RPC error code of a failed call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    /// Time taken to answer
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Version of the agent's system prompt that answered, if it has versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+/// The format timestamps are stored in, which sorts as text.
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Parameters of the `audit_log` method; every filter is optional.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditQueryParams {
+    /// Earliest timestamp, RFC 3339
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Timestamp the records must be before, RFC 3339
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    #[serde(default)]
+    pub prompt_version: Option<String>,
+    #[serde(default)]
+    pub outcome: Option<AuditOutcome>,
+    /// Only records older than this ID, to page through the log
+    #[serde(default)]
+    pub before_id: Option<i64>,
+    /// Most records to return (default: 100, at most 1000)
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+/// Result of the `audit_log` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditQueryResult {
+    /// Matching records, newest first
+    pub records: Vec<AuditRecord>,
+    /// `before_id` of the next page, absent on the last one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_before_id: Option<i64>,
+}
+
+/// A SQLite database of JSON-RPC calls.
+///
+/// Cheap to clone; clones share the connection.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the log at `path` and migrates it.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut conn = Connection::open(path)
+            .map_err(|e| format!("Failed to open audit log {}: {}", path.display(), e))?;
+        migrate(&mut conn, MIGRATIONS, "Audit log")
+            .map_err(|e| format!("Failed to migrate audit log: {}", e))?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Opens the log named by `AUDIT_DB`, if set.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var_os("AUDIT_DB").filter(|p| !p.is_empty()) {
+            Some(path) => Self::open(Path::new(&path)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `record`, ignoring its `id`, and returns the ID it was given.
+    ///
+    /// Blocks on SQLite; see [`record`](Self::record).
+    pub fn append(&self, record: &AuditRecord) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO audit_log
+                 (timestamp, client_id, method, agent_id, tokens, outcome, error_code,
+                  duration_ms, correlation_id, prompt_version)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                record.timestamp,
+                record.client_id,
+                record.method,
+                record.agent_id,
+                record.tokens.map(|tokens| tokens as i64),
+                record.outcome.as_str(),
+                record.error_code,
+                record.duration_ms as i64,
+                record.correlation_id,
+                record.prompt_version,
+            ],
+        )
+        .map_err(|e| format!("Failed to write audit record: {}", e))?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Stores `record` on a blocking thread, logging a failure.
+    pub fn record(&self, record: AuditRecord) {
+        let log = self.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = log.append(&record) {
+                tracing::error!("{}", e);
+            }
+        });
+    }
+
+    /// The records matching `params`, newest first.
+    ///
+    /// Fails with invalid params on an unparsable timestamp, or a storage
+    /// error if the log can't be read.
+    pub fn query(&self, params: &AuditQueryParams) -> Result<AuditQueryResult, ServerError> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let mut filter = |condition: &'static str, value: SqlValue| {
+            conditions.push(condition);
+            values.push(value);
+        };
+        for (condition, time) in [
+            ("timestamp >= ?", &params.from),
+            ("timestamp < ?", &params.to),
+        ] {
+            if let Some(time) = time {
+                let time = DateTime::parse_from_rfc3339(time).map_err(|e| {
+                    ServerError::InvalidParams(format!("invalid timestamp {}: {}", time, e))
+                })?;
+                filter(condition, format_timestamp(time.into()).into());
+            }
+        }
+        for (condition, value) in [
+            ("client_id = ?", &params.client_id),
+            ("method = ?", &params.method),
+            ("agent_id = ?", &params.agent_id),
+            ("correlation_id = ?", &params.correlation_id),
+            ("prompt_version = ?", &params.prompt_version),
+        ] {
+            if let Some(value) = value {
+                filter(condition, value.clone().into());
+            }
+        }
+        if let Some(outcome) = params.outcome {
+            filter("outcome = ?", outcome.as_str().to_string().into());
+        }
+        if let Some(before_id) = params.before_id {
+            filter("id < ?", before_id.into());
+        }
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+        let mut sql = "SELECT id, timestamp, client_id, method, agent_id, tokens, outcome,
+                              error_code, duration_ms, correlation_id, prompt_version
+                       FROM audit_log"
+            .to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(&format!(" ORDER BY id DESC LIMIT {}", limit));
+
+        let conn = self.conn.lock().unwrap();
+        let records = conn
+            .prepare(&sql)
+            .and_then(|mut stmt| {
+                stmt.query_map(params_from_iter(values), |row| {
+                    let outcome: String = row.get(6)?;
+                    Ok(AuditRecord {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        client_id: row.get(2)?,
+                        method: row.get(3)?,
+                        agent_id: row.get(4)?,
+                        tokens: row.get::<_, Option<i64>>(5)?.map(|tokens| tokens as u64),
+                        outcome: match outcome.as_str() {
+                            "ok" => AuditOutcome::Ok,
+                            _ => AuditOutcome::Error,
+                        },
+                        error_code: row.get(7)?,
+                        duration_ms: row.get::<_, i64>(8)? as u64,
+                        correlation_id: row.get(9)?,
+                        prompt_version: row.get(10)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| ServerError::Storage(format!("Failed to read the audit log: {}", e)))?;
+        let next_before_id = (records.len() == limit as usize)
+            .then(|| records.last().map(|r| r.id))
+            .flatten();
+        Ok(AuditQueryResult {
+            records,
+            next_before_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(client_id: &str, method: &str, outcome: AuditOutcome) -> AuditRecord {
+        AuditRecord {
+            id: 0,
+            timestamp: format_timestamp(Utc::now()),
+            client_id: Some(client_id.into()),
+            method: method.into(),
+            agent_id: Some("agent_002".into()),
+            tokens: Some(42),
+            outcome,
+            error_code: (outcome == AuditOutcome::Error).then_some(-32603),
+            duration_ms: 120,
+            correlation_id: Some("gw-42".into()),
+            prompt_version: (method == "process_text").then(|| "v2".into()),
+        }
+    }
+
+    #[test]
+    fn filters_and_pages_newest_first() {
+        let path = std::env::temp_dir().join(format!("audit-{}.db", uuid::Uuid::new_v4()));
+        let log = AuditLog::open(&path).unwrap();
+        log.append(&record("acme/u1", "process_text", AuditOutcome::Ok))
+            .unwrap();
+        log.append(&record("acme/u2", "process_text", AuditOutcome::Error))
+            .unwrap();
+        let last = log
+            .append(&record("acme/u1", "run_pipeline", AuditOutcome::Ok))
+            .unwrap();
+
+        let page = log
+            .query(&AuditQueryParams {
+                client_id: Some("acme/u1".into()),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.records[0].method, "run_pipeline");
+        assert_eq!(page.next_before_id, Some(last));
+        let page = log
+            .query(&AuditQueryParams {
+                client_id: Some("acme/u1".into()),
+                before_id: page.next_before_id,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(page.records[0].method, "process_text");
+        assert_eq!(page.next_before_id, None);
+        let v2 = log
+            .query(&AuditQueryParams {
+                prompt_version: Some("v2".into()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(v2.records.len(), 2);
+        assert_eq!(v2.records[0].prompt_version.as_deref(), Some("v2"));
+
+        let errors = log
+            .query(&AuditQueryParams {
+                outcome: Some(AuditOutcome::Error),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(errors.records[0].error_code, Some(-32603));
+        assert!(log
+            .query(&AuditQueryParams {
+                from: Some("yesterday".into()),
+                ..Default::default()
+            })
+            .is_err());
+
+        // Stored records can't be changed or removed
+        let conn = log.conn.lock().unwrap();
+        assert!(conn.execute("DELETE FROM audit_log", []).is_err());
+        assert!(conn.execute("UPDATE audit_log SET tokens = 0", []).is_err());
+        drop(conn);
+        drop(log);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
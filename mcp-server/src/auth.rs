@@ -0,0 +1,291 @@
+//! JWT authentication.
+//!
+//! With a key configured, every JSON-RPC request over HTTP must carry
+//! `Authorization: Bearer <jwt>`, signed with HS256 or RS256 and checked for
+//! expiry and, when configured, issuer and audience. Requests without a valid
+//! token are answered `401` with error `-32001`; requests presenting the admin
+//! token are let through as before. The token's subject and tenant claims
+//! become the request's [`Identity`], which is available to everything that
+//! runs while the request is handled through [`current_identity`], for quota
+//! and audit decisions. A tenant scopes the agents and sessions the caller
+//! can reach (see [`current_tenant`]). Over mutual TLS, a client certificate
+//! identifies requests sent without a bearer token instead (see
+//! [`crate::tls`]). stdio requests come from the launching process and carry
+//! no identity.
+//!
+//! # Environment Variables
+//!
+//! * `JWT_HS256_SECRET` - Optional. Shared secret of HS256 tokens
+//! * `JWT_RS256_PUBLIC_KEY_FILE` - Optional. PEM file with the RSA public key
+//!   of RS256 tokens
+//! * `JWT_ISSUER` - Optional. Required `iss` claim
+//! * `JWT_AUDIENCE` - Optional. Required `aud` claim
+//! * `JWT_TENANT_CLAIM` - Optional. Claim naming the tenant (default: `tenant`)
+//! * `JWT_LEEWAY_SECS` - Optional. Clock skew allowed on `exp` and `nbf`
+//!   (default: 60)
+//!
+//! Authentication is off unless one of the keys is set.
+
+use axum::http::{header, HeaderMap};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::future::Future;
+
+/// Who sent a request, from the claims of its token or its client
+/// certificate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Identity {
+    /// The `sub` claim, or the certificate's URI or common name
+    pub subject: String,
+    /// The tenant claim or the certificate's organization, if present
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+/// What a caller may do beyond the methods open to everyone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    /// Only the public methods
+    #[default]
+    None,
+    /// Also the admin methods that change nothing
+    ReadOnly,
+    /// Every method
+    Admin,
+}
+
+tokio::task_local! {
+    static IDENTITY: Option<Identity>;
+}
+
+/// Runs `future` on behalf of `identity`.
+pub async fn with_identity<F: Future>(identity: Option<Identity>, future: F) -> F::Output {
+    IDENTITY.scope(identity, future).await
+}
+
+/// Identity of the request being handled; `None` for unauthenticated ones
+/// and outside of a request.
+pub fn current_identity() -> Option<Identity> {
+    IDENTITY.try_with(Clone::clone).ok().flatten()
+}
+
+/// Tenant of the request being handled, if its identity names one.
+///
+/// Agents owned by other tenants and their sessions are out of its reach;
+/// requests without a tenant only reach shared agents and untenanted
+/// sessions.
+pub fn current_tenant() -> Option<String> {
+    current_identity().and_then(|identity| identity.tenant)
+}
+
+/// Checks bearer tokens against the configured keys.
+#[derive(Clone)]
+pub struct JwtVerifier {
+    keys: Vec<(Algorithm, DecodingKey)>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    tenant_claim: String,
+    leeway: u64,
+}
+
+impl JwtVerifier {
+    /// Accepts HS256 tokens signed with `secret`.
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self::with_keys(vec![(Algorithm::HS256, DecodingKey::from_secret(secret))])
+    }
+
+    /// Accepts RS256 tokens signed with the key of the PEM-encoded `public_key`.
+    pub fn rs256(public_key: &[u8]) -> Result<Self, String> {
+        Ok(Self::with_keys(vec![(
+            Algorithm::RS256,
+            rsa_key(public_key)?,
+        )]))
+    }
+
+    fn with_keys(keys: Vec<(Algorithm, DecodingKey)>) -> Self {
+        Self {
+            keys,
+            issuer: None,
+            audience: None,
+            tenant_claim: "tenant".to_string(),
+            leeway: 60,
+        }
+    }
+
+    /// Also requires the `iss` claim to be `issuer`.
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Also requires the `aud` claim to include `audience`.
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Reads the tenant from the claim `claim` instead of `tenant`.
+    pub fn with_tenant_claim(mut self, claim: impl Into<String>) -> Self {
+        self.tenant_claim = claim.into();
+        self
+    }
+
+    /// Builds the verifier from the environment, or `None` if neither key is
+    /// set.
+    ///
+    /// Fails if the public key file can't be read or parsed.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let mut keys = Vec::new();
+        if let Some(secret) = var("JWT_HS256_SECRET") {
+            keys.push((
+                Algorithm::HS256,
+                DecodingKey::from_secret(secret.as_bytes()),
+            ));
+        }
+        if let Some(path) = var("JWT_RS256_PUBLIC_KEY_FILE") {
+            let pem =
+                std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            keys.push((Algorithm::RS256, rsa_key(&pem)?));
+        }
+        if keys.is_empty() {
+            return Ok(None);
+        }
+        let mut verifier = Self::with_keys(keys);
+        verifier.issuer = var("JWT_ISSUER");
+        verifier.audience = var("JWT_AUDIENCE");
+        if let Some(claim) = var("JWT_TENANT_CLAIM") {
+            verifier.tenant_claim = claim;
+        }
+        if let Some(leeway) = var("JWT_LEEWAY_SECS").and_then(|v| v.trim().parse().ok()) {
+            verifier.leeway = leeway;
+        }
+        Ok(Some(verifier))
+    }
+
+    /// Verifies the bearer token in `headers`.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Identity, String> {
+        let token = bearer_token(headers).ok_or("missing bearer token")?;
+        self.verify(token)
+    }
+
+    /// Checks the signature and claims of `token` and returns its identity.
+    pub fn verify(&self, token: &str) -> Result<Identity, String> {
+        let header = jsonwebtoken::decode_header(token).map_err(|e| e.to_string())?;
+        let (algorithm, key) = self
+            .keys
+            .iter()
+            .find(|(algorithm, _)| *algorithm == header.alg)
+            .ok_or_else(|| format!("{:?} tokens are not accepted", header.alg))?;
+
+        let mut validation = Validation::new(*algorithm);
+        validation.leeway = self.leeway;
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(token, key, &validation)
+            .map_err(|e| e.to_string())?
+            .claims;
+
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or("sub must be a string")?;
+        Ok(Identity {
+            subject: subject.to_string(),
+            tenant: claims
+                .get(&self.tenant_claim)
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+fn rsa_key(pem: &[u8]) -> Result<DecodingKey, String> {
+    DecodingKey::from_rsa_pem(pem).map_err(|e| format!("Invalid RS256 public key: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn token(claims: Value) -> String {
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap()
+    }
+
+    fn in_an_hour() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    #[test]
+    fn verifies_claims_and_extracts_the_identity() {
+        let verifier = JwtVerifier::hs256(b"secret")
+            .with_issuer("https://auth.example.com")
+            .with_audience("mcp-server")
+            .with_tenant_claim("org");
+        let claims = json!({
+            "sub": "user-1",
+            "org": "acme",
+            "iss": "https://auth.example.com",
+            "aud": "mcp-server",
+            "exp": in_an_hour(),
+        });
+        assert_eq!(
+            verifier.verify(&token(claims.clone())).unwrap(),
+            Identity {
+                subject: "user-1".to_string(),
+                tenant: Some("acme".to_string()),
+            }
+        );
+
+        let mut wrong_audience = claims.clone();
+        wrong_audience["aud"] = json!("another-service");
+        assert!(verifier.verify(&token(wrong_audience)).is_err());
+        let mut expired = claims.clone();
+        expired["exp"] = json!(chrono::Utc::now().timestamp() - 3600);
+        assert!(verifier.verify(&token(expired)).is_err());
+        assert!(JwtVerifier::hs256(b"other").verify(&token(claims)).is_err());
+    }
+
+    #[test]
+    fn rejects_unconfigured_algorithms_and_missing_tokens() {
+        let verifier = JwtVerifier::hs256(b"secret");
+        let claims = json!({ "sub": "user-1", "exp": in_an_hour() });
+        let hs384 = encode(
+            &Header::new(Algorithm::HS384),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        let err = verifier.verify(&hs384).unwrap_err();
+        assert!(err.contains("HS384"), "{}", err);
+
+        let mut headers = HeaderMap::new();
+        assert!(verifier.authenticate(&headers).is_err());
+        let bearer = format!("Bearer {}", token(claims));
+        headers.insert(header::AUTHORIZATION, bearer.parse().unwrap());
+        assert_eq!(verifier.authenticate(&headers).unwrap().tenant, None);
+    }
+}
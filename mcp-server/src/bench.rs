@@ -0,0 +1,269 @@
+//! The `bench` subcommand: capacity planning without a running server.
+//!
+//! ```text
+//! mcp-server bench --agent agent_002 --requests 100 --concurrency 10
+//! ```
+//!
+//! sends `process_text` requests straight to the JSON-RPC dispatcher, with
+//! the configuration the server would run with, and prints latency
+//! percentiles, request and token throughput, and errors by code:
+//!
+//! ```text
+//! Benchmarked agent_002: 100 requests, concurrency 10, in 14.82s
+//! Throughput: 6.7 req/s, 1843.2 tokens/s (27316 tokens)
+//! Latency: p50 1.38s, p90 2.07s, p99 2.71s, max 2.94s
+//! Cache hits: 0
+//! Errors: 2 / 100
+//!   -32008 x2: The AI provider is rate limiting requests, retry later
+//! ```
+//!
+//! Requests skip HTTP, authentication and load shedding, and are made
+//! anonymously, so the numbers are those of the agents, the providers and
+//! everything the dispatcher runs around them. Quotas, the response cache,
+//! [coalescing](crate::coalesce) and the audit log apply as they would to any
+//! anonymous caller.
+
+use crate::auth::Access;
+use crate::i18n::Locale;
+use crate::models::JsonRpcRequest;
+use crate::{handlers, AppState};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Text sent when `--text` is not given.
+pub const DEFAULT_TEXT: &str = "Say hello in five words.";
+
+/// Flags of the `bench` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchArgs {
+    /// ID of the agent to ask
+    pub agent_id: String,
+    /// Number of requests to send
+    pub requests: usize,
+    /// Most requests running at once
+    pub concurrency: usize,
+    /// User text of every request
+    pub text: String,
+}
+
+/// How one request went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// Time the dispatcher took to answer
+    pub latency: Duration,
+    /// Tokens the reply reported using
+    pub tokens: Option<u64>,
+    /// Whether the reply came from the response cache, or was shared by an
+    /// identical request
+    pub cache_hit: bool,
+    /// Code and message of the error, if the request failed
+    pub error: Option<(i32, String)>,
+}
+
+/// Errors of one JSON-RPC code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorCount {
+    /// Requests failing with the code
+    pub count: usize,
+    /// Message of the first of them
+    pub message: String,
+}
+
+/// Outcome of a benchmark run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// The flags the run was made with
+    pub args: BenchArgs,
+    /// Wall-clock time of the whole run
+    pub elapsed: Duration,
+    /// Latency of every request, sorted
+    pub latencies: Vec<Duration>,
+    /// Tokens used by all the replies
+    pub tokens: u64,
+    /// Replies answered from the response cache or shared by identical
+    /// requests
+    pub cache_hits: usize,
+    /// Failed requests by error code
+    pub errors: BTreeMap<i32, ErrorCount>,
+}
+
+impl BenchReport {
+    /// Sums up the samples of a run.
+    pub fn new(args: BenchArgs, elapsed: Duration, samples: Vec<Sample>) -> Self {
+        let mut latencies: Vec<_> = samples.iter().map(|s| s.latency).collect();
+        latencies.sort();
+        let mut errors = BTreeMap::new();
+        for (code, message) in samples.iter().filter_map(|s| s.error.clone()) {
+            errors
+                .entry(code)
+                .or_insert(ErrorCount { count: 0, message })
+                .count += 1;
+        }
+        Self {
+            args,
+            elapsed,
+            latencies,
+            tokens: samples.iter().filter_map(|s| s.tokens).sum(),
+            cache_hits: samples.iter().filter(|s| s.cache_hit).count(),
+            errors,
+        }
+    }
+
+    /// The `p`th latency percentile, from 0 to 100.
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    /// Number of failed requests.
+    pub fn error_count(&self) -> usize {
+        self.errors.values().map(|e| e.count).sum()
+    }
+
+    /// Requests completed per second.
+    pub fn requests_per_sec(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Tokens used per second.
+    pub fn tokens_per_sec(&self) -> f64 {
+        self.tokens as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Benchmarked {}: {} requests, concurrency {}, in {:.2?}",
+            self.args.agent_id,
+            self.latencies.len(),
+            self.args.concurrency,
+            self.elapsed
+        )?;
+        writeln!(
+            f,
+            "Throughput: {:.1} req/s, {:.1} tokens/s ({} tokens)",
+            self.requests_per_sec(),
+            self.tokens_per_sec(),
+            self.tokens
+        )?;
+        writeln!(
+            f,
+            "Latency: p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.percentile(100.0)
+        )?;
+        writeln!(f, "Cache hits: {}", self.cache_hits)?;
+        write!(
+            f,
+            "Errors: {} / {}",
+            self.error_count(),
+            self.latencies.len()
+        )?;
+        for (code, error) in &self.errors {
+            write!(f, "\n  {} x{}: {}", code, error.count, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends `args.requests` `process_text` requests to the dispatcher, at most
+/// `args.concurrency` at a time.
+///
+/// Fails without sending anything if the agent doesn't exist or no provider
+/// is configured.
+pub async fn run(state: Arc<AppState>, args: BenchArgs) -> Result<BenchReport, String> {
+    if state.agents.get(&args.agent_id).is_none() {
+        return Err(format!("Agent not found: {}", args.agent_id));
+    }
+    if state.providers.default_provider().is_none() {
+        return Err("No AI provider is configured".to_string());
+    }
+
+    let semaphore = Arc::new(Semaphore::new(args.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    let started = Instant::now();
+    for i in 0..args.requests {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let state = state.clone();
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "process_text".to_string(),
+            params: Some(json!({ "agent_id": args.agent_id, "user_text": args.text })),
+            id: Some(json!(i)),
+        };
+        tasks.spawn(async move {
+            let _permit = permit;
+            let request_start = Instant::now();
+            let response = handlers::dispatch(&state, request, Locale::default(), Access::None)
+                .await
+                .expect("process_text requests with an id are answered");
+            let latency = request_start.elapsed();
+            let metadata = response.result.as_ref().map(|result| &result["metadata"]);
+            Sample {
+                latency,
+                tokens: metadata.and_then(|m| m["tokens_used"].as_u64()),
+                cache_hit: metadata.is_some_and(|m| m["cache"] == "hit" || m["cache"] == "shared"),
+                error: response.error.map(|e| (e.code, e.message)),
+            }
+        });
+    }
+
+    let mut samples = Vec::with_capacity(args.requests);
+    while let Some(joined) = tasks.join_next().await {
+        samples.push(joined.map_err(|e| format!("Benchmark request failed: {}", e))?);
+    }
+    Ok(BenchReport::new(args, started.elapsed(), samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_up_samples() {
+        let args = BenchArgs {
+            agent_id: "agent_002".into(),
+            requests: 4,
+            concurrency: 2,
+            text: DEFAULT_TEXT.into(),
+        };
+        let sample = |ms, tokens, error: Option<(i32, &str)>| Sample {
+            latency: Duration::from_millis(ms),
+            tokens,
+            cache_hit: ms == 10,
+            error: error.map(|(code, message)| (code, message.to_string())),
+        };
+        let report = BenchReport::new(
+            args,
+            Duration::from_secs(2),
+            vec![
+                sample(400, Some(120), None),
+                sample(10, Some(80), None),
+                sample(300, None, Some((-32008, "Rate limited"))),
+                sample(200, None, Some((-32008, "Rate limited"))),
+            ],
+        );
+        assert_eq!(report.percentile(50.0), Duration::from_millis(200));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(400));
+        assert_eq!(report.requests_per_sec(), 2.0);
+        assert_eq!(report.tokens_per_sec(), 100.0);
+        assert_eq!(report.cache_hits, 1);
+        assert_eq!(report.error_count(), 2);
+
+        let printed = report.to_string();
+        assert!(printed.contains("Throughput: 2.0 req/s, 100.0 tokens/s (200 tokens)"));
+        assert!(printed.contains("Errors: 2 / 4\n  -32008 x2: Rate limited"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Per-request token and cost budgets.
+//!
+//! `process_text` and `submit_text` accept a `max_total_tokens` or
+//! `max_cost_usd` budget, or both, capping what the request may spend across
+//! all of its provider calls, server tool rounds and guardrail regenerations
+//! included:
+//!
+//! - Before the first call, the oldest history messages are dropped until the
+//!   prompt leaves room for a reply of the request's `max_tokens`, or
+//!   [`DEFAULT_REPLY_TOKENS`] without one. A request whose prompt doesn't fit
+//!   even without history is rejected with `-32009` before any call.
+//! - Each call's `max_tokens` is capped to what is left, so a reply is cut
+//!   short at the budget (`finish_reason` `length`) rather than running over.
+//! - Once the budget is spent, no further call is made: a reply breaking a
+//!   [guardrail](crate::guardrails) is fixed up rather than asked for again,
+//!   and a server tool round fails with `-32009`.
+//!
+//! Prompts are estimated at four characters per token, and costs from the
+//! model [prices](crate::accounting); `max_cost_usd` is rejected for a model
+//! without a price. Replies from the response cache cost nothing.
+
+use crate::accounting::ModelPrice;
+use serde::{Deserialize, Serialize};
+use serde_json::Number;
+
+/// Reply tokens the history leaves room for when the request has no
+/// `max_tokens`.
+pub const DEFAULT_REPLY_TOKENS: u32 = 256;
+
+/// Most a request may spend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    /// Optional most tokens, prompts and replies, of all the request's calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_tokens: Option<u32>,
+    /// Optional most estimated cost in USD of all the request's calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost_usd: Option<f64>,
+}
+
+/// What a request spent so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Spent {
+    /// Tokens used by its calls
+    pub tokens: u64,
+    /// Estimated cost of its calls in USD
+    pub cost_usd: f64,
+}
+
+/// Why a request was stopped, sent as the error's `data`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BudgetExceeded {
+    /// Name of the limit reached, `max_total_tokens` or `max_cost_usd`
+    pub limit: &'static str,
+    /// Value of that limit
+    pub allowed: Number,
+    /// Spent so far plus the estimated prompt of the next call
+    pub needed: Number,
+}
+
+impl Budget {
+    /// Checks that the limits are positive.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_total_tokens == Some(0) {
+            return Err("max_total_tokens must be at least 1".to_string());
+        }
+        match self.max_cost_usd {
+            Some(cost) if !(cost.is_finite() && cost > 0.0) => {
+                Err(format!("max_cost_usd must be positive, got {}", cost))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Most prompt tokens a call may send after `spent` and still leave room
+    /// for `reply` tokens, at `price`; `None` without a limit.
+    pub fn prompt_allowance(
+        &self,
+        spent: Spent,
+        price: Option<ModelPrice>,
+        reply: u32,
+    ) -> Option<u64> {
+        let tokens = self
+            .max_total_tokens
+            .map(|max| u64::from(max).saturating_sub(spent.tokens + u64::from(reply)));
+        let cost = self
+            .max_cost_usd
+            .zip(price)
+            .filter(|(_, price)| price.input_per_mtok > 0.0)
+            .map(|(max, price)| {
+                let left = max - spent.cost_usd - price.cost(Some(0), reply);
+                (left.max(0.0) / price.input_per_mtok * 1_000_000.0) as u64
+            });
+        match (tokens, cost) {
+            (Some(tokens), Some(cost)) => Some(tokens.min(cost)),
+            (tokens, cost) => tokens.or(cost),
+        }
+    }
+
+    /// Most reply tokens a call with a prompt of `prompt_tokens` may use
+    /// after `spent`, at `price`; `None` without a limit. Fails with the limit
+    /// the call would break if nothing is left.
+    pub fn reply_allowance(
+        &self,
+        spent: Spent,
+        price: Option<ModelPrice>,
+        prompt_tokens: u32,
+    ) -> Result<Option<u32>, BudgetExceeded> {
+        let mut allowance: Option<u64> = None;
+        if let Some(max) = self.max_total_tokens {
+            let needed = spent.tokens + u64::from(prompt_tokens);
+            if needed >= u64::from(max) {
+                return Err(BudgetExceeded {
+                    limit: "max_total_tokens",
+                    allowed: max.into(),
+                    needed: needed.into(),
+                });
+            }
+            allowance = Some(u64::from(max) - needed);
+        }
+        if let (Some(max), Some(price)) = (self.max_cost_usd, price) {
+            let needed = spent.cost_usd + price.cost(Some(prompt_tokens), prompt_tokens);
+            let left = max - needed;
+            let tokens = match price.output_per_mtok {
+                free if free <= 0.0 => u64::MAX,
+                per_mtok => (left.max(0.0) / per_mtok * 1_000_000.0) as u64,
+            };
+            if left <= 0.0 || tokens == 0 {
+                return Err(BudgetExceeded {
+                    limit: "max_cost_usd",
+                    allowed: Number::from_f64(max).unwrap_or_else(|| 0.into()),
+                    needed: Number::from_f64(needed).unwrap_or_else(|| 0.into()),
+                });
+            }
+            allowance = Some(allowance.map_or(tokens, |allowance| allowance.min(tokens)));
+        }
+        Ok(allowance.map(|tokens| tokens.min(u64::from(u32::MAX)) as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_room_for_the_reply_and_stops_when_spent() {
+        let price = ModelPrice {
+            input_per_mtok: 1.0,
+            output_per_mtok: 2.0,
+        };
+        let tokens = Budget {
+            max_total_tokens: Some(1000),
+            max_cost_usd: None,
+        };
+        assert_eq!(
+            tokens.prompt_allowance(Spent::default(), None, 256),
+            Some(744)
+        );
+        assert_eq!(
+            tokens.reply_allowance(Spent::default(), None, 600),
+            Ok(Some(400))
+        );
+        let spent = Spent {
+            tokens: 900,
+            cost_usd: 0.0,
+        };
+        let exceeded = tokens.reply_allowance(spent, None, 100).unwrap_err();
+        assert_eq!(exceeded.limit, "max_total_tokens");
+        assert_eq!(exceeded.needed, 1000.into());
+
+        // 0.001 USD buys 1000 prompt tokens, or 500 reply tokens
+        let cost = Budget {
+            max_total_tokens: None,
+            max_cost_usd: Some(0.001),
+        };
+        assert_eq!(
+            cost.prompt_allowance(Spent::default(), Some(price), 250),
+            Some(500)
+        );
+        assert_eq!(
+            cost.reply_allowance(Spent::default(), Some(price), 500),
+            Ok(Some(250))
+        );
+        assert_eq!(
+            cost.reply_allowance(Spent::default(), Some(price), 1000)
+                .unwrap_err()
+                .limit,
+            "max_cost_usd"
+        );
+        let both = Budget {
+            max_total_tokens: Some(600),
+            ..cost
+        };
+        assert_eq!(
+            both.reply_allowance(Spent::default(), Some(price), 500),
+            Ok(Some(100))
+        );
+
+        assert_eq!(
+            Budget::default().reply_allowance(spent, Some(price), 10),
+            Ok(None)
+        );
+        assert!(Budget {
+            max_cost_usd: Some(-1.0),
+            ..Budget::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
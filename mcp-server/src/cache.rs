@@ -0,0 +1,338 @@
+//! Response caching.
+//!
+//! Demos and FAQ-style bots send the same question over and over. With a TTL
+//! configured, [`ResponseCache`] keeps each agent reply under a hash of the
+//! caller's tenant, the agent, the model, the normalized prompt (trimmed, whitespace collapsed,
+//! lowercased), the conversation history and the sampling settings, and
+//! answers repeats from memory without calling a provider. Replies report
+//! `"cache": "hit"` or `"miss"` in their metadata.
+//!
+//! Requests with a `session_id` or client `tools`, and replies that requested
+//! or ran tool calls, are never cached: their outcome depends on more than
+//! the prompt. The key covers the agent's whole definition, so editing an
+//! agent retires its old entries. The admin `flush_cache` method empties the
+//! cache, or just one agent's entries.
+//!
+//! # Environment Variables
+//!
+//! * `RESPONSE_CACHE_TTL_SECS` - Optional. How long replies are reused;
+//!   unset or `0` disables the cache
+//! * `RESPONSE_CACHE_MAX_ENTRIES` - Optional. Most replies kept; the oldest
+//!   is evicted first (default: 1024)
+
+use crate::models::{Agent, GenerationParams, Message, ProcessTextResult};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long and how many replies are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// How long a reply is reused
+    pub ttl: Duration,
+    /// Most replies kept
+    pub max_entries: usize,
+}
+
+impl CacheConfig {
+    /// Reads the settings, or `None` if `RESPONSE_CACHE_TTL_SECS` is unset or `0`.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok()?.trim().parse::<u64>().ok();
+        let ttl = var("RESPONSE_CACHE_TTL_SECS").filter(|n| *n > 0)?;
+        Some(Self {
+            ttl: Duration::from_secs(ttl),
+            max_entries: var("RESPONSE_CACHE_MAX_ENTRIES")
+                .filter(|n| *n > 0)
+                .unwrap_or(1024) as usize,
+        })
+    }
+}
+
+/// Whether a reply came from the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheStatus {
+    /// Answered from the cache, without a provider call
+    Hit,
+    /// Answered by a provider, and cached if eligible
+    Miss,
+    /// Answered by an identical request in flight, without a provider call,
+    /// see [`crate::coalesce`]
+    Shared,
+}
+
+/// Parameters of the admin `flush_cache` method.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FlushCacheParams {
+    /// Only drop this agent's replies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+}
+
+/// Result of the `flush_cache` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlushCacheResult {
+    /// Number of replies dropped
+    pub flushed: usize,
+}
+
+struct Entry {
+    agent_id: String,
+    result: ProcessTextResult,
+    stored: Instant,
+}
+
+struct Cache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+/// Cached agent replies, by key. Cheap to clone; the default is disabled.
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+    inner: Option<Arc<Cache>>,
+}
+
+impl ResponseCache {
+    /// Creates an empty cache, or a disabled one without `config`.
+    pub fn new(config: Option<CacheConfig>) -> Self {
+        Self {
+            inner: config.map(|config| {
+                Arc::new(Cache {
+                    config,
+                    entries: Mutex::new(HashMap::new()),
+                })
+            }),
+        }
+    }
+
+    /// Whether replies are cached at all.
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// The cached reply under `key`, if it hasn't expired.
+    pub fn get(&self, key: &str) -> Option<ProcessTextResult> {
+        let cache = self.inner.as_ref()?;
+        let mut entries = cache.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if entry.stored.elapsed() < cache.config.ttl => Some(entry.result.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Keeps `result` under `key`, evicting expired replies and then the
+    /// oldest ones to stay within `max_entries`.
+    pub fn insert(&self, key: String, result: &ProcessTextResult) {
+        let Some(cache) = &self.inner else {
+            return;
+        };
+        let mut entries = cache.entries.lock().unwrap();
+        let ttl = cache.config.ttl;
+        entries.retain(|_, entry| entry.stored.elapsed() < ttl);
+        while entries.len() >= cache.config.max_entries && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.insert(
+            key,
+            Entry {
+                agent_id: result.agent_id.clone(),
+                result: result.clone(),
+                stored: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops every reply, or only `agent_id`'s, and returns how many.
+    pub fn flush(&self, agent_id: Option<&str>) -> usize {
+        let Some(cache) = &self.inner else {
+            return 0;
+        };
+        let mut entries = cache.entries.lock().unwrap();
+        let before = entries.len();
+        match agent_id {
+            Some(agent_id) => entries.retain(|_, entry| entry.agent_id != agent_id),
+            None => entries.clear(),
+        }
+        before - entries.len()
+    }
+
+    /// Number of replies held, including expired ones not yet evicted.
+    pub fn len(&self) -> usize {
+        self.inner
+            .as_ref()
+            .map_or(0, |cache| cache.entries.lock().unwrap().len())
+    }
+
+    /// Whether no reply is held.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The cache key of a request: the hex SHA-256 of everything that shapes
+/// the reply.
+pub fn cache_key(
+    tenant: Option<&str>,
+    agent: &Agent,
+    model: &str,
+    user_text: &str,
+    history: Option<&[Message]>,
+    generation: &GenerationParams,
+) -> String {
+    let material = serde_json::json!({
+        "tenant": tenant,
+        "agent": agent,
+        "model": model,
+        "prompt": normalize_prompt(user_text),
+        "history": history,
+        "generation": generation,
+    });
+    Sha256::digest(material.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Trims `text`, collapses runs of whitespace and lowercases it, so trivially
+/// different phrasings of a question share an entry.
+///
+/// ```
+/// # use mcp_server::cache::normalize_prompt;
+/// assert_eq!(normalize_prompt("  What is\n\tGAS? "), "what is gas?");
+/// ```
+pub fn normalize_prompt(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::builtin_agents;
+    use crate::models::ProcessingMetadata;
+
+    fn reply(agent_id: &str) -> ProcessTextResult {
+        ProcessTextResult {
+            agent_id: agent_id.to_string(),
+            reply_text: "Gas is the fee for computation.".to_string(),
+            tool_calls: Vec::new(),
+            metadata: ProcessingMetadata {
+                provider: "groq".to_string(),
+                model: "llama-3.3-70b-versatile".to_string(),
+                tokens_used: Some(42),
+                cost_usd: None,
+                processing_time_ms: 800,
+                finish_reason: None,
+                confidence: None,
+                seed: None,
+                cache: None,
+                correlation_id: None,
+                sources: Vec::new(),
+                attachments: Vec::new(),
+                language: None,
+                prompt_version: None,
+                no_store: false,
+            },
+        }
+    }
+
+    #[test]
+    fn keys_ignore_whitespace_and_case_but_not_settings() {
+        let agent = builtin_agents()[1].clone();
+        let generation = GenerationParams::default();
+        let key = |text: &str, generation: &GenerationParams| {
+            cache_key(
+                None,
+                &agent,
+                "llama-3.3-70b-versatile",
+                text,
+                None,
+                generation,
+            )
+        };
+        assert_eq!(
+            key("What is gas?", &generation),
+            key("  what is   GAS? ", &generation)
+        );
+        assert_ne!(
+            key("What is gas?", &generation),
+            key("What is a DAO?", &generation)
+        );
+        let hotter = GenerationParams {
+            temperature: Some(1.5),
+            ..Default::default()
+        };
+        assert_ne!(
+            key("What is gas?", &generation),
+            key("What is gas?", &hotter)
+        );
+
+        let mut edited = agent.clone();
+        edited.system_prompt.push_str(" Be brief.");
+        assert_ne!(
+            key("What is gas?", &generation),
+            cache_key(
+                None,
+                &edited,
+                "llama-3.3-70b-versatile",
+                "What is gas?",
+                None,
+                &generation
+            )
+        );
+        assert_ne!(
+            key("What is gas?", &generation),
+            cache_key(
+                Some("acme"),
+                &agent,
+                "llama-3.3-70b-versatile",
+                "What is gas?",
+                None,
+                &generation
+            )
+        );
+    }
+
+    #[test]
+    fn expires_evicts_and_flushes() {
+        let cache = ResponseCache::new(Some(CacheConfig {
+            ttl: Duration::from_secs(60),
+            max_entries: 2,
+        }));
+        cache.insert("a".to_string(), &reply("agent_001"));
+        cache.insert("b".to_string(), &reply("agent_002"));
+        cache.insert("c".to_string(), &reply("agent_002"));
+        assert!(cache.get("a").is_none(), "oldest entry is evicted");
+        assert_eq!(cache.get("b").unwrap().reply_text, reply("x").reply_text);
+        assert_eq!(cache.flush(Some("agent_001")), 0);
+        assert_eq!(cache.flush(Some("agent_002")), 2);
+        assert!(cache.is_empty());
+
+        let expired = ResponseCache::new(Some(CacheConfig {
+            ttl: Duration::ZERO,
+            max_entries: 2,
+        }));
+        expired.insert("a".to_string(), &reply("agent_001"));
+        assert!(expired.get("a").is_none());
+
+        let disabled = ResponseCache::default();
+        disabled.insert("a".to_string(), &reply("agent_001"));
+        assert!(!disabled.is_enabled() && disabled.get("a").is_none());
+    }
+}
@@ -0,0 +1,199 @@
+//! Cancellation of in-flight requests.
+//!
+//! Requests that call a provider (`process_text`, `run_pipeline`,
+//! `process_audio` and `tools/call`) are registered under their caller and
+//! JSON-RPC `id` while they run. The `cancel_request` method, or the MCP
+//! `notifications/cancelled` notification, aborts them: the handler's future is dropped, which closes
+//! the upstream provider connection, and the request itself is answered with
+//! `-32800`. Voice UIs use this when the user talks over the agent.
+//!
+//! Callers only reach their own requests: an authenticated caller cancels
+//! the requests sent with the same subject and tenant, and unauthenticated
+//! callers those sent without credentials. Cancelling an ID aborts every
+//! request the caller has running under it.
+
+use crate::auth::Identity;
+use futures_util::future::{AbortHandle, Abortable, Aborted};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Parameters of the `cancel_request` method and the MCP
+/// `notifications/cancelled` notification.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelRequestParams {
+    /// `id` of the request to cancel
+    #[serde(alias = "request_id")]
+    pub request_id: Value,
+    /// Optional reason, logged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Result of the `cancel_request` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelRequestResult {
+    /// Whether a running request was found and aborted
+    pub cancelled: bool,
+}
+
+/// Requests that can currently be cancelled. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct InFlight {
+    inner: Arc<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+    next: AtomicU64,
+    requests: Mutex<HashMap<String, Vec<(u64, AbortHandle)>>>,
+}
+
+impl InFlight {
+    /// Runs `future` as the request `id` of `caller` until it finishes or is
+    /// cancelled.
+    pub async fn run<F: Future>(
+        &self,
+        caller: Option<&Identity>,
+        id: &Value,
+        future: F,
+    ) -> Result<F::Output, Aborted> {
+        let (handle, registration) = AbortHandle::new_pair();
+        let key = key(caller, id);
+        let token = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .requests
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .push((token, handle));
+        let _guard = Deregister {
+            registry: &self.inner,
+            key,
+            token,
+        };
+        Abortable::new(future, registration).await
+    }
+
+    /// Aborts the requests `caller` has running as `id`; returns whether
+    /// there were any.
+    pub fn cancel(&self, caller: Option<&Identity>, id: &Value) -> bool {
+        let handles = self.inner.requests.lock().unwrap().remove(&key(caller, id));
+        let handles = handles.unwrap_or_default();
+        for (_, handle) in &handles {
+            handle.abort();
+        }
+        !handles.is_empty()
+    }
+
+    /// Number of requests that can be cancelled.
+    pub fn len(&self) -> usize {
+        self.inner
+            .requests
+            .lock()
+            .unwrap()
+            .values()
+            .map(Vec::len)
+            .sum()
+    }
+
+    /// Whether no request can be cancelled.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Removes a finished request from the registry, however it ended.
+struct Deregister<'a> {
+    registry: &'a Registry,
+    key: String,
+    token: u64,
+}
+
+impl Drop for Deregister<'_> {
+    fn drop(&mut self) {
+        let mut requests = self.registry.requests.lock().unwrap();
+        if let Some(handles) = requests.get_mut(&self.key) {
+            handles.retain(|(token, _)| *token != self.token);
+            if handles.is_empty() {
+                requests.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Registry key of a caller's request ID; `1` and `"1"` are different IDs.
+fn key(caller: Option<&Identity>, id: &Value) -> String {
+    let (subject, tenant) = match caller {
+        Some(identity) => (Some(&identity.subject), identity.tenant.as_ref()),
+        None => (None, None),
+    };
+    serde_json::json!([tenant, subject, id]).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn cancels_running_requests_by_id() {
+        let in_flight = InFlight::default();
+        let running = {
+            let in_flight = in_flight.clone();
+            tokio::spawn(async move {
+                in_flight
+                    .run(None, &json!("a"), std::future::pending::<()>())
+                    .await
+            })
+        };
+        while in_flight.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert!(!in_flight.cancel(None, &json!(1)));
+        assert!(in_flight.cancel(None, &json!("a")));
+        assert!(running.await.unwrap().is_err());
+
+        assert_eq!(in_flight.run(None, &json!(2), async { 7 }).await, Ok(7));
+        assert!(in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn callers_only_cancel_their_own_requests() {
+        let identity = |subject: &str, tenant: Option<&str>| Identity {
+            subject: subject.to_string(),
+            tenant: tenant.map(str::to_string),
+        };
+        let alice = identity("alice", Some("acme"));
+        let in_flight = InFlight::default();
+        let running = {
+            let in_flight = in_flight.clone();
+            let alice = alice.clone();
+            tokio::spawn(async move {
+                in_flight
+                    .run(Some(&alice), &json!(1), std::future::pending::<()>())
+                    .await
+            })
+        };
+        while in_flight.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        for other in [
+            identity("mallory", Some("acme")),
+            identity("alice", Some("globex")),
+            identity("alice", None),
+        ] {
+            assert!(!in_flight.cancel(Some(&other), &json!(1)));
+        }
+        assert!(!in_flight.cancel(None, &json!(1)));
+        assert_eq!(in_flight.len(), 1);
+
+        assert!(in_flight.cancel(Some(&alice), &json!(1)));
+        assert!(running.await.unwrap().is_err());
+    }
+}
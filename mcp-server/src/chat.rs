@@ -0,0 +1,275 @@
+//! The `chat` subcommand: talking to an agent from a terminal.
+//!
+//! ```text
+//! mcp-server chat --agent agent_004
+//! ```
+//!
+//! reads one message per line and prints the agent's replies, without
+//! crafting JSON-RPC payloads or running a listener. Messages go to the
+//! dispatcher as `process_text` requests with the configuration the server
+//! would run with, carrying the conversation so far as their
+//! `conversation_history`, trimmed as usual by [`crate::history`].
+//!
+//! Lines starting with `/` are commands: `/reset` starts the conversation
+//! over, `/quit` exits, as does closing the input, and `/help` lists them.
+//! Logs go to stderr and only warnings are shown unless `RUST_LOG` says
+//! otherwise.
+
+use crate::auth::Access;
+use crate::handlers;
+use crate::i18n::Locale;
+use crate::models::{JsonRpcRequest, Message};
+use crate::AppState;
+use serde_json::{json, Value};
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Commands understood by the chat loop.
+pub const HELP: &str = "/reset starts over, /quit exits, /help shows this";
+
+/// Flags of the `chat` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatArgs {
+    /// ID of the agent to talk to
+    pub agent_id: String,
+}
+
+/// One line typed in the chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// A message for the agent
+    Say(String),
+    /// Forget the conversation so far
+    Reset,
+    /// Leave the chat
+    Quit,
+    /// List the commands
+    Help,
+    /// A blank line
+    Empty,
+}
+
+impl Input {
+    /// Reads a line, or the error to show for an unknown command.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        match line {
+            "" => Ok(Self::Empty),
+            "/reset" => Ok(Self::Reset),
+            "/quit" | "/exit" => Ok(Self::Quit),
+            "/help" => Ok(Self::Help),
+            command if command.starts_with('/') => {
+                Err(format!("Unknown command {}; {}", command, HELP))
+            }
+            text => Ok(Self::Say(text.to_string())),
+        }
+    }
+}
+
+/// Chats with `args.agent_id` over `input` and `output` until `/quit` or the
+/// end of `input`.
+///
+/// Fails before reading anything if the agent doesn't exist.
+pub async fn run<R, W>(
+    state: Arc<AppState>,
+    args: ChatArgs,
+    input: R,
+    mut output: W,
+    locale: Locale,
+) -> io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let agent = state.agents.get(&args.agent_id).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Agent not found: {}", args.agent_id),
+        )
+    })?;
+    let banner = format!("Chatting with {} ({}); {}\n", agent.name, agent.id, HELP);
+    output.write_all(banner.as_bytes()).await?;
+
+    let mut history: Vec<Message> = Vec::new();
+    let mut lines = input.lines();
+    let mut id = 0u64;
+    loop {
+        output.write_all(b"> ").await?;
+        output.flush().await?;
+        let Some(line) = lines.next_line().await? else {
+            output.write_all(b"\n").await?;
+            break;
+        };
+        let text = match Input::parse(&line) {
+            Ok(Input::Say(text)) => text,
+            Ok(Input::Empty) => continue,
+            Ok(Input::Reset) => {
+                history.clear();
+                output.write_all(b"Conversation cleared\n").await?;
+                continue;
+            }
+            Ok(Input::Quit) => break,
+            Ok(Input::Help) => {
+                output.write_all(format!("{}\n", HELP).as_bytes()).await?;
+                continue;
+            }
+            Err(e) => {
+                output.write_all(format!("{}\n", e).as_bytes()).await?;
+                continue;
+            }
+        };
+
+        id += 1;
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "process_text".to_string(),
+            params: Some(json!({
+                "agent_id": agent.id,
+                "user_text": text,
+                "conversation_history": history,
+            })),
+            id: Some(json!(id)),
+        };
+        let response = handlers::dispatch(&state, request, locale, Access::None).await;
+        let Some(response) = response else { continue };
+        if let Some(error) = response.error {
+            let line = format!("Error {}: {}\n", error.code, error.message);
+            output.write_all(line.as_bytes()).await?;
+            continue;
+        }
+        let result = response.result.unwrap_or_default();
+        output.write_all(transcript(&result).as_bytes()).await?;
+
+        let reply = result["reply_text"].as_str().unwrap_or_default();
+        history.push(Message {
+            role: "user".to_string(),
+            content: text,
+            ..Default::default()
+        });
+        history.push(Message {
+            role: "assistant".to_string(),
+            content: reply.to_string(),
+            ..Default::default()
+        });
+    }
+    output.flush().await
+}
+
+/// How a `process_text` result is shown: the reply, any tool calls the
+/// model requested, and a line of metadata.
+fn transcript(result: &Value) -> String {
+    let mut shown = String::new();
+    let reply = result["reply_text"].as_str().unwrap_or_default().trim();
+    if !reply.is_empty() {
+        shown.push_str(reply);
+        shown.push('\n');
+    }
+    for call in result["tool_calls"].as_array().into_iter().flatten() {
+        shown.push_str(&format!(
+            "[tool call] {}({})\n",
+            call["function"]["name"].as_str().unwrap_or_default(),
+            call["function"]["arguments"].as_str().unwrap_or_default()
+        ));
+    }
+    let metadata = &result["metadata"];
+    let mut details = vec![format!(
+        "{} {}",
+        metadata["provider"].as_str().unwrap_or_default(),
+        metadata["model"].as_str().unwrap_or_default()
+    )];
+    if let Some(tokens) = metadata["tokens_used"].as_u64() {
+        details.push(format!("{} tokens", tokens));
+    }
+    details.push(format!(
+        "{} ms",
+        metadata["processing_time_ms"].as_u64().unwrap_or_default()
+    ));
+    if metadata["cache"] == "hit" {
+        details.push("cached".to_string());
+    }
+    shown.push_str(&format!("  ({})\n", details.join(", ")));
+    shown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_shed::{LoadShedConfig, LoadShedder};
+    use crate::sessions::MemorySessionStore;
+
+    #[tokio::test]
+    async fn reads_commands_and_reports_errors() {
+        assert_eq!(Input::parse("  gm  "), Ok(Input::Say("gm".into())));
+        assert_eq!(Input::parse("/exit"), Ok(Input::Quit));
+        assert!(Input::parse("/model gpt").is_err());
+
+        let state = Arc::new(AppState {
+            http_client: reqwest::Client::new().into(),
+            providers: Default::default(),
+            agents: Default::default(),
+            admin_token: None,
+            jwt: None,
+            oidc: None,
+            reporter: Default::default(),
+            shedder: Arc::new(LoadShedder::new(LoadShedConfig::from_env())),
+            sessions: Arc::new(MemorySessionStore::default()),
+            history: Default::default(),
+            limits: Default::default(),
+            server_tools: Default::default(),
+            in_flight: Default::default(),
+            jobs: Default::default(),
+            cache: Default::default(),
+            coalescer: Default::default(),
+            quotas: Default::default(),
+            accounting: Default::default(),
+            guardrails: Default::default(),
+            plugins: Default::default(),
+            audit: None,
+            access_log: Default::default(),
+            metrics: Default::default(),
+            knowledge: Default::default(),
+            memories: Default::default(),
+            evals: Default::default(),
+            speech: Default::default(),
+        });
+        let args = |agent_id: &str| ChatArgs {
+            agent_id: agent_id.into(),
+        };
+        let input = "What is gas?\n\n/help\n/quit\nNever sent\n";
+        let mut output = Vec::new();
+        run(
+            state.clone(),
+            args("agent_004"),
+            input.as_bytes(),
+            &mut output,
+            Locale::En,
+        )
+        .await
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Chatting with "));
+        // No provider is configured, so the message fails
+        assert_eq!(output.matches("Error -32").count(), 1, "{}", output);
+        assert!(output.contains(HELP));
+
+        let missing = run(
+            state,
+            args("agent_404"),
+            "".as_bytes(),
+            Vec::new(),
+            Locale::En,
+        );
+        assert!(missing.await.is_err());
+
+        let shown = transcript(&json!({
+            "reply_text": "Gas is the fee for running a transaction.",
+            "metadata": { "provider": "groq", "model": "llama", "tokens_used": 42,
+                          "processing_time_ms": 900, "cache": "hit" },
+        }));
+        assert_eq!(
+            shown,
+            "Gas is the fee for running a transaction.\n  (groq llama, 42 tokens, 900 ms, cached)\n"
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Command-line flags of the server binary.
+//!
+//! Every flag can also be set with the environment variable shown in
+//! `mcp-server --help`; a flag wins over its variable, which wins over the
+//! default. `--print-config` prints the effective configuration as JSON,
+//! without secrets, and exits without serving. The `bench` subcommand
+//! measures the agents instead of serving, see [`crate::bench`], and `chat`
+//! talks to one from the terminal, see [`crate::chat`].
+//!
+//! # Environment Variables
+//!
+//! * `BIND_ADDRESS` - Optional. Address to listen on (default: `0.0.0.0`)
+//! * `PORT` - Optional. Port to listen on (default: 3000)
+//! * `WORKER_THREADS` - Optional. Runtime worker threads (default: one per
+//!   CPU core)
+//! * `LLM_PROVIDER` - Optional. Default AI backend, see [`crate::providers`]
+//! * `CONFIG_FILE` - Optional. TOML file reloaded on SIGHUP, see
+//!   [`crate::config`]
+//! * `AGENT_DB` - Optional. SQLite file persisting agents, see
+//!   [`crate::agent_db`]
+//! * `AUDIT_DB` - Optional. SQLite file recording every call, see
+//!   [`crate::audit`]
+//! * `TLS_CERT_FILE` / `TLS_KEY_FILE` - Optional. Serve HTTPS, see
+//!   [`crate::tls`]
+//! * `TLS_CLIENT_CA_FILE` - Optional. Require client certificates from these
+//!   CAs
+
+use crate::bench::{BenchArgs, DEFAULT_TEXT};
+use crate::chat::ChatArgs;
+use crate::config::Settings;
+use crate::providers::ProviderRegistry;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use serde_json::{json, Value};
+use std::ffi::OsString;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+
+/// Port listened on when neither `--port` nor `PORT` is given.
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// Parsed command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    /// Address to listen on
+    pub bind: IpAddr,
+    /// Port to listen on
+    pub port: u16,
+    /// Runtime worker threads, or `None` for one per CPU core
+    pub worker_threads: Option<usize>,
+    /// Name of the default AI backend
+    pub provider: Option<String>,
+    /// TOML file reloaded on SIGHUP
+    pub config_file: Option<PathBuf>,
+    /// SQLite file persisting agents
+    pub agent_db: Option<PathBuf>,
+    /// SQLite file recording every call
+    pub audit_db: Option<PathBuf>,
+    /// PEM certificate chain served over HTTPS
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    pub tls_key: Option<PathBuf>,
+    /// PEM bundle of the CAs issuing required client certificates
+    pub tls_client_ca: Option<PathBuf>,
+    /// Serve JSON-RPC over stdin/stdout instead of HTTP
+    pub stdio: bool,
+    /// Print the effective configuration and exit
+    pub print_config: bool,
+    /// Benchmark an agent and exit, with `mcp-server bench`
+    pub bench: Option<BenchArgs>,
+    /// Chat with an agent in the terminal, with `mcp-server chat`
+    pub chat: Option<ChatArgs>,
+}
+
+impl Cli {
+    /// The flags the binary accepts.
+    pub fn command() -> Command {
+        Command::new("mcp-server")
+            .version(env!("CARGO_PKG_VERSION"))
+            .about("JSON-RPC 2.0 MCP server answering through AI agents")
+            .arg(
+                Arg::new("bind")
+                    .long("bind")
+                    .env("BIND_ADDRESS")
+                    .value_name("ADDR")
+                    .value_parser(value_parser!(IpAddr))
+                    .default_value("0.0.0.0")
+                    .help("Address to listen on"),
+            )
+            .arg(
+                Arg::new("port")
+                    .long("port")
+                    .short('p')
+                    .env("PORT")
+                    .value_name("PORT")
+                    .value_parser(value_parser!(u16))
+                    .default_value("3000")
+                    .help("Port to listen on"),
+            )
+            .arg(
+                Arg::new("worker_threads")
+                    .long("worker-threads")
+                    .env("WORKER_THREADS")
+                    .value_name("N")
+                    .value_parser(value_parser!(u64).range(1..))
+                    .help("Runtime worker threads [default: one per CPU core]"),
+            )
+            .arg(
+                Arg::new("provider")
+                    .long("provider")
+                    .env("LLM_PROVIDER")
+                    .value_name("NAME")
+                    .help("Default AI backend: groq, gemini, azure or mock"),
+            )
+            .arg(
+                Arg::new("config_file")
+                    .long("config")
+                    .short('c')
+                    .env("CONFIG_FILE")
+                    .value_name("PATH")
+                    .value_parser(value_parser!(PathBuf))
+                    .help("TOML config file, reloaded on SIGHUP"),
+            )
+            .arg(
+                Arg::new("agent_db")
+                    .long("agent-db")
+                    .env("AGENT_DB")
+                    .value_name("PATH")
+                    .value_parser(value_parser!(PathBuf))
+                    .help("SQLite file persisting agents changed at runtime"),
+            )
+            .arg(
+                Arg::new("audit_db")
+                    .long("audit-db")
+                    .env("AUDIT_DB")
+                    .value_name("PATH")
+                    .value_parser(value_parser!(PathBuf))
+                    .help("SQLite file keeping an audit record of every JSON-RPC call"),
+            )
+            .arg(
+                Arg::new("tls_cert")
+                    .long("tls-cert")
+                    .env("TLS_CERT_FILE")
+                    .value_name("PATH")
+                    .value_parser(value_parser!(PathBuf))
+                    .requires("tls_key")
+                    .help("PEM certificate chain; serves HTTPS with --tls-key"),
+            )
+            .arg(
+                Arg::new("tls_key")
+                    .long("tls-key")
+                    .env("TLS_KEY_FILE")
+                    .value_name("PATH")
+                    .value_parser(value_parser!(PathBuf))
+                    .requires("tls_cert")
+                    .help("PEM private key of the certificate"),
+            )
+            .arg(
+                Arg::new("tls_client_ca")
+                    .long("tls-client-ca")
+                    .env("TLS_CLIENT_CA_FILE")
+                    .value_name("PATH")
+                    .value_parser(value_parser!(PathBuf))
+                    .requires("tls_cert")
+                    .help("PEM CA bundle; requires client certificates issued by it"),
+            )
+            .arg(
+                Arg::new("stdio")
+                    .long("stdio")
+                    .action(ArgAction::SetTrue)
+                    .help("Serve JSON-RPC over stdin/stdout instead of HTTP"),
+            )
+            .arg(
+                Arg::new("print_config")
+                    .long("print-config")
+                    .action(ArgAction::SetTrue)
+                    .help("Print the effective configuration as JSON and exit"),
+            )
+            .subcommand(
+                Command::new("bench")
+                    .about("Send process_text requests to an agent in-process and print latency, throughput and errors")
+                    .arg(
+                        Arg::new("agent")
+                            .long("agent")
+                            .short('a')
+                            .value_name("ID")
+                            .default_value("agent_001")
+                            .help("Agent to ask"),
+                    )
+                    .arg(
+                        Arg::new("requests")
+                            .long("requests")
+                            .short('n')
+                            .value_name("N")
+                            .value_parser(value_parser!(u64).range(1..))
+                            .default_value("100")
+                            .help("Number of requests to send"),
+                    )
+                    .arg(
+                        Arg::new("concurrency")
+                            .long("concurrency")
+                            .short('C')
+                            .value_name("N")
+                            .value_parser(value_parser!(u64).range(1..))
+                            .default_value("10")
+                            .help("Most requests running at once"),
+                    )
+                    .arg(
+                        Arg::new("text")
+                            .long("text")
+                            .value_name("TEXT")
+                            .default_value(DEFAULT_TEXT)
+                            .help("User text of every request"),
+                    ),
+            )
+            .subcommand(
+                Command::new("chat")
+                    .about("Chat with an agent in the terminal")
+                    .arg(
+                        Arg::new("agent")
+                            .long("agent")
+                            .short('a')
+                            .value_name("ID")
+                            .default_value("agent_001")
+                            .help("Agent to talk to"),
+                    ),
+            )
+    }
+
+    /// Parses the process's arguments, exiting with usage on errors and for
+    /// `--help` and `--version`.
+    pub fn parse() -> Self {
+        Self::from_matches(&Self::command().get_matches())
+    }
+
+    /// Parses `args`, the first of which is the binary name.
+    pub fn try_parse_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        Ok(Self::from_matches(
+            &Self::command().try_get_matches_from(args)?,
+        ))
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            bind: matches
+                .get_one::<IpAddr>("bind")
+                .copied()
+                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            port: matches
+                .get_one::<u16>("port")
+                .copied()
+                .unwrap_or(DEFAULT_PORT),
+            worker_threads: matches
+                .get_one::<u64>("worker_threads")
+                .map(|&n| n as usize),
+            provider: matches
+                .get_one::<String>("provider")
+                .filter(|name| !name.is_empty())
+                .cloned(),
+            config_file: matches.get_one::<PathBuf>("config_file").cloned(),
+            agent_db: matches.get_one::<PathBuf>("agent_db").cloned(),
+            audit_db: matches.get_one::<PathBuf>("audit_db").cloned(),
+            tls_cert: matches.get_one::<PathBuf>("tls_cert").cloned(),
+            tls_key: matches.get_one::<PathBuf>("tls_key").cloned(),
+            tls_client_ca: matches.get_one::<PathBuf>("tls_client_ca").cloned(),
+            stdio: matches.get_flag("stdio"),
+            print_config: matches.get_flag("print_config"),
+            bench: matches.subcommand_matches("bench").map(|bench| BenchArgs {
+                agent_id: bench
+                    .get_one::<String>("agent")
+                    .cloned()
+                    .unwrap_or_default(),
+                requests: bench
+                    .get_one::<u64>("requests")
+                    .map_or(100, |&n| n as usize),
+                concurrency: bench
+                    .get_one::<u64>("concurrency")
+                    .map_or(10, |&n| n as usize),
+                text: bench
+                    .get_one::<String>("text")
+                    .cloned()
+                    .unwrap_or_else(|| DEFAULT_TEXT.to_string()),
+            }),
+            chat: matches.subcommand_matches("chat").map(|chat| ChatArgs {
+                agent_id: chat.get_one::<String>("agent").cloned().unwrap_or_default(),
+            }),
+        }
+    }
+
+    /// The socket address to listen on.
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+
+    /// The configuration the server runs with, as printed by
+    /// `--print-config`. Credentials are left out.
+    pub fn effective_config(&self, settings: &Settings, providers: &ProviderRegistry) -> Value {
+        let worker_threads = self
+            .worker_threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        let names = providers.names();
+        json!({
+            "server": {
+                "transport": match (self.stdio, &self.tls_cert) {
+                    (true, _) => "stdio",
+                    (false, Some(_)) => "https",
+                    (false, None) => "http",
+                },
+                "address": self.addr().to_string(),
+                "worker_threads": worker_threads,
+            },
+            "tls_cert": self.tls_cert,
+            "tls_client_ca": self.tls_client_ca,
+            "config_file": self.config_file,
+            "agent_db": self.agent_db,
+            "audit_db": self.audit_db,
+            "providers": {
+                "default": names.first(),
+                "configured": names,
+            },
+            "agents": settings.agents.iter().map(|a| &a.id).collect::<Vec<_>>(),
+            "load_shed": {
+                "max_in_flight": settings.load_shed.max_in_flight,
+                "latency_ms": settings.load_shed.latency_threshold.as_millis() as u64,
+                "retry_after_secs": settings.load_shed.retry_after_secs,
+            },
+            "error_reporting": settings.error_reporting.has_sinks(),
+            "quotas": settings.quotas,
+            "prices": settings.prices,
+            "guardrails": settings.guardrails,
+            "plugins": settings.plugins,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flags_over_defaults() {
+        let cli = Cli::try_parse_from([
+            "mcp-server",
+            "--bind",
+            "127.0.0.1",
+            "--port",
+            "8080",
+            "--worker-threads",
+            "2",
+            "--config",
+            "valet.toml",
+            "--print-config",
+        ])
+        .unwrap();
+        assert_eq!(cli.addr().to_string(), "127.0.0.1:8080");
+        assert_eq!(cli.worker_threads, Some(2));
+        assert_eq!(cli.config_file, Some(PathBuf::from("valet.toml")));
+        assert!(cli.print_config && !cli.stdio);
+        assert_eq!(cli.bench, None);
+
+        let cli = Cli::try_parse_from([
+            "mcp-server",
+            "bench",
+            "--agent",
+            "agent_002",
+            "--requests",
+            "50",
+        ])
+        .unwrap();
+        let bench = cli.bench.unwrap();
+        assert_eq!(bench.agent_id, "agent_002");
+        assert_eq!((bench.requests, bench.concurrency), (50, 10));
+        assert_eq!(bench.text, DEFAULT_TEXT);
+        assert!(Cli::try_parse_from(["mcp-server", "bench", "--concurrency", "0"]).is_err());
+
+        let cli = Cli::try_parse_from(["mcp-server", "chat", "--agent", "agent_004"]).unwrap();
+        assert_eq!(cli.chat.unwrap().agent_id, "agent_004");
+        assert_eq!(cli.bench, None);
+
+        assert!(Cli::try_parse_from(["mcp-server", "--worker-threads", "0"]).is_err());
+        assert!(Cli::try_parse_from(["mcp-server", "--port", "http"]).is_err());
+        assert!(Cli::try_parse_from(["mcp-server", "--tls-cert", "cert.pem"]).is_err());
+        assert!(Cli::try_parse_from(["mcp-server", "--tls-client-ca", "ca.pem"]).is_err());
+    }
+
+    #[test]
+    fn effective_config_leaves_out_credentials() {
+        let cli = Cli::try_parse_from(["mcp-server", "--stdio"]).unwrap();
+        let config = cli.effective_config(&Settings::load(None).unwrap(), &ProviderRegistry::new());
+        assert_eq!(config["server"]["transport"], "stdio");
+        assert_eq!(config["error_reporting"], false);
+        assert!(config["providers"]["default"].is_null());
+    }
+}
@@ -0,0 +1,164 @@
+//! Coalescing of identical requests in flight.
+//!
+//! Clients that retry eagerly, or many users asking the same FAQ at once,
+//! send identical requests before the first has been answered, so the
+//! [response cache](crate::cache) can't help yet. A request whose reply could
+//! be cached (same tenant, agent, model, normalized prompt, history and
+//! sampling settings, and the same `timeout_ms`) that arrives while an
+//! identical one is waiting for a provider doesn't call the provider itself:
+//! it waits for the first and answers with a copy of its reply, reported as
+//! `"cache": "shared"` and costing nothing, as cache hits do. This holds
+//! whether or not the response cache is enabled. Requests with a budget are
+//! never coalesced.
+//!
+//! Only replies are shared: when the first request fails or is cancelled,
+//! each waiting one calls the provider on its own.
+//!
+//! # Environment Variables
+//!
+//! * `REQUEST_COALESCING` - Optional. `false` disables coalescing (default:
+//!   `true`)
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Requests in flight that identical ones can wait for.
+///
+/// Cheap to clone; clones share the requests in flight.
+#[derive(Debug, Clone)]
+pub struct Coalescer<T> {
+    enabled: bool,
+    waiting: Arc<Mutex<HashMap<String, broadcast::Sender<T>>>>,
+}
+
+/// What a request does once it joined a [`Coalescer`].
+#[derive(Debug)]
+pub enum Turn<T> {
+    /// Nobody is asking the same: call the provider, then
+    /// [`share`](Leader::share) the reply
+    Lead(Leader<T>),
+    /// An identical request is in flight: wait for its reply
+    Follow(broadcast::Receiver<T>),
+}
+
+/// The request the others wait for; they stop waiting when it is dropped
+/// without sharing a reply.
+#[derive(Debug)]
+pub struct Leader<T> {
+    key: Option<String>,
+    waiting: Arc<Mutex<HashMap<String, broadcast::Sender<T>>>>,
+}
+
+impl<T: Clone> Default for Coalescer<T> {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+impl<T: Clone> Coalescer<T> {
+    /// A coalescer, which lets every request lead when not `enabled`.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            waiting: Arc::default(),
+        }
+    }
+
+    /// Reads `REQUEST_COALESCING`.
+    pub fn from_env() -> Self {
+        let disabled = std::env::var("REQUEST_COALESCING")
+            .is_ok_and(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0"));
+        Self::new(!disabled)
+    }
+
+    /// Whether identical requests are coalesced.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Joins the requests with `key`: leads if none is in flight, or follows
+    /// the one that is.
+    pub fn join(&self, key: String) -> Turn<T> {
+        let mut waiting = self.waiting.lock().unwrap();
+        if let Some(sender) = waiting.get(&key).filter(|_| self.enabled) {
+            return Turn::Follow(sender.subscribe());
+        }
+        if self.enabled {
+            waiting.insert(key.clone(), broadcast::channel(1).0);
+        }
+        Turn::Lead(Leader {
+            key: Some(key),
+            waiting: self.waiting.clone(),
+        })
+    }
+
+    /// Number of requests others can wait for.
+    pub fn len(&self) -> usize {
+        self.waiting.lock().unwrap().len()
+    }
+
+    /// Whether no request is in flight.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Leader<T> {
+    /// Hands `reply` to the requests waiting for this one.
+    pub fn share(mut self, reply: T) {
+        let key = self.key.take().unwrap_or_default();
+        let sender = self.waiting.lock().unwrap().remove(&key);
+        // Followers may all have gone
+        if let Some(sender) = sender {
+            let _ = sender.send(reply);
+        }
+    }
+}
+
+impl<T> Drop for Leader<T> {
+    fn drop(&mut self) {
+        // Closes the channel of a leader that failed, so followers stop waiting
+        if let Some(key) = self.key.take() {
+            self.waiting.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+/// Waits for the reply of the request followed; `None` if it failed or was
+/// cancelled.
+pub async fn wait<T: Clone>(mut receiver: broadcast::Receiver<T>) -> Option<T> {
+    receiver.recv().await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn followers_get_the_leaders_reply() {
+        let coalescer = Coalescer::<String>::default();
+        let Turn::Lead(leader) = coalescer.join("key".to_string()) else {
+            panic!("the first request leads");
+        };
+        let Turn::Follow(follower) = coalescer.join("key".to_string()) else {
+            panic!("an identical request follows");
+        };
+        assert!(matches!(coalescer.join("other".to_string()), Turn::Lead(_)));
+        leader.share("reply".to_string());
+        assert_eq!(wait(follower).await.as_deref(), Some("reply"));
+        assert!(coalescer.is_empty());
+
+        // A failed leader lets its followers go
+        let leader = coalescer.join("key".to_string());
+        let Turn::Follow(follower) = coalescer.join("key".to_string()) else {
+            panic!("an identical request follows");
+        };
+        drop(leader);
+        assert_eq!(wait(follower).await, None);
+
+        let disabled = Coalescer::<String>::new(false);
+        let _leader = disabled.join("key".to_string());
+        assert!(matches!(disabled.join("key".to_string()), Turn::Lead(_)));
+    }
+}
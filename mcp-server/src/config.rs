@@ -0,0 +1,500 @@
+//! Reloadable configuration.
+//!
+//! Settings come from environment variables, optionally overridden by a TOML
+//! file named by `CONFIG_FILE`. Sending `SIGHUP` to the process, or calling
+//! `POST /admin/reload` with `Authorization: Bearer $ADMIN_TOKEN`, re-reads the
+//! file and applies it without a restart: the agent registry, load-shedding
+//! thresholds, error-reporting sinks, [quotas](crate::quota),
+//! [prices](crate::accounting), [guardrails](crate::guardrails),
+//! [plugins](crate::plugins) and [scheduled tasks](crate::tasks) are swapped
+//! in place, and in-flight
+//! requests finish with the settings they started with. An invalid file is
+//! rejected and the running settings are kept. A reload rebuilds the agents
+//! from the built-in ones and the file; agents changed at runtime through the
+//! admin JSON-RPC methods are kept only if they are persisted with `AGENT_DB`
+//! (see [`crate::agent_db`]).
+//!
+//! # Example
+//!
+//! ```toml
+//! [load_shed]
+//! max_in_flight = 64
+//! latency_ms = 5000
+//! retry_after_secs = 10
+//!
+//! [error_reporting]
+//! webhook_url = "https://hooks.example.com/errors"
+//!
+//! [quotas.default]
+//! daily_requests = 1000
+//!
+//! # Added to the built-in agents; an agent with a built-in ID replaces it
+//! [[agents]]
+//! id = "agent_005"
+//! name = "NFT Copywriter"
+//! description = "Writes descriptions for freshly minted NFTs"
+//! capabilities = ["text", "nft"]
+//! model = "llama-3.3-70b-versatile"
+//! system_prompt = "You write short, vivid NFT descriptions."
+//! temperature = 1.0
+//! max_tokens = 200
+//! fallbacks = [{ provider = "gemini" }]
+//! allowed_models = ["llama-3.1-8b-instant"]
+//!
+//! # Sends agent_005's replies through a WebAssembly module
+//! [[plugins]]
+//! id = "tidy"
+//! path = "plugins/tidy.wasm"
+//! agents = ["agent_005"]
+//!
+//! # Runs an agent on a cron schedule, in UTC
+//! [[tasks]]
+//! id = "daily_market_summary"
+//! schedule = "0 8 * * *"
+//! agent_id = "agent_002"
+//! user_text = "Summarize the last day of the crypto markets."
+//! session_id = "market-summaries"
+//! ```
+
+use crate::accounting::{Accounting, ModelPrice};
+use crate::agents::{builtin_agents, AgentRegistry, AgentStore};
+use crate::error_report::{ErrorReporter, ReportingConfig};
+use crate::guardrails::{GuardrailConfig, Guardrails};
+use crate::load_shed::{LoadShedConfig, LoadShedder};
+use crate::models::Agent;
+use crate::plugins::{PluginConfig, Plugins};
+use crate::quota::{QuotaConfig, QuotaTracker};
+use crate::tasks::{ScheduledTask, Tasks};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Contents of the TOML config file. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    /// Agents added to (or replacing) the built-in ones
+    pub agents: Vec<Agent>,
+    /// Load-shedding threshold overrides
+    pub load_shed: LoadShedOverrides,
+    /// Error-reporting sink overrides
+    pub error_reporting: ReportingOverrides,
+    /// Usage limits per caller
+    pub quotas: QuotaConfig,
+    /// Model prices added to or replacing the built-in ones
+    pub prices: HashMap<String, ModelPrice>,
+    /// Rules replies must follow
+    pub guardrails: GuardrailConfig,
+    /// WebAssembly modules extending agents
+    pub plugins: Vec<PluginConfig>,
+    /// Agent runs on a schedule
+    pub tasks: Vec<ScheduledTask>,
+}
+
+/// `[load_shed]` section, overriding the `LOAD_SHED_*` variables.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadShedOverrides {
+    pub max_in_flight: Option<usize>,
+    pub latency_ms: Option<u64>,
+    pub retry_after_secs: Option<u64>,
+}
+
+/// `[error_reporting]` section, overriding `SENTRY_DSN`, `SENTRY_ENVIRONMENT`
+/// and `ERROR_WEBHOOK_URL`. An empty string turns a sink off.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportingOverrides {
+    pub sentry_dsn: Option<String>,
+    pub environment: Option<String>,
+    pub webhook_url: Option<String>,
+}
+
+impl FileConfig {
+    /// Parses a config file's contents.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| format!("Invalid config: {}", e))?;
+        config
+            .guardrails
+            .validate()
+            .map_err(|e| format!("Invalid config: {}", e))?;
+        ScheduledTask::validate_all(&config.tasks).map_err(|e| format!("Invalid config: {}", e))?;
+        PluginConfig::validate_all(&config.plugins)
+            .map_err(|e| format!("Invalid config: {}", e))?;
+        for agent in &config.agents {
+            agent
+                .validate_generation()
+                .and_then(|_| {
+                    crate::prompt_versions::validate(
+                        agent.prompt_version.as_deref(),
+                        &agent.prompt_versions,
+                    )
+                })
+                .and_then(|_| agent.scripts.validate())
+                .map_err(|e| format!("Invalid config: agent {}: {}", agent.id, e))?;
+        }
+        Ok(config)
+    }
+
+    /// Reads and parses the config file at `path`.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+/// The reloadable settings, resolved from the environment and config file.
+#[derive(Debug)]
+pub struct Settings {
+    /// All agents to serve, built-in ones first
+    pub agents: Vec<Agent>,
+    /// Load-shedding thresholds
+    pub load_shed: LoadShedConfig,
+    /// Error-reporting sinks
+    pub error_reporting: ReportingConfig,
+    /// Usage limits per caller
+    pub quotas: QuotaConfig,
+    /// Model price overrides
+    pub prices: HashMap<String, ModelPrice>,
+    /// Rules replies must follow
+    pub guardrails: GuardrailConfig,
+    /// WebAssembly modules extending agents
+    pub plugins: Vec<PluginConfig>,
+    /// Agent runs on a schedule
+    pub tasks: Vec<ScheduledTask>,
+}
+
+impl Settings {
+    /// Loads settings from the environment, applying the file at `path` if given.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let file = match path {
+            Some(path) => FileConfig::read(path)?,
+            None => FileConfig::default(),
+        };
+        Ok(Self::resolve(file))
+    }
+
+    /// Applies `file` on top of the environment and built-in defaults.
+    pub fn resolve(file: FileConfig) -> Self {
+        let mut agents = builtin_agents();
+        agents.extend(file.agents);
+
+        let env = LoadShedConfig::from_env();
+        let load_shed = LoadShedConfig {
+            max_in_flight: file.load_shed.max_in_flight.unwrap_or(env.max_in_flight),
+            latency_threshold: file
+                .load_shed
+                .latency_ms
+                .map(Duration::from_millis)
+                .unwrap_or(env.latency_threshold),
+            retry_after_secs: file
+                .load_shed
+                .retry_after_secs
+                .unwrap_or(env.retry_after_secs),
+        };
+
+        let reporting = file.error_reporting;
+        let var = |name: &str| std::env::var(name).ok();
+        let error_reporting = ReportingConfig::new(
+            reporting
+                .sentry_dsn
+                .or_else(|| var("SENTRY_DSN"))
+                .as_deref(),
+            reporting.environment.or_else(|| var("SENTRY_ENVIRONMENT")),
+            reporting.webhook_url.or_else(|| var("ERROR_WEBHOOK_URL")),
+        );
+
+        Self {
+            agents,
+            load_shed,
+            error_reporting,
+            quotas: file.quotas,
+            prices: file.prices,
+            guardrails: file.guardrails,
+            plugins: file.plugins,
+            tasks: file.tasks,
+        }
+    }
+}
+
+/// What a reload changed, returned by the admin endpoint.
+#[derive(Debug, Serialize)]
+pub struct ReloadSummary {
+    /// Number of agents now served
+    pub agents: usize,
+    /// In-flight request threshold now in effect
+    pub load_shed_max_in_flight: usize,
+    /// Whether any error-reporting sink is configured
+    pub error_reporting: bool,
+    /// Number of callers with their own quota
+    pub quota_keys: usize,
+    /// Number of plugins loaded
+    pub plugins: usize,
+    /// Number of scheduled tasks
+    pub tasks: usize,
+}
+
+/// Applies reloaded settings to the running server's shared components.
+#[derive(Clone)]
+pub struct Reloader {
+    path: Option<PathBuf>,
+    admin_token: Option<String>,
+    agents: AgentStore,
+    shedder: Arc<LoadShedder>,
+    reporter: ErrorReporter,
+    quotas: QuotaTracker,
+    accounting: Accounting,
+    guardrails: Guardrails,
+    plugins: Plugins,
+    tasks: Tasks,
+}
+
+impl Reloader {
+    /// Creates a reloader for the given components.
+    ///
+    /// # Environment Variables
+    ///
+    /// * `CONFIG_FILE` - Optional. Path of the TOML config file to re-read
+    /// * `ADMIN_TOKEN` - Optional. Bearer token enabling `POST /admin/reload`
+    pub fn from_env(
+        agents: AgentStore,
+        shedder: Arc<LoadShedder>,
+        reporter: ErrorReporter,
+        quotas: QuotaTracker,
+        accounting: Accounting,
+        guardrails: Guardrails,
+        tasks: Tasks,
+    ) -> Self {
+        Self {
+            path: std::env::var_os("CONFIG_FILE").map(PathBuf::from),
+            admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+            agents,
+            shedder,
+            reporter,
+            quotas,
+            accounting,
+            guardrails,
+            plugins: Plugins::default(),
+            tasks,
+        }
+    }
+
+    /// Watches the config file at `path` instead of `CONFIG_FILE`.
+    pub fn with_path(mut self, path: Option<PathBuf>) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Loads the config's plugins into `plugins` rather than a set of its own.
+    pub fn with_plugins(mut self, plugins: Plugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// The config file being watched, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Whether the admin reload endpoint should be served.
+    pub fn admin_enabled(&self) -> bool {
+        self.admin_token.is_some()
+    }
+
+    /// The token admin requests must present, if admin access is enabled.
+    pub fn admin_token(&self) -> Option<&str> {
+        self.admin_token.as_deref()
+    }
+
+    /// Swaps `settings` into the running components.
+    ///
+    /// Fails, changing nothing, if a plugin cannot be loaded or the persisted
+    /// agents cannot be read.
+    pub fn apply(&self, settings: Settings) -> Result<ReloadSummary, String> {
+        let plugins = self.plugins.load(&settings.plugins)?;
+        self.agents.replace(AgentRegistry::new(settings.agents))?;
+        let summary = ReloadSummary {
+            agents: self.agents.snapshot().len(),
+            load_shed_max_in_flight: settings.load_shed.max_in_flight,
+            error_reporting: settings.error_reporting.has_sinks(),
+            quota_keys: settings.quotas.keys.len(),
+            plugins: plugins.len(),
+            tasks: settings.tasks.len(),
+        };
+        self.shedder.set_config(settings.load_shed);
+        self.reporter.reconfigure(settings.error_reporting);
+        self.quotas.reconfigure(settings.quotas);
+        self.accounting.reprice(settings.prices);
+        self.guardrails.reconfigure(settings.guardrails);
+        self.plugins.replace(plugins);
+        self.tasks.reconfigure(settings.tasks);
+        Ok(summary)
+    }
+
+    /// Re-reads the config file and applies it, keeping the running settings
+    /// if it is invalid.
+    pub fn reload(&self) -> Result<ReloadSummary, String> {
+        let settings = Settings::load(self.path())?;
+        let summary = self.apply(settings)?;
+        tracing::info!(?summary, "🔄 Configuration reloaded");
+        Ok(summary)
+    }
+
+    /// Reloads the configuration every time the process receives `SIGHUP`.
+    #[cfg(unix)]
+    pub fn spawn_sighup_listener(self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGHUP, reload disabled: {}", e);
+                return;
+            }
+        };
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                if let Err(e) = self.reload() {
+                    tracing::error!("Configuration reload failed: {}", e);
+                }
+            }
+        });
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        self.admin_token
+            .as_deref()
+            .is_some_and(|expected| bearer_matches(headers, expected))
+    }
+}
+
+/// Whether `headers` carry `Authorization: Bearer <expected>`.
+pub fn bearer_matches(headers: &HeaderMap, expected: &str) -> bool {
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compare without short-circuiting on the first differing byte
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// `POST /admin/reload` - reloads the configuration on demand.
+///
+/// Requires `Authorization: Bearer $ADMIN_TOKEN`.
+pub async fn reload_handler(State(reloader): State<Reloader>, headers: HeaderMap) -> Response {
+    if !reloader.authorized(&headers) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "unauthorized" })),
+        )
+            .into_response();
+    }
+    match reloader.reload() {
+        Ok(summary) => (StatusCode::OK, Json(summary)).into_response(),
+        Err(e) => {
+            tracing::error!("Configuration reload failed: {}", e);
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(serde_json::json!({ "error": e })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_overrides_extend_agents_and_thresholds() {
+        let file = FileConfig::parse(
+            r#"
+            [load_shed]
+            max_in_flight = 7
+
+            [error_reporting]
+            webhook_url = "https://hooks.example.com/errors"
+
+            [quotas.default]
+            daily_requests = 5
+
+            [prices]
+            "my-model" = { input_per_mtok = 1.0, output_per_mtok = 2.0 }
+
+            [[agents]]
+            id = "agent_001"
+            name = "Renamed"
+            description = "Replaces the built-in general assistant"
+            capabilities = ["text"]
+            model = "llama-3.3-70b-versatile"
+            system_prompt = "Be brief."
+
+            [[agents]]
+            id = "agent_005"
+            name = "NFT Copywriter"
+            description = "Writes NFT descriptions"
+            capabilities = ["nft"]
+            model = "llama-3.3-70b-versatile"
+            system_prompt = "Write vivid NFT descriptions."
+            "#,
+        )
+        .unwrap();
+        let settings = Settings::resolve(file);
+
+        let registry = AgentRegistry::new(settings.agents);
+        assert_eq!(registry.len(), builtin_agents().len() + 1);
+        assert_eq!(registry.list()[0].name, "Renamed");
+        assert!(registry.get("agent_005").is_some());
+        assert_eq!(settings.load_shed.max_in_flight, 7);
+        assert_eq!(
+            settings.error_reporting.webhook_url.as_deref(),
+            Some("https://hooks.example.com/errors")
+        );
+        assert_eq!(settings.quotas.limits("user-1").daily_requests, Some(5));
+        assert_eq!(settings.prices["my-model"].output_per_mtok, 2.0);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = FileConfig::parse("[load_shed]\nmax_inflight = 7\n").unwrap_err();
+        assert!(err.contains("max_inflight"), "{}", err);
+        let err = FileConfig::parse("[guardrails]\ndeny_patterns = [\"(\"]\n").unwrap_err();
+        assert!(err.contains("guardrail pattern"), "{}", err);
+        let err = FileConfig::parse(
+            r#"
+            [[agents]]
+            id = "agent_005"
+            name = "Scripted"
+            description = "Has a broken script"
+            capabilities = []
+            model = "llama-3.3-70b-versatile"
+            system_prompt = "Be brief."
+
+            [agents.scripts]
+            post_response = "reply +"
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            err.contains("agent agent_005: invalid post_response"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Correlation IDs tying together everything logged for one request.
+//!
+//! Every HTTP request gets an ID: the one sent in its `X-Request-Id` header,
+//! so IDs assigned by a gateway or the calling service carry through, or
+//! else a new random one. The request is handled inside a `request` tracing
+//! span carrying the ID, so each log line it causes, provider calls and
+//! retries included, can be found by it:
+//!
+//! ```text
+//! INFO request{correlation_id=5f0c2b7e9a414c6c8d1e2f3a4b5c6d7e}: mcp_server::providers::groq: Groq API response received successfully
+//! ```
+//!
+//! The ID is sent back in the `X-Request-Id` response header, in the
+//! `correlation_id` of `process_text` metadata and in error reports. Jobs
+//! queued by `submit_text` keep the ID of the request that submitted them.
+//! Each stdio request gets a new ID.
+//!
+//! Incoming IDs must be 1 to 128 letters, digits, `-`, `_`, `.` or `:`;
+//! others are replaced rather than written to the logs.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::future::Future;
+use tracing::Instrument;
+
+/// Header carrying the correlation ID in requests and responses.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest correlation ID accepted from a client.
+pub const MAX_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// A new random correlation ID (32 hex digits).
+pub fn new_correlation_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// The ID in the request's `X-Request-Id` header if it is acceptable, or a
+/// new one.
+///
+/// ```
+/// use axum::http::HeaderMap;
+/// use mcp_server::correlation::correlation_id_from_headers;
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert("x-request-id", "gw-42".parse().unwrap());
+/// assert_eq!(correlation_id_from_headers(&headers), "gw-42");
+/// assert_eq!(correlation_id_from_headers(&HeaderMap::new()).len(), 32);
+/// ```
+pub fn correlation_id_from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map_or_else(new_correlation_id, str::to_string)
+}
+
+fn is_valid(id: &str) -> bool {
+    (1..=MAX_ID_LEN).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+/// Runs `future` as the request identified by `id`, inside its `request`
+/// span.
+pub async fn with_correlation_id<F: Future>(id: String, future: F) -> F::Output {
+    let span = tracing::info_span!("request", correlation_id = %id);
+    CORRELATION_ID.scope(id, future.instrument(span)).await
+}
+
+/// Correlation ID of the request being handled; `None` outside of a request.
+pub fn current_correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(Clone::clone).ok()
+}
+
+/// Middleware assigning each request its correlation ID and returning it in
+/// the `X-Request-Id` response header.
+pub async fn middleware(request: Request, next: Next) -> Response {
+    let id = correlation_id_from_headers(request.headers());
+    let header = HeaderValue::from_str(&id).expect("correlation IDs are visible ASCII");
+    let mut response = with_correlation_id(id, next.run(request)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replaces_unacceptable_ids() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, "two words".parse().unwrap());
+        let id = correlation_id_from_headers(&headers);
+        assert_ne!(id, "two words");
+        assert!(is_valid(&id));
+        assert!(!is_valid(&"a".repeat(MAX_ID_LEN + 1)));
+
+        assert_eq!(current_correlation_id(), None);
+        let seen = with_correlation_id(id.clone(), async { current_correlation_id() }).await;
+        assert_eq!(seen, Some(id));
+    }
+}
@@ -0,0 +1,158 @@
+//! The `embed_text` method: vectors for texts, for retrieval and semantic
+//! routing.
+//!
+//! ```json
+//! { "jsonrpc": "2.0", "id": 1, "method": "embed_text",
+//!   "params": { "texts": ["bridge ETH to Arbitrum", "mint an NFT"] } }
+//! ```
+//!
+//! answers with one vector per text, in order:
+//!
+//! ```json
+//! { "embeddings": [[0.0123, -0.0456, ...], [...]],
+//!   "metadata": { "provider": "gemini", "model": "text-embedding-004",
+//!                 "dimensions": 768, "tokens_used": null, "processing_time_ms": 143 } }
+//! ```
+//!
+//! Without `provider`, the default backend computes the vectors if it
+//! [can](crate::providers::LlmProvider::supports_embeddings), else the first
+//! one that can: Gemini always, Azure OpenAI with an embedding deployment,
+//! Groq never. When none can, or with `"provider": "local"`, the server
+//! computes them itself with [`embed_local`], a hashing model that needs no
+//! backend but only captures shared words and spellings, not meaning.
+//!
+//! Vectors from different models can't be compared, so store the `model`
+//! with them. Each text must fit the [text limit](crate::limits), and a call
+//! embeds at most [`MAX_TEXTS`] texts. Calls count towards the caller's
+//! [quotas](crate::quota).
+
+use crate::providers::Embeddings;
+use serde::{Deserialize, Serialize};
+
+/// Most texts one `embed_text` call embeds.
+pub const MAX_TEXTS: usize = 100;
+
+/// Name of the server's own embedding model in the `provider` param.
+pub const LOCAL_PROVIDER: &str = "local";
+
+/// Model name reported for [`embed_local`] vectors.
+pub const LOCAL_MODEL: &str = "hashed-ngrams-256";
+
+/// Dimensions of [`embed_local`] vectors.
+pub const LOCAL_DIMENSIONS: usize = 256;
+
+/// Parameters for the embed_text JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedTextParams {
+    /// Texts to embed
+    pub texts: Vec<String>,
+    /// Optional backend computing the vectors, or `local` for the server's own
+    /// model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Optional embedding model overriding the backend's default; Azure
+    /// OpenAI uses its embedding deployment's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// Result of the embed_text JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedTextResult {
+    /// One vector per text, in input order
+    pub embeddings: Vec<Vec<f32>>,
+    /// How the vectors were computed
+    pub metadata: EmbeddingMetadata,
+}
+
+/// Metadata about computed embeddings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingMetadata {
+    /// Backend that computed the vectors, or `local`
+    pub provider: String,
+    /// Embedding model used
+    pub model: String,
+    /// Length of each vector
+    pub dimensions: usize,
+    /// Number of tokens consumed (if available)
+    pub tokens_used: Option<u32>,
+    /// Processing time in milliseconds
+    pub processing_time_ms: u64,
+}
+
+/// Embeds `texts` with the server's own model.
+///
+/// Each lowercased word and each three-letter piece of a word is hashed to
+/// one of [`LOCAL_DIMENSIONS`] dimensions, and the counts are scaled to unit
+/// length, so texts sharing words or spellings have a high cosine similarity.
+///
+/// ```
+/// use mcp_server::embeddings::embed_local;
+///
+/// let embeddings = embed_local(&["swap ETH".into(), "Swap eth!".into(), "mint".into()]);
+/// let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+/// let v = &embeddings.vectors;
+/// assert!((dot(&v[0], &v[1]) - 1.0).abs() < 1e-6);
+/// assert!(dot(&v[0], &v[2]) < 0.5);
+/// ```
+pub fn embed_local(texts: &[String]) -> Embeddings {
+    Embeddings {
+        vectors: texts.iter().map(|text| hashed_ngrams(text)).collect(),
+        model: LOCAL_MODEL.to_string(),
+        tokens_used: None,
+    }
+}
+
+fn hashed_ngrams(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; LOCAL_DIMENSIONS];
+    let mut add = |feature: &str| {
+        let hash = fnv1a(feature.as_bytes());
+        // The top bit picks the sign, so unrelated features tend to cancel
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[(hash % LOCAL_DIMENSIONS as u64) as usize] += sign;
+    };
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        let word = word.to_lowercase();
+        add(&word);
+        let padded: Vec<char> = format!(" {} ", word).chars().collect();
+        for trigram in padded.windows(3) {
+            add(&trigram.iter().collect::<String>());
+        }
+    }
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the standard library's hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_vectors_are_stable_unit_vectors() {
+        let embeddings = embed_local(&["bridge ETH to Arbitrum".into(), "".into()]);
+        let vector = &embeddings.vectors[0];
+        assert_eq!(vector.len(), LOCAL_DIMENSIONS);
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-6);
+        assert_eq!(
+            embed_local(&["bridge ETH to Arbitrum".into()]).vectors[0],
+            *vector
+        );
+        // Empty text has no features to normalize
+        assert!(embeddings.vectors[1].iter().all(|x| *x == 0.0));
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,381 @@
+//! Errors answered to JSON-RPC clients.
+//!
+//! Providers and handlers fail with a [`ServerError`], which decides the
+//! JSON-RPC error code, the localized message and a machine-readable `data`
+//! object. `data.kind` tells failures apart without parsing the message;
+//! provider failures also carry the underlying error in `data.details`, and
+//! the provider's HTTP status or `Retry-After` delay when there is one:
+//!
+//! ```json
+//! {
+//!   "code": -32008,
+//!   "message": "The AI provider is rate limiting requests, retry later",
+//!   "data": {
+//!     "kind": "rate_limited",
+//!     "provider": "groq",
+//!     "status": 429,
+//!     "retry_after_secs": 12,
+//!     "details": "Groq API error (429 Too Many Requests): Rate limit reached"
+//!   }
+//! }
+//! ```
+//!
+//! | Code     | `data.kind`                                            |
+//! |----------|--------------------------------------------------------|
+//! | `-32602` | `invalid_params`, `unknown_model`, `not_found`         |
+//! | `-32003` | `timeout`                                              |
+//! | `-32006` | `busy`                                                 |
+//! | `-32007` | `quota_exceeded`                                       |
+//! | `-32009` | `budget_exceeded`                                      |
+//! | `-32008` | `rate_limited`                                         |
+//! | `-32603` | `provider_error`, `unavailable`, `storage`, `internal` |
+
+use crate::budget::BudgetExceeded;
+use crate::i18n::{Locale, Message as Msg};
+use crate::models::JsonRpcError;
+use crate::quota::QuotaExceeded;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
+
+/// Kinds of things a request can name that may not exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    /// An agent, by ID
+    Agent,
+    /// An agent exposed as an MCP tool, by name
+    Tool,
+    /// A built-in pipeline, by ID
+    Pipeline,
+    /// A background job, by ID
+    Job,
+    /// A prompt template, by name
+    Prompt,
+}
+
+impl Resource {
+    fn name(self) -> &'static str {
+        match self {
+            Resource::Agent => "agent",
+            Resource::Tool => "tool",
+            Resource::Pipeline => "pipeline",
+            Resource::Job => "job",
+            Resource::Prompt => "prompt",
+        }
+    }
+
+    fn message(self) -> Msg {
+        match self {
+            Resource::Agent => Msg::AgentNotFound,
+            Resource::Tool => Msg::ToolNotFound,
+            Resource::Pipeline => Msg::PipelineNotFound,
+            Resource::Job => Msg::JobNotFound,
+            Resource::Prompt => Msg::PromptNotFound,
+        }
+    }
+}
+
+/// Why a request failed.
+///
+/// Displays as the underlying error, in English, for logs and error reports;
+/// clients get [`to_rpc_error`](Self::to_rpc_error).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerError {
+    /// A provider failed or sent a reply that could not be used
+    Provider {
+        /// HTTP status of the provider's answer, if it sent one
+        status: Option<u16>,
+        /// What went wrong
+        message: String,
+    },
+    /// A provider turned the call away with `429 Too Many Requests`
+    RateLimited {
+        /// How long the provider asked to wait, if it said
+        retry_after: Option<Duration>,
+        /// What went wrong
+        message: String,
+    },
+    /// A provider did not answer in time
+    Timeout(String),
+    /// A provider is at its [concurrency limit](crate::providers::limit)
+    Busy(String),
+    /// A provider's [circuit breaker](crate::providers::breaker) is open
+    Unavailable(String),
+    /// The params are invalid; takes the reason
+    InvalidParams(String),
+    /// The requested model isn't served by the provider
+    UnknownModel {
+        /// The model requested
+        model: String,
+        /// Which models are served instead
+        details: String,
+    },
+    /// Something the request names does not exist
+    NotFound {
+        /// What kind of thing
+        resource: Resource,
+        /// Its ID or name
+        id: String,
+    },
+    /// The caller has used up a [quota](crate::quota)
+    QuotaExceeded(QuotaExceeded),
+    /// The request spent its [budget](crate::budget)
+    BudgetExceeded(BudgetExceeded),
+    /// An agent change could not be saved
+    Storage(String),
+    /// Anything else that went wrong inside the server
+    Internal(String),
+}
+
+impl ServerError {
+    /// The error for an error `status` from `backend`: [`RateLimited`] for
+    /// `429`, [`Provider`] otherwise.
+    ///
+    /// [`RateLimited`]: Self::RateLimited
+    /// [`Provider`]: Self::Provider
+    ///
+    /// ```
+    /// use mcp_server::error::ServerError;
+    /// use reqwest::StatusCode;
+    ///
+    /// let error = ServerError::status("Groq", StatusCode::BAD_GATEWAY, "oops");
+    /// assert_eq!(error.code(), -32603);
+    /// assert_eq!(error.to_string(), "Groq API error (502 Bad Gateway): oops");
+    /// ```
+    pub fn status(backend: &str, status: StatusCode, body: &str) -> Self {
+        let message = match body {
+            "" => format!("{} API error ({})", backend, status),
+            body => format!("{} API error ({}): {}", backend, status, body),
+        };
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            ServerError::RateLimited {
+                retry_after: None,
+                message,
+            }
+        } else {
+            ServerError::Provider {
+                status: Some(status.as_u16()),
+                message,
+            }
+        }
+    }
+
+    /// A [`Provider`](Self::Provider) error without an HTTP status, such as
+    /// an unreadable reply.
+    pub fn provider(message: impl Into<String>) -> Self {
+        ServerError::Provider {
+            status: None,
+            message: message.into(),
+        }
+    }
+
+    /// Sets how long a rate-limited caller should wait; other errors are
+    /// returned unchanged.
+    pub fn with_retry_after(self, delay: Option<Duration>) -> Self {
+        match self {
+            ServerError::RateLimited {
+                retry_after,
+                message,
+            } => ServerError::RateLimited {
+                retry_after: delay.or(retry_after),
+                message,
+            },
+            error => error,
+        }
+    }
+
+    /// The JSON-RPC error code.
+    pub fn code(&self) -> i32 {
+        match self {
+            ServerError::InvalidParams(_)
+            | ServerError::UnknownModel { .. }
+            | ServerError::NotFound { .. } => -32602,
+            ServerError::Timeout(_) => -32003,
+            ServerError::Busy(_) => -32006,
+            ServerError::QuotaExceeded(_) => -32007,
+            ServerError::BudgetExceeded(_) => -32009,
+            ServerError::RateLimited { .. } => -32008,
+            ServerError::Provider { .. }
+            | ServerError::Unavailable(_)
+            | ServerError::Storage(_)
+            | ServerError::Internal(_) => -32603,
+        }
+    }
+
+    /// The value of `data.kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerError::Provider { .. } => "provider_error",
+            ServerError::RateLimited { .. } => "rate_limited",
+            ServerError::Timeout(_) => "timeout",
+            ServerError::Busy(_) => "busy",
+            ServerError::Unavailable(_) => "unavailable",
+            ServerError::InvalidParams(_) => "invalid_params",
+            ServerError::UnknownModel { .. } => "unknown_model",
+            ServerError::NotFound { .. } => "not_found",
+            ServerError::QuotaExceeded(_) => "quota_exceeded",
+            ServerError::BudgetExceeded(_) => "budget_exceeded",
+            ServerError::Storage(_) => "storage",
+            ServerError::Internal(_) => "internal",
+        }
+    }
+
+    /// Whether the provider, rather than the request, is at fault.
+    ///
+    /// Client errors such as `400 Bad Request` are the request's fault, while
+    /// `429`s, `5xx`s, timeouts and network errors are the provider's.
+    pub fn is_provider_fault(&self) -> bool {
+        !matches!(
+            self,
+            ServerError::Provider {
+                status: Some(400..=499),
+                ..
+            } | ServerError::InvalidParams(_)
+                | ServerError::UnknownModel { .. }
+                | ServerError::NotFound { .. }
+                | ServerError::QuotaExceeded(_)
+                | ServerError::BudgetExceeded(_)
+        )
+    }
+
+    /// The machine-readable `data` object.
+    pub fn data(&self) -> Value {
+        let mut data = match self {
+            ServerError::QuotaExceeded(exceeded) => json!(exceeded),
+            ServerError::BudgetExceeded(exceeded) => json!(exceeded),
+            ServerError::NotFound { resource, id } => {
+                json!({ "resource": resource.name(), "id": id })
+            }
+            // The message already says what is wrong with the params
+            ServerError::InvalidParams(_) => json!({}),
+            ServerError::UnknownModel { model, details } => {
+                json!({ "model": model, "details": details })
+            }
+            _ => json!({ "details": self.to_string() }),
+        };
+        data["kind"] = self.kind().into();
+        match self {
+            ServerError::Provider {
+                status: Some(status),
+                ..
+            } => data["status"] = (*status).into(),
+            ServerError::RateLimited { retry_after, .. } => {
+                data["status"] = 429.into();
+                if let Some(delay) = retry_after {
+                    data["retry_after_secs"] = delay.as_secs().into();
+                }
+            }
+            _ => {}
+        }
+        data
+    }
+
+    /// The JSON-RPC error object, with its message in `locale`.
+    pub fn to_rpc_error(&self, locale: Locale) -> JsonRpcError {
+        let message = match self {
+            ServerError::Provider { .. }
+            | ServerError::Unavailable(_)
+            | ServerError::Internal(_) => Msg::ProcessingFailed.text(locale).to_string(),
+            ServerError::RateLimited { .. } => Msg::ProviderRateLimited.text(locale).to_string(),
+            ServerError::Timeout(_) => Msg::ProviderTimeout.text(locale).to_string(),
+            ServerError::Busy(_) => Msg::ProviderBusy.text(locale).to_string(),
+            ServerError::Storage(_) => Msg::StorageFailed.text(locale).to_string(),
+            ServerError::InvalidParams(details) => Msg::InvalidParams.format(locale, details),
+            ServerError::UnknownModel { model, .. } => Msg::UnknownModel.format(locale, model),
+            ServerError::NotFound { resource, id } => resource.message().format(locale, id),
+            ServerError::QuotaExceeded(exceeded) => {
+                Msg::QuotaExceeded.format(locale, exceeded.limit)
+            }
+            ServerError::BudgetExceeded(exceeded) => {
+                Msg::BudgetExceeded.format(locale, exceeded.limit)
+            }
+        };
+        JsonRpcError {
+            code: self.code(),
+            message,
+            data: Some(self.data()),
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Provider { message, .. }
+            | ServerError::RateLimited { message, .. }
+            | ServerError::Timeout(message)
+            | ServerError::Busy(message)
+            | ServerError::Unavailable(message)
+            | ServerError::InvalidParams(message)
+            | ServerError::Storage(message)
+            | ServerError::Internal(message) => f.write_str(message),
+            ServerError::UnknownModel { details, .. } => f.write_str(details),
+            ServerError::NotFound { resource, id } => {
+                write!(f, "{} not found: {}", resource.name(), id)
+            }
+            ServerError::QuotaExceeded(exceeded) => {
+                write!(f, "{} is over its {} quota", exceeded.key, exceeded.limit)
+            }
+            ServerError::BudgetExceeded(exceeded) => write!(
+                f,
+                "request needs {} of its {} budget of {}",
+                exceeded.needed, exceeded.limit, exceeded.allowed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_provider_statuses_to_codes_and_data() {
+        let limited = ServerError::status("Groq", StatusCode::TOO_MANY_REQUESTS, "slow down")
+            .with_retry_after(Some(Duration::from_secs(12)));
+        let error = limited.to_rpc_error(Locale::En);
+        assert_eq!(error.code, -32008);
+        assert_eq!(
+            error.data,
+            Some(json!({
+                "kind": "rate_limited",
+                "status": 429,
+                "retry_after_secs": 12,
+                "details": "Groq API error (429 Too Many Requests): slow down",
+            }))
+        );
+        assert!(limited.is_provider_fault());
+
+        let rejected = ServerError::status("Groq", StatusCode::BAD_REQUEST, "bad");
+        assert_eq!(rejected.code(), -32603);
+        assert_eq!(rejected.data()["status"], 400);
+        assert!(!rejected.is_provider_fault());
+        assert!(ServerError::provider("Stream interrupted").is_provider_fault());
+    }
+
+    #[test]
+    fn localizes_messages_without_repeating_details() {
+        let missing = ServerError::NotFound {
+            resource: Resource::Agent,
+            id: "agent-404".into(),
+        };
+        let error = missing.to_rpc_error(Locale::Es);
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "Agente no encontrado: agent-404");
+        assert_eq!(
+            error.data,
+            Some(json!({ "kind": "not_found", "resource": "agent", "id": "agent-404" }))
+        );
+
+        let invalid = ServerError::InvalidParams("temperature must be between 0 and 2".into());
+        let error = invalid.to_rpc_error(Locale::En);
+        assert_eq!(
+            error.message,
+            "Invalid params: temperature must be between 0 and 2"
+        );
+        assert_eq!(error.data, Some(json!({ "kind": "invalid_params" })));
+    }
+}
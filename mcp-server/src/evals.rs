@@ -0,0 +1,669 @@
+//! Evaluation cases per agent, run against the live provider to catch
+//! regressions when prompts change.
+//!
+//! Operators store cases with the admin `add_eval_case` method. Each case is
+//! a message for the agent and what its reply should be like: properties
+//! checked on the text, a grader prompt asking a model to score it, or both.
+//!
+//! ```json
+//! { "jsonrpc": "2.0", "id": 1, "method": "add_eval_case",
+//!   "params": { "agent_id": "agent_002", "name": "gas",
+//!               "input": "What is gas on Ethereum?",
+//!               "expected": { "contains": ["fee"], "max_chars": 800, "language": "en" },
+//!               "grader_prompt": "The reply explains that gas pays for computation." } }
+//! ```
+//!
+//! `run_eval` runs an agent's cases, or some of them, and answers with a
+//! scored report:
+//!
+//! ```json
+//! { "agent_id": "agent_002", "prompt_version": "v2", "passed": 9, "failed": 1,
+//!   "score": 0.93, "cases": [
+//!     { "case_id": 4, "name": "gas", "passed": true, "score": 0.97,
+//!       "reply_text": "Gas is the fee...",
+//!       "checks": [ { "check": "contains \"fee\"", "passed": true }, ... ],
+//!       "grader": { "score": 0.9, "reason": "Explains gas as a fee for computation." },
+//!       "processing_time_ms": 1210 }, ... ] }
+//! ```
+//!
+//! A case's score is the mean of its checks, each 1 or 0, and the grader's
+//! score; it passes when every check does and the grader gave at least
+//! [`PASS_SCORE`]. The report's score is the mean over the cases, failed
+//! calls counting as 0. `prompt_version` runs a
+//! [version](crate::prompt_versions) of the agent's prompt other than the
+//! live one, so a candidate can be checked before it gets traffic.
+//!
+//! Cases run like `process_text` requests without a caller: no quotas,
+//! sessions or memories apply, and the response cache is bypassed. Grading
+//! calls go to the provider that answered, with its model unless
+//! `EVAL_GRADER_MODEL` names another.
+//!
+//! Cases are kept in an SQLite database, in process memory unless `EVAL_DB`
+//! names a file.
+//!
+//! # Environment Variables
+//!
+//! * `EVAL_DB` - Optional. SQLite file keeping the cases across restarts;
+//!   created if missing
+//! * `EVAL_GRADER_MODEL` - Optional. Model grading replies (default: the one
+//!   that answered)
+
+use crate::agent_db::migrate;
+use crate::audit::format_timestamp;
+use crate::language;
+use crate::models::{Agent, GenerationParams};
+use crate::providers::{CompletionRequest, LlmProvider};
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Schema migrations, applied in order, as in [`crate::agent_db::MIGRATIONS`].
+pub const MIGRATIONS: &[&str] = &[
+    // 1: one row per case; expected properties as a JSON object
+    "CREATE TABLE eval_cases (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        agent_id      TEXT NOT NULL,
+        name          TEXT,
+        input         TEXT NOT NULL,
+        expected      TEXT NOT NULL,
+        grader_prompt TEXT,
+        created_at    TEXT NOT NULL
+    );
+    CREATE INDEX eval_cases_agent ON eval_cases (agent_id, id);",
+];
+
+/// Most cases stored per agent.
+pub const MAX_CASES_PER_AGENT: usize = 100;
+
+/// Cases of one `run_eval` call running at once.
+pub const CONCURRENCY: usize = 4;
+
+/// Least grader score of a passing case.
+pub const PASS_SCORE: f64 = 0.7;
+
+/// System prompt of the grading call.
+pub const GRADER_PROMPT: &str = "You grade the replies of an AI assistant. Given the \
+    criteria, the user's message and the assistant's reply, score how well the reply meets \
+    the criteria, from 0 (not at all) to 1 (fully). Answer with only a JSON object such as \
+    {\"score\": 0.8, \"reason\": \"One short sentence.\"}.";
+
+/// Properties an agent's reply should have; every one is optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectations {
+    /// Texts the reply must contain, ignoring case
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contains: Vec<String>,
+    /// Texts the reply must not contain, ignoring case
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub not_contains: Vec<String>,
+    /// Regular expression the reply must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<String>,
+    /// Most characters in the reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chars: Option<usize>,
+    /// ISO 639-1 code of the language the reply must be in, one of
+    /// [`language::LANGUAGES`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+impl Expectations {
+    /// Whether no property is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A stored evaluation case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    /// ID to run or delete it by
+    pub id: i64,
+    /// Agent the case is for
+    pub agent_id: String,
+    /// Optional name, to tell cases apart in reports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Message sent to the agent
+    pub input: String,
+    /// Properties the reply should have
+    #[serde(default, skip_serializing_if = "Expectations::is_empty")]
+    pub expected: Expectations,
+    /// Criteria a model scores the reply against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grader_prompt: Option<String>,
+    /// When it was added, RFC 3339 in UTC
+    pub created_at: String,
+}
+
+/// Parameters for the add_eval_case JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddEvalCaseParams {
+    /// Agent the case is for
+    pub agent_id: String,
+    /// Optional name of the case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Message sent to the agent
+    pub input: String,
+    /// Properties the reply should have
+    #[serde(default)]
+    pub expected: Expectations,
+    /// Criteria a model scores the reply against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grader_prompt: Option<String>,
+}
+
+impl AddEvalCaseParams {
+    /// Checks that the case has an input and something to check, with a
+    /// valid regular expression and language.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.input.trim().is_empty() {
+            return Err("input must not be empty".to_string());
+        }
+        let grader_prompt = self.grader_prompt.as_deref().map(str::trim);
+        if grader_prompt == Some("") {
+            return Err("grader_prompt must not be empty".to_string());
+        }
+        if self.expected.is_empty() && grader_prompt.is_none() {
+            return Err("a case needs expected properties, a grader_prompt or both".to_string());
+        }
+        if let Some(pattern) = &self.expected.matches {
+            Regex::new(pattern).map_err(|e| format!("matches is not a valid regex: {}", e))?;
+        }
+        if let Some(code) = &self.expected.language {
+            if language::name(code).is_none() {
+                return Err(format!("unsupported language {}", code));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parameters for the list_eval_cases JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListEvalCasesParams {
+    /// Agent whose cases to list
+    pub agent_id: String,
+}
+
+/// Result of the list_eval_cases JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListEvalCasesResult {
+    /// The agent's cases, oldest first
+    pub cases: Vec<EvalCase>,
+}
+
+/// Parameters for the delete_eval_case JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteEvalCaseParams {
+    /// ID of the case to delete
+    pub case_id: i64,
+}
+
+/// Result of the delete_eval_case JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteEvalCaseResult {
+    /// Number of cases deleted, 0 or 1
+    pub deleted: usize,
+}
+
+/// Parameters for the run_eval JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunEvalParams {
+    /// Agent to evaluate
+    pub agent_id: String,
+    /// Optional IDs of the cases to run; all of the agent's when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_ids: Option<Vec<i64>>,
+    /// Optional version of the agent's prompt to run; the live one when
+    /// omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+/// One checked property of a reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalCheck {
+    /// The property, e.g. `contains "fee"`
+    pub check: String,
+    /// Whether the reply has it
+    pub passed: bool,
+}
+
+/// A grader's score of a reply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraderVerdict {
+    /// From 0 to 1
+    pub score: f64,
+    /// Why, in the grader's words
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Outcome of one case.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCaseResult {
+    /// ID of the case
+    pub case_id: i64,
+    /// Name of the case, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Whether every check passed and the grader was satisfied
+    pub passed: bool,
+    /// From 0 to 1, see the [module docs](self)
+    pub score: f64,
+    /// The agent's reply, unless the call failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_text: Option<String>,
+    /// Properties checked on the reply
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<EvalCheck>,
+    /// The grader's score, for cases with a grader prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grader: Option<GraderVerdict>,
+    /// Why the agent or the grader could not be asked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time the agent took to reply in milliseconds
+    pub processing_time_ms: u64,
+}
+
+impl EvalCaseResult {
+    /// A case whose agent or grader call failed.
+    pub fn failed(case: &EvalCase, error: String, processing_time_ms: u64) -> Self {
+        Self {
+            case_id: case.id,
+            name: case.name.clone(),
+            passed: false,
+            score: 0.0,
+            reply_text: None,
+            checks: Vec::new(),
+            grader: None,
+            error: Some(error),
+            processing_time_ms,
+        }
+    }
+}
+
+/// Result of the run_eval JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    /// Agent evaluated
+    pub agent_id: String,
+    /// Version of the agent's prompt that was run, for agents with versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    /// Cases that passed
+    pub passed: usize,
+    /// Cases that failed
+    pub failed: usize,
+    /// Mean score of the cases, from 0 to 1
+    pub score: f64,
+    /// Outcome of each case, in the order they were added
+    pub cases: Vec<EvalCaseResult>,
+    /// Time the whole run took in milliseconds
+    pub total_time_ms: u64,
+}
+
+impl EvalReport {
+    /// Totals the outcomes of `cases`.
+    pub fn new(
+        agent_id: String,
+        prompt_version: Option<String>,
+        cases: Vec<EvalCaseResult>,
+        total_time_ms: u64,
+    ) -> Self {
+        let passed = cases.iter().filter(|case| case.passed).count();
+        let score = match cases.len() {
+            0 => 0.0,
+            n => cases.iter().map(|case| case.score).sum::<f64>() / n as f64,
+        };
+        Self {
+            agent_id,
+            prompt_version,
+            passed,
+            failed: cases.len() - passed,
+            score,
+            cases,
+            total_time_ms,
+        }
+    }
+}
+
+/// Evaluation cases, or nothing when unconfigured.
+///
+/// Cheap to clone; clones share the database.
+#[derive(Debug, Clone, Default)]
+pub struct Evals {
+    conn: Option<Arc<Mutex<Connection>>>,
+    grader_model: Option<String>,
+}
+
+impl Evals {
+    /// Keeps cases in the SQLite file at `path`, created and migrated if
+    /// needed, or in process memory for `None`.
+    pub fn open(path: Option<&Path>) -> Result<Self, String> {
+        let conn = match path {
+            Some(path) => Connection::open(path)
+                .map_err(|e| format!("Failed to open eval db {}: {}", path.display(), e)),
+            None => {
+                Connection::open_in_memory().map_err(|e| format!("Failed to open eval db: {}", e))
+            }
+        };
+        let mut conn = conn?;
+        migrate(&mut conn, MIGRATIONS, "Eval db")
+            .map_err(|e| format!("Failed to migrate eval db: {}", e))?;
+        Ok(Self {
+            conn: Some(Arc::new(Mutex::new(conn))),
+            grader_model: None,
+        })
+    }
+
+    /// Grades replies with `model` instead of the one that answered.
+    pub fn with_grader_model(mut self, model: impl Into<String>) -> Self {
+        self.grader_model = Some(model.into());
+        self
+    }
+
+    /// The cases configured by the environment.
+    ///
+    /// Fails if `EVAL_DB` can't be opened.
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let mut evals = Self::open(var("EVAL_DB").as_deref().map(Path::new))?;
+        if let Some(model) = var("EVAL_GRADER_MODEL") {
+            evals = evals.with_grader_model(model.trim());
+        }
+        Ok(evals)
+    }
+
+    /// Model grading replies, if one is configured.
+    pub fn grader_model(&self) -> Option<&str> {
+        self.grader_model.as_deref()
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>, String> {
+        match &self.conn {
+            Some(conn) => Ok(conn.lock().unwrap()),
+            None => Err("Evaluation cases are not stored".to_string()),
+        }
+    }
+
+    /// Stores a case, failing when its agent has [`MAX_CASES_PER_AGENT`].
+    ///
+    /// Blocks on SQLite.
+    pub fn add(&self, params: AddEvalCaseParams) -> Result<EvalCase, String> {
+        let conn = self.conn()?;
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM eval_cases WHERE agent_id = ?1",
+                params![params.agent_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to read eval cases: {}", e))?;
+        if count as usize >= MAX_CASES_PER_AGENT {
+            return Err(format!(
+                "agent {} already has {} eval cases",
+                params.agent_id, MAX_CASES_PER_AGENT
+            ));
+        }
+        let created_at = format_timestamp(Utc::now());
+        conn.execute(
+            "INSERT INTO eval_cases (agent_id, name, input, expected, grader_prompt, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                params.agent_id,
+                params.name,
+                params.input,
+                serde_json::to_string(&params.expected).unwrap(),
+                params.grader_prompt,
+                created_at,
+            ],
+        )
+        .map_err(|e| format!("Failed to store eval case: {}", e))?;
+        Ok(EvalCase {
+            id: conn.last_insert_rowid(),
+            agent_id: params.agent_id,
+            name: params.name,
+            input: params.input,
+            expected: params.expected,
+            grader_prompt: params.grader_prompt,
+            created_at,
+        })
+    }
+
+    /// The cases of `agent_id`, oldest first.
+    pub fn list(&self, agent_id: &str) -> Result<Vec<EvalCase>, String> {
+        let conn = self.conn()?;
+        conn.prepare(
+            "SELECT id, agent_id, name, input, expected, grader_prompt, created_at
+             FROM eval_cases WHERE agent_id = ?1 ORDER BY id",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![agent_id], |row| {
+                let expected: String = row.get(4)?;
+                Ok(EvalCase {
+                    id: row.get(0)?,
+                    agent_id: row.get(1)?,
+                    name: row.get(2)?,
+                    input: row.get(3)?,
+                    expected: serde_json::from_str(&expected).unwrap_or_default(),
+                    grader_prompt: row.get(5)?,
+                    created_at: row.get(6)?,
+                })
+            })?
+            .collect()
+        })
+        .map_err(|e| format!("Failed to read eval cases: {}", e))
+    }
+
+    /// Deletes case `case_id`, returning the number deleted.
+    pub fn delete(&self, case_id: i64) -> Result<usize, String> {
+        self.conn()?
+            .execute("DELETE FROM eval_cases WHERE id = ?1", params![case_id])
+            .map_err(|e| format!("Failed to delete eval case: {}", e))
+    }
+}
+
+/// Checks `reply` for each of the `expected` properties.
+pub fn check(expected: &Expectations, reply: &str) -> Vec<EvalCheck> {
+    let lowercase = reply.to_lowercase();
+    let mut checks = Vec::new();
+    for text in &expected.contains {
+        checks.push(EvalCheck {
+            check: format!("contains {:?}", text),
+            passed: lowercase.contains(&text.to_lowercase()),
+        });
+    }
+    for text in &expected.not_contains {
+        checks.push(EvalCheck {
+            check: format!("does not contain {:?}", text),
+            passed: !lowercase.contains(&text.to_lowercase()),
+        });
+    }
+    if let Some(pattern) = &expected.matches {
+        checks.push(EvalCheck {
+            check: format!("matches {:?}", pattern),
+            passed: Regex::new(pattern).is_ok_and(|regex| regex.is_match(reply)),
+        });
+    }
+    if let Some(max_chars) = expected.max_chars {
+        checks.push(EvalCheck {
+            check: format!("at most {} characters", max_chars),
+            passed: reply.chars().count() <= max_chars,
+        });
+    }
+    if let Some(code) = &expected.language {
+        checks.push(EvalCheck {
+            check: format!("in language {}", code),
+            passed: language::detect(reply).is_some_and(|detection| detection.code == code),
+        });
+    }
+    checks
+}
+
+/// Scores a reply from its checks and the grader's verdict, as described in
+/// the [module docs](self), returning the score and whether it passed.
+///
+/// ```
+/// # use mcp_server::evals::{score, EvalCheck, GraderVerdict};
+/// let check = |passed| EvalCheck { check: "contains \"fee\"".into(), passed };
+/// let grader = GraderVerdict { score: 0.8, reason: String::new() };
+/// assert_eq!(score(&[check(true)], Some(&grader)), (0.9, true));
+/// assert_eq!(score(&[check(true), check(false)], None), (0.5, false));
+/// ```
+pub fn score(checks: &[EvalCheck], grader: Option<&GraderVerdict>) -> (f64, bool) {
+    let points = checks.iter().filter(|check| check.passed).count() as f64
+        + grader.map_or(0.0, |grader| grader.score);
+    let count = checks.len() + usize::from(grader.is_some());
+    let score = if count == 0 {
+        1.0
+    } else {
+        points / count as f64
+    };
+    let passed = checks.iter().all(|check| check.passed)
+        && grader.is_none_or(|grader| grader.score >= PASS_SCORE);
+    (score, passed)
+}
+
+/// Asks `provider` with `model` how well `reply` to `case` meets its grader
+/// prompt.
+pub async fn grade(
+    provider: &dyn LlmProvider,
+    model: String,
+    agent: &Agent,
+    case: &EvalCase,
+    reply: &str,
+) -> Result<GraderVerdict, String> {
+    let criteria = case.grader_prompt.as_deref().unwrap_or_default();
+    let request = CompletionRequest {
+        agent: Arc::new(Agent {
+            system_prompt: GRADER_PROMPT.to_string(),
+            stop: Vec::new(),
+            fallbacks: Vec::new(),
+            delimit_user_input: false,
+            allow_system_messages: false,
+            knowledge_base: None,
+            allowed_models: Vec::new(),
+            scripts: Default::default(),
+            ..agent.clone()
+        }),
+        model,
+        user_text: format!(
+            "Criteria:\n{}\n\nUser message:\n{}\n\nAssistant reply:\n{}",
+            criteria, case.input, reply
+        ),
+        conversation_history: None,
+        generation: GenerationParams {
+            temperature: Some(0.0),
+            max_tokens: Some(256),
+            ..Default::default()
+        },
+        tools: Vec::new(),
+        timeout: None,
+        images: Vec::new(),
+    };
+    let completion = provider
+        .complete(request)
+        .await
+        .map_err(|e| format!("Grading failed: {}", e))?;
+    parse_verdict(completion.text.as_deref().unwrap_or_default())
+        .ok_or_else(|| "The grader gave no score".to_string())
+}
+
+/// Reads the verdict from the reply of a grading call: the first JSON object
+/// in it, with its score clamped to 0 to 1.
+///
+/// ```
+/// # use mcp_server::evals::parse_verdict;
+/// let verdict = parse_verdict("```json\n{\"score\": 1.5, \"reason\": \"Spot on.\"}\n```");
+/// assert_eq!(verdict.unwrap().score, 1.0);
+/// assert!(parse_verdict("Looks good!").is_none());
+/// ```
+pub fn parse_verdict(reply: &str) -> Option<GraderVerdict> {
+    let (start, end) = (reply.find('{')?, reply.rfind('}')?);
+    if end < start {
+        return None;
+    }
+    let verdict: GraderVerdict = serde_json::from_str(&reply[start..=end]).ok()?;
+    verdict.score.is_finite().then(|| GraderVerdict {
+        score: verdict.score.clamp(0.0, 1.0),
+        ..verdict
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_checks_cases() {
+        let evals = Evals::open(None).unwrap();
+        let params: AddEvalCaseParams = serde_json::from_value(serde_json::json!({
+            "agent_id": "agent_002",
+            "name": "gas",
+            "input": "What is gas?",
+            "expected": {
+                "contains": ["Fee"],
+                "not_contains": ["bitcoin"],
+                "matches": "(?i)^gas",
+                "max_chars": 80,
+                "language": "en",
+            },
+        }))
+        .unwrap();
+        assert!(params.validate().is_ok());
+        let case = evals.add(params.clone()).unwrap();
+        assert_eq!(
+            evals.list("agent_002").unwrap(),
+            std::slice::from_ref(&case)
+        );
+        assert!(evals.list("agent_001").unwrap().is_empty());
+
+        let checks = check(&case.expected, "Gas is the fee you pay for a transaction.");
+        assert_eq!(checks.len(), 5);
+        assert!(checks.iter().all(|check| check.passed), "{:?}", checks);
+        let checks = check(
+            &case.expected,
+            "On Bitcoin you pay fees to the miners, and there is no gas.",
+        );
+        let failed: Vec<_> = checks.iter().filter(|c| !c.passed).collect();
+        assert_eq!(failed.len(), 2, "{:?}", checks);
+        assert_eq!(score(&checks, None), (0.6, false));
+
+        assert!(AddEvalCaseParams {
+            expected: Expectations::default(),
+            ..params.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(AddEvalCaseParams {
+            expected: Expectations {
+                matches: Some("(".into()),
+                ..Default::default()
+            },
+            ..params.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(AddEvalCaseParams {
+            expected: Expectations::default(),
+            grader_prompt: Some("Explains gas.".into()),
+            ..params
+        }
+        .validate()
+        .is_ok());
+
+        assert_eq!(evals.delete(case.id).unwrap(), 1);
+        assert_eq!(evals.delete(case.id).unwrap(), 0);
+        assert!(Evals::default().list("agent_002").is_err());
+    }
+}
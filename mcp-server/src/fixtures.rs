@@ -0,0 +1,280 @@
+//! Recorded provider calls, for hermetic tests.
+//!
+//! In `record` mode, every request the provider [`HttpClient`] sends with
+//! [`send_with_retry`](HttpClient::send_with_retry) goes out as usual, and
+//! the exchange is saved to a fixture file. In `replay` mode nothing goes out:
+//! the response is served from the fixture recorded for the same request, so
+//! the providers' parsing, error handling and everything built on them run
+//! deterministically without network access or API keys.
+//!
+//! Requests are matched by method, URL and body. Each fixture is a JSON file
+//! named after the host and a hash of those, in the format of the checked-in
+//! `testdata/providers` responses, with the request alongside:
+//!
+//! ```json
+//! { "request": { "method": "POST", "url": "https://api.groq.com/openai/v1/chat/completions",
+//!                "body": { "model": "llama-3.3-70b-versatile", "messages": [...] } },
+//!   "status": 200, "content_type": "application/json",
+//!   "body": { "choices": [...], "usage": { "total_tokens": 42 } } }
+//! ```
+//!
+//! Headers are not recorded, so API keys stay out of fixtures. Bodies that
+//! aren't JSON, such as streamed replies, are kept as strings; streams are
+//! read to the end before being handed on while recording, and served whole
+//! when replayed. Fixtures can be edited by hand to exercise error paths, a
+//! `429` or a malformed body for instance. A request without a fixture is
+//! answered `501 Not Implemented`, naming the file it was looked for in.
+//!
+//! # Environment Variables
+//!
+//! * `PROVIDER_FIXTURES` - Optional. `record` or `replay`; unset sends
+//!   provider calls as usual
+//! * `PROVIDER_FIXTURE_DIR` - Optional. Directory of the fixture files
+//!   (default: `testdata/fixtures`)
+
+use axum::http;
+use reqwest::{Request, Response, StatusCode};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Directory of the fixture files when `PROVIDER_FIXTURE_DIR` is unset.
+pub const DEFAULT_DIR: &str = "testdata/fixtures";
+
+/// What happens to provider calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Send them, saving each exchange
+    Record,
+    /// Answer them from the saved exchanges
+    Replay,
+}
+
+/// Fixture files of provider calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixtures {
+    mode: FixtureMode,
+    dir: PathBuf,
+}
+
+impl Fixtures {
+    /// Records to, or replays from, the files in `dir`.
+    pub fn new(mode: FixtureMode, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode,
+            dir: dir.into(),
+        }
+    }
+
+    /// Reads the settings, or `None` if `PROVIDER_FIXTURES` is unset.
+    ///
+    /// Fails if it is neither `record` nor `replay`.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let Some(mode) = var("PROVIDER_FIXTURES") else {
+            return Ok(None);
+        };
+        let mode = match mode.trim().to_ascii_lowercase().as_str() {
+            "record" => FixtureMode::Record,
+            "replay" => FixtureMode::Replay,
+            other => {
+                return Err(format!(
+                    "PROVIDER_FIXTURES must be record or replay, got {}",
+                    other
+                ))
+            }
+        };
+        let dir = var("PROVIDER_FIXTURE_DIR").unwrap_or_else(|| DEFAULT_DIR.to_string());
+        Ok(Some(Self::new(mode, dir)))
+    }
+
+    /// Whether calls are recorded or replayed.
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    /// Directory of the fixture files.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file holding the exchange of `request`.
+    pub fn path(&self, request: &Request) -> PathBuf {
+        let digest = Sha256::new()
+            .chain_update(request.method().as_str())
+            .chain_update([0])
+            .chain_update(request.url().as_str())
+            .chain_update([0])
+            .chain_update(body_bytes(request))
+            .finalize();
+        let hash: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        let host = request.url().host_str().unwrap_or("local");
+        self.dir.join(format!("{}-{}.json", host, hash))
+    }
+
+    /// The recorded response to `request`, or a `501` if there is none.
+    pub fn replay(&self, request: &Request) -> Response {
+        let path = self.path(request);
+        let fixture = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Value>(&text).ok());
+        let Some(fixture) = fixture else {
+            tracing::warn!(
+                "No fixture for {} {} in {}",
+                request.method(),
+                request.url(),
+                path.display()
+            );
+            let body = json!({ "error": { "message": format!(
+                "No fixture recorded for {} {} in {}",
+                request.method(),
+                request.url(),
+                path.display()
+            ) } });
+            return response(
+                StatusCode::NOT_IMPLEMENTED,
+                Some("application/json"),
+                body.to_string(),
+            );
+        };
+        let status = fixture["status"]
+            .as_u64()
+            .and_then(|status| StatusCode::from_u16(status as u16).ok())
+            .unwrap_or(StatusCode::OK);
+        // String bodies are served verbatim, to replay non-JSON responses
+        let body = match &fixture["body"] {
+            Value::String(raw) => raw.clone(),
+            json => json.to_string(),
+        };
+        response(status, fixture["content_type"].as_str(), body)
+    }
+
+    /// Saves the exchange of `request` and `response`, and returns the
+    /// response to use in its place, its body read to the end.
+    ///
+    /// Failing to save is logged, not returned, so recording never breaks
+    /// the calls themselves.
+    pub async fn record(&self, request: &Request, response: Response) -> reqwest::Result<Response> {
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = response.text().await?;
+        let fixture = json!({
+            "request": {
+                "method": request.method().as_str(),
+                "url": request.url().as_str(),
+                "body": as_json(&String::from_utf8_lossy(body_bytes(request))),
+            },
+            "status": status.as_u16(),
+            "content_type": content_type,
+            "body": as_json(&body),
+        });
+        let path = self.path(request);
+        let saved = std::fs::create_dir_all(&self.dir).and_then(|()| {
+            std::fs::write(
+                &path,
+                serde_json::to_string_pretty(&fixture).unwrap() + "\n",
+            )
+        });
+        match saved {
+            Ok(()) => tracing::debug!("Recorded {} to {}", request.url(), path.display()),
+            Err(e) => tracing::warn!("Failed to record fixture {}: {}", path.display(), e),
+        }
+        Ok(self::response(status, content_type.as_deref(), body))
+    }
+}
+
+/// The body of `request`, empty for streamed or absent bodies.
+fn body_bytes(request: &Request) -> &[u8] {
+    request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default()
+}
+
+/// `text` as JSON if it is, else as a string.
+fn as_json(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+/// A response with `status`, `body` and, if given, a `Content-Type`.
+fn response(status: StatusCode, content_type: Option<&str>, body: String) -> Response {
+    let mut builder = http::Response::builder().status(status);
+    if let Some(content_type) = content_type {
+        builder = builder.header(http::header::CONTENT_TYPE, content_type);
+    }
+    Response::from(builder.body(body).expect("status and header are valid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GenerationParams;
+    use crate::providers::{groq, CompletionRequest, GroqProvider, LlmProvider};
+    use axum::routing::post;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn records_and_replays_provider_calls() {
+        // A Groq stand-in that answers once, then goes away
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            post(|| async {
+                axum::Json(json!({
+                    "choices": [{ "message": { "content": "Gas is the fee." },
+                                  "finish_reason": "stop" }],
+                    "usage": { "total_tokens": 42, "prompt_tokens": 30 },
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/v1/chat/completions",
+            listener.local_addr().unwrap()
+        );
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let dir = std::env::temp_dir().join(format!("fixtures-{}", uuid::Uuid::new_v4()));
+        let provider = |mode| {
+            let client = crate::http_client::HttpClient::from(reqwest::Client::new())
+                .with_fixtures(Some(Fixtures::new(mode, &dir)));
+            GroqProvider::new(client, "secret-key".into()).with_api_url(url.clone())
+        };
+        let request = |text: &str| CompletionRequest {
+            agent: Arc::new(crate::agents::builtin_agents().remove(1)),
+            model: groq::GROQ_DEFAULT_MODEL.to_string(),
+            user_text: text.to_string(),
+            conversation_history: None,
+            generation: GenerationParams::default(),
+            tools: Vec::new(),
+            timeout: None,
+            images: Vec::new(),
+        };
+
+        let recorded = provider(FixtureMode::Record)
+            .complete(request("What is gas?"))
+            .await
+            .unwrap();
+        server.abort();
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let fixture = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(fixture.contains("\"total_tokens\": 42"));
+        assert!(!fixture.contains("secret-key"));
+
+        let replaying = provider(FixtureMode::Replay);
+        let replayed = replaying.complete(request("What is gas?")).await.unwrap();
+        assert_eq!(replayed, recorded);
+        assert_eq!(replayed.text.as_deref(), Some("Gas is the fee."));
+        let missing = replaying.complete(request("What is a DAO?")).await;
+        assert!(missing
+            .unwrap_err()
+            .to_string()
+            .contains("No fixture recorded"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+//! Checks applied to agent replies before they are returned.
+//!
+//! Rules are set in the `[guardrails]` section of the config file and change
+//! on reload:
+//!
+//! ```toml
+//! [guardrails]
+//! # Replies must not match these regular expressions
+//! deny_patterns = ["(?i)guaranteed (returns|profit)", "(?i)\\bseed phrase\\b"]
+//! # Longest reply, in characters
+//! max_reply_chars = 4000
+//! # Times a breaking reply is asked for again before it is fixed up
+//! max_regenerations = 1
+//!
+//! # Text every reply of an agent must contain
+//! [guardrails.disclaimers]
+//! agent_002 = "This is not financial advice."
+//! ```
+//!
+//! A reply that matches a deny pattern or is too long is requested again, up
+//! to `max_regenerations` times; the calls count towards the reply's tokens
+//! and cost. A reply still breaking the rules is then fixed up: matches are
+//! replaced with `[withheld]`, the text is cut to the length limit and a
+//! missing disclaimer is appended. Every violation is logged as a warning.
+//!
+//! Only replies with text are checked; a reply that only requests tool calls
+//! passes unchanged.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Replaces text matching a deny pattern.
+pub const WITHHELD: &str = "[withheld]";
+
+/// `[guardrails]` section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuardrailConfig {
+    /// Regular expressions replies must not match
+    pub deny_patterns: Vec<String>,
+    /// Longest reply in characters, if limited
+    pub max_reply_chars: Option<usize>,
+    /// Times a reply breaking a deny pattern or the length limit is requested
+    /// again before it is fixed up
+    pub max_regenerations: u32,
+    /// Text the replies of an agent must contain, by agent ID
+    pub disclaimers: HashMap<String, String>,
+}
+
+impl GuardrailConfig {
+    /// Checks that every deny pattern is a valid regular expression.
+    pub fn validate(&self) -> Result<(), String> {
+        self.compile().map(|_| ())
+    }
+
+    fn compile(&self) -> Result<Vec<Regex>, String> {
+        self.deny_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|e| format!("Invalid guardrail pattern {}: {}", pattern, e))
+            })
+            .collect()
+    }
+}
+
+/// A rule a reply breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The reply matches this deny pattern
+    Denied(String),
+    /// The reply is longer than allowed
+    TooLong {
+        /// Characters in the reply
+        chars: usize,
+        /// Characters allowed
+        max: usize,
+    },
+    /// The reply lacks the agent's disclaimer
+    MissingDisclaimer,
+}
+
+impl Violation {
+    /// Whether asking the provider again might avoid the violation; a missing
+    /// disclaimer is simply appended.
+    pub fn regenerates(&self) -> bool {
+        !matches!(self, Violation::MissingDisclaimer)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Denied(pattern) => write!(f, "matches deny pattern {}", pattern),
+            Violation::TooLong { chars, max } => {
+                write!(f, "has {} characters, at most {} are allowed", chars, max)
+            }
+            Violation::MissingDisclaimer => f.write_str("lacks the agent's disclaimer"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Rules {
+    config: GuardrailConfig,
+    deny: Vec<Regex>,
+}
+
+/// The configured reply rules.
+///
+/// Cheap to clone; clones share the rules.
+#[derive(Debug, Clone, Default)]
+pub struct Guardrails {
+    rules: Arc<RwLock<Rules>>,
+}
+
+impl Guardrails {
+    /// Guardrails enforcing `config`.
+    pub fn new(config: GuardrailConfig) -> Self {
+        let guardrails = Self::default();
+        guardrails.reconfigure(config);
+        guardrails
+    }
+
+    /// Swaps in new rules, e.g. after a config reload.
+    ///
+    /// Invalid deny patterns are skipped; config files are
+    /// [validated](GuardrailConfig::validate) before they get here.
+    pub fn reconfigure(&self, config: GuardrailConfig) {
+        let deny = config
+            .deny_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    tracing::warn!("Skipping guardrail pattern {}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        *self.rules.write().unwrap() = Rules { config, deny };
+    }
+
+    /// Times a breaking reply may be requested again.
+    pub fn max_regenerations(&self) -> u32 {
+        self.rules.read().unwrap().config.max_regenerations
+    }
+
+    /// The rules a reply of agent `agent_id` breaks.
+    pub fn check(&self, agent_id: &str, text: &str) -> Vec<Violation> {
+        let rules = self.rules.read().unwrap();
+        let mut violations: Vec<Violation> = rules
+            .deny
+            .iter()
+            .filter(|regex| regex.is_match(text))
+            .map(|regex| Violation::Denied(regex.as_str().to_string()))
+            .collect();
+        if let Some(max) = rules.config.max_reply_chars {
+            let chars = text.chars().count();
+            if chars > max {
+                violations.push(Violation::TooLong { chars, max });
+            }
+        }
+        if let Some(disclaimer) = rules.config.disclaimers.get(agent_id) {
+            if !text.contains(disclaimer.as_str()) {
+                violations.push(Violation::MissingDisclaimer);
+            }
+        }
+        violations
+    }
+
+    /// `text` fixed up to follow the rules, and the rules it broke.
+    ///
+    /// ```
+    /// use mcp_server::guardrails::{GuardrailConfig, Guardrails};
+    ///
+    /// let guardrails = Guardrails::new(GuardrailConfig {
+    ///     deny_patterns: vec!["(?i)guaranteed".into()],
+    ///     disclaimers: [("agent_002".into(), "Not financial advice.".into())].into(),
+    ///     ..Default::default()
+    /// });
+    /// let (text, violations) = guardrails.enforce("agent_002", "Guaranteed 10x!");
+    /// assert_eq!(text, "[withheld] 10x!\n\nNot financial advice.");
+    /// assert_eq!(violations.len(), 2);
+    /// ```
+    pub fn enforce(&self, agent_id: &str, text: &str) -> (String, Vec<Violation>) {
+        let violations = self.check(agent_id, text);
+        if violations.is_empty() {
+            return (text.to_string(), violations);
+        }
+        let rules = self.rules.read().unwrap();
+        let mut text = text.to_string();
+        for regex in &rules.deny {
+            text = regex.replace_all(&text, WITHHELD).into_owned();
+        }
+        let disclaimer = rules
+            .config
+            .disclaimers
+            .get(agent_id)
+            .filter(|disclaimer| !text.contains(disclaimer.as_str()))
+            .map(|disclaimer| format!("\n\n{}", disclaimer));
+        if let Some(max) = rules.config.max_reply_chars {
+            // Room is kept for the disclaimer, which is never cut
+            let room = max.saturating_sub(disclaimer.as_deref().map_or(0, |d| d.chars().count()));
+            if text.chars().count() > room {
+                text = text.chars().take(room.saturating_sub(1)).collect();
+                text.push('…');
+            }
+        }
+        if let Some(disclaimer) = disclaimer {
+            text.push_str(&disclaimer);
+        }
+        (text, violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_long_replies_keeping_the_disclaimer() {
+        let guardrails = Guardrails::new(GuardrailConfig {
+            max_reply_chars: Some(20),
+            disclaimers: [("agent_002".into(), "DYOR.".into())].into(),
+            ..Default::default()
+        });
+        assert!(guardrails.check("agent_001", "short").is_empty());
+        assert_eq!(
+            guardrails.check("agent_002", "short"),
+            [Violation::MissingDisclaimer]
+        );
+
+        let (text, violations) = guardrails.enforce("agent_002", "ETH is a great long-term hold");
+        assert_eq!(text, "ETH is a gre…\n\nDYOR.");
+        assert_eq!(text.chars().count(), 20);
+        assert_eq!(
+            violations,
+            [
+                Violation::TooLong { chars: 29, max: 20 },
+                Violation::MissingDisclaimer
+            ]
+        );
+        assert!(violations[0].regenerates());
+        assert!(!violations[1].regenerates());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let config = GuardrailConfig {
+            deny_patterns: vec!["(".into()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(Guardrails::new(config).check("agent_001", "(").is_empty());
+    }
+}
@@ -1,109 +1,892 @@
 //! Request handlers for JSON-RPC methods.
 //!
 //! This module contains the HTTP request handlers that process incoming JSON-RPC
-//! requests and route them to the appropriate functionality.
+//! requests and route them to the appropriate functionality. Routing itself
+//! lives in [`dispatch`], which is transport-agnostic.
 
-use crate::agents::{find_agent_by_id, get_agents};
-use crate::gemini::process_with_gemini;
+use crate::access_log::AccessEntry;
+use crate::accounting::{Outcome, UsageReportParams};
+use crate::agents::{validate_agent_id, AgentRegistry};
+use crate::attachments;
+use crate::audit::{self, AuditOutcome, AuditQueryParams, AuditRecord};
+use crate::auth::{self, Access, Identity};
+use crate::budget::{self, Budget, Spent};
+use crate::cache::{cache_key, CacheStatus, FlushCacheParams, FlushCacheResult};
+use crate::cancellation::{CancelRequestParams, CancelRequestResult};
+use crate::coalesce::{self, Turn};
+use crate::config::bearer_matches;
+use crate::correlation;
+use crate::embeddings::{self, EmbedTextParams, EmbedTextResult, EmbeddingMetadata};
+use crate::error::{Resource, ServerError};
+use crate::error_report::ErrorEvent;
+use crate::evals::{
+    self, AddEvalCaseParams, DeleteEvalCaseParams, DeleteEvalCaseResult, EvalCase, EvalCaseResult,
+    EvalReport, ListEvalCasesParams, ListEvalCasesResult, RunEvalParams,
+};
+use crate::guardrails::Violation;
+use crate::history::HistoryPolicy;
+use crate::i18n::{Locale, Message as Msg, RequestLocale};
+use crate::images::{self, ImageInput};
+use crate::jobs::{validate_callback_url, JobParams, SubmitTextParams};
+use crate::knowledge::document::{self, MAX_METADATA_BYTES};
+use crate::knowledge::{
+    self, validate_knowledge_base, DeleteDocumentParams, Document, DocumentResult,
+    IngestDocumentParams, ListDocumentsParams, ListDocumentsResult,
+};
+use crate::language;
+use crate::limits::FieldError;
+use crate::load_shed::{Priority, PRIORITY_HEADER};
+use crate::memories::{self, DeleteMemoriesParams, DeleteMemoriesResult, ListMemoriesResult};
 use crate::models::*;
+use crate::pipelines::{self, RunPipelineParams, RunPipelineResult, StepResult};
+use crate::plugins::{self, Plugin};
+use crate::progress::{self, Reporter, Stage};
+use crate::prompt_guard;
+use crate::prompt_versions::{self, PromotePromptVersionParams};
+use crate::prompts::{self, GetPromptParams, GetPromptResult, ListPromptsResult, PromptMessage};
+use crate::providers::keys::{RotateKeysParams, RotateKeysResult};
+use crate::providers::{
+    estimate_tokens, resolve_model, Completion, CompletionRequest, LlmProvider,
+};
+use crate::quota;
+use crate::resources::{self, ListResourcesResult, ReadResourceParams, ReadResourceResult};
+use crate::scheduler;
+use crate::scripts::{self, AgentScripts, PreRequest};
+use crate::server_tools;
+use crate::sessions;
+use crate::speech::{
+    SpeechMetadata, SpeechRequest, SynthesizeSpeechParams, SynthesizeSpeechResult,
+};
+use crate::style;
+use crate::tls::PeerIdentity;
+use crate::tools::{self, AgentToolArguments, CallToolParams, CallToolResult, ListToolsResult};
+use crate::voice::{
+    self, ProcessAudioMetadata, ProcessAudioParams, ProcessAudioResult, TranscriptionMetadata,
+};
 use crate::AppState;
-use axum::{extract::State, response::Json};
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use base64::Engine;
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
 use std::sync::Arc;
 
-/// Main JSON-RPC 2.0 request handler.
+/// MCP protocol revisions this server speaks, newest first.
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Methods that require admin access: the admin token, or an operator token
+/// with an admin role.
+pub const ADMIN_METHODS: &[&str] = &[
+    "create_agent",
+    "update_agent",
+    "delete_agent",
+    "promote_prompt_version",
+    "flush_cache",
+    "rotate_provider_keys",
+    "ingest_document",
+    "delete_document",
+    "add_eval_case",
+    "delete_eval_case",
+    "run_eval",
+];
+
+/// Admin methods that change nothing, also open to read-only operators.
+pub const READ_ONLY_METHODS: &[&str] = &[
+    "usage_report",
+    "audit_log",
+    "list_documents",
+    "list_eval_cases",
+];
+
+/// The access `method` requires.
+pub fn required_access(method: &str) -> Access {
+    if ADMIN_METHODS.contains(&method) {
+        Access::Admin
+    } else if READ_ONLY_METHODS.contains(&method) {
+        Access::ReadOnly
+    } else {
+        Access::None
+    }
+}
+
+/// Picks the protocol version to answer an `initialize` request with.
 ///
-/// Routes incoming JSON-RPC requests to the appropriate handler based on the method name.
-/// Validates the JSON-RPC version and returns appropriate error responses for invalid requests.
+/// A version the server supports is echoed back; anything else gets the
+/// newest supported version, and the client decides whether it can continue.
 ///
-/// # Supported Methods
+/// ```
+/// # use mcp_server::handlers::negotiate_protocol_version;
+/// assert_eq!(negotiate_protocol_version(Some("2024-11-05")), "2024-11-05");
+/// assert_eq!(negotiate_protocol_version(Some("1999-01-01")), "2025-06-18");
+/// ```
+pub fn negotiate_protocol_version(requested: Option<&str>) -> &'static str {
+    requested
+        .and_then(|v| SUPPORTED_PROTOCOL_VERSIONS.iter().find(|s| **s == v))
+        .unwrap_or(&SUPPORTED_PROTOCOL_VERSIONS[0])
+}
+
+/// Builds a successful JSON-RPC response.
+pub fn rpc_ok(id: Value, result: impl Serialize) -> JsonRpcResponse<Value> {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        result: Some(serde_json::to_value(result).unwrap()),
+        error: None,
+        id,
+    }
+}
+
+/// Builds a JSON-RPC error response.
+pub fn rpc_error(
+    id: Value,
+    code: i32,
+    message: impl Into<String>,
+    data: Option<Value>,
+) -> JsonRpcResponse<Value> {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        result: None,
+        error: Some(JsonRpcError {
+            code,
+            message: message.into(),
+            data,
+        }),
+        id,
+    }
+}
+
+/// Main JSON-RPC 2.0 HTTP handler.
 ///
-/// - `list_agents` - Lists all available agents
-/// - `process_text` - Processes user text through an agent
+/// Runs the request through [`dispatch`] and returns its response, or
+/// `202 Accepted` with an empty body for notifications. A request accepting
+/// `text/event-stream` with a `_meta.progressToken` is answered with its
+/// [progress](crate::progress) as server-sent events, the response last.
+///
+/// Error messages are localized: a `locale` field in the params takes
+/// precedence over the `?locale=` query parameter and `Accept-Language` header.
+/// Admin methods require `Authorization: Bearer $ADMIN_TOKEN` or an
+/// [OIDC](crate::oidc) operator token with a role granting access. With
+/// [JWT authentication](crate::auth) on, other callers must present a valid
+/// token instead, or are answered `401` with `-32001`. Provider calls are
+/// [scheduled](crate::scheduler) by the `X-Priority` header.
 ///
 /// # Arguments
 ///
 /// * `state` - Shared application state
+/// * `locale` - Locale negotiated from the HTTP request
+/// * `headers` - Request headers, checked for a bearer token and the priority
 /// * `request` - JSON-RPC request with dynamic params
-///
-/// # Returns
-///
-/// A JSON-RPC response with either result or error
 pub async fn handle_jsonrpc(
     State(state): State<Arc<AppState>>,
-    Json(request): Json<JsonRpcRequest<serde_json::Value>>,
-) -> Json<JsonRpcResponse<serde_json::Value>> {
+    RequestLocale(locale): RequestLocale,
+    headers: HeaderMap,
+    peer: Option<Extension<ConnectInfo<PeerIdentity>>>,
+    Json(request): Json<JsonRpcRequest<Value>>,
+) -> Response {
+    let priority = Priority::from_header(
+        headers
+            .get(PRIORITY_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    let peer = peer.and_then(|Extension(ConnectInfo(PeerIdentity(identity)))| identity);
+    let (access, identity) = match authenticate(&state, &headers, peer).await {
+        Ok(caller) => caller,
+        Err(e) => {
+            tracing::warn!("Rejected unauthenticated call to {}: {}", request.method, e);
+            let data = serde_json::json!({ "details": e });
+            let message = Msg::Unauthenticated.text(locale);
+            let response = rpc_error(request.id.unwrap_or_default(), -32001, message, Some(data));
+            return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
+        }
+    };
+    // Requests with a progress token may be answered with their progress first
+    let token = progress::progress_token(request.params.as_ref())
+        .filter(|_| request.id.is_some() && accepts_event_stream(&headers));
+    if let Some(token) = token {
+        let reporter = Reporter::new(token);
+        let (_, notifications) = reporter.subscribe();
+        let correlation_id =
+            correlation::current_correlation_id().unwrap_or_else(correlation::new_correlation_id);
+        let dispatched = async move {
+            // Boxed, so building the stream doesn't copy the whole request around
+            let dispatched = Box::pin(dispatch(&state, request, locale, access));
+            let dispatched = auth::with_identity(identity, dispatched);
+            scheduler::with_priority(priority, dispatched).await
+        };
+        let dispatched = progress::with_reporter(reporter, dispatched);
+        // The body outlives the correlation middleware, so the ID goes along
+        let response = correlation::with_correlation_id(correlation_id, dispatched);
+        return progress::sse(progress_events(Box::pin(response), notifications));
+    }
+    let dispatched = auth::with_identity(identity, dispatch(&state, request, locale, access));
+    match scheduler::with_priority(priority, dispatched).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// Whether the `Accept` header allows `text/event-stream`.
+fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(axum::http::header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("text/event-stream"))
+}
+
+/// The progress notifications sent while `response` runs, then the response.
+fn progress_events(
+    response: std::pin::Pin<
+        Box<dyn std::future::Future<Output = Option<JsonRpcResponse<Value>>> + Send>,
+    >,
+    notifications: tokio::sync::broadcast::Receiver<progress::ProgressParams>,
+) -> impl futures_util::Stream<Item = axum::response::sse::Event> {
+    futures_util::stream::unfold(Some((response, notifications)), |running| async move {
+        let (mut response, mut notifications) = running?;
+        loop {
+            tokio::select! {
+                // Notifications sent before the response go out first
+                biased;
+                received = notifications.recv() => match received {
+                    Ok(params) => {
+                        let event = progress::event(&progress::notification(params));
+                        return Some((event, Some((response, notifications))));
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    // Only once the request is done
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                        let response = response.await?;
+                        return Some((progress::event(&response), None));
+                    }
+                },
+                response = &mut response => {
+                    return Some((progress::event(&response?), None));
+                }
+            }
+        }
+    })
+}
+
+/// `GET /jobs/{job_id}/events` - the [progress](crate::progress) of a job as
+/// server-sent events, ending once it finished, or `404` for an unknown job.
+pub async fn handle_job_events(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Response {
+    match state.jobs.progress(&job_id) {
+        Some(reporter) => {
+            let events = progress::until_final(&reporter)
+                .map(|params| progress::event(&progress::notification(params)));
+            progress::sse(events)
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// `GET /audio/{id}` - audio kept by `synthesize_speech` or `process_audio`
+/// for a URL, or `404` once it expired. Audio still being synthesized is
+/// streamed as it arrives.
+pub async fn handle_audio(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Response {
+    match state.speech.clip(&id) {
+        Some((audio, format)) => {
+            let audio = audio.map(|chunk| chunk.map_err(std::io::Error::other));
+            (
+                [(axum::http::header::CONTENT_TYPE, format.mime_type())],
+                axum::body::Body::from_stream(audio),
+            )
+                .into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Works out who sent an HTTP request and what they may do.
+///
+/// The admin token grants admin access. A token from the OIDC issuer must be
+/// a valid operator token, whose roles decide its access. A client
+/// certificate (`peer`) identifies the caller unless a bearer token is sent;
+/// with JWT auth on, anything else must be a valid client JWT. Fails with the
+/// reason a token was rejected.
+async fn authenticate(
+    state: &AppState,
+    headers: &HeaderMap,
+    peer: Option<Identity>,
+) -> Result<(Access, Option<Identity>), String> {
+    if state
+        .admin_token
+        .as_deref()
+        .is_some_and(|token| bearer_matches(headers, token))
+    {
+        return Ok((Access::Admin, None));
+    }
+    if let (Some(oidc), Some(token)) = (&state.oidc, auth::bearer_token(headers)) {
+        if oidc.claims_issuer(token) {
+            let (identity, access) = oidc.verify(token).await?;
+            return Ok((access, Some(identity)));
+        }
+    }
+    match &state.jwt {
+        Some(jwt) if peer.is_none() || auth::bearer_token(headers).is_some() => {
+            Ok((Access::None, Some(jwt.authenticate(headers)?)))
+        }
+        _ => Ok((Access::None, peer)),
+    }
+}
+
+/// Routes a JSON-RPC request to the handler for its method.
+///
+/// Validates the JSON-RPC version and returns appropriate error responses for
+/// invalid requests. Returns `None` for notifications (requests without an
+/// `id`), which never get a response. Methods fail with `-32001` unless the
+/// transport granted the caller the [access](required_access) they require.
+///
+/// # Supported Methods
+///
+/// - `initialize` - MCP handshake: protocol version and capability negotiation
+/// - `ping` - Liveness check, returns an empty result
+/// - `tools/list` - Lists the MCP tools: `list_agents` and one per agent
+/// - `tools/call` - Runs an MCP tool
+/// - `resources/list` - Lists agent prompts and the config
+/// - `resources/read` - Reads a resource by URI
+/// - `prompts/list` - Lists the prompt templates
+/// - `prompts/get` - Fills a prompt template with arguments
+/// - `list_agents` - Lists all available agents
+/// - `find_agents` - The agents with given capabilities, ranked by how well
+///   they match a free-text query
+/// - `process_text` - Processes user text through an agent
+/// - `run_pipeline` - Runs user text through a chain of agents
+/// - `submit_text` - Queues `process_text` params as a background job
+/// - `get_job_status`, `get_job_result` - Poll a job submitted with `submit_text`
+/// - `cancel_request` - Aborts a running `process_text`, `run_pipeline`,
+///   `process_audio` or `tools/call` request by its `id`; the aborted request fails with `-32800`
+/// - `embed_text` - Embedding vectors for texts, see [`crate::embeddings`]
+/// - `synthesize_speech` - Audio of a text read aloud, or a temporary URL
+///   serving it, see [`crate::speech`]
+/// - `process_audio` - A recording transcribed, answered by an agent and the
+///   reply read aloud, see [`crate::voice`]
+/// - `list_memories`, `delete_memories` - The facts [remembered](crate::memories)
+///   about the caller, and forgetting them
+/// - `create_agent`, `update_agent`, `delete_agent` - Admin only, change the agents at runtime
+/// - `promote_prompt_version` - Admin only, makes a [version](crate::prompt_versions)
+///   of an agent's system prompt live and ends its experiment
+/// - `flush_cache` - Admin only, drops cached replies
+/// - `rotate_provider_keys` - Admin only, replaces the [API keys](crate::providers::keys)
+///   of a provider
+/// - `ingest_document`, `delete_document` - Admin only, change the documents
+///   of a [knowledge base](crate::knowledge), given as text or a URL
+/// - `usage_report` - Admin or read-only operator, requests, errors, tokens,
+///   cost and latency per agent and time bucket
+/// - `audit_log` - Admin or read-only operator, recorded calls when `AUDIT_DB`
+///   is set
+/// - `list_documents` - Admin or read-only operator, the documents of a
+///   knowledge base
+/// - `add_eval_case`, `delete_eval_case` - Admin only, change the
+///   [evaluation cases](crate::evals) of an agent
+/// - `list_eval_cases` - Admin or read-only operator, an agent's evaluation cases
+/// - `run_eval` - Admin only, runs an agent's evaluation cases and scores the replies
+///
+/// With an [audit log](crate::audit), every call is recorded once answered.
+///
+/// # Supported Notifications
+///
+/// - `notifications/initialized` - Sent by MCP clients after `initialize`
+/// - `notifications/cancelled` - Same as `cancel_request`, without a response
+pub async fn dispatch(
+    state: &Arc<AppState>,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+    access: Access,
+) -> Option<JsonRpcResponse<Value>> {
     tracing::info!("Received JSON-RPC request: method={}", request.method);
 
+    let locale = request
+        .params
+        .as_ref()
+        .and_then(|p| p.get("locale"))
+        .and_then(|l| l.as_str())
+        .and_then(Locale::parse)
+        .unwrap_or(locale);
+
+    let Some(id) = request.id.clone() else {
+        handle_notification(state, &request);
+        return None;
+    };
+
+    let started = (chrono::Utc::now(), std::time::Instant::now());
+    let method = request.method.clone();
+    let agent_id = audited_agent(state, &request);
+    let params = state.access_log.params(request.params.as_ref());
+    let response = route(state, id, request, locale, access).await;
+    report_handler_error(state, &method, agent_id.as_deref(), &response);
+    let latency = started.1.elapsed();
+    let failed = response.error.is_some()
+        || response
+            .result
+            .as_ref()
+            .is_some_and(|result| result["isError"] == true);
+    let client_id = auth::current_identity().map(|identity| quota::quota_key(&identity));
+    let tokens = response
+        .result
+        .as_ref()
+        .and_then(|result| reported(result, "tokens_used")?.as_u64());
+    let error_code = response.error.as_ref().map(|error| error.code);
+    state.access_log.record(AccessEntry {
+        method: &method,
+        agent_id: agent_id.as_deref(),
+        client_id: client_id.as_deref(),
+        failed,
+        error_code,
+        latency,
+        tokens,
+        params,
+    });
+    let Some(audit) = &state.audit else {
+        return Some(response);
+    };
+    audit.record(AuditRecord {
+        id: 0,
+        timestamp: audit::format_timestamp(started.0),
+        client_id,
+        method,
+        agent_id,
+        tokens,
+        outcome: if failed {
+            AuditOutcome::Error
+        } else {
+            AuditOutcome::Ok
+        },
+        error_code,
+        duration_ms: latency.as_millis() as u64,
+        correlation_id: correlation::current_correlation_id(),
+        prompt_version: response
+            .result
+            .as_ref()
+            .and_then(|result| Some(reported(result, "prompt_version")?.as_str()?.to_string())),
+    });
+    Some(response)
+}
+
+/// Reports a call that failed inside the server with a `storage` or
+/// `internal` error; provider failures are reported by [`provider_failure`].
+fn report_handler_error(
+    state: &AppState,
+    method: &str,
+    agent_id: Option<&str>,
+    response: &JsonRpcResponse<Value>,
+) {
+    let Some(data) = response
+        .error
+        .as_ref()
+        .and_then(|error| error.data.as_ref())
+    else {
+        return;
+    };
+    let kind = data["kind"].as_str().unwrap_or_default();
+    if !matches!(kind, "storage" | "internal") || data.get("provider").is_some() {
+        return;
+    }
+    let request_id = match &response.id {
+        Value::String(id) => id.clone(),
+        id => id.to_string(),
+    };
+    let message = data["details"].as_str().unwrap_or(kind);
+    state.reporter.report(
+        ErrorEvent::new("handler_error", message)
+            .with_request_id(request_id)
+            .with_details(serde_json::json!({
+                "method": method,
+                "kind": kind,
+                "agent_id": agent_id,
+                "caller": auth::current_identity(),
+                "correlation_id": correlation::current_correlation_id(),
+            })),
+    );
+}
+
+/// The agent a call names: the `agent_id` param, or the agent run by
+/// `tools/call`.
+fn audited_agent(state: &AppState, request: &JsonRpcRequest<Value>) -> Option<String> {
+    let params = request.params.as_ref()?;
+    let name = match request.method.as_str() {
+        "tools/call" => params.get("name")?.as_str()?,
+        "create_agent" => params.get("id")?.as_str()?,
+        _ => params.get("agent_id")?.as_str()?,
+    };
+    let is_agent = request.method != "tools/call" || visible_agents(state).get(name).is_some();
+    is_agent.then(|| name.to_string())
+}
+
+/// A field of a result's metadata, given directly, as a tool's structured
+/// content or as the agent stage of `process_audio`.
+fn reported<'a>(result: &'a Value, field: &str) -> Option<&'a Value> {
+    [
+        "/metadata",
+        "/structuredContent/metadata",
+        "/metadata/agent",
+    ]
+    .iter()
+    .find_map(|metadata| result.pointer(metadata)?.get(field))
+}
+
+/// Answers a request with an `id`.
+async fn route(
+    state: &Arc<AppState>,
+    id: Value,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+    access: Access,
+) -> JsonRpcResponse<Value> {
     // Validate JSON-RPC version
     if request.jsonrpc != "2.0" {
-        return Json(JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: None,
-            error: Some(JsonRpcError {
-                code: -32600,
-                message: "Invalid Request: jsonrpc must be '2.0'".to_string(),
-                data: None,
-            }),
-            id: request.id,
-        });
+        let message = Msg::InvalidVersion.text(locale);
+        return rpc_error(id, -32600, message, None);
+    }
+
+    if access < required_access(&request.method) {
+        tracing::warn!("Rejected unauthorized call to {}", request.method);
+        return rpc_error(id, -32001, Msg::Unauthorized.text(locale), None);
     }
 
     // Route to the appropriate handler
     match request.method.as_str() {
-        "list_agents" => handle_list_agents(request).await,
-        "process_text" => handle_process_text(State(state), request).await,
-        _ => Json(JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: None,
-            error: Some(JsonRpcError {
-                code: -32601,
-                message: format!("Method not found: {}", request.method),
-                data: None,
-            }),
-            id: request.id,
-        }),
+        "initialize" => handle_initialize(request, locale),
+        "ping" => rpc_ok(id, serde_json::json!({})),
+        "tools/list" => rpc_ok(
+            id,
+            ListToolsResult {
+                tools: tools::list_tools(&visible_agents(state)),
+            },
+        ),
+        "tools/call" => {
+            cancellable(state, id, locale, handle_call_tool(state, request, locale)).await
+        }
+        "resources/list" => rpc_ok(
+            id,
+            ListResourcesResult {
+                resources: resources::list_resources(state),
+            },
+        ),
+        "resources/read" => handle_read_resource(state, request, locale).await,
+        "prompts/list" => rpc_ok(
+            id,
+            ListPromptsResult {
+                prompts: prompts::PROMPTS,
+            },
+        ),
+        "prompts/get" => handle_get_prompt(state, request, locale),
+        "list_agents" => handle_list_agents(state, request).await,
+        "find_agents" => handle_find_agents(state, request, locale),
+        "process_text" => {
+            cancellable(
+                state,
+                id,
+                locale,
+                handle_process_text(state, request, locale),
+            )
+            .await
+        }
+        "run_pipeline" => {
+            cancellable(
+                state,
+                id,
+                locale,
+                handle_run_pipeline(state, request, locale),
+            )
+            .await
+        }
+        "submit_text" => handle_submit_text(state, request, locale),
+        "get_job_status" => handle_get_job_status(state, request, locale),
+        "get_job_result" => handle_get_job_result(state, request, locale),
+        "cancel_request" => handle_cancel_request(state, request, locale),
+        "create_agent" => handle_create_agent(state, request, locale),
+        "update_agent" => handle_update_agent(state, request, locale),
+        "delete_agent" => handle_delete_agent(state, request, locale),
+        "promote_prompt_version" => handle_promote_prompt_version(state, request, locale),
+        "flush_cache" => handle_flush_cache(state, request, locale),
+        "rotate_provider_keys" => handle_rotate_provider_keys(state, request, locale),
+        "usage_report" => handle_usage_report(state, request, locale),
+        "audit_log" => handle_audit_log(state, request, locale),
+        "embed_text" => handle_embed_text(state, request, locale).await,
+        "ingest_document" => handle_ingest_document(state, request, locale).await,
+        "delete_document" => handle_delete_document(state, request, locale).await,
+        "list_documents" => handle_list_documents(state, request, locale).await,
+        "list_memories" => handle_list_memories(state, request, locale),
+        "synthesize_speech" => handle_synthesize_speech(state, request, locale).await,
+        "process_audio" => {
+            cancellable(
+                state,
+                id,
+                locale,
+                handle_process_audio(state, request, locale),
+            )
+            .await
+        }
+        "delete_memories" => handle_delete_memories(state, request, locale),
+        "add_eval_case" => handle_add_eval_case(state, request, locale),
+        "list_eval_cases" => handle_list_eval_cases(state, request, locale),
+        "delete_eval_case" => handle_delete_eval_case(state, request, locale),
+        "run_eval" => cancellable(state, id, locale, handle_run_eval(state, request, locale)).await,
+        _ => {
+            let message = Msg::MethodNotFound.format(locale, &request.method);
+            rpc_error(id, -32601, message, None)
+        }
+    }
+}
+
+/// Runs a request's handler so that `cancel_request` can abort it, in which
+/// case it fails with `-32800`.
+async fn cancellable(
+    state: &AppState,
+    id: Value,
+    locale: Locale,
+    handler: impl std::future::Future<Output = JsonRpcResponse<Value>>,
+) -> JsonRpcResponse<Value> {
+    let caller = auth::current_identity();
+    match state.in_flight.run(caller.as_ref(), &id, handler).await {
+        Ok(response) => response,
+        Err(_) => rpc_error(id, -32800, Msg::RequestCancelled.text(locale), None),
+    }
+}
+
+/// Handles the `cancel_request` method.
+///
+/// Answers whether a running request was aborted; an unknown or finished
+/// `id` is not an error, since the request may just have completed.
+fn handle_cancel_request(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: CancelRequestParams =
+        match serde_json::from_value(request.params.unwrap_or_default()) {
+            Ok(params) => params,
+            Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+        };
+    let caller = auth::current_identity();
+    let cancelled = state.in_flight.cancel(caller.as_ref(), &params.request_id);
+    tracing::info!(
+        "Cancel request {}: {}{}",
+        params.request_id,
+        if cancelled { "aborted" } else { "not running" },
+        params
+            .reason
+            .map(|reason| format!(" ({})", reason))
+            .unwrap_or_default()
+    );
+    rpc_ok(id, CancelRequestResult { cancelled })
+}
+
+/// Handles JSON-RPC notifications; unknown ones are ignored as the spec requires.
+fn handle_notification(state: &AppState, request: &JsonRpcRequest<Value>) {
+    match request.method.as_str() {
+        "notifications/initialized" | "initialized" => {
+            tracing::info!("MCP client finished initialization");
+        }
+        "notifications/cancelled" => {
+            let params = request
+                .params
+                .clone()
+                .and_then(|p| serde_json::from_value::<CancelRequestParams>(p).ok());
+            if let Some(params) = params {
+                let caller = auth::current_identity();
+                if state.in_flight.cancel(caller.as_ref(), &params.request_id) {
+                    tracing::info!("Cancelled request {}", params.request_id);
+                }
+            }
+        }
+        method => tracing::debug!("Ignoring notification: {}", method),
+    }
+}
+
+/// Handles the MCP `initialize` request.
+///
+/// Negotiates the protocol version (see [`negotiate_protocol_version`]) and
+/// returns the server's capabilities and identity.
+pub fn handle_initialize(request: JsonRpcRequest<Value>, locale: Locale) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params = request.params.unwrap_or_else(|| serde_json::json!({}));
+    let params: InitializeParams = match serde_json::from_value(params) {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+
+    let protocol_version = negotiate_protocol_version(params.protocol_version.as_deref());
+    if let Some(client) = &params.client_info {
+        tracing::info!(
+            "MCP client {} {} connected (protocol {})",
+            client.name,
+            client.version,
+            protocol_version
+        );
     }
+
+    rpc_ok(
+        id,
+        InitializeResult {
+            protocol_version: protocol_version.to_string(),
+            capabilities: ServerCapabilities {
+                tools: Some(ListCapability::default()),
+                resources: Some(ListCapability::default()),
+                prompts: Some(ListCapability::default()),
+            },
+            server_info: Implementation {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            instructions: Some(
+                "Each tool is an AI agent: pass the user's message as user_text. \
+                 Call list_agents for details about the agents."
+                    .to_string(),
+            ),
+        },
+    )
+}
+
+/// Handles the MCP `resources/read` method.
+///
+/// Unknown URIs get the MCP "resource not found" error, `-32002`.
+pub async fn handle_read_resource(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: ReadResourceParams =
+        match serde_json::from_value(request.params.unwrap_or_default()) {
+            Ok(params) => params,
+            Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+        };
+
+    match resources::read_resource(state, &params.uri).await {
+        Some(contents) => rpc_ok(
+            id,
+            ReadResourceResult {
+                contents: vec![contents],
+            },
+        ),
+        None => rpc_error(
+            id,
+            -32002,
+            Msg::ResourceNotFound.format(locale, &params.uri),
+            Some(serde_json::json!({ "uri": params.uri })),
+        ),
+    }
+}
+
+/// Handles the MCP `prompts/get` method.
+///
+/// Unknown prompts and missing required arguments are `-32602` errors.
+pub fn handle_get_prompt(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: GetPromptParams = match serde_json::from_value(request.params.unwrap_or_default()) {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+
+    let Some(prompt) = prompts::find_prompt(&params.name) else {
+        let error = ServerError::NotFound {
+            resource: Resource::Prompt,
+            id: params.name,
+        };
+        return server_error(id, error, locale);
+    };
+    let text = match prompt.render(&params.arguments) {
+        Ok(text) => text,
+        Err(missing) => {
+            let message = Msg::MissingPromptArgument.format(locale, missing);
+            return rpc_error(id, -32602, message, None);
+        }
+    };
+
+    let description = match visible_agents(state).get(prompt.agent_id) {
+        Some(agent) => format!("{} (best sent to {})", prompt.description, agent.id),
+        None => prompt.description.to_string(),
+    };
+    rpc_ok(
+        id,
+        GetPromptResult {
+            description,
+            messages: vec![PromptMessage {
+                role: "user",
+                content: tools::Content::Text { text },
+            }],
+        },
+    )
 }
 
 /// Handles the `list_agents` JSON-RPC method.
 ///
-/// Returns a list of all available AI agents with their metadata.
+/// Returns a list of all AI agents available to the caller's tenant, with
+/// their metadata.
 ///
 /// # Arguments
 ///
+/// * `state` - Shared application state holding the agents
 /// * `request` - The JSON-RPC request
 ///
 /// # Returns
 ///
 /// A JSON-RPC response containing the list of agents
 pub async fn handle_list_agents(
-    request: JsonRpcRequest<serde_json::Value>,
-) -> Json<JsonRpcResponse<serde_json::Value>> {
-    let agents = get_agents();
-    let result = ListAgentsResult { agents };
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+) -> JsonRpcResponse<Value> {
+    let result = ListAgentsResult {
+        agents: visible_agents(state).list().to_vec(),
+    };
 
-    Json(JsonRpcResponse {
-        jsonrpc: "2.0".to_string(),
-        result: Some(serde_json::to_value(result).unwrap()),
-        error: None,
-        id: request.id,
-    })
+    rpc_ok(request.id.unwrap_or_default(), result)
+}
+
+/// Handles the `find_agents` JSON-RPC method.
+///
+/// Returns the agents available to the caller's tenant that have every
+/// requested capability and match the `query`, best matches first, so
+/// clients with many agents don't have to fetch and search them all. See
+/// [`AgentRegistry::find`](crate::agents::AgentRegistry::find) for the
+/// matching.
+pub fn handle_find_agents(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: FindAgentsParams = match request.params.map(serde_json::from_value) {
+        Some(Ok(params)) => params,
+        None => FindAgentsParams::default(),
+        Some(Err(e)) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    let mut agents = visible_agents(state).find(&params.capabilities, params.query.as_deref());
+    if let Some(limit) = params.limit {
+        agents.truncate(limit);
+    }
+    rpc_ok(id, ListAgentsResult { agents })
 }
 
 /// Handles the `process_text` JSON-RPC method.
 ///
-/// Processes user text through a specified agent using Google's Gemini API.
+/// Processes user text through a specified agent using the default AI provider.
 /// This includes:
 /// 1. Validating the agent ID
-/// 2. Building the Gemini API request with system instructions and conversation history
-/// 3. Calling the Gemini API
+/// 2. Building the provider request with system instructions and conversation history
+/// 3. Calling the provider
 /// 4. Parsing the response and extracting the agent's reply
 /// 5. Returning metadata about tokens used and processing time
 ///
 /// # Arguments
 ///
-/// * `state` - Shared application state containing the HTTP client and API key
+/// * `state` - Shared application state containing the AI providers
 /// * `request` - JSON-RPC request containing agent_id, user_text, and optional conversation history
+/// * `locale` - Locale for error messages and the fallback reply
 ///
 /// # Returns
 ///
@@ -112,110 +895,2734 @@ pub async fn handle_list_agents(
 /// # Errors
 ///
 /// Returns JSON-RPC errors for:
-/// - Invalid parameters
+/// - Invalid parameters, including [images](crate::images) the default
+///   provider does not accept and [attachments](crate::attachments) that
+///   can't be read
 /// - Unknown agent ID
-/// - Gemini API failures
+/// - AI provider failures
 /// - Response parsing errors
 pub async fn handle_process_text(
-    State(state): State<Arc<AppState>>,
-    request: JsonRpcRequest<serde_json::Value>,
-) -> Json<JsonRpcResponse<serde_json::Value>> {
-    // Parse the parameters
-    let params: ProcessTextParams = match request.params {
-        Some(ref p) => match serde_json::from_value(p.clone()) {
-            Ok(params) => params,
-            Err(e) => {
-                return Json(JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: format!("Invalid params: {}", e),
-                        data: None,
-                    }),
-                    id: request.id,
-                });
-            }
-        },
-        None => {
-            return Json(JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Invalid params: agent_id and user_text are required".to_string(),
-                    data: None,
-                }),
-                id: request.id,
-            });
-        }
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let (params, agent) = match process_text_params(state, request.params, &id, locale) {
+        Ok(parsed) => parsed,
+        Err(response) => return *response,
     };
 
-    // Find the requested agent
-    let agent = match find_agent_by_id(&params.agent_id) {
-        Some(a) => a,
+    match run_agent(state, &agent, params, true, &id, locale).await {
+        Ok(result) => rpc_ok(id, result),
+        Err(error) => rpc_failure(id, error),
+    }
+}
+
+/// Parses `process_text` params and finds their agent, or builds the error
+/// response for `id`.
+fn process_text_params(
+    state: &AppState,
+    params: Option<Value>,
+    id: &Value,
+    locale: Locale,
+) -> Result<(ProcessTextParams, Arc<Agent>), Box<JsonRpcResponse<Value>>> {
+    let params: ProcessTextParams = required_params(params, id, locale)?;
+    state
+        .limits
+        .check(&params)
+        .map_err(|errors| Box::new(limits_error(id.clone(), errors, locale)))?;
+    let agent = find_agent(state, &params.agent_id, id, locale)?;
+    Ok((params, agent))
+}
+
+/// Parses required params, or builds the error response for `id`.
+fn required_params<T: serde::de::DeserializeOwned>(
+    params: Option<Value>,
+    id: &Value,
+    locale: Locale,
+) -> Result<T, Box<JsonRpcResponse<Value>>> {
+    match params {
+        Some(p) => serde_json::from_value(p).map_err(|e| {
+            let error = ServerError::InvalidParams(e.to_string());
+            Box::new(server_error(id.clone(), error, locale))
+        }),
         None => {
-            return Json(JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: format!("Agent not found: {}", params.agent_id),
-                    data: None,
-                }),
-                id: request.id,
-            });
+            let message = Msg::MissingParams.text(locale);
+            Err(Box::new(rpc_error(id.clone(), -32602, message, None)))
+        }
+    }
+}
+
+/// The `-32602` response for params over the [request limits](crate::limits),
+/// naming each offending field in `data.fields`.
+fn limits_error(id: Value, errors: Vec<FieldError>, locale: Locale) -> JsonRpcResponse<Value> {
+    let details = errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ");
+    let mut error = ServerError::InvalidParams(details).to_rpc_error(locale);
+    if let Some(data) = &mut error.data {
+        data["fields"] = serde_json::json!(errors);
+    }
+    rpc_failure(id, error)
+}
+
+/// The agents available to the tenant of the request being handled.
+pub(crate) fn visible_agents(state: &AppState) -> Arc<AgentRegistry> {
+    state.agents.visible_to(auth::current_tenant().as_deref())
+}
+
+/// Finds an agent available to the caller, or builds the error response for
+/// `id`.
+fn find_agent(
+    state: &AppState,
+    agent_id: &str,
+    id: &Value,
+    locale: Locale,
+) -> Result<Arc<Agent>, Box<JsonRpcResponse<Value>>> {
+    visible_agents(state).get(agent_id).ok_or_else(|| {
+        let error = ServerError::NotFound {
+            resource: Resource::Agent,
+            id: agent_id.to_string(),
+        };
+        Box::new(server_error(id.clone(), error, locale))
+    })
+}
+
+/// An error response carrying `error`.
+fn rpc_failure(id: Value, error: JsonRpcError) -> JsonRpcResponse<Value> {
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        result: None,
+        error: Some(error),
+        id,
+    }
+}
+
+/// The error response for `error`, with its message in `locale`.
+fn server_error(id: Value, error: ServerError, locale: Locale) -> JsonRpcResponse<Value> {
+    rpc_failure(id, error.to_rpc_error(locale))
+}
+
+/// Handles the `submit_text` method.
+///
+/// Checks the params like `process_text`, then queues the agent run and
+/// answers with the job's status right away. A full queue is a `-32005`
+/// error.
+///
+/// With a `callback_url`, the finished job is also POSTed there, signed with
+/// `JOB_CALLBACK_SECRET`; without the secret, `callback_url` is rejected.
+/// Jobs are batch work: their provider calls are scheduled as `low`.
+pub fn handle_submit_text(
+    state: &Arc<AppState>,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let SubmitTextParams {
+        process: params,
+        callback_url,
+    } = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    if let Err(errors) = state.limits.check(&params) {
+        return limits_error(id, errors, locale);
+    }
+    if let Some(url) = &callback_url {
+        let checked = if state.jobs.accepts_callbacks() {
+            validate_callback_url(url)
+        } else {
+            Err("callback_url needs JOB_CALLBACK_SECRET to be set".to_string())
+        };
+        if let Err(e) = checked {
+            return server_error(id, ServerError::InvalidParams(e.to_string()), locale);
         }
+    }
+    let agent = match find_agent(state, &params.agent_id, &id, locale) {
+        Ok(agent) => agent,
+        Err(response) => return *response,
     };
+    let Some(status) = state.jobs.submit(&agent.id, callback_url, params.no_store) else {
+        return rpc_error(id, -32005, Msg::JobQueueFull.text(locale), None);
+    };
+    tracing::info!("Queued job {} for agent {}", status.job_id, agent.id);
 
-    // Start timing
-    let start_time = std::time::Instant::now();
+    let state = state.clone();
+    let job_id = status.job_id.clone();
+    let identity = auth::current_identity();
+    let correlation_id =
+        correlation::current_correlation_id().unwrap_or_else(correlation::new_correlation_id);
+    let job = async move {
+        // Provider failures are reported under the job's ID
+        let request_id = Value::String(job_id.clone());
+        let work = async {
+            let result = run_agent(&state, &agent, params, true, &request_id, locale).await?;
+            Ok(serde_json::to_value(result).unwrap())
+        };
+        scheduler::with_priority(Priority::Low, state.jobs.run(&job_id, work)).await;
+        tracing::info!("Job {} finished", job_id);
+    };
+    // The job runs on behalf of whoever submitted it, and logs under its ID
+    let job = correlation::with_correlation_id(correlation_id, job);
+    tokio::spawn(auth::with_identity(identity, job));
+    rpc_ok(id, status)
+}
 
-    // Process the text with Gemini or Groq
-    let (reply_text, tokens_used) = match process_with_gemini(
-        &state.http_client,
-        &state.gemini_api_key,
-        &agent,
-        params.user_text,
-        params.conversation_history,
-        state.use_groq,
-    )
-    .await
+/// Handles the `get_job_status` method.
+pub fn handle_get_job_status(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: JobParams = match serde_json::from_value(request.params.unwrap_or_default()) {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    match state.jobs.status(&params.job_id) {
+        Some(status) => rpc_ok(id, status),
+        None => server_error(id, job_not_found(params.job_id), locale),
+    }
+}
+
+/// Handles the `get_job_result` method.
+///
+/// A finished job answers with its `process_text` result or error, which can
+/// be fetched again until the job expires. An unfinished job is a `-32004`
+/// error whose `data` carries its status.
+pub fn handle_get_job_result(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: JobParams = match serde_json::from_value(request.params.unwrap_or_default()) {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    match state.jobs.outcome(&params.job_id) {
+        Some((_, Some(Ok(result)))) => rpc_ok(id, result),
+        Some((_, Some(Err(error)))) => rpc_failure(id, error),
+        Some((status, None)) => {
+            let message = Msg::JobPending.format(locale, &params.job_id);
+            let data = serde_json::json!({ "status": status.status });
+            rpc_error(id, -32004, message, Some(data))
+        }
+        None => server_error(id, job_not_found(params.job_id), locale),
+    }
+}
+
+fn job_not_found(job_id: String) -> ServerError {
+    ServerError::NotFound {
+        resource: Resource::Job,
+        id: job_id,
+    }
+}
+
+/// Handles the `run_pipeline` method.
+///
+/// Runs a built-in pipeline named by `pipeline_id`, or the inline `steps`,
+/// feeding each step's reply to the next. A failing step aborts the run; its
+/// error's `data` names the step and agent.
+pub async fn handle_run_pipeline(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: RunPipelineParams = match serde_json::from_value(request.params.unwrap_or_default())
     {
-        Ok(result) => result,
-        Err(err_msg) => {
-            tracing::error!("AI processing error: {}", err_msg);
-            return Json(JsonRpcResponse {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    if let Err(errors) = state.limits.check_user_text(&params.user_text) {
+        return limits_error(id, errors, locale);
+    }
+
+    let (pipeline_id, steps) = match (params.pipeline_id, params.steps) {
+        (Some(pipeline_id), None) => match pipelines::find_builtin(&pipeline_id) {
+            Some(pipeline) => (Some(pipeline_id), pipeline.steps),
+            None => {
+                let error = ServerError::NotFound {
+                    resource: Resource::Pipeline,
+                    id: pipeline_id,
+                };
+                return server_error(id, error, locale);
+            }
+        },
+        (None, Some(steps)) => (None, steps),
+        _ => {
+            let details = "pass exactly one of pipeline_id or steps";
+            return server_error(id, ServerError::InvalidParams(details.to_string()), locale);
+        }
+    };
+    if let Err(e) = pipelines::validate_steps(&steps)
+        .and_then(|_| ProcessTextParams::validate_timeout(params.timeout_ms))
+    {
+        return server_error(id, ServerError::InvalidParams(e.to_string()), locale);
+    }
+
+    let start_time = std::time::Instant::now();
+    let timeout_ms = params.timeout_ms;
+    let no_store = params.no_store;
+    let mut input = params.user_text;
+    let mut tokens_used = Some(0);
+    let mut results = Vec::with_capacity(steps.len());
+    let agents = visible_agents(state);
+    let total = steps.len() as u64;
+    for (i, step) in steps.into_iter().enumerate() {
+        progress::step(
+            i as u64 + 1,
+            total,
+            format!("Step {} of {}: {}", i + 1, total, step.agent_id),
+        );
+        let step_error = |error: JsonRpcError| {
+            let mut data = error.data.unwrap_or_else(|| serde_json::json!({}));
+            data["step"] = i.into();
+            data["agent_id"] = step.agent_id.clone().into();
+            JsonRpcResponse {
                 jsonrpc: "2.0".to_string(),
                 result: None,
                 error: Some(JsonRpcError {
-                    code: -32603,
-                    message: "Internal error: Gemini API processing failed".to_string(),
-                    data: Some(serde_json::json!({ "details": err_msg })),
+                    data: Some(data),
+                    ..error
                 }),
-                id: request.id,
-            });
-        }
+                id: id.clone(),
+            }
+        };
+        let Some(agent) = agents.get(&step.agent_id) else {
+            let error = ServerError::NotFound {
+                resource: Resource::Agent,
+                id: step.agent_id.clone(),
+            };
+            return step_error(error.to_rpc_error(locale));
+        };
+        let params = ProcessTextParams {
+            agent_id: agent.id.clone(),
+            user_text: step.prompt(&input),
+            conversation_history: None,
+            session_id: None,
+            model: step.model.clone(),
+            generation: step.generation.clone(),
+            budget: Default::default(),
+            tools: None,
+            timeout_ms,
+            images: None,
+            attachments: None,
+            reply_language: None,
+            style: None,
+            no_store,
+        };
+        let result = match run_agent(state, &agent, params, true, &id, locale).await {
+            Ok(result) => result,
+            Err(error) => return step_error(error),
+        };
+        tracing::debug!("Pipeline step {} ({}) done", i, agent.id);
+        tokens_used = tokens_used
+            .zip(result.metadata.tokens_used)
+            .map(|(a, b)| a + b);
+        input = result.reply_text.clone();
+        results.push(StepResult {
+            agent_id: result.agent_id,
+            reply_text: result.reply_text,
+            metadata: result.metadata,
+        });
+    }
+
+    rpc_ok(
+        id,
+        RunPipelineResult {
+            pipeline_id,
+            reply_text: input,
+            steps: results,
+            metadata: pipelines::PipelineMetadata {
+                tokens_used,
+                processing_time_ms: start_time.elapsed().as_millis() as u64,
+            },
+        },
+    )
+}
+
+/// Handles the admin `create_agent` method.
+///
+/// The params are a complete agent. Invalid IDs and IDs already in use are
+/// `-32602` errors.
+pub fn handle_create_agent(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let agent: Agent = match serde_json::from_value(request.params.unwrap_or_default()) {
+        Ok(agent) => agent,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
     };
+    if let Err(e) = validate_agent_id(&agent.id)
+        .and_then(|_| agent.validate_generation())
+        .and_then(|_| {
+            prompt_versions::validate(agent.prompt_version.as_deref(), &agent.prompt_versions)
+        })
+        .and_then(|_| ProviderFallback::validate_all(&agent.fallbacks))
+        .and_then(|_| {
+            agent
+                .knowledge_base
+                .as_deref()
+                .map_or(Ok(()), validate_knowledge_base)
+        })
+        .and_then(|_| agent.scripts.validate())
+    {
+        return server_error(id, ServerError::InvalidParams(e.to_string()), locale);
+    }
 
-    let processing_time = start_time.elapsed().as_millis() as u64;
+    let agent_id = agent.id.clone();
+    match state.agents.create(agent) {
+        Ok(Some(agent)) => {
+            tracing::info!("Agent {} created", agent.id);
+            rpc_ok(id, AgentResult { agent })
+        }
+        Ok(None) => rpc_error(id, -32602, Msg::AgentExists.format(locale, agent_id), None),
+        Err(e) => storage_error(id, e, locale),
+    }
+}
 
-    // Build the result
-    let result = ProcessTextResult {
-        agent_id: params.agent_id,
-        reply_text,
-        metadata: ProcessingMetadata {
-            model: agent.model.clone(),
-            tokens_used,
-            processing_time_ms: processing_time,
-            confidence: 0.95,
-        },
+/// Handles the admin `update_agent` method.
+///
+/// Only the fields present in the params are changed.
+pub fn handle_update_agent(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: UpdateAgentParams = match serde_json::from_value(request.params.unwrap_or_default())
+    {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
     };
+    let defaults = GenerationParams {
+        temperature: params.temperature.flatten(),
+        max_tokens: params.max_tokens.flatten(),
+        top_p: params.top_p.flatten(),
+        stop: params.stop.clone(),
+        ..Default::default()
+    };
+    if let Err(e) = defaults
+        .validate()
+        .and_then(|_| {
+            ProviderFallback::validate_all(params.fallbacks.as_deref().unwrap_or_default())
+        })
+        .and_then(|_| match params.knowledge_base.as_deref() {
+            None | Some("") => Ok(()),
+            Some(knowledge_base) => validate_knowledge_base(knowledge_base),
+        })
+        .and_then(|_| {
+            params
+                .scripts
+                .as_ref()
+                .map_or(Ok(()), AgentScripts::validate)
+        })
+        .and_then(|_| {
+            // Versions are checked against the agent's live one, and the reverse
+            if params.prompt_version.is_none() && params.prompt_versions.is_none() {
+                return Ok(());
+            }
+            let current = state.agents.get(&params.agent_id);
+            let live = params
+                .prompt_version
+                .as_deref()
+                .or_else(|| current.as_ref()?.prompt_version.as_deref());
+            let versions = match &params.prompt_versions {
+                Some(versions) => versions.as_slice(),
+                None => current
+                    .as_ref()
+                    .map_or(&[][..], |agent| agent.prompt_versions.as_slice()),
+            };
+            prompt_versions::validate(live, versions)
+        })
+    {
+        return server_error(id, ServerError::InvalidParams(e.to_string()), locale);
+    }
 
-    Json(JsonRpcResponse {
-        jsonrpc: "2.0".to_string(),
-        result: Some(serde_json::to_value(result).unwrap()),
-        error: None,
-        id: request.id,
-    })
+    let agent_id = params.agent_id.clone();
+    let updated = state.agents.update(&agent_id, |agent| {
+        let UpdateAgentParams {
+            name,
+            description,
+            capabilities,
+            model,
+            system_prompt,
+            prompt_version,
+            prompt_versions,
+            stop,
+            temperature,
+            max_tokens,
+            top_p,
+            fallbacks,
+            delimit_user_input,
+            allow_system_messages,
+            knowledge_base,
+            allowed_models,
+            scripts,
+            ..
+        } = params;
+        if let Some(name) = name {
+            agent.name = name;
+        }
+        if let Some(description) = description {
+            agent.description = description;
+        }
+        if let Some(capabilities) = capabilities {
+            agent.capabilities = capabilities;
+        }
+        if let Some(model) = model {
+            agent.model = model;
+        }
+        if let Some(system_prompt) = system_prompt {
+            agent.system_prompt = system_prompt;
+        }
+        if let Some(prompt_version) = prompt_version {
+            agent.prompt_version = Some(prompt_version);
+        }
+        if let Some(prompt_versions) = prompt_versions {
+            agent.prompt_versions = prompt_versions;
+        }
+        if let Some(stop) = stop {
+            agent.stop = stop;
+        }
+        if let Some(temperature) = temperature {
+            agent.temperature = temperature;
+        }
+        if let Some(max_tokens) = max_tokens {
+            agent.max_tokens = max_tokens;
+        }
+        if let Some(top_p) = top_p {
+            agent.top_p = top_p;
+        }
+        if let Some(fallbacks) = fallbacks {
+            agent.fallbacks = fallbacks;
+        }
+        if let Some(delimit_user_input) = delimit_user_input {
+            agent.delimit_user_input = delimit_user_input;
+        }
+        if let Some(allow_system_messages) = allow_system_messages {
+            agent.allow_system_messages = allow_system_messages;
+        }
+        if let Some(knowledge_base) = knowledge_base {
+            agent.knowledge_base = Some(knowledge_base).filter(|kb| !kb.is_empty());
+        }
+        if let Some(allowed_models) = allowed_models {
+            agent.allowed_models = allowed_models;
+        }
+        if let Some(scripts) = scripts {
+            agent.scripts = scripts;
+        }
+    });
+    match updated {
+        Ok(Some(agent)) => {
+            tracing::info!("Agent {} updated", agent.id);
+            rpc_ok(id, AgentResult { agent })
+        }
+        Ok(None) => {
+            let error = ServerError::NotFound {
+                resource: Resource::Agent,
+                id: agent_id,
+            };
+            server_error(id, error, locale)
+        }
+        Err(e) => storage_error(id, e, locale),
+    }
+}
+
+/// Handles the admin `promote_prompt_version` method, returning the agent
+/// with the version live.
+///
+/// Unknown versions are `-32602` errors.
+pub fn handle_promote_prompt_version(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: PromotePromptVersionParams =
+        match serde_json::from_value(request.params.unwrap_or_default()) {
+            Ok(params) => params,
+            Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+        };
+
+    // Checked first so that a bad version doesn't rewrite the stored agent
+    if let Some(agent) = state.agents.get(&params.agent_id) {
+        if let Err(e) = prompt_versions::promote(&mut Agent::clone(&agent), &params.version) {
+            return server_error(id, ServerError::InvalidParams(e), locale);
+        }
+    }
+    let mut promoted = Ok(());
+    let updated = state.agents.update(&params.agent_id, |agent| {
+        promoted = prompt_versions::promote(agent, &params.version);
+    });
+    if let Err(e) = promoted {
+        return server_error(id, ServerError::InvalidParams(e), locale);
+    }
+    match updated {
+        Ok(Some(agent)) => {
+            tracing::info!(
+                "Prompt version {} of agent {} promoted",
+                params.version,
+                agent.id
+            );
+            rpc_ok(id, AgentResult { agent })
+        }
+        Ok(None) => {
+            let error = ServerError::NotFound {
+                resource: Resource::Agent,
+                id: params.agent_id,
+            };
+            server_error(id, error, locale)
+        }
+        Err(e) => storage_error(id, e, locale),
+    }
+}
+
+/// Handles the admin `delete_agent` method, returning the removed agent.
+pub fn handle_delete_agent(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: DeleteAgentParams = match serde_json::from_value(request.params.unwrap_or_default())
+    {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+
+    match state.agents.delete(&params.agent_id) {
+        Ok(Some(agent)) => {
+            tracing::info!("Agent {} deleted", agent.id);
+            rpc_ok(id, AgentResult { agent })
+        }
+        Ok(None) => {
+            let error = ServerError::NotFound {
+                resource: Resource::Agent,
+                id: params.agent_id,
+            };
+            server_error(id, error, locale)
+        }
+        Err(e) => storage_error(id, e, locale),
+    }
+}
+
+/// Handles the admin `flush_cache` method.
+///
+/// Drops every cached reply, or only those of `agent_id`, and answers how
+/// many were dropped.
+pub fn handle_flush_cache(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: FlushCacheParams = match request.params.map(serde_json::from_value) {
+        Some(Ok(params)) => params,
+        None => FlushCacheParams::default(),
+        Some(Err(e)) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    let flushed = state.cache.flush(params.agent_id.as_deref());
+    tracing::info!("Flushed {} cached replies", flushed);
+    rpc_ok(id, FlushCacheResult { flushed })
+}
+
+/// Handles the admin `rotate_provider_keys` method.
+///
+/// Replaces the API keys of `provider` and answers what is known of each,
+/// masked. Calls in flight finish with the key they picked.
+pub fn handle_rotate_provider_keys(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: RotateKeysParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    let Some(pool) = state.providers.key_pool(&params.provider) else {
+        let e = ServerError::InvalidParams(format!(
+            "Provider {} is not configured with API keys",
+            params.provider
+        ));
+        return server_error(id, e, locale);
+    };
+    if let Err(e) = pool.rotate(params.api_keys) {
+        return server_error(id, ServerError::InvalidParams(e), locale);
+    }
+    rpc_ok(
+        id,
+        RotateKeysResult {
+            provider: params.provider,
+            keys: pool.status(),
+        },
+    )
+}
+
+/// Handles the admin `ingest_document` method.
+///
+/// The document is given as `text` or fetched from `url`, split into chunks
+/// as its format says, embedded and stored, replacing any earlier document
+/// with the same ID.
+pub async fn handle_ingest_document(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: IngestDocumentParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    if let Err(e) = validate_document(&params.knowledge_base, &params.document_id) {
+        return server_error(id, ServerError::InvalidParams(e), locale);
+    }
+    if serde_json::to_vec(&params.metadata).map_or(0, |json| json.len()) > MAX_METADATA_BYTES {
+        let message = format!("metadata is limited to {} bytes", MAX_METADATA_BYTES);
+        return server_error(id, ServerError::InvalidParams(message), locale);
+    }
+    let (text, document) = match (params.text, params.url) {
+        (Some(text), None) => {
+            let format = params.format.unwrap_or_default();
+            (text, Document::new(&params.document_id, format))
+        }
+        (None, Some(url)) => match document::fetch(&state.http_client, &url).await {
+            Ok((text, fetched)) => {
+                let format = params.format.or(fetched).unwrap_or_default();
+                let document = Document::new(&params.document_id, format).with_source(url);
+                (text, document)
+            }
+            Err(error) => {
+                tracing::warn!("Failed to fetch {}: {}", params.document_id, error);
+                return server_error(id, error, locale);
+            }
+        },
+        _ => {
+            let message = "give either text or url".to_string();
+            return server_error(id, ServerError::InvalidParams(message), locale);
+        }
+    };
+    let document = document.with_metadata(params.metadata);
+    match state
+        .knowledge
+        .ingest(&params.knowledge_base, document, &text)
+        .await
+    {
+        Ok(document) => {
+            tracing::info!(
+                "Ingested {} into knowledge base {} as {} chunks",
+                document.id,
+                params.knowledge_base,
+                document.chunks
+            );
+            rpc_ok(
+                id,
+                DocumentResult {
+                    knowledge_base: params.knowledge_base,
+                    document_id: document.id,
+                    chunks: document.chunks,
+                },
+            )
+        }
+        Err(error) => {
+            tracing::error!("Failed to ingest {}: {}", params.document_id, error);
+            server_error(id, error, locale)
+        }
+    }
+}
+
+/// Handles the read-only admin `list_documents` method.
+pub async fn handle_list_documents(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: ListDocumentsParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    if let Err(e) = validate_knowledge_base(&params.knowledge_base) {
+        return server_error(id, ServerError::InvalidParams(e), locale);
+    }
+    match state.knowledge.list(&params.knowledge_base).await {
+        Ok(documents) => rpc_ok(
+            id,
+            ListDocumentsResult {
+                knowledge_base: params.knowledge_base,
+                documents,
+            },
+        ),
+        Err(error) => server_error(id, error, locale),
+    }
+}
+
+/// Handles the admin `delete_document` method.
+pub async fn handle_delete_document(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: DeleteDocumentParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    if let Err(e) = validate_document(&params.knowledge_base, &params.document_id) {
+        return server_error(id, ServerError::InvalidParams(e), locale);
+    }
+    match state
+        .knowledge
+        .delete(&params.knowledge_base, &params.document_id)
+        .await
+    {
+        Ok(()) => {
+            tracing::info!(
+                "Deleted {} from knowledge base {}",
+                params.document_id,
+                params.knowledge_base
+            );
+            rpc_ok(
+                id,
+                DocumentResult {
+                    knowledge_base: params.knowledge_base,
+                    document_id: params.document_id,
+                    chunks: 0,
+                },
+            )
+        }
+        Err(error) => server_error(id, error, locale),
+    }
+}
+
+/// Checks the knowledge base and document ID of a document method.
+fn validate_document(knowledge_base: &str, document_id: &str) -> Result<(), String> {
+    validate_knowledge_base(knowledge_base)?;
+    if document_id.trim().is_empty() || document_id.chars().count() > 256 {
+        return Err("document_id must have 1 to 256 characters".to_string());
+    }
+    Ok(())
+}
+
+/// Handles the `list_memories` method: the facts remembered about the caller.
+pub fn handle_list_memories(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let user = match memory_user(&id, locale) {
+        Ok(user) => user,
+        Err(response) => return *response,
+    };
+    match state.memories.list(&user) {
+        Ok(memories) => rpc_ok(id, ListMemoriesResult { memories }),
+        Err(e) => server_error(id, ServerError::Storage(e), locale),
+    }
+}
+
+/// Handles the `delete_memories` method: forgets one fact remembered about
+/// the caller, or all of them.
+pub fn handle_delete_memories(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: DeleteMemoriesParams = match request.params.map(serde_json::from_value) {
+        Some(Ok(params)) => params,
+        None => DeleteMemoriesParams::default(),
+        Some(Err(e)) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    let user = match memory_user(&id, locale) {
+        Ok(user) => user,
+        Err(response) => return *response,
+    };
+    match state.memories.delete(&user, params.memory_id) {
+        Ok(deleted) => {
+            tracing::info!("Deleted {} memories", deleted);
+            rpc_ok(id, DeleteMemoriesResult { deleted })
+        }
+        Err(e) => server_error(id, ServerError::Storage(e), locale),
+    }
+}
+
+/// The user the memory methods act for: the caller's quota key.
+fn memory_user(id: &Value, locale: Locale) -> Result<String, Box<JsonRpcResponse<Value>>> {
+    auth::current_identity()
+        .map(|identity| quota::quota_key(&identity))
+        .ok_or_else(|| {
+            let error = ServerError::InvalidParams(
+                "memories are only kept for authenticated callers".to_string(),
+            );
+            Box::new(server_error(id.clone(), error, locale))
+        })
+}
+
+/// Handles the read-only admin `usage_report` method.
+///
+/// Reports requests, errors, tokens, cost and latency percentiles per agent
+/// and time bucket; every param is optional.
+pub fn handle_usage_report(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: UsageReportParams = match request.params.map(serde_json::from_value) {
+        Some(Ok(params)) => params,
+        None => UsageReportParams::default(),
+        Some(Err(e)) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    match state.accounting.report(&params) {
+        Ok(report) => rpc_ok(id, report),
+        Err(e) => server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    }
+}
+
+/// Handles the read-only admin `audit_log` method.
+///
+/// Answers `-32601`, like an unknown method, when `AUDIT_DB` is not set.
+pub fn handle_audit_log(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let Some(audit) = &state.audit else {
+        let message = Msg::MethodNotFound.format(locale, &request.method);
+        return rpc_error(id, -32601, message, None);
+    };
+    let params: AuditQueryParams = match request.params.map(serde_json::from_value) {
+        Some(Ok(params)) => params,
+        None => AuditQueryParams::default(),
+        Some(Err(e)) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+    match audit.query(&params) {
+        Ok(result) => rpc_ok(id, result),
+        Err(error) => server_error(id, error, locale),
+    }
+}
+
+/// Handles the admin `add_eval_case` method, returning the stored case.
+pub fn handle_add_eval_case(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: AddEvalCaseParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    if let Err(e) = params.validate() {
+        return server_error(id, ServerError::InvalidParams(e), locale);
+    }
+    if state.agents.get(&params.agent_id).is_none() {
+        let error = ServerError::NotFound {
+            resource: Resource::Agent,
+            id: params.agent_id,
+        };
+        return server_error(id, error, locale);
+    }
+    match state.evals.add(params) {
+        Ok(case) => {
+            tracing::info!("Eval case {} added for agent {}", case.id, case.agent_id);
+            rpc_ok(id, case)
+        }
+        Err(e) => server_error(id, ServerError::InvalidParams(e), locale),
+    }
+}
+
+/// Handles the read-only admin `list_eval_cases` method: an agent's
+/// evaluation cases.
+pub fn handle_list_eval_cases(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: ListEvalCasesParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    match state.evals.list(&params.agent_id) {
+        Ok(cases) => rpc_ok(id, ListEvalCasesResult { cases }),
+        Err(e) => server_error(id, ServerError::Storage(e), locale),
+    }
+}
+
+/// Handles the admin `delete_eval_case` method.
+pub fn handle_delete_eval_case(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: DeleteEvalCaseParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    match state.evals.delete(params.case_id) {
+        Ok(deleted) => {
+            tracing::info!("Deleted {} eval cases", deleted);
+            rpc_ok(id, DeleteEvalCaseResult { deleted })
+        }
+        Err(e) => server_error(id, ServerError::Storage(e), locale),
+    }
+}
+
+/// Handles the admin `run_eval` method: runs an agent's evaluation cases
+/// against the live provider and scores the replies, see [`crate::evals`].
+///
+/// Unknown agents, cases and prompt versions are errors; a failed agent or
+/// grader call only fails its case.
+pub async fn handle_run_eval(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: RunEvalParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    let Some(agent) = state.agents.get(&params.agent_id) else {
+        let error = ServerError::NotFound {
+            resource: Resource::Agent,
+            id: params.agent_id,
+        };
+        return server_error(id, error, locale);
+    };
+    // Agents with versions run the live one unless told otherwise
+    let version = params.prompt_version.clone().or_else(|| {
+        (agent.prompt_version.is_some() || !agent.prompt_versions.is_empty())
+            .then(|| prompt_versions::live_version(&agent).to_string())
+    });
+    let agent = match &version {
+        Some(version) => match prompt_versions::pinned(&agent, version) {
+            Some(agent) => Arc::new(agent),
+            None => {
+                let details = format!("agent {} has no prompt version {}", agent.id, version);
+                return server_error(id, ServerError::InvalidParams(details), locale);
+            }
+        },
+        None => agent,
+    };
+
+    let mut cases = match state.evals.list(&agent.id) {
+        Ok(cases) => cases,
+        Err(e) => return server_error(id, ServerError::Storage(e), locale),
+    };
+    if let Some(case_ids) = &params.case_ids {
+        if let Some(missing) = case_ids
+            .iter()
+            .find(|case_id| !cases.iter().any(|case| case.id == **case_id))
+        {
+            let details = format!("agent {} has no eval case {}", agent.id, missing);
+            return server_error(id, ServerError::InvalidParams(details), locale);
+        }
+        cases.retain(|case| case_ids.contains(&case.id));
+    }
+    if cases.is_empty() {
+        let details = format!("agent {} has no eval cases", agent.id);
+        return server_error(id, ServerError::InvalidParams(details), locale);
+    }
+
+    let start_time = std::time::Instant::now();
+    let runs: Vec<_> = cases
+        .iter()
+        .map(|case| run_eval_case(state, &agent, case, &id, locale))
+        .collect();
+    let results = futures_util::stream::iter(runs)
+        .buffered(evals::CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    let report = EvalReport::new(
+        agent.id.clone(),
+        version,
+        results,
+        start_time.elapsed().as_millis() as u64,
+    );
+    tracing::info!(
+        "Evaluated agent {}: {} of {} cases passed, score {:.2}",
+        report.agent_id,
+        report.passed,
+        report.cases.len(),
+        report.score
+    );
+    rpc_ok(id, report)
+}
+
+/// Runs one evaluation case like a `process_text` request without a caller
+/// or the response cache, then checks and grades the reply.
+async fn run_eval_case(
+    state: &AppState,
+    agent: &Arc<Agent>,
+    case: &EvalCase,
+    id: &Value,
+    locale: Locale,
+) -> EvalCaseResult {
+    let params = ProcessTextParams {
+        agent_id: agent.id.clone(),
+        user_text: case.input.clone(),
+        conversation_history: None,
+        session_id: None,
+        model: None,
+        generation: Default::default(),
+        budget: Default::default(),
+        tools: None,
+        timeout_ms: None,
+        images: None,
+        attachments: None,
+        reply_language: None,
+        style: None,
+        no_store: false,
+    };
+    let start_time = std::time::Instant::now();
+    let result =
+        auth::with_identity(None, run_agent(state, agent, params, false, id, locale)).await;
+    let result = match result {
+        Ok(result) => result,
+        Err(error) => {
+            let error = match error.data.as_ref().and_then(|d| d["details"].as_str()) {
+                Some(details) => format!("{}: {}", error.message, details),
+                None => error.message,
+            };
+            let elapsed = start_time.elapsed().as_millis() as u64;
+            return EvalCaseResult::failed(case, error, elapsed);
+        }
+    };
+    let checks = evals::check(&case.expected, &result.reply_text);
+    // Graded by the provider that answered
+    let grader = match (
+        &case.grader_prompt,
+        state.providers.get(&result.metadata.provider),
+    ) {
+        (None, _) => Ok(None),
+        (Some(_), None) => Err(format!("provider {} is gone", result.metadata.provider)),
+        (Some(_), Some(provider)) => {
+            let model = state
+                .evals
+                .grader_model()
+                .map_or_else(|| result.metadata.model.clone(), str::to_string);
+            evals::grade(provider.as_ref(), model, agent, case, &result.reply_text)
+                .await
+                .map(Some)
+        }
+    };
+    let processing_time_ms = result.metadata.processing_time_ms;
+    let grader = match grader {
+        Ok(grader) => grader,
+        Err(e) => {
+            return EvalCaseResult {
+                reply_text: Some(result.reply_text),
+                checks,
+                ..EvalCaseResult::failed(case, e, processing_time_ms)
+            }
+        }
+    };
+    let (score, passed) = evals::score(&checks, grader.as_ref());
+    EvalCaseResult {
+        case_id: case.id,
+        name: case.name.clone(),
+        passed,
+        score,
+        reply_text: Some(result.reply_text),
+        checks,
+        grader,
+        error: None,
+        processing_time_ms,
+    }
+}
+
+/// Handles the `embed_text` method, returning a vector for each text.
+///
+/// The `provider` param picks the backend; without it, the registry's
+/// [embedding provider](crate::providers::ProviderRegistry::embedding_provider)
+/// is used, or the server's own model when no backend computes embeddings.
+pub async fn handle_embed_text(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: EmbedTextParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    if let Err(errors) = state.limits.check_texts(&params.texts) {
+        return limits_error(id, errors, locale);
+    }
+    let provider = match params.provider.as_deref() {
+        Some(embeddings::LOCAL_PROVIDER) => None,
+        Some(name) => match state.providers.get(name) {
+            Some(provider) => Some(provider),
+            None => {
+                let error =
+                    ServerError::InvalidParams(format!("provider {} is not configured", name));
+                return server_error(id, error, locale);
+            }
+        },
+        None => state.providers.embedding_provider(),
+    };
+
+    let quota_key = auth::current_identity().map(|identity| quota::quota_key(&identity));
+    if let Some(key) = &quota_key {
+        if let Err(exceeded) = state.quotas.check(key) {
+            let error = ServerError::QuotaExceeded(exceeded);
+            tracing::info!("{}", error);
+            return server_error(id, error, locale);
+        }
+    }
+
+    let start_time = std::time::Instant::now();
+    let (provider_name, result) = match &provider {
+        Some(provider) => (
+            provider.name(),
+            provider.embed(&params.texts, params.model.as_deref()).await,
+        ),
+        None if params
+            .model
+            .as_deref()
+            .is_some_and(|m| m != embeddings::LOCAL_MODEL) =>
+        {
+            let error = ServerError::InvalidParams(format!(
+                "the local provider only serves model {}",
+                embeddings::LOCAL_MODEL
+            ));
+            return server_error(id, error, locale);
+        }
+        None => (
+            embeddings::LOCAL_PROVIDER,
+            Ok(embeddings::embed_local(&params.texts)),
+        ),
+    };
+    let embeddings = match result {
+        Ok(embeddings) => embeddings,
+        Err(error) => {
+            tracing::error!("{} embeddings failed: {}", provider_name, error);
+            return server_error(id, error, locale);
+        }
+    };
+    if let Some(key) = &quota_key {
+        state
+            .quotas
+            .record(key, embeddings.tokens_used.unwrap_or(0));
+    }
+    tracing::info!(
+        "Embedded {} texts with {} {}",
+        embeddings.vectors.len(),
+        provider_name,
+        embeddings.model
+    );
+    rpc_ok(
+        id,
+        EmbedTextResult {
+            metadata: EmbeddingMetadata {
+                provider: provider_name.to_string(),
+                model: embeddings.model,
+                dimensions: embeddings.vectors.first().map_or(0, Vec::len),
+                tokens_used: embeddings.tokens_used,
+                processing_time_ms: start_time.elapsed().as_millis() as u64,
+            },
+            embeddings: embeddings.vectors,
+        },
+    )
+}
+
+/// Handles the synthesize_speech JSON-RPC method.
+///
+/// Reads `text` aloud with the configured [speech backend](crate::speech),
+/// returning the audio base64 or, with `return_url`, a URL serving it for a
+/// while. Fails with invalid params when no backend is configured.
+pub async fn handle_synthesize_speech(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: SynthesizeSpeechParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    if let Err(e) = params.validate() {
+        return server_error(id, ServerError::InvalidParams(e), locale);
+    }
+    let Some(provider) = state.speech.synthesizer() else {
+        let error =
+            ServerError::InvalidParams("no text-to-speech backend is configured".to_string());
+        return server_error(id, error, locale);
+    };
+
+    let quota_key = auth::current_identity().map(|identity| quota::quota_key(&identity));
+    if let Some(key) = &quota_key {
+        if let Err(exceeded) = state.quotas.check(key) {
+            let error = ServerError::QuotaExceeded(exceeded);
+            tracing::info!("{}", error);
+            return server_error(id, error, locale);
+        }
+    }
+
+    let start_time = std::time::Instant::now();
+    let speech = SpeechRequest {
+        voice: params
+            .voice
+            .unwrap_or_else(|| state.speech.default_voice().to_string()),
+        text: params.text,
+        format: params.format,
+        speed: params.speed,
+    };
+    let audio = match provider.synthesize(&speech).await {
+        Ok(audio) => audio,
+        Err(error) => {
+            tracing::error!("{} speech failed: {}", provider.name(), error);
+            return server_error(id, error, locale);
+        }
+    };
+    if let Some(key) = &quota_key {
+        state.quotas.record(key, 0);
+    }
+    let metadata = SpeechMetadata {
+        provider: provider.name().to_string(),
+        model: provider.model().to_string(),
+        voice: speech.voice,
+        characters: speech.text.chars().count(),
+        bytes: Some(audio.len()),
+        processing_time_ms: start_time.elapsed().as_millis() as u64,
+    };
+    tracing::info!(
+        "Synthesized {} characters of speech with {} {}",
+        metadata.characters,
+        metadata.provider,
+        metadata.model
+    );
+    let mime_type = speech.format.mime_type().to_string();
+    let result = if params.return_url {
+        let (url, expires_at) = state.speech.keep(audio, speech.format);
+        SynthesizeSpeechResult {
+            audio: None,
+            url: Some(url),
+            expires_at: Some(audit::format_timestamp(expires_at)),
+            mime_type,
+            metadata,
+        }
+    } else {
+        SynthesizeSpeechResult {
+            audio: Some(base64::engine::general_purpose::STANDARD.encode(audio)),
+            url: None,
+            expires_at: None,
+            mime_type,
+            metadata,
+        }
+    };
+    rpc_ok(id, result)
+}
+
+/// Handles the process_audio JSON-RPC method.
+///
+/// Transcribes the recording, runs the agent on the transcript like
+/// `process_text`, and reads the reply aloud, timing each stage; see
+/// [`crate::voice`]. With `stream`, synthesis goes on in the background after
+/// the response, filling the clip behind the returned URL.
+///
+/// Fails with invalid params when no speech-to-text or text-to-speech
+/// backend is configured, or nothing was heard in the recording.
+pub async fn handle_process_audio(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let start_time = std::time::Instant::now();
+    let id = request.id.unwrap_or_default();
+    let params: ProcessAudioParams = match required_params(request.params, &id, locale) {
+        Ok(params) => params,
+        Err(response) => return *response,
+    };
+    let recording = match params.validate().and_then(|()| params.recording()) {
+        Ok(recording) => recording,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e), locale),
+    };
+    let (Some(transcriber), Some(synthesizer)) =
+        (state.speech.transcriber(), state.speech.synthesizer())
+    else {
+        let error = ServerError::InvalidParams(
+            "process_audio needs a speech-to-text and a text-to-speech backend".to_string(),
+        );
+        return server_error(id, error, locale);
+    };
+    // Checked before the transcription, which run_agent's check would come
+    // too late to save
+    if let Some(key) = auth::current_identity().map(|identity| quota::quota_key(&identity)) {
+        if let Err(exceeded) = state.quotas.check(&key) {
+            let error = ServerError::QuotaExceeded(exceeded);
+            tracing::info!("{}", error);
+            return server_error(id, error, locale);
+        }
+    }
+
+    let transcription_start = std::time::Instant::now();
+    let transcript = match transcriber.transcribe(&recording).await {
+        Ok(transcript) => transcript,
+        Err(error) => {
+            tracing::error!("{} transcription failed: {}", transcriber.name(), error);
+            return server_error(id, error, locale);
+        }
+    };
+    let transcription = TranscriptionMetadata {
+        provider: transcriber.name().to_string(),
+        model: transcriber.model().to_string(),
+        processing_time_ms: transcription_start.elapsed().as_millis() as u64,
+    };
+    if transcript.is_empty() {
+        let error = ServerError::InvalidParams("no speech was heard in the audio".to_string());
+        return server_error(id, error, locale);
+    }
+
+    let text_params = ProcessTextParams {
+        agent_id: params.agent_id,
+        user_text: transcript.clone(),
+        conversation_history: params.conversation_history,
+        session_id: params.session_id,
+        model: params.model,
+        generation: params.generation,
+        budget: Default::default(),
+        tools: None,
+        timeout_ms: None,
+        images: None,
+        attachments: None,
+        reply_language: params.reply_language,
+        style: params.style,
+        no_store: params.no_store,
+    };
+    if let Err(errors) = state.limits.check(&text_params) {
+        return limits_error(id, errors, locale);
+    }
+    let agent = match find_agent(state, &text_params.agent_id, &id, locale) {
+        Ok(agent) => agent,
+        Err(response) => return *response,
+    };
+    let reply = match run_agent(state, &agent, text_params, true, &id, locale).await {
+        Ok(reply) => reply,
+        Err(error) => return rpc_failure(id, error),
+    };
+
+    let mut result = ProcessAudioResult {
+        transcript,
+        agent_id: reply.agent_id,
+        reply_text: reply.reply_text,
+        tool_calls: reply.tool_calls,
+        audio: None,
+        url: None,
+        expires_at: None,
+        mime_type: None,
+        metadata: ProcessAudioMetadata {
+            transcription,
+            agent: reply.metadata,
+            speech: None,
+            total_time_ms: 0,
+        },
+    };
+    let spoken = voice::spoken_text(&result.reply_text);
+    if !spoken.is_empty() {
+        let speech_start = std::time::Instant::now();
+        let speech = SpeechRequest {
+            text: spoken.to_string(),
+            voice: params
+                .voice
+                .unwrap_or_else(|| state.speech.default_voice().to_string()),
+            format: params.format,
+            speed: params.speed,
+        };
+        let bytes = if params.stream {
+            let mut audio = match synthesizer.synthesize_stream(&speech).await {
+                Ok(audio) => audio,
+                Err(error) => {
+                    tracing::error!("{} speech failed: {}", synthesizer.name(), error);
+                    return server_error(id, error, locale);
+                }
+            };
+            let (url, expires_at, writer) = state.speech.keep_streaming(speech.format);
+            let name = synthesizer.name();
+            tokio::spawn(async move {
+                while let Some(chunk) = audio.next().await {
+                    match chunk {
+                        Ok(chunk) => writer.write(&chunk),
+                        Err(error) => {
+                            tracing::error!("{} speech stream failed: {}", name, error);
+                            writer.fail();
+                            return;
+                        }
+                    }
+                }
+            });
+            result.url = Some(url);
+            result.expires_at = Some(audit::format_timestamp(expires_at));
+            None
+        } else {
+            let audio = match synthesizer.synthesize(&speech).await {
+                Ok(audio) => audio,
+                Err(error) => {
+                    tracing::error!("{} speech failed: {}", synthesizer.name(), error);
+                    return server_error(id, error, locale);
+                }
+            };
+            let bytes = audio.len();
+            if params.return_url {
+                let (url, expires_at) = state.speech.keep(audio, speech.format);
+                result.url = Some(url);
+                result.expires_at = Some(audit::format_timestamp(expires_at));
+            } else {
+                result.audio = Some(base64::engine::general_purpose::STANDARD.encode(audio));
+            }
+            Some(bytes)
+        };
+        result.mime_type = Some(speech.format.mime_type().to_string());
+        result.metadata.speech = Some(SpeechMetadata {
+            provider: synthesizer.name().to_string(),
+            model: synthesizer.model().to_string(),
+            voice: speech.voice,
+            characters: speech.text.chars().count(),
+            bytes,
+            processing_time_ms: speech_start.elapsed().as_millis() as u64,
+        });
+    }
+    result.metadata.total_time_ms = start_time.elapsed().as_millis() as u64;
+    tracing::info!(
+        "Answered a {} transcript with {} in {} ms",
+        result.metadata.transcription.provider,
+        result.agent_id,
+        result.metadata.total_time_ms
+    );
+    rpc_ok(id, result)
+}
+
+/// The response for an agent change that could not be persisted.
+fn storage_error(id: Value, error: String, locale: Locale) -> JsonRpcResponse<Value> {
+    tracing::error!("Agent storage error: {}", error);
+    server_error(id, ServerError::Storage(error), locale)
+}
+
+/// Handles the MCP `tools/call` method.
+///
+/// Agent tools run the agent like `process_text` does; `list_agents` returns
+/// the agents as JSON. Unknown tools and malformed arguments are JSON-RPC
+/// errors, while provider failures come back as a result with `isError` set.
+pub async fn handle_call_tool(
+    state: &AppState,
+    request: JsonRpcRequest<Value>,
+    locale: Locale,
+) -> JsonRpcResponse<Value> {
+    let id = request.id.unwrap_or_default();
+    let params: CallToolParams = match serde_json::from_value(request.params.unwrap_or_default()) {
+        Ok(params) => params,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+
+    if params.name == tools::LIST_AGENTS_TOOL {
+        let agents = serde_json::to_value(ListAgentsResult {
+            agents: visible_agents(state).list().to_vec(),
+        })
+        .unwrap();
+        let text = serde_json::to_string_pretty(&agents).unwrap();
+        return rpc_ok(id, CallToolResult::text(text, Some(agents)));
+    }
+
+    let Some(agent) = visible_agents(state).get(&params.name) else {
+        let error = ServerError::NotFound {
+            resource: Resource::Tool,
+            id: params.name,
+        };
+        return server_error(id, error, locale);
+    };
+    let arguments: AgentToolArguments = match serde_json::from_value(params.arguments) {
+        Ok(arguments) => arguments,
+        Err(e) => return server_error(id, ServerError::InvalidParams(e.to_string()), locale),
+    };
+
+    let params = ProcessTextParams {
+        agent_id: agent.id.clone(),
+        user_text: arguments.user_text,
+        conversation_history: arguments.conversation_history,
+        session_id: arguments.session_id,
+        model: arguments.model,
+        generation: arguments.generation,
+        budget: Default::default(),
+        tools: None,
+        timeout_ms: arguments.timeout_ms,
+        images: None,
+        attachments: None,
+        reply_language: None,
+        style: None,
+        no_store: arguments.no_store,
+    };
+    if let Err(errors) = state.limits.check(&params) {
+        return limits_error(id, errors, locale);
+    }
+    let result = match run_agent(state, &agent, params, true, &id, locale).await {
+        Ok(result) => CallToolResult::text(
+            result.reply_text.clone(),
+            Some(serde_json::to_value(&result).unwrap()),
+        ),
+        // Unlike a JSON-RPC error, the result is read by the model, so include the cause
+        Err(error) => match error.data.as_ref().and_then(|d| d["details"].as_str()) {
+            Some(details) => CallToolResult::error(format!("{}: {}", error.message, details)),
+            None => CallToolResult::error(error.message),
+        },
+    };
+    rpc_ok(id, result)
+}
+
+/// Sends the user's text to `agent` and times the reply.
+///
+/// The agent's model, or the `model` override, is mapped onto the default
+/// provider by [`resolve_model`]; an override the provider doesn't serve, or
+/// outside the agent's [`allowed_models`](Agent::allowed_models), is an
+/// invalid-params error. With a `session_id`, the session's transcript stands
+/// in for a missing `conversation_history` and a successful exchange is
+/// appended to it, including any server tool calls and their results.
+/// Requests without a session or client tools are answered from the
+/// [response cache](crate::cache) when it holds their reply. Callers over a
+/// [quota](crate::quota) get `-32007`, and every answered request counts
+/// against theirs. Provider failures are reported to the configured error
+/// sinks, tagged with the JSON-RPC request `id`. Replies are checked against
+/// the [guardrails](crate::guardrails), requested again or fixed up. With
+/// [memories](crate::memories) enabled, an authenticated caller's remembered
+/// facts are added to the prompt, bypassing the response cache, and new ones
+/// are extracted from the exchange in the background. The agent's
+/// [plugins](crate::plugins) and [scripts](crate::scripts) rewrite the
+/// user's text and the reply, and may answer in place of a provider; their
+/// replies are recorded in the session but neither cached nor remembered.
+///
+/// Every request that gets past validation and the quota check is
+/// [accounted](crate::accounting) with its latency, tokens, cost and whether
+/// it failed. Without `cache`, the response cache is neither read nor
+/// written, so the provider is always asked.
+async fn run_agent(
+    state: &AppState,
+    agent: &Arc<Agent>,
+    params: ProcessTextParams,
+    cache: bool,
+    id: &Value,
+    locale: Locale,
+) -> Result<ProcessTextResult, JsonRpcError> {
+    let start_time = std::time::Instant::now();
+    let quota_key = auth::current_identity().map(|identity| quota::quota_key(&identity));
+    let mut result = ask_agent(
+        state,
+        agent,
+        params,
+        quota_key.as_deref(),
+        cache,
+        id,
+        locale,
+    )
+    .await;
+    if let Ok(result) = &mut result {
+        // Set here rather than cached, so replies from the cache carry their own
+        result.metadata.correlation_id = correlation::current_correlation_id();
+    }
+    let outcome = match &result {
+        Ok(result) => Outcome {
+            // Replies from the cache used no tokens this time
+            tokens: match result.metadata.cache {
+                Some(CacheStatus::Hit) => 0,
+                _ => result.metadata.tokens_used.unwrap_or(0),
+            },
+            cost_usd: result.metadata.cost_usd.unwrap_or(0.0),
+            latency: start_time.elapsed(),
+            failed: false,
+        },
+        // Rejected before reaching a provider
+        Err(error) if matches!(error.code, -32602 | -32007) => return result,
+        Err(_) => Outcome {
+            latency: start_time.elapsed(),
+            failed: true,
+            ..Default::default()
+        },
+    };
+    state
+        .accounting
+        .record(quota_key.as_deref(), &agent.id, outcome);
+    result
+}
+
+/// [`run_agent`] without the accounting.
+async fn ask_agent(
+    state: &AppState,
+    agent: &Arc<Agent>,
+    params: ProcessTextParams,
+    quota_key: Option<&str>,
+    cache: bool,
+    id: &Value,
+    locale: Locale,
+) -> Result<ProcessTextResult, JsonRpcError> {
+    let ProcessTextParams {
+        user_text,
+        conversation_history,
+        session_id,
+        model,
+        generation,
+        tools,
+        timeout_ms,
+        images,
+        attachments,
+        reply_language,
+        style,
+        budget,
+        no_store,
+        ..
+    } = params;
+    let tools = tools.unwrap_or_default();
+    let images = images.unwrap_or_default();
+    let tenant = auth::current_tenant();
+    let session_key = session_id
+        .as_deref()
+        .map(|session_id| sessions::session_key(tenant.as_deref(), session_id))
+        .transpose();
+    let (timeout, session_key, attachments) = match generation
+        .validate()
+        .and_then(|_| budget.validate())
+        .and_then(|_| FunctionTool::validate_all(&tools))
+        .and_then(|_| ImageInput::validate_all(&images))
+        .and_then(|_| {
+            reply_language
+                .as_deref()
+                .map_or(Ok(()), language::validate_reply_language)
+        })
+        .and_then(|_| ProcessTextParams::validate_timeout(timeout_ms))
+        .and_then(|timeout| {
+            let attachments = attachments::extract_all(attachments.unwrap_or_default())?;
+            Ok((timeout, session_key?, attachments))
+        }) {
+        Ok(validated) => validated,
+        Err(e) => return Err(ServerError::InvalidParams(e).to_rpc_error(locale)),
+    };
+
+    // Callers that used up a quota are turned away before any provider call
+    if let Some(key) = quota_key {
+        if let Err(exceeded) = state.quotas.check(key) {
+            let error = ServerError::QuotaExceeded(exceeded);
+            tracing::info!("{}", error);
+            return Err(error.to_rpc_error(locale));
+        }
+    }
+
+    // The agent's pre-request script, then its plugins, see the user's text
+    // before anything else does; the script may answer it right away
+    let (user_text, canned) = match scripts::pre_request(agent, &user_text)
+        .map_err(|error| hook_failure(state, agent, "script", error, id, locale))?
+    {
+        PreRequest::Keep => (user_text, None),
+        PreRequest::Rewrite(text) => (text, None),
+        PreRequest::Answer(reply) => (user_text, Some(reply)),
+    };
+    let plugins = state.plugins.for_agent(&agent.id);
+    let user_text = match canned {
+        Some(_) => user_text,
+        None => plugins::rewrite(&plugins, plugins::Stage::PreProcess, user_text)
+            .await
+            .map_err(|error| hook_failure(state, agent, "plugin", error, id, locale))?,
+    };
+
+    // Only the agent's own system prompt is sent, unless it trusts callers
+    // with their own
+    let conversation_history = conversation_history.map(|mut history| {
+        if agent.allow_system_messages {
+            return history;
+        }
+        let stripped = prompt_guard::strip_system_messages(&mut history);
+        if stripped > 0 {
+            tracing::warn!(
+                "Dropped {} system messages from the conversation history",
+                stripped
+            );
+        }
+        history
+    });
+
+    // Agents running a prompt experiment answer with one of its versions,
+    // the same one throughout a session or for an authenticated caller
+    let agent = &prompt_versions::choose(agent, session_key.as_deref().or(quota_key));
+    let generation = generation.with_agent_defaults(agent);
+    let seed = generation.seed;
+    // Sessions are stored under the caller's tenant
+    let session_id = session_key.as_deref();
+    let conversation_history = match (conversation_history, session_id) {
+        (Some(history), _) => Some(history),
+        (None, Some(session_id)) => state.sessions.get(session_id).await.unwrap_or_else(|e| {
+            tracing::error!("Failed to load session {}: {}", session_id, e);
+            None
+        }),
+        (None, None) => None,
+    };
+    // Detected in what the user wrote, not in their attachments
+    let language = language::resolve(&user_text, reply_language.as_deref());
+    let prompt_agent = match &language.reply {
+        Some(code) => Arc::new(language::with_reply_language(agent, code)),
+        None => agent.clone(),
+    };
+    let prompt_agent = match style.and_then(|style| style::with_style(&prompt_agent, &style)) {
+        Some(styled) => Arc::new(styled),
+        None => prompt_agent,
+    };
+    // Authenticated callers have facts remembered about them, from what
+    // they wrote rather than the files they attached, unless they asked for
+    // nothing to be kept
+    let memory_user = quota_key.filter(|_| state.memories.is_enabled());
+    let remembered_text = memory_user.filter(|_| !no_store).map(|_| user_text.clone());
+    let user_text = match attachments.is_empty() {
+        true => user_text,
+        false => attachments::with_attachments(&attachments, &user_text),
+    };
+    let recorded_text = session_id.filter(|_| !no_store).map(|_| user_text.clone());
+
+    // The script's answer, or a plugin handling the agent's requests, stands
+    // in for a provider's
+    let handling_start = std::time::Instant::now();
+    let handled = match canned {
+        Some(reply) => Some(("script", "pre_request".to_string(), reply)),
+        None => plugins::handle(&plugins, &user_text)
+            .await
+            .map_err(|error| hook_failure(state, agent, "plugin", error, id, locale))?
+            .map(|(plugin, reply)| ("plugin", plugin.id().to_string(), reply)),
+    };
+    if let Some((handler, model, reply)) = handled {
+        let reply_text = finished_reply(state, agent, &plugins, reply, id, locale).await?;
+        if let (Some(session_id), Some(user_text)) = (session_id, recorded_text) {
+            record_exchange(state, session_id, user_text, Vec::new(), &reply_text, &[]).await;
+        }
+        if let Some(key) = quota_key {
+            state.quotas.record(key, 0);
+        }
+        return Ok(ProcessTextResult {
+            agent_id: agent.id.clone(),
+            reply_text,
+            tool_calls: Vec::new(),
+            metadata: ProcessingMetadata {
+                provider: handler.to_string(),
+                model,
+                tokens_used: None,
+                cost_usd: None,
+                processing_time_ms: handling_start.elapsed().as_millis() as u64,
+                finish_reason: None,
+                confidence: None,
+                seed,
+                cache: None,
+                correlation_id: None,
+                sources: Vec::new(),
+                attachments: attachments.into_iter().map(|a| a.info).collect(),
+                language: Some(language),
+                prompt_version: agent.prompt_version.clone(),
+                no_store,
+            },
+        });
+    }
+
+    if let Some(Err(details)) = model.as_deref().map(|model| agent.allows_model(model)) {
+        let error = ServerError::UnknownModel {
+            model: model.unwrap_or_default(),
+            details,
+        };
+        return Err(error.to_rpc_error(locale));
+    }
+    let provider = state.providers.default_provider();
+    let model = match &provider {
+        Some(provider) => match resolve_model(provider.as_ref(), &agent.model, model.as_deref()) {
+            Ok(model) => model,
+            Err(details) => {
+                let error = ServerError::UnknownModel {
+                    model: model.unwrap_or_default(),
+                    details,
+                };
+                return Err(error.to_rpc_error(locale));
+            }
+        },
+        None => agent.model.clone(),
+    };
+    // Cost budgets need the model's price to be enforced
+    let price = state.accounting.price(&model);
+    if budget.max_cost_usd.is_some() && price.is_none() {
+        let details = format!("max_cost_usd needs a price for model {}", model);
+        return Err(ServerError::InvalidParams(details).to_rpc_error(locale));
+    }
+    if let Some(provider) = provider
+        .as_ref()
+        .filter(|p| !tools.is_empty() && !p.supports_tools())
+    {
+        let details = format!("the {} provider does not support tools", provider.name());
+        return Err(ServerError::InvalidParams(details).to_rpc_error(locale));
+    }
+    if let Some(provider) = provider
+        .as_ref()
+        .filter(|p| !images.is_empty() && !p.supports_images())
+    {
+        let details = format!(
+            "the {} provider does not accept images; make a vision-capable provider \
+             such as gemini the default with LLM_PROVIDER",
+            provider.name()
+        );
+        return Err(ServerError::InvalidParams(details).to_rpc_error(locale));
+    }
+
+    // Server tools are offered alongside the client's when the provider can call them
+    let server_tools = match &provider {
+        Some(provider) if provider.supports_tools() => state.server_tools.for_agent(agent),
+        _ => Vec::new(),
+    };
+    if let Some(tool) = tools
+        .iter()
+        .find(|t| server_tools::find(&server_tools, &t.function.name).is_some())
+    {
+        let details = format!("tool {} is provided by the server", tool.function.name);
+        return Err(ServerError::InvalidParams(details).to_rpc_error(locale));
+    }
+
+    // Start timing; timeout_ms covers the whole request from here, fallbacks
+    // and tool rounds included
+    let start_time = std::time::Instant::now();
+    let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+
+    // Replies that depend only on the prompt are reused while they are
+    // fresh, and shared by identical requests in flight
+    let reuse_key = (cache
+        && !no_store
+        && session_id.is_none()
+        && tools.is_empty()
+        && images.is_empty()
+        && memory_user.is_none())
+    .then(|| {
+        cache_key(
+            tenant.as_deref(),
+            &prompt_agent,
+            &model,
+            &user_text,
+            conversation_history.as_deref(),
+            &generation,
+        )
+    });
+    let reused = |mut result: ProcessTextResult, status| {
+        result.metadata.processing_time_ms = start_time.elapsed().as_millis() as u64;
+        result.metadata.cache = Some(status);
+        result.metadata.cost_usd = result.metadata.cost_usd.map(|_| 0.0);
+        if let Some(key) = quota_key {
+            state.quotas.record(key, 0);
+        }
+        result
+    };
+    let cache_key = reuse_key.clone().filter(|_| state.cache.is_enabled());
+    if let Some(result) = cache_key.as_deref().and_then(|key| state.cache.get(key)) {
+        tracing::debug!("Answering agent {} from the response cache", agent.id);
+        return Ok(reused(result, CacheStatus::Hit));
+    }
+    // Budgets and timeouts change the outcome, so they must match as well
+    let coalesce_key = reuse_key
+        .filter(|_| state.coalescer.is_enabled() && budget == Budget::default())
+        .map(|key| format!("{}:{}", key, timeout.map_or(0, |t| t.as_millis())));
+    let leader = match coalesce_key.map(|key| state.coalescer.join(key)) {
+        Some(Turn::Follow(receiver)) => match coalesce::wait(receiver).await {
+            Some(result) => {
+                tracing::debug!(
+                    "Answering agent {} with the reply of an identical request",
+                    agent.id
+                );
+                return Ok(reused(result, CacheStatus::Shared));
+            }
+            // The request followed failed, so this one tries for itself
+            None => None,
+        },
+        Some(Turn::Lead(leader)) => Some(leader),
+        None => None,
+    };
+
+    let mut tools = tools;
+    tools.extend(server_tools.iter().map(|tool| tool.definition()));
+
+    // The default provider first, then the agent's fallbacks that can serve the request
+    let mut chain: Vec<(Arc<dyn LlmProvider>, String)> = provider
+        .into_iter()
+        .map(|provider| (provider, model.clone()))
+        .collect();
+    chain.extend(
+        state
+            .providers
+            .fallbacks(agent)
+            .into_iter()
+            .filter(|(provider, _)| tools.is_empty() || provider.supports_tools())
+            .filter(|(provider, _)| images.is_empty() || provider.supports_images()),
+    );
+    // Images given by URL are fetched once the request is known to be servable
+    let images = match images::resolve(&state.http_client, images).await {
+        Ok(images) => images,
+        Err(error) => return Err(error.to_rpc_error(locale)),
+    };
+
+    let mut request = CompletionRequest {
+        agent: prompt_agent,
+        model: model.clone(),
+        user_text,
+        conversation_history,
+        generation,
+        tools,
+        timeout,
+        images,
+    };
+    // Chunks of the agent's knowledge base closest to the question
+    let mut sources = Vec::new();
+    if let Some(knowledge_base) = &agent.knowledge_base {
+        progress::report(
+            Stage::Retrieving,
+            format!("Searching knowledge base {}", knowledge_base),
+        );
+        match state
+            .knowledge
+            .retrieve(knowledge_base, &request.user_text)
+            .await
+        {
+            Ok(found) if !found.is_empty() => {
+                request.agent = Arc::new(knowledge::with_context(&request.agent, &found));
+                for found in found {
+                    if !sources.contains(&found.chunk.document) {
+                        sources.push(found.chunk.document);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Answering without knowledge base {}: {}", knowledge_base, e),
+        }
+    }
+    // Facts remembered about the caller from earlier conversations
+    if let Some(user) = memory_user {
+        match state.memories.list(user) {
+            Ok(remembered) if !remembered.is_empty() => {
+                request.agent = Arc::new(memories::with_memories(&request.agent, &remembered));
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Answering without memories: {}", e),
+        }
+    }
+    let mut trimmed = state.history.apply(&mut request);
+    // A budget keeps room for the reply, at the expense of the history
+    let reply_tokens = request
+        .generation
+        .max_tokens
+        .unwrap_or(budget::DEFAULT_REPLY_TOKENS);
+    if let Some(allowance) = budget.prompt_allowance(Spent::default(), price, reply_tokens) {
+        let policy = HistoryPolicy {
+            max_messages: None,
+            max_tokens: Some(allowance.min(u32::MAX.into()) as u32),
+        };
+        trimmed += policy.apply(&mut request);
+    }
+    if trimmed > 0 {
+        tracing::debug!(
+            "Trimmed {} old messages from the conversation history",
+            trimmed
+        );
+    }
+    prompt_guard::delimit_user_input(&mut request);
+
+    // Answer server tool calls until the model replies without one
+    let mut tokens_used = None;
+    let mut cost_usd = None;
+    let mut tool_messages = Vec::new();
+    let mut rounds = 0;
+    let mut regenerations = 0;
+    // Later rounds start at the provider that answered the previous one
+    let mut used = 0;
+    let max_tokens = request.generation.max_tokens;
+    let mut spent = Spent::default();
+    let Completion {
+        text: reply_text,
+        tool_calls,
+        finish_reason,
+        confidence,
+        ..
+    } = loop {
+        // Replies are cut short at what is left of the budget
+        match budget.reply_allowance(spent, price, estimate_tokens(&request)) {
+            Ok(allowance) => {
+                request.generation.max_tokens = allowance
+                    .map(|allowance| allowance.min(GenerationParams::MAX_MAX_TOKENS))
+                    .map_or(max_tokens, |allowance| {
+                        Some(max_tokens.map_or(allowance, |max| max.min(allowance)))
+                    });
+            }
+            Err(exceeded) => {
+                let error = ServerError::BudgetExceeded(exceeded);
+                tracing::info!("{}", error);
+                return Err(error.to_rpc_error(locale));
+            }
+        }
+        if let Some((_, model)) = chain.get(used) {
+            progress::report(Stage::Generating, format!("Asking {}", model));
+        }
+        let completion =
+            match complete_with_fallbacks(state, &chain[used..], &request, deadline).await {
+                Ok((completion, i)) => {
+                    used += i;
+                    completion
+                }
+                Err((provider, model, error)) => {
+                    return Err(provider_failure(
+                        state, agent, model, provider, error, id, locale,
+                    ))
+                }
+            };
+        progress::add_tokens(completion.tokens_used.unwrap_or(0) as u64);
+        tokens_used = match (tokens_used, completion.tokens_used) {
+            (Some(total), Some(tokens)) => Some(total + tokens),
+            (total, tokens) => total.or(tokens),
+        };
+        let cost = state.accounting.cost(
+            &chain[used].1,
+            completion.prompt_tokens,
+            completion.tokens_used,
+        );
+        cost_usd = match (cost_usd, cost) {
+            (Some(total), Some(cost)) => Some(total + cost),
+            (total, cost) => total.or(cost),
+        };
+        spent.tokens += completion.tokens_used.map_or_else(
+            || {
+                let reply = completion.text.as_deref().unwrap_or_default();
+                u64::from(estimate_tokens(&request)) + reply.chars().count().div_ceil(4) as u64
+            },
+            u64::from,
+        );
+        spent.cost_usd += cost.unwrap_or(0.0);
+
+        let served_here = !completion.tool_calls.is_empty()
+            && completion
+                .tool_calls
+                .iter()
+                .all(|call| server_tools::find(&server_tools, &call.function.name).is_some());
+        if !served_here || rounds == server_tools::MAX_ROUNDS {
+            // Replies breaking a guardrail are asked for again while allowed
+            let violations = completion
+                .text
+                .as_deref()
+                .map(|text| state.guardrails.check(&agent.id, text))
+                .unwrap_or_default();
+            if violations.iter().any(Violation::regenerates)
+                && regenerations < state.guardrails.max_regenerations()
+                && budget
+                    .reply_allowance(spent, price, estimate_tokens(&request))
+                    .is_ok()
+            {
+                regenerations += 1;
+                let violations: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+                tracing::warn!(
+                    "Regenerating the reply of agent {}, which {}",
+                    agent.id,
+                    violations.join(", ")
+                );
+                continue;
+            }
+            break completion;
+        }
+        rounds += 1;
+
+        let mut messages = Vec::new();
+        // The user text moves into the history, ahead of the calls it prompted
+        if !request.user_text.is_empty() {
+            messages.push(Message {
+                role: "user".to_string(),
+                content: std::mem::take(&mut request.user_text),
+                ..Default::default()
+            });
+        }
+        messages.push(Message {
+            role: "assistant".to_string(),
+            content: completion.text.unwrap_or_default(),
+            tool_calls: completion.tool_calls.clone(),
+            tool_call_id: None,
+        });
+        for call in &completion.tool_calls {
+            tracing::info!("Running server tool {}", call.function.name);
+            progress::report(
+                Stage::CallingTool,
+                format!("Running tool {}", call.function.name),
+            );
+            messages.push(server_tools::answer(&server_tools, call).await);
+        }
+        // The user message was recorded separately
+        tool_messages.extend(messages.iter().filter(|m| m.role != "user").cloned());
+        request
+            .conversation_history
+            .get_or_insert_with(Vec::new)
+            .extend(messages);
+    };
+
+    let processing_time = start_time.elapsed().as_millis() as u64;
+    let reply_text = match reply_text {
+        Some(text) => finished_reply(state, agent, &plugins, text, id, locale).await?,
+        None if !tool_calls.is_empty() => String::new(),
+        None => Msg::EmptyReply.text(locale).to_string(),
+    };
+
+    if let (Some(session_id), Some(user_text)) = (session_id, recorded_text) {
+        record_exchange(
+            state,
+            session_id,
+            user_text,
+            tool_messages,
+            &reply_text,
+            &tool_calls,
+        )
+        .await;
+    }
+    if let (Some(user), Some(user_text)) = (memory_user, remembered_text) {
+        state.memories.remember(
+            chain[used].0.clone(),
+            chain[used].1.clone(),
+            agent,
+            user.to_string(),
+            user_text,
+            reply_text.clone(),
+        );
+    }
+
+    // Build the result
+    let result = ProcessTextResult {
+        agent_id: agent.id.clone(),
+        reply_text,
+        tool_calls,
+        metadata: ProcessingMetadata {
+            provider: chain[used].0.name().to_string(),
+            model: chain[used].1.clone(),
+            tokens_used,
+            cost_usd,
+            processing_time_ms: processing_time,
+            finish_reason,
+            confidence,
+            seed,
+            cache: cache_key.as_ref().map(|_| CacheStatus::Miss),
+            correlation_id: None,
+            sources,
+            attachments: attachments.into_iter().map(|a| a.info).collect(),
+            language: Some(language),
+            prompt_version: agent.prompt_version.clone(),
+            no_store,
+        },
+    };
+    if let Some(key) = quota_key {
+        state.quotas.record(key, tokens_used.unwrap_or(0));
+    }
+    // Tool calls have effects, so replies involving them are not reused
+    if rounds == 0 && result.tool_calls.is_empty() {
+        if let Some(key) = cache_key {
+            state.cache.insert(key, &result);
+        }
+        if let Some(leader) = leader {
+            leader.share(result.clone());
+        }
+    }
+    Ok(result)
+}
+
+/// `text` rewritten by the agent's [plugins](crate::plugins) and
+/// [post-response script](crate::scripts), then fixed up to follow the
+/// [guardrails](crate::guardrails).
+async fn finished_reply(
+    state: &AppState,
+    agent: &Agent,
+    plugins: &[Arc<Plugin>],
+    text: String,
+    id: &Value,
+    locale: Locale,
+) -> Result<String, JsonRpcError> {
+    let text = plugins::rewrite(plugins, plugins::Stage::PostProcess, text)
+        .await
+        .map_err(|error| hook_failure(state, agent, "plugin", error, id, locale))?;
+    let text = scripts::post_response(agent, text)
+        .map_err(|error| hook_failure(state, agent, "script", error, id, locale))?;
+    let (text, violations) = state.guardrails.enforce(&agent.id, &text);
+    for violation in violations {
+        tracing::warn!("Reply of agent {} {}", agent.id, violation);
+    }
+    Ok(text)
+}
+
+/// Appends an exchange to session `session_id`: the user's text, any server
+/// tool calls and their results, and the reply.
+async fn record_exchange(
+    state: &AppState,
+    session_id: &str,
+    user_text: String,
+    tool_messages: Vec<Message>,
+    reply_text: &str,
+    tool_calls: &[ToolCall],
+) {
+    let mut messages = Vec::with_capacity(tool_messages.len() + 2);
+    // A turn that only returns tool results has no user text to record
+    if !user_text.is_empty() {
+        messages.push(Message {
+            role: "user".to_string(),
+            content: user_text,
+            ..Default::default()
+        });
+    }
+    messages.extend(tool_messages);
+    messages.push(Message {
+        role: "assistant".to_string(),
+        content: reply_text.to_string(),
+        tool_calls: tool_calls.to_vec(),
+        tool_call_id: None,
+    });
+    if let Err(e) = state.sessions.append(session_id, messages).await {
+        tracing::error!("Failed to record session {}: {}", session_id, e);
+    }
+}
+
+/// Asks each provider of `chain` in turn, with the model paired with it,
+/// until one answers.
+///
+/// Every call is counted in the [metrics](crate::metrics) and
+/// [accounted](crate::accounting) to the agent. Returns the completion and
+/// the index of the provider that gave it, or the provider, model and error
+/// of the last failure.
+async fn complete_with_fallbacks<'a>(
+    state: &AppState,
+    chain: &'a [(Arc<dyn LlmProvider>, String)],
+    request: &CompletionRequest,
+    deadline: Option<tokio::time::Instant>,
+) -> Result<(Completion, usize), (&'static str, &'a str, ServerError)> {
+    let mut failure = (
+        "none",
+        "",
+        ServerError::Internal("No AI provider configured".to_string()),
+    );
+    for (i, (provider, model)) in chain.iter().enumerate() {
+        if i > 0 {
+            tracing::warn!(
+                "{} failed, falling back to {} ({}): {}",
+                failure.0,
+                provider.name(),
+                model,
+                failure.2
+            );
+        }
+        let timed_out = || {
+            ServerError::Timeout(format!(
+                "{} did not answer within the request's timeout",
+                provider.name()
+            ))
+        };
+        // Each provider gets what is left of the request's time
+        let left = deadline
+            .map(|deadline| deadline.saturating_duration_since(tokio::time::Instant::now()));
+        if left.is_some_and(|left| left.is_zero()) {
+            failure = (provider.name(), model.as_str(), timed_out());
+            break;
+        }
+        let request = CompletionRequest {
+            model: model.clone(),
+            timeout: left.or(request.timeout),
+            ..request.clone()
+        };
+        let agent_id = request.agent.id.clone();
+        let started = std::time::Instant::now();
+        let result = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, provider.complete(request))
+                .await
+                .unwrap_or_else(|_| Err(timed_out())),
+            None => provider.complete(request).await,
+        };
+        let latency = started.elapsed();
+        let tokens = result.as_ref().ok().and_then(|c| c.tokens_used);
+        state.metrics.record(
+            &agent_id,
+            provider.name(),
+            model,
+            latency,
+            result.as_ref().map(|_| tokens),
+        );
+        state.accounting.record_call(&agent_id, latency, tokens);
+        match result {
+            Ok(completion) => return Ok((completion, i)),
+            Err(e) => failure = (provider.name(), model.as_str(), e),
+        }
+    }
+    Err(failure)
+}
+
+/// Reports a failed provider call and builds its JSON-RPC error, whose code
+/// the [`ServerError`] decides and whose `data` names the provider. Providers
+/// at their concurrency limit are not reported.
+fn provider_failure(
+    state: &AppState,
+    agent: &Agent,
+    model: &str,
+    provider: &str,
+    error: ServerError,
+    id: &Value,
+    locale: Locale,
+) -> JsonRpcError {
+    let mut failure = error.to_rpc_error(locale);
+    if let Some(data) = &mut failure.data {
+        data["provider"] = provider.into();
+    }
+    // A full provider is load, not a fault worth reporting
+    if matches!(error, ServerError::Busy(_)) {
+        return failure;
+    }
+    tracing::error!("AI processing error: {}", error);
+    let request_id = match id {
+        Value::String(id) => id.clone(),
+        id => id.to_string(),
+    };
+    state.reporter.report(
+        ErrorEvent::new("provider_failure", error.to_string())
+            .with_request_id(request_id)
+            .with_details(serde_json::json!({
+                "agent_id": agent.id,
+                "model": model,
+                "provider": provider,
+                "caller": auth::current_identity(),
+                "correlation_id": correlation::current_correlation_id(),
+            })),
+    );
+    failure
+}
+
+/// Reports a failed plugin or script like a failed provider call, with
+/// `kind` as the provider and the agent's model since none was asked.
+fn hook_failure(
+    state: &AppState,
+    agent: &Agent,
+    kind: &str,
+    error: ServerError,
+    id: &Value,
+    locale: Locale,
+) -> JsonRpcError {
+    provider_failure(state, agent, &agent.model, kind, error, id, locale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheConfig, ResponseCache};
+    use crate::coalesce::Coalescer;
+    use crate::load_shed::{LoadShedConfig, LoadShedder};
+    use crate::memories::Memories;
+    use crate::providers::{MockProvider, ProviderRegistry};
+    use crate::sessions::MemorySessionStore;
+    use serde_json::json;
+    use std::time::Duration;
+
+    /// What the mock answers to everything, which memory extraction reads as
+    /// a fact.
+    const FACTS: &str = r#"["The user holds 2 ETH."]"#;
+
+    /// A server answering with the mock, caching replies and remembering
+    /// facts with `memories`; callers with memories skip the cache.
+    fn state(memories: Memories) -> Arc<AppState> {
+        let mut providers = ProviderRegistry::new();
+        providers.register(Arc::new(MockProvider::new().with_reply(FACTS)));
+        Arc::new(AppState {
+            http_client: reqwest::Client::new().into(),
+            providers,
+            agents: Default::default(),
+            admin_token: None,
+            jwt: None,
+            oidc: None,
+            reporter: Default::default(),
+            shedder: Arc::new(LoadShedder::new(LoadShedConfig::from_env())),
+            sessions: Arc::new(MemorySessionStore::default()),
+            history: Default::default(),
+            limits: Default::default(),
+            server_tools: Default::default(),
+            in_flight: Default::default(),
+            jobs: Default::default(),
+            cache: ResponseCache::new(Some(CacheConfig {
+                ttl: Duration::from_secs(60),
+                max_entries: 16,
+            })),
+            coalescer: Coalescer::new(true),
+            quotas: Default::default(),
+            accounting: Default::default(),
+            guardrails: Default::default(),
+            plugins: Default::default(),
+            audit: None,
+            access_log: Default::default(),
+            metrics: Default::default(),
+            knowledge: Default::default(),
+            memories,
+            evals: Default::default(),
+            speech: Default::default(),
+        })
+    }
+
+    /// Calls `method` as alice of acme.
+    async fn call(state: &Arc<AppState>, method: &str, params: Value) -> Value {
+        call_as(state, ("alice", Some("acme")), method, params).await
+    }
+
+    /// Calls `method` as `subject` of `tenant`, without admin access.
+    async fn call_as(
+        state: &Arc<AppState>,
+        (subject, tenant): (&str, Option<&str>),
+        method: &str,
+        params: Value,
+    ) -> Value {
+        let request = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1,
+        }))
+        .unwrap();
+        let identity = Identity {
+            subject: subject.to_string(),
+            tenant: tenant.map(str::to_string),
+        };
+        let response = dispatch(state, request, Locale::En, Access::None);
+        let response = auth::with_identity(Some(identity), response).await;
+        serde_json::to_value(response.unwrap()).unwrap()
+    }
+
+    /// `process_text`, `tools/call` and `run_pipeline` params asking agent_001
+    /// about `text`, with `extra` params.
+    fn agent_calls(text: &str, extra: Value) -> [(&'static str, Value); 3] {
+        let mut params = json!({ "agent_id": "agent_001", "user_text": text });
+        let mut arguments = json!({ "user_text": text });
+        let mut pipeline = json!({ "steps": [{ "agent_id": "agent_001" }], "user_text": text });
+        for (name, value) in extra.as_object().unwrap() {
+            params[name] = value.clone();
+            arguments[name] = value.clone();
+            pipeline[name] = value.clone();
+        }
+        [
+            ("process_text", params),
+            (
+                "tools/call",
+                json!({ "name": "agent_001", "arguments": arguments }),
+            ),
+            ("run_pipeline", pipeline),
+        ]
+    }
+
+    /// The metadata of the agent's reply to an [`agent_calls`] call.
+    fn metadata(method: &str, reply: &Value) -> Value {
+        let result = &reply["result"];
+        let metadata = match method {
+            "tools/call" => &result["structuredContent"]["metadata"],
+            "run_pipeline" => &result["steps"][0]["metadata"],
+            _ => &result["metadata"],
+        };
+        assert!(metadata.is_object(), "{}", reply);
+        metadata.clone()
+    }
+
+    /// The facts remembered about alice, once extraction had time to run.
+    async fn remembered(state: &AppState) -> usize {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        state.memories.list("acme/alice").unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn no_store_calls_skip_the_cache() {
+        let state = state(Memories::default());
+        for (method, params) in agent_calls("gm", json!({ "no_store": true })) {
+            // Twice, so a cached or shared reply would show on the second
+            for _ in 0..2 {
+                let metadata = metadata(method, &call(&state, method, params.clone()).await);
+                assert_eq!(metadata["no_store"], true, "{}", method);
+                assert!(metadata["cache"].is_null(), "{}", method);
+            }
+        }
+
+        for (method, params) in agent_calls("gm", json!({})) {
+            call(&state, method, params.clone()).await;
+            let metadata = metadata(method, &call(&state, method, params).await);
+            assert_eq!(metadata["cache"], "hit", "{}", method);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_store_calls_skip_sessions_and_memories() {
+        let state = state(Memories::open(None).unwrap());
+        let session = sessions::session_key(Some("acme"), "s1").unwrap();
+        for (method, params) in agent_calls("gm", json!({ "no_store": true })) {
+            metadata(method, &call(&state, method, params).await);
+        }
+        // Pipeline steps have no sessions
+        for (method, params) in agent_calls("gm", json!({ "no_store": true, "session_id": "s1" }))
+            .into_iter()
+            .take(2)
+        {
+            metadata(method, &call(&state, method, params).await);
+        }
+        assert!(state.sessions.get(&session).await.unwrap().is_none());
+        assert_eq!(remembered(&state).await, 0);
+
+        let [(method, params), ..] = agent_calls("gm", json!({ "session_id": "s1" }));
+        call(&state, method, params).await;
+        assert_eq!(
+            state.sessions.get(&session).await.unwrap().unwrap().len(),
+            2
+        );
+        assert_eq!(remembered(&state).await, 1);
+    }
+
+    #[tokio::test]
+    async fn no_store_jobs_hand_out_their_result_once() {
+        let state = state(Memories::open(None).unwrap());
+        let params = json!({ "agent_id": "agent_001", "user_text": "gm", "no_store": true });
+        let job = call(&state, "submit_text", params).await;
+        let job_id = json!({ "job_id": job["result"]["job_id"] });
+        let result = loop {
+            let result = call(&state, "get_job_result", job_id.clone()).await;
+            if result["error"]["code"] != -32004 {
+                break result;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(result["result"]["metadata"]["no_store"], true, "{}", result);
+
+        let again = call(&state, "get_job_result", job_id.clone()).await;
+        assert!(again["error"].is_object(), "{}", again);
+        assert!(call(&state, "get_job_status", job_id).await["error"].is_object());
+        assert_eq!(remembered(&state).await, 0);
+    }
+
+    #[tokio::test]
+    async fn plugins_rewrite_and_answer_for_their_agents() {
+        let state = state(Memories::default());
+        let module = |stage: &str, reply: &str, data: &str| {
+            let path = std::env::temp_dir().join(format!("{}-{}.wat", stage, uuid::Uuid::new_v4()));
+            let wat = format!(
+                r#"(module
+                     (memory (export "memory") 1)
+                     (data (i32.const 0) "{data}")
+                     (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+                     (func (export "{stage}") (param i32 i32) (result i64) (i64.const {reply})))"#
+            );
+            std::fs::write(&path, wat).unwrap();
+            path
+        };
+        let configs = [
+            plugins::PluginConfig {
+                id: "tidy".into(),
+                path: module("post_process", "8", "withheld"),
+                agents: vec!["agent_002".into()],
+            },
+            plugins::PluginConfig {
+                id: "ping".into(),
+                path: module("handle", "4", "pong"),
+                agents: vec!["agent_001".into()],
+            },
+        ];
+        state.plugins.replace(state.plugins.load(&configs).unwrap());
+
+        let reply =
+            |agent: &str| json!({ "agent_id": agent, "user_text": "gm", "session_id": agent });
+        let rewritten = call(&state, "process_text", reply("agent_002")).await;
+        assert_eq!(
+            rewritten["result"]["reply_text"], "withheld",
+            "{}",
+            rewritten
+        );
+        assert_eq!(rewritten["result"]["metadata"]["provider"], "mock");
+        let untouched = call(&state, "process_text", reply("agent_003")).await;
+        assert_eq!(untouched["result"]["reply_text"], FACTS);
+
+        let handled = call(&state, "process_text", reply("agent_001")).await;
+        assert_eq!(handled["result"]["reply_text"], "pong", "{}", handled);
+        let metadata = &handled["result"]["metadata"];
+        assert_eq!(
+            (&metadata["provider"], &metadata["model"]),
+            (&json!("plugin"), &json!("ping"))
+        );
+        let session = sessions::session_key(Some("acme"), "agent_001").unwrap();
+        let transcript = state.sessions.get(&session).await.unwrap().unwrap();
+        assert_eq!(transcript[1].content, "pong");
+        for config in configs {
+            std::fs::remove_file(config.path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn agent_scripts_rewrite_and_answer() {
+        let state = state(Memories::default());
+        let create = |scripts: Value| {
+            let mut agent = serde_json::to_value(&*state.agents.get("agent_001").unwrap()).unwrap();
+            agent["id"] = json!("agent_scripted");
+            agent["scripts"] = scripts;
+            let request = serde_json::from_value(json!({
+                "jsonrpc": "2.0",
+                "method": "create_agent",
+                "params": agent,
+                "id": 1,
+            }))
+            .unwrap();
+            serde_json::to_value(handle_create_agent(&state, request, Locale::En)).unwrap()
+        };
+        let broken = create(json!({ "pre_request": "if {" }));
+        assert_eq!(broken["error"]["code"], -32602, "{}", broken);
+        create(json!({
+            "pre_request": r#"if text == "price?" { #{ reply: "Check the marketplace." } } else { text }"#,
+            "post_response": r#"reply + " (scripted)""#,
+        }));
+
+        let ask = |text: &str| json!({ "agent_id": "agent_scripted", "user_text": text });
+        let answered = call(&state, "process_text", ask("price?")).await;
+        let result = &answered["result"];
+        assert_eq!(
+            result["reply_text"], "Check the marketplace. (scripted)",
+            "{}",
+            answered
+        );
+        assert_eq!(result["metadata"]["provider"], "script");
+        let asked = call(&state, "process_text", ask("gm")).await;
+        assert_eq!(
+            asked["result"]["reply_text"],
+            format!("{} (scripted)", FACTS)
+        );
+        assert_eq!(asked["result"]["metadata"]["provider"], "mock");
+    }
+
+    #[tokio::test]
+    async fn transcripts_are_only_read_by_their_tenant_naming_them() {
+        let state = state(Memories::default());
+        let ask = json!({ "agent_id": "agent_001", "user_text": "gm", "session_id": "s1" });
+        call(&state, "process_text", ask).await;
+        let read = json!({ "uri": "transcript://s1" });
+        let own = call(&state, "resources/read", read.clone()).await;
+        assert!(
+            own["result"]["contents"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("gm"),
+            "{}",
+            own
+        );
+
+        for caller in [
+            ("alice", Some("acme")),
+            ("bob", Some("globex")),
+            ("eve", None),
+        ] {
+            let listed = call_as(&state, caller, "resources/list", json!({})).await;
+            let uris = listed["result"]["resources"].as_array().unwrap();
+            assert!(
+                uris.iter()
+                    .all(|r| !r["uri"].as_str().unwrap().starts_with("transcript://")),
+                "{}",
+                listed
+            );
+        }
+        for caller in [("bob", Some("globex")), ("eve", None)] {
+            let other = call_as(&state, caller, "resources/read", read.clone()).await;
+            assert_eq!(other["error"]["code"], -32002, "{}", other);
+        }
+    }
 }
@@ -0,0 +1,379 @@
+//! Dependency health checks served at `GET /healthz`, and the liveness and
+//! readiness probes `GET /livez` and `GET /readyz`.
+//!
+//! A check probes every configured AI provider with a cheap call (listing
+//! its models) and pings the session store, all at once, each giving up after
+//! [`PROBE_TIMEOUT`]. The report is cached for [`CACHE_TTL`] so orchestrators
+//! polling often don't spend the providers' rate limits; requests arriving
+//! while a check runs wait for it rather than starting their own.
+//!
+//! The endpoint answers `200` while the default provider and the session
+//! store are up and `503` otherwise, so bad credentials or a network problem
+//! show up before traffic fails. A failing fallback provider only makes the
+//! server `degraded`, since requests are still answered:
+//!
+//! ```json
+//! {
+//!   "status": "degraded",
+//!   "checked_at": "2026-10-15T09:30:00.123+00:00",
+//!   "dependencies": {
+//!     "provider:gemini": { "status": "down", "required": false, "latency_ms": 5001, "error": "Gemini API request timed out" },
+//!     "provider:groq": { "status": "up", "required": true, "latency_ms": 84 },
+//!     "sessions:redis": { "status": "up", "required": true, "latency_ms": 1 }
+//!   }
+//! }
+//! ```
+//!
+//! For Kubernetes, `GET /livez` answers `200` as long as the process serves
+//! HTTP at all, and `GET /readyz` answers `200` only once startup, including
+//! loading the configuration, has finished and the last check found the
+//! required dependencies up. A provider outage thus takes the replicas out
+//! of rotation, at most [`CACHE_TTL`] after it starts, without restarting
+//! them:
+//!
+//! ```json
+//! { "ready": false, "config_loaded": true, "down": ["provider:groq"] }
+//! ```
+//!
+//! Agents the [startup self-test](crate::self_test) found failing are
+//! reported `down` under `agent:<id>`, without being required.
+//!
+//! Health checks are exempt from load shedding and authentication.
+
+use crate::providers::ProviderRegistry;
+use crate::sessions::SessionStore;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long a report is reused before the dependencies are probed again.
+pub const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// How long each dependency gets to answer a probe.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State of one dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyStatus {
+    Up,
+    Down,
+}
+
+/// Outcome of probing one dependency.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Dependency {
+    pub status: DependencyStatus,
+    /// Whether the server is down while this dependency is
+    pub required: bool,
+    /// How long the probe took
+    pub latency_ms: u64,
+    /// Why the probe failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Overall state of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Every dependency is up
+    Ok,
+    /// Only optional dependencies, such as fallback providers, are down
+    Degraded,
+    /// A required dependency is down
+    Down,
+}
+
+/// Body of `GET /healthz`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    /// When the dependencies were probed, as an RFC 3339 timestamp
+    pub checked_at: String,
+    /// Probe outcomes by dependency, e.g. `provider:groq` or `sessions:redis`
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+impl HealthReport {
+    /// Sums up probe outcomes into a report.
+    pub fn new(dependencies: BTreeMap<String, Dependency>) -> Self {
+        let down = dependencies
+            .values()
+            .filter(|d| d.status == DependencyStatus::Down);
+        let status = match down.map(|d| d.required).max() {
+            None => HealthStatus::Ok,
+            Some(false) => HealthStatus::Degraded,
+            Some(true) => HealthStatus::Down,
+        };
+        Self {
+            status,
+            checked_at: chrono::Utc::now().to_rfc3339(),
+            dependencies,
+        }
+    }
+}
+
+/// Body of `GET /readyz`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Readiness {
+    /// Whether the server should receive traffic
+    pub ready: bool,
+    /// Whether startup, including loading the configuration, has finished
+    pub config_loaded: bool,
+    /// Required dependencies that are down
+    pub down: Vec<String>,
+}
+
+/// Probes the server's dependencies, caching the report. Cheap to clone.
+#[derive(Clone)]
+pub struct HealthChecker {
+    providers: ProviderRegistry,
+    sessions: Arc<dyn SessionStore>,
+    last: Arc<Mutex<Option<(Instant, HealthReport)>>>,
+    started: Arc<AtomicBool>,
+    /// Dependencies found down once and for all, such as failed agents
+    marked: Arc<std::sync::Mutex<BTreeMap<String, Dependency>>>,
+}
+
+impl HealthChecker {
+    /// Creates a checker for the given providers and session store.
+    pub fn new(providers: ProviderRegistry, sessions: Arc<dyn SessionStore>) -> Self {
+        Self {
+            providers,
+            sessions,
+            last: Arc::default(),
+            started: Arc::default(),
+            marked: Arc::default(),
+        }
+    }
+
+    /// Reports `name` down in every check from now on, as an optional
+    /// dependency that failed with `error`.
+    pub fn mark_down(&self, name: impl Into<String>, latency: Duration, error: String) {
+        let dependency = Dependency {
+            status: DependencyStatus::Down,
+            required: false,
+            latency_ms: latency.as_millis() as u64,
+            error: Some(error),
+        };
+        self.marked.lock().unwrap().insert(name.into(), dependency);
+    }
+
+    /// Records that startup has finished, so the server may become ready.
+    pub fn mark_started(&self) {
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the server should receive traffic, from the latest report.
+    pub async fn readiness(&self) -> Readiness {
+        let config_loaded = self.started.load(Ordering::Relaxed);
+        let down: Vec<String> = if config_loaded {
+            self.check()
+                .await
+                .dependencies
+                .into_iter()
+                .filter(|(_, d)| d.required && d.status == DependencyStatus::Down)
+                .map(|(name, _)| name)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Readiness {
+            ready: config_loaded && down.is_empty(),
+            config_loaded,
+            down,
+        }
+    }
+
+    /// The latest report, probing again if it is older than [`CACHE_TTL`].
+    pub async fn check(&self) -> HealthReport {
+        let mut last = self.last.lock().await;
+        if let Some((at, report)) = last.as_ref() {
+            if at.elapsed() < CACHE_TTL {
+                return report.clone();
+            }
+        }
+        let report = self.probe().await;
+        if report.status != HealthStatus::Ok {
+            tracing::warn!("Health check failed: {:?}", report.dependencies);
+        }
+        *last = Some((Instant::now(), report.clone()));
+        report
+    }
+
+    async fn probe(&self) -> HealthReport {
+        let default = self.providers.names().first().copied();
+        let providers = self.providers.names().into_iter().map(|name| {
+            let provider = self.providers.get(name);
+            async move {
+                let result = timed(async {
+                    match provider {
+                        Some(provider) => provider
+                            .probe(PROBE_TIMEOUT)
+                            .await
+                            .map_err(|e| e.to_string()),
+                        None => Err("not configured".to_string()),
+                    }
+                })
+                .await;
+                (
+                    format!("provider:{}", name),
+                    result.into_dependency(Some(name) == default),
+                )
+            }
+        });
+        let sessions = async {
+            let result = timed(async {
+                match tokio::time::timeout(PROBE_TIMEOUT, self.sessions.ping()).await {
+                    Ok(result) => result,
+                    Err(_) => Err("session store ping timed out".to_string()),
+                }
+            })
+            .await;
+            (
+                format!("sessions:{}", self.sessions.name()),
+                result.into_dependency(true),
+            )
+        };
+        let (mut dependencies, sessions) = tokio::join!(join_all(providers), sessions);
+        dependencies.push(sessions);
+        let marked = self.marked.lock().unwrap().clone();
+        HealthReport::new(dependencies.into_iter().chain(marked).collect())
+    }
+}
+
+struct Timed {
+    result: Result<(), String>,
+    latency: Duration,
+}
+
+impl Timed {
+    fn into_dependency(self, required: bool) -> Dependency {
+        Dependency {
+            status: match self.result {
+                Ok(()) => DependencyStatus::Up,
+                Err(_) => DependencyStatus::Down,
+            },
+            required,
+            latency_ms: self.latency.as_millis() as u64,
+            error: self.result.err(),
+        }
+    }
+}
+
+async fn timed(probe: impl Future<Output = Result<(), String>>) -> Timed {
+    let started = Instant::now();
+    let result = probe.await;
+    Timed {
+        result,
+        latency: started.elapsed(),
+    }
+}
+
+/// `GET /healthz` - reports the state of each dependency.
+pub async fn healthz_handler(State(health): State<HealthChecker>) -> Response {
+    let report = health.check().await;
+    let status = match report.status {
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        HealthStatus::Ok | HealthStatus::Degraded => StatusCode::OK,
+    };
+    (status, Json(report)).into_response()
+}
+
+/// `GET /livez` - answers as long as the process serves requests.
+pub async fn livez_handler() -> Response {
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "status": "alive" })),
+    )
+        .into_response()
+}
+
+/// `GET /readyz` - whether the server should receive traffic.
+pub async fn readyz_handler(State(health): State<HealthChecker>) -> Response {
+    let readiness = health.readiness().await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::memory::MemorySessionStore;
+
+    fn dependency(status: DependencyStatus, required: bool) -> Dependency {
+        Dependency {
+            status,
+            required,
+            latency_ms: 0,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn only_required_dependencies_take_the_server_down() {
+        let mut dependencies = BTreeMap::from([
+            (
+                "provider:groq".to_string(),
+                dependency(DependencyStatus::Up, true),
+            ),
+            (
+                "provider:gemini".to_string(),
+                dependency(DependencyStatus::Up, false),
+            ),
+        ]);
+        assert_eq!(
+            HealthReport::new(dependencies.clone()).status,
+            HealthStatus::Ok
+        );
+
+        dependencies.get_mut("provider:gemini").unwrap().status = DependencyStatus::Down;
+        assert_eq!(
+            HealthReport::new(dependencies.clone()).status,
+            HealthStatus::Degraded
+        );
+
+        dependencies.get_mut("provider:groq").unwrap().status = DependencyStatus::Down;
+        assert_eq!(HealthReport::new(dependencies).status, HealthStatus::Down);
+    }
+
+    #[tokio::test]
+    async fn reuses_the_report_until_it_expires() {
+        let health = HealthChecker::new(
+            ProviderRegistry::new(),
+            Arc::new(MemorySessionStore::default()),
+        );
+        let first = health.check().await;
+        assert_eq!(first.status, HealthStatus::Ok);
+        assert!(first.dependencies.contains_key("sessions:memory"));
+        assert_eq!(health.check().await.checked_at, first.checked_at);
+    }
+
+    #[tokio::test]
+    async fn becomes_ready_once_started() {
+        let health = HealthChecker::new(
+            ProviderRegistry::new(),
+            Arc::new(MemorySessionStore::default()),
+        );
+        let readiness = health.readiness().await;
+        assert!(!readiness.ready && !readiness.config_loaded);
+        health.mark_started();
+        assert!(health.readiness().await.ready);
+    }
+}
@@ -0,0 +1,168 @@
+//! Conversation history trimming.
+//!
+//! Long conversations eventually exceed the model's context window, and the
+//! provider rejects the request. Before a request is sent, [`HistoryPolicy`]
+//! keeps only the most recent messages of `conversation_history` that fit its
+//! message and token budgets, dropping the oldest first. The system prompt and
+//! the user's new text are always sent.
+//!
+//! # Environment Variables
+//!
+//! * `HISTORY_MAX_MESSAGES` - Optional. Most history messages sent (default: 40)
+//! * `HISTORY_MAX_TOKENS` - Optional. Estimated prompt tokens, including the
+//!   system prompt and user text, that the history may fill (default: 6000)
+//!
+//! Setting either to `0` disables that limit.
+
+use crate::models::Message;
+use crate::providers::{estimate_tokens, CompletionRequest};
+
+/// Limits on the conversation history sent to a provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryPolicy {
+    /// Most history messages to send, or `None` for no limit.
+    pub max_messages: Option<usize>,
+    /// Most estimated prompt tokens, or `None` for no limit.
+    pub max_tokens: Option<u32>,
+}
+
+/// History messages sent when `HISTORY_MAX_MESSAGES` is unset.
+pub const DEFAULT_MAX_MESSAGES: usize = 40;
+
+/// Prompt token budget when `HISTORY_MAX_TOKENS` is unset.
+pub const DEFAULT_MAX_TOKENS: u32 = 6000;
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self {
+            max_messages: Some(DEFAULT_MAX_MESSAGES),
+            max_tokens: Some(DEFAULT_MAX_TOKENS),
+        }
+    }
+}
+
+impl HistoryPolicy {
+    /// A policy that sends the whole history.
+    pub const UNLIMITED: Self = Self {
+        max_messages: None,
+        max_tokens: None,
+    };
+
+    /// Loads the budgets from the environment.
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            let value = std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+            match value {
+                Some(0) => None,
+                Some(n) => Some(n),
+                None => Some(default),
+            }
+        };
+        Self {
+            max_messages: var("HISTORY_MAX_MESSAGES", DEFAULT_MAX_MESSAGES as u64)
+                .map(|n| n as usize),
+            max_tokens: var("HISTORY_MAX_TOKENS", DEFAULT_MAX_TOKENS.into())
+                .map(|n| n.min(u32::MAX.into()) as u32),
+        }
+    }
+
+    /// Drops the oldest history messages of `request` until it fits the
+    /// budgets, and returns how many were dropped.
+    ///
+    /// A window that would start with an assistant reply also loses that
+    /// reply, so the kept history always opens with a user turn.
+    pub fn apply(&self, request: &mut CompletionRequest) -> usize {
+        let Some(history) = request.conversation_history.take() else {
+            return 0;
+        };
+        let prompt_tokens = estimate_tokens(&CompletionRequest {
+            conversation_history: None,
+            ..request.clone()
+        }) as u64;
+
+        let mut start = self
+            .max_messages
+            .map_or(0, |max| history.len().saturating_sub(max));
+        if let Some(max_tokens) = self.max_tokens {
+            let mut tokens =
+                prompt_tokens + history[start..].iter().map(message_tokens).sum::<u64>();
+            while start < history.len() && tokens > u64::from(max_tokens) {
+                tokens -= message_tokens(&history[start]);
+                start += 1;
+            }
+        }
+        if start > 0 {
+            while history.get(start).is_some_and(|m| m.role != "user") {
+                start += 1;
+            }
+        }
+
+        request.conversation_history = Some(history[start..].to_vec());
+        start
+    }
+}
+
+/// Estimated tokens of one message, at four characters per token.
+fn message_tokens(message: &Message) -> u64 {
+    message.content.chars().count().div_ceil(4) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::builtin_agents;
+    use std::sync::Arc;
+
+    fn request(history: Vec<Message>) -> CompletionRequest {
+        let mut agent = builtin_agents()[0].clone();
+        agent.system_prompt = "x".repeat(40);
+        CompletionRequest {
+            agent: Arc::new(agent),
+            model: "model".to_string(),
+            user_text: "y".repeat(40),
+            conversation_history: Some(history),
+            generation: Default::default(),
+            tools: Vec::new(),
+            timeout: None,
+            images: Vec::new(),
+        }
+    }
+
+    fn turns(n: usize) -> Vec<Message> {
+        (0..n)
+            .map(|i| Message {
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: format!("{:0>40}", i),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_messages_within_budget() {
+        // Message limit alone: 5 newest of 10, then the leading reply is dropped
+        let policy = HistoryPolicy {
+            max_messages: Some(5),
+            max_tokens: None,
+        };
+        let mut req = request(turns(10));
+        assert_eq!(policy.apply(&mut req), 6);
+        let history = req.conversation_history.unwrap();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[0].role, "user");
+        assert_eq!(history[3].content, format!("{:0>40}", 9));
+
+        // 20 tokens of prompt and 10 per message leave room for 3 messages
+        let policy = HistoryPolicy {
+            max_messages: None,
+            max_tokens: Some(50),
+        };
+        let mut req = request(turns(9));
+        assert_eq!(policy.apply(&mut req), 6);
+        assert_eq!(req.conversation_history.unwrap().len(), 3);
+
+        let mut req = request(turns(9));
+        assert_eq!(HistoryPolicy::UNLIMITED.apply(&mut req), 0);
+        assert_eq!(req.conversation_history.unwrap().len(), 9);
+    }
+}
@@ -0,0 +1,392 @@
+//! Localized user-facing messages.
+//!
+//! Error strings and the agent fallback reply are looked up in a small
+//! compiled-in catalog keyed by [`Message`]. The client's locale is taken from
+//! a `locale` request parameter when present, otherwise negotiated from the
+//! `Accept-Language` header, and falls back to English.
+//!
+//! Supported locales: `en`, `es`, `fr`, `de`.
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, Uri},
+};
+use std::convert::Infallible;
+
+/// A locale with a translated message catalog.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    /// English (default)
+    #[default]
+    En,
+    /// Spanish
+    Es,
+    /// French
+    Fr,
+    /// German
+    De,
+}
+
+impl Locale {
+    /// Parses a language tag such as `es`, `fr-CA` or `de_DE`.
+    ///
+    /// Only the primary language subtag is considered. Returns `None` for
+    /// languages without a catalog.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?;
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "es" => Some(Locale::Es),
+            "fr" => Some(Locale::Fr),
+            "de" => Some(Locale::De),
+            _ => None,
+        }
+    }
+
+    /// The primary language subtag, e.g. `"es"`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+            Locale::Fr => "fr",
+            Locale::De => "de",
+        }
+    }
+
+    /// Picks the supported locale with the highest `q` weight from an
+    /// `Accept-Language` header value.
+    ///
+    /// ```
+    /// # use mcp_server::i18n::Locale;
+    /// let locale = Locale::from_accept_language("ja, fr-CH;q=0.9, de;q=0.7");
+    /// assert_eq!(locale, Some(Locale::Fr));
+    /// ```
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut best: Option<(Locale, f32)> = None;
+        for entry in header.split(',') {
+            let mut parts = entry.split(';');
+            let Some(locale) = parts.next().and_then(Locale::parse) else {
+                continue;
+            };
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            // Earlier entries win ties
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((locale, q));
+            }
+        }
+        best.map(|(locale, _)| locale)
+    }
+
+    /// Resolves the locale of an HTTP request from its `?locale=` query
+    /// parameter or `Accept-Language` header.
+    pub fn from_request(uri: &Uri, headers: &HeaderMap) -> Self {
+        let from_query = uri.query().and_then(|query| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.strip_prefix("locale="))
+                .find_map(Locale::parse)
+        });
+        from_query
+            .or_else(|| {
+                headers
+                    .get(header::ACCEPT_LANGUAGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(Locale::from_accept_language)
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Extractor for the locale negotiated by [`Locale::from_request`].
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLocale(pub Locale);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestLocale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(RequestLocale(Locale::from_request(
+            &parts.uri,
+            &parts.headers,
+        )))
+    }
+}
+
+/// Keys into the message catalog.
+///
+/// Messages containing `{}` take one argument via [`Message::format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// A message is not valid JSON; takes the parse error
+    ParseError,
+    /// A message is not a JSON-RPC request; takes the reason
+    InvalidRequest,
+    /// The request's `jsonrpc` field is not `"2.0"`
+    InvalidVersion,
+    /// Unknown JSON-RPC method; takes the method name
+    MethodNotFound,
+    /// Params failed to deserialize; takes the parse error
+    InvalidParams,
+    /// `process_text` was called without params
+    MissingParams,
+    /// Unknown agent ID; takes the agent ID
+    AgentNotFound,
+    /// Unknown pipeline ID; takes the pipeline ID
+    PipelineNotFound,
+    /// Unknown or expired job ID; takes the job ID
+    JobNotFound,
+    /// The job has no result yet; takes the job ID
+    JobPending,
+    /// `submit_text` was called while the job queue is full
+    JobQueueFull,
+    /// Unknown MCP tool; takes the tool name
+    ToolNotFound,
+    /// Unknown MCP resource; takes the URI
+    ResourceNotFound,
+    /// Unknown prompt template; takes the prompt name
+    PromptNotFound,
+    /// A required prompt argument is missing; takes the argument name
+    MissingPromptArgument,
+    /// The requested model isn't served by the provider; takes the model name
+    UnknownModel,
+    /// An agent with this ID already exists; takes the agent ID
+    AgentExists,
+    /// Admin method called without the admin token
+    Unauthorized,
+    /// HTTP request without a valid bearer token while JWT auth is on
+    Unauthenticated,
+    /// An agent change could not be saved to the database
+    StorageFailed,
+    /// The AI provider call failed
+    ProcessingFailed,
+    /// The AI provider didn't answer within the timeout
+    ProviderTimeout,
+    /// The AI provider is at its concurrency limit
+    ProviderBusy,
+    /// The AI provider answered `429 Too Many Requests`
+    ProviderRateLimited,
+    /// The caller has used up a quota; takes the limit's name
+    QuotaExceeded,
+    /// The request spent its budget; takes the limit's name
+    BudgetExceeded,
+    /// The provider returned no reply text
+    EmptyReply,
+    /// The request was shed under load
+    Overloaded,
+    /// The request was aborted by `cancel_request`
+    RequestCancelled,
+}
+
+impl Message {
+    /// The message text in `locale`.
+    pub fn text(self, locale: Locale) -> &'static str {
+        use Locale::*;
+        use Message::*;
+        match (self, locale) {
+            (ParseError, En) => "Parse error: {}",
+            (ParseError, Es) => "Error de análisis: {}",
+            (ParseError, Fr) => "Erreur d'analyse : {}",
+            (ParseError, De) => "Parse-Fehler: {}",
+
+            (InvalidRequest, En) => "Invalid Request: {}",
+            (InvalidRequest, Es) => "Solicitud no válida: {}",
+            (InvalidRequest, Fr) => "Requête invalide : {}",
+            (InvalidRequest, De) => "Ungültige Anfrage: {}",
+
+            (InvalidVersion, En) => "Invalid Request: jsonrpc must be '2.0'",
+            (InvalidVersion, Es) => "Solicitud no válida: jsonrpc debe ser '2.0'",
+            (InvalidVersion, Fr) => "Requête invalide : jsonrpc doit valoir '2.0'",
+            (InvalidVersion, De) => "Ungültige Anfrage: jsonrpc muss '2.0' sein",
+
+            (MethodNotFound, En) => "Method not found: {}",
+            (MethodNotFound, Es) => "Método no encontrado: {}",
+            (MethodNotFound, Fr) => "Méthode introuvable : {}",
+            (MethodNotFound, De) => "Methode nicht gefunden: {}",
+
+            (InvalidParams, En) => "Invalid params: {}",
+            (InvalidParams, Es) => "Parámetros no válidos: {}",
+            (InvalidParams, Fr) => "Paramètres invalides : {}",
+            (InvalidParams, De) => "Ungültige Parameter: {}",
+
+            (MissingParams, En) => "Invalid params: agent_id and user_text are required",
+            (MissingParams, Es) => "Parámetros no válidos: agent_id y user_text son obligatorios",
+            (MissingParams, Fr) => "Paramètres invalides : agent_id et user_text sont obligatoires",
+            (MissingParams, De) => "Ungültige Parameter: agent_id und user_text sind erforderlich",
+
+            (AgentNotFound, En) => "Agent not found: {}",
+            (AgentNotFound, Es) => "Agente no encontrado: {}",
+            (AgentNotFound, Fr) => "Agent introuvable : {}",
+            (AgentNotFound, De) => "Agent nicht gefunden: {}",
+
+            (PipelineNotFound, En) => "Pipeline not found: {}",
+            (PipelineNotFound, Es) => "Canalización no encontrada: {}",
+            (PipelineNotFound, Fr) => "Pipeline introuvable : {}",
+            (PipelineNotFound, De) => "Pipeline nicht gefunden: {}",
+
+            (JobNotFound, En) => "Job not found: {}",
+            (JobNotFound, Es) => "Trabajo no encontrado: {}",
+            (JobNotFound, Fr) => "Tâche introuvable : {}",
+            (JobNotFound, De) => "Auftrag nicht gefunden: {}",
+
+            (JobPending, En) => "Job {} has not finished yet",
+            (JobPending, Es) => "El trabajo {} aún no ha terminado",
+            (JobPending, Fr) => "La tâche {} n'est pas encore terminée",
+            (JobPending, De) => "Auftrag {} ist noch nicht abgeschlossen",
+
+            (JobQueueFull, En) => "Too many jobs are queued, retry later",
+            (JobQueueFull, Es) => "Hay demasiados trabajos en cola, inténtalo más tarde",
+            (JobQueueFull, Fr) => "Trop de tâches en attente, réessayez plus tard",
+            (JobQueueFull, De) => {
+                "Zu viele Aufträge in der Warteschlange, bitte später erneut versuchen"
+            }
+
+            (ToolNotFound, En) => "Tool not found: {}",
+            (ToolNotFound, Es) => "Herramienta no encontrada: {}",
+            (ToolNotFound, Fr) => "Outil introuvable : {}",
+            (ToolNotFound, De) => "Werkzeug nicht gefunden: {}",
+
+            (ResourceNotFound, En) => "Resource not found: {}",
+            (ResourceNotFound, Es) => "Recurso no encontrado: {}",
+            (ResourceNotFound, Fr) => "Ressource introuvable : {}",
+            (ResourceNotFound, De) => "Ressource nicht gefunden: {}",
+
+            (PromptNotFound, En) => "Prompt not found: {}",
+            (PromptNotFound, Es) => "Plantilla no encontrada: {}",
+            (PromptNotFound, Fr) => "Modèle de prompt introuvable : {}",
+            (PromptNotFound, De) => "Prompt-Vorlage nicht gefunden: {}",
+
+            (MissingPromptArgument, En) => "Invalid params: missing prompt argument {}",
+            (MissingPromptArgument, Es) => "Parámetros no válidos: falta el argumento {}",
+            (MissingPromptArgument, Fr) => "Paramètres invalides : argument {} manquant",
+            (MissingPromptArgument, De) => "Ungültige Parameter: Argument {} fehlt",
+
+            (UnknownModel, En) => "Invalid params: unknown model {}",
+            (UnknownModel, Es) => "Parámetros no válidos: modelo desconocido {}",
+            (UnknownModel, Fr) => "Paramètres invalides : modèle inconnu {}",
+            (UnknownModel, De) => "Ungültige Parameter: unbekanntes Modell {}",
+
+            (AgentExists, En) => "Invalid params: agent {} already exists",
+            (AgentExists, Es) => "Parámetros no válidos: el agente {} ya existe",
+            (AgentExists, Fr) => "Paramètres invalides : l'agent {} existe déjà",
+            (AgentExists, De) => "Ungültige Parameter: Agent {} existiert bereits",
+
+            (Unauthorized, En) => "Unauthorized: admin token required",
+            (Unauthorized, Es) => "No autorizado: se requiere el token de administrador",
+            (Unauthorized, Fr) => "Non autorisé : jeton d'administration requis",
+            (Unauthorized, De) => "Nicht autorisiert: Admin-Token erforderlich",
+
+            (Unauthenticated, En) => "Unauthorized: a valid bearer token is required",
+            (Unauthenticated, Es) => "No autorizado: se requiere un token de portador válido",
+            (Unauthenticated, Fr) => "Non autorisé : un jeton porteur valide est requis",
+            (Unauthenticated, De) => "Nicht autorisiert: gültiges Bearer-Token erforderlich",
+
+            (StorageFailed, En) => "Internal error: failed to save the agent",
+            (StorageFailed, Es) => "Error interno: no se pudo guardar el agente",
+            (StorageFailed, Fr) => "Erreur interne : impossible d'enregistrer l'agent",
+            (StorageFailed, De) => "Interner Fehler: Agent konnte nicht gespeichert werden",
+
+            (ProcessingFailed, En) => "Internal error: Gemini API processing failed",
+            (ProcessingFailed, Es) => "Error interno: falló el procesamiento con la API de Gemini",
+            (ProcessingFailed, Fr) => "Erreur interne : échec du traitement par l'API Gemini",
+            (ProcessingFailed, De) => {
+                "Interner Fehler: Verarbeitung durch die Gemini-API fehlgeschlagen"
+            }
+
+            (ProviderTimeout, En) => "The AI provider did not answer in time",
+            (ProviderTimeout, Es) => "El proveedor de IA no respondió a tiempo",
+            (ProviderTimeout, Fr) => "Le fournisseur d'IA n'a pas répondu à temps",
+            (ProviderTimeout, De) => "Der KI-Anbieter hat nicht rechtzeitig geantwortet",
+
+            (ProviderBusy, En) => "The AI provider is busy, retry later",
+            (ProviderBusy, Es) => "El proveedor de IA está ocupado, inténtalo más tarde",
+            (ProviderBusy, Fr) => "Le fournisseur d'IA est occupé, réessayez plus tard",
+            (ProviderBusy, De) => "Der KI-Anbieter ist ausgelastet, bitte später erneut versuchen",
+
+            (ProviderRateLimited, En) => "The AI provider is rate limiting requests, retry later",
+            (ProviderRateLimited, Es) => {
+                "El proveedor de IA está limitando las solicitudes, inténtalo más tarde"
+            }
+            (ProviderRateLimited, Fr) => {
+                "Le fournisseur d'IA limite les requêtes, réessayez plus tard"
+            }
+            (ProviderRateLimited, De) => {
+                "Der KI-Anbieter drosselt die Anfragen, bitte später erneut versuchen"
+            }
+
+            (QuotaExceeded, En) => "Quota exceeded: {} limit reached",
+            (QuotaExceeded, Es) => "Cuota superada: se alcanzó el límite {}",
+            (QuotaExceeded, Fr) => "Quota dépassé : limite {} atteinte",
+            (QuotaExceeded, De) => "Kontingent überschritten: Limit {} erreicht",
+
+            (BudgetExceeded, En) => "Budget exceeded: {} reached",
+            (BudgetExceeded, Es) => "Presupuesto superado: se alcanzó {}",
+            (BudgetExceeded, Fr) => "Budget dépassé : {} atteint",
+            (BudgetExceeded, De) => "Budget überschritten: {} erreicht",
+
+            (EmptyReply, En) => "Sorry, I couldn't generate a response.",
+            (EmptyReply, Es) => "Lo siento, no pude generar una respuesta.",
+            (EmptyReply, Fr) => "Désolé, je n'ai pas pu générer de réponse.",
+            (EmptyReply, De) => "Entschuldigung, ich konnte keine Antwort erzeugen.",
+
+            (Overloaded, En) => "Server is overloaded, retry later",
+            (Overloaded, Es) => "El servidor está sobrecargado, inténtalo más tarde",
+            (Overloaded, Fr) => "Le serveur est surchargé, réessayez plus tard",
+            (Overloaded, De) => "Der Server ist überlastet, bitte später erneut versuchen",
+
+            (RequestCancelled, En) => "Request cancelled",
+            (RequestCancelled, Es) => "Solicitud cancelada",
+            (RequestCancelled, Fr) => "Requête annulée",
+            (RequestCancelled, De) => "Anfrage abgebrochen",
+        }
+    }
+
+    /// The message text in `locale` with its `{}` placeholder replaced by `arg`.
+    pub fn format(self, locale: Locale, arg: impl std::fmt::Display) -> String {
+        self.text(locale).replacen("{}", &arg.to_string(), 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_weighted_accept_language() {
+        assert_eq!(
+            Locale::from_accept_language("es-MX,es;q=0.9,en;q=0.8"),
+            Some(Locale::Es)
+        );
+        assert_eq!(
+            Locale::from_accept_language("en;q=0.5, de"),
+            Some(Locale::De)
+        );
+        assert_eq!(Locale::from_accept_language("fr;q=0, ja, *"), None);
+    }
+
+    #[test]
+    fn query_param_overrides_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, "de".parse().unwrap());
+        let uri: Uri = "/?id=7&locale=fr".parse().unwrap();
+        assert_eq!(Locale::from_request(&uri, &headers), Locale::Fr);
+        assert_eq!(
+            Locale::from_request(&"/".parse().unwrap(), &headers),
+            Locale::De
+        );
+        assert_eq!(
+            Locale::from_request(&"/".parse().unwrap(), &HeaderMap::new()),
+            Locale::En
+        );
+    }
+
+    #[test]
+    fn formats_placeholder() {
+        assert_eq!(
+            Message::AgentNotFound.format(Locale::Es, "robot"),
+            "Agente no encontrado: robot"
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Images attached to `process_text` requests, for vision-capable models.
+//!
+//! Each entry of `images` is either base64 `data`, optionally as a
+//! `data:image/png;base64,...` URL, or an http(s) `url` the server fetches:
+//!
+//! ```json
+//! { "agent_id": "agent_002", "user_text": "What does this transaction do?",
+//!   "images": [{ "data": "iVBORw0KGgo...", "mime_type": "image/png" },
+//!              { "url": "https://example.com/nft/42.webp" }] }
+//! ```
+//!
+//! Without a `mime_type`, the type is read from the data URL, the response's
+//! `Content-Type` or the image's first bytes. PNG, JPEG, WebP, HEIC and HEIF
+//! images are accepted, at most [`MAX_IMAGES`] per request and
+//! [`MAX_IMAGE_BYTES`] each. Inline images count towards the body limit
+//! (`MAX_BODY_BYTES`, see [`crate::limits`]), so larger ones are better sent
+//! by URL.
+//!
+//! Only providers that [accept images](crate::providers::LlmProvider::supports_images)
+//! get requests with images: Gemini does, Groq and Azure OpenAI don't, and a
+//! request with images the default provider can't take fails with invalid
+//! params. Images are not recorded in session transcripts, and requests with
+//! images are never answered from the response cache.
+
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// Most images one request carries.
+pub const MAX_IMAGES: usize = 8;
+
+/// Largest image, in bytes once decoded.
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Image types accepted.
+pub const SUPPORTED_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "image/heic",
+    "image/heif",
+];
+
+/// An image as given in the params: inline data or a URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageInput {
+    /// Base64 image bytes, or a base64 `data:` URL; give either this or `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// http(s) URL the server fetches the image from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Optional MIME type, e.g. `image/png`; detected when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl ImageInput {
+    /// Checks the number of images and that each gives either valid base64
+    /// `data` or an http(s) `url`, and a supported `mime_type` if any.
+    pub fn validate_all(images: &[ImageInput]) -> Result<(), String> {
+        if images.len() > MAX_IMAGES {
+            return Err(format!(
+                "images accepts at most {} images, got {}",
+                MAX_IMAGES,
+                images.len()
+            ));
+        }
+        for (i, image) in images.iter().enumerate() {
+            if let Some(mime_type) = &image.mime_type {
+                check_mime_type(mime_type).map_err(|e| format!("images[{}]: {}", i, e))?;
+            }
+            match (&image.data, &image.url) {
+                (Some(data), None) => {
+                    decode(data, image.mime_type.as_deref())
+                        .map_err(|e| format!("images[{}]: {}", i, e))?;
+                }
+                (None, Some(url)) => match reqwest::Url::parse(url) {
+                    Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {}
+                    _ => return Err(format!("images[{}]: url {} is not an http(s) URL", i, url)),
+                },
+                _ => return Err(format!("images[{}] must have either data or url", i)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An image ready to send to a provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// MIME type, one of [`SUPPORTED_MIME_TYPES`]
+    pub mime_type: String,
+    /// Base64 of the image bytes
+    pub data: String,
+}
+
+fn check_mime_type(mime_type: &str) -> Result<(), String> {
+    if SUPPORTED_MIME_TYPES.contains(&mime_type) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unsupported image type {}; use one of {}",
+            mime_type,
+            SUPPORTED_MIME_TYPES.join(", ")
+        ))
+    }
+}
+
+/// The type of an image from its first bytes, if it is a PNG, JPEG, WebP,
+/// HEIC or HEIF image.
+///
+/// ```
+/// # use mcp_server::images::sniff_mime_type;
+/// assert_eq!(sniff_mime_type(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+/// assert_eq!(sniff_mime_type(b"GIF89a"), None);
+/// ```
+pub fn sniff_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if brand.len() >= 4 => match &brand[..4] {
+            b"heic" | b"heix" | b"heim" | b"heis" => Some("image/heic"),
+            b"mif1" | b"msf1" => Some("image/heif"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Decodes base64 `data`, or a base64 `data:` URL, into an [`Image`] of
+/// `mime_type`, or the type the URL or bytes show.
+fn decode(data: &str, mime_type: Option<&str>) -> Result<Image, String> {
+    let (url_type, encoded) = match data.strip_prefix("data:") {
+        Some(url) => {
+            let (header, encoded) = url
+                .split_once(',')
+                .ok_or("data URL has no ',' before its data")?;
+            let url_type = header
+                .strip_suffix(";base64")
+                .ok_or("data URL must be base64")?;
+            (Some(url_type), encoded)
+        }
+        None => (None, data),
+    };
+    let encoded: String = encoded.split_whitespace().collect();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(&encoded)
+        .map_err(|e| format!("data is not valid base64: {}", e))?;
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(format!("images are limited to {} bytes", MAX_IMAGE_BYTES));
+    }
+    let mime_type = mime_type
+        .or(url_type.filter(|t| !t.is_empty()))
+        .or_else(|| sniff_mime_type(&bytes))
+        .ok_or("the image type could not be detected; set mime_type")?;
+    check_mime_type(mime_type)?;
+    Ok(Image {
+        mime_type: mime_type.to_string(),
+        data: encoded,
+    })
+}
+
+/// Fetches the image at `url`, of `mime_type` or the type its
+/// `Content-Type` or bytes show.
+async fn fetch(client: &HttpClient, url: &str, mime_type: Option<&str>) -> Result<Image, String> {
+    let _permit = client.acquire(url).await;
+    let mut response = client
+        .send_with_retry(client.get(url))
+        .await
+        .map_err(|e| format!("request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("answered {}", response.status()));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase());
+    let too_large = || format!("images are limited to {} bytes", MAX_IMAGE_BYTES);
+    if response
+        .content_length()
+        .is_some_and(|length| length as usize > MAX_IMAGE_BYTES)
+    {
+        return Err(too_large());
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("failed to read the body: {}", e))?
+    {
+        if bytes.len() + chunk.len() > MAX_IMAGE_BYTES {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let served = content_type.filter(|t| SUPPORTED_MIME_TYPES.contains(&t.as_str()));
+    let mime_type = mime_type
+        .map(str::to_string)
+        .or(served)
+        .or_else(|| sniff_mime_type(&bytes).map(str::to_string))
+        .ok_or("it is not a PNG, JPEG, WebP, HEIC or HEIF image")?;
+    Ok(Image {
+        mime_type,
+        data: base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+/// Turns validated `images` into images for the provider, fetching those
+/// given by URL.
+///
+/// Fails with invalid params naming the image that couldn't be read.
+pub async fn resolve(
+    client: &HttpClient,
+    images: Vec<ImageInput>,
+) -> Result<Vec<Image>, ServerError> {
+    let mut resolved = Vec::with_capacity(images.len());
+    for (i, image) in images.into_iter().enumerate() {
+        let mime_type = image.mime_type.as_deref();
+        let result = match (&image.data, &image.url) {
+            (Some(data), _) => decode(data, mime_type),
+            (None, Some(url)) => fetch(client, url, mime_type)
+                .await
+                .map_err(|e| format!("{}: {}", url, e)),
+            (None, None) => Err("no data or url".to_string()),
+        };
+        match result {
+            Ok(image) => resolved.push(image),
+            Err(e) => return Err(ServerError::InvalidParams(format!("images[{}]: {}", i, e))),
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_and_decodes_inline_images() {
+        let png = base64::engine::general_purpose::STANDARD.encode(b"\x89PNG\r\n\x1a\n\0\0");
+        let inline = |data: &str| ImageInput {
+            data: Some(data.to_string()),
+            url: None,
+            mime_type: None,
+        };
+        let image = decode(&png, None).unwrap();
+        assert_eq!(image.mime_type, "image/png");
+        assert_eq!(image.data, png);
+        let image = decode(&format!("data:image/webp;base64,{}", png), None).unwrap();
+        assert_eq!(image.mime_type, "image/webp");
+
+        assert!(ImageInput::validate_all(&[inline(&png)]).is_ok());
+        assert!(ImageInput::validate_all(&[inline("not base64!")]).is_err());
+        // Unknown bytes need a type
+        assert!(ImageInput::validate_all(&[inline("AAAA")]).is_err());
+        let url = ImageInput {
+            data: None,
+            url: Some("ftp://example.com/a.png".into()),
+            mime_type: None,
+        };
+        assert!(ImageInput::validate_all(&[url]).is_err());
+        let gif = ImageInput {
+            mime_type: Some("image/gif".into()),
+            ..inline(&png)
+        };
+        assert!(ImageInput::validate_all(&[gif]).is_err());
+        assert!(ImageInput::validate_all(&vec![inline(&png); MAX_IMAGES + 1]).is_err());
+    }
+}
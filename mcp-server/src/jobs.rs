@@ -0,0 +1,535 @@
+//! Asynchronous jobs.
+//!
+//! `submit_text` takes the same params as `process_text` but answers at once
+//! with a job ID; the agent runs in the background and clients poll
+//! `get_job_status` and `get_job_result`, so slow model calls don't hold an
+//! HTTP connection open. Jobs live in memory: they are lost on restart, and
+//! finished ones are dropped once they are older than the TTL. Jobs submitted
+//! with `no_store` are dropped as soon as their result has been fetched with
+//! `get_job_result`, or, with a `callback_url`, once they finish, the callback
+//! then being the only copy.
+//!
+//! Jobs report their [progress](crate::progress), streamed from
+//! `GET /jobs/{job_id}/events` while they run.
+//!
+//! A job submitted with a `callback_url` is also POSTed there when it
+//! finishes, as a [`CallbackPayload`] signed like this:
+//!
+//! * `X-Valet-Timestamp` - Unix time of the delivery, in seconds
+//! * `X-Valet-Signature` - `sha256=` and the hex HMAC-SHA256 of
+//!   `<timestamp>.<body>`, keyed with `JOB_CALLBACK_SECRET`
+//!
+//! Receivers should recompute the signature and reject old timestamps.
+//! Deliveries go through their own [`HttpClient`], which retries `429`s,
+//! `5xx`s and connection errors with backoff (see
+//! [`callback_client_config`]).
+//!
+//! # Environment Variables
+//!
+//! * `JOB_WORKERS` - Optional. Jobs run at the same time (default: 4)
+//! * `JOB_QUEUE_CAPACITY` - Optional. Most unfinished jobs; submissions past
+//!   it are rejected (default: 256)
+//! * `JOB_TTL_SECS` - Optional. How long finished jobs can be fetched
+//!   (default: 3600)
+//! * `JOB_CALLBACK_SECRET` - Optional. Key callbacks are signed with;
+//!   `callback_url` is rejected without it
+//! * `CALLBACK_HTTP_*` - Optional. Client settings for callback deliveries, as
+//!   in [`crate::http_client`]
+
+use crate::http_client::{HttpClient, HttpClientConfig, RetryPolicy};
+use crate::models::{JsonRpcError, ProcessTextParams};
+use crate::progress::{self, Reporter, Stage};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Limits of the job queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobConfig {
+    /// Jobs running at the same time
+    pub workers: usize,
+    /// Most jobs queued or running
+    pub capacity: usize,
+    /// How long finished jobs are kept
+    pub ttl: Duration,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            capacity: 256,
+            ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl JobConfig {
+    /// Reads the limits from the environment, falling back to the defaults.
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()?
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|&n| n > 0)
+        };
+        let defaults = Self::default();
+        Self {
+            workers: var("JOB_WORKERS").map_or(defaults.workers, |n| n as usize),
+            capacity: var("JOB_QUEUE_CAPACITY").map_or(defaults.capacity, |n| n as usize),
+            ttl: var("JOB_TTL_SECS").map_or(defaults.ttl, Duration::from_secs),
+        }
+    }
+}
+
+/// Where a job is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a worker
+    Queued,
+    /// The agent is answering
+    Running,
+    /// Finished with a result
+    Succeeded,
+    /// Finished with an error
+    Failed,
+}
+
+/// Status of a job, returned by `submit_text` and `get_job_status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    /// ID to poll the job by
+    pub job_id: String,
+    /// Where the job is
+    pub status: JobState,
+    /// Agent answering the job
+    pub agent_id: String,
+    /// When the job was submitted (RFC 3339)
+    pub created_at: String,
+    /// When a worker picked the job up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    /// When the job finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+}
+
+/// Parameters of the `submit_text` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubmitTextParams {
+    /// What to run, as for `process_text`
+    #[serde(flatten)]
+    pub process: ProcessTextParams,
+    /// Optional `http` or `https` URL the finished job is POSTed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+}
+
+/// Body of a callback request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CallbackPayload {
+    /// The finished job
+    pub job_id: String,
+    /// `succeeded` or `failed`
+    pub status: JobState,
+    /// Agent that answered
+    pub agent_id: String,
+    /// The `process_text` result, if the job succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The error, if the job failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+/// Parameters of `get_job_status` and `get_job_result`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobParams {
+    /// ID returned by `submit_text`
+    pub job_id: String,
+}
+
+struct Job {
+    status: JobStatus,
+    callback_url: Option<String>,
+    /// Whether the outcome is handed out once, then forgotten
+    no_store: bool,
+    /// The serialized `ProcessTextResult`, or the error the job failed with
+    outcome: Option<Result<Value, JsonRpcError>>,
+    finished: Option<Instant>,
+    progress: Reporter,
+}
+
+impl Job {
+    /// Records `outcome`, returning the callback to deliver, if any.
+    fn finish(
+        &mut self,
+        outcome: Result<Value, JsonRpcError>,
+    ) -> Option<(String, CallbackPayload)> {
+        self.status.status = match outcome {
+            Ok(_) => JobState::Succeeded,
+            Err(_) => JobState::Failed,
+        };
+        self.status.finished_at = Some(chrono::Utc::now().to_rfc3339());
+        self.finished = Some(Instant::now());
+        self.outcome = Some(outcome);
+        let url = self.callback_url.clone()?;
+        let (result, error) = match self.outcome.clone()? {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        let payload = CallbackPayload {
+            job_id: self.status.job_id.clone(),
+            status: self.status.status,
+            agent_id: self.status.agent_id.clone(),
+            result,
+            error,
+        };
+        Some((url, payload))
+    }
+}
+
+struct Queue {
+    config: JobConfig,
+    callbacks: Option<Callbacks>,
+    workers: Semaphore,
+    jobs: Mutex<HashMap<String, Job>>,
+    /// Jobs inside [`JobQueue::run`], including callback delivery
+    active: AtomicUsize,
+}
+
+/// Counts a job as active until dropped.
+struct Active<'a>(&'a AtomicUsize);
+
+impl<'a> Active<'a> {
+    fn new(active: &'a AtomicUsize) -> Self {
+        active.fetch_add(1, Ordering::SeqCst);
+        Self(active)
+    }
+}
+
+impl Drop for Active<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The jobs, by ID. Cheap to clone.
+#[derive(Clone)]
+pub struct JobQueue {
+    inner: Arc<Queue>,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new(JobConfig::default(), None)
+    }
+}
+
+impl JobQueue {
+    /// Creates an empty queue; without `callbacks`, jobs can't have a
+    /// `callback_url`.
+    pub fn new(config: JobConfig, callbacks: Option<Callbacks>) -> Self {
+        Self {
+            inner: Arc::new(Queue {
+                config,
+                callbacks,
+                workers: Semaphore::new(config.workers),
+                jobs: Mutex::new(HashMap::new()),
+                active: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Whether jobs may have a `callback_url`.
+    pub fn accepts_callbacks(&self) -> bool {
+        self.inner.callbacks.is_some()
+    }
+
+    /// Adds a queued job for `agent_id`, or returns `None` if the queue is at
+    /// capacity. Run it with [`run`](Self::run).
+    ///
+    /// The outcome of a `no_store` job is forgotten once it has been fetched,
+    /// or, with a `callback_url`, as soon as the job finishes.
+    pub fn submit(
+        &self,
+        agent_id: &str,
+        callback_url: Option<String>,
+        no_store: bool,
+    ) -> Option<JobStatus> {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        let ttl = self.inner.config.ttl;
+        jobs.retain(|_, job| job.finished.is_none_or(|at| at.elapsed() < ttl));
+        let unfinished = jobs.values().filter(|job| job.finished.is_none()).count();
+        if unfinished >= self.inner.config.capacity {
+            return None;
+        }
+        let status = JobStatus {
+            job_id: uuid::Uuid::new_v4().to_string(),
+            status: JobState::Queued,
+            agent_id: agent_id.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            started_at: None,
+            finished_at: None,
+        };
+        let progress = Reporter::new(status.job_id.clone());
+        progress.report(Stage::Queued, None);
+        jobs.insert(
+            status.job_id.clone(),
+            Job {
+                status: status.clone(),
+                callback_url,
+                no_store,
+                outcome: None,
+                finished: None,
+                progress,
+            },
+        );
+        Some(status)
+    }
+
+    /// Runs `work` as job `job_id` once a worker is free, keeps its outcome,
+    /// and delivers it to the job's callback URL, if any.
+    pub async fn run<F>(&self, job_id: &str, work: F)
+    where
+        F: Future<Output = Result<Value, JsonRpcError>>,
+    {
+        let _active = Active::new(&self.inner.active);
+        let worker = self.inner.workers.acquire().await;
+        let reporter = self
+            .progress(job_id)
+            .unwrap_or_else(|| Reporter::new(job_id));
+        self.update(job_id, |job| {
+            job.status.status = JobState::Running;
+            job.status.started_at = Some(chrono::Utc::now().to_rfc3339());
+        });
+        reporter.report(Stage::Running, None);
+        let outcome = progress::with_reporter(reporter.clone(), work).await;
+        let stage = match outcome {
+            Ok(_) => Stage::Succeeded,
+            Err(_) => Stage::Failed,
+        };
+        // Settled under one lock, so a poller can't take the outcome of a
+        // `no_store` job before its callback is built
+        let delivery = {
+            let mut jobs = self.inner.jobs.lock().unwrap();
+            let delivery = jobs.get_mut(job_id).and_then(|job| job.finish(outcome));
+            if delivery.is_some() && jobs.get(job_id).is_some_and(|job| job.no_store) {
+                jobs.remove(job_id);
+            }
+            delivery
+        };
+        reporter.report(stage, None);
+        drop(worker);
+
+        if let (Some(callbacks), Some((url, payload))) = (&self.inner.callbacks, delivery) {
+            callbacks.deliver(&url, &payload).await;
+        }
+    }
+
+    /// Waits until no job is queued, running or having its callback delivered.
+    pub async fn drained(&self) {
+        while self.inner.active.load(Ordering::SeqCst) > 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    fn update(&self, job_id: &str, change: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.inner.jobs.lock().unwrap().get_mut(job_id) {
+            change(job);
+        }
+    }
+
+    /// The status of job `job_id`, if it exists.
+    pub fn status(&self, job_id: &str) -> Option<JobStatus> {
+        let jobs = self.inner.jobs.lock().unwrap();
+        jobs.get(job_id).map(|job| job.status.clone())
+    }
+
+    /// The progress of job `job_id`, if it exists.
+    pub fn progress(&self, job_id: &str) -> Option<Reporter> {
+        let jobs = self.inner.jobs.lock().unwrap();
+        jobs.get(job_id).map(|job| job.progress.clone())
+    }
+
+    /// The status of job `job_id` and, once it finished, its outcome.
+    ///
+    /// A finished `no_store` job is forgotten as its outcome is returned.
+    pub fn outcome(
+        &self,
+        job_id: &str,
+    ) -> Option<(JobStatus, Option<Result<Value, JsonRpcError>>)> {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        let job = jobs.get(job_id)?;
+        if job.no_store && job.outcome.is_some() {
+            let job = jobs.remove(job_id)?;
+            return Some((job.status, job.outcome));
+        }
+        Some((job.status.clone(), job.outcome.clone()))
+    }
+}
+
+/// Client settings for callback deliveries: short timeouts and up to 5
+/// attempts, over `HttpClientConfig::default()`.
+pub fn callback_client_config() -> HttpClientConfig {
+    HttpClientConfig {
+        timeout: Some(Duration::from_secs(10)),
+        retry: RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        },
+        ..HttpClientConfig::default()
+    }
+}
+
+/// Sends signed callback requests.
+#[derive(Clone)]
+pub struct Callbacks {
+    client: HttpClient,
+    secret: String,
+}
+
+impl Callbacks {
+    /// Signs callbacks with `secret`, sending them with `client`, which
+    /// should retry (see [`callback_client_config`]).
+    pub fn new(client: HttpClient, secret: impl Into<String>) -> Self {
+        Self {
+            client,
+            secret: secret.into(),
+        }
+    }
+
+    /// Creates the sender if `JOB_CALLBACK_SECRET` is set.
+    pub fn from_env(client: HttpClient) -> Option<Self> {
+        let secret = std::env::var("JOB_CALLBACK_SECRET").ok()?;
+        (!secret.is_empty()).then(|| Self::new(client, secret))
+    }
+
+    /// POSTs `payload` to `url`; failures are logged, not returned.
+    async fn deliver(&self, url: &str, payload: &CallbackPayload) {
+        let body = serde_json::to_vec(payload).unwrap();
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let signature = sign(&self.secret, &timestamp, &body);
+        let request = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Valet-Timestamp", timestamp)
+            .header("X-Valet-Signature", format!("sha256={}", signature))
+            .body(body);
+        let _permit = self.client.acquire(url).await;
+        match self.client.send_with_retry(request).await {
+            Ok(response) if response.status().is_success() => {
+                tracing::info!("Delivered job {} to its callback", payload.job_id);
+            }
+            Ok(response) => tracing::warn!(
+                "Callback for job {} was answered {}",
+                payload.job_id,
+                response.status()
+            ),
+            Err(e) => tracing::warn!("Callback for job {} failed: {}", payload.job_id, e),
+        }
+    }
+}
+
+/// Checks that `url` can receive callbacks: an absolute `http` or `https` URL.
+pub fn validate_callback_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => Err(format!("callback_url {} is not an http(s) URL", url)),
+    }
+}
+
+/// The hex HMAC-SHA256 of `<timestamp>.<body>` keyed with `secret`.
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn runs_jobs_and_keeps_their_outcome() {
+        let queue = JobQueue::new(
+            JobConfig {
+                capacity: 1,
+                ..Default::default()
+            },
+            None,
+        );
+        let job = queue.submit("agent_001", None, false).unwrap();
+        assert_eq!(job.status, JobState::Queued);
+        assert!(
+            queue.submit("agent_001", None, false).is_none(),
+            "queue is full"
+        );
+
+        queue.run(&job.job_id, async { Ok(json!("done")) }).await;
+        queue.drained().await;
+        let (status, outcome) = queue.outcome(&job.job_id).unwrap();
+        assert_eq!(status.status, JobState::Succeeded);
+        assert!(status.finished_at.is_some());
+        assert_eq!(outcome.unwrap().unwrap(), json!("done"));
+        assert!(
+            queue.submit("agent_001", None, false).is_some(),
+            "finished jobs free a slot"
+        );
+        assert!(queue.status("unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn hands_out_no_store_outcomes_once() {
+        let queue = JobQueue::default();
+        let job = queue.submit("agent_001", None, true).unwrap();
+        queue.run(&job.job_id, async { Ok(json!("secret")) }).await;
+
+        let (status, outcome) = queue.outcome(&job.job_id).unwrap();
+        assert_eq!(status.status, JobState::Succeeded);
+        assert_eq!(outcome.unwrap().unwrap(), json!("secret"));
+        assert!(queue.outcome(&job.job_id).is_none());
+        assert!(queue.status(&job.job_id).is_none());
+
+        // Unfinished jobs keep their status until their outcome is taken
+        let job = queue.submit("agent_001", None, true).unwrap();
+        assert!(queue.outcome(&job.job_id).unwrap().1.is_none());
+        assert!(queue.status(&job.job_id).is_some());
+
+        // With a callback, the outcome is only delivered there
+        let url = Some("https://hooks.example.com/jobs".to_string());
+        let job = queue.submit("agent_001", url, true).unwrap();
+        queue.run(&job.job_id, async { Ok(json!("secret")) }).await;
+        assert!(queue.status(&job.job_id).is_none());
+    }
+
+    #[test]
+    fn signs_callbacks() {
+        assert_eq!(
+            sign("secret", "1700000000", br#"{"job_id":"j"}"#),
+            "7df6c290758fd19f14e0d864ab7636851c59780a58e91c8dad1b4090580dd379"
+        );
+        assert!(validate_callback_url("https://hooks.example.com/jobs").is_ok());
+        assert!(validate_callback_url("file:///etc/passwd").is_err());
+    }
+}
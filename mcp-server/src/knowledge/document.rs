@@ -0,0 +1,355 @@
+//! Documents of a knowledge base: their formats, how they are cut into
+//! chunks, and fetching them from a URL.
+
+use super::split_into_chunks;
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Largest document fetched from a URL, in bytes.
+pub const MAX_DOCUMENT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Largest `metadata` of a document, in bytes of JSON.
+pub const MAX_METADATA_BYTES: usize = 4096;
+
+/// How the text of a document is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    /// Plain text, cut between paragraphs
+    #[default]
+    Text,
+    /// Markdown, cut between sections; each chunk starts with its heading
+    Markdown,
+    /// HTML, reduced to its text first
+    Html,
+}
+
+impl DocumentFormat {
+    /// The format of a file or URL path by its extension, if it has a known
+    /// one.
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "txt" | "text" => Some(Self::Text),
+            "md" | "markdown" => Some(Self::Markdown),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    /// The format of a `Content-Type`, if it is a text type.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match mime.as_str() {
+            "text/markdown" | "text/x-markdown" => Some(Self::Markdown),
+            "text/html" | "application/xhtml+xml" => Some(Self::Html),
+            mime if mime.starts_with("text/") => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// Cuts `text` into chunks of at most `max_chars` characters.
+    pub fn split(self, text: &str, max_chars: usize) -> Vec<String> {
+        match self {
+            Self::Text => split_into_chunks(text, max_chars),
+            Self::Markdown => split_markdown(text, max_chars),
+            Self::Html => split_into_chunks(&html_to_text(text), max_chars),
+        }
+    }
+}
+
+/// A document stored in a knowledge base.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Document {
+    /// ID of the document, unique in its knowledge base
+    pub id: String,
+    /// How its text was written
+    #[serde(default)]
+    pub format: DocumentFormat,
+    /// URL or file it was read from, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Free-form metadata given at ingestion
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+    /// Number of chunks stored
+    #[serde(default)]
+    pub chunks: usize,
+    /// Characters of text ingested
+    #[serde(default)]
+    pub chars: usize,
+    /// When it was ingested (RFC 3339)
+    #[serde(default)]
+    pub ingested_at: String,
+}
+
+impl Document {
+    /// A document `id` written in `format`, not yet ingested.
+    pub fn new(id: impl Into<String>, format: DocumentFormat) -> Self {
+        Self {
+            id: id.into(),
+            format,
+            source: None,
+            metadata: Map::new(),
+            chunks: 0,
+            chars: 0,
+            ingested_at: String::new(),
+        }
+    }
+
+    /// Records the URL or file the document was read from.
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Attaches free-form `metadata`.
+    pub fn with_metadata(mut self, metadata: Map<String, Value>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Splits Markdown `text` into chunks of at most `max_chars` characters.
+///
+/// Every heading starts a new section, and sections are cut like
+/// [`split_into_chunks`] does. Chunks after the first of a section start with
+/// the section's heading, so each chunk says what it is about. Lines starting
+/// with `#` in fenced code blocks are not headings.
+///
+/// ```
+/// # use mcp_server::knowledge::document::split_markdown;
+/// let text = "# Gas\n\nPaid in ETH.\n\n# Bridges\n\nMove assets.\n\nTake minutes.";
+/// assert_eq!(
+///     split_markdown(text, 30),
+///     ["# Gas\n\nPaid in ETH.", "# Bridges\n\nMove assets.", "# Bridges\n\nTake minutes."]
+/// );
+/// ```
+pub fn split_markdown(text: &str, max_chars: usize) -> Vec<String> {
+    let mut sections: Vec<(Option<&str>, String)> = vec![(None, String::new())];
+    let mut in_fence = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if !in_fence && is_heading(trimmed) {
+            sections.push((Some(trimmed.trim_end()), String::new()));
+            continue;
+        }
+        let body = &mut sections.last_mut().expect("starts with a section").1;
+        body.push_str(line);
+        body.push('\n');
+    }
+
+    let mut chunks = Vec::new();
+    for (heading, body) in sections {
+        let Some(heading) = heading else {
+            chunks.extend(split_into_chunks(&body, max_chars));
+            continue;
+        };
+        // Leave room for the heading in every chunk, unless it takes most of it
+        let room = max_chars.saturating_sub(heading.chars().count() + 2);
+        if room < max_chars / 2 {
+            chunks.push(heading.to_string());
+            chunks.extend(split_into_chunks(&body, max_chars));
+            continue;
+        }
+        let pieces = split_into_chunks(&body, room);
+        if pieces.is_empty() {
+            chunks.push(heading.to_string());
+        }
+        chunks.extend(
+            pieces
+                .into_iter()
+                .map(|piece| format!("{}\n\n{}", heading, piece)),
+        );
+    }
+    chunks
+}
+
+/// Whether `line` is an ATX heading, `#` to `######` followed by a space.
+fn is_heading(line: &str) -> bool {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&level) && line[level..].starts_with([' ', '\t'])
+}
+
+/// The text of an HTML page: tags dropped, `script` and `style` elements
+/// removed, block elements on lines of their own and common entities decoded.
+///
+/// ```
+/// # use mcp_server::knowledge::document::html_to_text;
+/// let html = "<h1>Gas</h1><script>track()</script><p>Paid in <b>ETH</b> &amp; gwei.</p>";
+/// assert_eq!(html_to_text(html), "Gas\n\nPaid in ETH & gwei.");
+/// ```
+pub fn html_to_text(html: &str) -> String {
+    const BLOCKS: &[&str] = &[
+        "p",
+        "div",
+        "br",
+        "li",
+        "ul",
+        "ol",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "tr",
+        "table",
+        "section",
+        "article",
+        "header",
+        "footer",
+        "pre",
+        "blockquote",
+        "hr",
+    ];
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let closing = rest[1..end].starts_with('/');
+        let name = rest[1..end]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        rest = &rest[end + 1..];
+        if !closing && matches!(name.as_str(), "script" | "style") {
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(at) => &rest[at..],
+                None => "",
+            };
+        } else if BLOCKS.contains(&name.as_str()) {
+            text.push_str("\n\n");
+        }
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Checks that `url` can be fetched: `http` or `https` with a host.
+pub fn validate_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => Ok(()),
+        _ => Err(format!("url {} is not an http(s) URL", url)),
+    }
+}
+
+/// Fetches the document at `url`, returning its text and the format its
+/// `Content-Type` or path names, if any.
+///
+/// Fails on a non-text or error response, or one larger than
+/// [`MAX_DOCUMENT_BYTES`].
+pub async fn fetch(
+    client: &HttpClient,
+    url: &str,
+) -> Result<(String, Option<DocumentFormat>), ServerError> {
+    validate_url(url).map_err(ServerError::InvalidParams)?;
+    let failed = |reason: String| ServerError::InvalidParams(format!("{}: {}", url, reason));
+    let _permit = client.acquire(url).await;
+    let mut response = client
+        .send_with_retry(client.get(url))
+        .await
+        .map_err(|e| failed(format!("request failed: {}", e)))?;
+    if !response.status().is_success() {
+        return Err(failed(format!("answered {}", response.status())));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let format = match content_type.as_deref() {
+        Some(content_type) => match DocumentFormat::from_content_type(content_type) {
+            Some(DocumentFormat::Text) => {
+                DocumentFormat::from_extension(response.url().path()).or(Some(DocumentFormat::Text))
+            }
+            Some(format) => Some(format),
+            None => return Err(failed(format!("{} is not a text type", content_type))),
+        },
+        None => DocumentFormat::from_extension(response.url().path()),
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length as usize > MAX_DOCUMENT_BYTES)
+    {
+        return Err(failed(format!(
+            "documents are limited to {} bytes",
+            MAX_DOCUMENT_BYTES
+        )));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| failed(format!("failed to read the body: {}", e)))?
+    {
+        if body.len() + chunk.len() > MAX_DOCUMENT_BYTES {
+            return Err(failed(format!(
+                "documents are limited to {} bytes",
+                MAX_DOCUMENT_BYTES
+            )));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let text = String::from_utf8(body).map_err(|_| failed("the body is not UTF-8".into()))?;
+    Ok((text, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_headings_with_their_sections() {
+        let text =
+            "Intro line.\n\n## Fees\n\n```sh\n# not a heading\n```\n\n#hashtag\n\n## Empty\n";
+        assert_eq!(
+            split_markdown(text, 200),
+            [
+                "Intro line.",
+                "## Fees\n\n```sh\n# not a heading\n```\n\n#hashtag",
+                "## Empty"
+            ]
+        );
+        assert_eq!(
+            DocumentFormat::from_content_type("text/markdown; charset=utf-8"),
+            Some(DocumentFormat::Markdown)
+        );
+        assert_eq!(DocumentFormat::from_content_type("image/png"), None);
+        assert_eq!(
+            DocumentFormat::from_extension("/docs/Gas.MD"),
+            Some(DocumentFormat::Markdown)
+        );
+        assert!(validate_url("file:///etc/passwd").is_err());
+    }
+}
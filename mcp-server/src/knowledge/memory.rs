@@ -0,0 +1,123 @@
+//! In-memory vector store.
+//!
+//! Chunks live only as long as the process and are not shared between
+//! replicas. Searches compare the query with every chunk of the knowledge
+//! base, which is exact and fast enough for a few thousand chunks.
+
+use super::{Chunk, Document, ScoredChunk, VectorStore};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// The documents of one knowledge base and their chunks with their vectors.
+#[derive(Debug, Default)]
+struct Stored {
+    documents: HashMap<String, Document>,
+    chunks: Vec<(Chunk, Vec<f32>)>,
+}
+
+/// Chunks kept in process memory. Clones share the same chunks.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryVectorStore {
+    knowledge_bases: Arc<RwLock<HashMap<String, Stored>>>,
+}
+
+/// Cosine similarity of `a` and `b`, 0 if either is all zeros.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms =
+        a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norms > 0.0 {
+        dot / norms
+    } else {
+        0.0
+    }
+}
+
+#[async_trait]
+impl VectorStore for MemoryVectorStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn upsert(
+        &self,
+        knowledge_base: &str,
+        document: &Document,
+        chunks: Vec<(Chunk, Vec<f32>)>,
+    ) -> Result<(), String> {
+        let mut knowledge_bases = self.knowledge_bases.write().unwrap();
+        let stored = knowledge_bases
+            .entry(knowledge_base.to_string())
+            .or_default();
+        for (chunk, vector) in chunks {
+            if let Some((_, existing)) = stored.chunks.first() {
+                if existing.len() != vector.len() {
+                    return Err(format!(
+                        "Knowledge base {} holds {}-dimensional vectors, got {}",
+                        knowledge_base,
+                        existing.len(),
+                        vector.len()
+                    ));
+                }
+            }
+            stored
+                .chunks
+                .retain(|(c, _)| c.document != chunk.document || c.index != chunk.index);
+            stored.chunks.push((chunk, vector));
+        }
+        stored
+            .documents
+            .insert(document.id.clone(), document.clone());
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        knowledge_base: &str,
+        vector: &[f32],
+        limit: usize,
+    ) -> Result<Vec<ScoredChunk>, String> {
+        let knowledge_bases = self.knowledge_bases.read().unwrap();
+        let Some(stored) = knowledge_bases.get(knowledge_base) else {
+            return Ok(Vec::new());
+        };
+        let mut found: Vec<ScoredChunk> = stored
+            .chunks
+            .iter()
+            .map(|(chunk, stored)| ScoredChunk {
+                chunk: chunk.clone(),
+                score: cosine_similarity(vector, stored),
+            })
+            .collect();
+        found.sort_by(|a, b| b.score.total_cmp(&a.score));
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    async fn count(&self, knowledge_base: &str) -> Result<usize, String> {
+        let knowledge_bases = self.knowledge_bases.read().unwrap();
+        Ok(knowledge_bases
+            .get(knowledge_base)
+            .map_or(0, |stored| stored.chunks.len()))
+    }
+
+    async fn list_documents(&self, knowledge_base: &str) -> Result<Vec<Document>, String> {
+        let knowledge_bases = self.knowledge_bases.read().unwrap();
+        Ok(knowledge_bases
+            .get(knowledge_base)
+            .map(|stored| stored.documents.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn delete_document(&self, knowledge_base: &str, document: &str) -> Result<(), String> {
+        let mut knowledge_bases = self.knowledge_bases.write().unwrap();
+        if let Some(stored) = knowledge_bases.get_mut(knowledge_base) {
+            stored
+                .chunks
+                .retain(|(chunk, _)| chunk.document != document);
+            stored.documents.remove(document);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,627 @@
+//! Retrieval of curated documents into agent prompts.
+//!
+//! Documents are split into chunks of about `KNOWLEDGE_CHUNK_CHARS`
+//! characters, embedded like [`embed_text`](crate::embeddings) does, and kept
+//! in a named knowledge base of a [`VectorStore`]. An agent bound to a
+//! knowledge base (its `knowledge_base` field) has the chunks closest to each
+//! request's `user_text` added to its system prompt before the provider is
+//! called, and the reply's `metadata.sources` lists the documents they came
+//! from. The built-in Web3 Expert is bound to the `web3` knowledge base.
+//!
+//! Operators add documents with the admin `ingest_document` method, which
+//! replaces any earlier version of the document, remove them with
+//! `delete_document` and see them with `list_documents`. A document is given
+//! as `text` or fetched from a `url`, and is plain text, Markdown or HTML;
+//! Markdown is cut between sections, each chunk starting with its heading, and
+//! HTML is reduced to its text (see [`document`]). Free-form `metadata` is
+//! kept with the document. `KNOWLEDGE_DIR` loads a directory at startup
+//! instead: each subdirectory is a knowledge base, and each `.md`, `.txt` or
+//! `.html` file in it a document named after the file.
+//!
+//! Chunks are kept in process memory by default, searched exhaustively, which
+//! suits a few thousand chunks and is lost on restart; a [Qdrant](qdrant)
+//! server keeps them for every replica. Vectors of different embedding models
+//! can't be compared, so re-ingest every document after changing
+//! `KNOWLEDGE_PROVIDER`.
+//!
+//! Failing retrieval doesn't fail the request; the agent answers without the
+//! documents and a warning is logged.
+//!
+//! # Environment Variables
+//!
+//! * `KNOWLEDGE_STORE` - Optional. `memory` (default) or `qdrant`
+//! * `QDRANT_URL` - Qdrant REST URL, required for the `qdrant` store
+//!   (e.g. `http://127.0.0.1:6333`)
+//! * `QDRANT_API_KEY` - Optional. Sent in the `api-key` header
+//! * `QDRANT_COLLECTION_PREFIX` - Optional. Prefix of the collection of each
+//!   knowledge base (default: `valet_`)
+//! * `KNOWLEDGE_PROVIDER` - Optional. Backend computing the embeddings, or
+//!   `local` (default: the one `embed_text` uses without `provider`)
+//! * `KNOWLEDGE_TOP_K` - Optional. Chunks added to a prompt (default: 4)
+//! * `KNOWLEDGE_MIN_SCORE` - Optional. Lowest cosine similarity of a chunk
+//!   added to a prompt (default: 0)
+//! * `KNOWLEDGE_CHUNK_CHARS` - Optional. Longest chunk in characters
+//!   (default: 1000)
+//! * `KNOWLEDGE_DIR` - Optional. Directory of documents ingested at startup
+
+pub mod document;
+pub mod memory;
+pub mod qdrant;
+
+use crate::embeddings::{self, MAX_TEXTS};
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use crate::models::Agent;
+use crate::providers::{LlmProvider, ProviderRegistry};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::Arc;
+
+pub use document::{Document, DocumentFormat};
+pub use memory::MemoryVectorStore;
+pub use qdrant::QdrantVectorStore;
+
+/// Chunks added to a prompt when `KNOWLEDGE_TOP_K` is unset.
+pub const DEFAULT_TOP_K: usize = 4;
+
+/// Longest chunk when `KNOWLEDGE_CHUNK_CHARS` is unset.
+pub const DEFAULT_CHUNK_CHARS: usize = 1000;
+
+/// Introduces the retrieved chunks in the system prompt.
+pub const CONTEXT_INSTRUCTION: &str = "Use the following excerpts from the knowledge base \
+    when they are relevant to the question. If they don't answer it, say so rather than \
+    guessing.";
+
+/// Checks that `name` can name a knowledge base: 1 to 64 ASCII letters,
+/// digits, `_` or `-`.
+///
+/// ```
+/// # use mcp_server::knowledge::validate_knowledge_base;
+/// assert!(validate_knowledge_base("web3_docs").is_ok());
+/// assert!(validate_knowledge_base("../etc").is_err());
+/// ```
+pub fn validate_knowledge_base(name: &str) -> Result<(), String> {
+    let valid = (1..=64).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid knowledge base {:?}: use 1 to 64 letters, digits, '_' or '-'",
+            name
+        ))
+    }
+}
+
+/// A piece of a document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// ID of the document it was cut from
+    pub document: String,
+    /// Position in the document, from 0
+    pub index: usize,
+    /// The text
+    pub text: String,
+}
+
+/// A chunk found by a search, with its cosine similarity to the query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoredChunk {
+    /// The chunk
+    pub chunk: Chunk,
+    /// Cosine similarity, from -1 to 1
+    pub score: f32,
+}
+
+/// Splits `text` into chunks of at most `max_chars` characters.
+///
+/// Paragraphs are kept together while they fit; longer ones are cut between
+/// words, or mid-word for words longer than a chunk.
+///
+/// ```
+/// # use mcp_server::knowledge::split_into_chunks;
+/// let text = "Gas is paid in ETH.\n\nL2s batch transactions.\n\nBridges move assets.";
+/// assert_eq!(
+///     split_into_chunks(text, 45),
+///     ["Gas is paid in ETH.\n\nL2s batch transactions.", "Bridges move assets."]
+/// );
+/// ```
+pub fn split_into_chunks(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let flush = |current: &mut String, chunks: &mut Vec<String>| {
+        if !current.trim().is_empty() {
+            chunks.push(current.trim().to_string());
+        }
+        current.clear();
+    };
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        let joined = current.chars().count() + 2 + paragraph.chars().count();
+        if !current.is_empty() && joined > max_chars {
+            flush(&mut current, &mut chunks);
+        }
+        if paragraph.chars().count() <= max_chars {
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(paragraph);
+            continue;
+        }
+        // A paragraph too long for a chunk of its own is cut between words
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            while word.len() > max_chars {
+                flush(&mut current, &mut chunks);
+                chunks.push(word.drain(..max_chars).collect());
+            }
+            let length = current.chars().count();
+            if length > 0 && length + 1 + word.len() > max_chars {
+                flush(&mut current, &mut chunks);
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.extend(word);
+        }
+        flush(&mut current, &mut chunks);
+    }
+    flush(&mut current, &mut chunks);
+    chunks
+}
+
+/// The system prompt of `agent` followed by the retrieved `chunks`.
+pub fn with_context(agent: &Agent, chunks: &[ScoredChunk]) -> Agent {
+    let mut system_prompt = format!("{}\n\n{}", agent.system_prompt, CONTEXT_INSTRUCTION);
+    for (i, found) in chunks.iter().enumerate() {
+        system_prompt.push_str(&format!(
+            "\n\n[{}] {}:\n{}",
+            i + 1,
+            found.chunk.document,
+            found.chunk.text
+        ));
+    }
+    Agent {
+        system_prompt,
+        ..agent.clone()
+    }
+}
+
+/// A place to keep embedded chunks, by knowledge base.
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Short, stable name, e.g. `memory`.
+    fn name(&self) -> &'static str;
+
+    /// Stores `document` and its `chunks` with their vectors in
+    /// `knowledge_base`, creating it if needed.
+    async fn upsert(
+        &self,
+        knowledge_base: &str,
+        document: &Document,
+        chunks: Vec<(Chunk, Vec<f32>)>,
+    ) -> Result<(), String>;
+
+    /// The `limit` chunks of `knowledge_base` closest to `vector`, closest
+    /// first; none if the knowledge base doesn't exist.
+    async fn search(
+        &self,
+        knowledge_base: &str,
+        vector: &[f32],
+        limit: usize,
+    ) -> Result<Vec<ScoredChunk>, String>;
+
+    /// Number of chunks in `knowledge_base`, 0 if it doesn't exist.
+    async fn count(&self, knowledge_base: &str) -> Result<usize, String>;
+
+    /// The documents of `knowledge_base` by ID, none if it doesn't exist.
+    async fn list_documents(&self, knowledge_base: &str) -> Result<Vec<Document>, String>;
+
+    /// Removes `document` and its chunks from `knowledge_base`.
+    async fn delete_document(&self, knowledge_base: &str, document: &str) -> Result<(), String>;
+
+    /// Checks that the store can be reached.
+    async fn ping(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Knowledge bases and the embedding model filling them. Cheap to clone.
+#[derive(Clone)]
+pub struct Knowledge {
+    store: Arc<dyn VectorStore>,
+    /// Computes the vectors; `None` uses the local model
+    embedder: Option<Arc<dyn LlmProvider>>,
+    top_k: usize,
+    min_score: f32,
+    chunk_chars: usize,
+}
+
+impl Default for Knowledge {
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryVectorStore::default()))
+    }
+}
+
+impl Knowledge {
+    /// Knowledge bases in `store`, embedded with the local model.
+    pub fn new(store: Arc<dyn VectorStore>) -> Self {
+        Self {
+            store,
+            embedder: None,
+            top_k: DEFAULT_TOP_K,
+            min_score: 0.0,
+            chunk_chars: DEFAULT_CHUNK_CHARS,
+        }
+    }
+
+    /// Computes vectors with `embedder`, or the local model for `None`.
+    pub fn with_embedder(mut self, embedder: Option<Arc<dyn LlmProvider>>) -> Self {
+        self.embedder = embedder;
+        self
+    }
+
+    /// Adds the `top_k` closest chunks scoring at least `min_score` to prompts.
+    pub fn with_retrieval(mut self, top_k: usize, min_score: f32) -> Self {
+        self.top_k = top_k;
+        self.min_score = min_score;
+        self
+    }
+
+    /// Cuts documents into chunks of at most `chunk_chars` characters.
+    pub fn with_chunk_chars(mut self, chunk_chars: usize) -> Self {
+        self.chunk_chars = chunk_chars;
+        self
+    }
+
+    /// Sets up the store and embedding backend selected by the environment.
+    ///
+    /// Fails on an unknown store or backend, invalid numbers, or a missing
+    /// `QDRANT_URL`.
+    pub fn from_env(client: &HttpClient, providers: &ProviderRegistry) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let number = |name: &str| -> Result<Option<f64>, String> {
+            var(name)
+                .map(|v| {
+                    v.trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|n| *n >= 0.0)
+                        .ok_or_else(|| format!("{} must be a non-negative number", name))
+                })
+                .transpose()
+        };
+        let store: Arc<dyn VectorStore> = match var("KNOWLEDGE_STORE").as_deref() {
+            None | Some("memory") => Arc::new(MemoryVectorStore::default()),
+            Some("qdrant") => {
+                let url = var("QDRANT_URL")
+                    .ok_or("QDRANT_URL must be set when KNOWLEDGE_STORE=qdrant")?;
+                let mut store = QdrantVectorStore::new(client.clone(), url);
+                if let Some(api_key) = var("QDRANT_API_KEY") {
+                    store = store.with_api_key(api_key);
+                }
+                if let Some(prefix) = var("QDRANT_COLLECTION_PREFIX") {
+                    store = store.with_collection_prefix(prefix);
+                }
+                Arc::new(store)
+            }
+            Some(other) => {
+                return Err(format!(
+                    "KNOWLEDGE_STORE must be memory or qdrant, got {}",
+                    other
+                ))
+            }
+        };
+        let embedder = match var("KNOWLEDGE_PROVIDER").as_deref() {
+            Some(embeddings::LOCAL_PROVIDER) => None,
+            Some(name) => {
+                let provider = providers
+                    .get(name)
+                    .ok_or_else(|| format!("KNOWLEDGE_PROVIDER {} is not configured", name))?;
+                if !provider.supports_embeddings() {
+                    return Err(format!(
+                        "KNOWLEDGE_PROVIDER {} does not compute embeddings",
+                        name
+                    ));
+                }
+                Some(provider)
+            }
+            None => providers.embedding_provider(),
+        };
+        let top_k = number("KNOWLEDGE_TOP_K")?.map_or(DEFAULT_TOP_K, |n| n as usize);
+        let min_score = number("KNOWLEDGE_MIN_SCORE")?.unwrap_or(0.0) as f32;
+        let chunk_chars = match number("KNOWLEDGE_CHUNK_CHARS")? {
+            Some(n) if n < 1.0 => return Err("KNOWLEDGE_CHUNK_CHARS must be positive".into()),
+            Some(n) => n as usize,
+            None => DEFAULT_CHUNK_CHARS,
+        };
+        Ok(Self::new(store)
+            .with_embedder(embedder)
+            .with_retrieval(top_k, min_score)
+            .with_chunk_chars(chunk_chars))
+    }
+
+    /// Name of the vector store, e.g. `memory`.
+    pub fn store_name(&self) -> &'static str {
+        self.store.name()
+    }
+
+    /// Name of the backend computing the vectors, or `local`.
+    pub fn embedder_name(&self) -> &'static str {
+        self.embedder
+            .as_ref()
+            .map_or(embeddings::LOCAL_PROVIDER, |p| p.name())
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ServerError> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(MAX_TEXTS) {
+            let embedded = match &self.embedder {
+                Some(provider) => provider.embed(batch, None).await?,
+                None => embeddings::embed_local(batch),
+            };
+            vectors.extend(embedded.vectors);
+        }
+        Ok(vectors)
+    }
+
+    /// Splits `text` into chunks as its format says and stores them as
+    /// `document` of `knowledge_base`, replacing its earlier chunks. Returns
+    /// the document with its chunk count and ingestion time filled in.
+    ///
+    /// Fails with [`ServerError::InvalidParams`] if there is no text.
+    pub async fn ingest(
+        &self,
+        knowledge_base: &str,
+        mut document: Document,
+        text: &str,
+    ) -> Result<Document, ServerError> {
+        let texts = document.format.split(text, self.chunk_chars);
+        if texts.is_empty() {
+            return Err(ServerError::InvalidParams(format!(
+                "document {} has no text",
+                document.id
+            )));
+        }
+        let vectors = self.embed(&texts).await?;
+        self.delete(knowledge_base, &document.id).await?;
+        document.chunks = texts.len();
+        document.chars = text.chars().count();
+        document.ingested_at = chrono::Utc::now().to_rfc3339();
+        let chunks: Vec<_> = texts
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| Chunk {
+                document: document.id.clone(),
+                index,
+                text,
+            })
+            .zip(vectors)
+            .collect();
+        self.store
+            .upsert(knowledge_base, &document, chunks)
+            .await
+            .map_err(ServerError::Storage)?;
+        Ok(document)
+    }
+
+    /// The documents of `knowledge_base`, by ID.
+    pub async fn list(&self, knowledge_base: &str) -> Result<Vec<Document>, ServerError> {
+        let mut documents = self
+            .store
+            .list_documents(knowledge_base)
+            .await
+            .map_err(ServerError::Storage)?;
+        documents.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(documents)
+    }
+
+    /// Removes `document` from `knowledge_base`.
+    pub async fn delete(&self, knowledge_base: &str, document: &str) -> Result<(), ServerError> {
+        self.store
+            .delete_document(knowledge_base, document)
+            .await
+            .map_err(ServerError::Storage)
+    }
+
+    /// The chunks of `knowledge_base` to add to a prompt answering `query`.
+    ///
+    /// Empty knowledge bases are skipped without computing an embedding.
+    pub async fn retrieve(
+        &self,
+        knowledge_base: &str,
+        query: &str,
+    ) -> Result<Vec<ScoredChunk>, ServerError> {
+        if self.top_k == 0 || query.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let stored = self
+            .store
+            .count(knowledge_base)
+            .await
+            .map_err(ServerError::Storage)?;
+        if stored == 0 {
+            return Ok(Vec::new());
+        }
+        let vector = self.embed(&[query.to_string()]).await?.remove(0);
+        let mut found = self
+            .store
+            .search(knowledge_base, &vector, self.top_k)
+            .await
+            .map_err(ServerError::Storage)?;
+        found.retain(|chunk| chunk.score >= self.min_score);
+        Ok(found)
+    }
+
+    /// Ingests the documents under `dir`: each subdirectory is a knowledge
+    /// base, and each `.md`, `.txt` or `.html` file in it a document named
+    /// after the file. Returns the number of documents.
+    pub async fn load_dir(&self, dir: &Path) -> Result<usize, String> {
+        let read_dir = |dir: &Path| {
+            std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))
+        };
+        let mut documents = 0;
+        for entry in read_dir(dir)?.flatten() {
+            let path = entry.path();
+            let Some(knowledge_base) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if !path.is_dir() {
+                continue;
+            }
+            validate_knowledge_base(knowledge_base)?;
+            for file in read_dir(&path)?.flatten() {
+                let file = file.path();
+                let Some(name) = file.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                let Some(format) = DocumentFormat::from_extension(name) else {
+                    continue;
+                };
+                let text = std::fs::read_to_string(&file)
+                    .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+                let document = Document::new(name, format).with_source(file.display().to_string());
+                let document = self
+                    .ingest(knowledge_base, document, &text)
+                    .await
+                    .map_err(|e| format!("Failed to ingest {}: {}", file.display(), e))?;
+                tracing::debug!(
+                    "Ingested {} into {} as {} chunks",
+                    document.id,
+                    knowledge_base,
+                    document.chunks
+                );
+                documents += 1;
+            }
+        }
+        Ok(documents)
+    }
+}
+
+/// Parameters for the admin ingest_document JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IngestDocumentParams {
+    /// Knowledge base to add the document to
+    pub knowledge_base: String,
+    /// ID of the document; an earlier document with the same ID is replaced
+    pub document_id: String,
+    /// Text of the document; give either this or `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Optional http(s) URL the server fetches the document from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Optional format of the text; defaults to plain text for `text`, and to
+    /// the one the `Content-Type` or path of `url` names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<DocumentFormat>,
+    /// Optional free-form metadata kept with the document
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+}
+
+/// Parameters for the admin delete_document JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeleteDocumentParams {
+    /// Knowledge base holding the document
+    pub knowledge_base: String,
+    /// ID of the document to remove
+    pub document_id: String,
+}
+
+/// Parameters for the read-only admin list_documents JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListDocumentsParams {
+    /// Knowledge base to list
+    pub knowledge_base: String,
+}
+
+/// Result of the list_documents JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListDocumentsResult {
+    /// Knowledge base listed
+    pub knowledge_base: String,
+    /// Its documents, by ID
+    pub documents: Vec<Document>,
+}
+
+/// Result of the ingest_document and delete_document JSON-RPC methods.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentResult {
+    /// Knowledge base holding the document
+    pub knowledge_base: String,
+    /// ID of the document
+    pub document_id: String,
+    /// Chunks stored for the document; 0 after a deletion
+    pub chunks: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_long_paragraphs_between_words() {
+        let chunks = split_into_chunks("one two three four\n\nfive", 9);
+        assert_eq!(chunks, ["one two", "three", "four", "five"]);
+        assert_eq!(split_into_chunks("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert!(split_into_chunks(" \n\n ", 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn retrieves_the_closest_chunks() {
+        let knowledge = Knowledge::default().with_retrieval(1, 0.0);
+        let text = |id: &str| Document::new(id, DocumentFormat::Text);
+        knowledge
+            .ingest(
+                "web3",
+                text("gas.md"),
+                "Gas fees are paid in ETH on Ethereum mainnet.",
+            )
+            .await
+            .unwrap();
+        knowledge
+            .ingest(
+                "web3",
+                text("nft.md"),
+                "An NFT is a token with a unique ID.",
+            )
+            .await
+            .unwrap();
+
+        let found = knowledge
+            .retrieve("web3", "how much are gas fees?")
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].chunk.document, "gas.md");
+        assert!(knowledge
+            .retrieve("other", "gas fees")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let listed = knowledge.list("web3").await.unwrap();
+        let ids: Vec<_> = listed.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["gas.md", "nft.md"]);
+        assert_eq!(listed[0].chunks, 1);
+        assert!(knowledge.ingest("web3", text("empty"), " ").await.is_err());
+
+        knowledge.delete("web3", "gas.md").await.unwrap();
+        assert_eq!(knowledge.list("web3").await.unwrap().len(), 1);
+        let found = knowledge.retrieve("web3", "gas fees").await.unwrap();
+        assert_eq!(found[0].chunk.document, "nft.md");
+
+        let agent = with_context(&crate::agents::builtin_agents()[1], &found);
+        assert!(agent
+            .system_prompt
+            .ends_with("[1] nft.md:\nAn NFT is a token with a unique ID."));
+    }
+}
@@ -0,0 +1,301 @@
+//! Qdrant vector store.
+//!
+//! Each knowledge base is a collection named `{prefix}{knowledge_base}`,
+//! created with cosine distance on the first upsert. Points carry their chunk
+//! as payload, the first chunk of a document also the [`Document`] under
+//! `meta`, and their ID is derived from the document ID and chunk index, so
+//! re-ingesting a document overwrites its points. Calls go to Qdrant's
+//! REST API, so any Qdrant 1.x server or Qdrant Cloud cluster works.
+
+use super::{Chunk, Document, DocumentFormat, ScoredChunk, VectorStore};
+use crate::http_client::HttpClient;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// Collection prefix when `QDRANT_COLLECTION_PREFIX` is unset.
+pub const DEFAULT_COLLECTION_PREFIX: &str = "valet_";
+
+/// Points read per scroll request when listing documents.
+const SCROLL_PAGE: usize = 256;
+
+/// Chunks kept in a Qdrant server, shared by every replica using the same
+/// server and collection prefix.
+#[derive(Clone)]
+pub struct QdrantVectorStore {
+    client: HttpClient,
+    url: String,
+    api_key: Option<String>,
+    prefix: String,
+}
+
+impl QdrantVectorStore {
+    /// A store on the Qdrant server at `url`, e.g. `http://127.0.0.1:6333`.
+    pub fn new(client: HttpClient, url: impl Into<String>) -> Self {
+        Self {
+            client,
+            url: url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+            prefix: DEFAULT_COLLECTION_PREFIX.to_string(),
+        }
+    }
+
+    /// Authenticates with `api_key`.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Names collections `{prefix}{knowledge_base}`.
+    pub fn with_collection_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn collection_url(&self, knowledge_base: &str, path: &str) -> String {
+        format!(
+            "{}/collections/{}{}{}",
+            self.url, self.prefix, knowledge_base, path
+        )
+    }
+
+    /// Sends `request`, returning the `result` of the response, or `None` if
+    /// the collection doesn't exist.
+    async fn call(&self, request: reqwest::RequestBuilder) -> Result<Option<Value>, String> {
+        let request = match &self.api_key {
+            Some(api_key) => request.header("api-key", api_key),
+            None => request,
+        };
+        let response = self
+            .client
+            .send_with_retry(request)
+            .await
+            .map_err(|e| format!("Qdrant request failed: {}", e))?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read Qdrant response: {}", e))?;
+        if !status.is_success() {
+            return Err(format!("Qdrant returned {}: {}", status, body));
+        }
+        let body: Value = serde_json::from_str(&body)
+            .map_err(|e| format!("Failed to parse Qdrant response: {}", e))?;
+        Ok(Some(body["result"].clone()))
+    }
+
+    async fn ensure_collection(
+        &self,
+        knowledge_base: &str,
+        dimensions: usize,
+    ) -> Result<(), String> {
+        let url = self.collection_url(knowledge_base, "");
+        if self.call(self.client.get(&url)).await?.is_some() {
+            return Ok(());
+        }
+        let body = json!({ "vectors": { "size": dimensions, "distance": "Cosine" } });
+        self.call(self.client.put(&url).json(&body)).await?;
+        Ok(())
+    }
+}
+
+/// The point ID of chunk `index` of `document`, a UUID from their hash.
+///
+/// ```
+/// # use mcp_server::knowledge::qdrant::point_id;
+/// assert_eq!(point_id("gas.md", 0), point_id("gas.md", 0));
+/// assert_ne!(point_id("gas.md", 0), point_id("gas.md", 1));
+/// assert_eq!(point_id("gas.md", 0).len(), 36);
+/// ```
+pub fn point_id(document: &str, index: usize) -> String {
+    let digest = Sha256::digest(format!("{}\0{}", document, index));
+    uuid::Uuid::from_slice(&digest[..16])
+        .expect("16 bytes make a UUID")
+        .to_string()
+}
+
+/// Reads the chunks of a search `result`.
+pub fn parse_search_result(result: &Value) -> Result<Vec<ScoredChunk>, String> {
+    let points = result
+        .as_array()
+        .ok_or("Qdrant search result is not a list")?;
+    points
+        .iter()
+        .map(|point| {
+            let chunk: Chunk = serde_json::from_value(point["payload"].clone())
+                .map_err(|e| format!("Qdrant point without a chunk payload: {}", e))?;
+            let score = point["score"].as_f64().unwrap_or_default() as f32;
+            Ok(ScoredChunk { chunk, score })
+        })
+        .collect()
+}
+
+/// Reads the documents of a scroll `result` of first chunks, and the offset of
+/// the next page.
+///
+/// Points stored without a `meta` payload are listed with what their chunk
+/// says.
+pub fn parse_scroll_result(result: &Value) -> Result<(Vec<Document>, Option<Value>), String> {
+    let points = result["points"]
+        .as_array()
+        .ok_or("Qdrant scroll result has no points")?;
+    let documents = points
+        .iter()
+        .map(|point| {
+            let payload = &point["payload"];
+            if payload["meta"].is_object() {
+                return serde_json::from_value(payload["meta"].clone())
+                    .map_err(|e| format!("Qdrant point with an invalid document: {}", e));
+            }
+            let chunk: Chunk = serde_json::from_value(payload.clone())
+                .map_err(|e| format!("Qdrant point without a chunk payload: {}", e))?;
+            Ok(Document::new(chunk.document, DocumentFormat::Text))
+        })
+        .collect::<Result<_, String>>()?;
+    let next = Some(result["next_page_offset"].clone()).filter(|offset| !offset.is_null());
+    Ok((documents, next))
+}
+
+#[async_trait]
+impl VectorStore for QdrantVectorStore {
+    fn name(&self) -> &'static str {
+        "qdrant"
+    }
+
+    async fn upsert(
+        &self,
+        knowledge_base: &str,
+        document: &Document,
+        chunks: Vec<(Chunk, Vec<f32>)>,
+    ) -> Result<(), String> {
+        let Some((_, first)) = chunks.first() else {
+            return Ok(());
+        };
+        self.ensure_collection(knowledge_base, first.len()).await?;
+        let points: Vec<Value> = chunks
+            .into_iter()
+            .map(|(chunk, vector)| {
+                let id = point_id(&chunk.document, chunk.index);
+                let mut payload = json!(chunk);
+                if chunk.index == 0 {
+                    payload["meta"] = json!(document);
+                }
+                json!({ "id": id, "vector": vector, "payload": payload })
+            })
+            .collect();
+        let url = self.collection_url(knowledge_base, "/points?wait=true");
+        self.call(self.client.put(&url).json(&json!({ "points": points })))
+            .await?;
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        knowledge_base: &str,
+        vector: &[f32],
+        limit: usize,
+    ) -> Result<Vec<ScoredChunk>, String> {
+        let url = self.collection_url(knowledge_base, "/points/search");
+        let body = json!({ "vector": vector, "limit": limit, "with_payload": true });
+        match self.call(self.client.post(&url).json(&body)).await? {
+            Some(result) => parse_search_result(&result),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn count(&self, knowledge_base: &str) -> Result<usize, String> {
+        let url = self.collection_url(knowledge_base, "/points/count");
+        let result = self
+            .call(self.client.post(&url).json(&json!({ "exact": false })))
+            .await?;
+        Ok(result
+            .and_then(|result| result["count"].as_u64())
+            .unwrap_or_default() as usize)
+    }
+
+    async fn list_documents(&self, knowledge_base: &str) -> Result<Vec<Document>, String> {
+        let url = self.collection_url(knowledge_base, "/points/scroll");
+        let mut documents = Vec::new();
+        let mut offset = Value::Null;
+        loop {
+            let body = json!({
+                "filter": { "must": [{ "key": "index", "match": { "value": 0 } }] },
+                "limit": SCROLL_PAGE,
+                "offset": offset,
+                "with_payload": true,
+                "with_vector": false,
+            });
+            let Some(result) = self.call(self.client.post(&url).json(&body)).await? else {
+                return Ok(documents);
+            };
+            let (page, next) = parse_scroll_result(&result)?;
+            documents.extend(page);
+            match next {
+                Some(next) => offset = next,
+                None => return Ok(documents),
+            }
+        }
+    }
+
+    async fn delete_document(&self, knowledge_base: &str, document: &str) -> Result<(), String> {
+        let url = self.collection_url(knowledge_base, "/points/delete?wait=true");
+        let body = json!({
+            "filter": { "must": [{ "key": "document", "match": { "value": document } }] }
+        });
+        self.call(self.client.post(&url).json(&body)).await?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        self.call(self.client.get(format!("{}/collections", self.url)))
+            .await
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_search_results() {
+        let result = json!([
+            {
+                "id": point_id("gas.md", 0),
+                "version": 3,
+                "score": 0.82,
+                "payload": { "document": "gas.md", "index": 0, "text": "Gas is paid in ETH." }
+            }
+        ]);
+        let found = parse_search_result(&result).unwrap();
+        assert_eq!(found[0].chunk.text, "Gas is paid in ETH.");
+        assert!((found[0].score - 0.82).abs() < 1e-6);
+        assert!(parse_search_result(&json!([{ "score": 1.0 }])).is_err());
+
+        let result = json!({
+            "points": [
+                { "id": 1, "payload": { "document": "old.md", "index": 0, "text": "Old." } },
+                { "id": 2, "payload": {
+                    "document": "gas.md", "index": 0, "text": "Gas.",
+                    "meta": { "id": "gas.md", "format": "markdown", "chunks": 3 }
+                } }
+            ],
+            "next_page_offset": null
+        });
+        let (documents, next) = parse_scroll_result(&result).unwrap();
+        assert_eq!(documents[0].id, "old.md");
+        assert_eq!(documents[1].format, DocumentFormat::Markdown);
+        assert_eq!(documents[1].chunks, 3);
+        assert!(next.is_none());
+
+        let store = QdrantVectorStore::new(reqwest::Client::new().into(), "http://q:6333/");
+        assert_eq!(
+            store.collection_url("web3", "/points/search"),
+            "http://q:6333/collections/valet_web3/points/search"
+        );
+    }
+}
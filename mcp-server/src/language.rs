@@ -0,0 +1,381 @@
+//! The language of users' messages, and the language agents reply in.
+//!
+//! The language of each `user_text` is [detected](detect) from its script
+//! and, for Latin-script languages, from common words and letters; the
+//! result's `metadata.language` reports it:
+//!
+//! ```json
+//! "language": { "detected": "es", "confidence": 0.86, "reply": "es" }
+//! ```
+//!
+//! Which language agents reply in is otherwise up to the model, and is
+//! often that of their system prompt. `process_text`'s `reply_language`
+//! decides it: a code from [`LANGUAGES`] makes the agent always reply in
+//! that language, and `auto` in the language detected in the message, when
+//! detection is sure enough. The instruction is added to the system prompt,
+//! so the agent follows it even when the conversation history is in another
+//! language.
+
+use crate::models::Agent;
+use serde::{Deserialize, Serialize};
+
+/// Languages `reply_language` accepts and [`detect`] can find, as ISO 639-1
+/// codes with their English names.
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("de", "German"),
+    ("pt", "Portuguese"),
+    ("it", "Italian"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("tr", "Turkish"),
+    ("ru", "Russian"),
+    ("uk", "Ukrainian"),
+    ("el", "Greek"),
+    ("ar", "Arabic"),
+    ("fa", "Persian"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("th", "Thai"),
+    ("zh", "Chinese"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+];
+
+/// `reply_language` value asking for a reply in the detected language.
+pub const AUTO: &str = "auto";
+
+/// Least confidence at which `auto` trusts a detection.
+pub const MIN_CONFIDENCE: f32 = 0.5;
+
+/// Common words and distinctive letters of the Latin-script languages.
+const LATIN_MARKERS: &[(&str, &[&str], &str)] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "of", "to", "what", "how", "you", "i", "my", "it", "this",
+            "that", "with", "for", "can", "do", "does", "hello", "hi", "thanks", "please", "why",
+        ],
+        "",
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "de", "que", "y", "es", "en", "un", "una", "por", "para",
+            "cómo", "qué", "mi", "con", "hola", "gracias", "está", "son", "puedo", "del",
+        ],
+        "ñ¿¡",
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "de", "des", "et", "est", "un", "une", "je", "vous", "que", "qui",
+            "pour", "avec", "comment", "bonjour", "merci", "mon", "dans", "pas", "du", "c'est",
+        ],
+        "çœèêàù",
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "ich", "du", "sie", "nicht", "ein", "eine", "mit",
+            "wie", "was", "hallo", "danke", "bitte", "mein", "für", "zu", "auf", "den",
+        ],
+        "ßäöü",
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "as", "de", "que", "e", "é", "um", "uma", "para", "com", "não", "como",
+            "olá", "obrigado", "obrigada", "meu", "minha", "do", "da", "em", "você",
+        ],
+        "ãõ",
+    ),
+    (
+        "it",
+        &[
+            "il", "lo", "la", "gli", "di", "che", "e", "è", "un", "una", "per", "con", "non",
+            "come", "ciao", "grazie", "mio", "sono", "del", "della", "cosa", "perché",
+        ],
+        "ìò",
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "is", "ik", "je", "niet", "van", "wat", "hoe", "met", "voor",
+            "hallo", "dank", "mijn", "zijn", "op", "dat", "er",
+        ],
+        "ĳ",
+    ),
+    (
+        "pl",
+        &[
+            "i",
+            "w",
+            "nie",
+            "się",
+            "jest",
+            "na",
+            "że",
+            "to",
+            "jak",
+            "co",
+            "mój",
+            "czy",
+            "dla",
+            "cześć",
+            "dziękuję",
+            "proszę",
+            "mam",
+            "jestem",
+        ],
+        "ąęłśźżń",
+    ),
+    (
+        "tr",
+        &[
+            "ve",
+            "bir",
+            "bu",
+            "ne",
+            "için",
+            "değil",
+            "ile",
+            "nasıl",
+            "merhaba",
+            "teşekkürler",
+            "benim",
+            "var",
+            "mı",
+            "mi",
+            "çok",
+            "da",
+            "de",
+        ],
+        "ğış",
+    ),
+];
+
+/// The language found in a text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// ISO 639-1 code, one of [`LANGUAGES`]
+    pub code: &'static str,
+    /// How sure the detection is, from 0 to 1
+    pub confidence: f32,
+}
+
+/// The language of `text`, or `None` when it has no letters or its words
+/// don't tell.
+///
+/// ```
+/// # use mcp_server::language::detect;
+/// assert_eq!(detect("¿Cómo puedo comprar un NFT?").unwrap().code, "es");
+/// assert_eq!(detect("Что такое газ?").unwrap().code, "ru");
+/// assert!(detect("42 ETH").is_none());
+/// ```
+pub fn detect(text: &str) -> Option<Detection> {
+    // Letters per script, in the order of SCRIPTS
+    let mut counts = [0usize; SCRIPTS.len()];
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        if let Some(i) = SCRIPTS.iter().position(|(_, script)| script(c)) {
+            counts[i] += 1;
+        }
+    }
+    let letters: usize = counts.iter().sum();
+    if letters == 0 {
+        return None;
+    }
+    let (i, &count) = counts.iter().enumerate().max_by_key(|(_, count)| **count)?;
+    let share = count as f32 / letters as f32;
+    let code = match SCRIPTS[i].0 {
+        "latin" => {
+            return detect_latin(text).map(|d| Detection {
+                confidence: d.confidence * share,
+                ..d
+            })
+        }
+        // Japanese mixes kanji with kana
+        "han" if counts[index("kana")] > 0 => "ja",
+        "kana" => "ja",
+        "han" => "zh",
+        "cyrillic" if text.chars().any(|c| "іїєґІЇЄҐ".contains(c)) => "uk",
+        "cyrillic" => "ru",
+        "arabic" if text.chars().any(|c| "پچژگ".contains(c)) => "fa",
+        "arabic" => "ar",
+        script => script,
+    };
+    let confidence = if code == "ja" {
+        (counts[index("han")] + counts[index("kana")]) as f32 / letters as f32
+    } else {
+        share
+    };
+    Some(Detection { code, confidence })
+}
+
+/// A script's name and whether a letter belongs to it.
+type Script = (&'static str, fn(char) -> bool);
+
+/// Scripts told apart by [`detect`]; those named after a language code are
+/// only used by that language.
+const SCRIPTS: &[Script] = &[
+    ("latin", |c| {
+        c.is_ascii_alphabetic() || ('\u{c0}'..='\u{24f}').contains(&c)
+    }),
+    ("cyrillic", |c| ('\u{400}'..='\u{4ff}').contains(&c)),
+    ("el", |c| ('\u{370}'..='\u{3ff}').contains(&c)),
+    ("arabic", |c| ('\u{600}'..='\u{6ff}').contains(&c)),
+    ("he", |c| ('\u{590}'..='\u{5ff}').contains(&c)),
+    ("hi", |c| ('\u{900}'..='\u{97f}').contains(&c)),
+    ("th", |c| ('\u{e00}'..='\u{e7f}').contains(&c)),
+    ("ko", |c| {
+        ('\u{ac00}'..='\u{d7af}').contains(&c) || ('\u{1100}'..='\u{11ff}').contains(&c)
+    }),
+    ("kana", |c| ('\u{3040}'..='\u{30ff}').contains(&c)),
+    ("han", |c| {
+        ('\u{4e00}'..='\u{9fff}').contains(&c) || ('\u{3400}'..='\u{4dbf}').contains(&c)
+    }),
+];
+
+fn index(script: &str) -> usize {
+    SCRIPTS
+        .iter()
+        .position(|(name, _)| *name == script)
+        .expect("known script")
+}
+
+/// Scores each Latin-script language by its common words and letters in
+/// `text`; the best wins if no other ties it.
+fn detect_latin(text: &str) -> Option<Detection> {
+    let lowercase = text.to_lowercase();
+    let words: Vec<&str> = lowercase
+        .split(|c: char| !c.is_alphabetic() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .collect();
+    let scores: Vec<(&'static str, usize)> = LATIN_MARKERS
+        .iter()
+        .map(|(code, common, letters)| {
+            let words = words.iter().filter(|w| common.contains(w)).count();
+            let letters = lowercase.chars().filter(|c| letters.contains(*c)).count();
+            (*code, words + 2 * letters)
+        })
+        .collect();
+    let total: usize = scores.iter().map(|(_, score)| score).sum();
+    let &(code, best) = scores.iter().max_by_key(|(_, score)| *score)?;
+    if best == 0 || scores.iter().filter(|(_, score)| *score == best).count() > 1 {
+        return None;
+    }
+    Some(Detection {
+        code,
+        confidence: best as f32 / total as f32,
+    })
+}
+
+/// The English name of the language with `code`, if it is one of
+/// [`LANGUAGES`].
+pub fn name(code: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, name)| *name)
+}
+
+/// Checks a `reply_language`: [`AUTO`] or one of [`LANGUAGES`].
+pub fn validate_reply_language(reply_language: &str) -> Result<(), String> {
+    if reply_language == AUTO || name(reply_language).is_some() {
+        return Ok(());
+    }
+    let codes: Vec<&str> = LANGUAGES.iter().map(|(code, _)| *code).collect();
+    Err(format!(
+        "reply_language {} must be auto or one of {}",
+        reply_language,
+        codes.join(", ")
+    ))
+}
+
+/// The language of a request, reported in the result metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageMetadata {
+    /// Language detected in `user_text`; absent when it couldn't be told
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected: Option<String>,
+    /// How sure the detection is, from 0 to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Language the agent was told to reply in, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply: Option<String>,
+}
+
+/// Detects the language of `user_text` and works out the language to reply
+/// in from `reply_language`, which must be valid.
+pub fn resolve(user_text: &str, reply_language: Option<&str>) -> LanguageMetadata {
+    let detection = detect(user_text);
+    let reply = match reply_language {
+        Some(AUTO) => detection
+            .filter(|d| d.confidence >= MIN_CONFIDENCE)
+            .map(|d| d.code.to_string()),
+        other => other.map(str::to_string),
+    };
+    LanguageMetadata {
+        detected: detection.map(|d| d.code.to_string()),
+        // Two decimals are plenty, and keep the JSON short
+        confidence: detection.map(|d| (d.confidence * 100.0).round() / 100.0),
+        reply,
+    }
+}
+
+/// The system prompt of `agent` followed by the instruction to reply in the
+/// language with `code`.
+pub fn with_reply_language(agent: &Agent, code: &str) -> Agent {
+    let name = name(code).unwrap_or(code);
+    Agent {
+        system_prompt: format!(
+            "{}\n\nAlways reply in {}, whatever language the user or the \
+             conversation so far is in.",
+            agent.system_prompt, name
+        ),
+        ..agent.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_languages_and_resolves_reply_languages() {
+        let cases = [
+            ("What is the gas fee for this transaction?", "en"),
+            ("Bonjour, comment est-ce que je peux créer un NFT ?", "fr"),
+            ("Wie hoch ist die Gebühr für diese Transaktion?", "de"),
+            ("Olá, não consigo ver o meu saldo", "pt"),
+            ("Ciao, come posso vedere il mio saldo?", "it"),
+            ("Hoe kan ik mijn saldo zien?", "nl"),
+            ("Cześć, jak sprawdzić saldo?", "pl"),
+            ("Merhaba, bakiyemi nasıl görebilirim?", "tr"),
+            ("Як перевірити баланс гаманця?", "uk"),
+            ("ガス代とは何ですか？", "ja"),
+            ("什么是燃料费？", "zh"),
+            ("가스비가 뭐예요?", "ko"),
+            ("ما هي رسوم الغاز؟", "ar"),
+        ];
+        for (text, code) in cases {
+            assert_eq!(detect(text).map(|d| d.code), Some(code), "{}", text);
+        }
+        assert!(detect("").is_none());
+        assert!(detect("NFT").is_none());
+
+        let resolved = resolve("¿Qué es el gas?", Some(AUTO));
+        assert_eq!(resolved.detected.as_deref(), Some("es"));
+        assert_eq!(resolved.reply.as_deref(), Some("es"));
+        assert_eq!(
+            resolve("What is gas?", Some("de")).reply.as_deref(),
+            Some("de")
+        );
+        assert_eq!(resolve("NFT", Some(AUTO)).reply, None);
+        assert!(validate_reply_language("auto").is_ok());
+        assert!(validate_reply_language("xx").is_err());
+    }
+}
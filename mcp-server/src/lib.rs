@@ -0,0 +1,161 @@
+//! Library half of the MCP server.
+//!
+//! The binary in `main.rs` only wires these modules into an Axum router. Keeping
+//! them in a library lets the benchmarks under `benches/` exercise the same
+//! serialization and request-building code the server runs.
+
+pub mod access_log;
+pub mod accounting;
+pub mod agent_db;
+pub mod agents;
+pub mod attachments;
+pub mod audit;
+pub mod auth;
+pub mod bench;
+pub mod budget;
+pub mod cache;
+pub mod cancellation;
+pub mod chat;
+pub mod cli;
+pub mod coalesce;
+pub mod config;
+pub mod correlation;
+pub mod embeddings;
+pub mod error;
+pub mod error_report;
+pub mod evals;
+pub mod fixtures;
+pub mod guardrails;
+pub mod handlers;
+pub mod health;
+pub mod history;
+pub mod http_client;
+pub mod i18n;
+pub mod images;
+pub mod jobs;
+pub mod knowledge;
+pub mod language;
+pub mod limits;
+pub mod load_shed;
+pub mod log_file;
+pub mod memories;
+pub mod metrics;
+pub mod models;
+pub mod oidc;
+pub mod openrpc;
+pub mod pipelines;
+pub mod plugins;
+pub mod progress;
+pub mod prompt_guard;
+pub mod prompt_versions;
+pub mod prompts;
+pub mod providers;
+pub mod quota;
+pub mod redact;
+pub mod resources;
+pub mod scheduler;
+pub mod schemas;
+pub mod scripts;
+pub mod self_test;
+pub mod server_tools;
+pub mod sessions;
+pub mod shutdown;
+pub mod speech;
+pub mod stdio;
+pub mod style;
+pub mod tasks;
+pub mod tls;
+pub mod tools;
+pub mod voice;
+
+use access_log::AccessLog;
+use accounting::Accounting;
+use agents::AgentStore;
+use audit::AuditLog;
+use auth::JwtVerifier;
+use cache::ResponseCache;
+use cancellation::InFlight;
+use coalesce::Coalescer;
+use error_report::ErrorReporter;
+use evals::Evals;
+use guardrails::Guardrails;
+use history::HistoryPolicy;
+use http_client::HttpClient;
+use jobs::JobQueue;
+use knowledge::Knowledge;
+use limits::RequestLimits;
+use load_shed::LoadShedder;
+use memories::Memories;
+use metrics::Metrics;
+use models::ProcessTextResult;
+use oidc::OidcVerifier;
+use plugins::Plugins;
+use providers::ProviderRegistry;
+use quota::QuotaTracker;
+use server_tools::ServerTools;
+use sessions::SessionStore;
+use speech::Speech;
+use std::sync::Arc;
+
+/// Application state shared across all request handlers.
+///
+/// This struct is wrapped in an `Arc` and cloned for each request handler,
+/// providing thread-safe access to shared resources.
+#[derive(Clone)]
+pub struct AppState {
+    /// Shared, pooled HTTP client, also used by the AI providers.
+    pub http_client: HttpClient,
+    /// Configured AI backends (Groq, Gemini, ...).
+    pub providers: ProviderRegistry,
+    /// The agents, changed by config reloads and the admin methods.
+    pub agents: AgentStore,
+    /// Bearer token for the admin JSON-RPC methods; they are disabled without it.
+    pub admin_token: Option<String>,
+    /// Verifies the bearer tokens of HTTP requests; they are not required without it.
+    pub jwt: Option<JwtVerifier>,
+    /// Verifies operator tokens granting access to the admin methods.
+    pub oidc: Option<OidcVerifier>,
+    /// Forwards provider failures to Sentry or an error webhook.
+    pub reporter: ErrorReporter,
+    /// Load shedder, whose thresholds are exposed as a config resource.
+    pub shedder: Arc<LoadShedder>,
+    /// Transcripts of conversations that carry a `session_id`.
+    pub sessions: Arc<dyn SessionStore>,
+    /// Budgets the conversation history is trimmed to before each provider call.
+    pub history: HistoryPolicy,
+    /// Largest request bodies, texts and histories accepted.
+    pub limits: RequestLimits,
+    /// Tools the server runs for the agents, such as `mint_nft`.
+    pub server_tools: ServerTools,
+    /// Running requests that `cancel_request` can abort.
+    pub in_flight: InFlight,
+    /// Jobs submitted with `submit_text`.
+    pub jobs: JobQueue,
+    /// Agent replies reused for repeated identical requests.
+    pub cache: ResponseCache,
+    /// Identical requests in flight, answered by a single provider call.
+    pub coalescer: Coalescer<ProcessTextResult>,
+    /// Usage of each caller, checked against the configured quotas.
+    pub quotas: QuotaTracker,
+    /// Prices provider calls and adds up costs per caller and agent.
+    pub accounting: Accounting,
+    /// Rules agent replies are checked against before they are returned.
+    pub guardrails: Guardrails,
+    /// WebAssembly modules extending the agents they are configured for.
+    pub plugins: Plugins,
+    /// Record of every JSON-RPC call, when `AUDIT_DB` is set.
+    pub audit: Option<AuditLog>,
+    /// Logs every JSON-RPC call, without what users wrote.
+    pub access_log: AccessLog,
+    /// Latency, errors and tokens of the provider calls of each agent.
+    pub metrics: Metrics,
+    /// Documents retrieved into the prompts of agents bound to a knowledge base.
+    pub knowledge: Knowledge,
+    /// Facts remembered about users, added to the prompts of their requests.
+    pub memories: Memories,
+    /// Evaluation cases of the agents, run by `run_eval`.
+    pub evals: Evals,
+    /// Text-to-speech backend for `synthesize_speech`, and the audio kept for
+    /// its URLs.
+    pub speech: Speech,
+}
@@ -0,0 +1,62 @@
+//! Load-shedding middleware.
+//!
+//! Counts in-flight requests and their latency with the shared
+//! [`load_shed`](::load_shed) crate. While either is over its configured
+//! threshold the server is considered saturated, and low-priority requests are
+//! rejected with `503 Service Unavailable` and a `Retry-After` header instead
+//! of queueing behind interactive traffic. The latency average decays while
+//! nothing completes, so shedding stops once the server is idle again.
+//!
+//! Clients mark their requests with the `X-Priority` header:
+//!
+//! - `high` / `interactive` - never shed (e.g. live voice conversations)
+//! - `normal` (or no header) - never shed
+//! - `low` / `batch` - shed while the server is saturated
+
+use crate::i18n::{Locale, Message};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+pub use ::load_shed::{LoadShedConfig, LoadShedder, Priority, PRIORITY_HEADER};
+
+/// Axum middleware that sheds low-priority requests while the server is saturated.
+///
+/// Install with `axum::middleware::from_fn_with_state(shedder, load_shed::middleware)`.
+pub async fn middleware(
+    State(shedder): State<Arc<LoadShedder>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let priority = Priority::from_header(
+        request
+            .headers()
+            .get(PRIORITY_HEADER)
+            .and_then(|v| v.to_str().ok()),
+    );
+
+    if shedder.should_shed(priority) {
+        tracing::warn!(
+            in_flight = shedder.in_flight(),
+            avg_latency_ms = shedder.average_latency().as_millis() as u64,
+            "Shedding low-priority request: server saturated"
+        );
+        let locale = Locale::from_request(request.uri(), request.headers());
+        let mut response = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Message::Overloaded.text(locale),
+        )
+            .into_response();
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(shedder.config().retry_after_secs),
+        );
+        return response;
+    }
+
+    shedder.track(next.run(request)).await
+}
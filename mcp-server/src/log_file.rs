@@ -0,0 +1,399 @@
+//! Logs written to rotating files as JSON lines.
+//!
+//! For deployments without a log shipper, `LOG_FILE` writes every log line to
+//! a file as well, one JSON object per line, while stdout (or stderr) keeps
+//! the usual text:
+//!
+//! ```json
+//! {"correlation_id":"006f9582c2084e6f93e580750ac78607","fields":{"latency_ms":1,"message":"call","method":"process_text","status":"ok"},"level":"INFO","target":"mcp_server::access","timestamp":"2026-10-15T05:29:42.013780Z"}
+//! ```
+//!
+//! Lines logged while handling a request carry its
+//! [correlation ID](crate::correlation).
+//!
+//! The file is rotated daily (at midnight UTC) or once it reaches a size:
+//! it is renamed with the day it covers, such as `mcp-server.log.2026-10-14`,
+//! or the time it was rotated, and a new one is started. Only the newest
+//! rotated files are kept. Lines go through the same
+//! [redaction](crate::redact) patterns and `RUST_LOG` filter as the other
+//! logs.
+//!
+//! # Environment Variables
+//!
+//! * `LOG_FILE` - Optional. Path of the log file, e.g. `logs/mcp-server.log`;
+//!   its directory is created if needed
+//! * `LOG_FILE_ROTATION` - Optional. `daily`, `size` or `never` (default:
+//!   `daily`)
+//! * `LOG_FILE_MAX_BYTES` - Optional. Size a file is rotated at with `size`
+//!   (default: 100 MiB)
+//! * `LOG_FILE_MAX_FILES` - Optional. Rotated files kept, `0` for all
+//!   (default: 7)
+
+use crate::correlation;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Size a file is rotated at by default with [`Rotation::Size`].
+pub const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Rotated files kept by default.
+pub const DEFAULT_MAX_FILES: usize = 7;
+
+/// When the log file is rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// On the first line of a new day, UTC
+    Daily,
+    /// Before a line would take the file past this many bytes
+    Size(u64),
+    /// Never: the file grows until it is rotated by other means
+    Never,
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Daily => f.write_str("daily"),
+            Self::Size(bytes) => write!(f, "every {} bytes", bytes),
+            Self::Never => f.write_str("never"),
+        }
+    }
+}
+
+/// Where logs are written and how the file is rotated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFileConfig {
+    /// The file written to
+    pub path: PathBuf,
+    /// When the file is rotated
+    pub rotation: Rotation,
+    /// Rotated files kept; `0` keeps them all
+    pub max_files: usize,
+}
+
+impl LogFileConfig {
+    /// Reads `LOG_FILE` and the `LOG_FILE_*` settings; `None` without a file.
+    ///
+    /// Fails on an invalid setting.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let Some(path) = var("LOG_FILE") else {
+            return Ok(None);
+        };
+        let number = |name: &str| -> Result<Option<u64>, String> {
+            var(name)
+                .map(|v| {
+                    v.trim()
+                        .parse()
+                        .map_err(|_| format!("{} must be a number, got {:?}", name, v))
+                })
+                .transpose()
+        };
+        let rotation = match var("LOG_FILE_ROTATION")
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            None | Some("daily") => Rotation::Daily,
+            Some("size") => match number("LOG_FILE_MAX_BYTES")? {
+                Some(0) => return Err("LOG_FILE_MAX_BYTES must be at least 1".to_string()),
+                bytes => Rotation::Size(bytes.unwrap_or(DEFAULT_MAX_BYTES)),
+            },
+            Some("never") => Rotation::Never,
+            Some(other) => {
+                return Err(format!(
+                    "LOG_FILE_ROTATION must be daily, size or never, got {}",
+                    other
+                ))
+            }
+        };
+        Ok(Some(Self {
+            path: PathBuf::from(path.trim()),
+            rotation,
+            max_files: number("LOG_FILE_MAX_FILES")?.map_or(DEFAULT_MAX_FILES, |n| n as usize),
+        }))
+    }
+}
+
+/// A log file rotated as configured; a [`MakeWriter`] for the `fmt` layer.
+///
+/// The `fmt` layer writes each event in one call, so a line is never split
+/// between two files.
+#[derive(Debug)]
+pub struct LogFile {
+    config: LogFileConfig,
+    active: Mutex<Active>,
+}
+
+/// The file being written.
+#[derive(Debug)]
+struct Active {
+    file: File,
+    size: u64,
+    /// Day the file was started, UTC
+    day: NaiveDate,
+}
+
+impl LogFile {
+    /// Opens the file, appending to it, and creates its directory if needed.
+    pub fn open(config: LogFileConfig) -> Result<Self, String> {
+        let failed = |e: io::Error| format!("Failed to open {}: {}", config.path.display(), e);
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(dir).map_err(failed)?;
+        }
+        let file = append(&config.path).map_err(failed)?;
+        let metadata = file.metadata().map_err(failed)?;
+        let day = metadata
+            .modified()
+            .map(|modified| DateTime::<Utc>::from(modified).date_naive())
+            .unwrap_or_else(|_| Utc::now().date_naive());
+        Ok(Self {
+            active: Mutex::new(Active {
+                file,
+                size: metadata.len(),
+                day,
+            }),
+            config,
+        })
+    }
+
+    /// The configuration the file was opened with.
+    pub fn config(&self) -> &LogFileConfig {
+        &self.config
+    }
+
+    fn write_line(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+        let due = match self.config.rotation {
+            Rotation::Daily => now.date_naive() != active.day,
+            Rotation::Size(max) => active.size > 0 && active.size + buf.len() as u64 > max,
+            Rotation::Never => false,
+        };
+        if due {
+            // A file that can't be rotated is written on rather than losing lines
+            if let Err(e) = self.rotate(&mut active, now) {
+                eprintln!("Failed to rotate {}: {}", self.config.path.display(), e);
+            }
+        }
+        active.file.write_all(buf)?;
+        active.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    /// Renames the file, starts a new one and removes the oldest rotated ones.
+    fn rotate(&self, active: &mut Active, now: DateTime<Utc>) -> io::Result<()> {
+        active.file.flush()?;
+        let suffix = match self.config.rotation {
+            Rotation::Daily => active.day.format("%Y-%m-%d").to_string(),
+            _ => now.format("%Y-%m-%dT%H-%M-%S").to_string(),
+        };
+        let rotated = unused(&suffixed(&self.config.path, &suffix));
+        fs::rename(&self.config.path, rotated)?;
+        *active = Active {
+            file: append(&self.config.path)?,
+            size: 0,
+            day: now.date_naive(),
+        };
+        if self.config.max_files > 0 {
+            for old in self.rotated()?.iter().rev().skip(self.config.max_files) {
+                fs::remove_file(old)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The rotated files, oldest first.
+    fn rotated(&self) -> io::Result<Vec<PathBuf>> {
+        let dir = match self.config.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = format!(
+            "{}.",
+            self.config
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        );
+        let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+            })
+            .collect();
+        rotated.sort();
+        Ok(rotated)
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// `path`, or `path` with the first free `.1`, `.2`, ... suffix.
+fn unused(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    (1..)
+        .map(|n| suffixed(path, &n.to_string()))
+        .find(|candidate| !candidate.exists())
+        .expect("a suffix is free")
+}
+
+/// Writer made by [`LogFile`].
+pub struct LogFileWriter<'a>(&'a LogFile);
+
+impl Write for LogFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_line(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = LogFileWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogFileWriter(self)
+    }
+}
+
+/// Formats events as JSON objects, one per line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".into(),
+            json!(Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true)),
+        );
+        line.insert("level".into(), json!(metadata.level().as_str()));
+        line.insert("target".into(), json!(metadata.target()));
+        if let Some(id) = correlation::current_correlation_id() {
+            line.insert("correlation_id".into(), json!(id));
+        }
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        line.insert("fields".into(), Value::Object(fields.0));
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Collects the fields of an event.
+#[derive(Default)]
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), json!(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    async fn writes_json_lines_and_keeps_the_newest_files() {
+        let dir = std::env::temp_dir().join(format!("logs-{}", uuid::Uuid::new_v4()));
+        let log_file = LogFile::open(LogFileConfig {
+            path: dir.join("server.log"),
+            rotation: Rotation::Size(300),
+            max_files: 2,
+        })
+        .unwrap();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat)
+                .with_ansi(false)
+                .with_writer(log_file),
+        );
+        let logging = tracing::subscriber::set_default(subscriber);
+        correlation::with_correlation_id("gw-42".to_string(), async {
+            for n in 0..6u64 {
+                tracing::info!(target: "mcp_server::access", n, status = "ok", "call");
+            }
+        })
+        .await;
+        drop(logging);
+
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), 3, "{:?}", files);
+        assert_eq!(files[0], "server.log");
+
+        let written = fs::read_to_string(dir.join("server.log")).unwrap();
+        let line: Value = serde_json::from_str(written.lines().last().unwrap()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "mcp_server::access");
+        assert_eq!(line["correlation_id"], "gw-42");
+        assert_eq!(line["fields"]["message"], "call");
+        assert_eq!(line["fields"]["n"], 5);
+        assert_eq!(line["fields"]["status"], "ok");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
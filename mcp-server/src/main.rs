@@ -9,133 +9,797 @@
 //! The server is organized into several modules:
 //! - `models` - Data structures for JSON-RPC, agents, and AI API
 //! - `agents` - Agent definitions and management
-//! - `gemini` - AI API client and communication (supports both Groq and Gemini)
-//! - `handlers` - HTTP request handlers for JSON-RPC methods
+//! - `auth` - JWT authentication of HTTP callers
+//! - `oidc` - OIDC operator tokens for the admin methods
+//! - `providers` - Pluggable AI backends (Groq, Gemini) behind the `LlmProvider` trait
+//! - `handlers` - JSON-RPC dispatch and HTTP request handlers
+//! - `health` - Provider and session store probes behind `/healthz`, `/livez` and `/readyz`
+//! - `self_test` - A tiny prompt through each agent's providers at startup
+//! - `tools` - MCP tool definitions generated from the agents
+//! - `resources` - MCP resources: agent prompts, transcripts and config
+//! - `sessions` - Conversation transcripts, in memory or in Redis
+//! - `history` - Trimming of long conversation histories to token budgets
+//! - `limits` - Body size limits and validation of request texts and histories
+//! - `prompt_guard` - Dropping injected system messages and delimiting user input
+//! - `prompt_versions` - Versions of agents' system prompts and traffic splits between them
+//! - `server_tools` - Tools the server runs for agents, such as `mint_nft`
+//! - `pipelines` - Built-in and inline agent chains for `run_pipeline`
+//! - `cancellation` - Aborting running requests with `cancel_request`
+//! - `jobs` - Background jobs queued by `submit_text`
+//! - `progress` - `notifications/progress` of jobs and requests, as server-sent events
+//! - `tasks` - Agent runs on cron schedules set in the config file
+//! - `scheduler` - Priority queueing of provider calls by `X-Priority`
+//! - `cache` - Reuse of replies to repeated identical requests
+//! - `coalesce` - One provider call for identical requests in flight
+//! - `quota` - Daily and monthly usage limits per caller
+//! - `accounting` - Per-model prices and cost totals per caller and agent
+//! - `budget` - Token and cost budgets of single requests
+//! - `audit` - Append-only SQLite record of every JSON-RPC call
+//! - `access_log` - One log line per JSON-RPC call, without user text
+//! - `metrics` - Prometheus metrics of each agent's provider calls at `/metrics`
+//! - `log_file` - Logs as JSON lines in files rotated daily or by size
+//! - `guardrails` - Deny patterns, length limits and disclaimers applied to replies
+//! - `plugins` - WebAssembly modules rewriting or answering the requests of agents
+//! - `scripts` - Rhai scripts of agents rewriting prompts and replies, or answering
+//! - `embeddings` - `embed_text` and the local embedding model
+//! - `images` - Images sent to vision-capable models with `process_text`
+//! - `attachments` - Text files and PDFs attached to `process_text` requests
+//! - `language` - Language detection and the `reply_language` of agents
+//! - `style` - Tone, verbosity, reading level and emoji hints of a request
+//! - `knowledge` - Knowledge bases retrieved into agent prompts, in memory or in Qdrant
+//! - `memories` - Facts about each user extracted from conversations and added to later prompts
+//! - `evals` - Evaluation cases per agent, run and scored by `run_eval`
+//! - `openrpc` - OpenRPC document of the JSON-RPC methods served on `/openrpc.json`
+//! - `schemas` - Standalone JSON Schemas of the request and response models on `/schemas`
+//! - `speech` - `synthesize_speech` through an OpenAI-compatible or Azure text-to-speech backend, and transcription
+//! - `voice` - `process_audio`, chaining transcription, an agent and speech in one call
+//! - `prompts` - MCP prompt templates
+//! - `stdio` - Newline-delimited JSON-RPC over stdin/stdout
+//! - `tls` - HTTPS for the HTTP listener with rustls
+//! - `fixtures` - Recording provider calls to fixture files and replaying them
+//! - `cli` - Command-line flags and their environment variables
+//! - `bench` - The `bench` subcommand, measuring agents without serving
+//! - `chat` - The `chat` subcommand, talking to an agent from the terminal
+//! - `shutdown` - Draining running work on SIGTERM and SIGINT
+//! - `correlation` - Request correlation IDs in logs, headers and metadata
+//! - `error` - Typed errors and their JSON-RPC codes and `data`
+//! - `redact` - Masking of personal data and secrets in logs and transcripts
 //!
 //! # Supported Methods
 //!
+//! - `initialize` - MCP handshake and capability negotiation
+//! - `ping` - Liveness check
+//! - `tools/list`, `tools/call` - Agents exposed as MCP tools
+//! - `resources/list`, `resources/read` - Agent prompts, transcripts and config
+//! - `prompts/list`, `prompts/get` - Reusable prompt templates
 //! - `list_agents` - Returns all available AI agents
+//! - `find_agents` - Agents with given capabilities, ranked by a free-text query
 //! - `process_text` - Processes user text through a specified agent
+//! - `run_pipeline` - Chains agents, feeding each reply to the next
+//! - `submit_text`, `get_job_status`, `get_job_result` - `process_text` as a polled background job
+//! - `cancel_request` - Aborts a running request by its `id`
+//! - `embed_text` - Embedding vectors for texts, from a provider or a local model
+//! - `list_memories`, `delete_memories` - Facts remembered about the caller, and forgetting them
+//! - `synthesize_speech` - Reads a text aloud, returning the audio or a temporary URL
+//! - `process_audio` - Transcribes a recording, asks an agent and reads the reply aloud
+//! - `create_agent`, `update_agent`, `delete_agent` - Admin only, manage agents at runtime
+//! - `promote_prompt_version` - Admin only, makes a version of an agent's prompt live
+//! - `flush_cache` - Admin only, drops cached replies
+//! - `rotate_provider_keys` - Admin only, replaces a provider's API keys
+//! - `ingest_document`, `delete_document` - Admin only, manage knowledge base documents
+//! - `usage_report` - Admin or read-only operator, usage and cost per agent over time
+//! - `audit_log` - Admin or read-only operator, recorded calls per client, method and agent
+//! - `list_documents` - Admin or read-only operator, the documents of a knowledge base
+//! - `add_eval_case`, `delete_eval_case`, `run_eval` - Admin only, manage and run an agent's evaluation cases
+//! - `list_eval_cases` - Admin or read-only operator, an agent's evaluation cases
 //!
 //! # Quick Start
 //!
 //! 1. Set `GROQ_API_KEY` in your `.env` file
 //! 2. Run `cargo run --release`
-//! 3. Server starts on `http://0.0.0.0:3000`
+//! 3. Server starts on `http://0.0.0.0:3000` (see `--help` for the address,
+//!    port and other flags)
 //! 4. Send JSON-RPC 2.0 requests to the root path
+//!
+//! `GET /healthz` reports whether the AI providers and the session store are
+//! reachable, for orchestrator health checks; `GET /livez` and `GET /readyz`
+//! serve as Kubernetes liveness and readiness probes. `GET /openrpc.json`
+//! describes every JSON-RPC method and its params and result schemas, for
+//! generating typed clients, and `GET /schemas/{name}` serves the schema of
+//! one model, such as `ProcessTextParams`, for validating payloads.
+//! `GET /metrics` serves the latency, errors and tokens of each agent's
+//! provider calls to Prometheus.
+//!
+//! MCP hosts that launch servers as child processes run `mcp-server --stdio`
+//! instead, which speaks newline-delimited JSON-RPC over stdin/stdout.
+//!
+//! The `[[tasks]]` of the config file run agents on cron schedules while the
+//! HTTP server is up, e.g. a daily market summary posted to a webhook.
+//!
+//! For capacity planning, `mcp-server bench --agent agent_002 --requests 100
+//! --concurrency 10` sends requests to an agent in-process and prints latency
+//! percentiles, token throughput and error counts. To try an agent without
+//! writing JSON-RPC, `mcp-server chat --agent agent_004` opens a chat with it
+//! in the terminal.
 
-mod agents;
-mod gemini;
-mod handlers;
-mod models;
-
-use axum::{routing::post, Router};
-use reqwest::Client;
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use mcp_server::access_log::AccessLog;
+use mcp_server::accounting::Accounting;
+use mcp_server::agent_db::AgentDb;
+use mcp_server::agents::AgentStore;
+use mcp_server::audit::AuditLog;
+use mcp_server::auth::JwtVerifier;
+use mcp_server::cache::{CacheConfig, ResponseCache};
+use mcp_server::cli::Cli;
+use mcp_server::coalesce::Coalescer;
+use mcp_server::config::{self, Reloader, Settings};
+use mcp_server::correlation;
+use mcp_server::error_report::{ErrorReporter, ReportingConfig};
+use mcp_server::evals::Evals;
+use mcp_server::fixtures::{FixtureMode, Fixtures};
+use mcp_server::guardrails::Guardrails;
+use mcp_server::health::{self, HealthChecker};
+use mcp_server::history::HistoryPolicy;
+use mcp_server::http_client::{HttpClientConfig, ProxyConfig};
+use mcp_server::jobs::{callback_client_config, Callbacks, JobConfig, JobQueue};
+use mcp_server::knowledge::Knowledge;
+use mcp_server::limits::RequestLimits;
+use mcp_server::load_shed::{self, LoadShedConfig, LoadShedder};
+use mcp_server::log_file::{JsonFormat, LogFile, LogFileConfig};
+use mcp_server::memories::Memories;
+use mcp_server::metrics::{self, Metrics};
+use mcp_server::oidc::OidcVerifier;
+use mcp_server::plugins::Plugins;
+use mcp_server::providers::ProviderRegistry;
+use mcp_server::quota::QuotaTracker;
+use mcp_server::redact::{RedactedSessions, RedactingMakeWriter, Redactor};
+use mcp_server::self_test::{SelfTest, SelfTestMode};
+use mcp_server::server_tools::ServerTools;
+use mcp_server::sessions;
+use mcp_server::shutdown::{self, Shutdown};
+use mcp_server::speech::Speech;
+use mcp_server::tasks::Tasks;
+use mcp_server::tls::{PeerIdentity, TlsConfig, TlsListener};
+use mcp_server::{bench, chat, handlers, openrpc, schemas, stdio, AppState};
+use std::future::IntoFuture;
+use std::path::Path;
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-/// Application state shared across all request handlers.
-///
-/// This struct is wrapped in an `Arc` and cloned for each request handler,
-/// providing thread-safe access to shared resources.
-#[derive(Clone)]
-pub struct AppState {
-    /// Shared HTTP client for making requests to AI API.
-    pub http_client: Client,
-    /// AI API key for authentication (Groq or Gemini).
-    pub gemini_api_key: String,
-    /// Flag to indicate if using Groq instead of Gemini
-    pub use_groq: bool,
-}
-
 /// Main entry point for the MCP server.
 ///
 /// Initializes the server with:
 /// - Environment variable loading from .env file
 /// - Structured logging with tracing
 /// - CORS middleware for cross-origin requests
+/// - Load shedding of low-priority requests under overload
 /// - Shared application state with AI API key
 /// - HTTP route handlers for JSON-RPC methods
 ///
+/// With `--stdio`, the HTTP server is not started: JSON-RPC is served over
+/// stdin/stdout and logs are written to stderr. With `--print-config`, the
+/// effective configuration is printed and nothing is served. See
+/// [`mcp_server::cli`] for all flags.
+///
 /// # Environment Variables
 ///
 /// * `GROQ_API_KEY` - Groq API key for agent responses (recommended)
 /// * `GEMINI_API_KEY` - Alternative: Google Gemini API key
+/// * `GROQ_API_KEYS` / `GEMINI_API_KEYS` / `PROVIDER_KEY_STRATEGY` - Optional. Several keys per provider and how they are picked, see [`mcp_server::providers::keys`]
+/// * `AZURE_OPENAI_*` - Alternative: Azure OpenAI, see [`mcp_server::providers::azure`]
+/// * `MOCK_PROVIDER` / `MOCK_*` - Optional. Canned or echoed replies without a key, for tests and demos, see [`mcp_server::providers::mock`]
+/// * `LLM_PROVIDER` / `--provider` - Optional. Default provider when several are configured (`groq`, `gemini`, `azure` or `mock`)
+/// * `BIND_ADDRESS` / `--bind`, `PORT` / `--port` - Optional. Where to listen (default: 0.0.0.0:3000)
+/// * `WORKER_THREADS` / `--worker-threads` - Optional. Runtime worker threads (default: one per CPU core)
+/// * `TLS_CERT_FILE` / `TLS_KEY_FILE` - Optional. Serve HTTPS with this certificate, see [`mcp_server::tls`]
+/// * `TLS_CLIENT_CA_FILE` / `--tls-client-ca` - Optional. Require client certificates issued by these CAs (mutual TLS)
 /// * `RUST_LOG` - Optional. Logging level (default: info)
+/// * `LOG_FILE` / `LOG_FILE_*` - Optional. Rotating file of JSON log lines, see [`mcp_server::log_file`]
+/// * `LOAD_SHED_*` - Optional. Overload thresholds, see [`LoadShedConfig::from_env`]
+/// * `PROVIDER_HTTP_*` - Optional. Outbound client tuning, proxy and CAs, see [`mcp_server::http_client`]
+/// * `PROVIDER_FIXTURES` / `PROVIDER_FIXTURE_DIR` - Optional. Record provider calls to fixture files or replay them, see [`mcp_server::fixtures`]
+/// * `SENTRY_DSN` / `ERROR_WEBHOOK_URL` - Optional. Error reporting, see [`mcp_server::error_report`]
+/// * `CONFIG_FILE` / `--config` - Optional. TOML file reloaded on SIGHUP, see [`mcp_server::config`]
+/// * `AGENT_DB` / `--agent-db` - Optional. SQLite file persisting agents changed by the admin methods
+/// * `AUDIT_DB` / `--audit-db` - Optional. SQLite file recording every call, see [`mcp_server::audit`]
+/// * `ACCESS_LOG` / `ACCESS_LOG_BODIES` - Optional. Log line per call, user text only when debugging, see [`mcp_server::access_log`]
+/// * `STARTUP_SELF_TEST` / `STARTUP_SELF_TEST_TIMEOUT_MS` - Optional. Try each agent's providers and models before serving, and degrade or refuse to start on failures, see [`mcp_server::self_test`]
+/// * `METRICS` / `METRICS_TOKEN` - Optional. Serve provider call metrics at `/metrics`, behind a bearer token, see [`mcp_server::metrics`]
+/// * `SESSION_STORE` / `REDIS_URL` - Optional. Where transcripts are kept, see [`mcp_server::sessions`]
+/// * `KNOWLEDGE_STORE` / `KNOWLEDGE_DIR` - Optional. Where documents are kept and which are loaded, see [`mcp_server::knowledge`]
+/// * `MEMORY_ENABLED` / `MEMORY_DB` - Optional. Remembers facts about users, see [`mcp_server::memories`]
+/// * `EVAL_DB` / `EVAL_GRADER_MODEL` - Optional. Keeps evaluation cases in a file, and grades replies with another model, see [`mcp_server::evals`]
+/// * `TTS_PROVIDER` - Optional. Enables `synthesize_speech`, see [`mcp_server::speech`]
+/// * `STT_PROVIDER` - Optional. With `TTS_PROVIDER`, enables `process_audio`, see [`mcp_server::voice`]
+/// * `HISTORY_MAX_MESSAGES` / `HISTORY_MAX_TOKENS` - Optional. History budgets, see [`mcp_server::history`]
+/// * `MAX_BODY_BYTES` / `MAX_TEXT_CHARS` / `MAX_HISTORY_LENGTH` - Optional. Request size limits, see [`mcp_server::limits`]
+/// * `MINTING_SERVICE_URL` - Optional. Enables the `mint_nft` server tool, see [`mcp_server::server_tools`]
+/// * `EVM_RPC_URL` - Optional. Enables the `get_transaction_status` and `get_balance` server tools
+/// * `ENS_RPC_URL` - Optional. Ethereum mainnet RPC enabling the `resolve_ens` server tool
+/// * `JOB_CALLBACK_SECRET` - Optional. Enables signed `submit_text` callbacks, see [`mcp_server::jobs`]
+/// * `RESPONSE_CACHE_TTL_SECS` - Optional. Enables the response cache, see [`mcp_server::cache`]
+/// * `REQUEST_COALESCING` - Optional. `false` disables coalescing, see [`mcp_server::coalesce`]
+/// * `JWT_HS256_SECRET` / `JWT_RS256_PUBLIC_KEY_FILE` - Optional. Require JWTs over HTTP, see [`mcp_server::auth`]
+/// * `OIDC_ISSUER` - Optional. Accepts operator tokens for the admin methods, see [`mcp_server::oidc`]
+/// * `ADMIN_TOKEN` - Optional. Enables `POST /admin/reload` and the admin JSON-RPC methods for this bearer token
+/// * `REDACT_PII` / `REDACT_PATTERNS` - Optional. What is masked in logs and transcripts, see [`mcp_server::redact`]
+/// * `SHUTDOWN_GRACE_SECS` - Optional. Time running work gets to finish after SIGTERM, see [`mcp_server::shutdown`]
 ///
 /// # Panics
 ///
 /// Panics if:
-/// - Neither GROQ_API_KEY nor GEMINI_API_KEY is set
-/// - Server fails to bind to port 3000
-#[tokio::main]
-async fn main() {
-    // Load environment variables from .env file
+/// - None of GROQ_API_KEY, GEMINI_API_KEY and AZURE_OPENAI_API_KEY is set, and MOCK_PROVIDER is off
+/// - MOCK_LATENCY_MS or MOCK_FAILURE_RATE is invalid
+/// - PROVIDER_FIXTURES is neither record nor replay
+/// - AZURE_OPENAI_API_KEY is set without an endpoint or deployment
+/// - LLM_PROVIDER or `--provider` names a provider without an API key
+/// - CONFIG_FILE is set but cannot be read or parsed
+/// - JWT_RS256_PUBLIC_KEY_FILE is set but cannot be read or parsed
+/// - TLS_CERT_FILE or TLS_KEY_FILE is set without the other, or they cannot be read or don't match
+/// - TLS_CLIENT_CA_FILE cannot be read or holds no valid CA certificate
+/// - AGENT_DB is set but the database cannot be opened, migrated or read
+/// - AUDIT_DB is set but the database cannot be opened or migrated
+/// - REDACT_PII names an unknown pattern or REDACT_PATTERNS holds an invalid regex
+/// - SESSION_STORE is invalid, or is `redis` and Redis cannot be reached
+/// - KNOWLEDGE_* settings are invalid, or KNOWLEDGE_DIR cannot be read or ingested
+/// - MEMORY_* settings are invalid, or MEMORY_DB cannot be opened or migrated
+/// - EVAL_DB cannot be opened or migrated
+/// - TTS_* or STT_* settings are invalid, or TTS_PROVIDER or STT_PROVIDER is set without its key or deployment
+/// - STARTUP_SELF_TEST is `fail` and an agent's default provider rejects its key or model
+/// - `bench` names an unknown agent or no AI provider is configured
+/// - `chat` names an unknown agent
+/// - Server fails to bind to its address
+fn main() {
+    // Load environment variables from .env file, then read the flags, which
+    // fall back to them
     dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if let Some(threads) = cli.worker_threads {
+        runtime.worker_threads(threads);
+    }
+    runtime
+        .build()
+        .expect("Failed to start the async runtime")
+        .block_on(serve(cli));
+}
+
+/// Sets everything up and serves until shut down.
+async fn serve(cli: Cli) {
+    // With --stdio, stdout carries the protocol, so logs go to stderr, as
+    // they do when printing the configuration or a benchmark report and
+    // while chatting, which only shows warnings by default
+    let use_stdio = cli.stdio;
+    let quiet_stdout = use_stdio || cli.print_config || cli.bench.is_some() || cli.chat.is_some();
+    let default_filter = if cli.chat.is_some() {
+        "mcp_server=warn"
+    } else {
+        "mcp_server=debug,tower_http=debug,axum=trace"
+    };
+    let log_writer = if quiet_stdout {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    // Mask emails, phone numbers, seed phrases and keys in logs and transcripts
+    let redactor = Arc::new(Redactor::from_env().unwrap_or_else(|e| panic!("{}", e)));
+    let log_writer = RedactingMakeWriter::new(log_writer, redactor.clone());
+
+    // Also write JSON lines to a rotating file when LOG_FILE is set
+    let log_file = LogFileConfig::from_env()
+        .and_then(|config| config.map(LogFile::open).transpose())
+        .unwrap_or_else(|e| panic!("{}", e));
+    let log_file_info = log_file
+        .as_ref()
+        .map(|file| (file.config().path.clone(), file.config().rotation));
+    let file_layer = log_file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .event_format(JsonFormat)
+            .with_ansi(false)
+            .with_writer(RedactingMakeWriter::new(file, redactor.clone()))
+    });
 
     // Initialize structured logging
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "mcp_server=debug,tower_http=debug,axum=trace".into()),
+                .unwrap_or_else(|_| default_filter.into()),
+        )
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(log_writer)
+                .with_ansi(!quiet_stdout),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
         .init();
+    if let Some((path, rotation)) = log_file_info {
+        tracing::info!(
+            "🗒️ Logging to {} as JSON, rotated {}",
+            path.display(),
+            rotation
+        );
+    }
 
-    // Load API keys from environment
-    let gemini_api_key = std::env::var("GEMINI_API_KEY").ok();
-    let groq_api_key = std::env::var("GROQ_API_KEY").ok();
-    
-    let (api_key, use_groq) = match (groq_api_key, gemini_api_key) {
-        (Some(groq_key), _) => {
-            tracing::info!("🔧 Using Groq API");
-            (groq_key, true)
-        },
-        (None, Some(gemini_key)) => {
-            tracing::info!("🔧 Using Gemini API (fallback)");
-            (gemini_key, false)
-        },
-        (None, None) => {
-            panic!("Either GROQ_API_KEY or GEMINI_API_KEY must be set in .env file");
+    // Create shared HTTP client, tuned via PROVIDER_HTTP_* variables
+    let http_config = HttpClientConfig::from_env("PROVIDER_HTTP", HttpClientConfig::default());
+    if http_config.proxy != ProxyConfig::Environment {
+        tracing::info!("🌐 Provider HTTP proxy: {}", http_config.proxy);
+    }
+    if !http_config.ca_certs.is_empty() {
+        tracing::info!(
+            "🔏 Provider CA certificates: {}",
+            http_config
+                .ca_certs
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    if http_config.insecure_tls {
+        tracing::warn!(
+            "⚠️ PROVIDER_HTTP_INSECURE_TLS is set: provider certificates are not verified"
+        );
+    }
+    let http_client = http_config.build().unwrap_or_else(|e| panic!("{}", e));
+
+    // Record provider calls to fixture files, or replay them, with PROVIDER_FIXTURES
+    let fixtures = Fixtures::from_env().unwrap_or_else(|e| panic!("{}", e));
+    if let Some(fixtures) = &fixtures {
+        tracing::info!(
+            "📼 Provider calls {} fixtures in {}",
+            match fixtures.mode() {
+                FixtureMode::Record => "recorded to",
+                FixtureMode::Replay => "replayed from",
+            },
+            fixtures.dir().display()
+        );
+    }
+    let provider_client = http_client.clone().with_fixtures(fixtures);
+
+    // Register every AI provider with an API key in the environment
+    let mut providers =
+        ProviderRegistry::from_env(&provider_client).unwrap_or_else(|e| panic!("{}", e));
+    if let Some(name) = &cli.provider {
+        providers
+            .set_default(name)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    // With --print-config, show what the server would run with and stop
+    if cli.print_config {
+        let settings =
+            Settings::load(cli.config_file.as_deref()).expect("Failed to load configuration");
+        let config = cli.effective_config(&settings, &providers);
+        println!("{}", serde_json::to_string_pretty(&config).unwrap());
+        return;
+    }
+    tracing::info!("🔧 AI providers: {}", providers.names().join(", "));
+    for name in ["groq", "gemini"] {
+        if let Some(keys) = providers.key_pool(name).filter(|keys| keys.len() > 1) {
+            tracing::info!("🔑 {} API keys: {}", name, keys.len());
         }
+    }
+
+    // Reloadable components, configured from the environment and the config file
+    let reporter = ErrorReporter::new("mcp-server", ReportingConfig::default())
+        .with_redactor(redactor.clone());
+    let shedder = Arc::new(LoadShedder::new(LoadShedConfig::from_env()));
+    let agents = match &cli.agent_db {
+        Some(path) => AgentStore::with_db(Arc::new(
+            AgentDb::open(path).unwrap_or_else(|e| panic!("{}", e)),
+        )),
+        None => AgentStore::default(),
     };
+    let quotas = QuotaTracker::default();
+    let accounting = Accounting::default();
+    let guardrails = Guardrails::default();
+    let plugins = Plugins::default();
+    let tasks = Tasks::default();
+    let reloader = Reloader::from_env(
+        agents.clone(),
+        shedder.clone(),
+        reporter.clone(),
+        quotas.clone(),
+        accounting.clone(),
+        guardrails.clone(),
+        tasks.clone(),
+    )
+    .with_path(cli.config_file.clone())
+    .with_plugins(plugins.clone());
+    let settings = Settings::load(reloader.path()).expect("Failed to load configuration");
+    reloader
+        .apply(settings)
+        .expect("Failed to apply the configuration");
+
+    // Report panics, provider failures and internal errors, scrubbed, when
+    // SENTRY_DSN or ERROR_WEBHOOK_URL is set
+    reporter.install_panic_hook();
+    if reporter.is_enabled() {
+        tracing::info!("🚨 Error reporting enabled");
+    }
+
+    // Run tools such as mint_nft for the agents when their services are configured
+    let server_tools = ServerTools::from_env(&http_client);
+    if !server_tools.names().is_empty() {
+        tracing::info!("🛠️ Server tools: {}", server_tools.names().join(", "));
+    }
+
+    // Sign and retry job callbacks when JOB_CALLBACK_SECRET is set
+    let callback_client = HttpClientConfig::from_env("CALLBACK_HTTP", callback_client_config())
+        .build()
+        .unwrap_or_else(|e| panic!("Callback HTTP client: {}", e));
+    let callbacks = Callbacks::from_env(callback_client);
+    if callbacks.is_some() {
+        tracing::info!("🔔 Job callbacks enabled");
+    }
+
+    // Reuse replies to repeated requests when RESPONSE_CACHE_TTL_SECS is set
+    let cache = ResponseCache::new(CacheConfig::from_env());
+    if cache.is_enabled() {
+        tracing::info!("🗃️ Response cache enabled");
+    }
+
+    // Answer identical requests in flight with one provider call, unless
+    // REQUEST_COALESCING=false
+    let coalescer = Coalescer::from_env();
+    if !coalescer.is_enabled() {
+        tracing::info!("🔀 Request coalescing disabled");
+    }
+
+    // Keep session transcripts in memory, or in Redis with SESSION_STORE=redis
+    let mut sessions = sessions::from_env()
+        .await
+        .unwrap_or_else(|e| panic!("{}", e));
+    if redactor.is_enabled() {
+        sessions = Arc::new(RedactedSessions::new(sessions, redactor.clone()));
+        tracing::info!("🙈 Redacting personal data in logs and transcripts");
+    }
+    tracing::info!("💬 Session store: {}", sessions.name());
 
-    // Create shared HTTP client
-    let http_client = Client::new();
+    // Record every JSON-RPC call when AUDIT_DB is set
+    let audit = cli
+        .audit_db
+        .as_deref()
+        .map(|path| AuditLog::open(path).unwrap_or_else(|e| panic!("{}", e)));
+    if audit.is_some() {
+        tracing::info!("📜 Audit log enabled");
+    }
+    // Log every call, user text and history only with ACCESS_LOG_BODIES
+    let access_log = AccessLog::from_env();
+    if access_log.logs_bodies() {
+        tracing::warn!("⚠️ ACCESS_LOG_BODIES is set: user text and history are logged");
+    } else if !access_log.is_enabled() {
+        tracing::info!("📜 Access log disabled");
+    }
+    // Count each agent's provider calls for GET /metrics
+    let metrics = Metrics::from_env();
+    if metrics.is_enabled() {
+        let access = if metrics.requires_token() {
+            "with METRICS_TOKEN"
+        } else {
+            "open"
+        };
+        tracing::info!("📈 Metrics at /metrics, {}", access);
+    }
+
+    // Retrieve documents into the prompts of agents bound to a knowledge base
+    let knowledge =
+        Knowledge::from_env(&http_client, &providers).unwrap_or_else(|e| panic!("{}", e));
+    if let Some(dir) = std::env::var_os("KNOWLEDGE_DIR").filter(|d| !d.is_empty()) {
+        let documents = knowledge
+            .load_dir(Path::new(&dir))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e));
+        tracing::info!(
+            "📚 Ingested {} documents from {}",
+            documents,
+            Path::new(&dir).display()
+        );
+    }
+    tracing::info!(
+        "📚 Knowledge store: {} (embeddings: {})",
+        knowledge.store_name(),
+        knowledge.embedder_name()
+    );
+
+    // Remember facts about authenticated users when MEMORY_ENABLED=true
+    let memories = Memories::from_env().unwrap_or_else(|e| panic!("{}", e));
+    if memories.is_enabled() {
+        tracing::info!("🧠 Long-term memory enabled");
+    }
+
+    // Keep the agents' evaluation cases for run_eval
+    let evals = Evals::from_env().unwrap_or_else(|e| panic!("{}", e));
+
+    // Read replies aloud and transcribe recordings when the backends are
+    // configured
+    let speech = Speech::from_env(&http_client).unwrap_or_else(|e| panic!("{}", e));
+    if let Some(provider) = speech.synthesizer() {
+        tracing::info!(
+            "🔊 Using {} {} for synthesize_speech",
+            provider.name(),
+            provider.model()
+        );
+    }
+    if let Some(provider) = speech.transcriber() {
+        tracing::info!(
+            "🎙️ Using {} {} for transcription",
+            provider.name(),
+            provider.model()
+        );
+    }
+
+    // Require bearer JWTs from HTTP callers when a signing key is configured
+    let jwt = JwtVerifier::from_env().unwrap_or_else(|e| panic!("{}", e));
+    if jwt.is_some() && !use_stdio {
+        tracing::info!("🔐 JWT authentication enabled");
+    }
+
+    // Accept operator tokens from the OIDC provider for the admin methods
+    let oidc = OidcVerifier::from_env(http_client.clone());
+    if let Some(oidc) = &oidc {
+        tracing::info!("🔐 OIDC operator tokens accepted from {}", oidc.issuer());
+    }
+
+    let admin_methods = reloader.admin_enabled() || oidc.is_some();
+
+    // Finish running requests and jobs on SIGTERM or SIGINT
+    let shutdown = Shutdown::new();
+    let grace = shutdown::grace_from_env();
+
+    // Probe the providers and the session store for GET /healthz
+    let health = HealthChecker::new(providers.clone(), sessions.clone());
+
+    // Send a tiny prompt through each agent's providers before serving, with
+    // STARTUP_SELF_TEST; failed agents show up in GET /healthz
+    let self_test = SelfTest::from_env().unwrap_or_else(|e| panic!("{}", e));
+    if self_test.is_enabled() {
+        let results = self_test.run(&providers, &agents.list()).await;
+        let mut refused = Vec::new();
+        for result in results.iter() {
+            let Some((error, _)) = &result.error else {
+                continue;
+            };
+            let pairing = format!(
+                "{} on {} ({})",
+                result.agent_id, result.provider, result.model
+            );
+            tracing::warn!("⚠️ Self-test of {} failed: {}", pairing, error);
+            if self_test.mode == SelfTestMode::Fail && result.default && result.is_misconfigured() {
+                refused.push(format!("{}: {}", pairing, error));
+            } else {
+                health.mark_down(
+                    format!("agent:{}", result.agent_id),
+                    result.latency,
+                    format!("{} ({}): {}", result.provider, result.model, error),
+                );
+            }
+        }
+        if !refused.is_empty() {
+            panic!("Startup self-test failed: {}", refused.join("; "));
+        }
+        let passed = results.iter().filter(|r| r.error.is_none()).count();
+        tracing::info!(
+            "🩺 Self-test: {} of {} agent pairings answered",
+            passed,
+            results.len()
+        );
+    }
+
+    // Turn away oversized bodies, texts and histories
+    let limits = RequestLimits::from_env();
 
     // Create shared application state
     let state = Arc::new(AppState {
         http_client,
-        gemini_api_key: api_key,
-        use_groq,
+        providers: providers.clone(),
+        agents: agents.clone(),
+        admin_token: reloader.admin_token().map(str::to_string),
+        jwt,
+        oidc,
+        reporter: reporter.clone(),
+        shedder: shedder.clone(),
+        sessions,
+        history: HistoryPolicy::from_env(),
+        limits,
+        server_tools,
+        in_flight: Default::default(),
+        jobs: JobQueue::new(JobConfig::from_env(), callbacks),
+        cache,
+        coalescer,
+        quotas,
+        accounting,
+        guardrails,
+        plugins,
+        audit,
+        access_log,
+        metrics: metrics.clone(),
+        knowledge,
+        memories,
+        evals,
+        speech,
     });
+    let jobs = state.jobs.clone();
 
-    // Build the router with CORS support
+    // With `bench`, measure an agent through the dispatcher and stop
+    if let Some(args) = cli.bench.clone() {
+        let report = bench::run(state, args)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e));
+        println!("{}", report);
+        return;
+    }
+
+    // With `chat`, talk to an agent from the terminal and stop
+    if let Some(args) = cli.chat.clone() {
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        chat::run(
+            state,
+            args,
+            stdin,
+            tokio::io::stdout(),
+            stdio::locale_from_env(),
+        )
+        .await
+        .unwrap_or_else(|e| panic!("{}", e));
+        return;
+    }
+
+    if use_stdio {
+        #[cfg(unix)]
+        reloader.spawn_sighup_listener();
+        tracing::info!("🚀 MCP Server speaking JSON-RPC over stdio");
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        stdio::serve(state, stdin, tokio::io::stdout(), stdio::locale_from_env())
+            .await
+            .expect("Failed to serve over stdio");
+        return;
+    }
+
+    // Reload agents, thresholds and error sinks on SIGHUP or POST /admin/reload
+    let admin_enabled = reloader.admin_enabled();
+    let mut admin = Router::new();
+    if admin_enabled {
+        admin = admin.route("/admin/reload", post(config::reload_handler));
+    }
+    let admin = admin.with_state(reloader.clone());
+    #[cfg(unix)]
+    reloader.spawn_sighup_listener();
+    shutdown.spawn_signal_listener();
+    // Run the config file's tasks on their schedules
+    tasks.spawn(state.clone());
+
+    // Build the router, shedding low-priority traffic when the server is
+    // saturated; health checks and metrics scrapes are never shed
+    let probes = Router::new()
+        .route("/healthz", get(health::healthz_handler))
+        .route("/livez", get(health::livez_handler))
+        .route("/readyz", get(health::readyz_handler))
+        .with_state(health.clone());
+    let mut scrapes = Router::new();
+    if metrics.is_enabled() {
+        scrapes = scrapes.route("/metrics", get(metrics::metrics_handler));
+    }
+    let scrapes = scrapes.with_state(metrics);
     let app = Router::new()
-        .route("/", post(handlers::handle_jsonrpc))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+        .route(
+            "/",
+            post(handlers::handle_jsonrpc).layer(limits.body_limit()),
+        )
+        .route("/audio/{id}", get(handlers::handle_audio))
+        .route("/jobs/{job_id}/events", get(handlers::handle_job_events))
+        .route("/openrpc.json", get(openrpc::openrpc_handler))
+        .route("/schemas", get(schemas::index_handler))
+        .route("/schemas/{name}", get(schemas::schema_handler))
+        .with_state(state)
+        .merge(admin)
+        .layer(middleware::from_fn_with_state(
+            shedder,
+            load_shed::middleware,
+        ))
+        .merge(probes)
+        .merge(scrapes)
+        .layer(middleware::from_fn(correlation::middleware))
+        .layer(CorsLayer::permissive());
+
+    // Serve HTTPS when a certificate and key are configured
+    let tls = TlsConfig::from_paths(cli.tls_cert.clone(), cli.tls_key.clone())
+        .and_then(|tls| {
+            tls.map(|tls| {
+                tls.with_client_ca(cli.tls_client_ca.clone())
+                    .server_config()
+            })
+            .transpose()
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
 
     // Bind to TCP listener
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
+    let addr = cli.addr();
+    let listener = tokio::net::TcpListener::bind(addr)
         .await
-        .expect("Failed to bind to port 3000");
+        .unwrap_or_else(|e| panic!("Failed to bind to {}: {}", addr, e));
 
     // Log startup information
-    tracing::info!("🚀 MCP Server starting on http://0.0.0.0:3000");
-    tracing::info!("📋 Available agents: {}", agents::get_agents().len());
-    if use_groq {
-        tracing::info!("🤖 Using Groq API for agent responses");
-    } else {
-        tracing::info!("🤖 Using Google Gemini for agent responses");
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    tracing::info!("🚀 MCP Server starting on {}://{}", scheme, addr);
+    if cli.tls_client_ca.is_some() {
+        tracing::info!("🔐 Client certificates required (mutual TLS)");
+    }
+    tracing::info!("🩺 Health checks on GET /healthz, /livez and /readyz");
+    tracing::info!("📜 OpenRPC document on GET /openrpc.json, JSON Schemas on GET /schemas");
+    tracing::info!("📶 Job progress on GET /jobs/{{job_id}}/events");
+    tracing::info!("📋 Available agents: {}", agents.list().len());
+    if !tasks.list().is_empty() {
+        tracing::info!("⏰ Scheduled tasks: {}", tasks.list().len());
+    }
+    if let Some(provider) = providers.default_provider() {
+        tracing::info!("🤖 Using {} for agent responses", provider.name());
     }
     tracing::info!("📡 Supported JSON-RPC methods:");
-    tracing::info!("   - list_agents");
+    tracing::info!("   - initialize");
+    tracing::info!("   - ping");
+    tracing::info!("   - tools/list, tools/call");
+    tracing::info!("   - resources/list, resources/read");
+    tracing::info!("   - prompts/list, prompts/get");
+    tracing::info!("   - list_agents, find_agents");
     tracing::info!("   - process_text");
+    tracing::info!("   - run_pipeline");
+    tracing::info!("   - submit_text, get_job_status, get_job_result");
+    tracing::info!("   - cancel_request");
+    tracing::info!("   - embed_text");
+    tracing::info!("   - list_memories, delete_memories");
+    tracing::info!("   - synthesize_speech");
+    tracing::info!("   - process_audio");
+    if admin_methods {
+        tracing::info!("   - create_agent, update_agent, delete_agent (admin)");
+        tracing::info!("   - promote_prompt_version (admin)");
+        tracing::info!("   - flush_cache (admin)");
+        tracing::info!("   - rotate_provider_keys (admin)");
+        tracing::info!("   - ingest_document, delete_document (admin)");
+        tracing::info!("   - usage_report (admin, read-only)");
+        tracing::info!("   - list_documents (admin, read-only)");
+        tracing::info!("   - add_eval_case, delete_eval_case, run_eval (admin)");
+        tracing::info!("   - list_eval_cases (admin, read-only)");
+    }
 
-    // Start the server
-    axum::serve(listener, app)
-        .await
-        .expect("Failed to start server");
+    // Start the server, ready for traffic from now on
+    health.mark_started();
+    let server = match tls {
+        Some(config) => {
+            let listener = TlsListener::new(listener, config).expect("Failed to start TLS");
+            let app = app.into_make_service_with_connect_info::<PeerIdentity>();
+            let server = axum::serve(listener, app).with_graceful_shutdown(shutdown.triggered());
+            tokio::spawn(server.into_future())
+        }
+        None => {
+            let server = axum::serve(listener, app).with_graceful_shutdown(shutdown.triggered());
+            tokio::spawn(server.into_future())
+        }
+    };
+
+    // On SIGTERM or SIGINT, stop accepting and finish running work
+    shutdown.triggered().await;
+    let drained = shutdown::drain_within(grace, async {
+        match server.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Server error: {}", e),
+            Err(e) => tracing::error!("Server task failed: {}", e),
+        }
+        jobs.drained().await;
+        reporter.flush().await;
+    })
+    .await;
+    if drained {
+        tracing::info!("👋 Shutdown complete");
+    }
 }
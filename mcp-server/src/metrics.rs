@@ -0,0 +1,267 @@
+//! Prometheus metrics of the provider calls made for each agent.
+//!
+//! `GET /metrics` serves, in the Prometheus text format, one series per
+//! agent, provider and model:
+//!
+//! * `mcp_provider_call_duration_seconds` - Histogram of call latencies
+//! * `mcp_provider_calls_total` - Calls made, failed or not
+//! * `mcp_provider_errors_total` - Failed calls, by `kind` as in `data.kind`
+//!   of JSON-RPC errors
+//! * `mcp_provider_tokens_total` - Tokens the provider reported using
+//!
+//! so `rate(mcp_provider_tokens_total[5m])` is an agent's token throughput and
+//! a rising p90 latency or error rate for one agent shows its model degrading.
+//! Every call counts, retries of fallbacks and server tool rounds included;
+//! replies from the response cache make no call. Counters start over on
+//! restart.
+//!
+//! The endpoint is open unless `METRICS_TOKEN` is set, in which case scrapers
+//! must send it as a bearer token.
+//!
+//! # Environment Variables
+//!
+//! * `METRICS` - Optional. `false` turns the endpoint off (default: `true`)
+//! * `METRICS_TOKEN` - Optional. Bearer token `GET /metrics` requires
+
+use crate::config::bearer_matches;
+use crate::error::ServerError;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0];
+
+/// Content type of the Prometheus text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Agent, provider and model of a series.
+type Labels = (String, &'static str, String);
+
+/// One agent's calls to one provider and model.
+#[derive(Debug, Default)]
+struct Series {
+    calls: u64,
+    errors: BTreeMap<&'static str, u64>,
+    tokens: u64,
+    /// Calls per latency bucket, not cumulative; the last is `+Inf`
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    seconds: f64,
+}
+
+/// Provider call metrics. Cheap to clone; clones share the counters.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    enabled: bool,
+    token: Option<String>,
+    series: Arc<Mutex<BTreeMap<Labels, Series>>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new(true, None)
+    }
+}
+
+impl Metrics {
+    /// Metrics served with `token` required, or not recorded at all unless
+    /// `enabled`.
+    pub fn new(enabled: bool, token: Option<String>) -> Self {
+        Self {
+            enabled,
+            token,
+            series: Arc::default(),
+        }
+    }
+
+    /// Reads `METRICS` and `METRICS_TOKEN`.
+    pub fn from_env() -> Self {
+        let disabled = std::env::var("METRICS")
+            .is_ok_and(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0"));
+        let token = std::env::var("METRICS_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        Self::new(!disabled, token)
+    }
+
+    /// Whether calls are recorded and served.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether `GET /metrics` requires a bearer token.
+    pub fn requires_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Records a call to `provider` for `agent_id` that took `latency` and
+    /// used `tokens`, or failed with `error`.
+    pub fn record(
+        &self,
+        agent_id: &str,
+        provider: &'static str,
+        model: &str,
+        latency: Duration,
+        result: Result<Option<u32>, &ServerError>,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        let mut all = self.series.lock().unwrap();
+        let series = all
+            .entry((agent_id.to_string(), provider, model.to_string()))
+            .or_default();
+        series.calls += 1;
+        series.buckets[bucket] += 1;
+        series.seconds += seconds;
+        match result {
+            Ok(tokens) => series.tokens += u64::from(tokens.unwrap_or(0)),
+            Err(error) => *series.errors.entry(error.kind()).or_default() += 1,
+        }
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let all = self.series.lock().unwrap();
+        let mut out = String::new();
+        let labels = |(agent, provider, model): &Labels| {
+            format!(
+                "agent=\"{}\",provider=\"{}\",model=\"{}\"",
+                escape(agent),
+                provider,
+                escape(model)
+            )
+        };
+
+        out.push_str("# HELP mcp_provider_call_duration_seconds Latency of provider calls.\n");
+        out.push_str("# TYPE mcp_provider_call_duration_seconds histogram\n");
+        for (key, series) in all.iter() {
+            let labels = labels(key);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&series.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "mcp_provider_call_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "mcp_provider_call_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, series.calls
+            );
+            let _ = writeln!(
+                out,
+                "mcp_provider_call_duration_seconds_sum{{{}}} {}",
+                labels, series.seconds
+            );
+            let _ = writeln!(
+                out,
+                "mcp_provider_call_duration_seconds_count{{{}}} {}",
+                labels, series.calls
+            );
+        }
+
+        out.push_str("# HELP mcp_provider_calls_total Provider calls, failed or not.\n");
+        out.push_str("# TYPE mcp_provider_calls_total counter\n");
+        for (key, series) in all.iter() {
+            let _ = writeln!(
+                out,
+                "mcp_provider_calls_total{{{}}} {}",
+                labels(key),
+                series.calls
+            );
+        }
+
+        out.push_str("# HELP mcp_provider_errors_total Failed provider calls, by error kind.\n");
+        out.push_str("# TYPE mcp_provider_errors_total counter\n");
+        for (key, series) in all.iter() {
+            for (kind, count) in &series.errors {
+                let _ = writeln!(
+                    out,
+                    "mcp_provider_errors_total{{{},kind=\"{}\"}} {}",
+                    labels(key),
+                    kind,
+                    count
+                );
+            }
+        }
+
+        out.push_str("# HELP mcp_provider_tokens_total Tokens used by provider calls.\n");
+        out.push_str("# TYPE mcp_provider_tokens_total counter\n");
+        for (key, series) in all.iter() {
+            let _ = writeln!(
+                out,
+                "mcp_provider_tokens_total{{{}}} {}",
+                labels(key),
+                series.tokens
+            );
+        }
+        out
+    }
+}
+
+/// Escapes a label value of the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// `GET /metrics` - the metrics in the Prometheus text format.
+pub async fn metrics_handler(State(metrics): State<Metrics>, headers: HeaderMap) -> Response {
+    if let Some(token) = &metrics.token {
+        if !bearer_matches(&headers, token) {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], metrics.render()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_histograms_and_counters_per_agent() {
+        let metrics = Metrics::default();
+        let ms = Duration::from_millis;
+        metrics.record("agent_001", "groq", "llama", ms(80), Ok(Some(100)));
+        metrics.record("agent_001", "groq", "llama", ms(700), Ok(None));
+        let error = ServerError::Timeout("slow".into());
+        metrics.record("agent_001", "groq", "llama", ms(90_000), Err(&error));
+        metrics.record("agent_\"2", "gemini", "flash", ms(300), Ok(Some(5)));
+
+        let text = metrics.render();
+        let labels = "agent=\"agent_001\",provider=\"groq\",model=\"llama\"";
+        for line in [
+            format!("mcp_provider_call_duration_seconds_bucket{{{labels},le=\"0.1\"}} 1"),
+            format!("mcp_provider_call_duration_seconds_bucket{{{labels},le=\"1\"}} 2"),
+            format!("mcp_provider_call_duration_seconds_bucket{{{labels},le=\"60\"}} 2"),
+            format!("mcp_provider_call_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 3"),
+            format!("mcp_provider_call_duration_seconds_count{{{labels}}} 3"),
+            format!("mcp_provider_calls_total{{{labels}}} 3"),
+            format!("mcp_provider_errors_total{{{labels},kind=\"timeout\"}} 1"),
+            format!("mcp_provider_tokens_total{{{labels}}} 100"),
+            "mcp_provider_tokens_total{agent=\"agent_\\\"2\",provider=\"gemini\",model=\"flash\"} 5"
+                .to_string(),
+        ] {
+            assert!(text.lines().any(|l| l == line), "{} missing from\n{}", line, text);
+        }
+
+        let disabled = Metrics::new(false, None);
+        disabled.record("agent_001", "groq", "llama", ms(80), Ok(Some(1)));
+        assert!(!disabled.render().contains("agent_001"));
+    }
+}
@@ -4,7 +4,11 @@
 //! including JSON-RPC protocol types, agent definitions, AI API types (Groq/Gemini),
 //! and processing results.
 
+use crate::attachments::{AttachmentInfo, AttachmentInput};
+use crate::budget::Budget;
+use crate::images::ImageInput;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// JSON-RPC 2.0 request structure.
 ///
@@ -22,8 +26,24 @@ pub struct JsonRpcRequest<T> {
     /// Optional parameters for the method
     #[serde(skip_serializing_if = "Option::is_none")]
     pub params: Option<T>,
-    /// Request identifier for matching responses
-    pub id: serde_json::Value,
+    /// Request identifier for matching responses; absent for notifications
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub id: Option<serde_json::Value>,
+}
+
+/// Deserializes a field that is present, even as `null`, to `Some`.
+///
+/// A request with `"id": null` still expects a response, unlike a
+/// notification, which has no `id` at all.
+fn deserialize_present<'de, D>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    serde_json::Value::deserialize(deserializer).map(Some)
 }
 
 /// JSON-RPC 2.0 response structure.
@@ -50,7 +70,7 @@ pub struct JsonRpcResponse<T> {
 /// JSON-RPC 2.0 error object.
 ///
 /// Represents an error in JSON-RPC response.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcError {
     /// Error code
     pub code: i32,
@@ -61,6 +81,64 @@ pub struct JsonRpcError {
     pub data: Option<serde_json::Value>,
 }
 
+/// Name and version of an MCP client or server.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Implementation {
+    /// Software name
+    pub name: String,
+    /// Software version
+    pub version: String,
+}
+
+/// Params of the MCP `initialize` request.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InitializeParams {
+    /// Latest protocol version the client supports
+    pub protocol_version: Option<String>,
+    /// Capabilities the client offers
+    pub capabilities: serde_json::Value,
+    /// The connecting client
+    pub client_info: Option<Implementation>,
+}
+
+/// Capabilities the server advertises during `initialize`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ServerCapabilities {
+    /// Present if the server offers tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<ListCapability>,
+    /// Present if the server offers resources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ListCapability>,
+    /// Present if the server offers prompt templates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<ListCapability>,
+}
+
+/// Capability of a server feature with a listing method.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListCapability {
+    /// Whether the server notifies clients when the list changes
+    pub list_changed: bool,
+}
+
+/// Result of the MCP `initialize` request.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    /// Protocol version the session will use
+    pub protocol_version: String,
+    /// Capabilities the server offers
+    pub capabilities: ServerCapabilities,
+    /// This server
+    pub server_info: Implementation,
+    /// Usage hints for the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
 /// Information about an AI agent.
 ///
 /// Represents a specialized AI agent with unique capabilities and system instructions.
@@ -74,17 +152,234 @@ pub struct Agent {
     pub description: String,
     /// List of capabilities (e.g., "text", "web3", "coding")
     pub capabilities: Vec<String>,
-    /// AI model used by this agent (e.g., "mixtral-8x7b-32768" for Groq)
+    /// AI model used by this agent (e.g., "llama-3.3-70b-versatile" for Groq); providers
+    /// that don't serve it fall back to their default model
     pub model: String,
     /// System prompt that defines the agent's behavior
     pub system_prompt: String,
+    /// Name of the version of `system_prompt`, see [`crate::prompt_versions`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    /// Other versions of the system prompt, and the share of requests each
+    /// answers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompt_versions: Vec<crate::prompt_versions::PromptVersion>,
+    /// Stop sequences ending the agent's replies, unless a request sets its own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Sampling temperature of the agent's replies, unless a request sets its
+    /// own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Most tokens in the agent's replies, unless a request sets its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Nucleus sampling probability mass of the agent's replies, unless a
+    /// request sets its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// Providers to try, in order, when the default provider fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<ProviderFallback>,
+    /// Tenant owning the agent; only that tenant's callers can see or use it.
+    /// Agents without one are shared by every caller
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Whether user messages are sent wrapped in `<user_input>` tags, see
+    /// [`crate::prompt_guard`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub delimit_user_input: bool,
+    /// Whether `system` messages of the conversation history are sent after
+    /// the system prompt rather than dropped, see [`crate::prompt_guard`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_system_messages: bool,
+    /// Knowledge base whose closest chunks are added to the system prompt,
+    /// see [`crate::knowledge`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub knowledge_base: Option<String>,
+    /// Models a request may pick with its `model` override, besides the
+    /// agent's own. Empty lets requests pick any model the provider serves
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_models: Vec<String>,
+    /// Rhai scripts run on the requests and replies of the agent, see
+    /// [`crate::scripts`]
+    #[serde(
+        default,
+        skip_serializing_if = "crate::scripts::AgentScripts::is_empty"
+    )]
+    pub scripts: crate::scripts::AgentScripts,
+}
+
+impl Agent {
+    /// Checks the agent's default sampling settings, with the same ranges as
+    /// a request's.
+    pub fn validate_generation(&self) -> Result<(), String> {
+        GenerationParams {
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            top_p: self.top_p,
+            stop: Some(self.stop.clone()),
+            ..Default::default()
+        }
+        .validate()
+    }
+
+    /// Checks that a request may override the agent's model with `model`.
+    pub fn allows_model(&self, model: &str) -> Result<(), String> {
+        if self.allowed_models.is_empty()
+            || model == self.model
+            || self.allowed_models.iter().any(|m| m == model)
+        {
+            return Ok(());
+        }
+        Err(format!(
+            "agent {} only allows models: {}, {}",
+            self.id,
+            self.model,
+            self.allowed_models.join(", ")
+        ))
+    }
+}
+
+/// A provider an agent fails over to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderFallback {
+    /// Name of the provider, e.g. "gemini"
+    pub provider: String,
+    /// Model to call; without one, the agent's model or the provider's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl ProviderFallback {
+    /// Most fallbacks an agent may have.
+    pub const MAX: usize = 4;
+
+    /// Checks an agent's fallbacks: at most [`MAX`](Self::MAX), each naming a
+    /// provider.
+    ///
+    /// Providers that aren't configured are allowed, and skipped at request
+    /// time, so one agent definition works across deployments.
+    pub fn validate_all(fallbacks: &[Self]) -> Result<(), String> {
+        if fallbacks.len() > Self::MAX {
+            return Err(format!("at most {} fallbacks are allowed", Self::MAX));
+        }
+        if fallbacks.iter().any(|f| f.provider.is_empty()) {
+            return Err("a fallback must name a provider".to_string());
+        }
+        Ok(())
+    }
 }
 
-/// Result of the list_agents JSON-RPC method.
+/// Result of the list_agents and find_agents JSON-RPC methods.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ListAgentsResult {
     /// List of all available agents
-    pub agents: Vec<Agent>,
+    pub agents: Vec<Arc<Agent>>,
+}
+
+/// Parameters for the find_agents JSON-RPC method.
+///
+/// Without any, every agent is found.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FindAgentsParams {
+    /// Capabilities the agents must all have, compared ignoring case
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    /// Free text matched against names, descriptions and capabilities
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Most agents returned, best matches first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Parameters for the update_agent JSON-RPC method.
+///
+/// Fields left out keep their current value.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateAgentParams {
+    /// ID of the agent to change
+    pub agent_id: String,
+    /// New human-readable name
+    #[serde(default)]
+    pub name: Option<String>,
+    /// New description
+    #[serde(default)]
+    pub description: Option<String>,
+    /// New capabilities
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
+    /// New model
+    #[serde(default)]
+    pub model: Option<String>,
+    /// New system prompt
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// New name of the system prompt's version
+    #[serde(default)]
+    pub prompt_version: Option<String>,
+    /// New versions of the system prompt and their weights; an empty list
+    /// ends the agent's experiment
+    #[serde(default)]
+    pub prompt_versions: Option<Vec<crate::prompt_versions::PromptVersion>>,
+    /// New default stop sequences
+    #[serde(default)]
+    pub stop: Option<Vec<String>>,
+    /// New default temperature; `null` removes the agent's
+    #[serde(default, deserialize_with = "nullable")]
+    pub temperature: Option<Option<f64>>,
+    /// New default token limit; `null` removes the agent's
+    #[serde(default, deserialize_with = "nullable")]
+    pub max_tokens: Option<Option<u32>>,
+    /// New default `top_p`; `null` removes the agent's
+    #[serde(default, deserialize_with = "nullable")]
+    pub top_p: Option<Option<f64>>,
+    /// New provider fallbacks
+    #[serde(default)]
+    pub fallbacks: Option<Vec<ProviderFallback>>,
+    /// Whether to wrap user messages in `<user_input>` tags
+    #[serde(default)]
+    pub delimit_user_input: Option<bool>,
+    /// Whether to send `system` messages of the history rather than drop them
+    #[serde(default)]
+    pub allow_system_messages: Option<bool>,
+    /// New knowledge base; an empty string unbinds the agent's
+    #[serde(default)]
+    pub knowledge_base: Option<String>,
+    /// New models requests may pick; an empty list lets them pick any
+    #[serde(default)]
+    pub allowed_models: Option<Vec<String>>,
+    /// New scripts, replacing both; an empty object removes them
+    #[serde(default)]
+    pub scripts: Option<crate::scripts::AgentScripts>,
+}
+
+/// Tells a field set to `null` (`Some(None)`) from one left out (`None`).
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Parameters for the delete_agent JSON-RPC method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAgentParams {
+    /// ID of the agent to remove
+    pub agent_id: String,
+}
+
+/// Result of the create_agent, update_agent, delete_agent and promote_prompt_version
+/// JSON-RPC methods.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentResult {
+    /// The agent as created, updated or deleted
+    pub agent: Arc<Agent>,
 }
 
 /// Parameters for the process_text JSON-RPC method.
@@ -97,39 +392,364 @@ pub struct ProcessTextParams {
     /// Optional conversation history for context
     #[serde(skip_serializing_if = "Option::is_none")]
     pub conversation_history: Option<Vec<Message>>,
+    /// Optional session to record the exchange in; without
+    /// `conversation_history`, the session's transcript is used as history.
+    /// Sessions are kept per tenant, and IDs must not contain `/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Optional model overriding the agent's; must be one the provider serves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Optional sampling settings forwarded to the provider
+    #[serde(flatten)]
+    pub generation: GenerationParams,
+    /// Optional most tokens or cost the request may spend, see
+    /// [`crate::budget`]
+    #[serde(flatten)]
+    pub budget: Budget,
+    /// Optional functions the model may call instead of answering directly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<FunctionTool>>,
+    /// Optional time the request may take in milliseconds, retries and
+    /// fallbacks included, overriding the provider's timeout; at most
+    /// [`MAX_TIMEOUT_MS`](Self::MAX_TIMEOUT_MS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Optional images for vision-capable models, see [`crate::images`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<ImageInput>>,
+    /// Optional text files or PDFs put before `user_text`, see
+    /// [`crate::attachments`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<Vec<AttachmentInput>>,
+    /// Optional language to reply in, a code such as `es` or `auto` for the
+    /// language of `user_text`, see [`crate::language`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_language: Option<String>,
+    /// Optional tone, verbosity, reading level and emoji hints, see
+    /// [`crate::style`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<crate::style::Style>,
+    /// Keep nothing of the call: the exchange isn't added to the session or
+    /// the user's memories, the response cache is neither read nor written
+    /// and the access log leaves out its body
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_store: bool,
+}
+
+impl ProcessTextParams {
+    /// Longest `timeout_ms` accepted, 10 minutes.
+    pub const MAX_TIMEOUT_MS: u64 = 600_000;
+
+    /// Checks that `timeout_ms` is within range, returning it as a duration.
+    pub fn validate_timeout(
+        timeout_ms: Option<u64>,
+    ) -> Result<Option<std::time::Duration>, String> {
+        match timeout_ms {
+            Some(ms) if ms == 0 || ms > Self::MAX_TIMEOUT_MS => Err(format!(
+                "timeout_ms must be between 1 and {}",
+                Self::MAX_TIMEOUT_MS
+            )),
+            ms => Ok(ms.map(std::time::Duration::from_millis)),
+        }
+    }
+}
+
+/// A function the model may call, in the OpenAI tools format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionTool {
+    /// Kind of tool; only "function" is supported
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The function's name, description and parameters
+    pub function: FunctionDefinition,
+}
+
+/// Declaration of a callable function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    /// Name the model calls the function by: 1-64 letters, digits, `_` or `-`
+    pub name: String,
+    /// What the function does, to help the model decide when to call it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON Schema object describing the arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+}
+
+impl FunctionTool {
+    /// Most tools accepted in one request, the OpenAI API's limit.
+    pub const MAX_TOOLS: usize = 128;
+
+    /// Checks a request's tools: function tools only, with valid, distinct
+    /// names and object parameter schemas.
+    ///
+    /// ```
+    /// use mcp_server::models::FunctionTool;
+    ///
+    /// let tool: FunctionTool = serde_json::from_value(serde_json::json!({
+    ///     "type": "function",
+    ///     "function": { "name": "get_balance", "parameters": { "type": "object" } }
+    /// }))
+    /// .unwrap();
+    /// assert!(FunctionTool::validate_all(&[tool.clone()]).is_ok());
+    /// assert!(FunctionTool::validate_all(&[tool.clone(), tool]).is_err());
+    /// ```
+    pub fn validate_all(tools: &[FunctionTool]) -> Result<(), String> {
+        if tools.len() > Self::MAX_TOOLS {
+            return Err(format!(
+                "tools accepts at most {} functions, got {}",
+                Self::MAX_TOOLS,
+                tools.len()
+            ));
+        }
+        let mut names = std::collections::HashSet::new();
+        for tool in tools {
+            let name = &tool.function.name;
+            if tool.kind != "function" {
+                return Err(format!("tool {} has unsupported type {}", name, tool.kind));
+            }
+            let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+            if name.is_empty() || name.len() > 64 || !name.chars().all(valid_char) {
+                return Err(format!(
+                    "tool name {} must be 1-64 letters, digits, _ or -",
+                    name
+                ));
+            }
+            if !names.insert(name) {
+                return Err(format!("tool {} is declared twice", name));
+            }
+            if tool
+                .function
+                .parameters
+                .as_ref()
+                .is_some_and(|p| !p.is_object())
+            {
+                return Err(format!("parameters of tool {} must be an object", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function call requested by the model, in the OpenAI format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// ID to answer the call with, as the `tool_call_id` of a `tool` message
+    pub id: String,
+    /// Kind of call; always "function"
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The function called and its arguments
+    pub function: FunctionCall,
+}
+
+/// The function and arguments of a [`ToolCall`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    /// Name of the declared function
+    pub name: String,
+    /// Arguments as a JSON-encoded object, exactly as the model produced them
+    pub arguments: String,
+}
+
+/// Sampling settings for a reply; unset ones use the provider's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    /// Sampling temperature, 0 to 2 (default: the agent's, or 0.7)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Most tokens to generate, 1 to 32768 (default: the agent's, or 1024)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Nucleus sampling probability mass, above 0 and at most 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// Penalty for tokens by how often they already appeared, -2 to 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// Up to 4 sequences at which the reply is cut off, replacing the agent's
+    /// own; an empty list sends none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Seed for best-effort deterministic sampling; the same seed and params
+    /// should give the same reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
+}
+
+impl GenerationParams {
+    /// Temperature used when none is requested.
+    pub const DEFAULT_TEMPERATURE: f64 = 0.7;
+    /// Token limit used when none is requested.
+    pub const DEFAULT_MAX_TOKENS: u32 = 1024;
+    /// Largest `max_tokens` accepted.
+    pub const MAX_MAX_TOKENS: u32 = 32768;
+    /// Most stop sequences accepted, the OpenAI API's limit.
+    pub const MAX_STOP_SEQUENCES: usize = 4;
+
+    /// Checks that every set value is in range.
+    ///
+    /// ```
+    /// use mcp_server::models::GenerationParams;
+    ///
+    /// let params = GenerationParams { temperature: Some(2.5), ..Default::default() };
+    /// assert!(params.validate().is_err());
+    /// assert!(GenerationParams::default().validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<(), String> {
+        let check =
+            |name: &str, value: Option<f64>, valid: fn(f64) -> bool, range: &str| match value {
+                Some(v) if !valid(v) => Err(format!("{} must be {}, got {}", name, range, v)),
+                _ => Ok(()),
+            };
+        check(
+            "temperature",
+            self.temperature,
+            |v| (0.0..=2.0).contains(&v),
+            "between 0 and 2",
+        )?;
+        check(
+            "top_p",
+            self.top_p,
+            |v| v > 0.0 && v <= 1.0,
+            "above 0 and at most 1",
+        )?;
+        check(
+            "frequency_penalty",
+            self.frequency_penalty,
+            |v| (-2.0..=2.0).contains(&v),
+            "between -2 and 2",
+        )?;
+        match self.max_tokens {
+            Some(n) if n == 0 || n > Self::MAX_MAX_TOKENS => {
+                return Err(format!(
+                    "max_tokens must be between 1 and {}, got {}",
+                    Self::MAX_MAX_TOKENS,
+                    n
+                ))
+            }
+            _ => {}
+        }
+        Self::validate_stop(self.stop.as_deref().unwrap_or_default())
+    }
+
+    /// Checks a list of stop sequences, from a request or an agent.
+    pub fn validate_stop(stop: &[String]) -> Result<(), String> {
+        if stop.len() > Self::MAX_STOP_SEQUENCES {
+            return Err(format!(
+                "stop accepts at most {} sequences, got {}",
+                Self::MAX_STOP_SEQUENCES,
+                stop.len()
+            ));
+        }
+        if stop.iter().any(String::is_empty) {
+            return Err("stop sequences must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// These settings with the agent's defaults filled in for those the
+    /// request doesn't set.
+    pub fn with_agent_defaults(self, agent: &Agent) -> Self {
+        let stop = self
+            .stop
+            .or_else(|| (!agent.stop.is_empty()).then(|| agent.stop.clone()));
+        Self {
+            temperature: self.temperature.or(agent.temperature),
+            max_tokens: self.max_tokens.or(agent.max_tokens),
+            top_p: self.top_p.or(agent.top_p),
+            stop,
+            ..self
+        }
+    }
 }
 
 /// A message in the conversation history.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Message {
-    /// Role of the message sender ("user" or "assistant")
+    /// Role of the message sender ("user", "assistant" or "tool")
     pub role: String,
-    /// Content of the message
+    /// Content of the message; for a "tool" message, the call's result
+    #[serde(default)]
     pub content: String,
+    /// Function calls requested by an "assistant" message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// For a "tool" message, the ID of the call it answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
 /// Result of the process_text JSON-RPC method.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessTextResult {
     /// ID of the agent that processed the text
     pub agent_id: String,
-    /// Agent's text response
+    /// Agent's text response; empty when the model only requested tool calls
     pub reply_text: String,
+    /// Function calls the model requested; run them and send the results back
+    /// as "tool" messages to continue
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
     /// Metadata about the processing
     pub metadata: ProcessingMetadata,
 }
 
 /// Metadata about text processing.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessingMetadata {
+    /// AI provider that answered; differs from the default after a failover
+    #[serde(default)]
+    pub provider: String,
     /// AI model used
     pub model: String,
     /// Number of tokens consumed (if available)
     pub tokens_used: Option<u32>,
+    /// Estimated cost of the provider calls in USD, if the model has a
+    /// [price](crate::accounting) and token counts were reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
     /// Processing time in milliseconds
     pub processing_time_ms: u64,
-    /// Confidence score (currently hardcoded)
-    pub confidence: f64,
+    /// Why the provider stopped generating the reply, if it said
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<crate::providers::FinishReason>,
+    /// How far the reply can be trusted, from 0 to 1, as scored by
+    /// [`confidence`](crate::providers::confidence); absent when the provider
+    /// reported no token logprobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    /// The requested sampling seed, to reproduce the reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
+    /// Whether the reply came from the response cache; absent when the cache
+    /// is off or the request can't be cached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<crate::cache::CacheStatus>,
+    /// [Correlation ID](crate::correlation) of the request, to find its logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Documents of the agent's [knowledge base](crate::knowledge) added to
+    /// the prompt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    /// What was read from the [attached files](crate::attachments)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<AttachmentInfo>,
+    /// Language detected in `user_text` and the one the agent was told to
+    /// reply in, see [`crate::language`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<crate::language::LanguageMetadata>,
+    /// Version of the agent's system prompt that answered, for agents with
+    /// [versions](crate::prompt_versions)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    /// Whether the call was made with `no_store`, confirming nothing of it
+    /// was kept
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_store: bool,
 }
 
 /// Request structure for Google Gemini API.
@@ -142,22 +762,89 @@ pub struct GeminiRequest {
     /// Optional system instruction to define agent behavior
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system_instruction: Option<GeminiSystemInstruction>,
+    /// Optional sampling settings; Gemini's defaults apply when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GeminiGenerationConfig>,
+}
+
+/// Sampling settings of a Gemini request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeminiGenerationConfig {
+    /// Sampling temperature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Most tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    /// Nucleus sampling probability mass
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// Penalty for repeated tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// Sequences at which the reply is cut off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    /// Sampling seed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
 }
 
 /// A single message/content in the Gemini conversation.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GeminiContent {
     /// Role of the message sender ("user" or "model")
+    #[serde(default)]
     pub role: String,
     /// Parts of the message (text, images, etc.)
+    #[serde(default)]
     pub parts: Vec<GeminiPart>,
 }
 
-/// A part of a Gemini message (currently only text).
-#[derive(Debug, Serialize, Deserialize)]
+/// A part of a Gemini message: text, an inline image, or a function call or
+/// its result.
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GeminiPart {
-    /// Text content of the message part
+    /// Text content of the message part, empty for other parts
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub text: String,
+    /// Image bytes of the message part
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<GeminiInlineData>,
+    /// Function called by a "model" message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<GeminiFunctionCall>,
+    /// Result of a function call, sent in a "user" message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_response: Option<GeminiFunctionResponse>,
+}
+
+/// A function call of a Gemini message part.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeminiFunctionCall {
+    /// Name of the declared function
+    pub name: String,
+    /// Arguments as a JSON object
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+/// The result of a function call, as a Gemini message part.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeminiFunctionResponse {
+    /// Name of the function called
+    pub name: String,
+    /// Result as a JSON object
+    pub response: serde_json::Value,
+}
+
+/// Inline bytes of a Gemini message part.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeminiInlineData {
+    /// MIME type, e.g. `image/png`
+    pub mime_type: String,
+    /// Base64 of the bytes
+    pub data: String,
 }
 
 /// System instruction for Gemini to define agent behavior.
@@ -169,9 +856,14 @@ pub struct GeminiSystemInstruction {
 
 /// Response structure from Google Gemini API.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GeminiResponse {
-    /// List of candidate responses (usually one)
+    /// List of candidate responses (usually one; absent when the prompt is blocked)
+    #[serde(default)]
     pub candidates: Vec<GeminiCandidate>,
+    /// Feedback about the prompt, e.g. why it was blocked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_feedback: Option<GeminiPromptFeedback>,
     /// Optional metadata about token usage
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage_metadata: Option<GeminiUsageMetadata>,
@@ -179,9 +871,40 @@ pub struct GeminiResponse {
 
 /// A single candidate response from Gemini.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GeminiCandidate {
-    /// Content of the candidate response
-    pub content: GeminiContent,
+    /// Content of the candidate response (absent if generation was blocked)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<GeminiContent>,
+    /// Why generation stopped ("STOP", "MAX_TOKENS", "SAFETY", ...)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    /// Average log probability of the candidate's tokens, if reported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_logprobs: Option<f64>,
+    /// How likely the candidate is to be harmful, per category
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub safety_ratings: Vec<GeminiSafetyRating>,
+}
+
+/// Gemini's rating of a candidate for one harm category.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeminiSafetyRating {
+    /// Harm category, e.g. "HARM_CATEGORY_HARASSMENT"
+    #[serde(default)]
+    pub category: String,
+    /// "NEGLIGIBLE", "LOW", "MEDIUM" or "HIGH"
+    #[serde(default)]
+    pub probability: String,
+}
+
+/// Gemini's feedback about the prompt itself.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPromptFeedback {
+    /// Set when the prompt was blocked ("SAFETY", "OTHER", ...)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_reason: Option<String>,
 }
 
 /// Metadata about token usage in the Gemini API call.
@@ -195,3 +918,185 @@ pub struct GeminiUsageMetadata {
     /// Total tokens used (prompt + response)
     pub total_token_count: Option<u32>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::Value;
+
+    /// Arbitrary JSON values (no floats, which do not round-trip exactly).
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::Bool),
+            any::<i64>().prop_map(Value::from),
+            ".{0,24}".prop_map(Value::String),
+        ];
+        leaf.prop_recursive(3, 32, 6, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..6).prop_map(Value::Array),
+                prop::collection::btree_map("[a-z_]{1,8}", inner, 0..6)
+                    .prop_map(|m| Value::Object(m.into_iter().collect())),
+            ]
+        })
+    }
+
+    /// Optional payloads; a top-level `null` decodes the same as an absent field.
+    fn payload() -> impl Strategy<Value = Option<Value>> {
+        prop::option::of(json_value().prop_filter("null payload", |v| !v.is_null()))
+    }
+
+    /// Valid JSON-RPC ids: numbers, strings or null.
+    fn request_id() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            any::<i64>().prop_map(Value::from),
+            "[A-Za-z0-9-]{0,16}".prop_map(Value::String),
+            Just(Value::Null),
+        ]
+    }
+
+    fn message() -> impl Strategy<Value = Message> {
+        (
+            prop_oneof!["user", "assistant", "system", ".{0,8}"],
+            ".{0,64}",
+        )
+            .prop_map(|(role, content)| Message {
+                role,
+                content,
+                ..Default::default()
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn request_envelope_round_trips(
+            method in ".{0,32}",
+            params in payload(),
+            id in prop::option::of(request_id()),
+        ) {
+            let request = JsonRpcRequest { jsonrpc: "2.0".to_string(), method, params, id };
+            let encoded = serde_json::to_string(&request).unwrap();
+            let decoded: JsonRpcRequest<Value> = serde_json::from_str(&encoded).unwrap();
+
+            prop_assert_eq!(&decoded.jsonrpc, "2.0");
+            prop_assert_eq!(&decoded.method, &request.method);
+            prop_assert_eq!(&decoded.id, &request.id);
+            // An absent `params` is omitted rather than sent as null
+            prop_assert_eq!(encoded.contains("\"params\""), request.params.is_some());
+            prop_assert_eq!(&decoded.params, &request.params);
+        }
+
+        #[test]
+        fn response_envelope_round_trips(
+            result in payload(),
+            code in any::<i32>(),
+            message in ".{0,32}",
+            data in payload(),
+            is_error in any::<bool>(),
+            id in request_id(),
+        ) {
+            let response = if is_error {
+                JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    result: None,
+                    error: Some(JsonRpcError { code, message, data }),
+                    id,
+                }
+            } else {
+                JsonRpcResponse { jsonrpc: "2.0".to_string(), result, error: None, id }
+            };
+            let encoded = serde_json::to_value(&response).unwrap();
+            let decoded: JsonRpcResponse<Value> = serde_json::from_value(encoded.clone()).unwrap();
+
+            // Re-encoding the decoded envelope is lossless
+            prop_assert_eq!(serde_json::to_value(&decoded).unwrap(), encoded.clone());
+            prop_assert_eq!(encoded.get("error").is_some(), is_error);
+        }
+
+        #[test]
+        fn process_text_params_round_trip(
+            agent_id in "[a-z0-9_]{1,16}",
+            user_text in ".{0,128}",
+            history in prop::option::of(prop::collection::vec(message(), 0..8)),
+            temperature in prop::option::of(0.0f64..=2.0),
+            max_tokens in prop::option::of(1u32..=GenerationParams::MAX_MAX_TOKENS),
+        ) {
+            let params = ProcessTextParams {
+                agent_id,
+                user_text,
+                conversation_history: history,
+                session_id: None,
+                model: None,
+                tools: None,
+                timeout_ms: None,
+                images: None,
+                attachments: None,
+                reply_language: None,
+                style: None,
+                no_store: false,
+                budget: Default::default(),
+                generation: GenerationParams {
+                    temperature,
+                    max_tokens,
+                    ..Default::default()
+                },
+            };
+            let decoded: ProcessTextParams =
+                serde_json::from_value(serde_json::to_value(&params).unwrap()).unwrap();
+            prop_assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&params).unwrap()
+            );
+        }
+
+        #[test]
+        fn arbitrary_bytes_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+            let _ = serde_json::from_slice::<JsonRpcRequest<Value>>(&bytes);
+            let _ = serde_json::from_slice::<GeminiResponse>(&bytes);
+        }
+    }
+
+    #[test]
+    fn null_id_is_a_request_not_a_notification() {
+        let request: JsonRpcRequest<Value> =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","method":"ping","id":null}"#).unwrap();
+        assert_eq!(request.id, Some(Value::Null));
+
+        let notification: JsonRpcRequest<Value> =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+                .unwrap();
+        assert_eq!(notification.id, None);
+    }
+
+    #[test]
+    fn agent_defaults_fill_unset_settings() {
+        let voice = crate::agents::builtin_agents().remove(2);
+        let requested = GenerationParams {
+            temperature: Some(1.0),
+            ..Default::default()
+        };
+        let generation = requested.with_agent_defaults(&voice);
+        assert_eq!(generation.temperature, Some(1.0));
+        assert_eq!(generation.max_tokens, voice.max_tokens);
+        assert!(voice.validate_generation().is_ok());
+
+        let update: UpdateAgentParams =
+            serde_json::from_str(r#"{"agent_id":"agent_003","temperature":null,"max_tokens":100}"#)
+                .unwrap();
+        assert_eq!(update.temperature, Some(None));
+        assert_eq!(update.max_tokens, Some(Some(100)));
+        assert_eq!(update.top_p, None);
+    }
+
+    #[test]
+    fn allowed_models_limit_overrides() {
+        let mut agent = crate::agents::builtin_agents().remove(0);
+        assert!(agent.allows_model("gpt-5").is_ok());
+        agent.allowed_models = vec!["llama-3.1-8b-instant".to_string()];
+        assert!(agent.allows_model("llama-3.1-8b-instant").is_ok());
+        assert!(agent.allows_model(&agent.model.clone()).is_ok());
+        let err = agent.allows_model("gpt-5").unwrap_err();
+        assert!(err.contains("llama-3.1-8b-instant"), "{}", err);
+    }
+}
@@ -0,0 +1,380 @@
+//! OIDC authentication of operators.
+//!
+//! With `OIDC_ISSUER` set, the admin methods also accept an ID or access token
+//! from that OpenID Connect provider as `Authorization: Bearer <token>`. The
+//! provider's signing keys are found through its discovery document
+//! (`/.well-known/openid-configuration`) and cached; they are fetched again
+//! after an hour, or when a token names a key the cache doesn't hold. Tokens
+//! must be signed with an asymmetric algorithm, unexpired, issued by the
+//! issuer and, when `OIDC_AUDIENCE` is set, meant for that audience.
+//!
+//! The token's role claim decides what the operator may do ([`Access`]): any
+//! of the admin roles grants every admin method, any of the read-only roles
+//! only those that change nothing. A valid token without either role
+//! authenticates the caller but grants no admin access. The static
+//! `ADMIN_TOKEN` keeps working alongside. The tenant claim scopes operators to
+//! a tenant's agents and sessions, as it does for client JWTs.
+//!
+//! # Environment Variables
+//!
+//! * `OIDC_ISSUER` - Optional. Issuer URL, e.g.
+//!   `https://accounts.example.com/realms/valet`
+//! * `OIDC_AUDIENCE` - Optional. Required `aud` claim, usually the client ID
+//! * `OIDC_ROLES_CLAIM` - Optional. Claim holding the roles, as an array or a
+//!   space-separated string; dots reach into objects, as in Keycloak's
+//!   `realm_access.roles` (default: `roles`)
+//! * `OIDC_TENANT_CLAIM` - Optional. Claim naming the operator's tenant; dots
+//!   reach into objects as for roles (default: `tenant`)
+//! * `OIDC_ADMIN_ROLES` - Optional. Comma-separated roles granting admin
+//!   access (default: `admin`)
+//! * `OIDC_READONLY_ROLES` - Optional. Comma-separated roles granting
+//!   read-only access (default: `readonly`)
+
+use crate::auth::{Access, Identity};
+use crate::http_client::HttpClient;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long fetched signing keys are used before they are fetched again.
+const KEYS_TTL: Duration = Duration::from_secs(3600);
+
+/// Shortest time between fetches prompted by unknown key IDs.
+const REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where to find the provider and how to read its tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcConfig {
+    /// Expected `iss` claim, and the base of the discovery URL
+    pub issuer: String,
+    /// Expected `aud` claim, if checked
+    pub audience: Option<String>,
+    /// Path of the claim holding the roles
+    pub roles_claim: String,
+    /// Path of the claim naming the tenant
+    pub tenant_claim: String,
+    /// Roles granting [`Access::Admin`]
+    pub admin_roles: Vec<String>,
+    /// Roles granting [`Access::ReadOnly`]
+    pub readonly_roles: Vec<String>,
+}
+
+impl OidcConfig {
+    /// A config for `issuer` with the default claim and roles.
+    pub fn new(issuer: impl Into<String>) -> Self {
+        Self {
+            issuer: issuer.into(),
+            audience: None,
+            roles_claim: "roles".to_string(),
+            tenant_claim: "tenant".to_string(),
+            admin_roles: vec!["admin".to_string()],
+            readonly_roles: vec!["readonly".to_string()],
+        }
+    }
+
+    /// Reads the settings, or `None` if `OIDC_ISSUER` is unset.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let roles = |value: String| {
+            value
+                .split(',')
+                .map(|role| role.trim().to_string())
+                .filter(|role| !role.is_empty())
+                .collect()
+        };
+        let mut config = Self::new(var("OIDC_ISSUER")?.trim().trim_end_matches('/'));
+        config.audience = var("OIDC_AUDIENCE");
+        if let Some(claim) = var("OIDC_ROLES_CLAIM") {
+            config.roles_claim = claim;
+        }
+        if let Some(claim) = var("OIDC_TENANT_CLAIM") {
+            config.tenant_claim = claim;
+        }
+        if let Some(admin_roles) = var("OIDC_ADMIN_ROLES") {
+            config.admin_roles = roles(admin_roles);
+        }
+        if let Some(readonly_roles) = var("OIDC_READONLY_ROLES") {
+            config.readonly_roles = roles(readonly_roles);
+        }
+        Some(config)
+    }
+
+    /// The access granted by the roles in `claims`.
+    fn access(&self, claims: &Map<String, Value>) -> Access {
+        let roles: Vec<&str> = match claim(claims, &self.roles_claim) {
+            Some(Value::Array(roles)) => roles.iter().filter_map(Value::as_str).collect(),
+            Some(Value::String(roles)) => roles.split_whitespace().collect(),
+            _ => Vec::new(),
+        };
+        let has_any = |granting: &[String]| roles.iter().any(|r| granting.iter().any(|g| g == r));
+        if has_any(&self.admin_roles) {
+            Access::Admin
+        } else if has_any(&self.readonly_roles) {
+            Access::ReadOnly
+        } else {
+            Access::None
+        }
+    }
+
+    /// The tenant named in `claims`, if any.
+    fn tenant(&self, claims: &Map<String, Value>) -> Option<String> {
+        claim(claims, &self.tenant_claim)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    }
+}
+
+/// The claim at `path`, where dots reach into objects.
+fn claim<'a>(claims: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut path = path.split('.');
+    let first = path.next().and_then(|key| claims.get(key));
+    path.fold(first, |value, key| value?.get(key))
+}
+
+/// The part of the discovery document that locates the keys.
+#[derive(Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched: Instant,
+}
+
+struct Verifier {
+    config: OidcConfig,
+    client: HttpClient,
+    keys: Mutex<Option<CachedKeys>>,
+}
+
+/// Checks operator tokens against the provider's keys. Cheap to clone.
+#[derive(Clone)]
+pub struct OidcVerifier {
+    inner: Arc<Verifier>,
+}
+
+impl OidcVerifier {
+    /// Creates a verifier that fetches the provider's keys with `client`
+    /// when it first needs them.
+    pub fn new(config: OidcConfig, client: HttpClient) -> Self {
+        Self::with_keys(config, client, None)
+    }
+
+    /// Creates a verifier for `OIDC_ISSUER`, if it is set.
+    pub fn from_env(client: HttpClient) -> Option<Self> {
+        OidcConfig::from_env().map(|config| Self::new(config, client))
+    }
+
+    fn with_keys(config: OidcConfig, client: HttpClient, keys: Option<JwkSet>) -> Self {
+        Self {
+            inner: Arc::new(Verifier {
+                config,
+                client,
+                keys: Mutex::new(keys.map(|keys| CachedKeys {
+                    keys,
+                    fetched: Instant::now(),
+                })),
+            }),
+        }
+    }
+
+    /// The issuer tokens are checked against.
+    pub fn issuer(&self) -> &str {
+        &self.inner.config.issuer
+    }
+
+    /// Whether `token` claims to come from this provider, going by its
+    /// unverified `iss`; such tokens are checked with [`verify`](Self::verify)
+    /// rather than as client JWTs.
+    pub fn claims_issuer(&self, token: &str) -> bool {
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.insecure_disable_signature_validation();
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        validation.set_required_spec_claims::<&str>(&[]);
+        let key = DecodingKey::from_secret(&[]);
+        jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+            .is_ok_and(|data| data.claims.get("iss").and_then(Value::as_str) == Some(self.issuer()))
+    }
+
+    /// Checks `token` and returns who sent it and what they may do.
+    pub async fn verify(&self, token: &str) -> Result<(Identity, Access), String> {
+        let header = jsonwebtoken::decode_header(token).map_err(|e| e.to_string())?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(format!("{:?} tokens are not accepted", header.alg));
+        }
+        let kid = header.kid.ok_or("token has no kid")?;
+        let key = self.key(&kid).await?;
+
+        let config = &self.inner.config;
+        let mut validation = Validation::new(header.alg);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        validation.set_issuer(&[&config.issuer]);
+        match &config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|e| e.to_string())?
+            .claims;
+
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or("sub must be a string")?;
+        let identity = Identity {
+            subject: subject.to_string(),
+            tenant: config.tenant(&claims),
+        };
+        Ok((identity, config.access(&claims)))
+    }
+
+    /// The signing key `kid`, fetching the keys if they are stale or don't
+    /// include it.
+    async fn key(&self, kid: &str) -> Result<DecodingKey, String> {
+        let mut cached = self.inner.keys.lock().await;
+        let stale = cached.as_ref().is_none_or(|c| {
+            c.fetched.elapsed() > KEYS_TTL
+                || (c.keys.find(kid).is_none() && c.fetched.elapsed() > REFETCH_INTERVAL)
+        });
+        if stale {
+            match self.fetch_keys().await {
+                Ok(keys) => {
+                    *cached = Some(CachedKeys {
+                        keys,
+                        fetched: Instant::now(),
+                    })
+                }
+                // Keep using the keys we have while the provider is unreachable
+                Err(e) if cached.is_some() => tracing::warn!("Failed to refresh OIDC keys: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+        let jwk = cached
+            .as_ref()
+            .and_then(|c| c.keys.find(kid))
+            .ok_or_else(|| format!("unknown signing key {}", kid))?;
+        DecodingKey::from_jwk(jwk).map_err(|e| format!("unusable signing key {}: {}", kid, e))
+    }
+
+    /// Fetches the provider's keys through its discovery document.
+    async fn fetch_keys(&self) -> Result<JwkSet, String> {
+        let client = &self.inner.client;
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.inner.config.issuer
+        );
+        let discovery: Discovery = get_json(client, &url).await?;
+        let keys: JwkSet = get_json(client, &discovery.jwks_uri).await?;
+        tracing::info!("Fetched {} OIDC signing keys", keys.keys.len());
+        Ok(keys)
+    }
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(
+    client: &HttpClient,
+    url: &str,
+) -> Result<T, String> {
+    let response = client
+        .send_with_retry(client.get(url))
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid response from {}: {}", url, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use std::path::Path;
+
+    const ISSUER: &str = "https://accounts.example.com";
+
+    fn verifier(config: OidcConfig) -> OidcVerifier {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/oidc");
+        let keys = serde_json::from_str(&std::fs::read_to_string(dir.join("jwks.json")).unwrap());
+        OidcVerifier::with_keys(config, reqwest::Client::new().into(), Some(keys.unwrap()))
+    }
+
+    fn token(claims: Value) -> String {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/oidc");
+        let pem = std::fs::read(dir.join("signing_key.pem")).unwrap();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test-key".to_string());
+        encode(&header, &claims, &EncodingKey::from_rsa_pem(&pem).unwrap()).unwrap()
+    }
+
+    fn claims(roles: Value) -> Value {
+        json!({
+            "iss": ISSUER,
+            "sub": "operator-1",
+            "exp": chrono::Utc::now().timestamp() + 3600,
+            "realm_access": { "roles": roles },
+        })
+    }
+
+    #[tokio::test]
+    async fn maps_role_claims_to_access() {
+        let mut config = OidcConfig::new(ISSUER);
+        config.roles_claim = "realm_access.roles".to_string();
+        config.readonly_roles = vec!["auditor".to_string(), "viewer".to_string()];
+        let oidc = verifier(config);
+
+        let (identity, access) = oidc.verify(&token(claims(json!(["admin"])))).await.unwrap();
+        assert_eq!(identity.subject, "operator-1");
+        assert_eq!(identity.tenant, None);
+        assert_eq!(access, Access::Admin);
+        let viewer = token(claims(json!("offline viewer")));
+        assert_eq!(oidc.verify(&viewer).await.unwrap().1, Access::ReadOnly);
+        let (_, access) = oidc.verify(&token(claims(json!([])))).await.unwrap();
+        assert_eq!(access, Access::None);
+    }
+
+    #[tokio::test]
+    async fn reads_the_tenant_claim() {
+        let mut claims = claims(json!(["admin"]));
+        claims["tenant"] = json!("acme");
+        claims["org"] = json!({ "id": "globex" });
+
+        let oidc = verifier(OidcConfig::new(ISSUER));
+        let (identity, _) = oidc.verify(&token(claims.clone())).await.unwrap();
+        assert_eq!(identity.tenant.as_deref(), Some("acme"));
+
+        let mut config = OidcConfig::new(ISSUER);
+        config.tenant_claim = "org.id".to_string();
+        let (identity, _) = verifier(config).verify(&token(claims)).await.unwrap();
+        assert_eq!(identity.tenant.as_deref(), Some("globex"));
+    }
+
+    #[tokio::test]
+    async fn rejects_foreign_and_symmetric_tokens() {
+        let oidc = verifier(OidcConfig::new(ISSUER));
+        assert!(oidc.claims_issuer(&token(claims(json!(["admin"])))));
+        let mut foreign = claims(json!(["admin"]));
+        foreign["iss"] = json!("https://evil.example.com");
+        assert!(!oidc.claims_issuer(&token(foreign.clone())));
+        assert!(oidc.verify(&token(foreign)).await.is_err());
+
+        let hs256 = encode(
+            &Header::default(),
+            &claims(json!(["admin"])),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        let err = oidc.verify(&hs256).await.unwrap_err();
+        assert!(err.contains("HS256"), "{}", err);
+    }
+}
@@ -0,0 +1,1225 @@
+//! OpenRPC description of the JSON-RPC API.
+//!
+//! `GET /openrpc.json` serves an [OpenRPC](https://spec.open-rpc.org)
+//! document listing every method with its params and result, so clients can
+//! generate typed bindings instead of copying payloads from the README:
+//!
+//! ```text
+//! npx @open-rpc/generator generate -t client -l typescript -d http://localhost:3000/openrpc.json
+//! ```
+//!
+//! Schemas are built from the serde models the handlers read and answer
+//! with. Each model declares its fields once below with `object!` or
+//! `enumeration!`, which also checks at compile time that the declaration
+//! names every field or variant of the type with the right types, so adding
+//! a field without describing it fails the build. Fields are required
+//! unless they are `Option`s or marked `#[optional]`, for those with a serde
+//! default or left out when empty; `#[skip]` ones aren't serialized at all.
+//!
+//! Methods are tagged with the access they require, `admin` or `read-only`,
+//! as in [`handlers::required_access`]. The document is built once, on the
+//! first request. [`crate::schemas`] serves its schemas one model at a time.
+
+use crate::accounting::{BucketWidth, Percentiles, UsageReportParams, UsageReportResult, UsageRow};
+use crate::attachments::{AttachmentInfo, AttachmentInput};
+use crate::audit::{AuditOutcome, AuditQueryParams, AuditQueryResult, AuditRecord};
+use crate::auth::Access;
+use crate::budget::Budget;
+use crate::cache::{CacheStatus, FlushCacheParams, FlushCacheResult};
+use crate::cancellation::{CancelRequestParams, CancelRequestResult};
+use crate::embeddings::{EmbedTextParams, EmbedTextResult, EmbeddingMetadata};
+use crate::evals::{
+    AddEvalCaseParams, DeleteEvalCaseParams, DeleteEvalCaseResult, EvalCase, EvalCaseResult,
+    EvalCheck, EvalReport, Expectations, GraderVerdict, ListEvalCasesParams, ListEvalCasesResult,
+    RunEvalParams,
+};
+use crate::handlers;
+use crate::images::ImageInput;
+use crate::jobs::{JobParams, JobState, JobStatus, SubmitTextParams};
+use crate::knowledge::{
+    DeleteDocumentParams, Document, DocumentFormat, DocumentResult, IngestDocumentParams,
+    ListDocumentsParams, ListDocumentsResult,
+};
+use crate::language::LanguageMetadata;
+use crate::memories::{DeleteMemoriesParams, DeleteMemoriesResult, ListMemoriesResult, Memory};
+use crate::models::{
+    Agent, AgentResult, DeleteAgentParams, FindAgentsParams, FunctionCall, FunctionDefinition,
+    FunctionTool, GenerationParams, Implementation, InitializeParams, InitializeResult,
+    ListAgentsResult, ListCapability, Message, ProcessTextParams, ProcessTextResult,
+    ProcessingMetadata, ProviderFallback, ServerCapabilities, ToolCall, UpdateAgentParams,
+};
+use crate::pipelines::{
+    PipelineMetadata, PipelineStep, RunPipelineParams, RunPipelineResult, StepResult,
+};
+use crate::prompt_versions::{PromotePromptVersionParams, PromptVersion};
+use crate::prompts::{
+    GetPromptParams, GetPromptResult, ListPromptsResult, Prompt, PromptArgument, PromptMessage,
+};
+use crate::providers::keys::{KeyState, KeyStatus, RotateKeysParams, RotateKeysResult};
+use crate::providers::FinishReason;
+use crate::resources::{
+    ListResourcesResult, ReadResourceParams, ReadResourceResult, Resource, ResourceContents,
+};
+use crate::scripts::AgentScripts;
+use crate::speech::{AudioFormat, SpeechMetadata, SynthesizeSpeechParams, SynthesizeSpeechResult};
+use crate::style::{ReadingLevel, Style, Tone, Verbosity};
+use crate::tools::{CallToolParams, CallToolResult, Content, ListToolsResult, Tool};
+use crate::voice::{
+    ProcessAudioMetadata, ProcessAudioParams, ProcessAudioResult, TranscriptionMetadata,
+};
+use axum::Json;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+/// Version of the OpenRPC specification the document follows.
+pub const OPENRPC_VERSION: &str = "1.2.6";
+
+/// A type with a JSON Schema.
+pub trait Schema {
+    /// The schema of the type, or a `$ref` to it in `components` for named
+    /// types.
+    fn schema(components: &mut Components) -> Value;
+
+    /// Whether fields of the type may be left out.
+    fn optional() -> bool {
+        false
+    }
+}
+
+/// The named schemas of a document, under `#/components/schemas`.
+#[derive(Debug, Default)]
+pub struct Components {
+    schemas: Map<String, Value>,
+}
+
+impl Components {
+    /// A `$ref` to the schema named `name`, built with `build` the first
+    /// time.
+    ///
+    /// The name is taken before building, so recursive types end in a
+    /// `$ref` to themselves.
+    pub fn define(&mut self, name: &str, build: impl FnOnce(&mut Self) -> Value) -> Value {
+        if !self.schemas.contains_key(name) {
+            self.schemas.insert(name.to_string(), Value::Null);
+            let schema = build(self);
+            self.schemas.insert(name.to_string(), schema);
+        }
+        json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+
+    /// The schema `schema` refers to, or `schema` itself if it isn't a
+    /// `$ref`.
+    pub fn resolve<'a>(&'a self, schema: &'a Value) -> &'a Value {
+        schema["$ref"]
+            .as_str()
+            .and_then(|r| r.strip_prefix("#/components/schemas/"))
+            .and_then(|name| self.schemas.get(name))
+            .unwrap_or(schema)
+    }
+
+    /// Adds the properties of `T` to those of an object, as
+    /// `#[serde(flatten)]` does.
+    fn flatten<T: Schema>(
+        &mut self,
+        properties: &mut Map<String, Value>,
+        required: &mut Vec<Value>,
+    ) {
+        let schema = T::schema(self);
+        let schema = self.resolve(&schema);
+        if let Some(flattened) = schema["properties"].as_object() {
+            properties.extend(flattened.clone());
+        }
+        if let Some(flattened) = schema["required"].as_array() {
+            required.extend(flattened.iter().cloned());
+        }
+    }
+
+    /// The schemas defined so far.
+    pub fn into_schemas(self) -> Map<String, Value> {
+        self.schemas
+    }
+}
+
+/// An object schema from its properties and the required ones among them.
+fn object_schema(properties: Map<String, Value>, required: Vec<Value>) -> Value {
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = Value::Array(required);
+    }
+    schema
+}
+
+/// Name of a field in JSON: `field`, in camelCase if `case` says so.
+fn field_name(field: &str, case: &str) -> String {
+    if case != "camel_case" {
+        return field.to_string();
+    }
+    let mut parts = field.split('_');
+    let mut name = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+    }
+    name
+}
+
+/// Implements [`Schema`] for a struct from its fields.
+///
+/// `#[camel_case]` before the type follows `#[serde(rename_all =
+/// "camelCase")]`, `field as "name"` a `#[serde(rename)]`, and the
+/// `flatten` block `#[serde(flatten)]` fields.
+macro_rules! object {
+    (
+        $(#[$case:ident])? $ty:ident {
+            $($(#[$attr:ident])? $field:ident $(as $rename:literal)?: $fty:ty),* $(,)?
+        }
+        $(flatten { $($flat:ident: $flat_ty:ty),* $(,)? })?
+    ) => {
+        impl Schema for $ty {
+            fn schema(components: &mut Components) -> Value {
+                components.define(stringify!($ty), |components| {
+                    let mut properties = Map::new();
+                    #[allow(unused_mut)]
+                    let mut required = Vec::new();
+                    let case = stringify!($($case)?);
+                    $(
+                        #[allow(unused_variables)]
+                        let name = field_name(stringify!($field), case);
+                        $(let name = $rename.to_string();)?
+                        property!([$($attr)?] components, properties, required, name, $fty);
+                    )*
+                    $($(components.flatten::<$flat_ty>(&mut properties, &mut required);)*)?
+                    object_schema(properties, required)
+                })
+            }
+        }
+
+        // Fails to compile when the type gains, loses or changes a field
+        const _: () = {
+            #[allow(dead_code)]
+            fn fields(value: &$ty) {
+                let $ty { $($field: _,)* $($($flat: _,)*)? } = value;
+                $(let _: &$fty = &value.$field;)*
+                $($(let _: &$flat_ty = &value.$flat;)*)?
+            }
+        };
+    };
+}
+
+/// Adds a field to the properties of an [`object!`].
+macro_rules! property {
+    ([] $components:ident, $properties:ident, $required:ident, $name:ident, $fty:ty) => {
+        if !<$fty as Schema>::optional() {
+            $required.push(Value::String($name.clone()));
+        }
+        $properties.insert($name, <$fty as Schema>::schema($components));
+    };
+    ([optional] $components:ident, $properties:ident, $required:ident, $name:ident, $fty:ty) => {
+        $properties.insert($name, <$fty as Schema>::schema($components));
+    };
+    ([skip] $components:ident, $properties:ident, $required:ident, $name:ident, $fty:ty) => {
+        let _ = $name;
+    };
+}
+
+/// Implements [`Schema`] for a unit-only enum from its variants and their
+/// names in JSON.
+macro_rules! enumeration {
+    ($ty:ident { $($variant:ident = $name:literal),* $(,)? }) => {
+        impl Schema for $ty {
+            fn schema(components: &mut Components) -> Value {
+                components.define(stringify!($ty), |_| {
+                    json!({ "type": "string", "enum": [$($name),*] })
+                })
+            }
+        }
+
+        // Fails to compile when the type gains or loses a variant
+        const _: () = {
+            #[allow(dead_code)]
+            fn variants(value: &$ty) {
+                match value {
+                    $($ty::$variant => {})*
+                }
+            }
+        };
+    };
+}
+
+impl Schema for () {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "object", "properties": {} })
+    }
+}
+
+impl Schema for String {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for &'static str {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for bool {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+macro_rules! integer {
+    ($($ty:ty),* ; $($unsigned:ty),*) => {
+        $(impl Schema for $ty {
+            fn schema(_: &mut Components) -> Value {
+                json!({ "type": "integer" })
+            }
+        })*
+        $(impl Schema for $unsigned {
+            fn schema(_: &mut Components) -> Value {
+                json!({ "type": "integer", "minimum": 0 })
+            }
+        })*
+    };
+}
+
+integer!(i32, i64; u8, u32, u64, usize);
+
+impl Schema for f32 {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl Schema for f64 {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl Schema for Value {
+    fn schema(_: &mut Components) -> Value {
+        json!({})
+    }
+}
+
+impl Schema for Map<String, Value> {
+    fn schema(_: &mut Components) -> Value {
+        json!({ "type": "object" })
+    }
+}
+
+impl<T: Schema> Schema for HashMap<String, T> {
+    fn schema(components: &mut Components) -> Value {
+        json!({ "type": "object", "additionalProperties": T::schema(components) })
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema(components: &mut Components) -> Value {
+        let schema = T::schema(components);
+        if T::optional() {
+            return schema;
+        }
+        json!({ "oneOf": [schema, { "type": "null" }] })
+    }
+
+    fn optional() -> bool {
+        true
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema(components: &mut Components) -> Value {
+        json!({ "type": "array", "items": T::schema(components) })
+    }
+}
+
+impl<T: Schema> Schema for &'static [T] {
+    fn schema(components: &mut Components) -> Value {
+        json!({ "type": "array", "items": T::schema(components) })
+    }
+}
+
+impl<T: Schema> Schema for Arc<T> {
+    fn schema(components: &mut Components) -> Value {
+        T::schema(components)
+    }
+}
+
+impl Schema for Content {
+    fn schema(components: &mut Components) -> Value {
+        components.define("Content", |_| {
+            json!({
+                "type": "object",
+                "properties": { "type": { "const": "text" }, "text": { "type": "string" } },
+                "required": ["type", "text"],
+            })
+        })
+    }
+}
+
+// Fails to compile when `Content` gains a variant
+const _: () = {
+    #[allow(dead_code)]
+    fn variants(value: &Content) {
+        match value {
+            Content::Text { text: _ } => {}
+        }
+    }
+};
+
+// MCP
+
+object! { Implementation { name: String, version: String } }
+object! {
+    #[camel_case] InitializeParams {
+        #[optional] protocol_version: Option<String>,
+        #[optional] capabilities: Value,
+        #[optional] client_info: Option<Implementation>,
+    }
+}
+object! {
+    ServerCapabilities {
+        tools: Option<ListCapability>,
+        resources: Option<ListCapability>,
+        prompts: Option<ListCapability>,
+    }
+}
+object! { #[camel_case] ListCapability { list_changed: bool } }
+object! {
+    #[camel_case] InitializeResult {
+        protocol_version: String,
+        capabilities: ServerCapabilities,
+        server_info: Implementation,
+        instructions: Option<String>,
+    }
+}
+object! { #[camel_case] Tool { name: String, description: String, input_schema: Value } }
+object! { ListToolsResult { tools: Vec<Tool> } }
+object! { CallToolParams { name: String, #[optional] arguments: Value } }
+object! {
+    #[camel_case] CallToolResult {
+        content: Vec<Content>,
+        #[optional] is_error: bool,
+        structured_content: Option<Value>,
+    }
+}
+object! {
+    #[camel_case] Resource {
+        uri: String,
+        name: String,
+        description: Option<String>,
+        mime_type: String,
+    }
+}
+object! { ListResourcesResult { resources: Vec<Resource> } }
+object! { ReadResourceParams { uri: String } }
+object! { #[camel_case] ResourceContents { uri: String, mime_type: String, text: String } }
+object! { ReadResourceResult { contents: Vec<ResourceContents> } }
+object! {
+    PromptArgument {
+        name: &'static str,
+        description: &'static str,
+        required: bool,
+        #[skip] default: &'static str,
+    }
+}
+object! {
+    Prompt {
+        name: &'static str,
+        description: &'static str,
+        arguments: &'static [PromptArgument],
+        #[skip] agent_id: &'static str,
+        #[skip] template: &'static str,
+    }
+}
+object! { ListPromptsResult { prompts: &'static [Prompt] } }
+object! { GetPromptParams { name: String, #[optional] arguments: HashMap<String, String> } }
+object! { PromptMessage { role: &'static str, content: Content } }
+object! { GetPromptResult { description: String, messages: Vec<PromptMessage> } }
+
+// Agents
+
+object! {
+    Agent {
+        id: String,
+        name: String,
+        description: String,
+        capabilities: Vec<String>,
+        model: String,
+        system_prompt: String,
+        prompt_version: Option<String>,
+        #[optional] prompt_versions: Vec<PromptVersion>,
+        #[optional] stop: Vec<String>,
+        temperature: Option<f64>,
+        max_tokens: Option<u32>,
+        top_p: Option<f64>,
+        #[optional] fallbacks: Vec<ProviderFallback>,
+        tenant: Option<String>,
+        #[optional] delimit_user_input: bool,
+        #[optional] allow_system_messages: bool,
+        knowledge_base: Option<String>,
+        #[optional] allowed_models: Vec<String>,
+        #[optional] scripts: AgentScripts,
+    }
+}
+object! { AgentScripts { pre_request: Option<String>, post_response: Option<String> } }
+object! { PromptVersion { version: String, system_prompt: String, #[optional] weight: u8 } }
+object! { ProviderFallback { provider: String, model: Option<String> } }
+object! { ListAgentsResult { agents: Vec<Arc<Agent>> } }
+object! {
+    FindAgentsParams {
+        #[optional] capabilities: Vec<String>,
+        query: Option<String>,
+        limit: Option<usize>,
+    }
+}
+object! {
+    UpdateAgentParams {
+        agent_id: String,
+        name: Option<String>,
+        description: Option<String>,
+        capabilities: Option<Vec<String>>,
+        model: Option<String>,
+        system_prompt: Option<String>,
+        prompt_version: Option<String>,
+        prompt_versions: Option<Vec<PromptVersion>>,
+        stop: Option<Vec<String>>,
+        temperature: Option<Option<f64>>,
+        max_tokens: Option<Option<u32>>,
+        top_p: Option<Option<f64>>,
+        fallbacks: Option<Vec<ProviderFallback>>,
+        delimit_user_input: Option<bool>,
+        allow_system_messages: Option<bool>,
+        knowledge_base: Option<String>,
+        allowed_models: Option<Vec<String>>,
+        scripts: Option<AgentScripts>,
+    }
+}
+object! { DeleteAgentParams { agent_id: String } }
+object! { PromotePromptVersionParams { agent_id: String, version: String } }
+object! { AgentResult { agent: Arc<Agent> } }
+
+// Text processing
+
+object! {
+    GenerationParams {
+        temperature: Option<f64>,
+        max_tokens: Option<u32>,
+        top_p: Option<f64>,
+        frequency_penalty: Option<f64>,
+        stop: Option<Vec<String>>,
+        seed: Option<i32>,
+    }
+}
+object! { Budget { max_total_tokens: Option<u32>, max_cost_usd: Option<f64> } }
+object! {
+    ProcessTextParams {
+        agent_id: String,
+        user_text: String,
+        conversation_history: Option<Vec<Message>>,
+        session_id: Option<String>,
+        model: Option<String>,
+        tools: Option<Vec<FunctionTool>>,
+        timeout_ms: Option<u64>,
+        images: Option<Vec<ImageInput>>,
+        attachments: Option<Vec<AttachmentInput>>,
+        reply_language: Option<String>,
+        style: Option<Style>,
+        #[optional] no_store: bool,
+    } flatten { generation: GenerationParams, budget: Budget }
+}
+object! {
+    Style {
+        tone: Option<Tone>,
+        verbosity: Option<Verbosity>,
+        reading_level: Option<ReadingLevel>,
+        emoji: Option<bool>,
+    }
+}
+enumeration! {
+    Tone { Neutral = "neutral", Friendly = "friendly", Formal = "formal", Playful = "playful" }
+}
+enumeration! { Verbosity { Brief = "brief", Normal = "normal", Detailed = "detailed" } }
+enumeration! { ReadingLevel { Simple = "simple", General = "general", Expert = "expert" } }
+object! {
+    Message {
+        role: String,
+        #[optional] content: String,
+        #[optional] tool_calls: Vec<ToolCall>,
+        tool_call_id: Option<String>,
+    }
+}
+object! { FunctionTool { kind as "type": String, function: FunctionDefinition } }
+object! {
+    FunctionDefinition {
+        name: String,
+        description: Option<String>,
+        parameters: Option<Value>,
+    }
+}
+object! { ToolCall { id: String, kind as "type": String, function: FunctionCall } }
+object! { FunctionCall { name: String, arguments: String } }
+object! { ImageInput { data: Option<String>, url: Option<String>, mime_type: Option<String> } }
+object! {
+    AttachmentInput {
+        name: String,
+        text: Option<String>,
+        data: Option<String>,
+        mime_type: Option<String>,
+    }
+}
+object! {
+    ProcessTextResult {
+        agent_id: String,
+        reply_text: String,
+        #[optional] tool_calls: Vec<ToolCall>,
+        metadata: ProcessingMetadata,
+    }
+}
+object! {
+    ProcessingMetadata {
+        #[optional] provider: String,
+        model: String,
+        tokens_used: Option<u32>,
+        cost_usd: Option<f64>,
+        processing_time_ms: u64,
+        finish_reason: Option<FinishReason>,
+        confidence: Option<f64>,
+        seed: Option<i32>,
+        cache: Option<CacheStatus>,
+        correlation_id: Option<String>,
+        #[optional] sources: Vec<String>,
+        #[optional] attachments: Vec<AttachmentInfo>,
+        language: Option<LanguageMetadata>,
+        prompt_version: Option<String>,
+        #[optional] no_store: bool,
+    }
+}
+enumeration! {
+    FinishReason {
+        Stop = "stop",
+        Length = "length",
+        ContentFilter = "content_filter",
+        ToolCalls = "tool_calls",
+        Other = "other",
+    }
+}
+enumeration! { CacheStatus { Hit = "hit", Miss = "miss", Shared = "shared" } }
+object! {
+    AttachmentInfo {
+        name: String,
+        mime_type: String,
+        bytes: usize,
+        chars: usize,
+        #[optional] truncated: bool,
+    }
+}
+object! {
+    LanguageMetadata {
+        detected: Option<String>,
+        confidence: Option<f32>,
+        reply: Option<String>,
+    }
+}
+object! {
+    RunPipelineParams {
+        pipeline_id: Option<String>,
+        steps: Option<Vec<PipelineStep>>,
+        user_text: String,
+        timeout_ms: Option<u64>,
+        #[optional] no_store: bool,
+    }
+}
+object! {
+    PipelineStep {
+        agent_id: String,
+        instructions: Option<String>,
+        model: Option<String>,
+    } flatten { generation: GenerationParams }
+}
+object! {
+    RunPipelineResult {
+        pipeline_id: Option<String>,
+        reply_text: String,
+        steps: Vec<StepResult>,
+        metadata: PipelineMetadata,
+    }
+}
+object! { StepResult { agent_id: String, reply_text: String, metadata: ProcessingMetadata } }
+object! { PipelineMetadata { tokens_used: Option<u32>, processing_time_ms: u64 } }
+
+// Jobs and cancellation
+
+object! { SubmitTextParams { callback_url: Option<String> } flatten { process: ProcessTextParams } }
+object! { JobParams { job_id: String } }
+object! {
+    JobStatus {
+        job_id: String,
+        status: JobState,
+        agent_id: String,
+        created_at: String,
+        started_at: Option<String>,
+        finished_at: Option<String>,
+    }
+}
+enumeration! {
+    JobState {
+        Queued = "queued",
+        Running = "running",
+        Succeeded = "succeeded",
+        Failed = "failed",
+    }
+}
+object! { #[camel_case] CancelRequestParams { request_id: Value, reason: Option<String> } }
+object! { CancelRequestResult { cancelled: bool } }
+
+// Operations
+
+object! { FlushCacheParams { agent_id: Option<String> } }
+object! { FlushCacheResult { flushed: usize } }
+object! { RotateKeysParams { provider: String, api_keys: Vec<String> } }
+enumeration! { KeyState { Ok = "ok", RateLimited = "rate_limited", Rejected = "rejected" } }
+object! {
+    KeyStatus {
+        key: String,
+        state: KeyState,
+        retry_after_secs: Option<u64>,
+        calls: u64,
+    }
+}
+object! { RotateKeysResult { provider: String, keys: Vec<KeyStatus> } }
+object! {
+    UsageReportParams {
+        from: Option<String>,
+        to: Option<String>,
+        #[optional] bucket: BucketWidth,
+        agent_id: Option<String>,
+    }
+}
+enumeration! { BucketWidth { Hour = "hour", Day = "day" } }
+object! { UsageReportResult { from: String, to: String, bucket: BucketWidth, rows: Vec<UsageRow> } }
+object! {
+    UsageRow {
+        agent_id: String,
+        bucket_start: String,
+        requests: u64,
+        errors: u64,
+        error_rate: f64,
+        tokens: u64,
+        cost_usd: f64,
+        latency_ms: Option<Percentiles>,
+        provider_calls: u64,
+        provider_latency_ms: Option<Percentiles>,
+        tokens_per_sec: Option<f64>,
+    }
+}
+object! { Percentiles { p50: u64, p90: u64, p99: u64 } }
+object! {
+    AuditQueryParams {
+        from: Option<String>,
+        to: Option<String>,
+        client_id: Option<String>,
+        method: Option<String>,
+        agent_id: Option<String>,
+        correlation_id: Option<String>,
+        prompt_version: Option<String>,
+        outcome: Option<AuditOutcome>,
+        before_id: Option<i64>,
+        limit: Option<u32>,
+    }
+}
+object! { AuditQueryResult { records: Vec<AuditRecord>, next_before_id: Option<i64> } }
+object! {
+    AuditRecord {
+        id: i64,
+        timestamp: String,
+        client_id: Option<String>,
+        method: String,
+        agent_id: Option<String>,
+        tokens: Option<u64>,
+        outcome: AuditOutcome,
+        error_code: Option<i32>,
+        duration_ms: u64,
+        correlation_id: Option<String>,
+        prompt_version: Option<String>,
+    }
+}
+enumeration! { AuditOutcome { Ok = "ok", Error = "error" } }
+
+// Embeddings, knowledge bases and memories
+
+object! { EmbedTextParams { texts: Vec<String>, provider: Option<String>, model: Option<String> } }
+object! { EmbedTextResult { embeddings: Vec<Vec<f32>>, metadata: EmbeddingMetadata } }
+object! {
+    EmbeddingMetadata {
+        provider: String,
+        model: String,
+        dimensions: usize,
+        tokens_used: Option<u32>,
+        processing_time_ms: u64,
+    }
+}
+object! {
+    IngestDocumentParams {
+        knowledge_base: String,
+        document_id: String,
+        text: Option<String>,
+        url: Option<String>,
+        format: Option<DocumentFormat>,
+        #[optional] metadata: Map<String, Value>,
+    }
+}
+enumeration! { DocumentFormat { Text = "text", Markdown = "markdown", Html = "html" } }
+object! { DeleteDocumentParams { knowledge_base: String, document_id: String } }
+object! { DocumentResult { knowledge_base: String, document_id: String, chunks: usize } }
+object! { ListDocumentsParams { knowledge_base: String } }
+object! { ListDocumentsResult { knowledge_base: String, documents: Vec<Document> } }
+object! {
+    Document {
+        id: String,
+        #[optional] format: DocumentFormat,
+        source: Option<String>,
+        #[optional] metadata: Map<String, Value>,
+        #[optional] chunks: usize,
+        #[optional] chars: usize,
+        #[optional] ingested_at: String,
+    }
+}
+object! { ListMemoriesResult { memories: Vec<Memory> } }
+object! { Memory { id: i64, fact: String, agent_id: Option<String>, created_at: String } }
+object! { DeleteMemoriesParams { memory_id: Option<i64> } }
+object! { DeleteMemoriesResult { deleted: usize } }
+
+// Speech
+
+object! {
+    SynthesizeSpeechParams {
+        text: String,
+        voice: Option<String>,
+        #[optional] format: AudioFormat,
+        speed: Option<f32>,
+        #[optional] return_url: bool,
+    }
+}
+enumeration! { AudioFormat { Mp3 = "mp3", Opus = "opus", Aac = "aac", Flac = "flac", Wav = "wav" } }
+object! {
+    SynthesizeSpeechResult {
+        audio: Option<String>,
+        url: Option<String>,
+        expires_at: Option<String>,
+        mime_type: String,
+        metadata: SpeechMetadata,
+    }
+}
+object! {
+    SpeechMetadata {
+        provider: String,
+        model: String,
+        voice: String,
+        characters: usize,
+        bytes: Option<usize>,
+        processing_time_ms: u64,
+    }
+}
+object! {
+    ProcessAudioParams {
+        agent_id: String,
+        audio: String,
+        mime_type: Option<String>,
+        language: Option<String>,
+        conversation_history: Option<Vec<Message>>,
+        session_id: Option<String>,
+        model: Option<String>,
+        reply_language: Option<String>,
+        style: Option<Style>,
+        voice: Option<String>,
+        #[optional] format: AudioFormat,
+        speed: Option<f32>,
+        #[optional] return_url: bool,
+        #[optional] stream: bool,
+        #[optional] no_store: bool,
+    } flatten { generation: GenerationParams }
+}
+object! {
+    ProcessAudioResult {
+        transcript: String,
+        agent_id: String,
+        reply_text: String,
+        #[optional] tool_calls: Vec<ToolCall>,
+        audio: Option<String>,
+        url: Option<String>,
+        expires_at: Option<String>,
+        mime_type: Option<String>,
+        metadata: ProcessAudioMetadata,
+    }
+}
+object! {
+    ProcessAudioMetadata {
+        transcription: TranscriptionMetadata,
+        agent: ProcessingMetadata,
+        speech: Option<SpeechMetadata>,
+        total_time_ms: u64,
+    }
+}
+object! { TranscriptionMetadata { provider: String, model: String, processing_time_ms: u64 } }
+
+// Evaluations
+
+object! {
+    AddEvalCaseParams {
+        agent_id: String,
+        name: Option<String>,
+        input: String,
+        #[optional] expected: Expectations,
+        grader_prompt: Option<String>,
+    }
+}
+object! {
+    Expectations {
+        #[optional] contains: Vec<String>,
+        #[optional] not_contains: Vec<String>,
+        matches: Option<String>,
+        max_chars: Option<usize>,
+        language: Option<String>,
+    }
+}
+object! {
+    EvalCase {
+        id: i64,
+        agent_id: String,
+        name: Option<String>,
+        input: String,
+        #[optional] expected: Expectations,
+        grader_prompt: Option<String>,
+        created_at: String,
+    }
+}
+object! { ListEvalCasesParams { agent_id: String } }
+object! { ListEvalCasesResult { cases: Vec<EvalCase> } }
+object! { DeleteEvalCaseParams { case_id: i64 } }
+object! { DeleteEvalCaseResult { deleted: usize } }
+object! {
+    RunEvalParams {
+        agent_id: String,
+        case_ids: Option<Vec<i64>>,
+        prompt_version: Option<String>,
+    }
+}
+object! {
+    EvalReport {
+        agent_id: String,
+        prompt_version: Option<String>,
+        passed: usize,
+        failed: usize,
+        score: f64,
+        cases: Vec<EvalCaseResult>,
+        total_time_ms: u64,
+    }
+}
+object! {
+    EvalCaseResult {
+        case_id: i64,
+        name: Option<String>,
+        passed: bool,
+        score: f64,
+        reply_text: Option<String>,
+        #[optional] checks: Vec<EvalCheck>,
+        grader: Option<GraderVerdict>,
+        error: Option<String>,
+        processing_time_ms: u64,
+    }
+}
+object! { EvalCheck { check: String, passed: bool } }
+object! { GraderVerdict { score: f64, #[optional] reason: String } }
+
+/// The OpenRPC method object of `name`, taking the fields of `P` as named
+/// params and answering with `R`.
+fn method<P: Schema, R: Schema>(components: &mut Components, name: &str, summary: &str) -> Value {
+    let params = P::schema(components);
+    let params = components.resolve(&params);
+    let required: Vec<&str> = params["required"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let params: Vec<Value> = params["properties"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(param, schema)| {
+            json!({
+                "name": param,
+                "required": required.contains(&param.as_str()),
+                "schema": schema,
+            })
+        })
+        .collect();
+    let mut method = json!({
+        "name": name,
+        "summary": summary,
+        "paramStructure": "by-name",
+        "params": params,
+        "result": { "name": "result", "schema": R::schema(components) },
+    });
+    match handlers::required_access(name) {
+        Access::Admin => method["tags"] = json!([{ "name": "admin" }]),
+        Access::ReadOnly => method["tags"] = json!([{ "name": "read-only" }]),
+        Access::None => {}
+    }
+    method
+}
+
+/// Builds the OpenRPC document of every JSON-RPC method.
+pub fn document() -> Value {
+    let mut c = Components::default();
+    let methods = vec![
+        method::<InitializeParams, InitializeResult>(
+            &mut c,
+            "initialize",
+            "Starts an MCP session, agreeing on the protocol version",
+        ),
+        method::<(), ()>(&mut c, "ping", "Checks that the server is up"),
+        method::<(), ListToolsResult>(&mut c, "tools/list", "Lists the MCP tools"),
+        method::<CallToolParams, CallToolResult>(&mut c, "tools/call", "Calls an MCP tool"),
+        method::<(), ListResourcesResult>(&mut c, "resources/list", "Lists the MCP resources"),
+        method::<ReadResourceParams, ReadResourceResult>(
+            &mut c,
+            "resources/read",
+            "Reads an MCP resource",
+        ),
+        method::<(), ListPromptsResult>(&mut c, "prompts/list", "Lists the MCP prompt templates"),
+        method::<GetPromptParams, GetPromptResult>(
+            &mut c,
+            "prompts/get",
+            "Fills in an MCP prompt template",
+        ),
+        method::<(), ListAgentsResult>(&mut c, "list_agents", "Lists the agents"),
+        method::<FindAgentsParams, ListAgentsResult>(
+            &mut c,
+            "find_agents",
+            "Finds agents by capabilities and free text",
+        ),
+        method::<ProcessTextParams, ProcessTextResult>(
+            &mut c,
+            "process_text",
+            "Asks an agent to answer a text",
+        ),
+        method::<RunPipelineParams, RunPipelineResult>(
+            &mut c,
+            "run_pipeline",
+            "Runs a text through a chain of agents",
+        ),
+        method::<SubmitTextParams, JobStatus>(
+            &mut c,
+            "submit_text",
+            "Queues a process_text request as a background job",
+        ),
+        method::<JobParams, JobStatus>(&mut c, "get_job_status", "Reports the state of a job"),
+        method::<JobParams, ProcessTextResult>(
+            &mut c,
+            "get_job_result",
+            "Returns the result of a finished job",
+        ),
+        method::<CancelRequestParams, CancelRequestResult>(
+            &mut c,
+            "cancel_request",
+            "Aborts an in-flight request",
+        ),
+        method::<Agent, AgentResult>(&mut c, "create_agent", "Adds an agent"),
+        method::<UpdateAgentParams, AgentResult>(&mut c, "update_agent", "Changes an agent"),
+        method::<DeleteAgentParams, AgentResult>(&mut c, "delete_agent", "Removes an agent"),
+        method::<PromotePromptVersionParams, AgentResult>(
+            &mut c,
+            "promote_prompt_version",
+            "Makes a version of an agent's system prompt the live one",
+        ),
+        method::<FlushCacheParams, FlushCacheResult>(
+            &mut c,
+            "flush_cache",
+            "Empties the response cache",
+        ),
+        method::<RotateKeysParams, RotateKeysResult>(
+            &mut c,
+            "rotate_provider_keys",
+            "Replaces the API keys of a provider",
+        ),
+        method::<UsageReportParams, UsageReportResult>(
+            &mut c,
+            "usage_report",
+            "Reports requests, tokens, cost and latency over time",
+        ),
+        method::<AuditQueryParams, AuditQueryResult>(&mut c, "audit_log", "Searches the audit log"),
+        method::<EmbedTextParams, EmbedTextResult>(&mut c, "embed_text", "Embeds texts as vectors"),
+        method::<IngestDocumentParams, DocumentResult>(
+            &mut c,
+            "ingest_document",
+            "Adds a document to a knowledge base",
+        ),
+        method::<DeleteDocumentParams, DocumentResult>(
+            &mut c,
+            "delete_document",
+            "Removes a document from a knowledge base",
+        ),
+        method::<ListDocumentsParams, ListDocumentsResult>(
+            &mut c,
+            "list_documents",
+            "Lists the documents of a knowledge base",
+        ),
+        method::<(), ListMemoriesResult>(
+            &mut c,
+            "list_memories",
+            "Lists the facts remembered about the caller",
+        ),
+        method::<DeleteMemoriesParams, DeleteMemoriesResult>(
+            &mut c,
+            "delete_memories",
+            "Forgets facts remembered about the caller",
+        ),
+        method::<SynthesizeSpeechParams, SynthesizeSpeechResult>(
+            &mut c,
+            "synthesize_speech",
+            "Reads a text aloud",
+        ),
+        method::<ProcessAudioParams, ProcessAudioResult>(
+            &mut c,
+            "process_audio",
+            "Transcribes a recording, has an agent answer it and reads the answer aloud",
+        ),
+        method::<AddEvalCaseParams, EvalCase>(
+            &mut c,
+            "add_eval_case",
+            "Stores an evaluation case for an agent",
+        ),
+        method::<ListEvalCasesParams, ListEvalCasesResult>(
+            &mut c,
+            "list_eval_cases",
+            "Lists the evaluation cases of an agent",
+        ),
+        method::<DeleteEvalCaseParams, DeleteEvalCaseResult>(
+            &mut c,
+            "delete_eval_case",
+            "Removes an evaluation case",
+        ),
+        method::<RunEvalParams, EvalReport>(
+            &mut c,
+            "run_eval",
+            "Runs the evaluation cases of an agent and scores its replies",
+        ),
+    ];
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "JSON-RPC 2.0 API of the MCP server's AI agents",
+        },
+        "methods": methods,
+        "components": { "schemas": c.into_schemas() },
+    })
+}
+
+/// The [`document`], built on the first call.
+pub fn cached() -> &'static Value {
+    static DOCUMENT: OnceLock<Value> = OnceLock::new();
+    DOCUMENT.get_or_init(document)
+}
+
+/// `GET /openrpc.json` - the OpenRPC document of the JSON-RPC API.
+pub async fn openrpc_handler() -> Json<Value> {
+    Json(cached().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditLog;
+    use crate::load_shed::{LoadShedConfig, LoadShedder};
+    use crate::models::JsonRpcRequest;
+    use crate::sessions::MemorySessionStore;
+    use crate::AppState;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn describes_every_method() {
+        let document = document();
+        let methods = document["methods"].as_array().unwrap();
+        let names: Vec<&str> = methods
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        for method in handlers::ADMIN_METHODS
+            .iter()
+            .chain(handlers::READ_ONLY_METHODS)
+        {
+            assert!(names.contains(method), "{} is not described", method);
+        }
+
+        // Every described method is routed
+        let state = Arc::new(AppState {
+            http_client: reqwest::Client::new().into(),
+            providers: Default::default(),
+            agents: Default::default(),
+            admin_token: None,
+            jwt: None,
+            oidc: None,
+            reporter: Default::default(),
+            shedder: Arc::new(LoadShedder::new(LoadShedConfig::from_env())),
+            sessions: Arc::new(MemorySessionStore::default()),
+            history: Default::default(),
+            limits: Default::default(),
+            server_tools: Default::default(),
+            in_flight: Default::default(),
+            jobs: Default::default(),
+            cache: Default::default(),
+            coalescer: Default::default(),
+            quotas: Default::default(),
+            accounting: Default::default(),
+            guardrails: Default::default(),
+            plugins: Default::default(),
+            audit: Some(AuditLog::open(Path::new(":memory:")).unwrap()),
+            access_log: Default::default(),
+            metrics: Default::default(),
+            knowledge: Default::default(),
+            memories: Default::default(),
+            evals: Default::default(),
+            speech: Default::default(),
+        });
+        for name in &names {
+            let request = JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                method: name.to_string(),
+                params: Some(json!({ "agent_id": "agent_404" })),
+                id: Some(json!(1)),
+            };
+            let response = handlers::dispatch(&state, request, Default::default(), Access::Admin)
+                .await
+                .unwrap();
+            assert_ne!(response.error.map(|e| e.code), Some(-32601), "{}", name);
+        }
+
+        let process_text = &methods[names.iter().position(|n| *n == "process_text").unwrap()];
+        let params = process_text["params"].as_array().unwrap();
+        let param = |name: &str| params.iter().find(|p| p["name"] == name).unwrap();
+        assert_eq!(param("user_text")["required"], true);
+        // Flattened sampling settings are params of their own
+        assert_eq!(param("temperature")["required"], false);
+        let create_agent = &methods[names.iter().position(|n| *n == "create_agent").unwrap()];
+        assert_eq!(create_agent["tags"][0]["name"], "admin");
+        let schemas = &document["components"]["schemas"];
+        assert_eq!(schemas["JobState"]["enum"][3], "failed");
+        assert_eq!(
+            schemas["FunctionTool"]["required"],
+            json!(["type", "function"])
+        );
+        assert!(schemas.as_object().unwrap().values().all(|s| !s.is_null()));
+    }
+}
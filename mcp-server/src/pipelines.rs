@@ -0,0 +1,163 @@
+//! Agent pipelines.
+//!
+//! A pipeline chains agents: the user's text goes to the first step, and each
+//! step's reply becomes the next step's input. `run_pipeline` runs either a
+//! built-in pipeline by ID or steps given inline, and returns the last reply
+//! along with every step's reply and metadata.
+//!
+//! Steps don't see each other's conversation history; a step's optional
+//! `instructions` are put in front of its input, to tell the agent what to do
+//! with the previous step's output.
+
+use crate::models::{GenerationParams, ProcessingMetadata};
+use serde::{Deserialize, Serialize};
+
+/// Most steps a pipeline may have.
+pub const MAX_STEPS: usize = 8;
+
+/// A named chain of agents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    /// Unique identifier, e.g. "voice_web3"
+    pub id: String,
+    /// What the pipeline does
+    pub description: String,
+    /// Agents to run, in order
+    pub steps: Vec<PipelineStep>,
+}
+
+/// One agent run in a pipeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineStep {
+    /// Agent to run
+    pub agent_id: String,
+    /// Optional text put in front of the step's input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// Optional model overriding the agent's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Optional sampling settings for this step
+    #[serde(flatten)]
+    pub generation: GenerationParams,
+}
+
+impl PipelineStep {
+    /// The text sent to the step's agent for `input`.
+    pub fn prompt(&self, input: &str) -> String {
+        match &self.instructions {
+            Some(instructions) => format!("{}\n\n{}", instructions, input),
+            None => input.to_string(),
+        }
+    }
+}
+
+/// Parameters for the run_pipeline JSON-RPC method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunPipelineParams {
+    /// ID of a built-in pipeline; exclusive with `steps`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline_id: Option<String>,
+    /// Steps to run; exclusive with `pipeline_id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<PipelineStep>>,
+    /// Input of the first step
+    pub user_text: String,
+    /// Optional time each step may take in milliseconds, retries and
+    /// fallbacks included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Keep nothing of any step, as for `process_text`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_store: bool,
+}
+
+/// Result of the run_pipeline JSON-RPC method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunPipelineResult {
+    /// The built-in pipeline that ran, if one was named
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline_id: Option<String>,
+    /// Reply of the last step
+    pub reply_text: String,
+    /// Every step's reply, in order
+    pub steps: Vec<StepResult>,
+    /// Totals over all steps
+    pub metadata: PipelineMetadata,
+}
+
+/// Reply of one pipeline step.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StepResult {
+    /// Agent that ran the step
+    pub agent_id: String,
+    /// The agent's reply, passed on to the next step
+    pub reply_text: String,
+    /// Metadata about the step's processing
+    pub metadata: ProcessingMetadata,
+}
+
+/// Totals of a pipeline run.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PipelineMetadata {
+    /// Tokens consumed by all steps, if every provider reported them
+    pub tokens_used: Option<u32>,
+    /// Processing time of the whole pipeline in milliseconds
+    pub processing_time_ms: u64,
+}
+
+/// The pipelines available by ID.
+pub fn builtin_pipelines() -> Vec<Pipeline> {
+    vec![Pipeline {
+        id: "voice_web3".to_string(),
+        description: "Cleans up a voice transcript, answers it as the Web3 Expert, then rewrites the answer for speech".to_string(),
+        steps: vec![
+            PipelineStep {
+                agent_id: "agent_001".to_string(),
+                instructions: Some("Clean up this voice transcript: fix punctuation, remove filler words and false starts, and keep the meaning. Reply with the cleaned-up text only.".to_string()),
+                model: None,
+                generation: GenerationParams::default(),
+            },
+            PipelineStep {
+                agent_id: "agent_002".to_string(),
+                instructions: None,
+                model: None,
+                generation: GenerationParams::default(),
+            },
+            PipelineStep {
+                agent_id: "agent_003".to_string(),
+                instructions: Some("Rewrite this answer to be spoken aloud: short sentences, no markdown, lists or code. Reply with the rewritten text only.".to_string()),
+                model: None,
+                generation: GenerationParams::default(),
+            },
+        ],
+    }]
+}
+
+/// The built-in pipeline with `id`, if any.
+pub fn find_builtin(id: &str) -> Option<Pipeline> {
+    builtin_pipelines().into_iter().find(|p| p.id == id)
+}
+
+/// Checks that `steps` is a usable pipeline: 1 to [`MAX_STEPS`] steps with
+/// valid sampling settings.
+///
+/// ```
+/// # use mcp_server::pipelines::{builtin_pipelines, validate_steps};
+/// assert!(validate_steps(&builtin_pipelines()[0].steps).is_ok());
+/// assert!(validate_steps(&[]).is_err());
+/// ```
+pub fn validate_steps(steps: &[PipelineStep]) -> Result<(), String> {
+    if steps.is_empty() {
+        return Err("a pipeline needs at least one step".to_string());
+    }
+    if steps.len() > MAX_STEPS {
+        return Err(format!("a pipeline has at most {} steps", MAX_STEPS));
+    }
+    for (i, step) in steps.iter().enumerate() {
+        step.generation
+            .validate()
+            .map_err(|e| format!("step {}: {}", i, e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,427 @@
+//! WebAssembly plugin agents.
+//!
+//! Plugins let third parties extend agents without forking the server. Each
+//! plugin is a WebAssembly module listed in the `[[plugins]]` section of the
+//! config file together with the agents whose requests go through it, and
+//! changes on reload:
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "tidy"
+//! # Relative to the working directory; `.wat` text modules work as well
+//! path = "plugins/tidy.wasm"
+//! agents = ["agent_001", "agent_005"]
+//! ```
+//!
+//! A module takes part in the stages it exports, each a function from text to
+//! text:
+//!
+//! - `pre_process` rewrites the user's text before anything else sees it
+//! - `handle` answers the request itself; no provider is asked
+//! - `post_process` rewrites the reply, before the
+//!   [guardrails](crate::guardrails) are applied
+//!
+//! An agent with several plugins runs them in the order they are listed, and
+//! the first that exports `handle` answers. A stage function takes the
+//! pointer and length of UTF-8 input in the module's exported `memory`, and
+//! returns the pointer of the UTF-8 output in the high 32 bits of an `i64`
+//! and its length in the low ones. Input is written to memory the module
+//! hands out from its exported `alloc(len: i32) -> i32`:
+//!
+//! ```wat
+//! (func (export "alloc") (param i32) (result i32) ...)
+//! (func (export "handle") (param $ptr i32) (param $len i32) (result i64) ...)
+//! ```
+//!
+//! Modules may not import anything, so they can reach neither the network
+//! nor the file system. Every call runs in a fresh instance, keeping no state
+//! between requests, and is stopped once it runs out of fuel or grows its
+//! memory past [`MAX_MEMORY_BYTES`]. A plugin that fails fails the request.
+
+use crate::error::ServerError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Instructions a plugin may run per call, roughly a second of work.
+pub const FUEL: u64 = 1_000_000_000;
+
+/// Most memory a plugin instance may use.
+pub const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Longest text a plugin may return.
+pub const MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// A `[[plugins]]` entry of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Unique name, shown in logs and as the model of replies it gives
+    pub id: String,
+    /// WebAssembly module, binary or text
+    pub path: PathBuf,
+    /// Agents whose requests go through the plugin
+    pub agents: Vec<String>,
+}
+
+impl PluginConfig {
+    /// Checks that plugins have distinct, non-empty IDs and name agents.
+    pub fn validate_all(plugins: &[Self]) -> Result<(), String> {
+        let mut ids = HashSet::new();
+        for plugin in plugins {
+            if plugin.id.is_empty() {
+                return Err("a plugin must have an id".to_string());
+            }
+            if !ids.insert(plugin.id.as_str()) {
+                return Err(format!("plugin {} is listed twice", plugin.id));
+            }
+            if plugin.agents.is_empty() {
+                return Err(format!("plugin {} applies to no agent", plugin.id));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A point of a request where plugins run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Rewrites the user's text
+    PreProcess,
+    /// Answers the request
+    Handle,
+    /// Rewrites the reply
+    PostProcess,
+}
+
+impl Stage {
+    const ALL: [Stage; 3] = [Stage::PreProcess, Stage::Handle, Stage::PostProcess];
+
+    /// Name of the function a module exports to take part in the stage.
+    pub fn export(self) -> &'static str {
+        match self {
+            Stage::PreProcess => "pre_process",
+            Stage::Handle => "handle",
+            Stage::PostProcess => "post_process",
+        }
+    }
+}
+
+/// A compiled plugin.
+pub struct Plugin {
+    config: PluginConfig,
+    engine: Engine,
+    module: Module,
+    stages: Vec<Stage>,
+}
+
+impl std::fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Plugin")
+            .field("config", &self.config)
+            .field("stages", &self.stages)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Plugin {
+    /// The plugin's ID.
+    pub fn id(&self) -> &str {
+        &self.config.id
+    }
+
+    /// Whether the module exports the function of `stage`.
+    pub fn runs(&self, stage: Stage) -> bool {
+        self.stages.contains(&stage)
+    }
+
+    /// Passes `text` through the plugin's `stage` function, in a fresh
+    /// instance. Blocks until the call returns.
+    pub fn call(&self, stage: Stage, text: &str) -> Result<String, String> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_BYTES)
+            .build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL).map_err(|e| e.to_string())?;
+        let instance = Instance::new(&mut store, &self.module, &[]).map_err(|e| e.to_string())?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("no memory export")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(|e| e.to_string())?;
+        let run = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, stage.export())
+            .map_err(|e| e.to_string())?;
+
+        let input = text.as_bytes();
+        let len = i32::try_from(input.len()).map_err(|_| "input too long")?;
+        let ptr = alloc.call(&mut store, len).map_err(|e| e.to_string())?;
+        memory
+            .write(&mut store, ptr as u32 as usize, input)
+            .map_err(|e| format!("alloc returned unusable memory: {}", e))?;
+        let packed = run
+            .call(&mut store, (ptr, len))
+            .map_err(|e| e.to_string())? as u64;
+        let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if len > MAX_OUTPUT_BYTES {
+            return Err(format!(
+                "returned {} bytes, at most {} are allowed",
+                len, MAX_OUTPUT_BYTES
+            ));
+        }
+        let mut output = vec![0; len];
+        memory
+            .read(&store, ptr, &mut output)
+            .map_err(|e| format!("returned text outside its memory: {}", e))?;
+        String::from_utf8(output).map_err(|_| "returned text that is not UTF-8".to_string())
+    }
+}
+
+/// The loaded plugins, by the agents they apply to.
+///
+/// Cheap to clone; clones share the plugins.
+#[derive(Clone)]
+pub struct Plugins {
+    engine: Engine,
+    loaded: Arc<RwLock<Vec<Arc<Plugin>>>>,
+}
+
+impl Default for Plugins {
+    fn default() -> Self {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        Self {
+            engine: Engine::new(&config).expect("WebAssembly engine"),
+            loaded: Default::default(),
+        }
+    }
+}
+
+impl Plugins {
+    /// Reads and compiles the modules of `configs`, failing on the first that
+    /// can't be used.
+    pub fn load(&self, configs: &[PluginConfig]) -> Result<Vec<Arc<Plugin>>, String> {
+        configs
+            .iter()
+            .map(|config| {
+                self.compile(config)
+                    .map(Arc::new)
+                    .map_err(|e| format!("plugin {}: {}", config.id, e))
+            })
+            .collect()
+    }
+
+    fn compile(&self, config: &PluginConfig) -> Result<Plugin, String> {
+        let module = Module::from_file(&self.engine, &config.path)
+            .map_err(|e| format!("{}: {:#}", config.path.display(), e))?;
+        if let Some(import) = module.imports().next() {
+            return Err(format!(
+                "imports {}::{}, but plugins may not import anything",
+                import.module(),
+                import.name()
+            ));
+        }
+        for export in ["memory", "alloc"] {
+            if module.get_export(export).is_none() {
+                return Err(format!("does not export {}", export));
+            }
+        }
+        let stages: Vec<Stage> = Stage::ALL
+            .into_iter()
+            .filter(|stage| module.get_export(stage.export()).is_some())
+            .collect();
+        if stages.is_empty() {
+            return Err("exports none of pre_process, handle and post_process".to_string());
+        }
+        Ok(Plugin {
+            config: config.clone(),
+            engine: self.engine.clone(),
+            module,
+            stages,
+        })
+    }
+
+    /// Swaps in plugins [loaded](Self::load) from a new config.
+    pub fn replace(&self, plugins: Vec<Arc<Plugin>>) {
+        *self.loaded.write().unwrap() = plugins;
+    }
+
+    /// Number of plugins loaded.
+    pub fn len(&self) -> usize {
+        self.loaded.read().unwrap().len()
+    }
+
+    /// Whether no plugin is loaded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The plugins agent `agent_id` goes through, in order.
+    pub fn for_agent(&self, agent_id: &str) -> Vec<Arc<Plugin>> {
+        self.loaded
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|plugin| plugin.config.agents.iter().any(|a| a == agent_id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// `text` passed through the `stage` of each of `plugins` that runs it.
+pub async fn rewrite(
+    plugins: &[Arc<Plugin>],
+    stage: Stage,
+    mut text: String,
+) -> Result<String, ServerError> {
+    for plugin in plugins.iter().filter(|plugin| plugin.runs(stage)) {
+        text = call(plugin, stage, text).await?;
+    }
+    Ok(text)
+}
+
+/// The first of `plugins` that handles requests and its reply to `text`, if
+/// any handles them.
+pub async fn handle(
+    plugins: &[Arc<Plugin>],
+    text: &str,
+) -> Result<Option<(Arc<Plugin>, String)>, ServerError> {
+    match plugins.iter().find(|plugin| plugin.runs(Stage::Handle)) {
+        Some(plugin) => {
+            let reply = call(plugin, Stage::Handle, text.to_string()).await?;
+            Ok(Some((plugin.clone(), reply)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Runs a plugin call on the blocking pool.
+async fn call(plugin: &Arc<Plugin>, stage: Stage, text: String) -> Result<String, ServerError> {
+    let runner = plugin.clone();
+    tokio::task::spawn_blocking(move || runner.call(stage, &text))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| {
+            ServerError::Internal(format!(
+                "plugin {} failed in {}: {}",
+                plugin.id(),
+                stage.export(),
+                e
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module whose `handle` answers "pong" and whose `post_process`
+    /// upper-cases ASCII letters in place.
+    const SHOUT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "pong")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "handle") (param i32 i32) (result i64)
+            (i64.const 4))
+          (func (export "post_process") (param $ptr i32) (param $len i32) (result i64)
+            (local $i i32)
+            (local $c i32)
+            (block $done
+              (loop $next
+                (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+                (local.set $c (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+                (if (i32.and (i32.ge_u (local.get $c) (i32.const 97))
+                             (i32.le_u (local.get $c) (i32.const 122)))
+                  (then (i32.store8 (i32.add (local.get $ptr) (local.get $i))
+                                    (i32.sub (local.get $c) (i32.const 32)))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next)))
+            (i64.or (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len)))))
+    "#;
+
+    /// Writes `wat` to a temporary file and loads it as plugin `id` of agent_001.
+    fn load(plugins: &Plugins, id: &str, wat: &str) -> Result<Vec<Arc<Plugin>>, String> {
+        let path = std::env::temp_dir().join(format!("{}-{}.wat", id, uuid::Uuid::new_v4()));
+        std::fs::write(&path, wat).unwrap();
+        let loaded = plugins.load(&[PluginConfig {
+            id: id.to_string(),
+            path: path.clone(),
+            agents: vec!["agent_001".to_string()],
+        }]);
+        std::fs::remove_file(path).unwrap();
+        loaded
+    }
+
+    #[tokio::test]
+    async fn runs_the_stages_a_module_exports() {
+        let plugins = Plugins::default();
+        plugins.replace(load(&plugins, "shout", SHOUT).unwrap());
+        assert_eq!(plugins.len(), 1);
+        assert!(plugins.for_agent("agent_002").is_empty());
+
+        let shout = plugins.for_agent("agent_001");
+        assert!(!shout[0].runs(Stage::PreProcess));
+        let text = rewrite(&shout, Stage::PreProcess, "hi".into())
+            .await
+            .unwrap();
+        assert_eq!(text, "hi");
+        let reply = rewrite(&shout, Stage::PostProcess, "gm, ser".into())
+            .await
+            .unwrap();
+        assert_eq!(reply, "GM, SER");
+        let (plugin, reply) = handle(&shout, "ping").await.unwrap().unwrap();
+        assert_eq!((plugin.id(), reply.as_str()), ("shout", "pong"));
+    }
+
+    #[tokio::test]
+    async fn stops_runaway_and_unsandboxed_modules() {
+        let plugins = Plugins::default();
+        let spin = r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "handle") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0)))
+        "#;
+        let spinning = load(&plugins, "spin", spin).unwrap();
+        let err = handle(&spinning, "hi").await.unwrap_err();
+        assert!(
+            err.to_string().contains("plugin spin failed in handle"),
+            "{}",
+            err
+        );
+
+        let importing = r#"
+            (module
+              (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0)))
+        "#;
+        let err = load(&plugins, "importing", importing).err().unwrap();
+        assert!(err.contains("may not import"), "{}", err);
+        let err = load(&plugins, "idle", "(module (memory (export \"memory\") 1))")
+            .err()
+            .unwrap();
+        assert!(err.contains("alloc"), "{}", err);
+
+        let twice = PluginConfig {
+            id: "shout".into(),
+            path: "shout.wasm".into(),
+            agents: vec!["agent_001".into()],
+        };
+        assert!(PluginConfig::validate_all(&[twice.clone(), twice]).is_err());
+    }
+}
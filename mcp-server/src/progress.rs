@@ -0,0 +1,302 @@
+//! Progress notifications of long-running work.
+//!
+//! Jobs, and requests that ask for it, report how far they got as MCP
+//! `notifications/progress`, with the stage reached and the tokens used so
+//! far, so UIs can show real progress rather than a spinner. They are sent as
+//! server-sent events:
+//!
+//! - `GET /jobs/{job_id}/events` - the progress of a job queued by
+//!   `submit_text`, with the job ID as progress token, starting with where
+//!   the job is and ending once it succeeded or failed
+//! - `POST /` with `Accept: text/event-stream` and a `_meta.progressToken` in
+//!   the params - the request's progress as it runs, then its JSON-RPC
+//!   response as the last event; `process_text` and `run_pipeline` report
+//!   their stages, other methods just answer
+//!
+//! The data of every event is a JSON-RPC notification:
+//!
+//! ```json
+//! { "jsonrpc": "2.0", "method": "notifications/progress",
+//!   "params": { "progressToken": "c6e13bcb-e736-426e-b169-cbaa7dae1510", "progress": 3,
+//!               "stage": "generating", "message": "Asking llama-3.3-70b-versatile",
+//!               "tokensUsed": 412 } }
+//! ```
+//!
+//! `progress` counts the notifications, so it always increases, and there is
+//! no `total`; pipelines give the step running in `step` and their number of
+//! steps in `steps`.
+//! Notifications a slow reader falls behind on are skipped.
+
+use crate::models::JsonRpcRequest;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Method of progress notifications.
+pub const PROGRESS_METHOD: &str = "notifications/progress";
+
+/// Notifications kept for readers that fall behind.
+const CAPACITY: usize = 64;
+
+/// How far a job or request got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// A job waits for a worker
+    Queued,
+    /// A worker picked the job up
+    Running,
+    /// A pipeline started one of its steps
+    Step,
+    /// Searching the agent's knowledge base
+    Retrieving,
+    /// Waiting for a model's reply
+    Generating,
+    /// Running a tool the model called
+    CallingTool,
+    /// The job finished with a result
+    Succeeded,
+    /// The job finished with an error
+    Failed,
+}
+
+impl Stage {
+    /// Whether nothing follows this stage.
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed)
+    }
+}
+
+/// Params of a `notifications/progress` notification.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressParams {
+    /// The job ID, or the token the request was sent with
+    pub progress_token: Value,
+    /// Number of this notification, from 1
+    pub progress: u64,
+    /// Stage reached
+    pub stage: Stage,
+    /// Human-readable detail of the stage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Pipeline step running, from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<u64>,
+    /// Number of steps of the pipeline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<u64>,
+    /// Tokens used so far
+    pub tokens_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    count: u64,
+    tokens_used: u64,
+    step: Option<u64>,
+    steps: Option<u64>,
+    last: Option<ProgressParams>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    token: Value,
+    sender: broadcast::Sender<ProgressParams>,
+    progress: Mutex<Progress>,
+}
+
+/// Sends the progress notifications of one job or request.
+///
+/// Cheap to clone; clones report to the same readers.
+#[derive(Debug, Clone)]
+pub struct Reporter {
+    inner: Arc<Inner>,
+}
+
+impl Reporter {
+    /// Reports under `token`.
+    pub fn new(token: impl Into<Value>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                token: token.into(),
+                sender: broadcast::channel(CAPACITY).0,
+                progress: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Sends a notification that `stage` was reached.
+    pub fn report(&self, stage: Stage, message: Option<String>) {
+        let mut progress = self.inner.progress.lock().unwrap();
+        progress.count += 1;
+        let params = ProgressParams {
+            progress_token: self.inner.token.clone(),
+            progress: progress.count,
+            stage,
+            message,
+            step: progress.step,
+            steps: progress.steps,
+            tokens_used: progress.tokens_used,
+        };
+        progress.last = Some(params.clone());
+        // Nobody may be listening
+        let _ = self.inner.sender.send(params);
+    }
+
+    /// Adds `tokens` to those reported as used.
+    pub fn add_tokens(&self, tokens: u64) {
+        self.inner.progress.lock().unwrap().tokens_used += tokens;
+    }
+
+    /// Reports the start of step `step` (from 1) of `steps`.
+    pub fn step(&self, step: u64, steps: u64, message: String) {
+        {
+            let mut progress = self.inner.progress.lock().unwrap();
+            progress.step = Some(step);
+            progress.steps = Some(steps);
+        }
+        self.report(Stage::Step, Some(message));
+    }
+
+    /// The last notification sent, and the ones sent from now on.
+    pub fn subscribe(&self) -> (Option<ProgressParams>, broadcast::Receiver<ProgressParams>) {
+        // Under the lock, so no notification falls between the two
+        let progress = self.inner.progress.lock().unwrap();
+        (progress.last.clone(), self.inner.sender.subscribe())
+    }
+}
+
+tokio::task_local! {
+    static REPORTER: Reporter;
+}
+
+/// Runs `future` with its progress sent by `reporter`.
+pub async fn with_reporter<F: Future>(reporter: Reporter, future: F) -> F::Output {
+    REPORTER.scope(reporter, future).await
+}
+
+/// Reports that the current job or request reached `stage`; does nothing
+/// outside of one reporting its progress.
+pub fn report(stage: Stage, message: impl Into<String>) {
+    let _ = REPORTER.try_with(|reporter| reporter.report(stage, Some(message.into())));
+}
+
+/// Reports the start of a pipeline's step `step` (from 1) of `steps`.
+pub fn step(step: u64, steps: u64, message: impl Into<String>) {
+    let _ = REPORTER.try_with(|reporter| reporter.step(step, steps, message.into()));
+}
+
+/// Counts `tokens` as used by the current job or request.
+pub fn add_tokens(tokens: u64) {
+    let _ = REPORTER.try_with(|reporter| reporter.add_tokens(tokens));
+}
+
+/// The `_meta.progressToken` of a request's params: a string or an integer.
+pub fn progress_token(params: Option<&Value>) -> Option<Value> {
+    let token = params?.get("_meta")?.get("progressToken")?;
+    (token.is_string() || token.is_i64() || token.is_u64()).then(|| token.clone())
+}
+
+/// `params` as a JSON-RPC notification.
+pub fn notification(params: ProgressParams) -> JsonRpcRequest<ProgressParams> {
+    JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: PROGRESS_METHOD.to_string(),
+        params: Some(params),
+        id: None,
+    }
+}
+
+/// The notifications of `reporter`, starting with the last one sent and
+/// ending after a [final](Stage::is_final) one.
+pub fn until_final(reporter: &Reporter) -> impl Stream<Item = ProgressParams> {
+    let (last, receiver) = reporter.subscribe();
+    let done = last.as_ref().is_some_and(|last| last.stage.is_final());
+    let live = futures_util::stream::unfold((receiver, done), |(mut receiver, done)| async move {
+        if done {
+            return None;
+        }
+        loop {
+            match receiver.recv().await {
+                Ok(params) => {
+                    let done = params.stage.is_final();
+                    return Some((params, (receiver, done)));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    futures_util::stream::iter(last).chain(live)
+}
+
+/// An SSE event carrying `message` as JSON.
+pub fn event(message: &impl Serialize) -> Event {
+    Event::default().data(serde_json::to_string(message).unwrap())
+}
+
+/// A server-sent events response of `events`.
+pub fn sse(events: impl Stream<Item = Event> + Send + 'static) -> Response {
+    let events = events.map(Ok::<_, std::convert::Infallible>);
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn reports_stages_to_late_readers() {
+        let reporter = Reporter::new("job-1");
+        reporter.report(Stage::Queued, None);
+        let stream = until_final(&reporter);
+        with_reporter(reporter.clone(), async {
+            step(1, 2, "Step 1 of 2: agent_002");
+            add_tokens(40);
+            report(Stage::Generating, "Asking a model");
+        })
+        .await;
+        report(Stage::Generating, "Nobody is listening");
+        reporter.report(Stage::Succeeded, None);
+        reporter.report(Stage::Failed, None);
+
+        let seen: Vec<ProgressParams> = stream.collect().await;
+        let stages: Vec<Stage> = seen.iter().map(|params| params.stage).collect();
+        assert_eq!(
+            stages,
+            [
+                Stage::Queued,
+                Stage::Step,
+                Stage::Generating,
+                Stage::Succeeded
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(notification(seen[2].clone())).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/progress",
+                "params": {
+                    "progressToken": "job-1", "progress": 3, "step": 1, "steps": 2,
+                    "stage": "generating", "message": "Asking a model", "tokensUsed": 40,
+                },
+            })
+        );
+        // A reader arriving after the end gets the last notification only
+        let late: Vec<_> = until_final(&reporter).collect().await;
+        assert_eq!(late.len(), 1);
+
+        let params = json!({ "agent_id": "agent_001", "_meta": { "progressToken": 7 } });
+        assert_eq!(progress_token(Some(&params)), Some(json!(7)));
+        assert_eq!(progress_token(Some(&json!({ "_meta": {} }))), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Versions of agents' system prompts, and experiments splitting traffic
+//! between them.
+//!
+//! An agent's `system_prompt` is its live version, named by `prompt_version`
+//! (`default` when unset). Other versions are listed in `prompt_versions`,
+//! each with the percentage of requests it serves:
+//!
+//! ```json
+//! { "agent_id": "agent_002", "prompt_version": "v1",
+//!   "prompt_versions": [
+//!     { "version": "v2", "system_prompt": "You are a concise Web3 expert...", "weight": 20 } ] }
+//! ```
+//!
+//! Here 20% of requests are answered with `v2` and the other 80% with `v1`.
+//! Callers stay on one version: the choice is made from the session, or the
+//! authenticated caller, so only anonymous requests without a session are
+//! split at random. Results report the version in `metadata.prompt_version`,
+//! and the [audit log](crate::audit) records it with each call.
+//!
+//! The admin `promote_prompt_version` method makes a version live. The
+//! version it replaces is kept with weight 0, as is every other version, so
+//! the experiment ends and the old prompt is at hand to roll back to.
+
+use crate::models::Agent;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Name of the live version of agents without a `prompt_version`.
+pub const DEFAULT_VERSION: &str = "default";
+
+/// Most versions an agent may keep besides its live one.
+pub const MAX_VERSIONS: usize = 8;
+
+/// Longest version name.
+pub const MAX_NAME_CHARS: usize = 64;
+
+/// A version of an agent's system prompt, other than the live one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PromptVersion {
+    /// Name of the version, e.g. `v2`
+    pub version: String,
+    /// The system prompt of this version
+    pub system_prompt: String,
+    /// Percentage of requests answered with this version; the live version
+    /// serves those left over
+    #[serde(default)]
+    pub weight: u8,
+}
+
+/// Parameters for the promote_prompt_version JSON-RPC method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromotePromptVersionParams {
+    /// ID of the agent
+    pub agent_id: String,
+    /// Version to make live
+    pub version: String,
+}
+
+/// Name of the live version of `agent`.
+pub fn live_version(agent: &Agent) -> &str {
+    agent.prompt_version.as_deref().unwrap_or(DEFAULT_VERSION)
+}
+
+/// Checks an agent's versions: at most [`MAX_VERSIONS`] of them with
+/// distinct names, none named like the live version, with non-empty prompts
+/// and weights adding up to at most 100.
+pub fn validate(live: Option<&str>, versions: &[PromptVersion]) -> Result<(), String> {
+    let live = live.unwrap_or(DEFAULT_VERSION);
+    if versions.len() > MAX_VERSIONS {
+        return Err(format!(
+            "agents can have at most {} prompt_versions",
+            MAX_VERSIONS
+        ));
+    }
+    for name in std::iter::once(live).chain(versions.iter().map(|v| v.version.as_str())) {
+        if name.trim().is_empty() || name.chars().count() > MAX_NAME_CHARS {
+            return Err(format!(
+                "prompt version names must have 1 to {} characters",
+                MAX_NAME_CHARS
+            ));
+        }
+    }
+    for (i, version) in versions.iter().enumerate() {
+        if version.version == live || versions[..i].iter().any(|v| v.version == version.version) {
+            return Err(format!(
+                "prompt version {} is defined twice",
+                version.version
+            ));
+        }
+        if version.system_prompt.trim().is_empty() {
+            return Err(format!(
+                "prompt version {} has an empty system_prompt",
+                version.version
+            ));
+        }
+    }
+    let total: u32 = versions.iter().map(|v| u32::from(v.weight)).sum();
+    if total > 100 {
+        return Err(format!(
+            "prompt version weights add up to {}, more than 100",
+            total
+        ));
+    }
+    Ok(())
+}
+
+/// The agent answering a request: `agent` with the system prompt of the
+/// version chosen for it, and that version's name as `prompt_version`.
+///
+/// Requests with the same `key`, such as a session, get the same version as
+/// long as the weights don't change; without a key the version is chosen at
+/// random. Agents without versions are returned unchanged.
+pub fn choose(agent: &Arc<Agent>, key: Option<&str>) -> Arc<Agent> {
+    if agent.prompt_version.is_none() && agent.prompt_versions.is_empty() {
+        return agent.clone();
+    }
+    let bucket = match key {
+        Some(key) => {
+            let digest = Sha256::new()
+                .chain_update(agent.id.as_bytes())
+                .chain_update([0])
+                .chain_update(key.as_bytes())
+                .finalize();
+            u64::from_be_bytes(digest[..8].try_into().unwrap()) % 100
+        }
+        None => (uuid::Uuid::new_v4().as_u128() % 100) as u64,
+    };
+    let mut threshold = 0;
+    let chosen = agent.prompt_versions.iter().find(|version| {
+        threshold += u64::from(version.weight);
+        bucket < threshold
+    });
+    let (version, system_prompt) = match chosen {
+        Some(version) => (version.version.clone(), version.system_prompt.clone()),
+        None => (live_version(agent).to_string(), agent.system_prompt.clone()),
+    };
+    Arc::new(Agent {
+        system_prompt,
+        prompt_version: Some(version),
+        prompt_versions: Vec::new(),
+        ..Agent::clone(agent)
+    })
+}
+
+/// `agent` answering with `version` of its system prompt, the live one
+/// included, whatever the weights; `None` if it has no such version.
+pub fn pinned(agent: &Agent, version: &str) -> Option<Agent> {
+    let system_prompt = if version == live_version(agent) {
+        agent.system_prompt.clone()
+    } else {
+        let version = agent
+            .prompt_versions
+            .iter()
+            .find(|v| v.version == version)?;
+        version.system_prompt.clone()
+    };
+    Some(Agent {
+        system_prompt,
+        prompt_version: Some(version.to_string()),
+        prompt_versions: Vec::new(),
+        ..agent.clone()
+    })
+}
+
+/// Makes `version` the live version of `agent`, keeping the one it replaces
+/// and ending any experiment by setting every weight to 0.
+pub fn promote(agent: &mut Agent, version: &str) -> Result<(), String> {
+    let Some(i) = agent
+        .prompt_versions
+        .iter()
+        .position(|v| v.version == version)
+    else {
+        return Err(format!(
+            "agent {} has no prompt version {}",
+            agent.id, version
+        ));
+    };
+    let promoted = agent.prompt_versions.remove(i);
+    let previous = PromptVersion {
+        version: live_version(agent).to_string(),
+        system_prompt: std::mem::replace(&mut agent.system_prompt, promoted.system_prompt),
+        weight: 0,
+    };
+    agent.prompt_version = Some(promoted.version);
+    agent.prompt_versions.insert(i, previous);
+    for version in &mut agent.prompt_versions {
+        version.weight = 0;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::builtin_agents;
+
+    #[test]
+    fn splits_traffic_and_promotes_versions() {
+        let mut agent = builtin_agents().remove(1);
+        let live = agent.system_prompt.clone();
+        agent.prompt_versions = vec![PromptVersion {
+            version: "concise".into(),
+            system_prompt: "Answer in one sentence.".into(),
+            weight: 30,
+        }];
+        assert!(validate(agent.prompt_version.as_deref(), &agent.prompt_versions).is_ok());
+        let agent = Arc::new(agent);
+
+        let served: Vec<_> = (0..1000)
+            .map(|i| choose(&agent, Some(&format!("session-{}", i))))
+            .collect();
+        let concise = served
+            .iter()
+            .filter(|a| a.prompt_version.as_deref() == Some("concise"))
+            .count();
+        assert!((200..400).contains(&concise), "{} of 1000", concise);
+        let default = served.iter().find(|a| a.system_prompt == live).unwrap();
+        assert_eq!(default.prompt_version.as_deref(), Some(DEFAULT_VERSION));
+        assert!(default.prompt_versions.is_empty());
+        // Sticky per key
+        for _ in 0..5 {
+            assert_eq!(
+                choose(&agent, Some("session-7")).prompt_version,
+                served[7].prompt_version
+            );
+        }
+        // Agents without versions are served as they are
+        let plain = Arc::new(builtin_agents().remove(0));
+        assert!(Arc::ptr_eq(&choose(&plain, None), &plain));
+
+        let mut agent = Agent::clone(&agent);
+        assert!(promote(&mut agent, "verbose").is_err());
+        promote(&mut agent, "concise").unwrap();
+        assert_eq!(agent.system_prompt, "Answer in one sentence.");
+        assert_eq!(agent.prompt_version.as_deref(), Some("concise"));
+        assert_eq!(
+            agent.prompt_versions,
+            vec![PromptVersion {
+                version: DEFAULT_VERSION.into(),
+                system_prompt: live,
+                weight: 0,
+            }]
+        );
+
+        let twice = |name: &str| PromptVersion {
+            version: name.into(),
+            system_prompt: "Hi".into(),
+            weight: 60,
+        };
+        assert!(validate(None, &[twice("a"), twice("a")]).is_err());
+        assert!(validate(None, &[twice("default")]).is_err());
+        assert!(validate(None, &[twice("a"), twice("b")]).is_err());
+        assert!(validate(Some(""), &[]).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! MCP prompt templates.
+//!
+//! Reusable prompts that clients discover through `prompts/list` and fill in
+//! with `prompts/get`, so common Web3 tasks don't have to be hardcoded in every
+//! client. Each template names the agent best suited to answer it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// An argument a prompt template can be filled with.
+#[derive(Debug, Serialize)]
+pub struct PromptArgument {
+    /// Placeholder name; `{name}` in the template is replaced by its value
+    pub name: &'static str,
+    /// What the value should be
+    pub description: &'static str,
+    /// Whether `prompts/get` fails without it
+    pub required: bool,
+    /// Value used when an optional argument is omitted
+    #[serde(skip)]
+    pub default: &'static str,
+}
+
+/// A prompt template.
+#[derive(Debug, Serialize)]
+pub struct Prompt {
+    /// Unique name, passed to `prompts/get`
+    pub name: &'static str,
+    /// What the prompt is for
+    pub description: &'static str,
+    /// Arguments the template accepts
+    pub arguments: &'static [PromptArgument],
+    /// Agent the filled prompt is meant for
+    #[serde(skip)]
+    pub agent_id: &'static str,
+    /// Text with `{argument}` placeholders
+    #[serde(skip)]
+    pub template: &'static str,
+}
+
+/// Result of the `prompts/list` method.
+#[derive(Debug, Serialize)]
+pub struct ListPromptsResult {
+    /// All prompt templates
+    pub prompts: &'static [Prompt],
+}
+
+/// Params of the `prompts/get` method.
+#[derive(Debug, Deserialize)]
+pub struct GetPromptParams {
+    /// Name of the template
+    pub name: String,
+    /// Values for the template's arguments
+    #[serde(default)]
+    pub arguments: HashMap<String, String>,
+}
+
+/// A message of a filled prompt.
+#[derive(Debug, Serialize)]
+pub struct PromptMessage {
+    /// Who the message is from, always `user` here
+    pub role: &'static str,
+    /// The message content
+    pub content: crate::tools::Content,
+}
+
+/// Result of the `prompts/get` method.
+#[derive(Debug, Serialize)]
+pub struct GetPromptResult {
+    /// What the prompt is for, and which agent to send it to
+    pub description: String,
+    /// The filled prompt
+    pub messages: Vec<PromptMessage>,
+}
+
+/// The built-in prompt templates.
+pub static PROMPTS: &[Prompt] = &[
+    Prompt {
+        name: "explain_transaction",
+        description: "Explain what a blockchain transaction does, step by step",
+        arguments: &[
+            PromptArgument {
+                name: "tx_hash",
+                description: "Hash of the transaction",
+                required: true,
+                default: "",
+            },
+            PromptArgument {
+                name: "chain",
+                description: "Network the transaction was sent on",
+                required: false,
+                default: "Ethereum mainnet",
+            },
+        ],
+        agent_id: "agent_002",
+        template: "Explain what transaction {tx_hash} on {chain} does. Describe the \
+                   contracts and functions involved, the tokens moved, the fees paid and \
+                   anything a careful user should double-check.",
+    },
+    Prompt {
+        name: "draft_nft_description",
+        description: "Draft a description for an NFT before minting it",
+        arguments: &[
+            PromptArgument {
+                name: "name",
+                description: "Name of the NFT",
+                required: true,
+                default: "",
+            },
+            PromptArgument {
+                name: "traits",
+                description: "Notable traits or attributes, comma separated",
+                required: false,
+                default: "none given",
+            },
+            PromptArgument {
+                name: "tone",
+                description: "Tone of the description, e.g. playful or formal",
+                required: false,
+                default: "vivid",
+            },
+        ],
+        agent_id: "agent_002",
+        template: "Write a {tone} description of at most three sentences for an NFT named \
+                   \"{name}\". Traits: {traits}. It will be stored in the token's metadata, \
+                   so do not use markdown.",
+    },
+    Prompt {
+        name: "review_contract",
+        description: "Review smart contract code for bugs and security issues",
+        arguments: &[
+            PromptArgument {
+                name: "code",
+                description: "Source code of the contract",
+                required: true,
+                default: "",
+            },
+            PromptArgument {
+                name: "language",
+                description: "Contract language",
+                required: false,
+                default: "Solidity",
+            },
+        ],
+        agent_id: "agent_004",
+        template: "Review this {language} contract. List security issues first, most \
+                   severe first, then other bugs and gas optimizations.\n\n{code}",
+    },
+];
+
+/// Looks up a prompt template by name.
+pub fn find_prompt(name: &str) -> Option<&'static Prompt> {
+    PROMPTS.iter().find(|p| p.name == name)
+}
+
+impl Prompt {
+    /// Fills the template, failing with the name of the first missing
+    /// required argument.
+    ///
+    /// Placeholders are replaced in a single pass, so argument values that
+    /// contain `{...}` are inserted verbatim.
+    pub fn render(&self, arguments: &HashMap<String, String>) -> Result<String, &'static str> {
+        let mut values = HashMap::new();
+        for argument in self.arguments {
+            let value = match arguments.get(argument.name) {
+                Some(value) => value.as_str(),
+                None if argument.required => return Err(argument.name),
+                None => argument.default,
+            };
+            values.insert(argument.name, value);
+        }
+
+        let mut text = String::with_capacity(self.template.len());
+        let mut rest = self.template;
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let placeholder = rest[1..]
+                .find('}')
+                .and_then(|end| values.get(&rest[1..=end]).map(|v| (*v, end + 2)));
+            match placeholder {
+                Some((value, len)) => {
+                    text.push_str(value);
+                    rest = &rest[len..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_arguments_and_defaults() {
+        let prompt = find_prompt("explain_transaction").unwrap();
+        let arguments = HashMap::from([("tx_hash".to_string(), "0xabc".to_string())]);
+        let text = prompt.render(&arguments).unwrap();
+        assert!(text.contains("0xabc on Ethereum mainnet"), "{}", text);
+        assert!(!text.contains('{'));
+
+        assert_eq!(prompt.render(&HashMap::new()), Err("tx_hash"));
+
+        let review = find_prompt("review_contract").unwrap();
+        let code = "function f() { emit Log(\"{language}\"); }".to_string();
+        let text = review
+            .render(&HashMap::from([("code".to_string(), code.clone())]))
+            .unwrap();
+        assert!(text.starts_with("Review this Solidity contract."));
+        assert!(text.ends_with(&code));
+    }
+}
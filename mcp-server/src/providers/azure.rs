@@ -0,0 +1,434 @@
+//! Azure OpenAI backend.
+//!
+//! Azure serves each model from a named deployment, so every agent's `model`
+//! is mapped to a deployment before the call.
+//!
+//! # Environment Variables
+//!
+//! * `AZURE_OPENAI_API_KEY` - Enables the backend; sent in the `api-key` header
+//! * `AZURE_OPENAI_ENDPOINT` - Resource endpoint, e.g. `https://my-resource.openai.azure.com`
+//! * `AZURE_OPENAI_API_VERSION` - Optional. REST API version (default: [`DEFAULT_API_VERSION`])
+//! * `AZURE_OPENAI_DEPLOYMENTS` - Optional. Comma-separated `model=deployment` pairs
+//! * `AZURE_OPENAI_DEPLOYMENT` - Optional. Deployment for models without a mapping
+//! * `AZURE_OPENAI_TIMEOUT_MS` - Optional. Timeout of each call, overriding
+//!   `PROVIDER_HTTP_TIMEOUT_MS`
+//! * `AZURE_OPENAI_LOGPROBS` - Optional. `true` requests token logprobs, from
+//!   which replies get a [confidence](super::confidence); deployments of
+//!   models without logprobs reject such calls (default: false)
+//! * `AZURE_OPENAI_EMBEDDING_DEPLOYMENT` - Optional. Deployment of an
+//!   embedding model, such as `text-embedding-3-small`, serving
+//!   [`embed`](LlmProvider::embed); without it the backend computes none
+
+use super::groq::{
+    add_sampling, add_tools, chat_messages, parse_chat_chunk, parse_chat_completion,
+};
+use super::{
+    estimate_tokens, probe_request, request_error, retry_after, sse_text_stream, timeout_from_env,
+    within, Completion, CompletionRequest, CompletionStream, Embeddings, LlmProvider,
+};
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// API version used when `AZURE_OPENAI_API_VERSION` is unset.
+pub const DEFAULT_API_VERSION: &str = "2024-10-21";
+
+/// Maps agent models to Azure deployment names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeploymentMap {
+    deployments: HashMap<String, String>,
+    fallback: Option<String>,
+}
+
+impl DeploymentMap {
+    /// Parses comma-separated `model=deployment` pairs.
+    ///
+    /// ```
+    /// use mcp_server::providers::azure::DeploymentMap;
+    ///
+    /// let map = DeploymentMap::parse("mixtral-8x7b-32768=chat, gpt-4o=gpt4o-prod", None).unwrap();
+    /// assert_eq!(map.resolve("gpt-4o"), Some("gpt4o-prod"));
+    /// assert_eq!(map.resolve("llama"), None);
+    /// ```
+    pub fn parse(pairs: &str, fallback: Option<String>) -> Result<Self, String> {
+        let mut deployments = HashMap::new();
+        for pair in pairs.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (model, deployment) = pair
+                .split_once('=')
+                .map(|(m, d)| (m.trim(), d.trim()))
+                .filter(|(m, d)| !m.is_empty() && !d.is_empty())
+                .ok_or_else(|| format!("Invalid Azure deployment mapping: {}", pair))?;
+            deployments.insert(model.to_string(), deployment.to_string());
+        }
+        Ok(Self {
+            deployments,
+            fallback,
+        })
+    }
+
+    /// Models with a deployment, or `None` if the default deployment serves any.
+    pub fn models(&self) -> Option<Vec<String>> {
+        match self.fallback {
+            Some(_) => None,
+            None => {
+                let mut models: Vec<_> = self.deployments.keys().cloned().collect();
+                models.sort();
+                Some(models)
+            }
+        }
+    }
+
+    /// The deployment serving `model`, falling back to the default deployment.
+    pub fn resolve(&self, model: &str) -> Option<&str> {
+        self.deployments
+            .get(model)
+            .or(self.fallback.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// Talks to an Azure OpenAI resource with an API key.
+pub struct AzureOpenAiProvider {
+    client: HttpClient,
+    api_key: String,
+    endpoint: String,
+    api_version: String,
+    deployments: DeploymentMap,
+    timeout: Option<Duration>,
+    logprobs: bool,
+    embedding_deployment: Option<String>,
+}
+
+impl AzureOpenAiProvider {
+    /// Creates a provider for the resource at `endpoint`.
+    pub fn new(
+        client: HttpClient,
+        api_key: String,
+        endpoint: impl Into<String>,
+        deployments: DeploymentMap,
+    ) -> Self {
+        Self {
+            client,
+            api_key,
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            api_version: DEFAULT_API_VERSION.to_string(),
+            deployments,
+            timeout: None,
+            logprobs: false,
+            embedding_deployment: None,
+        }
+    }
+
+    /// Uses another REST API version instead of [`DEFAULT_API_VERSION`].
+    pub fn with_api_version(mut self, api_version: impl Into<String>) -> Self {
+        self.api_version = api_version.into();
+        self
+    }
+
+    /// Gives up on calls after `timeout`, unless a request sets its own.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Requests token logprobs with each completion, to score its confidence.
+    pub fn with_logprobs(mut self, logprobs: bool) -> Self {
+        self.logprobs = logprobs;
+        self
+    }
+
+    /// Computes embeddings with the embedding model of `deployment`.
+    pub fn with_embedding_deployment(mut self, deployment: impl Into<String>) -> Self {
+        self.embedding_deployment = Some(deployment.into());
+        self
+    }
+
+    /// Reads the configuration from the environment.
+    ///
+    /// Returns `Ok(None)` without `AZURE_OPENAI_API_KEY`, and fails if the key
+    /// is set but the endpoint or deployment mapping is missing or invalid.
+    pub fn from_env(client: &HttpClient) -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(api_key) = var("AZURE_OPENAI_API_KEY") else {
+            return Ok(None);
+        };
+        let endpoint = var("AZURE_OPENAI_ENDPOINT")
+            .ok_or("AZURE_OPENAI_ENDPOINT must be set when AZURE_OPENAI_API_KEY is")?;
+        let deployments = DeploymentMap::parse(
+            &var("AZURE_OPENAI_DEPLOYMENTS").unwrap_or_default(),
+            var("AZURE_OPENAI_DEPLOYMENT"),
+        )?;
+        if deployments == DeploymentMap::default() {
+            return Err(
+                "AZURE_OPENAI_DEPLOYMENTS or AZURE_OPENAI_DEPLOYMENT must be set when \
+                 AZURE_OPENAI_API_KEY is"
+                    .to_string(),
+            );
+        }
+        let mut provider = Self::new(client.clone(), api_key, endpoint, deployments);
+        if let Some(api_version) = var("AZURE_OPENAI_API_VERSION") {
+            provider = provider.with_api_version(api_version);
+        }
+        if let Some(timeout) = timeout_from_env("AZURE_OPENAI_TIMEOUT_MS")? {
+            provider = provider.with_timeout(timeout);
+        }
+        let logprobs = var("AZURE_OPENAI_LOGPROBS").map(|v| v.trim().to_ascii_lowercase());
+        if matches!(logprobs.as_deref(), Some("true" | "1" | "on")) {
+            provider = provider.with_logprobs(true);
+        }
+        if let Some(deployment) = var("AZURE_OPENAI_EMBEDDING_DEPLOYMENT") {
+            provider = provider.with_embedding_deployment(deployment);
+        }
+        Ok(Some(provider))
+    }
+
+    /// The chat completions URL of the deployment serving `model`.
+    fn url(&self, model: &str) -> Result<String, ServerError> {
+        let deployment = self.deployments.resolve(model).ok_or_else(|| {
+            ServerError::Internal(format!(
+                "No Azure OpenAI deployment configured for model {}",
+                model
+            ))
+        })?;
+        Ok(format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint, deployment, self.api_version
+        ))
+    }
+
+    async fn send(
+        &self,
+        url: &str,
+        body: &serde_json::Value,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, ServerError> {
+        let request = self
+            .client
+            .post(url)
+            .header("api-key", &self.api_key)
+            .header("Content-Type", "application/json")
+            .json(body);
+        let request = match timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        let send = self.client.send_with_retry(request);
+        within("Azure OpenAI", timeout, async {
+            send.await
+                .map_err(|e| request_error("Azure OpenAI", "Azure OpenAI API request failed", e))
+        })
+        .await
+    }
+}
+
+/// Builds the chat completion body; the deployment in the URL picks the model.
+fn build_azure_request(request: CompletionRequest, stream: bool) -> serde_json::Value {
+    let mut body = json!({
+        "messages": chat_messages(&request.agent, request.user_text, request.conversation_history),
+        "stream": stream
+    });
+    add_sampling(&mut body, &request.generation);
+    add_tools(&mut body, &request.tools);
+    body
+}
+
+/// Interprets an Azure OpenAI embeddings HTTP response to `expected` texts,
+/// returning the vectors in input order, the model and the tokens billed.
+pub fn parse_azure_embeddings(
+    status: reqwest::StatusCode,
+    body: &str,
+    expected: usize,
+) -> Result<Embeddings, ServerError> {
+    #[derive(serde::Deserialize)]
+    struct Response {
+        data: Vec<Item>,
+        model: String,
+        usage: Option<Usage>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Item {
+        index: usize,
+        embedding: Vec<f32>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Usage {
+        total_tokens: u32,
+    }
+
+    if !status.is_success() {
+        return Err(ServerError::status("Azure OpenAI", status, body));
+    }
+    let mut response: Response = serde_json::from_str(body).map_err(|e| {
+        ServerError::provider(format!(
+            "Failed to parse Azure OpenAI response: {}. Raw: {}",
+            e, body
+        ))
+    })?;
+    response.data.sort_by_key(|item| item.index);
+    if response.data.len() != expected {
+        return Err(ServerError::provider(format!(
+            "Azure OpenAI returned {} embeddings for {} texts",
+            response.data.len(),
+            expected
+        )));
+    }
+    Ok(Embeddings {
+        vectors: response
+            .data
+            .into_iter()
+            .map(|item| item.embedding)
+            .collect(),
+        model: response.model,
+        tokens_used: response.usage.map(|usage| usage.total_tokens),
+    })
+}
+
+fn parse_azure_chunk(data: &str) -> Result<Option<String>, ServerError> {
+    parse_chat_chunk("Azure OpenAI", data)
+}
+
+#[async_trait]
+impl LlmProvider for AzureOpenAiProvider {
+    fn name(&self) -> &'static str {
+        "azure"
+    }
+
+    fn known_models(&self) -> Option<Vec<String>> {
+        self.deployments.models()
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion, ServerError> {
+        let url = self.url(&request.model)?;
+        let timeout = request.timeout.or(self.timeout);
+        let mut body = build_azure_request(request, false);
+        if self.logprobs {
+            body["logprobs"] = json!(true);
+        }
+
+        within("Azure OpenAI", timeout, async {
+            // Hold a per-host slot until the body is read
+            let _permit = self.client.acquire(&url).await;
+            let response = self.send(&url, &body, timeout).await?;
+            let response_status = response.status();
+            let delay = retry_after(&response);
+            let response_text = response.text().await.map_err(|e| {
+                request_error("Azure OpenAI", "Failed to read Azure OpenAI response", e)
+            })?;
+
+            parse_chat_completion("Azure OpenAI", response_status, &response_text)
+                .map_err(|e| e.with_retry_after(delay))
+        })
+        .await
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream, ServerError> {
+        let url = self.url(&request.model)?;
+        let timeout = request.timeout.or(self.timeout);
+        let body = build_azure_request(request, true);
+
+        let permit = self.client.acquire(&url).await;
+        let response = self.send(&url, &body, timeout).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let delay = retry_after(&response);
+            let body = response.text().await.unwrap_or_default();
+            let error = ServerError::status("Azure OpenAI", status, &body);
+            return Err(error.with_retry_after(delay));
+        }
+        Ok(sse_text_stream(response, permit, parse_azure_chunk))
+    }
+
+    async fn count_tokens(&self, request: &CompletionRequest) -> Result<u32, ServerError> {
+        // Azure OpenAI has no token counting endpoint
+        Ok(estimate_tokens(request))
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<(), ServerError> {
+        let request = self
+            .client
+            .get(format!("{}/openai/models", self.endpoint))
+            .query(&[("api-version", &self.api_version)])
+            .header("api-key", &self.api_key);
+        probe_request("Azure OpenAI", request, timeout).await
+    }
+
+    fn supports_embeddings(&self) -> bool {
+        self.embedding_deployment.is_some()
+    }
+
+    async fn embed(
+        &self,
+        texts: &[String],
+        _model: Option<&str>,
+    ) -> Result<Embeddings, ServerError> {
+        // The deployment picks the model
+        let Some(deployment) = &self.embedding_deployment else {
+            return Err(ServerError::InvalidParams(
+                "provider azure has no embedding deployment configured".to_string(),
+            ));
+        };
+        let url = format!(
+            "{}/openai/deployments/{}/embeddings?api-version={}",
+            self.endpoint, deployment, self.api_version
+        );
+        let body = json!({ "input": texts });
+
+        let _permit = self.client.acquire(&url).await;
+        let response = self.send(&url, &body, self.timeout).await?;
+        let status = response.status();
+        let delay = retry_after(&response);
+        let text = response.text().await.map_err(|e| {
+            request_error("Azure OpenAI", "Failed to read Azure OpenAI response", e)
+        })?;
+        parse_azure_embeddings(status, &text, texts.len()).map_err(|e| e.with_retry_after(delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_models_to_deployment_urls() {
+        let deployments =
+            DeploymentMap::parse("mixtral-8x7b-32768=chat", Some("fallback".into())).unwrap();
+        let provider = AzureOpenAiProvider::new(
+            reqwest::Client::new().into(),
+            "k".into(),
+            "https://res.openai.azure.com/",
+            deployments,
+        );
+        assert_eq!(
+            provider.url("mixtral-8x7b-32768").unwrap(),
+            "https://res.openai.azure.com/openai/deployments/chat/chat/completions?api-version=2024-10-21"
+        );
+        assert!(provider
+            .url("other")
+            .unwrap()
+            .contains("/deployments/fallback/"));
+
+        assert!(DeploymentMap::parse("gpt-4o", None).is_err());
+        let unmapped = DeploymentMap::parse("gpt-4o=prod", None).unwrap();
+        assert_eq!(unmapped.resolve("llama"), None);
+    }
+
+    #[test]
+    fn orders_embeddings_by_index() {
+        let body = r#"{"object":"list","model":"text-embedding-3-small","data":[
+            {"object":"embedding","index":1,"embedding":[0.5]},
+            {"object":"embedding","index":0,"embedding":[0.25]}
+        ],"usage":{"prompt_tokens":7,"total_tokens":7}}"#;
+        let embeddings = parse_azure_embeddings(reqwest::StatusCode::OK, body, 2).unwrap();
+        assert_eq!(embeddings.vectors, [vec![0.25], vec![0.5]]);
+        assert_eq!(embeddings.model, "text-embedding-3-small");
+        assert_eq!(embeddings.tokens_used, Some(7));
+        assert!(parse_azure_embeddings(reqwest::StatusCode::OK, body, 3).is_err());
+    }
+}
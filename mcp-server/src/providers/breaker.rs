@@ -0,0 +1,285 @@
+//! Circuit breakers around providers.
+//!
+//! During an outage every call to a provider waits for its timeouts and
+//! retries before failing. A [`CircuitBreaker`] counts consecutive failures
+//! and, past a threshold, opens: calls fail immediately for a cooldown, so
+//! requests fall over to the agent's fallbacks or fail fast. After the
+//! cooldown one probe call is let through (half-open); if it succeeds the
+//! breaker closes, otherwise it opens for another cooldown.
+//!
+//! Only errors that [point at the provider](ServerError::is_provider_fault)
+//! count as failures: client errors such as `400 Bad Request`, caused by the
+//! request itself, don't open the breaker, while `429`s, `5xx`s and network
+//! errors do. So do timeouts, unless the request set a `timeout_ms` of its
+//! own. An open breaker fails calls with [`ServerError::Unavailable`].
+//!
+//! # Environment Variables
+//!
+//! * `CIRCUIT_BREAKER_FAILURES` - Optional. Consecutive failures that open a
+//!   provider's breaker (default: 5; `0` disables the breakers)
+//! * `CIRCUIT_BREAKER_COOLDOWN_SECS` - Optional. How long it stays open
+//!   before a probe (default: 30)
+
+use super::{Completion, CompletionRequest, CompletionStream, Embeddings, LlmProvider};
+use crate::error::ServerError;
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// When breakers open and how long they stay open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerConfig {
+    /// Consecutive failures that open the breaker.
+    pub failure_threshold: u32,
+    /// Time an open breaker rejects calls before letting a probe through.
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl BreakerConfig {
+    /// Reads the settings, or `None` if breakers are disabled.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok()?.trim().parse::<u64>().ok();
+        let defaults = Self::default();
+        let failure_threshold = match var("CIRCUIT_BREAKER_FAILURES") {
+            Some(0) => return None,
+            Some(n) => n.min(u32::MAX.into()) as u32,
+            None => defaults.failure_threshold,
+        };
+        Some(Self {
+            failure_threshold,
+            cooldown: var("CIRCUIT_BREAKER_COOLDOWN_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.cooldown),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe call started at this time is in flight.
+    HalfOpen {
+        probe_started: Instant,
+    },
+}
+
+/// Tracks the health of one provider.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker.
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a call may go ahead now. Once the cooldown is over, the first
+    /// caller gets to probe.
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if now < until => false,
+            // A probe abandoned mid-call must not block the provider for good
+            State::HalfOpen { probe_started } if now < probe_started + self.config.cooldown => {
+                false
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { probe_started: now };
+                true
+            }
+        }
+    }
+
+    /// Records the outcome of an allowed call; returns whether it changed the
+    /// breaker between open and closed.
+    pub fn record(&self, success: bool) -> bool {
+        self.record_at(success, Instant::now())
+    }
+
+    fn record_at(&self, success: bool, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let was_closed = matches!(*state, State::Closed { .. });
+        *state = match (*state, success) {
+            (_, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 < self.config.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, false) => State::Open {
+                until: now + self.config.cooldown,
+            },
+        };
+        was_closed != matches!(*state, State::Closed { .. })
+    }
+
+    /// Whether calls are currently rejected or waiting on a probe.
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), State::Closed { .. })
+    }
+}
+
+/// A provider whose calls go through a [`CircuitBreaker`].
+pub struct Guarded {
+    inner: Arc<dyn LlmProvider>,
+    breaker: CircuitBreaker,
+}
+
+impl Guarded {
+    /// Wraps `inner` in a closed breaker.
+    pub fn new(inner: Arc<dyn LlmProvider>, config: BreakerConfig) -> Self {
+        Self {
+            inner,
+            breaker: CircuitBreaker::new(config),
+        }
+    }
+
+    fn check(&self) -> Result<(), ServerError> {
+        if self.breaker.allow() {
+            Ok(())
+        } else {
+            Err(ServerError::Unavailable(format!(
+                "{} is unavailable after repeated failures; try again shortly",
+                self.inner.name()
+            )))
+        }
+    }
+
+    /// Records a call's outcome; with `own_timeout`, the request set a timeout
+    /// of its own, so timing out isn't held against the provider.
+    fn record<T>(&self, result: &Result<T, ServerError>, own_timeout: bool) {
+        let success = match result {
+            Ok(_) => true,
+            Err(ServerError::Timeout(_)) => own_timeout,
+            Err(e) => !e.is_provider_fault(),
+        };
+        if self.breaker.record(success) {
+            if success {
+                tracing::info!("{} recovered, circuit closed", self.inner.name());
+            } else {
+                tracing::warn!(
+                    "{} circuit opened after repeated failures",
+                    self.inner.name()
+                );
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Guarded {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn known_models(&self) -> Option<Vec<String>> {
+        self.inner.known_models()
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        self.inner.default_model()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+
+    fn supports_images(&self) -> bool {
+        self.inner.supports_images()
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion, ServerError> {
+        self.check()?;
+        let own_timeout = request.timeout.is_some();
+        let result = self.inner.complete(request).await;
+        self.record(&result, own_timeout);
+        result
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream, ServerError> {
+        self.check()?;
+        let own_timeout = request.timeout.is_some();
+        let result = self.inner.stream(request).await;
+        self.record(&result, own_timeout);
+        result
+    }
+
+    async fn count_tokens(&self, request: &CompletionRequest) -> Result<u32, ServerError> {
+        self.inner.count_tokens(request).await
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<(), ServerError> {
+        // Probes bypass the breaker so health checks see a recovered backend
+        self.inner.probe(timeout).await
+    }
+
+    fn supports_embeddings(&self) -> bool {
+        self.inner.supports_embeddings()
+    }
+
+    async fn embed(
+        &self,
+        texts: &[String],
+        model: Option<&str>,
+    ) -> Result<Embeddings, ServerError> {
+        self.check()?;
+        let result = self.inner.embed(texts, model).await;
+        self.record(&result, false);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_failures_and_probes_after_cooldown() {
+        let breaker = CircuitBreaker::new(BreakerConfig {
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(10),
+        });
+        let start = Instant::now();
+        assert!(breaker.allow_at(start));
+        assert!(!breaker.record_at(false, start));
+        assert!(breaker.record_at(false, start), "second failure opens");
+        assert!(!breaker.allow_at(start + Duration::from_secs(5)));
+
+        // One probe after the cooldown; a failed probe reopens
+        let later = start + Duration::from_secs(10);
+        assert!(breaker.allow_at(later));
+        assert!(!breaker.allow_at(later));
+        breaker.record_at(false, later);
+        assert!(!breaker.allow_at(later + Duration::from_secs(1)));
+
+        let probe = later + Duration::from_secs(10);
+        assert!(breaker.allow_at(probe));
+        assert!(breaker.record_at(true, probe), "successful probe closes");
+        assert!(breaker.allow_at(probe));
+    }
+}
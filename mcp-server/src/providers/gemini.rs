@@ -0,0 +1,660 @@
+//! Google Gemini backend.
+
+use super::keys::KeyPool;
+use super::{
+    confidence, probe_request, request_error, retry_after, sse_text_stream, within, Completion,
+    CompletionRequest, CompletionStream, Embeddings, FinishReason, LlmProvider,
+};
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use crate::images::Image;
+use crate::models::*;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Base URL of the Gemini model endpoints.
+pub const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+
+/// Models served by the Gemini API.
+pub const GEMINI_MODELS: &[&str] = &[
+    "gemini-2.5-pro",
+    "gemini-2.5-flash",
+    "gemini-2.5-flash-lite",
+    "gemini-2.0-flash",
+    "gemini-2.0-flash-lite",
+    "gemini-1.5-pro",
+    "gemini-1.5-flash",
+];
+
+/// Model used for agents configured with one Gemini doesn't serve.
+pub const GEMINI_DEFAULT_MODEL: &str = "gemini-2.0-flash";
+
+/// Model computing embeddings when a request doesn't pick one.
+pub const GEMINI_EMBEDDING_MODEL: &str = "text-embedding-004";
+
+/// Talks to Gemini with one or more API keys.
+pub struct GeminiProvider {
+    client: HttpClient,
+    keys: Arc<KeyPool>,
+    api_base: String,
+    timeout: Option<Duration>,
+}
+
+impl GeminiProvider {
+    /// Creates a provider calling the public Gemini API.
+    pub fn new(client: HttpClient, api_key: String) -> Self {
+        Self::with_keys(client, Arc::new(KeyPool::single("gemini", api_key)))
+    }
+
+    /// Creates a provider calling the public Gemini API with the keys of
+    /// `keys`.
+    pub fn with_keys(client: HttpClient, keys: Arc<KeyPool>) -> Self {
+        Self {
+            client,
+            keys,
+            api_base: GEMINI_API_BASE.to_string(),
+            timeout: None,
+        }
+    }
+
+    /// Sends requests to another base URL instead.
+    pub fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+
+    /// Gives up on calls after `timeout`, unless a request sets its own.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn url(&self, model: &str, method: &str) -> String {
+        format!("{}/{}:{}", self.api_base, model, method)
+    }
+
+    async fn send(
+        &self,
+        url: &str,
+        body: &impl serde::Serialize,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, ServerError> {
+        let send = self.keys.send(|api_key| {
+            // A rate-limited key is swapped for another rather than waited on
+            let rotate = self.keys.has_other_usable(&api_key);
+            let request = self
+                .client
+                .post(url)
+                .header("x-goog-api-key", api_key)
+                .header("Content-Type", "application/json")
+                .json(body);
+            let request = match timeout {
+                Some(timeout) => request.timeout(timeout),
+                None => request,
+            };
+            async move {
+                self.client
+                    .send_with_retry_unless(request, |status| {
+                        rotate && status == StatusCode::TOO_MANY_REQUESTS
+                    })
+                    .await
+                    .map_err(|e| request_error("Gemini", "Gemini API request failed", e))
+            }
+        });
+        within("Gemini", timeout, send).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for GeminiProvider {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn known_models(&self) -> Option<Vec<String>> {
+        Some(GEMINI_MODELS.iter().map(|m| m.to_string()).collect())
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        Some(GEMINI_DEFAULT_MODEL)
+    }
+
+    fn supports_images(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion, ServerError> {
+        let api_url = self.url(&request.model, "generateContent");
+        let timeout = request.timeout.or(self.timeout);
+        let gemini_request = build_gemini_request(
+            &request.agent,
+            request.user_text,
+            request.conversation_history,
+            &request.generation,
+            request.images,
+        );
+
+        within("Gemini", timeout, async {
+            // Make the HTTP request, holding a per-host slot until the body is read
+            let _permit = self.client.acquire(&api_url).await;
+            let response = self.send(&api_url, &gemini_request, timeout).await?;
+            let response_status = response.status();
+            let delay = retry_after(&response);
+            let response_text = response
+                .text()
+                .await
+                .map_err(|e| request_error("Gemini", "Failed to read Gemini response", e))?;
+
+            parse_gemini_response(response_status, &response_text)
+                .map_err(|e| e.with_retry_after(delay))
+        })
+        .await
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream, ServerError> {
+        let api_url = self.url(&request.model, "streamGenerateContent?alt=sse");
+        let timeout = request.timeout.or(self.timeout);
+        let gemini_request = build_gemini_request(
+            &request.agent,
+            request.user_text,
+            request.conversation_history,
+            &request.generation,
+            request.images,
+        );
+
+        let permit = self.client.acquire(&api_url).await;
+        let response = self.send(&api_url, &gemini_request, timeout).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let delay = retry_after(&response);
+            let body = response.text().await.unwrap_or_default();
+            return Err(ServerError::status("Gemini", status, &body).with_retry_after(delay));
+        }
+        Ok(sse_text_stream(response, permit, parse_gemini_chunk))
+    }
+
+    async fn count_tokens(&self, request: &CompletionRequest) -> Result<u32, ServerError> {
+        let api_url = self.url(&request.model, "countTokens");
+        let body = serde_json::json!({
+            "generateContentRequest": {
+                "model": format!("models/{}", request.model),
+                "contents": build_gemini_request(
+                    &request.agent,
+                    request.user_text.clone(),
+                    request.conversation_history.clone(),
+                    &request.generation,
+                    request.images.clone(),
+                ).contents,
+            }
+        });
+
+        let _permit = self.client.acquire(&api_url).await;
+        let response = self
+            .send(&api_url, &body, request.timeout.or(self.timeout))
+            .await?;
+        let status = response.status();
+        let delay = retry_after(&response);
+        let text = response
+            .text()
+            .await
+            .map_err(|e| request_error("Gemini", "Failed to read Gemini response", e))?;
+        if !status.is_success() {
+            return Err(ServerError::status("Gemini", status, &text).with_retry_after(delay));
+        }
+        let counted: serde_json::Value = serde_json::from_str(&text).map_err(|e| {
+            ServerError::provider(format!(
+                "Failed to parse Gemini response: {}. Raw: {}",
+                e, text
+            ))
+        })?;
+        counted["totalTokens"]
+            .as_u64()
+            .map(|n| n as u32)
+            .ok_or_else(|| {
+                ServerError::provider(format!(
+                    "Gemini countTokens response has no totalTokens: {}",
+                    text
+                ))
+            })
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<(), ServerError> {
+        let request = self
+            .client
+            .get(&self.api_base)
+            .query(&[("pageSize", "1")])
+            .header("x-goog-api-key", self.keys.pick());
+        probe_request("Gemini", request, timeout).await
+    }
+
+    fn supports_embeddings(&self) -> bool {
+        true
+    }
+
+    async fn embed(
+        &self,
+        texts: &[String],
+        model: Option<&str>,
+    ) -> Result<Embeddings, ServerError> {
+        let model = model.unwrap_or(GEMINI_EMBEDDING_MODEL);
+        let api_url = self.url(model, "batchEmbedContents");
+        let requests: Vec<_> = texts
+            .iter()
+            .map(|text| {
+                serde_json::json!({
+                    "model": format!("models/{}", model),
+                    "content": { "parts": [{ "text": text }] }
+                })
+            })
+            .collect();
+        let body = serde_json::json!({ "requests": requests });
+
+        let _permit = self.client.acquire(&api_url).await;
+        let response = self.send(&api_url, &body, self.timeout).await?;
+        let status = response.status();
+        let delay = retry_after(&response);
+        let text = response
+            .text()
+            .await
+            .map_err(|e| request_error("Gemini", "Failed to read Gemini response", e))?;
+        let vectors = parse_gemini_embeddings(status, &text, texts.len())
+            .map_err(|e| e.with_retry_after(delay))?;
+        Ok(Embeddings {
+            vectors,
+            model: model.to_string(),
+            // batchEmbedContents reports no usage
+            tokens_used: None,
+        })
+    }
+}
+
+/// Interprets a Gemini `generateContent` HTTP response.
+///
+/// Returns the reply text and total token count. Error statuses, prompts
+/// blocked by safety filters, and candidates stopped for safety reasons are
+/// reported as errors; a successful response without candidates yields no
+/// reply text.
+pub fn parse_gemini_response(status: StatusCode, body: &str) -> Result<Completion, ServerError> {
+    // Check for HTTP errors
+    if !status.is_success() {
+        tracing::error!("Gemini API error response ({}): {}", status, body);
+        return Err(ServerError::status("Gemini", status, body));
+    }
+
+    tracing::info!("Gemini API response received successfully");
+
+    // Parse the JSON response
+    let gemini_response: GeminiResponse = serde_json::from_str(body).map_err(|e| {
+        ServerError::provider(format!(
+            "Failed to parse Gemini response: {}. Raw: {}",
+            e, body
+        ))
+    })?;
+
+    let text = reply_text(&gemini_response)?;
+
+    // Score the reply from its average logprob and safety ratings
+    let candidate = gemini_response.candidates.first();
+    let finish_reason = candidate
+        .and_then(|c| c.finish_reason.as_deref())
+        .map(FinishReason::parse);
+    let flagged = candidate.is_some_and(|c| {
+        c.safety_ratings
+            .iter()
+            .any(|r| matches!(r.probability.as_str(), "MEDIUM" | "HIGH"))
+    });
+    let confidence = confidence(
+        candidate.and_then(|c| c.avg_logprobs),
+        finish_reason,
+        flagged,
+    );
+
+    // Extract token usage metadata
+    let usage = gemini_response.usage_metadata;
+    let tokens_used = usage.as_ref().and_then(|u| u.total_token_count);
+    let prompt_tokens = usage.as_ref().and_then(|u| u.prompt_token_count);
+
+    Ok(Completion {
+        text,
+        tokens_used,
+        prompt_tokens,
+        tool_calls: Vec::new(),
+        finish_reason,
+        confidence,
+    })
+}
+
+/// Interprets a Gemini `batchEmbedContents` HTTP response to `expected`
+/// texts, returning one vector per text.
+pub fn parse_gemini_embeddings(
+    status: StatusCode,
+    body: &str,
+    expected: usize,
+) -> Result<Vec<Vec<f32>>, ServerError> {
+    if !status.is_success() {
+        return Err(ServerError::status("Gemini", status, body));
+    }
+    let response: serde_json::Value = serde_json::from_str(body).map_err(|e| {
+        ServerError::provider(format!(
+            "Failed to parse Gemini response: {}. Raw: {}",
+            e, body
+        ))
+    })?;
+    let vectors: Option<Vec<Vec<f32>>> = response["embeddings"]
+        .as_array()
+        .map(|embeddings| {
+            embeddings
+                .iter()
+                .map(|embedding| serde_json::from_value(embedding["values"].clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    match vectors {
+        Some(vectors) if vectors.len() == expected => Ok(vectors),
+        _ => Err(ServerError::provider(format!(
+            "Gemini batchEmbedContents response has no {} embeddings: {}",
+            expected, body
+        ))),
+    }
+}
+
+/// Extracts the reply text of a (possibly partial) response, failing if
+/// safety filters blocked it.
+fn reply_text(gemini_response: &GeminiResponse) -> Result<Option<String>, ServerError> {
+    // The whole prompt was rejected before generation
+    if let Some(reason) = gemini_response
+        .prompt_feedback
+        .as_ref()
+        .and_then(|f| f.block_reason.as_deref())
+    {
+        return Err(ServerError::provider(format!(
+            "Gemini blocked the prompt: {}",
+            reason
+        )));
+    }
+
+    let candidate = gemini_response.candidates.first();
+
+    // Generation stopped by safety filters before producing any text
+    if let Some(candidate) = candidate {
+        let has_text = candidate
+            .content
+            .as_ref()
+            .is_some_and(|c| !c.parts.is_empty());
+        if !has_text && candidate.finish_reason.as_deref() == Some("SAFETY") {
+            return Err(ServerError::provider("Gemini blocked the response: SAFETY"));
+        }
+    }
+
+    // Extract the reply text
+    Ok(candidate
+        .and_then(|c| c.content.as_ref())
+        .and_then(|c| c.parts.first())
+        .map(|p| p.text.clone()))
+}
+
+/// Extracts the text delta from one streamed `GenerateContentResponse`.
+fn parse_gemini_chunk(data: &str) -> Result<Option<String>, ServerError> {
+    let chunk: GeminiResponse = serde_json::from_str(data).map_err(|e| {
+        ServerError::provider(format!(
+            "Failed to parse Gemini stream chunk: {}. Raw: {}",
+            e, data
+        ))
+    })?;
+    Ok(reply_text(&chunk)?.filter(|text| !text.is_empty()))
+}
+
+/// Builds the Gemini `generateContent` request body for an agent.
+///
+/// Conversation history is mapped onto Gemini roles: `assistant` becomes
+/// `model`, with its tool calls as function calls, and `tool` results are
+/// sent as function responses from the `user`. Gemini has no `system` role
+/// in the contents, so `system` messages follow the agent's system prompt in
+/// the system instruction. Messages with any other role are skipped. The
+/// current user text is appended last, after any `images` as inline data
+/// parts.
+pub fn build_gemini_request(
+    agent: &Agent,
+    user_text: String,
+    conversation_history: Option<Vec<Message>>,
+    generation: &GenerationParams,
+    images: Vec<Image>,
+) -> GeminiRequest {
+    let mut contents = vec![];
+    let mut system_parts = vec![GeminiPart {
+        text: agent.system_prompt.clone(),
+        ..Default::default()
+    }];
+
+    // Convert conversation history to Gemini format
+    let history = conversation_history.unwrap_or_default();
+    // Gemini answers a function call by its name rather than an ID
+    let called: HashMap<&str, &str> = history
+        .iter()
+        .flat_map(|msg| &msg.tool_calls)
+        .map(|call| (call.id.as_str(), call.function.name.as_str()))
+        .collect();
+    for msg in &history {
+        let text = |text: &str| GeminiPart {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let (role, parts) = match msg.role.as_str() {
+            "user" => ("user", vec![text(&msg.content)]),
+            "assistant" => {
+                let calls = msg.tool_calls.iter().map(|call| GeminiPart {
+                    function_call: Some(GeminiFunctionCall {
+                        name: call.function.name.clone(),
+                        args: serde_json::from_str(&call.function.arguments)
+                            .unwrap_or_else(|_| serde_json::json!({})),
+                    }),
+                    ..Default::default()
+                });
+                let content = (!msg.content.is_empty() || msg.tool_calls.is_empty())
+                    .then(|| text(&msg.content));
+                ("model", content.into_iter().chain(calls).collect())
+            }
+            "tool" => {
+                let name = msg
+                    .tool_call_id
+                    .as_deref()
+                    .and_then(|id| called.get(id).copied());
+                let part = match name {
+                    Some(name) => GeminiPart {
+                        function_response: Some(GeminiFunctionResponse {
+                            name: name.to_string(),
+                            response: function_response(&msg.content),
+                        }),
+                        ..Default::default()
+                    },
+                    // A result without its call can only be passed on as text
+                    None => text(&format!("Tool result: {}", msg.content)),
+                };
+                ("user", vec![part])
+            }
+            "system" => {
+                system_parts.push(text(&msg.content));
+                continue;
+            }
+            _ => continue,
+        };
+        contents.push(GeminiContent {
+            role: role.to_string(),
+            parts,
+        });
+    }
+
+    // Add the current user message, images first as Gemini recommends, unless
+    // the turn only returns tool results
+    let answers_tool_calls = history.last().is_some_and(|msg| msg.role == "tool");
+    let mut parts: Vec<GeminiPart> = images
+        .into_iter()
+        .map(|image| GeminiPart {
+            inline_data: Some(GeminiInlineData {
+                mime_type: image.mime_type,
+                data: image.data,
+            }),
+            ..Default::default()
+        })
+        .collect();
+    if !user_text.is_empty() || (parts.is_empty() && !answers_tool_calls) {
+        parts.push(GeminiPart {
+            text: user_text,
+            ..Default::default()
+        });
+    }
+    if !parts.is_empty() {
+        contents.push(GeminiContent {
+            role: "user".to_string(),
+            parts,
+        });
+    }
+
+    let config = GeminiGenerationConfig {
+        temperature: generation.temperature,
+        max_output_tokens: generation.max_tokens,
+        top_p: generation.top_p,
+        frequency_penalty: generation.frequency_penalty,
+        stop_sequences: generation.stop.clone().filter(|stop| !stop.is_empty()),
+        seed: generation.seed,
+    };
+
+    GeminiRequest {
+        contents,
+        system_instruction: Some(GeminiSystemInstruction {
+            parts: system_parts,
+        }),
+        generation_config: (config != GeminiGenerationConfig::default()).then_some(config),
+    }
+}
+
+/// The `response` object of a function response: the tool's result when it
+/// is a JSON object, or the result wrapped in `{"content": ...}`.
+fn function_response(content: &str) -> serde_json::Value {
+    match serde_json::from_str::<serde_json::Value>(content) {
+        Ok(object) if object.is_object() => object,
+        _ => serde_json::json!({ "content": content }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gemini_request_maps_roles_and_skips_unknown() {
+        let agent = crate::agents::AgentStore::default()
+            .get("agent_001")
+            .unwrap();
+        let history = vec![
+            Message {
+                role: "user".to_string(),
+                content: "hi".to_string(),
+                ..Default::default()
+            },
+            Message {
+                role: "assistant".to_string(),
+                content: "hello".to_string(),
+                ..Default::default()
+            },
+            Message {
+                role: "narrator".to_string(),
+                content: "ignored".to_string(),
+                ..Default::default()
+            },
+        ];
+        let request = build_gemini_request(
+            &agent,
+            "next".to_string(),
+            Some(history),
+            &Default::default(),
+            Vec::new(),
+        );
+        let roles: Vec<_> = request.contents.iter().map(|c| c.role.as_str()).collect();
+        assert_eq!(roles, ["user", "model", "user"]);
+        assert_eq!(request.contents[2].parts[0].text, "next");
+    }
+
+    #[test]
+    fn gemini_request_maps_tool_and_system_messages() {
+        let agent = crate::agents::AgentStore::default()
+            .get("agent_001")
+            .unwrap();
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: "get_balance".to_string(),
+                arguments: r#"{"address":"0xabc"}"#.to_string(),
+            },
+        };
+        let history = vec![
+            Message {
+                role: "system".to_string(),
+                content: "Answer in one line.".to_string(),
+                ..Default::default()
+            },
+            Message {
+                role: "user".to_string(),
+                content: "balance of 0xabc?".to_string(),
+                ..Default::default()
+            },
+            Message {
+                role: "assistant".to_string(),
+                tool_calls: vec![call],
+                ..Default::default()
+            },
+            Message {
+                role: "tool".to_string(),
+                content: "1.5 ETH".to_string(),
+                tool_call_id: Some("call_1".to_string()),
+                ..Default::default()
+            },
+        ];
+        let request = build_gemini_request(
+            &agent,
+            String::new(),
+            Some(history),
+            &Default::default(),
+            Vec::new(),
+        );
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            body["contents"],
+            serde_json::json!([
+                { "role": "user", "parts": [{ "text": "balance of 0xabc?" }] },
+                { "role": "model", "parts": [
+                    { "function_call": { "name": "get_balance", "args": { "address": "0xabc" } } },
+                ] },
+                { "role": "user", "parts": [
+                    { "function_response": { "name": "get_balance", "response": { "content": "1.5 ETH" } } },
+                ] },
+            ])
+        );
+        let system = &request.system_instruction.unwrap().parts;
+        assert_eq!(system[1].text, "Answer in one line.");
+    }
+
+    #[test]
+    fn parses_embeddings() {
+        let body = r#"{"embeddings":[{"values":[0.1,-0.2]},{"values":[0.3,0.4]}]}"#;
+        assert_eq!(
+            parse_gemini_embeddings(StatusCode::OK, body, 2),
+            Ok(vec![vec![0.1, -0.2], vec![0.3, 0.4]])
+        );
+        assert!(parse_gemini_embeddings(StatusCode::OK, body, 3).is_err());
+        assert!(parse_gemini_embeddings(StatusCode::OK, r#"{"embeddings":[{}]}"#, 1).is_err());
+        assert!(parse_gemini_embeddings(StatusCode::BAD_REQUEST, "{}", 1).is_err());
+    }
+
+    #[test]
+    fn parses_stream_chunks() {
+        let delta = r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Hi"}]}}]}"#;
+        assert_eq!(parse_gemini_chunk(delta), Ok(Some("Hi".to_string())));
+        let blocked = r#"{"promptFeedback":{"blockReason":"SAFETY"}}"#;
+        assert!(parse_gemini_chunk(blocked).is_err());
+    }
+}
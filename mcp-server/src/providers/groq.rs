@@ -0,0 +1,476 @@
+//! Groq backend (OpenAI-compatible chat completions).
+
+use super::keys::KeyPool;
+use super::{
+    confidence, estimate_tokens, probe_request, request_error, retry_after, sse_text_stream,
+    within, Completion, CompletionRequest, CompletionStream, FinishReason, LlmProvider,
+};
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use crate::models::{Agent, FunctionTool, GenerationParams, Message};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Groq's chat completions endpoint.
+pub const GROQ_API_URL: &str = "https://api.groq.com/openai/v1/chat/completions";
+
+/// Production models served by Groq.
+pub const GROQ_MODELS: &[&str] = &[
+    "llama-3.3-70b-versatile",
+    "llama-3.1-8b-instant",
+    "meta-llama/llama-4-scout-17b-16e-instruct",
+    "meta-llama/llama-4-maverick-17b-128e-instruct",
+    "openai/gpt-oss-120b",
+    "openai/gpt-oss-20b",
+    "qwen/qwen3-32b",
+    "gemma2-9b-it",
+];
+
+/// Model used for agents configured with one Groq doesn't serve (llama
+/// models are fast and free).
+pub const GROQ_DEFAULT_MODEL: &str = "llama-3.3-70b-versatile";
+
+/// Talks to Groq with one or more API keys.
+pub struct GroqProvider {
+    client: HttpClient,
+    keys: Arc<KeyPool>,
+    api_url: String,
+    timeout: Option<Duration>,
+}
+
+impl GroqProvider {
+    /// Creates a provider calling the public Groq API.
+    pub fn new(client: HttpClient, api_key: String) -> Self {
+        Self::with_keys(client, Arc::new(KeyPool::single("groq", api_key)))
+    }
+
+    /// Creates a provider calling the public Groq API with the keys of `keys`.
+    pub fn with_keys(client: HttpClient, keys: Arc<KeyPool>) -> Self {
+        Self {
+            client,
+            keys,
+            api_url: GROQ_API_URL.to_string(),
+            timeout: None,
+        }
+    }
+
+    /// Sends requests to another OpenAI-compatible endpoint instead.
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = api_url.into();
+        self
+    }
+
+    /// Gives up on calls after `timeout`, unless a request sets its own.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The models-list URL next to the chat completions endpoint.
+    fn models_url(&self) -> Result<String, ServerError> {
+        self.api_url
+            .strip_suffix("/chat/completions")
+            .map(|base| format!("{}/models", base))
+            .ok_or_else(|| {
+                ServerError::Internal(format!("Cannot derive a models URL from {}", self.api_url))
+            })
+    }
+
+    async fn send(
+        &self,
+        body: &serde_json::Value,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, ServerError> {
+        let send = self.keys.send(|api_key| {
+            // A rate-limited key is swapped for another rather than waited on
+            let rotate = self.keys.has_other_usable(&api_key);
+            let request = self
+                .client
+                .post(&self.api_url)
+                .header("Authorization", format!("Bearer {}", api_key))
+                .header("Content-Type", "application/json")
+                .json(body);
+            let request = match timeout {
+                Some(timeout) => request.timeout(timeout),
+                None => request,
+            };
+            async move {
+                self.client
+                    .send_with_retry_unless(request, |status| {
+                        rotate && status == StatusCode::TOO_MANY_REQUESTS
+                    })
+                    .await
+                    .map_err(|e| request_error("Groq", "Groq API request failed", e))
+            }
+        });
+        within("Groq", timeout, send).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for GroqProvider {
+    fn name(&self) -> &'static str {
+        "groq"
+    }
+
+    fn known_models(&self) -> Option<Vec<String>> {
+        Some(GROQ_MODELS.iter().map(|m| m.to_string()).collect())
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        Some(GROQ_DEFAULT_MODEL)
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion, ServerError> {
+        let timeout = request.timeout.or(self.timeout);
+        let body = build_groq_request(
+            &request.agent,
+            &request.model,
+            request.user_text,
+            request.conversation_history,
+            &request.generation,
+            &request.tools,
+        );
+
+        within("Groq", timeout, async {
+            // Hold a per-host slot until the body is read
+            let _permit = self.client.acquire(&self.api_url).await;
+            let response = self.send(&body, timeout).await?;
+            let response_status = response.status();
+            let delay = retry_after(&response);
+            let response_text = response
+                .text()
+                .await
+                .map_err(|e| request_error("Groq", "Failed to read Groq response", e))?;
+
+            parse_groq_response(response_status, &response_text)
+                .map_err(|e| e.with_retry_after(delay))
+        })
+        .await
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream, ServerError> {
+        let timeout = request.timeout.or(self.timeout);
+        let mut body = build_groq_request(
+            &request.agent,
+            &request.model,
+            request.user_text,
+            request.conversation_history,
+            &request.generation,
+            &request.tools,
+        );
+        body["stream"] = json!(true);
+
+        let permit = self.client.acquire(&self.api_url).await;
+        let response = self.send(&body, timeout).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let delay = retry_after(&response);
+            let body = response.text().await.unwrap_or_default();
+            return Err(ServerError::status("Groq", status, &body).with_retry_after(delay));
+        }
+        Ok(sse_text_stream(response, permit, parse_groq_chunk))
+    }
+
+    async fn count_tokens(&self, request: &CompletionRequest) -> Result<u32, ServerError> {
+        // Groq has no token counting endpoint
+        Ok(estimate_tokens(request))
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<(), ServerError> {
+        let request = self
+            .client
+            .get(self.models_url()?)
+            .header("Authorization", format!("Bearer {}", self.keys.pick()));
+        probe_request("Groq", request, timeout).await
+    }
+}
+
+/// Interprets a Groq (OpenAI-compatible) chat completion HTTP response.
+///
+/// Returns the reply text and total token count. A successful response
+/// without a message yields no reply text.
+pub fn parse_groq_response(status: StatusCode, body: &str) -> Result<Completion, ServerError> {
+    parse_chat_completion("Groq", status, body)
+}
+
+/// Interprets an OpenAI-compatible chat completion response from `backend`.
+pub(super) fn parse_chat_completion(
+    backend: &str,
+    status: StatusCode,
+    body: &str,
+) -> Result<Completion, ServerError> {
+    if !status.is_success() {
+        tracing::error!("{} API error response ({}): {}", backend, status, body);
+        return Err(ServerError::status(backend, status, body));
+    }
+
+    tracing::info!("{} API response received successfully", backend);
+
+    // Parse OpenAI-compatible response
+    let response: serde_json::Value = serde_json::from_str(body).map_err(|e| {
+        ServerError::provider(format!(
+            "Failed to parse {} response: {}. Raw: {}",
+            backend, e, body
+        ))
+    })?;
+
+    // Extract the reply text from OpenAI-compatible format
+    let text = response["choices"][0]["message"]["content"]
+        .as_str()
+        .map(str::to_string);
+
+    // Extract token usage
+    let tokens_used = response["usage"]["total_tokens"].as_u64().map(|t| t as u32);
+    let prompt_tokens = response["usage"]["prompt_tokens"]
+        .as_u64()
+        .map(|t| t as u32);
+
+    // Extract requested function calls
+    let tool_calls = match response["choices"][0]["message"].get("tool_calls") {
+        Some(calls) if !calls.is_null() => serde_json::from_value(calls.clone()).map_err(|e| {
+            ServerError::provider(format!("Failed to parse {} tool calls: {}", backend, e))
+        })?,
+        _ => Vec::new(),
+    };
+
+    // Score the reply from its token logprobs, when they were requested
+    let finish_reason = response["choices"][0]["finish_reason"]
+        .as_str()
+        .map(FinishReason::parse);
+    let avg_logprob = response["choices"][0]["logprobs"]["content"]
+        .as_array()
+        .filter(|tokens| !tokens.is_empty())
+        .map(|tokens| {
+            let sum: f64 = tokens.iter().filter_map(|t| t["logprob"].as_f64()).sum();
+            sum / tokens.len() as f64
+        });
+
+    Ok(Completion {
+        text,
+        tokens_used,
+        prompt_tokens,
+        tool_calls,
+        finish_reason,
+        confidence: confidence(avg_logprob, finish_reason, false),
+    })
+}
+
+/// Extracts the text delta from one streamed chunk.
+fn parse_groq_chunk(data: &str) -> Result<Option<String>, ServerError> {
+    parse_chat_chunk("Groq", data)
+}
+
+/// Extracts the text delta from one OpenAI-compatible streamed chunk.
+pub(super) fn parse_chat_chunk(backend: &str, data: &str) -> Result<Option<String>, ServerError> {
+    if data == "[DONE]" {
+        return Ok(None);
+    }
+    let chunk: serde_json::Value = serde_json::from_str(data).map_err(|e| {
+        ServerError::provider(format!(
+            "Failed to parse {} stream chunk: {}. Raw: {}",
+            backend, e, data
+        ))
+    })?;
+    if let Some(error) = chunk.get("error") {
+        return Err(ServerError::provider(format!(
+            "{} stream error: {}",
+            backend, error
+        )));
+    }
+    Ok(chunk["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|text| !text.is_empty())
+        .map(str::to_string))
+}
+
+/// Builds the OpenAI-compatible chat completion body sent to Groq.
+///
+/// The agent's system prompt is the first message, followed by the conversation
+/// history as-is and finally the current user text.
+pub fn build_groq_request(
+    agent: &Agent,
+    model: &str,
+    user_text: String,
+    conversation_history: Option<Vec<Message>>,
+    generation: &GenerationParams,
+    tools: &[FunctionTool],
+) -> serde_json::Value {
+    let mut body = json!({
+        "model": model,
+        "messages": chat_messages(agent, user_text, conversation_history),
+    });
+    add_sampling(&mut body, generation);
+    add_tools(&mut body, tools);
+    body
+}
+
+/// Declares the functions the model may call, if any.
+pub(super) fn add_tools(body: &mut serde_json::Value, tools: &[FunctionTool]) {
+    if !tools.is_empty() {
+        body["tools"] = json!(tools);
+    }
+}
+
+/// Adds the OpenAI-style sampling fields to a chat completion body.
+///
+/// `temperature` and `max_tokens` fall back to the server defaults; the other
+/// fields are only sent when requested.
+pub(super) fn add_sampling(body: &mut serde_json::Value, generation: &GenerationParams) {
+    body["temperature"] = json!(generation
+        .temperature
+        .unwrap_or(GenerationParams::DEFAULT_TEMPERATURE));
+    body["max_tokens"] = json!(generation
+        .max_tokens
+        .unwrap_or(GenerationParams::DEFAULT_MAX_TOKENS));
+    if let Some(top_p) = generation.top_p {
+        body["top_p"] = json!(top_p);
+    }
+    if let Some(penalty) = generation.frequency_penalty {
+        body["frequency_penalty"] = json!(penalty);
+    }
+    if let Some(stop) = generation.stop.as_ref().filter(|stop| !stop.is_empty()) {
+        body["stop"] = json!(stop);
+    }
+    if let Some(seed) = generation.seed {
+        body["seed"] = json!(seed);
+    }
+}
+
+/// The OpenAI-style `messages` array for an agent's reply.
+pub(super) fn chat_messages(
+    agent: &Agent,
+    user_text: String,
+    conversation_history: Option<Vec<Message>>,
+) -> Vec<serde_json::Value> {
+    let mut messages = vec![json!({
+        "role": "system",
+        "content": agent.system_prompt.clone()
+    })];
+
+    // Add conversation history if available
+    let history = conversation_history.unwrap_or_default();
+    let answers_tool_calls = history.last().is_some_and(|msg| msg.role == "tool");
+    for msg in history {
+        let mut message = json!({
+            "role": msg.role,
+            "content": msg.content
+        });
+        if !msg.tool_calls.is_empty() {
+            if msg.content.is_empty() {
+                message["content"] = serde_json::Value::Null;
+            }
+            message["tool_calls"] = json!(msg.tool_calls);
+        }
+        if let Some(id) = msg.tool_call_id {
+            message["tool_call_id"] = json!(id);
+        }
+        messages.push(message);
+    }
+
+    // Add current user message, unless the turn only returns tool results
+    if !(user_text.is_empty() && answers_tool_calls) {
+        messages.push(json!({
+            "role": "user",
+            "content": user_text
+        }));
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ToolCall;
+
+    #[test]
+    fn probes_the_models_list_next_to_the_endpoint() {
+        let groq = GroqProvider::new(reqwest::Client::new().into(), String::new());
+        assert_eq!(
+            groq.models_url().unwrap(),
+            "https://api.groq.com/openai/v1/models"
+        );
+        let custom = groq.with_api_url("http://localhost:8080/complete");
+        assert!(custom.models_url().is_err());
+    }
+
+    #[test]
+    fn parses_stream_chunks() {
+        let delta = r#"{"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#;
+        assert_eq!(parse_groq_chunk(delta), Ok(Some("Hel".to_string())));
+        let role_only = r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":""}}]}"#;
+        assert_eq!(parse_groq_chunk(role_only), Ok(None));
+        assert_eq!(parse_groq_chunk("[DONE]"), Ok(None));
+        assert!(parse_groq_chunk(r#"{"error":{"message":"overloaded"}}"#).is_err());
+    }
+
+    #[test]
+    fn forwards_sampling_settings() {
+        let agent = crate::agents::AgentStore::default()
+            .get("agent_001")
+            .unwrap();
+        let build =
+            |generation| build_groq_request(&agent, "m", "hi".into(), None, &generation, &[]);
+
+        let body = build(GenerationParams::default());
+        assert_eq!(body["temperature"], json!(0.7));
+        assert_eq!(body["max_tokens"], json!(1024));
+        assert!(body.get("top_p").is_none());
+
+        let body = build(GenerationParams {
+            temperature: Some(0.0),
+            max_tokens: Some(64),
+            top_p: Some(0.5),
+            frequency_penalty: Some(-1.0),
+            stop: Some(vec!["\n\n".to_string()]),
+            seed: Some(42),
+        });
+        assert_eq!(body["temperature"], json!(0.0));
+        assert_eq!(body["max_tokens"], json!(64));
+        assert_eq!(body["top_p"], json!(0.5));
+        assert_eq!(body["frequency_penalty"], json!(-1.0));
+        assert_eq!(body["stop"], json!(["\n\n"]));
+        assert_eq!(body["seed"], json!(42));
+    }
+
+    #[test]
+    fn sends_tool_calls_and_results_back() {
+        let agent = crate::agents::AgentStore::default()
+            .get("agent_001")
+            .unwrap();
+        let call: ToolCall = serde_json::from_value(json!({
+            "id": "call_1",
+            "type": "function",
+            "function": { "name": "get_balance", "arguments": "{}" }
+        }))
+        .unwrap();
+        let history = vec![
+            Message {
+                role: "assistant".to_string(),
+                tool_calls: vec![call],
+                ..Default::default()
+            },
+            Message {
+                role: "tool".to_string(),
+                content: "1.5 ETH".to_string(),
+                tool_call_id: Some("call_1".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        // An empty user text after tool results adds no user message
+        let messages = chat_messages(&agent, String::new(), Some(history));
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"], serde_json::Value::Null);
+        assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+    }
+}
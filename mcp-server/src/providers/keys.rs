@@ -0,0 +1,458 @@
+//! Pools of API keys shared by the calls to one backend.
+//!
+//! Groq and Gemini accept several keys, so a single rate-limited or revoked
+//! key doesn't take the backend down. Each call picks a key by the pool's
+//! [`KeyStrategy`]; a key answered with `429` rests for the `Retry-After` the
+//! provider asked for (or a minute), and a key answered with `401` or `403`
+//! is set aside as rejected. A call that hits either is sent again right away
+//! with the next usable key. When no key is usable, calls use the one back
+//! soonest, so a pool of one key behaves like a plain key.
+//!
+//! The admin `rotate_provider_keys` method replaces a pool's keys at runtime,
+//! keeping what is known of the keys it keeps; rotated keys last until the
+//! server restarts.
+//!
+//! # Environment Variables
+//!
+//! * `GROQ_API_KEYS`, `GEMINI_API_KEYS` - Optional. Comma-separated keys,
+//!   used with `GROQ_API_KEY` or `GEMINI_API_KEY` if that is set too
+//! * `PROVIDER_KEY_STRATEGY` - Optional. `round_robin` or
+//!   `least_recently_limited` (default: `round_robin`)
+
+use crate::error::ServerError;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a key answered with `429` rests when the provider didn't say.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// How the next key is picked among the usable ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStrategy {
+    /// Each key in turn
+    #[default]
+    RoundRobin,
+    /// The key rate-limited longest ago, or never
+    LeastRecentlyLimited,
+}
+
+impl KeyStrategy {
+    /// Reads `PROVIDER_KEY_STRATEGY`.
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("PROVIDER_KEY_STRATEGY")
+            .unwrap_or_default()
+            .trim()
+        {
+            "" | "round_robin" => Ok(Self::RoundRobin),
+            "least_recently_limited" => Ok(Self::LeastRecentlyLimited),
+            other => Err(format!(
+                "PROVIDER_KEY_STRATEGY must be round_robin or least_recently_limited, got {}",
+                other
+            )),
+        }
+    }
+}
+
+/// What is known of a key, as reported by `rotate_provider_keys`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyState {
+    /// Usable
+    Ok,
+    /// Answered with `429`, resting
+    RateLimited,
+    /// Answered with `401` or `403`
+    Rejected,
+}
+
+/// A key of a pool, without the key itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyStatus {
+    /// The key's last four characters, e.g. `...f3Qx`
+    pub key: String,
+    /// Whether it is used
+    pub state: KeyState,
+    /// Seconds it still rests, when rate-limited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    /// Calls sent with it since it joined the pool
+    pub calls: u64,
+}
+
+/// Parameters of the admin `rotate_provider_keys` method.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RotateKeysParams {
+    /// Backend whose keys are replaced, `groq` or `gemini`
+    pub provider: String,
+    /// The new keys, at least one
+    pub api_keys: Vec<String>,
+}
+
+/// Result of the `rotate_provider_keys` method.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RotateKeysResult {
+    /// Backend whose keys were replaced
+    pub provider: String,
+    /// Its keys now, in order
+    pub keys: Vec<KeyStatus>,
+}
+
+#[derive(Debug)]
+struct PooledKey {
+    key: String,
+    limited_until: Option<Instant>,
+    last_limited: Option<Instant>,
+    rejected_at: Option<Instant>,
+    calls: u64,
+}
+
+impl PooledKey {
+    fn new(key: String) -> Self {
+        Self {
+            key,
+            limited_until: None,
+            last_limited: None,
+            rejected_at: None,
+            calls: 0,
+        }
+    }
+
+    fn is_usable(&self, now: Instant) -> bool {
+        self.rejected_at.is_none() && self.limited_until.is_none_or(|until| until <= now)
+    }
+}
+
+#[derive(Debug)]
+struct Keys {
+    keys: Vec<PooledKey>,
+    /// Where the next round-robin search starts
+    next: usize,
+}
+
+/// The API keys of one backend.
+#[derive(Debug)]
+pub struct KeyPool {
+    provider: &'static str,
+    strategy: KeyStrategy,
+    keys: Mutex<Keys>,
+}
+
+impl KeyPool {
+    /// A pool of `keys` for `provider`; fails without a key.
+    pub fn new(
+        provider: &'static str,
+        keys: Vec<String>,
+        strategy: KeyStrategy,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            provider,
+            strategy,
+            keys: Mutex::new(Keys {
+                keys: validated(provider, keys)?
+                    .into_iter()
+                    .map(PooledKey::new)
+                    .collect(),
+                next: 0,
+            }),
+        })
+    }
+
+    /// A pool of the single `key`.
+    pub fn single(provider: &'static str, key: String) -> Self {
+        Self {
+            provider,
+            strategy: KeyStrategy::RoundRobin,
+            keys: Mutex::new(Keys {
+                keys: vec![PooledKey::new(key)],
+                next: 0,
+            }),
+        }
+    }
+
+    /// Reads the keys of `provider` from `<PREFIX>_API_KEY` and
+    /// `<PREFIX>_API_KEYS`, each key once in the order first listed; `None`
+    /// without any.
+    pub fn from_env(
+        provider: &'static str,
+        prefix: &str,
+        strategy: KeyStrategy,
+    ) -> Result<Option<Self>, String> {
+        let var = |name: String| std::env::var(name).unwrap_or_default();
+        let keys: Vec<String> = [
+            var(format!("{}_API_KEY", prefix)),
+            var(format!("{}_API_KEYS", prefix)),
+        ]
+        .iter()
+        .flat_map(|keys| keys.split(','))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect();
+        // Duplicates, adjacent or not, are dropped by `new`.
+        match keys.is_empty() {
+            true => Ok(None),
+            false => Self::new(provider, keys, strategy).map(Some),
+        }
+    }
+
+    /// Name of the backend the keys are for.
+    pub fn provider(&self) -> &'static str {
+        self.provider
+    }
+
+    /// Number of keys.
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().keys.len()
+    }
+
+    /// Whether the pool has no key, which never happens.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The key the next call should use.
+    pub fn pick(&self) -> String {
+        let now = Instant::now();
+        let mut pool = self.keys.lock().unwrap();
+        let count = pool.keys.len();
+        let in_turn = (0..count).map(|offset| (pool.next + offset) % count);
+        let mut usable = in_turn.filter(|&i| pool.keys[i].is_usable(now));
+        let picked = match self.strategy {
+            KeyStrategy::RoundRobin => usable.next(),
+            KeyStrategy::LeastRecentlyLimited => usable.min_by_key(|&i| pool.keys[i].last_limited),
+        }
+        // None is usable: the key back soonest, rejected keys last
+        .or_else(|| {
+            (0..count).min_by_key(|&i| {
+                let key = &pool.keys[i];
+                (
+                    key.rejected_at.is_some(),
+                    key.limited_until,
+                    key.rejected_at,
+                )
+            })
+        })
+        .unwrap_or_default();
+        pool.next = (picked + 1) % count.max(1);
+        let key = &mut pool.keys[picked];
+        key.calls += 1;
+        key.key.clone()
+    }
+
+    /// Notes that `key` was answered with `429` and rests for `retry_after`.
+    pub fn rate_limited(&self, key: &str, retry_after: Option<Duration>) {
+        let now = Instant::now();
+        self.update(key, |pooled| {
+            pooled.limited_until = Some(now + retry_after.unwrap_or(DEFAULT_COOLDOWN));
+            pooled.last_limited = Some(now);
+        });
+        tracing::warn!("{} API key {} is rate-limited", self.provider, masked(key));
+    }
+
+    /// Notes that `key` was answered with `401` or `403`.
+    pub fn rejected(&self, key: &str) {
+        self.update(key, |pooled| pooled.rejected_at = Some(Instant::now()));
+        tracing::warn!("{} API key {} was rejected", self.provider, masked(key));
+    }
+
+    /// Notes that `key` was accepted.
+    pub fn succeeded(&self, key: &str) {
+        self.update(key, |pooled| {
+            pooled.limited_until = None;
+            pooled.rejected_at = None;
+        });
+    }
+
+    fn update(&self, key: &str, change: impl FnOnce(&mut PooledKey)) {
+        let mut pool = self.keys.lock().unwrap();
+        if let Some(pooled) = pool.keys.iter_mut().find(|pooled| pooled.key == key) {
+            change(pooled);
+        }
+    }
+
+    /// Whether a call could use another key than `key` right now, in which
+    /// case a `429` with `key` should come back to [`send`](Self::send) rather
+    /// than be retried.
+    pub fn has_other_usable(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let pool = self.keys.lock().unwrap();
+        pool.keys
+            .iter()
+            .any(|pooled| pooled.key != key && pooled.is_usable(now))
+    }
+
+    /// Replaces the keys, keeping what is known of those that stay.
+    ///
+    /// Fails without a key, or with an empty one.
+    pub fn rotate(&self, keys: Vec<String>) -> Result<(), String> {
+        let keys = validated(self.provider, keys)?;
+        let mut pool = self.keys.lock().unwrap();
+        let mut old = std::mem::take(&mut pool.keys);
+        pool.keys = keys
+            .into_iter()
+            .map(
+                |key| match old.iter().position(|pooled| pooled.key == key) {
+                    Some(i) => old.swap_remove(i),
+                    None => PooledKey::new(key),
+                },
+            )
+            .collect();
+        pool.next = 0;
+        tracing::info!(
+            "Rotated {} API keys: {} now",
+            self.provider,
+            pool.keys.len()
+        );
+        Ok(())
+    }
+
+    /// What is known of each key, in order.
+    pub fn status(&self) -> Vec<KeyStatus> {
+        let now = Instant::now();
+        let pool = self.keys.lock().unwrap();
+        pool.keys
+            .iter()
+            .map(|pooled| {
+                let resting = pooled
+                    .limited_until
+                    .filter(|until| *until > now)
+                    .map(|until| until - now);
+                KeyStatus {
+                    key: masked(&pooled.key),
+                    state: match (pooled.rejected_at, resting) {
+                        (Some(_), _) => KeyState::Rejected,
+                        (None, Some(_)) => KeyState::RateLimited,
+                        (None, None) => KeyState::Ok,
+                    },
+                    retry_after_secs: resting.map(|rest| rest.as_secs_f64().ceil() as u64),
+                    calls: pooled.calls,
+                }
+            })
+            .collect()
+    }
+
+    /// Sends a request with a key from the pool through `send`, once more
+    /// with another key each time one is rate-limited or rejected while
+    /// another is usable.
+    ///
+    /// `send` should hand `429`s back without retrying them while
+    /// [`has_other_usable`](Self::has_other_usable) says so, or the pool only
+    /// hears of them once the retries ran out.
+    pub async fn send<F, Fut>(&self, mut send: F) -> Result<reqwest::Response, ServerError>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<reqwest::Response, ServerError>>,
+    {
+        for _ in 1..self.len() {
+            let key = self.pick();
+            let response = send(key.clone()).await?;
+            if !self.record(&key, &response) || !self.has_other_usable(&key) {
+                return Ok(response);
+            }
+        }
+        let key = self.pick();
+        let response = send(key.clone()).await?;
+        self.record(&key, &response);
+        Ok(response)
+    }
+
+    /// Notes how a call with `key` was answered; whether the key failed.
+    fn record(&self, key: &str, response: &reqwest::Response) -> bool {
+        match response.status().as_u16() {
+            429 => self.rate_limited(key, super::retry_after(response)),
+            401 | 403 => self.rejected(key),
+            status => {
+                if (200..300).contains(&status) {
+                    self.succeeded(key);
+                }
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// `keys` trimmed, without duplicates; fails if none is left or one is empty.
+fn validated(provider: &str, keys: Vec<String>) -> Result<Vec<String>, String> {
+    let mut unique: Vec<String> = Vec::with_capacity(keys.len());
+    for key in keys {
+        let key = key.trim().to_string();
+        if key.is_empty() {
+            return Err(format!("{} API keys must not be empty", provider));
+        }
+        if !unique.contains(&key) {
+            unique.push(key);
+        }
+    }
+    if unique.is_empty() {
+        return Err(format!("{} needs at least one API key", provider));
+    }
+    Ok(unique)
+}
+
+/// The last four characters of `key`, for logs and status reports.
+pub fn masked(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("...{}", tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_limited_and_rejected_keys() {
+        let keys = vec!["key-a".to_string(), "key-b".into(), "key-c".into()];
+        let pool = KeyPool::new("groq", keys.clone(), KeyStrategy::RoundRobin).unwrap();
+        assert_eq!([pool.pick(), pool.pick(), pool.pick()], *keys);
+
+        pool.rate_limited("key-a", Some(Duration::from_secs(30)));
+        pool.rejected("key-b");
+        assert_eq!([pool.pick(), pool.pick()], ["key-c", "key-c"]);
+        pool.rejected("key-c");
+        // Nothing usable: the key back soonest
+        assert_eq!(pool.pick(), "key-a");
+
+        let status = pool.status();
+        assert_eq!(status[0].key, "...ey-a");
+        assert_eq!(status[0].state, KeyState::RateLimited);
+        assert_eq!(status[0].retry_after_secs, Some(30));
+        assert_eq!(status[1].state, KeyState::Rejected);
+
+        pool.rotate(vec!["key-a".into(), "key-d".into()]).unwrap();
+        let status = pool.status();
+        assert_eq!(status[0].state, KeyState::RateLimited);
+        assert_eq!((status[1].key.as_str(), status[1].calls), ("...ey-d", 0));
+        assert_eq!(pool.pick(), "key-d");
+        assert!(pool.rotate(vec![]).is_err());
+        assert!(pool.rotate(vec![" ".into()]).is_err());
+
+        let lrl = KeyPool::new("gemini", keys, KeyStrategy::LeastRecentlyLimited).unwrap();
+        lrl.rate_limited("key-a", Some(Duration::ZERO));
+        lrl.rate_limited("key-b", Some(Duration::ZERO));
+        assert_eq!(lrl.pick(), "key-c");
+        assert_eq!(lrl.pick(), "key-c");
+    }
+
+    #[test]
+    fn reads_each_key_once_from_env() {
+        std::env::set_var("DEDUP_TEST_API_KEY", "key-a");
+        std::env::set_var("DEDUP_TEST_API_KEYS", "key-b, key-a,,key-c,key-b");
+        let pool = KeyPool::from_env("test", "DEDUP_TEST", KeyStrategy::RoundRobin)
+            .unwrap()
+            .unwrap();
+        let keys: Vec<String> = pool.status().into_iter().map(|s| s.key).collect();
+        assert_eq!(keys, ["...ey-a", "...ey-b", "...ey-c"]);
+
+        assert!(
+            KeyPool::from_env("test", "DEDUP_NONE", KeyStrategy::RoundRobin)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
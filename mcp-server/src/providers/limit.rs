@@ -0,0 +1,225 @@
+//! Caps on simultaneous calls to each provider.
+//!
+//! Under load, unbounded fan-out to a provider runs straight into its rate
+//! limits (Groq's are per minute and per concurrent request), and every
+//! request then fails at once. A [`Limited`] provider lets at most
+//! `max_concurrent` calls run; further calls wait in line, in
+//! [priority](crate::scheduler) order, for up to `queue_timeout`. Calls that
+//! find `max_queued` already waiting, or that time out in line, fail with
+//! [`ServerError::Busy`], which the handlers answer with `-32006`, or the
+//! agent's fallbacks pick up.
+//!
+//! # Environment Variables
+//!
+//! * `PROVIDER_MAX_CONCURRENT` - Optional. Calls each provider runs at once
+//!   (default: unlimited)
+//! * `PROVIDER_MAX_QUEUED` - Optional. Calls that may wait for a slot; `0`
+//!   rejects calls as soon as the provider is full (default: 64)
+//! * `PROVIDER_QUEUE_TIMEOUT_MS` - Optional. Longest wait for a slot
+//!   (default: 30000)
+
+use super::{Completion, CompletionRequest, CompletionStream, Embeddings, LlmProvider};
+use crate::error::ServerError;
+use crate::scheduler::{current_priority, Permit, PriorityLimiter, PriorityWeights};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many calls run and wait for each provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitConfig {
+    /// Calls running at once.
+    pub max_concurrent: usize,
+    /// Calls waiting for a slot before further ones are rejected.
+    pub max_queued: usize,
+    /// Longest a call waits for a slot.
+    pub queue_timeout: Duration,
+    /// How waiting calls are served, by priority.
+    pub weights: PriorityWeights,
+}
+
+impl LimitConfig {
+    /// Reads the settings, or `None` if `PROVIDER_MAX_CONCURRENT` is unset or `0`.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok()?.trim().parse::<u64>().ok();
+        let max_concurrent = var("PROVIDER_MAX_CONCURRENT").filter(|n| *n > 0)?;
+        Some(Self {
+            max_concurrent: max_concurrent as usize,
+            max_queued: var("PROVIDER_MAX_QUEUED").unwrap_or(64) as usize,
+            // Shared with the per-host connection cap
+            weights: std::env::var("PROVIDER_HTTP_PRIORITY_WEIGHTS")
+                .ok()
+                .and_then(|value| PriorityWeights::parse(&value).ok())
+                .unwrap_or_default(),
+            queue_timeout: Duration::from_millis(
+                var("PROVIDER_QUEUE_TIMEOUT_MS").unwrap_or(30_000),
+            ),
+        })
+    }
+}
+
+/// A provider whose calls go through a concurrency limit.
+pub struct Limited {
+    inner: Arc<dyn LlmProvider>,
+    config: LimitConfig,
+    slots: Arc<PriorityLimiter>,
+}
+
+impl Limited {
+    /// Wraps `inner`, with every slot free.
+    pub fn new(inner: Arc<dyn LlmProvider>, config: LimitConfig) -> Self {
+        Self {
+            inner,
+            config,
+            slots: Arc::new(PriorityLimiter::new(config.max_concurrent, config.weights)),
+        }
+    }
+
+    /// Waits for a slot, or fails with the busy error.
+    async fn slot(&self) -> Result<Permit, ServerError> {
+        let busy = || {
+            tracing::warn!("{} is at its concurrency limit", self.inner.name());
+            ServerError::Busy(format!(
+                "{} is at its concurrency limit; try again shortly",
+                self.inner.name()
+            ))
+        };
+        if let Some(slot) = self.slots.try_acquire() {
+            return Ok(slot);
+        }
+        if self.slots.waiting() >= self.config.max_queued {
+            return Err(busy());
+        }
+        let acquire = self.slots.clone().acquire(current_priority());
+        tokio::time::timeout(self.config.queue_timeout, acquire)
+            .await
+            .map_err(|_| busy())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Limited {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn known_models(&self) -> Option<Vec<String>> {
+        self.inner.known_models()
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        self.inner.default_model()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+
+    fn supports_images(&self) -> bool {
+        self.inner.supports_images()
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion, ServerError> {
+        let _slot = self.slot().await?;
+        self.inner.complete(request).await
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream, ServerError> {
+        use futures_util::StreamExt;
+
+        let slot = self.slot().await?;
+        let stream = self.inner.stream(request).await?;
+        // The slot is held until the reply has been read
+        Ok(stream
+            .inspect(move |_| {
+                let _ = &slot;
+            })
+            .boxed())
+    }
+
+    async fn count_tokens(&self, request: &CompletionRequest) -> Result<u32, ServerError> {
+        self.inner.count_tokens(request).await
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<(), ServerError> {
+        self.inner.probe(timeout).await
+    }
+
+    fn supports_embeddings(&self) -> bool {
+        self.inner.supports_embeddings()
+    }
+
+    async fn embed(
+        &self,
+        texts: &[String],
+        model: Option<&str>,
+    ) -> Result<Embeddings, ServerError> {
+        let _slot = self.slot().await?;
+        self.inner.embed(texts, model).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for a real backend; never called.
+    struct Stub;
+
+    #[async_trait]
+    impl LlmProvider for Stub {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        fn known_models(&self) -> Option<Vec<String>> {
+            None
+        }
+
+        fn default_model(&self) -> Option<&str> {
+            None
+        }
+
+        fn supports_tools(&self) -> bool {
+            false
+        }
+
+        async fn complete(&self, _: CompletionRequest) -> Result<Completion, ServerError> {
+            unreachable!()
+        }
+
+        async fn stream(&self, _: CompletionRequest) -> Result<CompletionStream, ServerError> {
+            unreachable!()
+        }
+
+        async fn count_tokens(&self, _: &CompletionRequest) -> Result<u32, ServerError> {
+            Ok(0)
+        }
+
+        async fn probe(&self, _: Duration) -> Result<(), ServerError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn queues_calls_past_the_limit_and_rejects_the_rest() {
+        let provider = Limited::new(
+            Arc::new(Stub),
+            LimitConfig {
+                max_concurrent: 1,
+                max_queued: 1,
+                queue_timeout: Duration::from_secs(5),
+                weights: PriorityWeights::default(),
+            },
+        );
+        let running = provider.slot().await.unwrap();
+        let queued = provider.slot();
+        tokio::pin!(queued);
+        assert!(futures_util::poll!(queued.as_mut()).is_pending());
+
+        let rejected = provider.slot().await.err().unwrap();
+        assert!(matches!(rejected, ServerError::Busy(_)), "{}", rejected);
+        drop(running);
+        assert!(queued.await.is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+//! Mock backend, answering without a network or an API key.
+//!
+//! For integration tests, CI and local demos: it echoes the user text, or
+//! gives the same canned reply to everything, after an optional delay, and
+//! fails a share of calls on purpose to exercise retries, fallbacks and
+//! circuit breakers. It accepts any model name and reports token counts
+//! estimated like [`estimate_tokens`].
+//!
+//! # Environment Variables
+//!
+//! * `MOCK_PROVIDER` - Optional. `true` registers the backend as `mock`,
+//!   after any real ones; pick it with `LLM_PROVIDER=mock`
+//! * `MOCK_REPLY` - Optional. Reply to every request (default: echo the
+//!   user text)
+//! * `MOCK_LATENCY_MS` - Optional. Delay before each reply (default: 0)
+//! * `MOCK_FAILURE_RATE` - Optional. Share of calls failing, from 0 to 1
+//!   (default: 0)
+
+use super::{
+    estimate_tokens, Completion, CompletionRequest, CompletionStream, FinishReason, LlmProvider,
+};
+use crate::error::ServerError;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use std::time::Duration;
+
+/// Answers from memory instead of a model.
+#[derive(Debug, Clone, Default)]
+pub struct MockProvider {
+    reply: Option<String>,
+    latency: Duration,
+    failure_rate: f64,
+}
+
+impl MockProvider {
+    /// Creates a backend echoing the user text, at once and without failing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replies with `reply` whatever is asked.
+    pub fn with_reply(mut self, reply: impl Into<String>) -> Self {
+        self.reply = Some(reply.into());
+        self
+    }
+
+    /// Waits `latency` before each reply.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Fails `rate` of the calls, from 0 (none) to 1 (all).
+    pub fn with_failure_rate(mut self, rate: f64) -> Self {
+        self.failure_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Reads the configuration from the environment.
+    ///
+    /// Returns `Ok(None)` unless `MOCK_PROVIDER` is `true`, and fails if
+    /// the latency or failure rate is invalid.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let enabled = var("MOCK_PROVIDER").map(|v| v.trim().to_ascii_lowercase());
+        if !matches!(enabled.as_deref(), Some("true" | "1" | "on")) {
+            return Ok(None);
+        }
+        let mut provider = Self::new();
+        if let Some(reply) = var("MOCK_REPLY") {
+            provider = provider.with_reply(reply);
+        }
+        if let Some(ms) = var("MOCK_LATENCY_MS") {
+            let ms: u64 = ms
+                .trim()
+                .parse()
+                .map_err(|_| "MOCK_LATENCY_MS must be a number of milliseconds")?;
+            provider = provider.with_latency(Duration::from_millis(ms));
+        }
+        if let Some(rate) = var("MOCK_FAILURE_RATE") {
+            let rate: f64 = rate
+                .trim()
+                .parse()
+                .ok()
+                .filter(|rate| (0.0..=1.0).contains(rate))
+                .ok_or("MOCK_FAILURE_RATE must be a number from 0 to 1")?;
+            provider = provider.with_failure_rate(rate);
+        }
+        Ok(Some(provider))
+    }
+
+    /// The reply to `request`.
+    fn reply(&self, request: &CompletionRequest) -> String {
+        match &self.reply {
+            Some(reply) => reply.clone(),
+            None => format!("Echo from {}: {}", request.agent.name, request.user_text),
+        }
+    }
+
+    /// Waits out the latency, then fails the call if its turn has come.
+    ///
+    /// A request timeout shorter than the latency times the call out.
+    async fn answer(&self, timeout: Option<Duration>) -> Result<(), ServerError> {
+        match timeout {
+            Some(timeout) if timeout < self.latency => {
+                tokio::time::sleep(timeout).await;
+                return Err(ServerError::Timeout(
+                    "Mock API request timed out".to_string(),
+                ));
+            }
+            _ => tokio::time::sleep(self.latency).await,
+        }
+        let roll = (uuid::Uuid::new_v4().as_u128() % 10_000) as f64 / 10_000.0;
+        if roll < self.failure_rate {
+            return Err(ServerError::provider("Mock API failure injected"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn known_models(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion, ServerError> {
+        self.answer(request.timeout).await?;
+        let text = self.reply(&request);
+        let prompt_tokens = estimate_tokens(&request);
+        let reply_tokens = text.chars().count().div_ceil(4) as u32;
+        Ok(Completion {
+            text: Some(text),
+            tokens_used: Some(prompt_tokens + reply_tokens),
+            prompt_tokens: Some(prompt_tokens),
+            tool_calls: Vec::new(),
+            finish_reason: Some(FinishReason::Stop),
+            confidence: None,
+        })
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream, ServerError> {
+        self.answer(request.timeout).await?;
+        // One delta per word, keeping the spaces
+        let deltas: Vec<_> = self
+            .reply(&request)
+            .split_inclusive(' ')
+            .map(|word| Ok(word.to_string()))
+            .collect();
+        Ok(futures_util::stream::iter(deltas).boxed())
+    }
+
+    async fn count_tokens(&self, request: &CompletionRequest) -> Result<u32, ServerError> {
+        Ok(estimate_tokens(request))
+    }
+
+    async fn probe(&self, _timeout: Duration) -> Result<(), ServerError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::builtin_agents;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn echoes_replies_and_injects_failures() {
+        let request = CompletionRequest {
+            agent: Arc::new(builtin_agents().remove(1)),
+            model: "any-model".into(),
+            user_text: "What is gas?".into(),
+            conversation_history: None,
+            generation: Default::default(),
+            tools: Vec::new(),
+            timeout: None,
+            images: Vec::new(),
+        };
+        let echo = MockProvider::new();
+        let completion = echo.complete(request.clone()).await.unwrap();
+        assert_eq!(
+            completion.text.as_deref(),
+            Some("Echo from Web3 Expert: What is gas?")
+        );
+        assert!(completion.tokens_used > completion.prompt_tokens);
+        let streamed: Vec<_> = echo.stream(request.clone()).await.unwrap().collect().await;
+        let streamed: String = streamed.into_iter().map(Result::unwrap).collect();
+        assert_eq!(streamed, "Echo from Web3 Expert: What is gas?");
+
+        let canned = MockProvider::new().with_reply("gm");
+        let completion = canned.complete(request.clone()).await.unwrap();
+        assert_eq!(completion.text.as_deref(), Some("gm"));
+
+        let failing = MockProvider::new().with_failure_rate(1.0);
+        let error = failing.complete(request.clone()).await.unwrap_err();
+        assert!(error.is_provider_fault());
+
+        let slow = MockProvider::new().with_latency(Duration::from_secs(60));
+        let request = CompletionRequest {
+            timeout: Some(Duration::from_millis(10)),
+            ..request
+        };
+        assert!(matches!(
+            slow.complete(request).await,
+            Err(ServerError::Timeout(_))
+        ));
+    }
+}
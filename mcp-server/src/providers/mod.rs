@@ -0,0 +1,873 @@
+//! LLM provider backends.
+//!
+//! Every backend implements [`LlmProvider`], and the server keeps the ones it
+//! has credentials for in a [`ProviderRegistry`]. Handlers only talk to the
+//! trait, so adding a backend means adding a module here and registering it in
+//! [`ProviderRegistry::from_env`].
+//!
+//! # Environment Variables
+//!
+//! * `GROQ_API_KEY` - Enables the Groq backend
+//! * `GEMINI_API_KEY` - Enables the Google Gemini backend
+//! * `GROQ_API_KEYS`, `GEMINI_API_KEYS` - Optional. Comma-separated keys
+//!   that enable the backend too, or add to its key; see [`keys`] for how
+//!   they are used
+//! * `AZURE_OPENAI_API_KEY` - Enables the Azure OpenAI backend (see [`azure`]
+//!   for its other settings)
+//! * `MOCK_PROVIDER` - Enables the [`mock`] backend, which needs no key
+//! * `LLM_PROVIDER` - Optional. Name of the default backend (default: the
+//!   first configured of `groq`, `gemini`, `azure` and `mock`)
+//! * `GROQ_TIMEOUT_MS`, `GEMINI_TIMEOUT_MS` - Optional. Timeout of each call
+//!   to that backend, retries and key rotation included, overriding
+//!   `PROVIDER_HTTP_TIMEOUT_MS` (Azure's is `AZURE_OPENAI_TIMEOUT_MS`)
+//!
+//! Each registered backend sits behind a circuit breaker and, optionally, a
+//! cap on simultaneous calls; see [`breaker`] and [`limit`] for their
+//! settings.
+
+pub mod azure;
+pub mod breaker;
+pub mod gemini;
+pub mod groq;
+pub mod keys;
+pub mod limit;
+pub mod mock;
+
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use crate::images::Image;
+use crate::models::{Agent, FunctionTool, GenerationParams, Message, ToolCall};
+use crate::scheduler::Permit;
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use azure::AzureOpenAiProvider;
+pub use breaker::{BreakerConfig, Guarded};
+pub use gemini::GeminiProvider;
+pub use groq::GroqProvider;
+pub use keys::{KeyPool, KeyStrategy};
+pub use limit::{LimitConfig, Limited};
+pub use mock::MockProvider;
+
+/// Everything a provider needs to produce an agent's reply.
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    /// The agent answering, whose system prompt is used
+    pub agent: Arc<Agent>,
+    /// Model to call, as picked by [`resolve_model`]
+    pub model: String,
+    /// The user's input text
+    pub user_text: String,
+    /// Previous messages in the conversation, oldest first
+    pub conversation_history: Option<Vec<Message>>,
+    /// Sampling settings, already validated
+    pub generation: GenerationParams,
+    /// Functions the model may call, already validated
+    pub tools: Vec<FunctionTool>,
+    /// Time the whole call may take, retries included, overriding the
+    /// provider's
+    pub timeout: Option<Duration>,
+    /// Images sent with the user text, only to providers that
+    /// [accept them](LlmProvider::supports_images)
+    pub images: Vec<Image>,
+}
+
+/// A provider's reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    /// The reply text, or `None` if the provider returned none
+    pub text: Option<String>,
+    /// Total tokens billed for the call, if reported
+    pub tokens_used: Option<u32>,
+    /// Prompt tokens among them, if reported
+    pub prompt_tokens: Option<u32>,
+    /// Function calls the model requested
+    pub tool_calls: Vec<ToolCall>,
+    /// Why generation stopped, if reported
+    pub finish_reason: Option<FinishReason>,
+    /// How far the reply can be trusted, see [`confidence`]
+    pub confidence: Option<f64>,
+}
+
+/// Why a provider stopped generating, in the same terms for every backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished its reply
+    Stop,
+    /// The reply was cut off at `max_tokens`
+    Length,
+    /// Safety or content filters stopped the reply
+    ContentFilter,
+    /// The model stopped to call functions
+    ToolCalls,
+    /// Any other reason
+    Other,
+}
+
+impl FinishReason {
+    /// Maps an OpenAI-style `finish_reason` or a Gemini `finishReason`.
+    pub fn parse(reason: &str) -> Self {
+        match reason {
+            "stop" | "STOP" => FinishReason::Stop,
+            "length" | "MAX_TOKENS" => FinishReason::Length,
+            "content_filter" | "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT"
+            | "SPII" => FinishReason::ContentFilter,
+            "tool_calls" | "function_call" => FinishReason::ToolCalls,
+            _ => FinishReason::Other,
+        }
+    }
+}
+
+/// Scores a reply from 0 to 1 from the signals its provider reported.
+///
+/// The base is the mean probability of the reply's tokens, the exponential of
+/// their average log probability, so there is no score without logprobs.
+/// Replies cut off at the token limit, stopped by content filters, or rated
+/// risky by safety filters (`flagged`) get half of it each.
+///
+/// ```
+/// use mcp_server::providers::{confidence, FinishReason};
+///
+/// assert_eq!(confidence(Some(-0.1), Some(FinishReason::Stop), false), Some(0.905));
+/// assert_eq!(confidence(Some(-0.1), Some(FinishReason::Length), false), Some(0.452));
+/// assert_eq!(confidence(None, Some(FinishReason::Stop), false), None);
+/// ```
+pub fn confidence(
+    avg_logprob: Option<f64>,
+    finish_reason: Option<FinishReason>,
+    flagged: bool,
+) -> Option<f64> {
+    let mut score = avg_logprob?.min(0.0).exp();
+    if matches!(
+        finish_reason,
+        Some(FinishReason::Length | FinishReason::ContentFilter)
+    ) {
+        score /= 2.0;
+    }
+    if flagged {
+        score /= 2.0;
+    }
+    Some((score * 1000.0).round() / 1000.0)
+}
+
+/// Vectors computed by [`LlmProvider::embed`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embeddings {
+    /// One vector per input text, in input order
+    pub vectors: Vec<Vec<f32>>,
+    /// Embedding model that computed them
+    pub model: String,
+    /// Tokens billed for the call, if reported
+    pub tokens_used: Option<u32>,
+}
+
+/// Incremental reply text, ending at the first error.
+pub type CompletionStream = BoxStream<'static, Result<String, ServerError>>;
+
+/// An AI backend that can answer as an agent.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short, stable name, e.g. `groq`.
+    fn name(&self) -> &'static str;
+
+    /// Models this backend serves, or `None` if it accepts any name.
+    fn known_models(&self) -> Option<Vec<String>>;
+
+    /// Model used for agents configured with one this backend doesn't serve.
+    fn default_model(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether this backend forwards [`CompletionRequest::tools`] and returns
+    /// the model's tool calls.
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// Whether this backend sends [`CompletionRequest::images`] to the model.
+    fn supports_images(&self) -> bool {
+        false
+    }
+
+    /// Generates the complete reply.
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion, ServerError>;
+
+    /// Generates the reply as a stream of text deltas.
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream, ServerError>;
+
+    /// Counts the prompt tokens `request` would use.
+    ///
+    /// Backends without a counting endpoint return [`estimate_tokens`].
+    async fn count_tokens(&self, request: &CompletionRequest) -> Result<u32, ServerError>;
+
+    /// Checks that the backend is reachable and accepts our credentials with
+    /// a cheap call, such as listing models, that gives up after `timeout`.
+    async fn probe(&self, timeout: Duration) -> Result<(), ServerError>;
+
+    /// Whether this backend computes [embeddings](Self::embed).
+    fn supports_embeddings(&self) -> bool {
+        false
+    }
+
+    /// Embeds each of `texts` with `model`, or the backend's default
+    /// embedding model.
+    async fn embed(
+        &self,
+        texts: &[String],
+        model: Option<&str>,
+    ) -> Result<Embeddings, ServerError> {
+        let _ = (texts, model);
+        Err(ServerError::InvalidParams(format!(
+            "provider {} does not compute embeddings",
+            self.name()
+        )))
+    }
+}
+
+/// Picks the model `provider` should call for an agent configured with
+/// `configured`, honoring a per-request `requested` override.
+///
+/// An override must be one of the provider's [known
+/// models](LlmProvider::known_models). A configured model the provider doesn't
+/// serve, typically one meant for another backend, is replaced by its
+/// [default model](LlmProvider::default_model) when it has one.
+pub fn resolve_model(
+    provider: &dyn LlmProvider,
+    configured: &str,
+    requested: Option<&str>,
+) -> Result<String, String> {
+    let known = provider.known_models();
+    let serves = |model: &str| known.as_ref().is_none_or(|k| k.iter().any(|m| m == model));
+    match requested {
+        Some(model) if serves(model) => Ok(model.to_string()),
+        Some(model) => Err(format!(
+            "model {} is not available on {}; known models: {}",
+            model,
+            provider.name(),
+            known.unwrap_or_default().join(", ")
+        )),
+        None if serves(configured) => Ok(configured.to_string()),
+        None => Ok(provider.default_model().unwrap_or(configured).to_string()),
+    }
+}
+
+/// Roughly estimates prompt tokens at four characters per token.
+pub fn estimate_tokens(request: &CompletionRequest) -> u32 {
+    let history = request.conversation_history.iter().flatten();
+    let chars = request.agent.system_prompt.chars().count()
+        + request.user_text.chars().count()
+        + history.map(|m| m.content.chars().count()).sum::<usize>();
+    chars.div_ceil(4) as u32
+}
+
+/// Reads a timeout in milliseconds from the environment variable `var`;
+/// unset, empty or `0` leaves the client's timeout in place.
+fn timeout_from_env(var: &str) -> Result<Option<Duration>, String> {
+    match std::env::var(var).ok().filter(|v| !v.is_empty()) {
+        None => Ok(None),
+        Some(value) => match value.trim().parse::<u64>() {
+            Ok(0) => Ok(None),
+            Ok(ms) => Ok(Some(Duration::from_millis(ms))),
+            Err(_) => Err(format!("{} must be a number of milliseconds", var)),
+        },
+    }
+}
+
+/// Describes a failed request to `backend` as `context: error`, or as a
+/// [`ServerError::Timeout`] for timeouts.
+fn request_error(backend: &str, context: &str, error: reqwest::Error) -> ServerError {
+    if error.is_timeout() {
+        ServerError::Timeout(format!("{} API request timed out", backend))
+    } else {
+        ServerError::provider(format!("{}: {}", context, error))
+    }
+}
+
+/// Runs `call` to `backend`, failing with [`ServerError::Timeout`] unless it
+/// is done within `timeout`, however many attempts it makes.
+async fn within<T>(
+    backend: &str,
+    timeout: Option<Duration>,
+    call: impl Future<Output = Result<T, ServerError>>,
+) -> Result<T, ServerError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, call)
+            .await
+            .unwrap_or_else(|_| {
+                Err(ServerError::Timeout(format!(
+                    "{} API request timed out",
+                    backend
+                )))
+            }),
+        None => call.await,
+    }
+}
+
+/// Sends a [probe](LlmProvider::probe) request to `backend` without retrying,
+/// failing on an error status.
+async fn probe_request(
+    backend: &str,
+    request: reqwest::RequestBuilder,
+    timeout: Duration,
+) -> Result<(), ServerError> {
+    let response = request
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| request_error(backend, &format!("{} API unreachable", backend), e))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(ServerError::status(backend, status, ""))
+    }
+}
+
+/// How long a `429` response asks callers to wait.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    crate::http_client::retry_after(response.headers(), std::time::SystemTime::now())
+}
+
+/// The configured backends. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct ProviderRegistry {
+    /// Backends in registration order; the first is the default unless
+    /// [`set_default`](Self::set_default) picks another.
+    providers: Vec<Arc<dyn LlmProvider>>,
+    default: usize,
+    /// Breaker settings for backends registered from now on; `None` registers
+    /// them unguarded.
+    breaker: Option<BreakerConfig>,
+    /// Concurrency limit of backends registered from now on; `None` leaves
+    /// them unlimited.
+    limit: Option<LimitConfig>,
+    /// API keys of the backends that pool them, for rotation.
+    key_pools: Vec<Arc<KeyPool>>,
+}
+
+impl ProviderRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts backends registered from now on behind circuit breakers.
+    pub fn with_breakers(mut self, config: Option<BreakerConfig>) -> Self {
+        self.breaker = config;
+        self
+    }
+
+    /// Caps simultaneous calls to each backend registered from now on.
+    pub fn with_limits(mut self, config: Option<LimitConfig>) -> Self {
+        self.limit = config;
+        self
+    }
+
+    /// Registers every backend that has credentials in the environment.
+    ///
+    /// Fails if none does, if a backend's settings are invalid, or if
+    /// `LLM_PROVIDER` names one that is not configured.
+    pub fn from_env(client: &HttpClient) -> Result<Self, String> {
+        let key = |name: &str| std::env::var(name).ok().filter(|k| !k.is_empty());
+        let mut registry = Self::new()
+            .with_breakers(BreakerConfig::from_env())
+            .with_limits(LimitConfig::from_env());
+        let strategy = KeyStrategy::from_env()?;
+        if let Some(keys) = KeyPool::from_env("groq", "GROQ", strategy)? {
+            let keys = registry.add_key_pool(keys);
+            let mut groq = GroqProvider::with_keys(client.clone(), keys);
+            if let Some(timeout) = timeout_from_env("GROQ_TIMEOUT_MS")? {
+                groq = groq.with_timeout(timeout);
+            }
+            registry.register(Arc::new(groq));
+        }
+        if let Some(keys) = KeyPool::from_env("gemini", "GEMINI", strategy)? {
+            let keys = registry.add_key_pool(keys);
+            let mut gemini = GeminiProvider::with_keys(client.clone(), keys);
+            if let Some(timeout) = timeout_from_env("GEMINI_TIMEOUT_MS")? {
+                gemini = gemini.with_timeout(timeout);
+            }
+            registry.register(Arc::new(gemini));
+        }
+        if let Some(azure) = AzureOpenAiProvider::from_env(client)? {
+            registry.register(Arc::new(azure));
+        }
+        if let Some(mock) = MockProvider::from_env()? {
+            registry.register(Arc::new(mock));
+        }
+        if registry.is_empty() {
+            return Err(
+                "One of GROQ_API_KEY, GEMINI_API_KEY or AZURE_OPENAI_API_KEY must be set in .env file, \
+                 or MOCK_PROVIDER=true"
+                    .into(),
+            );
+        }
+        if let Some(name) = key("LLM_PROVIDER") {
+            registry.set_default(&name)?;
+        }
+        Ok(registry)
+    }
+
+    /// Adds a backend, replacing any registered under the same name.
+    pub fn register(&mut self, provider: Arc<dyn LlmProvider>) {
+        let provider: Arc<dyn LlmProvider> = match self.breaker {
+            Some(config) => Arc::new(Guarded::new(provider, config)),
+            None => provider,
+        };
+        // Calls wait for a slot before the breaker sees them, so rejections
+        // for being busy don't count as failures
+        let provider: Arc<dyn LlmProvider> = match self.limit {
+            Some(config) => Arc::new(Limited::new(provider, config)),
+            None => provider,
+        };
+        match self
+            .providers
+            .iter()
+            .position(|p| p.name() == provider.name())
+        {
+            Some(i) => self.providers[i] = provider,
+            None => self.providers.push(provider),
+        }
+    }
+
+    /// Keeps `keys` for [`key_pool`](Self::key_pool), replacing the pool of
+    /// the same backend.
+    pub fn add_key_pool(&mut self, keys: KeyPool) -> Arc<KeyPool> {
+        let keys = Arc::new(keys);
+        self.key_pools
+            .retain(|pool| pool.provider() != keys.provider());
+        self.key_pools.push(keys.clone());
+        keys
+    }
+
+    /// The API keys of backend `name`, if it pools them.
+    pub fn key_pool(&self, name: &str) -> Option<Arc<KeyPool>> {
+        self.key_pools
+            .iter()
+            .find(|pool| pool.provider() == name)
+            .cloned()
+    }
+
+    /// Makes `name` the default backend.
+    pub fn set_default(&mut self, name: &str) -> Result<(), String> {
+        self.default = self
+            .providers
+            .iter()
+            .position(|p| p.name() == name)
+            .ok_or_else(|| format!("LLM provider {} is not configured", name))?;
+        Ok(())
+    }
+
+    /// Looks up a backend by name.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn LlmProvider>> {
+        self.providers.iter().find(|p| p.name() == name).cloned()
+    }
+
+    /// The backend used when a request doesn't pick one.
+    pub fn default_provider(&self) -> Option<Arc<dyn LlmProvider>> {
+        self.providers.get(self.default).cloned()
+    }
+
+    /// The backend computing embeddings when a request doesn't pick one: the
+    /// default backend if it [can](LlmProvider::supports_embeddings), else the
+    /// first registered one that can.
+    pub fn embedding_provider(&self) -> Option<Arc<dyn LlmProvider>> {
+        self.default_provider()
+            .into_iter()
+            .chain(self.providers.iter().cloned())
+            .find(|p| p.supports_embeddings())
+    }
+
+    /// The configured backends of `agent`'s [fallbacks](Agent::fallbacks),
+    /// in order, with the model each should call.
+    ///
+    /// Fallbacks naming a backend that isn't registered, or a model it doesn't
+    /// serve, are skipped.
+    pub fn fallbacks(&self, agent: &Agent) -> Vec<(Arc<dyn LlmProvider>, String)> {
+        agent
+            .fallbacks
+            .iter()
+            .filter_map(|fallback| {
+                let Some(provider) = self.get(&fallback.provider) else {
+                    tracing::debug!("Skipping unconfigured fallback {}", fallback.provider);
+                    return None;
+                };
+                match resolve_model(provider.as_ref(), &agent.model, fallback.model.as_deref()) {
+                    Ok(model) => Some((provider, model)),
+                    Err(e) => {
+                        tracing::warn!("Skipping fallback for agent {}: {}", agent.id, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Names of all backends, the default first.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.providers.iter().map(|p| p.name()).collect();
+        if !names.is_empty() {
+            names[..=self.default].rotate_right(1);
+        }
+        names
+    }
+
+    /// Whether no backend is registered.
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+}
+
+/// Splits a `text/event-stream` body into the payloads of its `data:` lines.
+///
+/// Chunks may end mid-line; the remainder is kept until the next chunk.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: String,
+}
+
+impl SseDecoder {
+    /// Feeds a chunk of the body, returning the data payloads it completed.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        let mut payloads = vec![];
+        while let Some(end) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=end).collect();
+            let line = line.trim_end_matches(['\r', '\n']);
+            if let Some(data) = line.strip_prefix("data:") {
+                payloads.push(data.trim_start().to_string());
+            }
+        }
+        payloads
+    }
+}
+
+/// Turns an SSE response into a stream of text deltas.
+///
+/// `parse` maps each `data:` payload to its text, `Ok(None)` for payloads
+/// without any, such as the final `[DONE]` marker. The per-host `permit`, if
+/// any, is held until the stream is dropped.
+fn sse_text_stream(
+    response: reqwest::Response,
+    permit: Option<Permit>,
+    parse: fn(&str) -> Result<Option<String>, ServerError>,
+) -> CompletionStream {
+    use futures_util::StreamExt;
+
+    let mut decoder = SseDecoder::default();
+    response
+        .bytes_stream()
+        .map(move |chunk| match chunk {
+            Ok(chunk) => {
+                let _permit = &permit;
+                decoder
+                    .push(&chunk)
+                    .iter()
+                    .map(|data| parse(data))
+                    .collect()
+            }
+            Err(e) => vec![Err(ServerError::provider(format!(
+                "Stream interrupted: {}",
+                e
+            )))],
+        })
+        .flat_map(futures_util::stream::iter)
+        .filter_map(|item| async move { item.transpose() })
+        .scan(false, |failed, item| {
+            // Stop after the first error
+            let next = (!*failed).then_some(item);
+            *failed = next.as_ref().is_some_and(|item| item.is_err());
+            async move { next }
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use std::path::Path;
+
+    /// Runs every recorded provider response under `testdata/providers` through
+    /// its parser and compares the outcome with the checked-in `.golden` file.
+    ///
+    /// Set `UPDATE_GOLDEN=1` to rewrite the golden files after an intentional change.
+    #[test]
+    fn golden_provider_responses() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/providers");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut fixtures: Vec<_> = std::fs::read_dir(&dir)
+            .expect("fixture directory exists")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        fixtures.sort();
+        assert!(
+            !fixtures.is_empty(),
+            "no fixtures found in {}",
+            dir.display()
+        );
+
+        let mut mismatches = vec![];
+        for fixture in &fixtures {
+            let name = fixture.file_stem().unwrap().to_str().unwrap();
+            let recorded: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(fixture).unwrap()).unwrap();
+            let status = StatusCode::from_u16(recorded["status"].as_u64().unwrap() as u16).unwrap();
+            // String bodies are passed through verbatim to simulate non-JSON responses
+            let body = match &recorded["body"] {
+                serde_json::Value::String(raw) => raw.clone(),
+                json => json.to_string(),
+            };
+
+            let parsed = if name.starts_with("gemini_") {
+                gemini::parse_gemini_response(status, &body)
+            } else if name.starts_with("groq_") {
+                groq::parse_groq_response(status, &body)
+            } else {
+                panic!("fixture {} must start with gemini_ or groq_", name);
+            };
+            let actual = match parsed {
+                Ok(completion) => {
+                    let mut ok = serde_json::json!({
+                        "reply_text": completion.text,
+                        "tokens_used": completion.tokens_used,
+                    });
+                    if !completion.tool_calls.is_empty() {
+                        ok["tool_calls"] = serde_json::json!(completion.tool_calls);
+                    }
+                    if let Some(reason) = completion.finish_reason {
+                        ok["finish_reason"] = serde_json::json!(reason);
+                    }
+                    if let Some(confidence) = completion.confidence {
+                        ok["confidence"] = serde_json::json!(confidence);
+                    }
+                    serde_json::json!({ "ok": ok })
+                }
+                Err(err) => serde_json::json!({ "err": err.to_string() }),
+            };
+            let actual = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+
+            let golden_path = fixture.with_extension("golden");
+            if update {
+                std::fs::write(&golden_path, &actual).unwrap();
+                continue;
+            }
+            match std::fs::read_to_string(&golden_path) {
+                Ok(expected) if expected == actual => {}
+                Ok(expected) => mismatches.push(format!(
+                    "{}:\n--- expected\n{}--- actual\n{}",
+                    name, expected, actual
+                )),
+                Err(_) => mismatches.push(format!(
+                    "{}: missing {} (run with UPDATE_GOLDEN=1)",
+                    name,
+                    golden_path.display()
+                )),
+            }
+        }
+
+        assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    }
+
+    #[test]
+    fn sse_decoder_handles_split_lines() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(b"data: {\"a\"").is_empty());
+        assert_eq!(
+            decoder.push(b":1}\r\n\r\n: keep-alive\ndata: [DONE]\n"),
+            ["{\"a\":1}", "[DONE]"]
+        );
+    }
+
+    #[test]
+    fn resolves_configured_and_requested_models() {
+        let groq = GroqProvider::new(reqwest::Client::new().into(), "k".into());
+        assert_eq!(
+            resolve_model(&groq, "llama-3.1-8b-instant", None).unwrap(),
+            "llama-3.1-8b-instant"
+        );
+        // A model meant for another backend falls back to the default
+        assert_eq!(
+            resolve_model(&groq, "gemini-2.0-flash", None).unwrap(),
+            "llama-3.3-70b-versatile"
+        );
+        assert_eq!(
+            resolve_model(&groq, "gemini-2.0-flash", Some("qwen/qwen3-32b")).unwrap(),
+            "qwen/qwen3-32b"
+        );
+        let err = resolve_model(&groq, "llama-3.3-70b-versatile", Some("gpt-5")).unwrap_err();
+        assert!(
+            err.starts_with("model gpt-5 is not available on groq"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn default_provider_is_listed_first() {
+        let client: HttpClient = reqwest::Client::new().into();
+        let mut registry = ProviderRegistry::new();
+        registry.register(Arc::new(GroqProvider::new(client.clone(), "k".into())));
+        registry.register(Arc::new(GeminiProvider::new(client, "k".into())));
+        assert_eq!(registry.names(), ["groq", "gemini"]);
+
+        registry.set_default("gemini").unwrap();
+        assert_eq!(registry.default_provider().unwrap().name(), "gemini");
+        assert_eq!(registry.names(), ["gemini", "groq"]);
+        assert!(registry.set_default("azure").is_err());
+    }
+
+    #[test]
+    fn picks_configured_fallbacks() {
+        let client: HttpClient = reqwest::Client::new().into();
+        let mut registry = ProviderRegistry::new();
+        registry.register(Arc::new(GroqProvider::new(client.clone(), "k".into())));
+        registry.register(Arc::new(GeminiProvider::new(client, "k".into())));
+
+        let mut agent = crate::agents::builtin_agents()[0].clone();
+        let fallback = |provider: &str, model: Option<&str>| crate::models::ProviderFallback {
+            provider: provider.to_string(),
+            model: model.map(str::to_string),
+        };
+        agent.fallbacks = vec![
+            fallback("ollama", None),
+            fallback("gemini", None),
+            fallback("groq", Some("gpt-5")),
+            fallback("groq", Some("llama-3.1-8b-instant")),
+        ];
+        let chain: Vec<_> = registry
+            .fallbacks(&agent)
+            .into_iter()
+            .map(|(provider, model)| (provider.name(), model))
+            .collect();
+        assert_eq!(
+            chain,
+            [
+                ("gemini", gemini::GEMINI_DEFAULT_MODEL.to_string()),
+                ("groq", "llama-3.1-8b-instant".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_timed_out_calls() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let groq = GroqProvider::new(reqwest::Client::new().into(), "k".into())
+            .with_api_url(url)
+            .with_timeout(Duration::from_secs(30));
+        let request = CompletionRequest {
+            agent: Arc::new(crate::agents::builtin_agents()[0].clone()),
+            model: groq::GROQ_DEFAULT_MODEL.to_string(),
+            user_text: "hi".to_string(),
+            conversation_history: None,
+            generation: GenerationParams::default(),
+            tools: Vec::new(),
+            timeout: Some(Duration::from_millis(50)),
+            images: Vec::new(),
+        };
+        let error = groq.complete(request).await.unwrap_err();
+        assert!(matches!(error, ServerError::Timeout(_)), "{}", error);
+        assert_eq!(error.code(), -32003);
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn timeouts_cover_every_retry() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Always busy, asking to be retried in 5 seconds
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let _ = socket.read(&mut [0; 4096]).await;
+                let _ = socket
+                    .write_all(
+                        b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 5\r\n\
+                          Content-Length: 0\r\n\r\n",
+                    )
+                    .await;
+            }
+        });
+        let client = crate::http_client::HttpClientConfig::default()
+            .build()
+            .unwrap();
+        let groq = GroqProvider::new(client, "k".into()).with_api_url(url);
+        let request = CompletionRequest {
+            agent: Arc::new(crate::agents::builtin_agents()[0].clone()),
+            model: groq::GROQ_DEFAULT_MODEL.to_string(),
+            user_text: "hi".to_string(),
+            conversation_history: None,
+            generation: GenerationParams::default(),
+            tools: Vec::new(),
+            timeout: Some(Duration::from_millis(300)),
+            images: Vec::new(),
+        };
+        let started = std::time::Instant::now();
+        let error = groq.complete(request).await.unwrap_err();
+        assert!(matches!(error, ServerError::Timeout(_)), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_keys_are_swapped_rather_than_retried() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // key-a is rate-limited, key-b answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let limited = Arc::new(AtomicUsize::new(0));
+        let counted = limited.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = vec![0; 8192];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                let response = if request.contains("bearer key-a") {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\n\
+                     Content-Length: 0\r\n\r\n"
+                        .to_string()
+                } else {
+                    let body =
+                        r#"{"choices":[{"message":{"content":"gm"},"finish_reason":"stop"}]}"#;
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        let client = crate::http_client::HttpClientConfig::default()
+            .build()
+            .unwrap();
+        let keys = vec!["key-a".to_string(), "key-b".to_string()];
+        let keys = KeyPool::new("groq", keys, keys::KeyStrategy::RoundRobin).unwrap();
+        let groq = GroqProvider::with_keys(client, Arc::new(keys)).with_api_url(url);
+        let request = CompletionRequest {
+            agent: Arc::new(crate::agents::builtin_agents()[0].clone()),
+            model: groq::GROQ_DEFAULT_MODEL.to_string(),
+            user_text: "hi".to_string(),
+            conversation_history: None,
+            generation: GenerationParams::default(),
+            tools: Vec::new(),
+            timeout: None,
+            images: Vec::new(),
+        };
+        let completion = groq.complete(request).await.unwrap();
+        assert_eq!(completion.text.as_deref(), Some("gm"));
+        assert_eq!(limited.load(Ordering::SeqCst), 1);
+    }
+}
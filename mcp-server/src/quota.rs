@@ -0,0 +1,316 @@
+//! Usage quotas per caller.
+//!
+//! Each authenticated caller is a quota key: its token's subject, prefixed
+//! with its tenant as `{tenant}/{subject}` when it has one. [`QuotaTracker`]
+//! counts the agent requests each key makes and the tokens they use, per UTC
+//! day and per UTC month, and turns away requests from keys that have reached
+//! a limit with error `-32007` before any provider is called. Replies served
+//! from the response cache count as requests but use no tokens.
+//!
+//! Limits are set in the `[quotas]` section of the config file and change on
+//! reload without resetting the counters:
+//!
+//! ```toml
+//! # Applies to every key without its own entry
+//! [quotas.default]
+//! daily_requests = 1000
+//! monthly_tokens = 2000000
+//!
+//! [quotas.keys."acme/user-1"]
+//! daily_tokens = 500000
+//! ```
+//!
+//! Without a section, or for a key whose entry sets no limits, usage is still
+//! counted but never refused. Counters live in memory, so each replica counts
+//! its own traffic and a restart resets them. A check and the request's
+//! accounting are not atomic, so concurrent requests may overshoot a limit
+//! by the requests already in flight.
+
+use crate::auth::Identity;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Limits of one key; unset limits don't apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaLimits {
+    pub daily_requests: Option<u64>,
+    pub daily_tokens: Option<u64>,
+    pub monthly_requests: Option<u64>,
+    pub monthly_tokens: Option<u64>,
+}
+
+/// `[quotas]` section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Limits of keys without an entry in `keys`
+    pub default: QuotaLimits,
+    /// Limits by quota key, replacing the defaults
+    pub keys: HashMap<String, QuotaLimits>,
+}
+
+impl QuotaConfig {
+    /// The limits that apply to `key`.
+    pub fn limits(&self, key: &str) -> QuotaLimits {
+        self.keys.get(key).copied().unwrap_or(self.default)
+    }
+}
+
+/// Why a request was refused, sent as the error's `data`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuotaExceeded {
+    /// The caller's quota key
+    pub key: String,
+    /// Name of the limit reached, e.g. `daily_tokens`
+    pub limit: &'static str,
+    /// Value of that limit
+    pub allowed: u64,
+    /// Usage counted against it so far
+    pub used: u64,
+    /// When the counter starts over, as an RFC 3339 timestamp
+    pub resets_at: String,
+}
+
+/// Requests and tokens counted for a key in the current day and month.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub daily_requests: u64,
+    pub daily_tokens: u64,
+    pub monthly_requests: u64,
+    pub monthly_tokens: u64,
+}
+
+#[derive(Debug)]
+struct Counter {
+    day: NaiveDate,
+    usage: Usage,
+}
+
+impl Counter {
+    /// Starts the day's and, on a new month, the month's counts over.
+    fn roll(&mut self, today: NaiveDate) {
+        if today == self.day {
+            return;
+        }
+        let monthly = (today.year(), today.month()) == (self.day.year(), self.day.month());
+        self.usage = Usage {
+            monthly_requests: if monthly {
+                self.usage.monthly_requests
+            } else {
+                0
+            },
+            monthly_tokens: if monthly {
+                self.usage.monthly_tokens
+            } else {
+                0
+            },
+            ..Usage::default()
+        };
+        self.day = today;
+    }
+}
+
+/// Counts usage per key and enforces the configured limits.
+///
+/// Cheap to clone; clones share the counters and limits.
+#[derive(Debug, Clone, Default)]
+pub struct QuotaTracker {
+    config: Arc<RwLock<QuotaConfig>>,
+    counters: Arc<Mutex<HashMap<String, Counter>>>,
+}
+
+impl QuotaTracker {
+    /// Creates a tracker enforcing `config`, with nothing counted yet.
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(config)),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Swaps in new limits, e.g. after a config reload, keeping the counts.
+    pub fn reconfigure(&self, config: QuotaConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Fails if `key` has reached any of its limits.
+    pub fn check(&self, key: &str) -> Result<(), QuotaExceeded> {
+        self.check_at(key, Utc::now())
+    }
+
+    /// Counts a request by `key` that used `tokens`.
+    pub fn record(&self, key: &str, tokens: u32) {
+        self.record_at(key, tokens, Utc::now())
+    }
+
+    /// What `key` has used today and this month.
+    pub fn usage(&self, key: &str) -> Usage {
+        let now = Utc::now();
+        let mut counters = self.counters.lock().unwrap();
+        counters
+            .get_mut(key)
+            .map_or_else(Usage::default, |counter| {
+                counter.roll(now.date_naive());
+                counter.usage
+            })
+    }
+
+    fn check_at(&self, key: &str, now: DateTime<Utc>) -> Result<(), QuotaExceeded> {
+        let limits = self.config.read().unwrap().limits(key);
+        if limits == QuotaLimits::default() {
+            return Ok(());
+        }
+        let usage = {
+            let mut counters = self.counters.lock().unwrap();
+            match counters.get_mut(key) {
+                Some(counter) => {
+                    counter.roll(now.date_naive());
+                    counter.usage
+                }
+                None => return Ok(()),
+            }
+        };
+
+        let today = now.date_naive();
+        let tomorrow = today.succ_opt().unwrap_or(today);
+        let next_month = today
+            .with_day(1)
+            .and_then(|first| first.checked_add_months(Months::new(1)))
+            .unwrap_or(tomorrow);
+        let checks = [
+            (
+                "daily_requests",
+                limits.daily_requests,
+                usage.daily_requests,
+                tomorrow,
+            ),
+            (
+                "daily_tokens",
+                limits.daily_tokens,
+                usage.daily_tokens,
+                tomorrow,
+            ),
+            (
+                "monthly_requests",
+                limits.monthly_requests,
+                usage.monthly_requests,
+                next_month,
+            ),
+            (
+                "monthly_tokens",
+                limits.monthly_tokens,
+                usage.monthly_tokens,
+                next_month,
+            ),
+        ];
+        for (limit, allowed, used, resets) in checks {
+            if let Some(allowed) = allowed.filter(|allowed| used >= *allowed) {
+                return Err(QuotaExceeded {
+                    key: key.to_string(),
+                    limit,
+                    allowed,
+                    used,
+                    resets_at: resets.and_hms_opt(0, 0, 0).unwrap().and_utc().to_rfc3339(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn record_at(&self, key: &str, tokens: u32, now: DateTime<Utc>) {
+        let today = now.date_naive();
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters.entry(key.to_string()).or_insert_with(|| Counter {
+            day: today,
+            usage: Usage::default(),
+        });
+        counter.roll(today);
+        let usage = &mut counter.usage;
+        usage.daily_requests += 1;
+        usage.monthly_requests += 1;
+        usage.daily_tokens += u64::from(tokens);
+        usage.monthly_tokens += u64::from(tokens);
+    }
+}
+
+/// The quota key of `identity`.
+///
+/// ```
+/// # use mcp_server::{auth::Identity, quota::quota_key};
+/// let identity = Identity { subject: "user-1".to_string(), tenant: Some("acme".to_string()) };
+/// assert_eq!(quota_key(&identity), "acme/user-1");
+/// ```
+pub fn quota_key(identity: &Identity) -> String {
+    match &identity.tenant {
+        Some(tenant) => format!("{}/{}", tenant, identity.subject),
+        None => identity.subject.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> DateTime<Utc> {
+        format!("{}T12:00:00Z", date).parse().unwrap()
+    }
+
+    #[test]
+    fn refuses_keys_over_a_limit_until_it_resets() {
+        let config = QuotaConfig {
+            default: QuotaLimits {
+                daily_requests: Some(2),
+                ..Default::default()
+            },
+            keys: HashMap::from([(
+                "acme/user-1".to_string(),
+                QuotaLimits {
+                    monthly_tokens: Some(100),
+                    ..Default::default()
+                },
+            )]),
+        };
+        let quotas = QuotaTracker::new(config);
+
+        quotas.record_at("user-2", 10, at("2026-01-31"));
+        assert!(quotas.check_at("user-2", at("2026-01-31")).is_ok());
+        quotas.record_at("user-2", 10, at("2026-01-31"));
+        let exceeded = quotas.check_at("user-2", at("2026-01-31")).unwrap_err();
+        assert_eq!(exceeded.limit, "daily_requests");
+        assert_eq!((exceeded.allowed, exceeded.used), (2, 2));
+        assert_eq!(exceeded.resets_at, "2026-02-01T00:00:00+00:00");
+        assert!(quotas.check_at("user-2", at("2026-02-01")).is_ok());
+
+        // A key's own limits replace the defaults
+        for _ in 0..3 {
+            quotas.record_at("acme/user-1", 40, at("2026-01-10"));
+        }
+        let exceeded = quotas
+            .check_at("acme/user-1", at("2026-01-20"))
+            .unwrap_err();
+        assert_eq!(exceeded.limit, "monthly_tokens");
+        assert_eq!(exceeded.resets_at, "2026-02-01T00:00:00+00:00");
+        assert!(quotas.check_at("acme/user-1", at("2026-02-01")).is_ok());
+    }
+
+    #[test]
+    fn parses_the_config_section() {
+        let config: QuotaConfig = toml::from_str(
+            r#"
+            [default]
+            daily_requests = 1000
+
+            [keys."acme/user-1"]
+            daily_tokens = 500000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.limits("someone").daily_requests, Some(1000));
+        assert_eq!(config.limits("acme/user-1").daily_requests, None);
+        assert_eq!(config.limits("acme/user-1").daily_tokens, Some(500000));
+        assert!(toml::from_str::<QuotaConfig>("[default]\nhourly_requests = 1").is_err());
+    }
+}
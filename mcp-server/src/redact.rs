@@ -0,0 +1,338 @@
+//! Masking of personal data and secrets in logs and stored transcripts.
+//!
+//! Users paste all sorts of things into a chat with a web3 agent, including
+//! their email address, phone number, wallet seed phrase or private key.
+//! A [`Redactor`] replaces those with a marker naming what was removed before
+//! a log line is written or a message is [stored](RedactedSessions) under its
+//! session:
+//!
+//! ```text
+//! INFO mcp_server::handlers: Processing text: my seed is [REDACTED:seed_phrase], mail me at [REDACTED:email]
+//! ```
+//!
+//! The built-in patterns are:
+//!
+//! | Name          | Matches                                                     |
+//! |---------------|-------------------------------------------------------------|
+//! | `private_key` | 64 hex digits, with or without `0x`; WIF keys; `xprv` keys  |
+//! | `seed_phrase` | 12 to 24 lowercase words of 3 to 8 letters in a row         |
+//! | `email`       | Email addresses                                             |
+//! | `phone`       | `+`-prefixed international numbers and `(555) 123-4567`     |
+//!
+//! Transaction hashes are 64 hex digits too and are masked as private keys.
+//! Wallet addresses are public and left alone. Replies sent back to the
+//! caller are never redacted, only what the server keeps.
+//!
+//! # Environment Variables
+//!
+//! * `REDACT_PII` - Optional. Comma-separated built-in patterns to apply, or
+//!   `none` (default: all of them)
+//! * `REDACT_PATTERNS` - Optional. Extra regular expressions, separated by
+//!   whitespace, whose matches are replaced with `[REDACTED]`; write `\s` for
+//!   a space inside a pattern
+
+use crate::models::Message;
+use crate::sessions::SessionStore;
+use async_trait::async_trait;
+use regex::Regex;
+use std::borrow::Cow;
+use std::io;
+use std::sync::Arc;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Names of the built-in patterns, in the order they are applied.
+pub const BUILTIN_PATTERNS: [&str; 4] = ["private_key", "seed_phrase", "email", "phone"];
+
+fn builtin_regex(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "private_key" => concat!(
+            r"\b(?:0x)?[0-9a-fA-F]{64}\b",
+            r"|\b[5KL][1-9A-HJ-NP-Za-km-z]{50,51}\b",
+            r"|\b[xt]prv[1-9A-HJ-NP-Za-km-z]{100,108}\b",
+        ),
+        "seed_phrase" => r"\b[a-z]{3,8}(?:\s+[a-z]{3,8}){11,23}\b",
+        "email" => r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b",
+        "phone" => concat!(
+            r"\+\d{1,3}(?:[\s.-]?\d{2,4}){2,5}\b",
+            r"|\(?\b\d{3}\)?[\s.-]\d{3}[\s.-]\d{4}\b",
+        ),
+        _ => return None,
+    })
+}
+
+/// A pattern and the marker its matches are replaced with.
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    marker: String,
+}
+
+/// Replaces personal data and secrets in text with `[REDACTED:<name>]`.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+}
+
+impl Redactor {
+    /// A redactor applying every built-in pattern.
+    pub fn builtin() -> Self {
+        Self::new(&BUILTIN_PATTERNS, &[]).expect("built-in patterns are valid")
+    }
+
+    /// A redactor applying the named built-in patterns, then the `extra`
+    /// regular expressions.
+    ///
+    /// Fails on an unknown built-in name or an invalid expression.
+    pub fn new(builtins: &[&str], extra: &[&str]) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for name in builtins {
+            let pattern = builtin_regex(name).ok_or_else(|| {
+                format!(
+                    "Unknown redaction pattern {}, expected one of {}",
+                    name,
+                    BUILTIN_PATTERNS.join(", ")
+                )
+            })?;
+            rules.push(Rule {
+                regex: Regex::new(pattern).expect("built-in patterns are valid"),
+                marker: format!("[REDACTED:{}]", name),
+            });
+        }
+        for pattern in extra {
+            let regex = Regex::new(pattern)
+                .map_err(|e| format!("Invalid redaction pattern {}: {}", pattern, e))?;
+            rules.push(Rule {
+                regex,
+                marker: "[REDACTED]".to_string(),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Loads the patterns from `REDACT_PII` and `REDACT_PATTERNS`.
+    ///
+    /// Fails on an unknown built-in name or an invalid expression.
+    pub fn from_env() -> Result<Self, String> {
+        let builtins = std::env::var("REDACT_PII").unwrap_or_default();
+        let builtins: Vec<&str> = match builtins.trim() {
+            "" => BUILTIN_PATTERNS.to_vec(),
+            "none" => Vec::new(),
+            names => names
+                .split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .collect(),
+        };
+        let extra = std::env::var("REDACT_PATTERNS").unwrap_or_default();
+        let extra: Vec<&str> = extra.split_whitespace().collect();
+        Self::new(&builtins, &extra)
+    }
+
+    /// Whether any pattern is applied.
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    /// `text` with every match replaced, borrowed if nothing matched.
+    ///
+    /// ```
+    /// use mcp_server::redact::Redactor;
+    ///
+    /// let redactor = Redactor::builtin();
+    /// assert_eq!(
+    ///     redactor.redact("write to vitalik@example.org"),
+    ///     "write to [REDACTED:email]"
+    /// );
+    /// assert_eq!(redactor.redact("gm"), "gm");
+    /// ```
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in &self.rules {
+            if let Cow::Owned(replaced) = rule.regex.replace_all(&text, rule.marker.as_str()) {
+                text = Cow::Owned(replaced);
+            }
+        }
+        text
+    }
+
+    /// `message` with its content and tool call arguments redacted.
+    pub fn redact_message(&self, mut message: Message) -> Message {
+        if let Cow::Owned(content) = self.redact(&message.content) {
+            message.content = content;
+        }
+        for call in &mut message.tool_calls {
+            if let Cow::Owned(arguments) = self.redact(&call.function.arguments) {
+                call.function.arguments = arguments;
+            }
+        }
+        message
+    }
+}
+
+/// A [`MakeWriter`] redacting everything written through its writers.
+///
+/// The `fmt` layer writes each event in one call, so a match is never split
+/// between two writes.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    redactor: Arc<Redactor>,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    /// Wraps `inner`, redacting with `redactor`.
+    pub fn new(inner: M, redactor: Arc<Redactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<'a, M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redactor: &self.redactor,
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer_for(meta),
+            redactor: &self.redactor,
+        }
+    }
+}
+
+/// Writer made by [`RedactingMakeWriter`].
+pub struct RedactingWriter<'a, W> {
+    inner: W,
+    redactor: &'a Redactor,
+}
+
+impl<W: io::Write> io::Write for RedactingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) => self
+                .inner
+                .write_all(self.redactor.redact(text).as_bytes())?,
+            Err(_) => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A [`SessionStore`] redacting messages before they are stored.
+pub struct RedactedSessions {
+    inner: Arc<dyn SessionStore>,
+    redactor: Arc<Redactor>,
+}
+
+impl RedactedSessions {
+    /// Wraps `inner`, redacting with `redactor`.
+    pub fn new(inner: Arc<dyn SessionStore>, redactor: Arc<Redactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedactedSessions {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Vec<Message>>, String> {
+        self.inner.get(session_id).await
+    }
+
+    async fn append(&self, session_id: &str, messages: Vec<Message>) -> Result<(), String> {
+        let messages = messages
+            .into_iter()
+            .map(|message| self.redactor.redact_message(message))
+            .collect();
+        self.inner.append(session_id, messages).await
+    }
+
+    async fn ids(&self) -> Result<Vec<String>, String> {
+        self.inner.ids().await
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        self.inner.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::MemorySessionStore;
+    use std::io::Write;
+    use std::time::Duration;
+
+    const SEED: &str =
+        "abandon ability able about above absent absorb abstract absurd abuse access accident";
+
+    #[test]
+    fn masks_each_builtin_pattern() {
+        let redactor = Redactor::builtin();
+        let key = format!("0x{}", "4c0883a69102937d6231471b5dbb6204fe512961".repeat(2));
+        let text = format!(
+            "key {}, seed: {}, mail a.b+c@mail.example.com call +44 20 7946 0958 or (555) 123-4567",
+            &key[..66],
+            SEED
+        );
+        assert_eq!(
+            redactor.redact(&text),
+            "key [REDACTED:private_key], seed: [REDACTED:seed_phrase], \
+             mail [REDACTED:email] call [REDACTED:phone] or [REDACTED:phone]"
+        );
+
+        // Addresses, amounts, dates and ordinary sentences are kept
+        let kept = "send 1.5 ETH to 0x742d35Cc6634C0532925a3b844Bc454e4438f44e on 2024-01-15, \
+                    what is the floor price of this nft collection today";
+        assert!(matches!(redactor.redact(kept), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn applies_selected_and_extra_patterns() {
+        let redactor = Redactor::new(&["email"], &[r"ACCT-\d+"]).unwrap();
+        assert_eq!(
+            redactor.redact("ACCT-991 of x@y.io, +1 415 555 0100"),
+            "[REDACTED] of [REDACTED:email], +1 415 555 0100"
+        );
+        assert!(Redactor::new(&["ssn"], &[]).is_err());
+        assert!(Redactor::new(&[], &["("]).is_err());
+        assert!(!Redactor::new(&[], &[]).unwrap().is_enabled());
+    }
+
+    #[test]
+    fn redacts_log_lines() {
+        let redactor = Arc::new(Redactor::builtin());
+        let mut out = Vec::new();
+        let mut writer = RedactingWriter {
+            inner: &mut out,
+            redactor: &redactor,
+        };
+        writer.write_all(b"INFO user=x@y.io\n").unwrap();
+        assert_eq!(out, b"INFO user=[REDACTED:email]\n");
+    }
+
+    #[tokio::test]
+    async fn stores_redacted_messages() {
+        let inner = Arc::new(MemorySessionStore::new(Duration::from_secs(60)));
+        let store = RedactedSessions::new(inner.clone(), Arc::new(Redactor::builtin()));
+        let message = Message {
+            role: "user".into(),
+            content: format!("restore: {}", SEED),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        };
+        store.append("s1", vec![message]).await.unwrap();
+        let stored = inner.get("s1").await.unwrap().unwrap();
+        assert_eq!(stored[0].content, "restore: [REDACTED:seed_phrase]");
+        assert_eq!(store.name(), "memory");
+    }
+}
@@ -0,0 +1,144 @@
+//! MCP resources.
+//!
+//! Read-only views of server state, addressed by URI:
+//!
+//! - `agent://{agent_id}/prompt` - an agent's system prompt (`text/plain`)
+//! - `transcript://{session_id}` - a session's recorded messages (`application/json`)
+//! - `config://server` - the active, non-secret configuration (`application/json`)
+//!
+//! Transcripts are not listed: a session ID is the secret of the client that
+//! chose it, so only a caller naming it, in the same tenant, reads it.
+
+use crate::auth;
+use crate::handlers::{visible_agents, SUPPORTED_PROTOCOL_VERSIONS};
+use crate::sessions;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// URI of the server configuration resource.
+pub const CONFIG_URI: &str = "config://server";
+
+/// An MCP resource listing entry.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    /// Address passed to `resources/read`
+    pub uri: String,
+    /// Human-readable name
+    pub name: String,
+    /// What the resource holds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MIME type of the contents
+    pub mime_type: String,
+}
+
+/// Result of the `resources/list` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListResourcesResult {
+    /// All readable resources
+    pub resources: Vec<Resource>,
+}
+
+/// Params of the `resources/read` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadResourceParams {
+    /// URI of the resource to read
+    pub uri: String,
+}
+
+/// The text contents of a resource.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContents {
+    /// URI of the resource
+    pub uri: String,
+    /// MIME type of `text`
+    pub mime_type: String,
+    /// The contents
+    pub text: String,
+}
+
+/// Result of the `resources/read` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadResourceResult {
+    /// Contents of the requested resource
+    pub contents: Vec<ResourceContents>,
+}
+
+/// The resources anyone may discover: the prompts of the caller's agents and
+/// the server configuration.
+pub fn list_resources(state: &AppState) -> Vec<Resource> {
+    let agents = visible_agents(state);
+    let mut resources: Vec<Resource> = agents
+        .list()
+        .iter()
+        .map(|agent| Resource {
+            uri: format!("agent://{}/prompt", agent.id),
+            name: format!("{} system prompt", agent.name),
+            description: Some(agent.description.clone()),
+            mime_type: "text/plain".to_string(),
+        })
+        .collect();
+    resources.push(Resource {
+        uri: CONFIG_URI.to_string(),
+        name: "Server configuration".to_string(),
+        description: Some("Providers, agents and load-shedding thresholds in effect".to_string()),
+        mime_type: "application/json".to_string(),
+    });
+    resources
+}
+
+/// Reads the resource at `uri`, or `None` if there is none.
+pub async fn read_resource(state: &AppState, uri: &str) -> Option<ResourceContents> {
+    let (mime_type, text) = if let Some(rest) = uri.strip_prefix("agent://") {
+        let agent_id = rest.strip_suffix("/prompt")?;
+        let agent = visible_agents(state).get(agent_id)?;
+        ("text/plain", agent.system_prompt.clone())
+    } else if let Some(session_id) = uri.strip_prefix("transcript://") {
+        let session_id =
+            sessions::session_key(auth::current_tenant().as_deref(), session_id).ok()?;
+        let messages = match state.sessions.get(&session_id).await {
+            Ok(messages) => messages?,
+            Err(e) => {
+                tracing::error!("Failed to load session {}: {}", session_id, e);
+                return None;
+            }
+        };
+        (
+            "application/json",
+            serde_json::to_string_pretty(&messages).ok()?,
+        )
+    } else if uri == CONFIG_URI {
+        ("application/json", server_config(state))
+    } else {
+        return None;
+    };
+    Some(ResourceContents {
+        uri: uri.to_string(),
+        mime_type: mime_type.to_string(),
+        text,
+    })
+}
+
+/// The active configuration, without API keys or sink URLs.
+fn server_config(state: &AppState) -> String {
+    let load_shed = state.shedder.config();
+    let config = json!({
+        "providers": state.providers.names(),
+        "protocol_versions": SUPPORTED_PROTOCOL_VERSIONS,
+        "agents": visible_agents(state)
+            .list()
+            .iter()
+            .map(|agent| json!({ "id": agent.id, "model": agent.model }))
+            .collect::<Vec<_>>(),
+        "load_shed": {
+            "max_in_flight": load_shed.max_in_flight,
+            "latency_ms": load_shed.latency_threshold.as_millis() as u64,
+            "retry_after_secs": load_shed.retry_after_secs,
+        },
+        "error_reporting": state.reporter.is_enabled(),
+    });
+    serde_json::to_string_pretty(&config).unwrap()
+}
@@ -0,0 +1,265 @@
+//! Priority scheduling of provider calls.
+//!
+//! When `PROVIDER_HTTP_MAX_CONNECTIONS_PER_HOST` or `PROVIDER_MAX_CONCURRENT`
+//! (see [`crate::providers::limit`]) caps the calls to a provider, requests
+//! that find every slot taken wait in one queue per [`Priority`].
+//! Freed slots go to the queues by weighted round robin (8:4:1 for
+//! high:normal:low by default): interactive voice traffic overtakes a flood of
+//! batch jobs, while batch work still gets a share and is never starved.
+//!
+//! A request's priority comes from its `X-Priority` header, the same one
+//! [load shedding](crate::load_shed) reads, and holds for every provider call
+//! made while handling it. `submit_text` jobs always run as `low`.
+//!
+//! # Environment Variables
+//!
+//! * `PROVIDER_HTTP_PRIORITY_WEIGHTS` - Optional. Share of freed slots for
+//!   `high`, `normal` and `low` requests, comma separated (default: `8,4,1`)
+
+use crate::load_shed::Priority;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+tokio::task_local! {
+    static PRIORITY: Priority;
+}
+
+/// Runs `future` with provider calls scheduled at `priority`.
+pub async fn with_priority<F: Future>(priority: Priority, future: F) -> F::Output {
+    PRIORITY.scope(priority, future).await
+}
+
+/// Priority of the request being handled; `Normal` outside of one.
+pub fn current_priority() -> Priority {
+    PRIORITY.try_with(|p| *p).unwrap_or(Priority::Normal)
+}
+
+/// Relative share of freed slots each priority gets while several wait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityWeights {
+    /// Weight of `high` (interactive) requests
+    pub high: u32,
+    /// Weight of `normal` requests
+    pub normal: u32,
+    /// Weight of `low` (batch) requests
+    pub low: u32,
+}
+
+impl Default for PriorityWeights {
+    fn default() -> Self {
+        Self {
+            high: 8,
+            normal: 4,
+            low: 1,
+        }
+    }
+}
+
+impl PriorityWeights {
+    /// Parses `high,normal,low`, e.g. `8,4,1`. Weights must be at least 1.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let weights: Vec<u32> = value
+            .split(',')
+            .map(|w| w.trim().parse().ok().filter(|w| *w > 0))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("invalid priority weights {:?}", value))?;
+        match weights[..] {
+            [high, normal, low] => Ok(Self { high, normal, low }),
+            _ => Err(format!(
+                "expected three priority weights (high,normal,low), got {:?}",
+                value
+            )),
+        }
+    }
+
+    fn get(&self, class: usize) -> i64 {
+        [self.low, self.normal, self.high][class].into()
+    }
+}
+
+/// A semaphore whose waiters are served by priority.
+pub struct PriorityLimiter {
+    weights: PriorityWeights,
+    state: Mutex<State>,
+}
+
+struct State {
+    available: usize,
+    /// Waiters by [`Priority`], oldest first
+    waiting: [VecDeque<oneshot::Sender<()>>; 3],
+    /// Smooth weighted round robin credit of each queue
+    credit: [i64; 3],
+}
+
+/// A slot from a [`PriorityLimiter`], freed on drop.
+pub struct Permit {
+    limiter: Arc<PriorityLimiter>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.release();
+    }
+}
+
+impl PriorityLimiter {
+    /// Creates a limiter with `permits` slots.
+    pub fn new(permits: usize, weights: PriorityWeights) -> Self {
+        Self {
+            weights,
+            state: Mutex::new(State {
+                available: permits,
+                waiting: Default::default(),
+                credit: [0; 3],
+            }),
+        }
+    }
+
+    /// Waits for a slot, behind waiters that the weights put first.
+    pub async fn acquire(self: Arc<Self>, priority: Priority) -> Permit {
+        let queued = {
+            let mut state = self.state.lock().unwrap();
+            if state.available > 0 && state.waiting.iter().all(VecDeque::is_empty) {
+                state.available -= 1;
+                None
+            } else {
+                let (sender, receiver) = oneshot::channel();
+                let queue = &mut state.waiting[priority as usize];
+                queue.retain(|waiter| !waiter.is_closed());
+                queue.push_back(sender);
+                Some(receiver)
+            }
+        };
+        if let Some(receiver) = queued {
+            let mut waiter = Waiter {
+                limiter: &self,
+                receiver,
+                granted: false,
+            };
+            // The sender is only dropped by a send
+            let _ = (&mut waiter.receiver).await;
+            waiter.granted = true;
+        }
+        Permit { limiter: self }
+    }
+
+    /// Takes a slot if one is free and nobody is waiting for it.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut state = self.state.lock().unwrap();
+        if state.available > 0 && state.waiting.iter().all(VecDeque::is_empty) {
+            state.available -= 1;
+            return Some(Permit {
+                limiter: self.clone(),
+            });
+        }
+        None
+    }
+
+    /// Number of requests waiting for a slot.
+    pub fn waiting(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        let waiting = state.waiting.iter().flatten();
+        waiting.filter(|waiter| !waiter.is_closed()).count()
+    }
+
+    /// Hands a freed slot to the next waiter, or puts it back.
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(class) = self.next_class(&mut state) {
+            let sender = state.waiting[class].pop_front().unwrap();
+            // Waiters that gave up have dropped their receiver
+            if sender.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+
+    /// Picks the queue to serve by smooth weighted round robin.
+    fn next_class(&self, state: &mut State) -> Option<usize> {
+        let ready: Vec<usize> = (0..3).filter(|&c| !state.waiting[c].is_empty()).collect();
+        let total: i64 = ready.iter().map(|&c| self.weights.get(c)).sum();
+        for &class in &ready {
+            state.credit[class] += self.weights.get(class);
+        }
+        // Ties go to the higher priority
+        let next = *ready.iter().max_by_key(|&&c| state.credit[c])?;
+        state.credit[next] -= total;
+        Some(next)
+    }
+}
+
+/// Returns a slot granted to a waiter that was dropped before it woke up.
+struct Waiter<'a> {
+    limiter: &'a PriorityLimiter,
+    receiver: oneshot::Receiver<()>,
+    granted: bool,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if !self.granted {
+            self.receiver.close();
+            if self.receiver.try_recv().is_ok() {
+                self.limiter.release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn serves_waiters_by_weight() {
+        let limiter = Arc::new(PriorityLimiter::new(1, PriorityWeights::default()));
+        let held = limiter.clone().acquire(Priority::Low).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for priority in [Priority::Low; 4].into_iter().chain([Priority::High; 8]) {
+            let (waiter, order) = (limiter.clone(), order.clone());
+            waiters.push(tokio::spawn(async move {
+                let _permit = waiter.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+            }));
+            while limiter.waiting() < waiters.len() {
+                tokio::task::yield_now().await;
+            }
+        }
+        // A waiter that gives up doesn't keep its slot
+        let abandoned = tokio::time::timeout(
+            Duration::from_millis(10),
+            limiter.clone().acquire(Priority::High),
+        );
+        assert!(abandoned.await.is_err());
+
+        drop(held);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        let order = order.lock().unwrap();
+        // One low in every 9 while high requests wait, then the rest
+        assert_eq!(order.iter().filter(|p| **p == Priority::High).count(), 8);
+        assert_eq!(
+            order[..9].iter().filter(|p| **p == Priority::Low).count(),
+            1
+        );
+        assert_eq!(order[9..], [Priority::Low; 3]);
+
+        assert_eq!(
+            PriorityWeights::parse("10, 2,1"),
+            Ok(PriorityWeights {
+                high: 10,
+                normal: 2,
+                low: 1
+            })
+        );
+        assert!(PriorityWeights::parse("1,0,1").is_err());
+        assert!(PriorityWeights::parse("1,1").is_err());
+    }
+}
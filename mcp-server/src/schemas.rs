@@ -0,0 +1,156 @@
+//! Standalone JSON Schemas of the request and response models.
+//!
+//! Clients that validate payloads rather than generate bindings from the
+//! [OpenRPC document](crate::openrpc) fetch one schema per model:
+//!
+//! - `GET /schemas` - the names of the models, with the URL of each schema
+//! - `GET /schemas/{name}` - the schema of one model, e.g.
+//!   `/schemas/ProcessTextParams`, `/schemas/ProcessTextResult` or
+//!   `/schemas/Agent` for agent definitions; a `.json` suffix is allowed
+//!
+//! The schemas are those of the OpenRPC document, so both always agree.
+//! Each is self-contained JSON Schema 2020-12: the models it refers to are
+//! copied under `$defs`.
+
+use crate::openrpc;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::{json, Map, Value};
+
+/// Dialect of the schemas.
+pub const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Prefix of references in the OpenRPC document.
+const COMPONENTS: &str = "#/components/schemas/";
+
+/// All the models' schemas, by name.
+fn models() -> &'static Map<String, Value> {
+    openrpc::cached()["components"]["schemas"]
+        .as_object()
+        .expect("the OpenRPC document has component schemas")
+}
+
+/// The names of the models with a schema, sorted.
+pub fn names() -> Vec<&'static str> {
+    models().keys().map(String::as_str).collect()
+}
+
+/// The self-contained schema of the model `name`, or `None` if there is no
+/// such model.
+///
+/// ```
+/// use mcp_server::schemas;
+///
+/// let schema = schemas::schema("ProcessTextParams").unwrap();
+/// assert_eq!(schema["title"], "ProcessTextParams");
+/// assert_eq!(schema["properties"]["conversation_history"]["oneOf"][0]["items"]["$ref"], "#/$defs/Message");
+/// assert!(schema["$defs"]["Message"].is_object());
+/// assert!(schemas::schema("Gas").is_none());
+/// ```
+pub fn schema(name: &str) -> Option<Value> {
+    let models = models();
+    let mut root = models.get(name)?.clone();
+    let mut defs = Map::new();
+    let mut pending = relink(&mut root);
+    while let Some(referenced) = pending.pop() {
+        if referenced == name || defs.contains_key(&referenced) {
+            continue;
+        }
+        let Some(model) = models.get(&referenced) else {
+            continue;
+        };
+        let mut model = model.clone();
+        pending.extend(relink(&mut model));
+        defs.insert(referenced, model);
+    }
+
+    let mut schema = Map::new();
+    schema.insert("$schema".into(), JSON_SCHEMA_DIALECT.into());
+    schema.insert("title".into(), name.into());
+    schema.extend(root.as_object().cloned().unwrap_or_default());
+    if !defs.is_empty() {
+        schema.insert("$defs".into(), Value::Object(defs));
+    }
+    Some(Value::Object(schema))
+}
+
+/// Points the references in `schema` to its `$defs`, or to the root for a
+/// model referring to itself, returning the names of the models referred to.
+fn relink(schema: &mut Value) -> Vec<String> {
+    let mut referenced = Vec::new();
+    match schema {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.get_mut("$ref") {
+                if let Some(name) = reference.strip_prefix(COMPONENTS) {
+                    referenced.push(name.to_string());
+                    *reference = format!("#/$defs/{}", name);
+                }
+            }
+            for value in object.values_mut() {
+                referenced.extend(relink(value));
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                referenced.extend(relink(item));
+            }
+        }
+        _ => {}
+    }
+    referenced
+}
+
+/// `GET /schemas` - the models with a schema.
+pub async fn index_handler() -> Json<Value> {
+    let schemas: Map<String, Value> = names()
+        .into_iter()
+        .map(|name| (name.to_string(), format!("/schemas/{}", name).into()))
+        .collect();
+    Json(json!({ "schemas": schemas }))
+}
+
+/// `GET /schemas/{name}` - the schema of one model, or `404`.
+pub async fn schema_handler(Path(name): Path<String>) -> Response {
+    let name = name.strip_suffix(".json").unwrap_or(&name);
+    match schema(name) {
+        Some(schema) => Json(schema).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas_are_self_contained() {
+        assert!(names().contains(&"Agent"));
+        for name in names() {
+            let schema = schema(name).unwrap();
+            let text = schema.to_string();
+            assert!(!text.contains(COMPONENTS), "{}", name);
+            // Every reference resolves within the schema itself
+            for (i, _) in text.match_indices("\"#/$defs/") {
+                let rest = &text[i + 9..];
+                let referenced = &rest[..rest.find('"').unwrap()];
+                assert!(
+                    schema["$defs"].get(referenced).is_some(),
+                    "{} refers to {}",
+                    name,
+                    referenced
+                );
+            }
+        }
+
+        let result = schema("ProcessTextResult").unwrap();
+        assert_eq!(result["$schema"], JSON_SCHEMA_DIALECT);
+        assert_eq!(
+            result["properties"]["metadata"]["$ref"],
+            "#/$defs/ProcessingMetadata"
+        );
+        // Models used only through others are pulled in too
+        assert!(result["$defs"]["FinishReason"]["enum"].is_array());
+    }
+}
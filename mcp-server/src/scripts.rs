@@ -0,0 +1,244 @@
+//! Rhai scripts run around an agent's requests.
+//!
+//! An agent may carry two [Rhai](https://rhai.rs) scripts, set with the rest
+//! of its definition in the config file or through `create_agent` and
+//! `update_agent`:
+//!
+//! ```toml
+//! [[agents]]
+//! id = "agent_005"
+//! # ...
+//!
+//! [agents.scripts]
+//! # Sees `text`, what the user wrote, and `agent_id`
+//! pre_request = '''
+//! if text.contains("floor price") {
+//!     return #{ reply: "Floor prices move by the minute; check the marketplace." };
+//! }
+//! "Answer in one paragraph. " + text
+//! '''
+//! # Sees `reply` and `agent_id`
+//! post_response = '''
+//! reply.replace("to the moon", "upwards");
+//! reply
+//! '''
+//! ```
+//!
+//! `pre_request` returns the text to send instead of the user's, or a map
+//! with a `reply` to answer with it without asking a provider.
+//! `post_response` returns the reply to send instead, before the
+//! [guardrails](crate::guardrails) are applied. A script that returns nothing
+//! (`()`) leaves the text as it was.
+//!
+//! Scripts can't import modules or reach the file system or network, and are
+//! stopped after [`MAX_OPERATIONS`] operations. A script that fails fails the
+//! request; one that doesn't compile is rejected with the agent definition.
+
+use crate::error::ServerError;
+use crate::models::Agent;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Most operations a script may run per request.
+pub const MAX_OPERATIONS: u64 = 100_000;
+
+/// Longest string a script may build, in bytes.
+pub const MAX_STRING_BYTES: usize = 1024 * 1024;
+
+/// An agent's scripts, as Rhai source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentScripts {
+    /// Runs on the user's text before the request is served
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_request: Option<String>,
+    /// Runs on the reply before it is returned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_response: Option<String>,
+}
+
+impl AgentScripts {
+    /// Whether the agent has no script.
+    pub fn is_empty(&self) -> bool {
+        self.pre_request.is_none() && self.post_response.is_none()
+    }
+
+    /// Checks that both scripts compile.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, source) in self.sources() {
+            compile(name, source)?;
+        }
+        Ok(())
+    }
+
+    fn sources(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("pre_request", self.pre_request.as_deref()),
+            ("post_response", self.post_response.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, source)| Some((name, source?)))
+    }
+}
+
+/// What an agent's `pre_request` script made of the user's text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreRequest {
+    /// Send the text as it is
+    Keep,
+    /// Send this text instead
+    Rewrite(String),
+    /// Answer with this reply without asking a provider
+    Answer(String),
+}
+
+/// Runs `agent`'s `pre_request` script on the user's `text`.
+pub fn pre_request(agent: &Agent, text: &str) -> Result<PreRequest, ServerError> {
+    let Some(source) = &agent.scripts.pre_request else {
+        return Ok(PreRequest::Keep);
+    };
+    let output = run(agent, "pre_request", source, ("text", text))?;
+    if output.is_unit() {
+        return Ok(PreRequest::Keep);
+    }
+    if output.is_map() {
+        let reply = output
+            .cast::<Map>()
+            .remove("reply")
+            .filter(Dynamic::is_string)
+            .ok_or_else(|| failure(agent, "pre_request", "returned a map without a reply"))?;
+        return Ok(PreRequest::Answer(reply.cast::<String>()));
+    }
+    output
+        .into_string()
+        .map(PreRequest::Rewrite)
+        .map_err(|kind| failure(agent, "pre_request", format!("returned a {}", kind)))
+}
+
+/// Runs `agent`'s `post_response` script on its `reply`.
+pub fn post_response(agent: &Agent, reply: String) -> Result<String, ServerError> {
+    let Some(source) = &agent.scripts.post_response else {
+        return Ok(reply);
+    };
+    let output = run(agent, "post_response", source, ("reply", &reply))?;
+    if output.is_unit() {
+        return Ok(reply);
+    }
+    output
+        .into_string()
+        .map_err(|kind| failure(agent, "post_response", format!("returned a {}", kind)))
+}
+
+/// Evaluates script `name` of `agent` with `agent_id` and `input` in scope.
+fn run(
+    agent: &Agent,
+    name: &'static str,
+    source: &str,
+    (variable, input): (&str, &str),
+) -> Result<Dynamic, ServerError> {
+    let ast = compile(name, source).map_err(|e| failure(agent, name, e))?;
+    let mut scope = Scope::new();
+    scope.push_constant("agent_id", agent.id.clone());
+    scope.push(variable.to_string(), input.to_string());
+    engine()
+        .eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+        .map_err(|e| failure(agent, name, e))
+}
+
+fn compile(name: &str, source: &str) -> Result<AST, String> {
+    engine()
+        .compile(source)
+        .map_err(|e| format!("invalid {} script: {}", name, e))
+}
+
+fn failure(agent: &Agent, name: &str, error: impl std::fmt::Display) -> ServerError {
+    ServerError::Internal(format!(
+        "{} script of agent {} failed: {}",
+        name, agent.id, error
+    ))
+}
+
+/// The engine scripts run in, sandboxed and limited.
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_string_size(MAX_STRING_BYTES)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .disable_symbol("eval");
+        engine.on_print(|text| tracing::debug!("Agent script: {}", text));
+        engine.on_debug(|text, _, _| tracing::debug!("Agent script: {}", text));
+        engine
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(pre_request: Option<&str>, post_response: Option<&str>) -> Agent {
+        let mut agent = crate::agents::builtin_agents().remove(0);
+        agent.scripts = AgentScripts {
+            pre_request: pre_request.map(str::to_string),
+            post_response: post_response.map(str::to_string),
+        };
+        agent
+    }
+
+    #[test]
+    fn rewrites_and_answers_requests() {
+        let pre = r#"
+            if text.contains("floor price") {
+                return #{ reply: "Check the marketplace." };
+            }
+            if text == "gm" { return; }
+            "[" + agent_id + "] " + text
+        "#;
+        let post = r#"reply.replace("moon", "rise"); reply"#;
+        let agent = agent(Some(pre), Some(post));
+        assert!(agent.scripts.validate().is_ok());
+
+        assert_eq!(
+            pre_request(&agent, "floor price of punks?").unwrap(),
+            PreRequest::Answer("Check the marketplace.".into())
+        );
+        assert_eq!(pre_request(&agent, "gm").unwrap(), PreRequest::Keep);
+        assert_eq!(
+            pre_request(&agent, "hi").unwrap(),
+            PreRequest::Rewrite(format!("[{}] hi", agent.id))
+        );
+        assert_eq!(
+            post_response(&agent, "to the moon".into()).unwrap(),
+            "to the rise"
+        );
+
+        let plain = self::agent(None, None);
+        assert!(plain.scripts.is_empty());
+        assert_eq!(pre_request(&plain, "hi").unwrap(), PreRequest::Keep);
+        assert_eq!(post_response(&plain, "hi".into()).unwrap(), "hi");
+    }
+
+    #[test]
+    fn rejects_broken_and_runaway_scripts() {
+        let err = agent(Some("if {"), None).scripts.validate().unwrap_err();
+        assert!(err.contains("invalid pre_request script"), "{}", err);
+        assert!(agent(None, Some(r#"eval("1")"#))
+            .scripts
+            .validate()
+            .is_err());
+
+        let spinning = agent(Some("loop {}"), None);
+        let err = pre_request(&spinning, "hi").unwrap_err().to_string();
+        assert!(err.contains("pre_request script of agent"), "{}", err);
+        let numeric = agent(None, Some("42"));
+        assert!(post_response(&numeric, "hi".into()).is_err());
+        let no_reply = agent(Some("#{ text: text }"), None);
+        assert!(pre_request(&no_reply, "hi").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Startup self-test of the agents' providers and models.
+//!
+//! A misspelt model or a key without access to it otherwise shows up on the
+//! first user request. With `STARTUP_SELF_TEST` set, the server sends a tiny
+//! prompt (`Reply with OK.`, at most a few tokens) through every pairing of an
+//! agent with its provider and model, the default provider and each
+//! configured fallback, before it serves. Pairings shared by several agents
+//! are tried once, all at once, each giving up after
+//! `STARTUP_SELF_TEST_TIMEOUT_MS`.
+//!
+//! A pairing answered with a client error, such as `401`, `403` or `404 model
+//! not found`, is misconfigured; rate limits, timeouts and `5xx`s are
+//! transient and only warned about. What happens next depends on the mode:
+//!
+//! * `degrade` - Agents with a failed pairing are reported `down` under
+//!   `agent:<id>` by [`GET /healthz`](crate::health), which makes the server
+//!   `degraded`, and the server starts anyway
+//! * `fail` - The server refuses to start when an agent's default pairing is
+//!   misconfigured; other failures degrade as above
+//!
+//! Agents added at runtime or by a config reload aren't tested.
+//!
+//! # Environment Variables
+//!
+//! * `STARTUP_SELF_TEST` - Optional. `off`, `degrade` or `fail` (default:
+//!   `off`)
+//! * `STARTUP_SELF_TEST_TIMEOUT_MS` - Optional. How long each pairing gets to
+//!   answer (default: 10000)
+
+use crate::error::ServerError;
+use crate::models::{Agent, GenerationParams};
+use crate::providers::{resolve_model, CompletionRequest, LlmProvider, ProviderRegistry};
+use futures_util::future::join_all;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long each pairing gets to answer when not configured.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the self-test does with failures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelfTestMode {
+    /// No self-test
+    #[default]
+    Off,
+    /// Report failed agents as down in the health check
+    Degrade,
+    /// Refuse to start when an agent's default pairing is misconfigured
+    Fail,
+}
+
+/// Settings of the startup self-test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTest {
+    pub mode: SelfTestMode,
+    /// How long each pairing gets to answer
+    pub timeout: Duration,
+}
+
+/// Outcome of one agent's pairing with a provider and model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairingResult {
+    pub agent_id: String,
+    pub provider: &'static str,
+    pub model: String,
+    /// Whether it is the agent's default provider rather than a fallback
+    pub default: bool,
+    /// How long the provider took to answer
+    pub latency: Duration,
+    /// Why the pairing failed, and whether it is misconfigured rather than
+    /// transiently failing
+    pub error: Option<(String, bool)>,
+}
+
+impl PairingResult {
+    /// Whether the provider answered with a client error, such as an
+    /// invalid key or model.
+    pub fn is_misconfigured(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|(_, misconfigured)| *misconfigured)
+    }
+}
+
+impl SelfTest {
+    /// Reads `STARTUP_SELF_TEST` and `STARTUP_SELF_TEST_TIMEOUT_MS`.
+    pub fn from_env() -> Result<Self, String> {
+        let mode = match std::env::var("STARTUP_SELF_TEST")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "off" | "false" | "0" => SelfTestMode::Off,
+            "degrade" => SelfTestMode::Degrade,
+            "fail" => SelfTestMode::Fail,
+            other => {
+                return Err(format!(
+                    "STARTUP_SELF_TEST must be off, degrade or fail, got {}",
+                    other
+                ))
+            }
+        };
+        let timeout = match std::env::var("STARTUP_SELF_TEST_TIMEOUT_MS") {
+            Ok(ms) => ms
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis)
+                .ok_or_else(|| {
+                    format!(
+                        "STARTUP_SELF_TEST_TIMEOUT_MS must be a positive number, got {}",
+                        ms
+                    )
+                })?,
+            Err(_) => DEFAULT_TIMEOUT,
+        };
+        Ok(Self { mode, timeout })
+    }
+
+    /// Whether the self-test runs.
+    pub fn is_enabled(&self) -> bool {
+        self.mode != SelfTestMode::Off
+    }
+
+    /// Tries every pairing of `agents` with the providers of `providers`,
+    /// each distinct provider and model once.
+    pub async fn run(
+        &self,
+        providers: &ProviderRegistry,
+        agents: &[Arc<Agent>],
+    ) -> Vec<PairingResult> {
+        let mut pairings = Vec::new();
+        for agent in agents {
+            let default = providers.default_provider().and_then(|provider| {
+                let model = resolve_model(provider.as_ref(), &agent.model, None).ok()?;
+                Some((provider, model))
+            });
+            let has_default = default.is_some();
+            let chain = default.into_iter().chain(providers.fallbacks(agent));
+            for (i, (provider, model)) in chain.enumerate() {
+                pairings.push((agent.clone(), provider, model, has_default && i == 0));
+            }
+        }
+
+        // Each provider and model once, all at once
+        let mut distinct: HashMap<(&'static str, String), _> = HashMap::new();
+        for (agent, provider, model, _) in &pairings {
+            distinct
+                .entry((provider.name(), model.clone()))
+                .or_insert_with(|| (agent.clone(), provider.clone()));
+        }
+        let tried = join_all(
+            distinct
+                .into_iter()
+                .map(|((name, model), (agent, provider))| {
+                    let timeout = self.timeout;
+                    async move {
+                        let started = Instant::now();
+                        let result = ping(provider, agent, model.clone(), timeout).await;
+                        ((name, model), (started.elapsed(), result.err()))
+                    }
+                }),
+        )
+        .await
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+        pairings
+            .into_iter()
+            .map(|(agent, provider, model, default)| {
+                let (latency, error) = &tried[&(provider.name(), model.clone())];
+                PairingResult {
+                    agent_id: agent.id.clone(),
+                    provider: provider.name(),
+                    model,
+                    default,
+                    latency: *latency,
+                    error: error
+                        .as_ref()
+                        .map(|e| (e.to_string(), !e.is_provider_fault())),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Sends the self-test prompt to `provider`'s `model` as `agent`.
+async fn ping(
+    provider: Arc<dyn LlmProvider>,
+    agent: Arc<Agent>,
+    model: String,
+    timeout: Duration,
+) -> Result<(), ServerError> {
+    let mut agent = Agent::clone(&agent);
+    agent.system_prompt = "You are a health check.".to_string();
+    agent.prompt_versions.clear();
+    agent.knowledge_base = None;
+    let request = CompletionRequest {
+        agent: Arc::new(agent),
+        model,
+        user_text: "Reply with OK.".to_string(),
+        conversation_history: None,
+        generation: GenerationParams {
+            temperature: Some(0.0),
+            max_tokens: Some(5),
+            ..Default::default()
+        },
+        tools: Vec::new(),
+        timeout: Some(timeout),
+        images: Vec::new(),
+    };
+    match tokio::time::timeout(timeout, provider.complete(request)).await {
+        Ok(result) => result.map(|_| ()),
+        Err(_) => Err(ServerError::Timeout(format!(
+            "{} did not answer the self-test within {:?}",
+            provider.name(),
+            timeout
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::builtin_agents;
+    use crate::models::ProviderFallback;
+    use crate::providers::MockProvider;
+
+    #[tokio::test]
+    async fn tries_each_pairing_and_flags_misconfigured_ones() {
+        let mut providers = ProviderRegistry::new();
+        providers.register(Arc::new(MockProvider::default()));
+        let mut agents: Vec<Arc<Agent>> = builtin_agents().into_iter().map(Arc::new).collect();
+        let mut agent = Agent::clone(&agents[0]);
+        agent.fallbacks = vec![ProviderFallback {
+            provider: "groq".to_string(),
+            model: None,
+        }];
+        agents[0] = Arc::new(agent);
+
+        let self_test = SelfTest {
+            mode: SelfTestMode::Fail,
+            timeout: Duration::from_secs(1),
+        };
+        let results = self_test.run(&providers, &agents).await;
+        // Fallbacks to unconfigured providers are skipped
+        assert_eq!(results.len(), agents.len());
+        assert!(results.iter().all(|r| r.default && r.provider == "mock"));
+        assert!(results.iter().all(|r| r.error.is_none()));
+
+        let broken = PairingResult {
+            error: Some(("Groq API error (404 Not Found)".to_string(), true)),
+            ..results[0].clone()
+        };
+        assert!(broken.is_misconfigured());
+        assert!(!results[0].is_misconfigured());
+    }
+}
@@ -0,0 +1,205 @@
+//! `get_balance`: reads the ETH or ERC-20 balance of an address.
+//!
+//! Uses `eth_getBalance` for the chain's native currency, and the token's
+//! `balanceOf`, `decimals` and `symbol` through `eth_call` when a token
+//! contract is given. Balances are returned both in base units and formatted
+//! with the token's decimals. Offered to agents with the `blockchain`
+//! capability, on the node configured for `get_transaction_status`.
+//!
+//! # Environment Variables
+//!
+//! * `EVM_RPC_URL` - JSON-RPC endpoint of an EVM node
+
+use super::evm::{address_word, decode_string, encode_call, is_address, EvmRpc};
+use super::ServerTool;
+use crate::http_client::HttpClient;
+use crate::models::FunctionTool;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// `balanceOf(address)` on an ERC-20 token.
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+/// `decimals()` on an ERC-20 token.
+const DECIMALS_SELECTOR: [u8; 4] = [0x31, 0x3c, 0xe5, 0x67];
+/// `symbol()` on an ERC-20 token.
+const SYMBOL_SELECTOR: [u8; 4] = [0x95, 0xd8, 0x9b, 0x41];
+
+/// Balance lookups, as a server tool.
+pub struct BalanceTool {
+    rpc: EvmRpc,
+}
+
+/// Arguments of a `get_balance` call.
+#[derive(Debug, Deserialize)]
+struct BalanceArguments {
+    address: String,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+impl BalanceTool {
+    /// Creates a tool querying `rpc`.
+    pub fn new(rpc: EvmRpc) -> Self {
+        Self { rpc }
+    }
+
+    /// Creates the tool if `EVM_RPC_URL` is set.
+    pub fn from_env(client: HttpClient) -> Option<Self> {
+        EvmRpc::from_env(client, "EVM_RPC_URL").map(Self::new)
+    }
+
+    /// The native balance of `address`, in wei.
+    async fn native_balance(&self, address: &str) -> Result<u128, String> {
+        let result = self
+            .rpc
+            .call("eth_getBalance", json!([address, "latest"]))
+            .await?;
+        result
+            .as_str()
+            .and_then(|hex| u128::from_str_radix(hex.strip_prefix("0x")?, 16).ok())
+            .ok_or_else(|| format!("Unexpected eth_getBalance result: {}", result))
+    }
+
+    /// The `token` balance of `address` in base units, with the token's
+    /// decimals and symbol.
+    async fn token_balance(
+        &self,
+        address: &str,
+        token: &str,
+    ) -> Result<(u128, u8, Option<String>), String> {
+        let holder =
+            address_word(address).ok_or_else(|| format!("{} is not an address", address))?;
+        let data = self
+            .rpc
+            .eth_call(token, &encode_call(BALANCE_OF_SELECTOR, &[holder]))
+            .await?;
+        let balance = decode_uint(&data)
+            .ok_or_else(|| format!("{} did not return a balance; is it an ERC-20 token?", token))?;
+        let data = self
+            .rpc
+            .eth_call(token, &encode_call(DECIMALS_SELECTOR, &[]))
+            .await?;
+        let decimals = decode_uint(&data)
+            .and_then(|d| u8::try_from(d).ok())
+            .ok_or_else(|| format!("{} did not return its decimals", token))?;
+        // Some early tokens return the symbol as bytes32, or not at all
+        let symbol = self
+            .rpc
+            .eth_call(token, &encode_call(SYMBOL_SELECTOR, &[]))
+            .await
+            .ok()
+            .and_then(|data| decode_string(&data));
+        Ok((balance, decimals, symbol))
+    }
+}
+
+#[async_trait]
+impl ServerTool for BalanceTool {
+    fn name(&self) -> &'static str {
+        "get_balance"
+    }
+
+    fn definition(&self) -> FunctionTool {
+        serde_json::from_value(json!({
+            "type": "function",
+            "function": {
+                "name": self.name(),
+                "description": "Read the on-chain balance of a wallet: the native currency (ETH), or an ERC-20 token when its contract address is given.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "address": { "type": "string", "description": "Wallet address (0x followed by 40 hex digits)" },
+                        "token": { "type": "string", "description": "ERC-20 contract address; omit for ETH" }
+                    },
+                    "required": ["address"]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn capability(&self) -> &'static str {
+        "blockchain"
+    }
+
+    async fn call(&self, arguments: &str) -> Result<Value, String> {
+        let BalanceArguments { address, token } = serde_json::from_str(arguments)
+            .map_err(|e| format!("invalid get_balance arguments: {}", e))?;
+        if !is_address(&address) {
+            return Err(format!("{} is not an address", address));
+        }
+        match token {
+            None => {
+                let wei = self.native_balance(&address).await?;
+                Ok(json!({
+                    "address": address,
+                    "symbol": "ETH",
+                    "decimals": 18,
+                    "raw": wei.to_string(),
+                    "balance": format_units(wei, 18),
+                }))
+            }
+            Some(token) if is_address(&token) => {
+                let (raw, decimals, symbol) = self.token_balance(&address, &token).await?;
+                Ok(json!({
+                    "address": address,
+                    "token": token,
+                    "symbol": symbol,
+                    "decimals": decimals,
+                    "raw": raw.to_string(),
+                    "balance": format_units(raw, decimals),
+                }))
+            }
+            Some(token) => Err(format!("{} is not a token address", token)),
+        }
+    }
+}
+
+/// The `uint256` at the start of ABI return data, if it fits in a `u128`.
+fn decode_uint(data: &[u8]) -> Option<u128> {
+    let word = data.get(..32)?;
+    let (high, low) = word.split_at(16);
+    if high.iter().any(|&b| b != 0) {
+        return None;
+    }
+    Some(u128::from_be_bytes(low.try_into().ok()?))
+}
+
+/// `amount` base units as a decimal number of whole tokens, e.g. `1.5`.
+fn format_units(amount: u128, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = usize::from(decimals);
+    if decimals == 0 {
+        return digits;
+    }
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_tools::evm::{from_hex, keccak256};
+
+    #[test]
+    fn formats_balances() {
+        assert_eq!(format_units(1_500_000_000_000_000_000, 18), "1.5");
+        assert_eq!(format_units(1, 6), "0.000001");
+        assert_eq!(format_units(42_000_000, 6), "42");
+        assert_eq!(format_units(7, 0), "7");
+
+        let word = from_hex(&format!("0x{:064x}", 21_000_000u64)).unwrap();
+        assert_eq!(decode_uint(&word), Some(21_000_000));
+        assert_eq!(decode_uint(&[0xff; 32]), None);
+        assert_eq!(&keccak256(b"balanceOf(address)")[..4], BALANCE_OF_SELECTOR);
+        assert_eq!(&keccak256(b"decimals()")[..4], DECIMALS_SELECTOR);
+        assert_eq!(&keccak256(b"symbol()")[..4], SYMBOL_SELECTOR);
+    }
+}
@@ -0,0 +1,185 @@
+//! `resolve_ens`: resolves ENS names to addresses, and addresses to names.
+//!
+//! Reads the ENS registry on Ethereum mainnet through the configured RPC
+//! node: the name's resolver is looked up in the registry, then asked for the
+//! address (`addr`) or, for reverse lookups of `<address>.addr.reverse`, the
+//! primary name (`name`). A reverse result is only returned if that name
+//! resolves back to the address, as ENS requires. Offered to agents with the
+//! `web3` capability.
+//!
+//! Names are lowercased but not otherwise normalized, so names outside ASCII
+//! may not resolve.
+//!
+//! # Environment Variables
+//!
+//! * `ENS_RPC_URL` - JSON-RPC endpoint of an Ethereum mainnet node
+
+use super::evm::{decode_address, decode_string, encode_call, is_address, keccak256, EvmRpc};
+use super::ServerTool;
+use crate::http_client::HttpClient;
+use crate::models::FunctionTool;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// The ENS registry, at the same address on mainnet and the testnets.
+pub const ENS_REGISTRY: &str = "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+
+/// `resolver(bytes32)` on the registry.
+const RESOLVER_SELECTOR: [u8; 4] = [0x01, 0x78, 0xb8, 0xbf];
+/// `addr(bytes32)` on a resolver.
+const ADDR_SELECTOR: [u8; 4] = [0x3b, 0x3b, 0x57, 0xde];
+/// `name(bytes32)` on a reverse resolver.
+const NAME_SELECTOR: [u8; 4] = [0x69, 0x1f, 0x34, 0x31];
+
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// ENS lookups, as a server tool.
+pub struct EnsTool {
+    rpc: EvmRpc,
+}
+
+/// Arguments of a `resolve_ens` call: a name, or an address to reverse.
+#[derive(Debug, Deserialize)]
+struct EnsArguments {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    address: Option<String>,
+}
+
+impl EnsTool {
+    /// Creates a tool querying `rpc`, which must serve Ethereum mainnet.
+    pub fn new(rpc: EvmRpc) -> Self {
+        Self { rpc }
+    }
+
+    /// Creates the tool if `ENS_RPC_URL` is set.
+    pub fn from_env(client: HttpClient) -> Option<Self> {
+        EvmRpc::from_env(client, "ENS_RPC_URL").map(Self::new)
+    }
+
+    /// The resolver set for `node`, or `None` if there is none.
+    async fn resolver(&self, node: [u8; 32]) -> Result<Option<String>, String> {
+        let data = self
+            .rpc
+            .eth_call(ENS_REGISTRY, &encode_call(RESOLVER_SELECTOR, &[node]))
+            .await?;
+        Ok(decode_address(&data).filter(|a| a != ZERO_ADDRESS))
+    }
+
+    /// The address `name` resolves to, if any.
+    async fn resolve(&self, name: &str) -> Result<Option<String>, String> {
+        let node = namehash(name);
+        let Some(resolver) = self.resolver(node).await? else {
+            return Ok(None);
+        };
+        let data = self
+            .rpc
+            .eth_call(&resolver, &encode_call(ADDR_SELECTOR, &[node]))
+            .await?;
+        Ok(decode_address(&data).filter(|a| a != ZERO_ADDRESS))
+    }
+
+    /// The verified primary name of `address`, if any.
+    async fn reverse(&self, address: &str) -> Result<Option<String>, String> {
+        let address = address.to_ascii_lowercase();
+        let node = namehash(&format!("{}.addr.reverse", &address[2..]));
+        let Some(resolver) = self.resolver(node).await? else {
+            return Ok(None);
+        };
+        let data = self
+            .rpc
+            .eth_call(&resolver, &encode_call(NAME_SELECTOR, &[node]))
+            .await?;
+        let Some(name) = decode_string(&data).filter(|n| !n.is_empty()) else {
+            return Ok(None);
+        };
+        let forward = self.resolve(&name).await?;
+        Ok((forward.as_deref() == Some(address.as_str())).then_some(name))
+    }
+}
+
+#[async_trait]
+impl ServerTool for EnsTool {
+    fn name(&self) -> &'static str {
+        "resolve_ens"
+    }
+
+    fn definition(&self) -> FunctionTool {
+        serde_json::from_value(json!({
+            "type": "function",
+            "function": {
+                "name": self.name(),
+                "description": "Resolve an ENS name (e.g. vitalik.eth) to its Ethereum address, or an address to its primary ENS name. Pass exactly one of name or address.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string", "description": "ENS name to resolve" },
+                        "address": { "type": "string", "description": "Address to look up the primary name of" }
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn capability(&self) -> &'static str {
+        "web3"
+    }
+
+    async fn call(&self, arguments: &str) -> Result<Value, String> {
+        let arguments: EnsArguments = serde_json::from_str(arguments)
+            .map_err(|e| format!("invalid resolve_ens arguments: {}", e))?;
+        match (arguments.name, arguments.address) {
+            (Some(name), None) => {
+                let name = name.trim().to_lowercase();
+                let address = self.resolve(&name).await?;
+                Ok(json!({ "name": name, "address": address }))
+            }
+            (None, Some(address)) if is_address(&address) => {
+                let name = self.reverse(&address).await?;
+                Ok(json!({ "address": address, "name": name }))
+            }
+            (None, Some(address)) => Err(format!("{} is not an address", address)),
+            _ => Err("pass exactly one of name or address".to_string()),
+        }
+    }
+}
+
+/// The ENS namehash of `name`: labels are hashed from the top-level domain
+/// down, starting from 32 zero bytes.
+fn namehash(name: &str) -> [u8; 32] {
+    let mut node = [0u8; 32];
+    if name.is_empty() {
+        return node;
+    }
+    for label in name.rsplit('.') {
+        let mut buf = [0u8; 64];
+        buf[..32].copy_from_slice(&node);
+        buf[32..].copy_from_slice(&keccak256(label.as_bytes()));
+        node = keccak256(&buf);
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_tools::evm::to_hex;
+
+    #[test]
+    fn hashes_names_and_selectors() {
+        assert_eq!(
+            to_hex(&namehash("eth")),
+            "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+        );
+        assert_eq!(
+            to_hex(&namehash("foo.eth")),
+            "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+        );
+        assert_eq!(&keccak256(b"resolver(bytes32)")[..4], RESOLVER_SELECTOR);
+        assert_eq!(&keccak256(b"addr(bytes32)")[..4], ADDR_SELECTOR);
+        assert_eq!(&keccak256(b"name(bytes32)")[..4], NAME_SELECTOR);
+    }
+}
@@ -0,0 +1,169 @@
+//! Minimal EVM JSON-RPC client shared by the on-chain tools.
+//!
+//! Only what the tools need: plain calls, `eth_call` against a contract, and
+//! decoding of hex quantities and ABI words.
+
+use crate::http_client::HttpClient;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+
+/// A JSON-RPC endpoint of an EVM node.
+#[derive(Clone)]
+pub struct EvmRpc {
+    client: HttpClient,
+    url: String,
+}
+
+impl EvmRpc {
+    /// Creates a client for the node at `url`.
+    pub fn new(client: HttpClient, url: &str) -> Self {
+        Self {
+            client,
+            url: url.to_string(),
+        }
+    }
+
+    /// Creates a client for the URL in the environment variable `var`, if set.
+    pub fn from_env(client: HttpClient, var: &str) -> Option<Self> {
+        let url = std::env::var(var).ok()?;
+        (!url.is_empty()).then(|| Self::new(client, &url))
+    }
+
+    /// Sends one JSON-RPC call and returns its `result`.
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let _permit = self.client.acquire(&self.url).await;
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("RPC request failed: {}", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("RPC error ({})", status));
+        }
+        let mut response: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse RPC response: {}", e))?;
+        if let Some(error) = response.get("error") {
+            let message = error["message"].as_str().unwrap_or("unknown error");
+            return Err(format!("RPC error: {}", message));
+        }
+        Ok(response["result"].take())
+    }
+
+    /// Calls a contract at the latest block and returns the raw return data.
+    pub async fn eth_call(&self, to: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+        let call = json!({ "to": to, "data": format!("0x{}", to_hex(data)) });
+        let result = self.call("eth_call", json!([call, "latest"])).await?;
+        result
+            .as_str()
+            .and_then(from_hex)
+            .ok_or_else(|| format!("Unexpected eth_call result: {}", result))
+    }
+}
+
+/// Keccak-256 of `data`.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Lowercase hex of `bytes`, without a `0x` prefix.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hex with an optional `0x` prefix.
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Decodes a hex JSON-RPC quantity such as `"0x1b4"`.
+pub fn quantity(value: &Value) -> Option<u64> {
+    let hex = value.as_str()?.strip_prefix("0x")?;
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// Whether `s` is `0x` followed by `len` hex digits.
+pub fn is_hex_of_len(s: &str, len: usize) -> bool {
+    s.strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == len && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Whether `s` looks like an address: `0x` followed by 40 hex digits.
+pub fn is_address(s: &str) -> bool {
+    is_hex_of_len(s, 40)
+}
+
+/// The address in the 32-byte ABI word at the start of `data`.
+pub fn decode_address(data: &[u8]) -> Option<String> {
+    let word = data.get(..32)?;
+    Some(format!("0x{}", to_hex(&word[12..])))
+}
+
+/// The dynamic `string` an ABI-encoded return value starts with.
+pub fn decode_string(data: &[u8]) -> Option<String> {
+    let offset = word_to_usize(data.get(..32)?)?;
+    let len = word_to_usize(data.get(offset..offset.checked_add(32)?)?)?;
+    let start = offset + 32;
+    let bytes = data.get(start..start.checked_add(len)?)?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// A 32-byte ABI word as a `usize`, if it fits.
+fn word_to_usize(word: &[u8]) -> Option<usize> {
+    let (high, low) = word.split_at(24);
+    if high.iter().any(|&b| b != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(low.try_into().ok()?) as usize)
+}
+
+/// ABI-encodes a call: the selector followed by 32-byte words.
+pub fn encode_call(selector: [u8; 4], words: &[[u8; 32]]) -> Vec<u8> {
+    let mut data = selector.to_vec();
+    for word in words {
+        data.extend_from_slice(word);
+    }
+    data
+}
+
+/// An address left-padded to a 32-byte ABI word.
+pub fn address_word(address: &str) -> Option<[u8; 32]> {
+    let bytes = from_hex(address).filter(|b| b.len() == 20)?;
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(&bytes);
+    Some(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_abi_values() {
+        let mut data = vec![0u8; 96];
+        data[31] = 0x20;
+        data[63] = 3;
+        data[64..67].copy_from_slice(b"eth");
+        assert_eq!(decode_string(&data).as_deref(), Some("eth"));
+
+        let word = address_word("0x00000000000c2e074ec69a0dfb2997ba6c7d2e1e").unwrap();
+        assert_eq!(
+            decode_address(&word).as_deref(),
+            Some("0x00000000000c2e074ec69a0dfb2997ba6c7d2e1e")
+        );
+        assert_eq!(quantity(&json!("0x5208")), Some(21000));
+        assert_eq!(from_hex("0xabc"), None);
+    }
+}
@@ -0,0 +1,148 @@
+//! `mint_nft`: mints an NFT through the web3-minting service.
+//!
+//! The model's arguments are posted to the service's `POST /mint` endpoint,
+//! and the transaction hash, token ID and metadata URL it answers with are
+//! handed back to the model. Offered to agents with the `nft` capability.
+//!
+//! # Environment Variables
+//!
+//! * `MINTING_SERVICE_URL` - Base URL of the web3-minting service
+//!   (e.g. `http://localhost:8081`)
+
+use super::ServerTool;
+use crate::http_client::HttpClient;
+use crate::models::FunctionTool;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// The web3-minting `/mint` call, as a server tool.
+pub struct MintTool {
+    client: HttpClient,
+    url: String,
+}
+
+/// Arguments of a `mint_nft` call, forwarded as the `/mint` request body.
+#[derive(Debug, Serialize, Deserialize)]
+struct MintArguments {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    asset_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recipient: Option<String>,
+}
+
+impl MintTool {
+    /// Creates a tool calling the service at `base_url`.
+    pub fn new(client: HttpClient, base_url: &str) -> Self {
+        Self {
+            client,
+            url: format!("{}/mint", base_url.trim_end_matches('/')),
+        }
+    }
+
+    /// Creates the tool if `MINTING_SERVICE_URL` is set.
+    pub fn from_env(client: HttpClient) -> Option<Self> {
+        let url = std::env::var("MINTING_SERVICE_URL").ok()?;
+        (!url.is_empty()).then(|| Self::new(client, &url))
+    }
+}
+
+#[async_trait]
+impl ServerTool for MintTool {
+    fn name(&self) -> &'static str {
+        "mint_nft"
+    }
+
+    fn definition(&self) -> FunctionTool {
+        serde_json::from_value(json!({
+            "type": "function",
+            "function": {
+                "name": self.name(),
+                "description": "Mint an NFT for the user. Returns the transaction hash, token ID and metadata URL.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string", "description": "Title of the NFT" },
+                        "description": { "type": "string", "description": "Description or transcript" },
+                        "asset_url": { "type": "string", "description": "URL of an uploaded image or audio file" },
+                        "recipient": { "type": "string", "description": "Wallet address to mint to" }
+                    },
+                    "required": ["name"]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn capability(&self) -> &'static str {
+        "nft"
+    }
+
+    async fn call(&self, arguments: &str) -> Result<Value, String> {
+        let arguments: MintArguments = serde_json::from_str(arguments)
+            .map_err(|e| format!("invalid mint_nft arguments: {}", e))?;
+
+        let _permit = self.client.acquire(&self.url).await;
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&arguments)
+            .send()
+            .await
+            .map_err(|e| format!("Minting service request failed: {}", e))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read minting service response: {}", e))?;
+        parse_mint_response(status, &body)
+    }
+}
+
+/// Picks the transaction hash, token ID and metadata URL out of a `/mint`
+/// response.
+fn parse_mint_response(status: StatusCode, body: &str) -> Result<Value, String> {
+    let response: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+    if !status.is_success() {
+        let error = response["error"].as_str().unwrap_or(body);
+        return Err(format!("Minting service error ({}): {}", status, error));
+    }
+    let tx_hash = response["mint"]["tx_hash"]
+        .as_str()
+        .ok_or_else(|| format!("Unexpected minting service response: {}", body))?;
+    Ok(json!({
+        "tx_hash": tx_hash,
+        "token_id": response["mint"]["token_id"],
+        "metadata_url": response["upload"]["url"],
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_the_mint_result() {
+        let body = r#"{
+            "status": "success",
+            "upload": { "cid": "bafy", "url": "https://ipfs.io/ipfs/bafy" },
+            "mint": { "tx_hash": "0xabc", "token_id": "7" }
+        }"#;
+        assert_eq!(
+            parse_mint_response(StatusCode::OK, body).unwrap(),
+            json!({
+                "tx_hash": "0xabc",
+                "token_id": "7",
+                "metadata_url": "https://ipfs.io/ipfs/bafy"
+            })
+        );
+
+        let failed = r#"{"error": "Mint failed: reverted"}"#;
+        let err = parse_mint_response(StatusCode::INTERNAL_SERVER_ERROR, failed).unwrap_err();
+        assert!(err.ends_with("Mint failed: reverted"), "{}", err);
+    }
+}
@@ -0,0 +1,135 @@
+//! Tools the server runs itself.
+//!
+//! The `tools` a client passes to `process_text` come back to the client as
+//! `tool_calls`. Server tools are instead answered by the server: when a
+//! reply only calls server tools, the server runs them, appends the calls and
+//! their results to the conversation and asks the model again, up to
+//! [`MAX_ROUNDS`] times, until it replies without calling one.
+//!
+//! Each tool is offered to the agents that have its
+//! [capability](ServerTool::capability), and only when the provider supports
+//! tools.
+//!
+//! # Environment Variables
+//!
+//! * `MINTING_SERVICE_URL` - Enables `mint_nft` (see [`mint`])
+//! * `EVM_RPC_URL` - Enables `get_transaction_status` and `get_balance` (see
+//!   [`tx_status`] and [`balance`])
+//! * `ENS_RPC_URL` - Enables `resolve_ens` (see [`ens`])
+
+pub mod balance;
+pub mod ens;
+pub mod evm;
+pub mod mint;
+pub mod tx_status;
+
+use crate::http_client::HttpClient;
+use crate::models::{Agent, FunctionTool, Message, ToolCall};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+pub use balance::BalanceTool;
+pub use ens::EnsTool;
+pub use mint::MintTool;
+pub use tx_status::TxStatusTool;
+
+/// Most rounds of server tool calls answered for one request.
+pub const MAX_ROUNDS: usize = 4;
+
+/// A function the server can run on the model's behalf.
+#[async_trait]
+pub trait ServerTool: Send + Sync {
+    /// Name the model calls the tool by, e.g. `mint_nft`.
+    fn name(&self) -> &'static str;
+
+    /// Declaration sent to the model; its function name is [`name`](Self::name).
+    fn definition(&self) -> FunctionTool;
+
+    /// Agent capability the tool is offered to, e.g. `nft`.
+    fn capability(&self) -> &'static str;
+
+    /// Runs a call with the model's JSON-encoded arguments.
+    ///
+    /// The returned value is sent back to the model as the call's result.
+    async fn call(&self, arguments: &str) -> Result<Value, String>;
+}
+
+/// The configured server tools. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct ServerTools {
+    tools: Vec<Arc<dyn ServerTool>>,
+}
+
+impl ServerTools {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers every tool whose settings are in the environment.
+    pub fn from_env(client: &HttpClient) -> Self {
+        let mut tools = Self::new();
+        if let Some(mint) = MintTool::from_env(client.clone()) {
+            tools.register(Arc::new(mint));
+        }
+        if let Some(tx_status) = TxStatusTool::from_env(client.clone()) {
+            tools.register(Arc::new(tx_status));
+        }
+        if let Some(balance) = BalanceTool::from_env(client.clone()) {
+            tools.register(Arc::new(balance));
+        }
+        if let Some(ens) = EnsTool::from_env(client.clone()) {
+            tools.register(Arc::new(ens));
+        }
+        tools
+    }
+
+    /// Adds a tool.
+    pub fn register(&mut self, tool: Arc<dyn ServerTool>) {
+        self.tools.push(tool);
+    }
+
+    /// Tools offered to `agent`.
+    pub fn for_agent(&self, agent: &Agent) -> Vec<Arc<dyn ServerTool>> {
+        self.tools
+            .iter()
+            .filter(|tool| agent.capabilities.iter().any(|c| c == tool.capability()))
+            .cloned()
+            .collect()
+    }
+
+    /// Names of all registered tools.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.tools.iter().map(|tool| tool.name()).collect()
+    }
+}
+
+/// The tool in `tools` called `name`, if any.
+pub fn find<'a>(tools: &'a [Arc<dyn ServerTool>], name: &str) -> Option<&'a Arc<dyn ServerTool>> {
+    tools.iter().find(|tool| tool.name() == name)
+}
+
+/// Runs `call` and returns the `tool` message answering it.
+///
+/// Failures are reported to the model as `{"error": ...}` so it can explain
+/// them to the user.
+pub async fn answer(tools: &[Arc<dyn ServerTool>], call: &ToolCall) -> Message {
+    let result = match find(tools, &call.function.name) {
+        Some(tool) => tool.call(&call.function.arguments).await,
+        None => Err(format!("unknown tool {}", call.function.name)),
+    };
+    let content = match result {
+        Ok(value) => value.to_string(),
+        Err(e) => {
+            tracing::warn!("Server tool {} failed: {}", call.function.name, e);
+            json!({ "error": e }).to_string()
+        }
+    };
+    Message {
+        role: "tool".to_string(),
+        content,
+        tool_call_id: Some(call.id.clone()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,167 @@
+//! `get_transaction_status`: looks a transaction up on an EVM chain.
+//!
+//! Queries the configured JSON-RPC node with `eth_getTransactionReceipt`, so
+//! agents can tell whether a mint or transfer confirmed, reverted or is still
+//! pending from on-chain data. Offered to agents with the `blockchain`
+//! capability.
+//!
+//! # Environment Variables
+//!
+//! * `EVM_RPC_URL` - JSON-RPC endpoint of an EVM node
+//!   (e.g. `https://sepolia.base.org`)
+
+use super::evm::{is_hex_of_len, quantity, EvmRpc};
+use super::ServerTool;
+use crate::http_client::HttpClient;
+use crate::models::FunctionTool;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// The `eth_getTransactionReceipt` lookup, as a server tool.
+pub struct TxStatusTool {
+    rpc: EvmRpc,
+}
+
+/// Arguments of a `get_transaction_status` call.
+#[derive(Debug, Deserialize)]
+struct TxStatusArguments {
+    tx_hash: String,
+}
+
+impl TxStatusTool {
+    /// Creates a tool querying `rpc`.
+    pub fn new(rpc: EvmRpc) -> Self {
+        Self { rpc }
+    }
+
+    /// Creates the tool if `EVM_RPC_URL` is set.
+    pub fn from_env(client: HttpClient) -> Option<Self> {
+        EvmRpc::from_env(client, "EVM_RPC_URL").map(Self::new)
+    }
+}
+
+#[async_trait]
+impl ServerTool for TxStatusTool {
+    fn name(&self) -> &'static str {
+        "get_transaction_status"
+    }
+
+    fn definition(&self) -> FunctionTool {
+        serde_json::from_value(json!({
+            "type": "function",
+            "function": {
+                "name": self.name(),
+                "description": "Look up a transaction on-chain. Returns whether it is confirmed, reverted, pending or not found, with its block and confirmations.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "tx_hash": { "type": "string", "description": "Transaction hash (0x followed by 64 hex digits)" }
+                    },
+                    "required": ["tx_hash"]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn capability(&self) -> &'static str {
+        "blockchain"
+    }
+
+    async fn call(&self, arguments: &str) -> Result<Value, String> {
+        let TxStatusArguments { tx_hash } = serde_json::from_str(arguments)
+            .map_err(|e| format!("invalid get_transaction_status arguments: {}", e))?;
+        if !is_tx_hash(&tx_hash) {
+            return Err(format!("{} is not a transaction hash", tx_hash));
+        }
+
+        let receipt = self
+            .rpc
+            .call("eth_getTransactionReceipt", json!([tx_hash]))
+            .await?;
+        if receipt.is_null() {
+            // No receipt yet: the node either has it in its mempool or never saw it
+            let tx = self
+                .rpc
+                .call("eth_getTransactionByHash", json!([tx_hash]))
+                .await?;
+            let status = if tx.is_null() { "not_found" } else { "pending" };
+            return Ok(json!({ "tx_hash": tx_hash, "status": status }));
+        }
+        let latest = self.rpc.call("eth_blockNumber", json!([])).await?;
+        Ok(describe_receipt(&tx_hash, &receipt, quantity(&latest)))
+    }
+}
+
+/// Whether `s` is `0x` followed by 64 hex digits.
+fn is_tx_hash(s: &str) -> bool {
+    is_hex_of_len(s, 64)
+}
+
+/// Summarizes a receipt for the model, with numbers in decimal.
+fn describe_receipt(tx_hash: &str, receipt: &Value, latest_block: Option<u64>) -> Value {
+    let status = match receipt["status"].as_str() {
+        Some("0x1") => "confirmed",
+        Some("0x0") => "reverted",
+        // Pre-Byzantium receipts have a state root instead of a status
+        _ => "included",
+    };
+    let block = quantity(&receipt["blockNumber"]);
+    let confirmations = match (block, latest_block) {
+        (Some(block), Some(latest)) => Some(latest.saturating_sub(block) + 1),
+        _ => None,
+    };
+    let mut summary = json!({
+        "tx_hash": tx_hash,
+        "status": status,
+        "block_number": block,
+        "confirmations": confirmations,
+        "from": receipt["from"],
+        "to": receipt["to"],
+        "gas_used": quantity(&receipt["gasUsed"]),
+    });
+    if !receipt["contractAddress"].is_null() {
+        summary["contract_address"] = receipt["contractAddress"].clone();
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_a_receipt() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert!(is_tx_hash(&hash));
+        assert!(!is_tx_hash("0xabc"));
+
+        let receipt = json!({
+            "status": "0x1",
+            "blockNumber": "0x10",
+            "from": "0x1111111111111111111111111111111111111111",
+            "to": "0x2222222222222222222222222222222222222222",
+            "gasUsed": "0x5208",
+            "contractAddress": null
+        });
+        assert_eq!(
+            describe_receipt(&hash, &receipt, Some(0x12)),
+            json!({
+                "tx_hash": hash,
+                "status": "confirmed",
+                "block_number": 16,
+                "confirmations": 3,
+                "from": "0x1111111111111111111111111111111111111111",
+                "to": "0x2222222222222222222222222222222222222222",
+                "gas_used": 21000
+            })
+        );
+
+        let reverted = json!({ "status": "0x0", "blockNumber": "0x10" });
+        assert_eq!(
+            describe_receipt(&hash, &reverted, None)["status"],
+            "reverted"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! In-memory session store.
+//!
+//! Transcripts live only as long as the process and are not shared between
+//! replicas. The store keeps at most [`MAX_SESSIONS`] sessions, evicting the
+//! least recently updated one.
+
+use super::{SessionStore, DEFAULT_TTL, MAX_MESSAGES};
+use crate::models::Message;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Maximum number of sessions kept.
+pub const MAX_SESSIONS: usize = 1024;
+
+#[derive(Debug)]
+struct Transcript {
+    messages: Vec<Message>,
+    updated: Instant,
+}
+
+/// Session transcripts kept in process memory. Clones share the same sessions.
+#[derive(Debug, Clone)]
+pub struct MemorySessionStore {
+    sessions: Arc<RwLock<HashMap<String, Transcript>>>,
+    ttl: Duration,
+}
+
+impl Default for MemorySessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl MemorySessionStore {
+    /// Creates an empty store whose sessions expire after `ttl` without updates.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            sessions: Arc::default(),
+            ttl,
+        }
+    }
+
+    fn is_live(&self, transcript: &Transcript) -> bool {
+        transcript.updated.elapsed() < self.ttl
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Vec<Message>>, String> {
+        let sessions = self.sessions.read().unwrap();
+        Ok(sessions
+            .get(session_id)
+            .filter(|t| self.is_live(t))
+            .map(|t| t.messages.clone()))
+    }
+
+    async fn append(&self, session_id: &str, messages: Vec<Message>) -> Result<(), String> {
+        let mut sessions = self.sessions.write().unwrap();
+        sessions.retain(|_, t| self.is_live(t));
+        if !sessions.contains_key(session_id) && sessions.len() >= MAX_SESSIONS {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, t)| t.updated)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        let transcript = sessions
+            .entry(session_id.to_string())
+            .or_insert_with(|| Transcript {
+                messages: Vec::new(),
+                updated: Instant::now(),
+            });
+        transcript.messages.extend(messages);
+        let excess = transcript.messages.len().saturating_sub(MAX_MESSAGES);
+        transcript.messages.drain(..excess);
+        transcript.updated = Instant::now();
+        Ok(())
+    }
+
+    async fn ids(&self) -> Result<Vec<String>, String> {
+        let sessions = self.sessions.read().unwrap();
+        let mut ids: Vec<_> = sessions
+            .iter()
+            .filter(|(_, t)| self.is_live(t))
+            .map(|(id, t)| (t.updated, id))
+            .collect();
+        ids.sort_by_key(|(updated, _)| std::cmp::Reverse(*updated));
+        Ok(ids.into_iter().map(|(_, id)| id.clone()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str) -> Message {
+        Message {
+            role: "user".to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_latest_messages() {
+        let store = MemorySessionStore::default();
+        let messages = (0..MAX_MESSAGES + 5).map(|i| message(&i.to_string()));
+        store.append("s1", messages.collect()).await.unwrap();
+
+        let transcript = store.get("s1").await.unwrap().unwrap();
+        assert_eq!(transcript.len(), MAX_MESSAGES);
+        assert_eq!(transcript[0].content, "5");
+        assert!(store.get("s2").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn sessions_expire_after_the_ttl() {
+        let store = MemorySessionStore::new(Duration::from_millis(20));
+        store.append("s1", vec![message("hi")]).await.unwrap();
+        assert_eq!(store.ids().await.unwrap(), ["s1"]);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(store.get("s1").await.unwrap().is_none());
+        assert!(store.ids().await.unwrap().is_empty());
+    }
+}
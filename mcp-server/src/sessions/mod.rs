@@ -0,0 +1,146 @@
+//! Conversation transcripts.
+//!
+//! Requests that carry a `session_id` have their user and assistant messages
+//! recorded under it. A later request in the same session that omits
+//! `conversation_history` continues from the recorded transcript, and the
+//! transcript can be read back as an MCP resource.
+//!
+//! Transcripts are kept by a [`SessionStore`]: in process memory by default,
+//! or in Redis so that every replica behind a load balancer sees the same
+//! sessions. Either way a session expires once it hasn't been updated for the
+//! configured TTL, and only its last [`MAX_MESSAGES`] messages are kept.
+//!
+//! Callers whose identity names a tenant have their sessions kept under
+//! `{tenant}/` in the store (see [`session_key`]), so each tenant only ever
+//! reads and lists its own transcripts. Session IDs therefore can't contain
+//! a `/`.
+//!
+//! # Environment Variables
+//!
+//! * `SESSION_STORE` - Optional. `memory` (default) or `redis`
+//! * `REDIS_URL` - Redis connection URL, required for the `redis` store
+//!   (e.g. `redis://127.0.0.1:6379/0`)
+//! * `REDIS_KEY_PREFIX` - Optional. Prefix of the Redis keys (default: `mcp:`)
+//! * `SESSION_TTL_SECS` - Optional. Idle time before a session expires
+//!   (default: 86400)
+
+pub mod memory;
+pub mod redis;
+
+use crate::models::Message;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use self::redis::RedisSessionStore;
+pub use memory::MemorySessionStore;
+
+/// Maximum number of messages kept per session.
+pub const MAX_MESSAGES: usize = 200;
+
+/// Idle time after which a session expires when `SESSION_TTL_SECS` is unset.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Separates the tenant from the session ID in store keys.
+pub const TENANT_SEPARATOR: char = '/';
+
+/// The store key of `session_id` for a caller of `tenant`.
+///
+/// Fails if the ID contains the [`TENANT_SEPARATOR`].
+///
+/// ```
+/// # use mcp_server::sessions::session_key;
+/// assert_eq!(session_key(Some("acme"), "s1").unwrap(), "acme/s1");
+/// assert_eq!(session_key(None, "s1").unwrap(), "s1");
+/// assert!(session_key(None, "acme/s1").is_err());
+/// ```
+pub fn session_key(tenant: Option<&str>, session_id: &str) -> Result<String, String> {
+    if session_id.contains(TENANT_SEPARATOR) {
+        return Err(format!(
+            "session_id must not contain '{}'",
+            TENANT_SEPARATOR
+        ));
+    }
+    Ok(match tenant {
+        Some(tenant) => format!("{}{}{}", tenant, TENANT_SEPARATOR, session_id),
+        None => session_id.to_string(),
+    })
+}
+
+/// The session IDs a caller of `tenant` sees among the store `keys`, in order.
+///
+/// ```
+/// # use mcp_server::sessions::tenant_session_ids;
+/// let keys = vec!["acme/s1".to_string(), "s2".to_string(), "globex/s3".to_string()];
+/// assert_eq!(tenant_session_ids(Some("acme"), keys.clone()), ["s1"]);
+/// assert_eq!(tenant_session_ids(None, keys), ["s2"]);
+/// ```
+pub fn tenant_session_ids(tenant: Option<&str>, keys: Vec<String>) -> Vec<String> {
+    keys.into_iter()
+        .filter_map(|key| {
+            let session_id = match tenant {
+                Some(tenant) => key
+                    .strip_prefix(tenant)?
+                    .strip_prefix(TENANT_SEPARATOR)?
+                    .to_string(),
+                None => key,
+            };
+            (!session_id.contains(TENANT_SEPARATOR)).then_some(session_id)
+        })
+        .collect()
+}
+
+/// A place to keep session transcripts.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Short, stable name, e.g. `memory`.
+    fn name(&self) -> &'static str;
+
+    /// The recorded messages of a session, oldest first, or `None` if there
+    /// is no such session or it expired.
+    async fn get(&self, session_id: &str) -> Result<Option<Vec<Message>>, String>;
+
+    /// Appends messages to a session, creating it if needed, and restarts its TTL.
+    async fn append(&self, session_id: &str, messages: Vec<Message>) -> Result<(), String>;
+
+    /// IDs of the live sessions, most recently updated first.
+    async fn ids(&self) -> Result<Vec<String>, String>;
+
+    /// Checks that the store can be reached.
+    async fn ping(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Creates the session store selected by `SESSION_STORE`.
+///
+/// Fails on an unknown store name, an invalid TTL, or if Redis is selected
+/// but cannot be reached.
+pub async fn from_env() -> Result<Arc<dyn SessionStore>, String> {
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let ttl = match var("SESSION_TTL_SECS") {
+        Some(secs) => match secs.parse::<u64>() {
+            Ok(secs) if secs > 0 => Duration::from_secs(secs),
+            _ => {
+                return Err(format!(
+                    "SESSION_TTL_SECS must be a positive integer, got {}",
+                    secs
+                ))
+            }
+        },
+        None => DEFAULT_TTL,
+    };
+    match var("SESSION_STORE").as_deref().unwrap_or("memory") {
+        "memory" => Ok(Arc::new(MemorySessionStore::new(ttl))),
+        "redis" => {
+            let url = var("REDIS_URL").ok_or("REDIS_URL must be set when SESSION_STORE=redis")?;
+            let prefix = var("REDIS_KEY_PREFIX").unwrap_or_else(|| "mcp:".to_string());
+            let store = RedisSessionStore::connect(&url, prefix, ttl).await?;
+            Ok(Arc::new(store))
+        }
+        other => Err(format!(
+            "SESSION_STORE must be memory or redis, got {}",
+            other
+        )),
+    }
+}
@@ -0,0 +1,139 @@
+//! Redis session store.
+//!
+//! Each transcript is a Redis list of JSON messages at `{prefix}session:{id}`
+//! whose TTL is restarted on every append, so Redis expires idle sessions by
+//! itself. A sorted set at `{prefix}sessions`, scored by last update time,
+//! lists the live sessions; entries older than the TTL are pruned from it as
+//! sessions are written or listed.
+
+use super::{SessionStore, MAX_MESSAGES};
+use crate::models::Message;
+use ::redis::aio::ConnectionManager;
+use ::redis::AsyncCommands;
+use async_trait::async_trait;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Session transcripts kept in Redis, shared by every replica using the same
+/// server and key prefix.
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    conn: ConnectionManager,
+    prefix: String,
+    ttl: Duration,
+}
+
+impl RedisSessionStore {
+    /// Connects to the Redis server at `url`.
+    ///
+    /// The connection is re-established automatically if it drops later.
+    pub async fn connect(url: &str, prefix: String, ttl: Duration) -> Result<Self, String> {
+        let client = ::redis::Client::open(url).map_err(|e| format!("Invalid REDIS_URL: {}", e))?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+        Ok(Self { conn, prefix, ttl })
+    }
+
+    fn session_key(&self, session_id: &str) -> String {
+        format!("{}session:{}", self.prefix, session_id)
+    }
+
+    fn index_key(&self) -> String {
+        format!("{}sessions", self.prefix)
+    }
+
+    /// Index scores at or below this belong to expired sessions.
+    fn expired_before(&self, now_ms: u64) -> u64 {
+        now_ms.saturating_sub(self.ttl.as_millis() as u64)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn redis_error(e: ::redis::RedisError) -> String {
+    format!("Redis session store error: {}", e)
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Vec<Message>>, String> {
+        let mut conn = self.conn.clone();
+        let raw: Vec<String> = conn
+            .lrange(self.session_key(session_id), 0, -1)
+            .await
+            .map_err(redis_error)?;
+        if raw.is_empty() {
+            return Ok(None);
+        }
+        raw.iter()
+            .map(|json| {
+                serde_json::from_str(json)
+                    .map_err(|e| format!("Corrupt message in session {}: {}", session_id, e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
+    async fn append(&self, session_id: &str, messages: Vec<Message>) -> Result<(), String> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let key = self.session_key(session_id);
+        let index = self.index_key();
+        let ttl_ms = self.ttl.as_millis() as i64;
+        let now = now_ms();
+        let encoded: Vec<String> = messages
+            .iter()
+            .map(|m| serde_json::to_string(m).unwrap())
+            .collect();
+
+        let mut conn = self.conn.clone();
+        ::redis::pipe()
+            .atomic()
+            .rpush(&key, encoded)
+            .ignore()
+            .ltrim(&key, -(MAX_MESSAGES as isize), -1)
+            .ignore()
+            .pexpire(&key, ttl_ms)
+            .ignore()
+            .zadd(&index, session_id, now)
+            .ignore()
+            .zrembyscore(&index, "-inf", self.expired_before(now))
+            .ignore()
+            .pexpire(&index, ttl_ms)
+            .ignore()
+            .query_async::<()>(&mut conn)
+            .await
+            .map_err(redis_error)
+    }
+
+    async fn ids(&self) -> Result<Vec<String>, String> {
+        let index = self.index_key();
+        let mut conn = self.conn.clone();
+        let (ids,): (Vec<String>,) = ::redis::pipe()
+            .atomic()
+            .zrembyscore(&index, "-inf", self.expired_before(now_ms()))
+            .ignore()
+            .zrevrange(&index, 0, -1)
+            .query_async(&mut conn)
+            .await
+            .map_err(redis_error)?;
+        Ok(ids)
+    }
+    async fn ping(&self) -> Result<(), String> {
+        let mut conn = self.conn.clone();
+        ::redis::cmd("PING")
+            .query_async::<()>(&mut conn)
+            .await
+            .map_err(redis_error)
+    }
+}
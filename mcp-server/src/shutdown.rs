@@ -0,0 +1,152 @@
+//! Graceful shutdown on `SIGTERM` and `SIGINT`.
+//!
+//! On the first signal the server stops accepting connections, then waits
+//! for the requests it is answering, the background jobs (including their
+//! callbacks) and the queued error reports to finish. Whatever is still
+//! running when the grace period ends is abandoned and the process exits; a
+//! second signal exits at once.
+//!
+//! Session transcripts, agents and usage counters need no flushing: the
+//! Redis and SQLite stores are written through on every request, and the
+//! in-memory ones are lost on exit either way.
+//!
+//! # Environment Variables
+//!
+//! * `SHUTDOWN_GRACE_SECS` - Optional. How long to wait for running work
+//!   after a signal (default: 30)
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Grace period used when `SHUTDOWN_GRACE_SECS` is unset.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(30);
+
+/// The grace period set by `SHUTDOWN_GRACE_SECS`.
+pub fn grace_from_env() -> Duration {
+    std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map_or(DEFAULT_GRACE, Duration::from_secs)
+}
+
+/// Whether shutdown has begun, shared by everything that stops on it.
+///
+/// Cheap to clone; clones are triggered together.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            tx: Arc::new(watch::channel(false).0),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Creates a handle that hasn't been triggered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begins shutting down.
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Whether shutdown has begun.
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Resolves once shutdown has begun.
+    pub fn triggered(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut rx = self.tx.subscribe();
+        async move {
+            let _ = rx.wait_for(|triggered| *triggered).await;
+        }
+    }
+
+    /// Triggers shutdown on the first `SIGTERM` or `SIGINT` and exits the
+    /// process on the second.
+    pub fn spawn_signal_listener(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            signal().await;
+            tracing::info!("🛑 Shutting down, finishing running requests");
+            shutdown.trigger();
+            signal().await;
+            tracing::warn!("Second signal received, exiting now");
+            std::process::exit(1);
+        });
+    }
+}
+
+/// Resolves on the next `SIGTERM` or `SIGINT`.
+async fn signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Cannot listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terms) => {
+                terms.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Runs `drain`, giving up on it after `grace`.
+///
+/// Returns whether `drain` finished in time.
+pub async fn drain_within(grace: Duration, drain: impl Future<Output = ()>) -> bool {
+    match tokio::time::timeout(grace, drain).await {
+        Ok(()) => true,
+        Err(_) => {
+            tracing::warn!(
+                "Shutdown grace period of {}s elapsed, abandoning running work",
+                grace.as_secs()
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn clones_are_triggered_together() {
+        let shutdown = Shutdown::new();
+        let waiting = shutdown.triggered();
+        assert!(!shutdown.is_triggered());
+        shutdown.clone().trigger();
+        assert!(shutdown.is_triggered());
+        waiting.await;
+
+        let finished = drain_within(Duration::from_millis(10), std::future::pending()).await;
+        assert!(!finished);
+    }
+}
@@ -0,0 +1,676 @@
+//! The `synthesize_speech` method: agent replies read aloud, completing the
+//! voice loop of the Voice Specialist (`agent_003`).
+//!
+//! ```json
+//! { "jsonrpc": "2.0", "id": 1, "method": "synthesize_speech",
+//!   "params": { "text": "Gas is the fee you pay to run a transaction.", "voice": "nova" } }
+//! ```
+//!
+//! answers with the audio, base64:
+//!
+//! ```json
+//! { "audio": "SUQzBAAAAAAAI1RTU0UAAAA...", "mime_type": "audio/mpeg",
+//!   "metadata": { "provider": "openai", "model": "tts-1", "voice": "nova",
+//!                 "characters": 45, "bytes": 38912, "processing_time_ms": 812 } }
+//! ```
+//!
+//! With `"return_url": true` the audio is kept in memory instead, and the
+//! result has a `url` serving it from `GET /audio/{id}` until `expires_at`.
+//! The ID is random, so anyone holding the URL can fetch the audio without
+//! a token, like a presigned URL.
+//!
+//! Texts are at most [`MAX_SPEECH_CHARS`] characters, and calls count
+//! towards the caller's [quotas](crate::quota).
+//!
+//! A speech-to-text backend can be configured the same way with the `STT_*`
+//! variables, for [`process_audio`](crate::voice), which transcribes a
+//! recording, asks an agent and reads the reply aloud in one call.
+//!
+//! # Environment Variables
+//!
+//! * `TTS_PROVIDER` - Optional. `openai` for OpenAI or another server with
+//!   its `/audio/speech` API, such as Groq, or `azure` for an Azure OpenAI
+//!   deployment of a speech model; without it, `synthesize_speech` fails
+//! * `TTS_API_URL` - Optional. Base URL of the `openai` API (default:
+//!   [`openai::DEFAULT_API_URL`]), e.g. `https://api.groq.com/openai/v1`
+//! * `TTS_API_KEY` - Bearer token of the `openai` API; required with it
+//! * `TTS_MODEL` - Optional. Speech model of the `openai` API (default:
+//!   [`openai::DEFAULT_MODEL`])
+//! * `TTS_DEPLOYMENT` - Deployment of the speech model, required with
+//!   `azure`, which uses `AZURE_OPENAI_ENDPOINT`, `AZURE_OPENAI_API_KEY` and
+//!   `AZURE_OPENAI_API_VERSION` like the [Azure backend](crate::providers::azure)
+//! * `TTS_VOICE` - Optional. Voice used when a call names none (default:
+//!   [`DEFAULT_VOICE`])
+//! * `TTS_URL_TTL_SECS` - Optional. How long returned URLs work (default:
+//!   [`DEFAULT_URL_TTL`])
+//! * `TTS_PUBLIC_URL` - Optional. URL clients reach the server at, such as
+//!   `https://valet.example.com`, making returned URLs absolute; they are
+//!   paths like `/audio/{id}` without it
+//! * `STT_PROVIDER`, `STT_API_URL`, `STT_API_KEY`, `STT_MODEL`,
+//!   `STT_DEPLOYMENT` - Optional. The speech-to-text backend, like their
+//!   `TTS_*` counterparts, using the `/audio/transcriptions` API; `STT_MODEL`
+//!   defaults to [`openai::DEFAULT_TRANSCRIPTION_MODEL`]
+
+pub mod openai;
+
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Longest text read aloud, in characters.
+pub const MAX_SPEECH_CHARS: usize = 4096;
+
+/// Voice used when neither the call nor `TTS_VOICE` names one.
+pub const DEFAULT_VOICE: &str = "alloy";
+
+/// How long returned URLs work when `TTS_URL_TTL_SECS` is unset.
+pub const DEFAULT_URL_TTL: Duration = Duration::from_secs(300);
+
+/// Most audio clips kept for URLs at once; the oldest go first.
+const MAX_CLIPS: usize = 256;
+
+/// Encoding of the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    /// MP3
+    #[default]
+    Mp3,
+    /// Opus in Ogg, for streaming and low latency
+    Opus,
+    /// AAC
+    Aac,
+    /// FLAC, lossless
+    Flac,
+    /// WAV, uncompressed
+    Wav,
+}
+
+impl AudioFormat {
+    /// The name of the format in the `/audio/speech` API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Opus => "opus",
+            Self::Aac => "aac",
+            Self::Flac => "flac",
+            Self::Wav => "wav",
+        }
+    }
+
+    /// The MIME type of audio in this format.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Mp3 => "audio/mpeg",
+            Self::Opus => "audio/ogg",
+            Self::Aac => "audio/aac",
+            Self::Flac => "audio/flac",
+            Self::Wav => "audio/wav",
+        }
+    }
+}
+
+/// What to read aloud, and how.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeechRequest {
+    /// Text to read
+    pub text: String,
+    /// Voice to read it in
+    pub voice: String,
+    /// Encoding of the audio
+    pub format: AudioFormat,
+    /// Speaking speed, 1 being normal
+    pub speed: Option<f32>,
+}
+
+/// Audio arriving in chunks as it is synthesized.
+pub type AudioStream = BoxStream<'static, Result<Vec<u8>, ServerError>>;
+
+/// A text-to-speech backend.
+#[async_trait]
+pub trait SpeechProvider: Send + Sync {
+    /// Short, stable name, e.g. `openai`.
+    fn name(&self) -> &'static str;
+
+    /// The model or deployment reading the text.
+    fn model(&self) -> &str;
+
+    /// The audio of `request`, encoded as it asks.
+    async fn synthesize(&self, request: &SpeechRequest) -> Result<Vec<u8>, ServerError>;
+
+    /// The audio of `request` as the backend produces it. Fails if the
+    /// backend refuses the request; errors while reading come from the
+    /// stream.
+    ///
+    /// Backends that can't stream return the whole audio as one chunk.
+    async fn synthesize_stream(&self, request: &SpeechRequest) -> Result<AudioStream, ServerError> {
+        let audio = self.synthesize(request).await?;
+        Ok(Box::pin(futures_util::stream::once(
+            async move { Ok(audio) },
+        )))
+    }
+}
+
+/// A recording to transcribe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptionRequest {
+    /// The encoded audio
+    pub audio: Vec<u8>,
+    /// Its MIME type, one of [`INPUT_TYPES`]
+    pub mime_type: String,
+    /// ISO-639-1 code of the spoken language, detected when unset
+    pub language: Option<String>,
+}
+
+/// MIME types of recordings that can be transcribed, with the file
+/// extension the backends expect for each.
+pub const INPUT_TYPES: &[(&str, &str)] = &[
+    ("audio/wav", "wav"),
+    ("audio/mpeg", "mp3"),
+    ("audio/ogg", "ogg"),
+    ("audio/webm", "webm"),
+    ("audio/flac", "flac"),
+    ("audio/mp4", "m4a"),
+];
+
+/// The MIME type of a recording, from its first bytes, if it is one of
+/// [`INPUT_TYPES`].
+pub fn detect_input_type(audio: &[u8]) -> Option<&'static str> {
+    let mime_type = match audio {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => "audio/wav",
+        [b'I', b'D', b'3', ..] => "audio/mpeg",
+        [0xff, second, ..] if second & 0xe0 == 0xe0 => "audio/mpeg",
+        [b'O', b'g', b'g', b'S', ..] => "audio/ogg",
+        [0x1a, 0x45, 0xdf, 0xa3, ..] => "audio/webm",
+        [b'f', b'L', b'a', b'C', ..] => "audio/flac",
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => "audio/mp4",
+        _ => return None,
+    };
+    Some(mime_type)
+}
+
+/// A speech-to-text backend.
+#[async_trait]
+pub trait TranscriptionProvider: Send + Sync {
+    /// Short, stable name, e.g. `openai`.
+    fn name(&self) -> &'static str;
+
+    /// The model or deployment transcribing the audio.
+    fn model(&self) -> &str;
+
+    /// The text spoken in `request`'s recording.
+    async fn transcribe(&self, request: &TranscriptionRequest) -> Result<String, ServerError>;
+}
+
+/// Audio kept for a URL, complete or still being synthesized.
+#[derive(Default)]
+struct ClipBuffer {
+    state: Mutex<ClipState>,
+    changed: Notify,
+}
+
+#[derive(Default)]
+struct ClipState {
+    audio: Vec<u8>,
+    done: bool,
+    failed: bool,
+}
+
+impl ClipBuffer {
+    fn update(&self, update: impl FnOnce(&mut ClipState)) {
+        update(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()));
+        self.changed.notify_waiters();
+    }
+}
+
+/// An audio clip kept for a URL.
+struct Clip {
+    buffer: Arc<ClipBuffer>,
+    format: AudioFormat,
+    expires: Instant,
+}
+
+/// Adds the audio of a clip as it is synthesized; the clip is complete once
+/// this is dropped.
+pub struct ClipWriter {
+    buffer: Arc<ClipBuffer>,
+}
+
+impl ClipWriter {
+    /// Appends `chunk` to the clip, waking its readers.
+    pub fn write(&self, chunk: &[u8]) {
+        self.buffer
+            .update(|state| state.audio.extend_from_slice(chunk));
+    }
+
+    /// Marks the clip as broken, so readers get an error after the audio
+    /// written so far.
+    pub fn fail(self) {
+        self.buffer.update(|state| state.failed = true);
+    }
+}
+
+impl Drop for ClipWriter {
+    fn drop(&mut self) {
+        self.buffer.update(|state| state.done = true);
+    }
+}
+
+/// The configured text-to-speech and speech-to-text backends, and the audio
+/// kept for URLs. Cheap to clone; disabled by default.
+#[derive(Clone)]
+pub struct Speech {
+    synthesizer: Option<Arc<dyn SpeechProvider>>,
+    transcriber: Option<Arc<dyn TranscriptionProvider>>,
+    voice: String,
+    url_ttl: Duration,
+    public_url: Option<String>,
+    clips: Arc<Mutex<HashMap<String, Clip>>>,
+}
+
+impl Default for Speech {
+    fn default() -> Self {
+        Self {
+            synthesizer: None,
+            transcriber: None,
+            voice: DEFAULT_VOICE.to_string(),
+            url_ttl: DEFAULT_URL_TTL,
+            public_url: None,
+            clips: Default::default(),
+        }
+    }
+}
+
+impl Speech {
+    /// Speech synthesized by `provider`.
+    pub fn new(provider: Arc<dyn SpeechProvider>) -> Self {
+        Self {
+            synthesizer: Some(provider),
+            ..Default::default()
+        }
+    }
+
+    /// Transcribes recordings with `provider`.
+    pub fn with_transcriber(mut self, provider: Arc<dyn TranscriptionProvider>) -> Self {
+        self.transcriber = Some(provider);
+        self
+    }
+
+    /// Reads texts in `voice` when a call names none.
+    pub fn with_voice(mut self, voice: impl Into<String>) -> Self {
+        self.voice = voice.into();
+        self
+    }
+
+    /// Keeps audio for URLs for `ttl`.
+    pub fn with_url_ttl(mut self, ttl: Duration) -> Self {
+        self.url_ttl = ttl;
+        self
+    }
+
+    /// Returns URLs under `public_url` instead of paths.
+    pub fn with_public_url(mut self, public_url: impl Into<String>) -> Self {
+        self.public_url = Some(public_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Reads the configuration from the `TTS_*` and `STT_*` variables.
+    pub fn from_env(client: &HttpClient) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let mut speech = Self::default();
+        if let Some(provider) = backend_from_env(client, "TTS", openai::DEFAULT_MODEL)? {
+            speech.synthesizer = Some(Arc::new(provider));
+        }
+        if let Some(provider) =
+            backend_from_env(client, "STT", openai::DEFAULT_TRANSCRIPTION_MODEL)?
+        {
+            speech.transcriber = Some(Arc::new(provider));
+        }
+        if let Some(voice) = var("TTS_VOICE") {
+            speech = speech.with_voice(voice);
+        }
+        if let Some(ttl) = var("TTS_URL_TTL_SECS") {
+            let secs = ttl
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or("TTS_URL_TTL_SECS must be a positive number of seconds")?;
+            speech = speech.with_url_ttl(Duration::from_secs(secs));
+        }
+        if let Some(public_url) = var("TTS_PUBLIC_URL") {
+            speech = speech.with_public_url(public_url);
+        }
+        Ok(speech)
+    }
+
+    /// The text-to-speech backend, if one is configured.
+    pub fn synthesizer(&self) -> Option<&Arc<dyn SpeechProvider>> {
+        self.synthesizer.as_ref()
+    }
+
+    /// The speech-to-text backend, if one is configured.
+    pub fn transcriber(&self) -> Option<&Arc<dyn TranscriptionProvider>> {
+        self.transcriber.as_ref()
+    }
+
+    /// The voice used when a call names none.
+    pub fn default_voice(&self) -> &str {
+        &self.voice
+    }
+
+    /// Keeps `audio` for the URL returned, with the time it stops working.
+    pub fn keep(&self, audio: Vec<u8>, format: AudioFormat) -> (String, DateTime<Utc>) {
+        let (url, expires_at, writer) = self.keep_streaming(format);
+        writer.write(&audio);
+        (url, expires_at)
+    }
+
+    /// Keeps audio still being synthesized for the URL returned, with the
+    /// time it stops working and the writer adding the audio. Readers get
+    /// the audio as it is written.
+    pub fn keep_streaming(&self, format: AudioFormat) -> (String, DateTime<Utc>, ClipWriter) {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let buffer = Arc::new(ClipBuffer::default());
+        let now = Instant::now();
+        let mut clips = self.clips.lock().unwrap_or_else(|e| e.into_inner());
+        clips.retain(|_, clip| clip.expires > now);
+        if clips.len() >= MAX_CLIPS {
+            let oldest = clips
+                .iter()
+                .min_by_key(|(_, clip)| clip.expires)
+                .map(|(id, _)| id.clone());
+            clips.remove(&oldest.expect("MAX_CLIPS is positive"));
+        }
+        clips.insert(
+            id.clone(),
+            Clip {
+                buffer: buffer.clone(),
+                format,
+                expires: now + self.url_ttl,
+            },
+        );
+        let path = format!("/audio/{}", id);
+        let url = match &self.public_url {
+            Some(public_url) => format!("{}{}", public_url, path),
+            None => path,
+        };
+        let expires_at = Utc::now() + self.url_ttl;
+        (url, expires_at, ClipWriter { buffer })
+    }
+
+    /// The audio kept under `id`, unless it expired. The stream ends once
+    /// the whole clip was read, waiting for audio still being synthesized.
+    pub fn clip(&self, id: &str) -> Option<(AudioStream, AudioFormat)> {
+        let clips = self.clips.lock().unwrap_or_else(|e| e.into_inner());
+        let clip = clips.get(id).filter(|clip| clip.expires > Instant::now())?;
+        let stream =
+            futures_util::stream::unfold((clip.buffer.clone(), 0), |(buffer, read)| async move {
+                if read == usize::MAX {
+                    return None;
+                }
+                loop {
+                    let changed = buffer.clone();
+                    let notified = changed.changed.notified();
+                    {
+                        let state = buffer.state.lock().unwrap_or_else(|e| e.into_inner());
+                        if read < state.audio.len() {
+                            let chunk = state.audio[read..].to_vec();
+                            let read = state.audio.len();
+                            drop(state);
+                            return Some((Ok(chunk), (buffer, read)));
+                        }
+                        if state.failed {
+                            drop(state);
+                            let error = ServerError::provider("speech synthesis failed");
+                            // Past the end, so the next poll ends the stream
+                            return Some((Err(error), (buffer, usize::MAX)));
+                        }
+                        if state.done {
+                            return None;
+                        }
+                    }
+                    notified.await;
+                }
+            });
+        Some((Box::pin(stream), clip.format))
+    }
+}
+
+/// A speech or transcription backend built from the `{prefix}_*`
+/// variables, if `{prefix}_PROVIDER` is set.
+fn backend_from_env(
+    client: &HttpClient,
+    prefix: &str,
+    default_model: &str,
+) -> Result<Option<openai::OpenAiSpeech>, String> {
+    let var = |name: &str| {
+        std::env::var(format!("{}_{}", prefix, name))
+            .ok()
+            .filter(|v| !v.is_empty())
+    };
+    let provider = match var("PROVIDER").as_deref() {
+        None => return Ok(None),
+        Some("openai") => {
+            let api_key = var("API_KEY").ok_or_else(|| {
+                format!("{0}_API_KEY must be set when {0}_PROVIDER=openai", prefix)
+            })?;
+            let mut provider =
+                openai::OpenAiSpeech::new(client.clone(), api_key).with_model(default_model);
+            if let Some(url) = var("API_URL") {
+                provider = provider.with_api_url(url);
+            }
+            if let Some(model) = var("MODEL") {
+                provider = provider.with_model(model);
+            }
+            provider
+        }
+        Some("azure") => {
+            let deployment = var("DEPLOYMENT").ok_or_else(|| {
+                format!("{0}_DEPLOYMENT must be set when {0}_PROVIDER=azure", prefix)
+            })?;
+            let azure = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+            let (Some(endpoint), Some(api_key)) = (
+                azure("AZURE_OPENAI_ENDPOINT"),
+                azure("AZURE_OPENAI_API_KEY"),
+            ) else {
+                return Err(format!(
+                    "AZURE_OPENAI_ENDPOINT and AZURE_OPENAI_API_KEY must be set when \
+                     {}_PROVIDER=azure",
+                    prefix
+                ));
+            };
+            let api_version = azure("AZURE_OPENAI_API_VERSION")
+                .unwrap_or_else(|| crate::providers::azure::DEFAULT_API_VERSION.to_string());
+            openai::OpenAiSpeech::azure(
+                client.clone(),
+                &endpoint,
+                api_key,
+                deployment,
+                &api_version,
+            )
+        }
+        Some(other) => {
+            return Err(format!(
+                "{}_PROVIDER must be openai or azure, got {}",
+                prefix, other
+            ))
+        }
+    };
+    Ok(Some(provider))
+}
+
+/// Checks a requested voice and speaking speed.
+pub(crate) fn validate_voice(voice: Option<&str>, speed: Option<f32>) -> Result<(), String> {
+    if let Some(voice) = voice {
+        let valid = (1..=64).contains(&voice.len())
+            && voice
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(format!(
+                "voice {} must be 1-64 letters, digits, '-', '_' or '.'",
+                voice
+            ));
+        }
+    }
+    if speed.is_some_and(|speed| !(0.25..=4.0).contains(&speed)) {
+        return Err("speed must be between 0.25 and 4".to_string());
+    }
+    Ok(())
+}
+
+/// Parameters for the synthesize_speech JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SynthesizeSpeechParams {
+    /// Text to read aloud, usually an agent's `reply_text`
+    pub text: String,
+    /// Optional voice, such as `alloy` or `nova`; the configured default
+    /// otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+    /// Encoding of the audio (default: mp3)
+    #[serde(default)]
+    pub format: AudioFormat,
+    /// Optional speaking speed, from 0.25 to 4, 1 being normal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Whether to return a temporary URL instead of the audio
+    #[serde(default)]
+    pub return_url: bool,
+}
+
+impl SynthesizeSpeechParams {
+    /// Checks the text, voice and speed.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.text.trim().is_empty() {
+            return Err("text must not be empty".to_string());
+        }
+        let chars = self.text.chars().count();
+        if chars > MAX_SPEECH_CHARS {
+            return Err(format!(
+                "text has {} characters; at most {} can be read aloud",
+                chars, MAX_SPEECH_CHARS
+            ));
+        }
+        validate_voice(self.voice.as_deref(), self.speed)
+    }
+}
+
+/// Result of the synthesize_speech JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SynthesizeSpeechResult {
+    /// Base64 of the audio, unless a URL was asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+    /// URL serving the audio until `expires_at`, if asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// When `url` stops working (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// MIME type of the audio
+    pub mime_type: String,
+    /// How the audio was made
+    pub metadata: SpeechMetadata,
+}
+
+/// Metadata about synthesized speech.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeechMetadata {
+    /// Backend that read the text
+    pub provider: String,
+    /// Model or deployment used
+    pub model: String,
+    /// Voice used
+    pub voice: String,
+    /// Characters read
+    pub characters: usize,
+    /// Size of the audio in bytes; unknown when it is streamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<usize>,
+    /// Processing time in milliseconds
+    pub processing_time_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures_util::StreamExt;
+
+    async fn read(speech: &Speech, id: &str) -> Option<Result<Vec<u8>, ServerError>> {
+        let (stream, _) = speech.clip(id)?;
+        let chunks: Vec<_> = stream.collect().await;
+        Some(
+            chunks
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .map(|c| c.concat()),
+        )
+    }
+
+    #[tokio::test]
+    async fn keeps_clips_until_they_expire() {
+        let speech = Speech::default().with_public_url("https://valet.example.com/");
+        let (url, _) = speech.keep(b"ID3".to_vec(), AudioFormat::Mp3);
+        let id = url
+            .strip_prefix("https://valet.example.com/audio/")
+            .unwrap();
+        assert_eq!(read(&speech, id).await.unwrap().unwrap(), b"ID3");
+        assert_eq!(speech.clip(id).unwrap().1.mime_type(), "audio/mpeg");
+        assert!(speech.clip("unknown").is_none());
+
+        let expired = Speech::default().with_url_ttl(Duration::ZERO);
+        let (url, _) = expired.keep(b"ID3".to_vec(), AudioFormat::Mp3);
+        assert!(expired.clip(url.trim_start_matches("/audio/")).is_none());
+
+        // Readers of a streamed clip wait for the rest of the audio
+        let (url, _, writer) = speech.keep_streaming(AudioFormat::Opus);
+        let id = url.rsplit('/').next().unwrap().to_string();
+        writer.write(b"Ogg");
+        let reader = tokio::spawn({
+            let speech = speech.clone();
+            async move { read(&speech, &id).await }
+        });
+        tokio::task::yield_now().await;
+        writer.write(b"S");
+        drop(writer);
+        assert_eq!(reader.await.unwrap().unwrap().unwrap(), b"OggS");
+        let (url, _, writer) = speech.keep_streaming(AudioFormat::Opus);
+        writer.fail();
+        assert!(read(&speech, url.rsplit('/').next().unwrap())
+            .await
+            .unwrap()
+            .is_err());
+
+        assert_eq!(detect_input_type(b"OggS\0\x02"), Some("audio/ogg"));
+        assert_eq!(
+            detect_input_type(b"RIFF\x24\0\0\0WAVEfmt "),
+            Some("audio/wav")
+        );
+        assert_eq!(detect_input_type(b"\xff\xfb\x90\x00"), Some("audio/mpeg"));
+        assert_eq!(detect_input_type(b"hello"), None);
+
+        let params = |text: &str, speed| SynthesizeSpeechParams {
+            text: text.into(),
+            voice: None,
+            format: AudioFormat::default(),
+            speed,
+            return_url: false,
+        };
+        assert!(params("Hello", Some(1.5)).validate().is_ok());
+        assert!(params(" ", None).validate().is_err());
+        assert!(params("Hello", Some(5.0)).validate().is_err());
+        assert!(params(&"a".repeat(MAX_SPEECH_CHARS + 1), None)
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Speech from OpenAI's `/audio/speech` API, and transcripts from its
+//! `/audio/transcriptions` API, which Groq and Azure OpenAI also serve.
+
+use super::{
+    AudioStream, SpeechProvider, SpeechRequest, TranscriptionProvider, TranscriptionRequest,
+    INPUT_TYPES,
+};
+use crate::error::ServerError;
+use crate::http_client::HttpClient;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Base URL of the API when `TTS_API_URL` or `STT_API_URL` is unset.
+pub const DEFAULT_API_URL: &str = "https://api.openai.com/v1";
+
+/// Model when `TTS_MODEL` is unset.
+pub const DEFAULT_MODEL: &str = "tts-1";
+
+/// Model when `STT_MODEL` is unset.
+pub const DEFAULT_TRANSCRIPTION_MODEL: &str = "whisper-1";
+
+/// How calls are authenticated.
+#[derive(Clone)]
+enum Auth {
+    /// `Authorization: Bearer`, as OpenAI and Groq expect
+    Bearer(String),
+    /// The `api-key` header of Azure OpenAI
+    ApiKey(String),
+}
+
+/// A backend speaking the `/audio/speech` and `/audio/transcriptions` APIs.
+#[derive(Clone)]
+pub struct OpenAiSpeech {
+    client: HttpClient,
+    name: &'static str,
+    base_url: String,
+    /// Query string of every call, e.g. Azure's `?api-version=`
+    query: String,
+    auth: Auth,
+    model: String,
+}
+
+/// Body of a transcription.
+#[derive(Deserialize)]
+struct Transcription {
+    text: String,
+}
+
+impl OpenAiSpeech {
+    /// OpenAI's API, authenticated with `api_key`.
+    pub fn new(client: HttpClient, api_key: impl Into<String>) -> Self {
+        Self {
+            client,
+            name: "openai",
+            base_url: DEFAULT_API_URL.to_string(),
+            query: String::new(),
+            auth: Auth::Bearer(api_key.into()),
+            model: DEFAULT_MODEL.to_string(),
+        }
+    }
+
+    /// The Azure OpenAI `deployment` of a speech or transcription model at
+    /// `endpoint`.
+    pub fn azure(
+        client: HttpClient,
+        endpoint: &str,
+        api_key: impl Into<String>,
+        deployment: impl Into<String>,
+        api_version: &str,
+    ) -> Self {
+        let deployment = deployment.into();
+        Self {
+            client,
+            name: "azure",
+            base_url: format!(
+                "{}/openai/deployments/{}",
+                endpoint.trim_end_matches('/'),
+                deployment
+            ),
+            query: format!("?api-version={}", api_version),
+            auth: Auth::ApiKey(api_key.into()),
+            model: deployment,
+        }
+    }
+
+    /// Calls the API under `api_url`, such as `https://api.groq.com/openai/v1`.
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.base_url = api_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Uses `model`, such as `gpt-4o-mini-tts`, `playai-tts` or
+    /// `whisper-large-v3`.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}{}", self.base_url, path, self.query)
+    }
+
+    /// Sends `http` to `url`, failing unless the backend accepts it. The
+    /// permit limiting calls to the host is returned with the response, for
+    /// as long as its body is read.
+    async fn send(
+        &self,
+        url: &str,
+        http: reqwest::RequestBuilder,
+        what: &str,
+    ) -> Result<(reqwest::Response, Option<crate::scheduler::Permit>), ServerError> {
+        let http = match &self.auth {
+            Auth::Bearer(key) => http.bearer_auth(key),
+            Auth::ApiKey(key) => http.header("api-key", key),
+        };
+        let permit = self.client.acquire(url).await;
+        let response = self.client.send_with_retry(http).await.map_err(|e| {
+            if e.is_timeout() {
+                ServerError::Timeout(format!("{} {} request timed out", self.name, what))
+            } else {
+                ServerError::provider(format!("{} {} request failed: {}", self.name, what, e))
+            }
+        })?;
+        let status = response.status();
+        if !status.is_success() {
+            let delay =
+                crate::http_client::retry_after(response.headers(), std::time::SystemTime::now());
+            let body = response.text().await.unwrap_or_default();
+            return Err(ServerError::status(self.name, status, &body).with_retry_after(delay));
+        }
+        Ok((response, permit))
+    }
+
+    async fn speech_response(
+        &self,
+        request: &SpeechRequest,
+    ) -> Result<(reqwest::Response, Option<crate::scheduler::Permit>), ServerError> {
+        let mut body = json!({
+            "model": self.model,
+            "input": request.text,
+            "voice": request.voice,
+            "response_format": request.format.as_str(),
+        });
+        if let Some(speed) = request.speed {
+            body["speed"] = json!(speed);
+        }
+        let url = self.url("audio/speech");
+        let http = self.client.post(&url).json(&body);
+        self.send(&url, http, "speech").await
+    }
+}
+
+#[async_trait]
+impl SpeechProvider for OpenAiSpeech {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn synthesize(&self, request: &SpeechRequest) -> Result<Vec<u8>, ServerError> {
+        let (response, _permit) = self.speech_response(request).await?;
+        let audio = response.bytes().await.map_err(|e| {
+            ServerError::provider(format!("Failed to read {} speech: {}", self.name, e))
+        })?;
+        if audio.is_empty() {
+            return Err(ServerError::provider(format!(
+                "{} returned no audio",
+                self.name
+            )));
+        }
+        Ok(audio.to_vec())
+    }
+
+    async fn synthesize_stream(&self, request: &SpeechRequest) -> Result<AudioStream, ServerError> {
+        let (response, permit) = self.speech_response(request).await?;
+        let name = self.name;
+        let stream = response.bytes_stream().map(move |chunk| {
+            // Held until the whole body was read
+            let _ = &permit;
+            chunk.map(|chunk| chunk.to_vec()).map_err(|e| {
+                ServerError::provider(format!("Failed to read {} speech: {}", name, e))
+            })
+        });
+        Ok(Box::pin(stream))
+    }
+}
+
+#[async_trait]
+impl TranscriptionProvider for OpenAiSpeech {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn transcribe(&self, request: &TranscriptionRequest) -> Result<String, ServerError> {
+        let extension = INPUT_TYPES
+            .iter()
+            .find(|(mime_type, _)| *mime_type == request.mime_type)
+            .map_or("bin", |(_, extension)| extension);
+        let boundary = format!("valet-{}", uuid::Uuid::new_v4().simple());
+        let mut body = Vec::with_capacity(request.audio.len() + 512);
+        let mut field = |name: &str, value: &str| {
+            body.extend_from_slice(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                    boundary, name, value
+                )
+                .as_bytes(),
+            );
+        };
+        field("model", &self.model);
+        field("response_format", "json");
+        if let Some(language) = &request.language {
+            field("language", language);
+        }
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"file\"; \
+                 filename=\"audio.{}\"\r\nContent-Type: {}\r\n\r\n",
+                boundary, extension, request.mime_type
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(&request.audio);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let url = self.url("audio/transcriptions");
+        let http = self
+            .client
+            .post(&url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body);
+        let (response, _permit) = self.send(&url, http, "transcription").await?;
+        let transcription: Transcription = response.json().await.map_err(|e| {
+            ServerError::provider(format!(
+                "Failed to parse {} transcription: {}",
+                self.name, e
+            ))
+        })?;
+        Ok(transcription.text.trim().to_string())
+    }
+}
@@ -0,0 +1,173 @@
+//! stdio transport.
+//!
+//! With `--stdio`, an MCP host launches the server as a child process and
+//! exchanges newline-delimited JSON-RPC messages over its stdin and stdout.
+//! Requests go through the same [`dispatch`] as the HTTP transport. They are
+//! handled concurrently, so responses may arrive out of order and are matched
+//! by `id`; notifications get no response. Logs go to stderr, since stdout
+//! carries the protocol.
+//!
+//! Only the process that launched the server can write to its stdin, so admin
+//! methods are allowed whenever `ADMIN_TOKEN` or `OIDC_ISSUER` is configured.
+
+use crate::auth::Access;
+use crate::correlation;
+use crate::handlers::{dispatch, rpc_error};
+use crate::i18n::{Locale, Message as Msg};
+use crate::models::{JsonRpcRequest, JsonRpcResponse};
+use crate::AppState;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// The locale named by the `LANG` environment variable, e.g. `de_DE.UTF-8`.
+pub fn locale_from_env() -> Locale {
+    std::env::var("LANG")
+        .ok()
+        .and_then(|lang| Locale::parse(&lang))
+        .unwrap_or_default()
+}
+
+/// Serves JSON-RPC over `input` and `output` until `input` is closed, then
+/// waits for in-flight requests to finish.
+pub async fn serve<R, W>(
+    state: Arc<AppState>,
+    input: R,
+    mut output: W,
+    locale: Locale,
+) -> std::io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<JsonRpcResponse<Value>>();
+    let mut lines = input.lines();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_request(&line, locale) {
+                    Ok(request) => {
+                        let state = state.clone();
+                        let access = if state.admin_token.is_some() || state.oidc.is_some() {
+                            Access::Admin
+                        } else {
+                            Access::None
+                        };
+                        let tx = tx.clone();
+                        let id = correlation::new_correlation_id();
+                        tokio::spawn(correlation::with_correlation_id(id, async move {
+                            if let Some(response) = dispatch(&state, request, locale, access).await {
+                                let _ = tx.send(response);
+                            }
+                        }));
+                    }
+                    Err(response) => write_response(&mut output, &response).await?,
+                }
+            }
+            Some(response) = rx.recv() => write_response(&mut output, &response).await?,
+        }
+    }
+
+    // Every spawned request holds a sender; the channel closes once they finish
+    drop(tx);
+    while let Some(response) = rx.recv().await {
+        write_response(&mut output, &response).await?;
+    }
+    Ok(())
+}
+
+/// Parses one line into a request, or the error response to send instead.
+fn parse_request(
+    line: &str,
+    locale: Locale,
+) -> Result<JsonRpcRequest<Value>, Box<JsonRpcResponse<Value>>> {
+    let value: Value = serde_json::from_str(line).map_err(|e| {
+        let message = Msg::ParseError.format(locale, e);
+        Box::new(rpc_error(Value::Null, -32700, message, None))
+    })?;
+    let id = value.get("id").cloned().unwrap_or_default();
+    serde_json::from_value(value).map_err(|e| {
+        let message = Msg::InvalidRequest.format(locale, e);
+        Box::new(rpc_error(id, -32600, message, None))
+    })
+}
+
+async fn write_response<W: AsyncWrite + Unpin>(
+    output: &mut W,
+    response: &JsonRpcResponse<Value>,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(response)?;
+    line.push(b'\n');
+    output.write_all(&line).await?;
+    output.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_shed::{LoadShedConfig, LoadShedder};
+    use crate::sessions::MemorySessionStore;
+
+    #[tokio::test]
+    async fn answers_requests_and_skips_notifications() {
+        let state = Arc::new(AppState {
+            http_client: reqwest::Client::new().into(),
+            providers: Default::default(),
+            agents: Default::default(),
+            admin_token: None,
+            jwt: None,
+            oidc: None,
+            reporter: Default::default(),
+            shedder: Arc::new(LoadShedder::new(LoadShedConfig::from_env())),
+            sessions: Arc::new(MemorySessionStore::default()),
+            history: Default::default(),
+            limits: Default::default(),
+            server_tools: Default::default(),
+            in_flight: Default::default(),
+            jobs: Default::default(),
+            cache: Default::default(),
+            coalescer: Default::default(),
+            quotas: Default::default(),
+            accounting: Default::default(),
+            guardrails: Default::default(),
+            plugins: Default::default(),
+            audit: None,
+            access_log: Default::default(),
+            metrics: Default::default(),
+            knowledge: Default::default(),
+            memories: Default::default(),
+            evals: Default::default(),
+            speech: Default::default(),
+        });
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","method":"initialize","params":{},"id":1}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            "\n\n",
+            "not json\n",
+            r#"{"jsonrpc":"2.0","method":"tools/list","id":"two"}"#,
+            "\n",
+        );
+        let mut output = Vec::new();
+        serve(state, input.as_bytes(), &mut output, Locale::En)
+            .await
+            .unwrap();
+
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3);
+        let by_id = |id: Value| responses.iter().find(|r| r["id"] == id).unwrap();
+        assert!(by_id(1.into())["result"]["protocolVersion"].is_string());
+        assert!(by_id("two".into())["result"]["tools"].is_array());
+        assert_eq!(by_id(Value::Null)["error"]["code"], -32700);
+    }
+}
@@ -0,0 +1,145 @@
+//! Style hints adjusting an agent's voice for one request.
+//!
+//! `process_text`, `submit_text` and `process_audio` accept an optional
+//! `style`, so a product can have the same agent sound different on each of
+//! its surfaces without defining new agents:
+//!
+//! ```json
+//! "style": { "tone": "friendly", "verbosity": "brief", "reading_level": "simple", "emoji": false }
+//! ```
+//!
+//! Every hint is optional and takes one of a few fixed values, each turned
+//! into a fixed sentence added after the agent's system prompt. Callers
+//! can't put text of their own in the prompt this way, and the agent's
+//! instructions still come first.
+
+use crate::models::Agent;
+use serde::{Deserialize, Serialize};
+
+/// Tone of a reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tone {
+    /// Plain and factual
+    Neutral,
+    /// Warm and approachable
+    Friendly,
+    /// Polite and professional
+    Formal,
+    /// Light-hearted, with a little humor
+    Playful,
+}
+
+/// Length of a reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// A few sentences at most
+    Brief,
+    /// As long as the question needs
+    Normal,
+    /// Thorough, with explanations and examples
+    Detailed,
+}
+
+/// Who a reply is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadingLevel {
+    /// Newcomers: short sentences, no jargon
+    Simple,
+    /// A general audience
+    General,
+    /// Specialists, who want the precise terms
+    Expert,
+}
+
+/// How an agent should reply to one request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Style {
+    /// Optional tone of the reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tone: Option<Tone>,
+    /// Optional length of the reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbosity: Option<Verbosity>,
+    /// Optional audience of the reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reading_level: Option<ReadingLevel>,
+    /// Optional whether the reply may use emoji
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<bool>,
+}
+
+impl Style {
+    /// The sentences telling a model to reply in this style, one per hint.
+    pub fn instructions(&self) -> Vec<&'static str> {
+        let tone = self.tone.map(|tone| match tone {
+            Tone::Neutral => "Use a neutral, factual tone.",
+            Tone::Friendly => "Use a warm, friendly tone.",
+            Tone::Formal => "Use a formal, professional tone.",
+            Tone::Playful => "Use a playful, light-hearted tone.",
+        });
+        let verbosity = self.verbosity.map(|verbosity| match verbosity {
+            Verbosity::Brief => "Keep replies brief: a few sentences at most.",
+            Verbosity::Normal => "Make replies as long as the question needs, and no longer.",
+            Verbosity::Detailed => "Give detailed replies, with explanations and examples.",
+        });
+        let reading_level = self.reading_level.map(|level| match level {
+            ReadingLevel::Simple => {
+                "Write for a newcomer: short sentences, everyday words, and no jargon."
+            }
+            ReadingLevel::General => "Write for a general audience, explaining technical terms.",
+            ReadingLevel::Expert => "Write for an expert, using precise technical terms.",
+        });
+        let emoji = self.emoji.map(|emoji| match emoji {
+            true => "Emoji are welcome where they fit.",
+            false => "Do not use emoji.",
+        });
+        [tone, verbosity, reading_level, emoji]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+/// The system prompt of `agent` followed by the instructions of `style`;
+/// `None` when `style` has no hints.
+pub fn with_style(agent: &Agent, style: &Style) -> Option<Agent> {
+    let instructions = style.instructions();
+    if instructions.is_empty() {
+        return None;
+    }
+    Some(Agent {
+        system_prompt: format!(
+            "{}\n\nStyle of your replies: {}",
+            agent.system_prompt,
+            instructions.join(" ")
+        ),
+        ..agent.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_one_fixed_sentence_per_hint() {
+        let agent = crate::agents::builtin_agents().remove(0);
+        assert!(with_style(&agent, &Style::default()).is_none());
+
+        let style: Style =
+            serde_json::from_str(r#"{"tone":"friendly","verbosity":"brief","emoji":false}"#)
+                .unwrap();
+        let styled = with_style(&agent, &style).unwrap();
+        assert!(styled.system_prompt.starts_with(&agent.system_prompt));
+        assert!(styled.system_prompt.ends_with(
+            "Style of your replies: Use a warm, friendly tone. Keep replies brief: a few \
+             sentences at most. Do not use emoji."
+        ));
+        assert!(serde_json::from_str::<Style>(r#"{"tone":"sarcastic"}"#).is_err());
+        assert!(serde_json::from_str::<Style>(r#"{"persona":"pirate"}"#).is_err());
+    }
+}
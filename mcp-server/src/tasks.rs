@@ -0,0 +1,450 @@
+//! Scheduled agent tasks.
+//!
+//! Tasks run an agent with a fixed prompt on a cron schedule, such as a
+//! daily market summary from the Web3 Expert. They are set in the
+//! `[[tasks]]` sections of the config file and change on reload:
+//!
+//! ```toml
+//! [[tasks]]
+//! id = "daily_market_summary"
+//! # minute hour day-of-month month day-of-week, in UTC
+//! schedule = "0 8 * * *"
+//! agent_id = "agent_002"
+//! user_text = "Summarize the last day of the crypto markets in five bullet points."
+//! # Optional: POST each result here, signed like job callbacks
+//! callback_url = "https://hooks.example.com/market-summary"
+//! # Optional: keep the results in this session's transcript
+//! session_id = "market-summaries"
+//! ```
+//!
+//! Schedule fields take `*`, numbers, ranges (`1-5`), lists (`0,30`) and
+//! steps (`*/15`); days of the week run from 0 (Sunday) to 6, with 7 also
+//! Sunday. As in cron, a day matching either the day of the month or the day
+//! of the week matches when both are restricted. `@hourly`, `@daily`,
+//! `@weekly` and `@monthly` are shorthands.
+//!
+//! Each run is submitted like a `submit_text` request from an anonymous
+//! caller: it becomes a [job](crate::jobs), queued with the others, whose
+//! result can be fetched with `get_job_result` until it expires and is
+//! POSTed to the task's `callback_url`, which needs `JOB_CALLBACK_SECRET`.
+//! With a `session_id`, every exchange is also appended to the session's
+//! transcript, readable as the `transcript://{session_id}` resource, and
+//! earlier results are the conversation history of the next run. Runs missed
+//! while the server was down are not made up for.
+//!
+//! Tasks only run while the server listens on HTTP, not over stdio or in the
+//! `bench` and `chat` subcommands.
+
+use crate::auth::Access;
+use crate::handlers;
+use crate::jobs::validate_callback_url;
+use crate::models::JsonRpcRequest;
+use crate::AppState;
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Notify;
+
+/// When a task runs: a five-field cron expression, in UTC.
+///
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use mcp_server::tasks::Schedule;
+///
+/// let weekdays: Schedule = "30 9 * * 1-5".parse().unwrap();
+/// let saturday = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+/// assert_eq!(
+///     weekdays.next_after(saturday),
+///     Some(Utc.with_ymd_and_hms(2024, 6, 3, 9, 30, 0).unwrap())
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month and of the week were both restricted
+    either_day: bool,
+}
+
+impl Schedule {
+    /// The first time after `time`, to the minute, that the schedule
+    /// matches, or `None` if it matches no time in the next five years.
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let minute = Duration::minutes(1);
+        let mut next = time.duration_trunc(minute).ok()? + minute;
+        let limit = time + Duration::days(5 * 366);
+        while next < limit {
+            let matches = |set: u64, value: u32| set & (1 << value) != 0;
+            if !matches(self.months, next.month()) {
+                let (year, month) = match next.month() {
+                    12 => (next.year() + 1, 1),
+                    month => (next.year(), month + 1),
+                };
+                next = next
+                    .with_day(1)?
+                    .with_year(year)?
+                    .with_month(month)?
+                    .duration_trunc(Duration::days(1))
+                    .ok()?;
+                continue;
+            }
+            let day = matches(self.days, next.day());
+            let weekday = matches(self.weekdays, next.weekday().num_days_from_sunday());
+            let day_matches = if self.either_day {
+                day || weekday
+            } else {
+                day && weekday
+            };
+            if !day_matches {
+                next = next.duration_trunc(Duration::days(1)).ok()? + Duration::days(1);
+                continue;
+            }
+            if !matches(self.hours, next.hour()) {
+                next = next.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+                continue;
+            }
+            if !matches(self.minutes, next.minute()) {
+                next += minute;
+                continue;
+            }
+            return Some(next);
+        }
+        None
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "schedule {} must have 5 fields: minute hour day month weekday",
+                expression
+            ));
+        };
+        let field = |name: &str, text: &str, min: u32, max: u32| {
+            parse_field(text, min, max)
+                .map_err(|e| format!("schedule {}: {} {}", expression, name, e))
+        };
+        let mut weekday_set = field("weekday", weekdays, 0, 7)?;
+        // 7 is Sunday too
+        if weekday_set & (1 << 7) != 0 {
+            weekday_set = (weekday_set | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            expression: expression.trim().to_string(),
+            minutes: field("minute", minutes, 0, 59)?,
+            hours: field("hour", hours, 0, 23)?,
+            days: field("day", days, 1, 31)?,
+            months: field("month", months, 1, 12)?,
+            weekdays: weekday_set,
+            either_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+        })
+    }
+}
+
+/// The values a cron field matches, as bits.
+fn parse_field(text: &str, min: u32, max: u32) -> Result<u64, String> {
+    let number = |n: &str| match n.parse::<u32>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n),
+        _ => Err(format!("{} is not a number from {} to {}", n, min, max)),
+    };
+    let mut set = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("has an invalid step in {}", part)),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // A single value with a step runs to the end of the range
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start > end {
+            return Err(format!("has an empty range {}", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = String;
+
+    fn try_from(expression: String) -> Result<Self, Self::Error> {
+        expression.parse()
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(schedule: Schedule) -> Self {
+        schedule.expression
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// A `[[tasks]]` section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledTask {
+    /// Unique name of the task, used in logs
+    pub id: String,
+    /// When it runs
+    pub schedule: Schedule,
+    /// ID of the agent to run
+    pub agent_id: String,
+    /// Prompt sent on every run
+    pub user_text: String,
+    /// Optional model overriding the agent's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Optional session keeping the results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Optional URL each result is POSTed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+}
+
+impl ScheduledTask {
+    /// Checks a config file's tasks: distinct IDs, a prompt and valid
+    /// callback URLs. Agents are looked up when the tasks run.
+    pub fn validate_all(tasks: &[Self]) -> Result<(), String> {
+        let mut ids = HashSet::new();
+        for task in tasks {
+            if task.id.trim().is_empty() {
+                return Err("a task must have an id".to_string());
+            }
+            if !ids.insert(&task.id) {
+                return Err(format!("task {} is defined twice", task.id));
+            }
+            if task.user_text.trim().is_empty() {
+                return Err(format!("task {} has no user_text", task.id));
+            }
+            if let Some(url) = &task.callback_url {
+                validate_callback_url(url).map_err(|e| format!("task {}: {}", task.id, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The `submit_text` request of one run.
+    fn request(&self) -> JsonRpcRequest<Value> {
+        JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "submit_text".to_string(),
+            params: Some(json!({
+                "agent_id": self.agent_id,
+                "user_text": self.user_text,
+                "model": self.model,
+                "session_id": self.session_id,
+                "callback_url": self.callback_url,
+            })),
+            id: Some(json!(format!("task:{}", self.id))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    tasks: RwLock<Vec<ScheduledTask>>,
+    changed: Notify,
+}
+
+/// The configured tasks.
+///
+/// Cheap to clone; clones share the tasks.
+#[derive(Debug, Clone, Default)]
+pub struct Tasks {
+    inner: Arc<Inner>,
+}
+
+impl Tasks {
+    /// Runs `tasks` once [spawned](Self::spawn).
+    pub fn new(tasks: Vec<ScheduledTask>) -> Self {
+        let scheduled = Self::default();
+        scheduled.reconfigure(tasks);
+        scheduled
+    }
+
+    /// Swaps in new tasks, e.g. after a config reload.
+    pub fn reconfigure(&self, tasks: Vec<ScheduledTask>) {
+        *self.inner.tasks.write().unwrap() = tasks;
+        self.inner.changed.notify_one();
+    }
+
+    /// The configured tasks.
+    pub fn list(&self) -> Vec<ScheduledTask> {
+        self.inner.tasks.read().unwrap().clone()
+    }
+
+    /// The next time after `time` that a task runs, and the tasks running
+    /// then.
+    pub fn due_after(&self, time: DateTime<Utc>) -> Option<(DateTime<Utc>, Vec<ScheduledTask>)> {
+        let tasks = self.inner.tasks.read().unwrap();
+        let next = tasks
+            .iter()
+            .filter_map(|task| task.schedule.next_after(time))
+            .min()?;
+        let due = tasks
+            .iter()
+            .filter(|task| task.schedule.next_after(time) == Some(next))
+            .cloned()
+            .collect();
+        Some((next, due))
+    }
+
+    /// Runs the tasks on their schedules against `state` until the server
+    /// stops.
+    pub fn spawn(&self, state: Arc<AppState>) {
+        let tasks = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = Utc::now();
+                let due = tasks.due_after(now);
+                let wait = due
+                    .as_ref()
+                    .map(|(at, _)| (*at - now).to_std().unwrap_or_default());
+                tokio::select! {
+                    // Start over with the new tasks
+                    _ = tasks.inner.changed.notified() => continue,
+                    _ = async {
+                        match wait {
+                            Some(wait) => tokio::time::sleep(wait).await,
+                            None => std::future::pending().await,
+                        }
+                    } => {}
+                }
+                for task in due.map(|(_, due)| due).unwrap_or_default() {
+                    run(&state, &task).await;
+                }
+            }
+        });
+    }
+}
+
+/// Submits one run of `task`, logging the job or the error.
+async fn run(state: &Arc<AppState>, task: &ScheduledTask) {
+    let response =
+        handlers::dispatch(state, task.request(), Default::default(), Access::None).await;
+    match response {
+        Some(response) => match (response.result, response.error) {
+            (Some(status), _) => tracing::info!(
+                "⏰ Task {} queued as job {}",
+                task.id,
+                status["job_id"].as_str().unwrap_or_default()
+            ),
+            (_, Some(error)) => {
+                tracing::warn!("⏰ Task {} failed to start: {}", task.id, error.message)
+            }
+            _ => {}
+        },
+        None => tracing::warn!("⏰ Task {} got no response", task.id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parses_schedules_and_finds_the_next_run() {
+        let at = |d, h, m| Utc.with_ymd_and_hms(2024, 1, d, h, m, 0).unwrap();
+        let schedule = |text: &str| text.parse::<Schedule>().unwrap();
+
+        // Monday 1 January 2024, 08:00:30
+        let now = at(1, 8, 0) + Duration::seconds(30);
+        assert_eq!(schedule("0 8 * * *").next_after(now), Some(at(2, 8, 0)));
+        assert_eq!(schedule("*/15 * * * *").next_after(now), Some(at(1, 8, 15)));
+        assert_eq!(schedule("@hourly").next_after(now), Some(at(1, 9, 0)));
+        assert_eq!(schedule("0 9 * * 0").next_after(now), Some(at(7, 9, 0)));
+        assert_eq!(schedule("0 9 * * 7").next_after(now), Some(at(7, 9, 0)));
+        // Either the 15th or a Friday
+        assert_eq!(schedule("0 0 15 * 5").next_after(now), Some(at(5, 0, 0)));
+        assert_eq!(
+            schedule("0 0 29 2 *").next_after(now),
+            Some(Utc.with_ymd_and_hms(2024, 2, 29, 0, 0, 0).unwrap())
+        );
+        assert_eq!(schedule("0 0 31 2 *").next_after(now), None);
+        for invalid in [
+            "0 8 * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{}", invalid);
+        }
+
+        let config = crate::config::FileConfig::parse(
+            r#"
+            [[tasks]]
+            id = "daily_market_summary"
+            schedule = "0 8 * * *"
+            agent_id = "agent_002"
+            user_text = "Summarize the markets."
+
+            [[tasks]]
+            id = "weekly_digest"
+            schedule = "0 8 * * 1"
+            agent_id = "agent_001"
+            user_text = "Write the weekly digest."
+            "#,
+        )
+        .unwrap();
+        let tasks = Tasks::new(config.tasks);
+        let (next, due) = tasks.due_after(at(7, 9, 0)).unwrap();
+        assert_eq!(next, at(8, 8, 0));
+        assert_eq!(due.len(), 2);
+        assert_eq!(
+            tasks.list()[0].request().params.unwrap()["agent_id"],
+            "agent_002"
+        );
+
+        let duplicate = r#"
+            [[tasks]]
+            id = "t"
+            schedule = "@daily"
+            agent_id = "agent_002"
+            user_text = "gm"
+
+            [[tasks]]
+            id = "t"
+            schedule = "@weekly"
+            agent_id = "agent_002"
+            user_text = "gm"
+            "#;
+        assert!(crate::config::FileConfig::parse(duplicate).is_err());
+    }
+}
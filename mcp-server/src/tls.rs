@@ -0,0 +1,474 @@
+//! HTTPS for the HTTP listener.
+//!
+//! With a certificate and key configured, the server speaks TLS 1.2 and 1.3
+//! (rustls) on its port instead of plain HTTP, so deployments without a
+//! TLS-terminating proxy don't send API keys and user text in cleartext.
+//! Handshakes run in the background, each limited to [`HANDSHAKE_TIMEOUT`],
+//! so slow or broken clients don't hold up the others.
+//!
+//! With a client CA bundle configured as well, the listener requires mutual
+//! TLS: clients must present a certificate issued by one of those CAs or the
+//! handshake fails. The certificate then identifies the caller, as a JWT
+//! would (see [`crate::auth`]), which suits servers reached only by other
+//! services on an internal mesh:
+//!
+//! - the subject is the first URI subject alternative name, such as a SPIFFE
+//!   ID (`spiffe://mesh.local/billing`), or else the subject's common name
+//! - the tenant is the subject's organization (`O`), if any
+//!
+//! # Environment Variables
+//!
+//! * `TLS_CERT_FILE` - PEM certificate chain, leaf first; enables HTTPS
+//!   together with `TLS_KEY_FILE`
+//! * `TLS_KEY_FILE` - PEM private key (PKCS#8, PKCS#1 or SEC1)
+//! * `TLS_CLIENT_CA_FILE` - Optional. PEM bundle of the CAs issuing client
+//!   certificates; requires one from every client
+//!
+//! They can also be given as `--tls-cert`, `--tls-key` and `--tls-client-ca`.
+//! The files are read
+//! at startup, so a renewed certificate takes a restart. Certificates are not
+//! obtained automatically: ACME clients such as certbot or cert-manager can
+//! keep the files up to date.
+
+use crate::auth::Identity;
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use simple_asn1::{oid, ASN1Block, ASN1Class, BigUint, OID};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// How long a client gets to complete the TLS handshake.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the certificate and key are read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert_file: PathBuf,
+    /// PEM private key
+    pub key_file: PathBuf,
+    /// PEM bundle of the CAs issuing client certificates, if they are required
+    pub client_ca_file: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// HTTPS settings from a certificate and key path, or `None` for plain
+    /// HTTP when neither is given. Fails if only one of them is.
+    pub fn from_paths(
+        cert_file: Option<PathBuf>,
+        key_file: Option<PathBuf>,
+    ) -> Result<Option<Self>, String> {
+        match (cert_file, key_file) {
+            (Some(cert_file), Some(key_file)) => Ok(Some(Self {
+                cert_file,
+                key_file,
+                client_ca_file: None,
+            })),
+            (None, None) => Ok(None),
+            (Some(_), None) => Err("TLS_CERT_FILE is set without TLS_KEY_FILE".into()),
+            (None, Some(_)) => Err("TLS_KEY_FILE is set without TLS_CERT_FILE".into()),
+        }
+    }
+
+    /// Requires client certificates issued by the CAs in `client_ca_file`,
+    /// if given.
+    pub fn with_client_ca(mut self, client_ca_file: Option<PathBuf>) -> Self {
+        self.client_ca_file = client_ca_file;
+        self
+    }
+
+    /// Reads the certificate and key into a rustls server configuration.
+    ///
+    /// Fails if a file can't be read or parsed, or the key doesn't match the
+    /// certificate.
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, String> {
+        let certs = read_certs(&self.cert_file)?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_file)
+            .map_err(|e| format!("Failed to read {}: {}", self.key_file.display(), e))?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Failed to configure TLS: {}", e))?;
+        let builder = match &self.client_ca_file {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(path)? {
+                    roots
+                        .add(cert)
+                        .map_err(|e| format!("Invalid CA in {}: {}", path.display(), e))?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()
+                        .map_err(|e| format!("Failed to configure client certificates: {}", e))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+}
+
+/// Reads every certificate in the PEM file at `path`.
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// Works out who a client certificate identifies: its first URI subject
+/// alternative name or else its common name, and its organization as the
+/// tenant.
+///
+/// Fails if the certificate can't be parsed or names neither.
+pub fn identity_from_cert(cert: &CertificateDer<'_>) -> Result<Identity, String> {
+    let invalid = || "Malformed client certificate".to_string();
+    let blocks =
+        simple_asn1::from_der(cert).map_err(|e| format!("Malformed client certificate: {}", e))?;
+    let Some(ASN1Block::Sequence(_, certificate)) = blocks.first() else {
+        return Err(invalid());
+    };
+    let Some(ASN1Block::Sequence(_, tbs)) = certificate.first() else {
+        return Err(invalid());
+    };
+    // Skip the version, then serial number, signature algorithm, issuer and
+    // validity come before the subject
+    let fields: Vec<&ASN1Block> = tbs
+        .iter()
+        .skip_while(|block| matches!(block, ASN1Block::Explicit(_, _, tag, _) if *tag == BigUint::from(0u8)))
+        .collect();
+    let Some(ASN1Block::Sequence(_, subject)) = fields.get(4) else {
+        return Err(invalid());
+    };
+    let uri = tbs.iter().find_map(|block| match block {
+        ASN1Block::Explicit(ASN1Class::ContextSpecific, _, tag, extensions)
+            if *tag == BigUint::from(3u8) =>
+        {
+            uri_san(extensions)
+        }
+        _ => None,
+    });
+    let subject_name = uri
+        .or_else(|| name_attribute(subject, oid!(2, 5, 4, 3)))
+        .ok_or("Client certificate names no subject")?;
+    Ok(Identity {
+        subject: subject_name,
+        tenant: name_attribute(subject, oid!(2, 5, 4, 10)),
+    })
+}
+
+/// The value of the first `attribute` (such as CN) in a distinguished name.
+fn name_attribute(name: &[ASN1Block], attribute: OID) -> Option<String> {
+    name.iter()
+        .filter_map(|rdn| match rdn {
+            ASN1Block::Set(_, pairs) => Some(pairs),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|pair| match pair {
+            ASN1Block::Sequence(_, pair) => match pair.as_slice() {
+                [ASN1Block::ObjectIdentifier(_, id), ASN1Block::UTF8String(_, value)
+                | ASN1Block::PrintableString(_, value)
+                | ASN1Block::IA5String(_, value)
+                | ASN1Block::TeletexString(_, value)]
+                    if *id == attribute =>
+                {
+                    Some(value.clone())
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+/// The first URI in the subject alternative name extension, if any.
+fn uri_san(extensions: &ASN1Block) -> Option<String> {
+    let ASN1Block::Sequence(_, extensions) = extensions else {
+        return None;
+    };
+    let value = extensions.iter().find_map(|extension| match extension {
+        ASN1Block::Sequence(_, fields) => match (fields.first(), fields.last()) {
+            (Some(ASN1Block::ObjectIdentifier(_, id)), Some(ASN1Block::OctetString(_, value)))
+                if *id == oid!(2, 5, 29, 17) =>
+            {
+                Some(value)
+            }
+            _ => None,
+        },
+        _ => None,
+    })?;
+    let names = simple_asn1::from_der(value).ok()?;
+    let Some(ASN1Block::Sequence(_, names)) = names.first() else {
+        return None;
+    };
+    // GeneralName uniformResourceIdentifier is [6] IA5String
+    names.iter().find_map(|name| match name {
+        ASN1Block::Unknown(ASN1Class::ContextSpecific, false, _, tag, uri)
+            if *tag == BigUint::from(6u8) =>
+        {
+            String::from_utf8(uri.clone()).ok()
+        }
+        _ => None,
+    })
+}
+
+/// Who is on the other end of a connection, from the client certificate it
+/// presented; `None` without mutual TLS.
+///
+/// Handed to requests as [`ConnectInfo`](axum::extract::ConnectInfo) when
+/// serving with `into_make_service_with_connect_info::<PeerIdentity>()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerIdentity(pub Option<Identity>);
+
+impl Connected<IncomingStream<'_, TlsListener>> for PeerIdentity {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        let (_, connection) = stream.io().get_ref();
+        let Some(cert) = connection
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+        else {
+            return Self(None);
+        };
+        match identity_from_cert(cert) {
+            Ok(identity) => Self(Some(identity)),
+            Err(e) => {
+                tracing::warn!(
+                    "Ignoring client certificate from {}: {}",
+                    stream.remote_addr(),
+                    e
+                );
+                Self(None)
+            }
+        }
+    }
+}
+
+/// Accepts TCP connections and hands out those that completed the TLS
+/// handshake.
+///
+/// Stops accepting once dropped, e.g. when the server shuts down.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    streams: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    /// Serves TLS with `config` on connections accepted by `listener`.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (tx, streams) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let accepted = tokio::select! {
+                    _ = tx.closed() => break,
+                    accepted = listener.accept() => accepted,
+                };
+                let (tcp, addr) = match accepted {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        // Typically out of file descriptors; give it a moment
+                        tracing::warn!("Failed to accept a connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            local_addr,
+            streams,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.streams.recv().await {
+            Some(connection) => connection,
+            // The accept loop only ends once this listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/tls")
+            .join(name)
+    }
+
+    #[test]
+    fn needs_both_the_certificate_and_the_key() {
+        assert_eq!(TlsConfig::from_paths(None, None), Ok(None));
+        assert!(TlsConfig::from_paths(Some(testdata("server.pem")), None).is_err());
+        let swapped = TlsConfig {
+            cert_file: testdata("server.key"),
+            key_file: testdata("server.pem"),
+            client_ca_file: None,
+        };
+        assert!(swapped.server_config().is_err());
+    }
+
+    #[tokio::test]
+    async fn hands_out_connections_after_the_handshake() {
+        let config =
+            TlsConfig::from_paths(Some(testdata("server.pem")), Some(testdata("server.key")))
+                .unwrap()
+                .unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut listener = TlsListener::new(tcp, config.server_config().unwrap()).unwrap();
+        let addr = Listener::local_addr(&listener).unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in read_certs(&testdata("ca.pem")).unwrap() {
+            roots.add(cert).unwrap();
+        }
+        let client = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client));
+        let client = tokio::spawn(async move {
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let name = ServerName::try_from("localhost").unwrap();
+            let mut stream = connector.connect(name, tcp).await.unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            stream.flush().await.unwrap();
+            stream
+        });
+
+        let (mut stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .expect("handshake completes");
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        drop(client.await.unwrap());
+    }
+
+    #[test]
+    fn identifies_clients_by_uri_or_common_name() {
+        let client = read_certs(&testdata("client.pem")).unwrap();
+        assert_eq!(
+            identity_from_cert(&client[0]).unwrap(),
+            Identity {
+                subject: "spiffe://mesh.local/billing".into(),
+                tenant: Some("acme".into()),
+            }
+        );
+        let server = read_certs(&testdata("server.pem")).unwrap();
+        assert_eq!(
+            identity_from_cert(&server[0]).unwrap(),
+            Identity {
+                subject: "localhost".into(),
+                tenant: None,
+            }
+        );
+        assert!(identity_from_cert(&CertificateDer::from(vec![0x30, 0x00])).is_err());
+    }
+
+    #[tokio::test]
+    async fn requires_a_client_certificate_from_the_ca() {
+        let config =
+            TlsConfig::from_paths(Some(testdata("server.pem")), Some(testdata("server.key")))
+                .unwrap()
+                .unwrap()
+                .with_client_ca(Some(testdata("ca.pem")));
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut listener = TlsListener::new(tcp, config.server_config().unwrap()).unwrap();
+        let addr = Listener::local_addr(&listener).unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in read_certs(&testdata("ca.pem")).unwrap() {
+            roots.add(cert).unwrap();
+        }
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+        let anonymous = builder.clone().with_no_client_auth();
+        let key = PrivateKeyDer::from_pem_file(testdata("client.key")).unwrap();
+        let authenticated = builder
+            .with_client_auth_cert(read_certs(&testdata("client.pem")).unwrap(), key)
+            .unwrap();
+
+        for client in [anonymous, authenticated] {
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(client));
+            tokio::spawn(async move {
+                let tcp = TcpStream::connect(addr).await.unwrap();
+                let name = ServerName::try_from("localhost").unwrap();
+                if let Ok(mut stream) = connector.connect(name, tcp).await {
+                    let _ = stream.write_all(b"ping").await;
+                    let _ = stream.flush().await;
+                    std::future::pending::<()>().await;
+                }
+            });
+        }
+
+        // Only the client with a certificate gets through
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .expect("handshake completes");
+        let cert = &stream.get_ref().1.peer_certificates().unwrap()[0];
+        assert_eq!(
+            identity_from_cert(cert).unwrap().tenant.as_deref(),
+            Some("acme")
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), listener.accept())
+                .await
+                .is_err()
+        );
+    }
+}
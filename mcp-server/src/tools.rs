@@ -0,0 +1,238 @@
+//! MCP tools.
+//!
+//! Every agent is exposed as a tool named after its ID that takes the user's
+//! text and an optional conversation history, next to a `list_agents` tool.
+//! Tool schemas are generated from the agent registry on each `tools/list`, so
+//! agents added by a config reload show up without reconnecting.
+
+use crate::agents::AgentRegistry;
+use crate::models::{Agent, GenerationParams, Message, ProcessTextParams};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Name of the tool that lists the available agents.
+pub const LIST_AGENTS_TOOL: &str = "list_agents";
+
+/// An MCP tool definition.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    /// Unique tool name, passed back in `tools/call`
+    pub name: String,
+    /// What the tool does, shown to the model
+    pub description: String,
+    /// JSON Schema of the tool's arguments
+    pub input_schema: Value,
+}
+
+/// Result of the `tools/list` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListToolsResult {
+    /// All available tools
+    pub tools: Vec<Tool>,
+}
+
+/// Params of the `tools/call` method.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CallToolParams {
+    /// Name of the tool to run
+    pub name: String,
+    /// Tool arguments matching its input schema
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+/// Arguments of an agent tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentToolArguments {
+    /// The user's input text
+    pub user_text: String,
+    /// Optional previous messages in the conversation
+    #[serde(default)]
+    pub conversation_history: Option<Vec<Message>>,
+    /// Optional session to record the exchange in
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Optional model overriding the agent's
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Optional sampling settings
+    #[serde(flatten)]
+    pub generation: GenerationParams,
+    /// Optional time the call may take in milliseconds, retries and
+    /// fallbacks included
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Keep nothing of the call, as for `process_text`
+    #[serde(default)]
+    pub no_store: bool,
+}
+
+/// A piece of tool output.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Content {
+    /// Plain text
+    Text { text: String },
+}
+
+/// Result of the `tools/call` method.
+///
+/// Failures while running a tool are reported here with `is_error` set, so
+/// the model can see them, rather than as JSON-RPC errors.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    /// Output shown to the model
+    pub content: Vec<Content>,
+    /// Whether the tool failed
+    #[serde(default)]
+    pub is_error: bool,
+    /// Machine-readable output, if the tool has any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+}
+
+impl CallToolResult {
+    /// A successful result with text and optional structured output.
+    pub fn text(text: impl Into<String>, structured_content: Option<Value>) -> Self {
+        Self {
+            content: vec![Content::Text { text: text.into() }],
+            is_error: false,
+            structured_content,
+        }
+    }
+
+    /// A failed result carrying an error message.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            content: vec![Content::Text {
+                text: message.into(),
+            }],
+            is_error: true,
+            structured_content: None,
+        }
+    }
+}
+
+/// The tools currently offered: `list_agents`, then one per agent.
+pub fn list_tools(agents: &AgentRegistry) -> Vec<Tool> {
+    let mut tools = Vec::with_capacity(agents.len() + 1);
+    tools.push(Tool {
+        name: LIST_AGENTS_TOOL.to_string(),
+        description: "Lists the available AI agents with their capabilities and models."
+            .to_string(),
+        input_schema: json!({ "type": "object", "properties": {} }),
+    });
+    tools.extend(agents.list().iter().map(|agent| agent_tool(agent)));
+    tools
+}
+
+/// The tool definition for an agent.
+pub fn agent_tool(agent: &Agent) -> Tool {
+    Tool {
+        name: agent.id.clone(),
+        description: format!(
+            "{}: {} (capabilities: {})",
+            agent.name,
+            agent.description,
+            agent.capabilities.join(", ")
+        ),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "user_text": {
+                    "type": "string",
+                    "description": "The message to send to the agent"
+                },
+                "conversation_history": {
+                    "type": "array",
+                    "description": "Previous messages in the conversation, oldest first",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "role": { "type": "string", "enum": ["user", "assistant"] },
+                            "content": { "type": "string" }
+                        },
+                        "required": ["role", "content"]
+                    }
+                },
+                "session_id": {
+                    "type": "string",
+                    "description": "Session to continue; its transcript is used when conversation_history is omitted"
+                },
+                "model": {
+                    "type": "string",
+                    "description": "Model to use instead of the agent's own, e.g. llama-3.1-8b-instant"
+                },
+                "temperature": {
+                    "type": "number",
+                    "minimum": 0,
+                    "maximum": 2,
+                    "description": "Sampling temperature (default: the agent's, or 0.7)"
+                },
+                "max_tokens": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": GenerationParams::MAX_MAX_TOKENS,
+                    "description": "Most tokens to generate (default: the agent's, or 1024)"
+                },
+                "top_p": {
+                    "type": "number",
+                    "exclusiveMinimum": 0,
+                    "maximum": 1,
+                    "description": "Nucleus sampling probability mass"
+                },
+                "frequency_penalty": {
+                    "type": "number",
+                    "minimum": -2,
+                    "maximum": 2,
+                    "description": "Penalty for repeating tokens already used"
+                },
+                "stop": {
+                    "type": "array",
+                    "items": { "type": "string", "minLength": 1 },
+                    "maxItems": GenerationParams::MAX_STOP_SEQUENCES,
+                    "description": "Sequences at which to cut off the reply, replacing the agent's own"
+                },
+                "seed": {
+                    "type": "integer",
+                    "minimum": i32::MIN,
+                    "maximum": i32::MAX,
+                    "description": "Seed for reproducible sampling, echoed in the metadata"
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": ProcessTextParams::MAX_TIMEOUT_MS,
+                    "description": "Milliseconds the call may take, retries and fallbacks included, before giving up"
+                },
+                "no_store": {
+                    "type": "boolean",
+                    "description": "Keep nothing of the call: no session transcript, remembered facts or cached reply"
+                }
+            },
+            "required": ["user_text"]
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_a_tool_per_agent() {
+        let agents = crate::agents::AgentStore::default().snapshot();
+        let tools = list_tools(&agents);
+        assert_eq!(tools.len(), agents.len() + 1);
+        assert_eq!(tools[0].name, LIST_AGENTS_TOOL);
+
+        let web3 = tools.iter().find(|t| t.name == "agent_002").unwrap();
+        assert!(web3.description.contains("web3"), "{}", web3.description);
+        assert_eq!(web3.input_schema["required"], json!(["user_text"]));
+
+        let encoded = serde_json::to_value(&tools[1]).unwrap();
+        assert!(encoded.get("inputSchema").is_some());
+    }
+}
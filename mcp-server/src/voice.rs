@@ -0,0 +1,257 @@
+//! The `process_audio` method: one round trip for voice clients, which
+//! transcribes a recording, asks an agent about it and reads the reply aloud.
+//!
+//! ```json
+//! { "jsonrpc": "2.0", "id": 1, "method": "process_audio",
+//!   "params": { "agent_id": "agent_003", "audio": "T2dnUwACAAAAAAAA...",
+//!               "session_id": "call-42", "voice": "nova", "format": "opus" } }
+//! ```
+//!
+//! answers with the transcript, the agent's reply and its audio, and the time
+//! each stage took:
+//!
+//! ```json
+//! { "transcript": "What is gas?", "agent_id": "agent_003",
+//!   "reply_text": "Gas is the fee you pay to run a transaction.",
+//!   "audio": "T2dnUwACAAAAAAAA...", "mime_type": "audio/ogg",
+//!   "metadata": {
+//!     "transcription": { "provider": "openai", "model": "whisper-1", "processing_time_ms": 640 },
+//!     "agent": { "provider": "groq", "model": "mixtral-8x7b-32768", "processing_time_ms": 910, ... },
+//!     "speech": { "provider": "openai", "model": "tts-1", "voice": "nova", "characters": 45,
+//!                 "bytes": 38912, "processing_time_ms": 812 },
+//!     "total_time_ms": 2371 } }
+//! ```
+//!
+//! The transcript goes to the agent like `process_text`'s `user_text`, so
+//! sessions, memories, quotas, the response cache and `no_store` apply as
+//! usual. The
+//! reply is read aloud with the [speech backend](crate::speech), up to its
+//! first [`MAX_SPEECH_CHARS`] characters; replies with only tool calls have no
+//! audio. `return_url` works as in `synthesize_speech`, and with `"stream":
+//! true` the URL comes back as soon as the speech backend starts answering,
+//! serving the audio while it is being synthesized. Audio served from a URL
+//! is kept until it expires, so `no_store` calls can't ask for one.
+//!
+//! Recordings are WAV, MP3, Ogg, WebM, FLAC or MP4 audio, detected from
+//! their content unless `mime_type` is given, of at most
+//! [`MAX_AUDIO_BYTES`]. Base64 makes requests a third larger than the
+//! recording, so `MAX_BODY_BYTES` usually needs raising for anything but
+//! short questions.
+
+use crate::models::{GenerationParams, Message, ProcessingMetadata, ToolCall};
+use crate::speech::{
+    validate_voice, AudioFormat, SpeechMetadata, TranscriptionRequest, INPUT_TYPES,
+    MAX_SPEECH_CHARS,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// Largest recording, in bytes once decoded, as OpenAI's transcription API
+/// accepts.
+pub const MAX_AUDIO_BYTES: usize = 25 * 1024 * 1024;
+
+/// Parameters for the process_audio JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessAudioParams {
+    /// ID of the agent to answer
+    pub agent_id: String,
+    /// Base64 of the recording
+    pub audio: String,
+    /// Optional MIME type of the recording, one of
+    /// [`INPUT_TYPES`](crate::speech::INPUT_TYPES); detected when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Optional ISO-639-1 code of the spoken language, e.g. `en`; detected
+    /// when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Optional conversation history for context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_history: Option<Vec<Message>>,
+    /// Optional session to record the exchange in, as in `process_text`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Optional model overriding the agent's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Optional sampling settings forwarded to the provider
+    #[serde(flatten)]
+    pub generation: GenerationParams,
+    /// Optional language to reply in, as in `process_text`; `auto` replies
+    /// in the language spoken
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_language: Option<String>,
+    /// Optional style hints, as in `process_text`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<crate::style::Style>,
+    /// Optional voice of the reply; the configured default otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+    /// Encoding of the reply's audio (default: mp3)
+    #[serde(default)]
+    pub format: AudioFormat,
+    /// Optional speaking speed, from 0.25 to 4, 1 being normal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Whether to return a temporary URL instead of the audio
+    #[serde(default)]
+    pub return_url: bool,
+    /// Whether to return the URL before the audio is complete; implies
+    /// `return_url`
+    #[serde(default)]
+    pub stream: bool,
+    /// Keep nothing of the call, as with `process_text`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_store: bool,
+}
+
+impl ProcessAudioParams {
+    /// Checks the voice, speed and language.
+    pub fn validate(&self) -> Result<(), String> {
+        validate_voice(self.voice.as_deref(), self.speed)?;
+        if self.no_store && (self.return_url || self.stream) {
+            return Err("no_store can't be combined with return_url or stream".to_string());
+        }
+        if let Some(language) = &self.language {
+            if !(2..=3).contains(&language.len())
+                || !language.chars().all(|c| c.is_ascii_lowercase())
+            {
+                return Err(format!(
+                    "language {} must be an ISO-639-1 code such as en",
+                    language
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the recording and works out its type.
+    pub fn recording(&self) -> Result<TranscriptionRequest, String> {
+        let data: String = self.audio.split_whitespace().collect();
+        let audio = base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|e| format!("audio is not valid base64: {}", e))?;
+        if audio.is_empty() {
+            return Err("audio must not be empty".to_string());
+        }
+        if audio.len() > MAX_AUDIO_BYTES {
+            return Err(format!(
+                "audio is limited to {} bytes, got {}",
+                MAX_AUDIO_BYTES,
+                audio.len()
+            ));
+        }
+        let mime_type = match self.mime_type.as_deref() {
+            Some(mime_type) => INPUT_TYPES
+                .iter()
+                .map(|(known, _)| *known)
+                .find(|known| *known == mime_type)
+                .ok_or_else(|| format!("unsupported audio type {}", mime_type))?,
+            None => crate::speech::detect_input_type(&audio).ok_or(
+                "audio is not WAV, MP3, Ogg, WebM, FLAC or MP4; give its mime_type if it is",
+            )?,
+        };
+        Ok(TranscriptionRequest {
+            audio,
+            mime_type: mime_type.to_string(),
+            language: self.language.clone(),
+        })
+    }
+}
+
+/// The part of `reply` read aloud: its first [`MAX_SPEECH_CHARS`] characters.
+pub fn spoken_text(reply: &str) -> &str {
+    let reply = reply.trim();
+    match reply.char_indices().nth(MAX_SPEECH_CHARS) {
+        Some((end, _)) => &reply[..end],
+        None => reply,
+    }
+}
+
+/// Result of the process_audio JSON-RPC method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessAudioResult {
+    /// What was heard in the recording
+    pub transcript: String,
+    /// ID of the agent that answered
+    pub agent_id: String,
+    /// Agent's text response
+    pub reply_text: String,
+    /// Function calls the model requested, as in `process_text`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Base64 of the reply's audio, unless a URL was asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+    /// URL serving the reply's audio until `expires_at`, if asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// When `url` stops working (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// MIME type of the audio; absent when the reply had nothing to read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// What each stage did and how long it took
+    pub metadata: ProcessAudioMetadata,
+}
+
+/// Metadata about each stage of a process_audio call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessAudioMetadata {
+    /// How the recording was transcribed
+    pub transcription: TranscriptionMetadata,
+    /// How the agent answered, as in `process_text`
+    pub agent: ProcessingMetadata,
+    /// How the reply was read aloud, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speech: Option<SpeechMetadata>,
+    /// Time the whole call took in milliseconds
+    pub total_time_ms: u64,
+}
+
+/// Metadata about a transcription.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionMetadata {
+    /// Backend that transcribed the recording
+    pub provider: String,
+    /// Model or deployment used
+    pub model: String,
+    /// Processing time in milliseconds
+    pub processing_time_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_and_checks_recordings() {
+        let encode = |audio: &[u8]| base64::engine::general_purpose::STANDARD.encode(audio);
+        let mut params: ProcessAudioParams = serde_json::from_value(serde_json::json!({
+            "agent_id": "agent_003",
+            "audio": encode(b"OggS\0\x02 voice"),
+            "language": "en",
+            "temperature": 0.2,
+        }))
+        .unwrap();
+        assert!(params.validate().is_ok());
+        assert_eq!(params.generation.temperature, Some(0.2));
+        let recording = params.recording().unwrap();
+        assert_eq!(recording.mime_type, "audio/ogg");
+        assert_eq!(recording.language.as_deref(), Some("en"));
+
+        params.audio = encode(b"plain text");
+        assert!(params.recording().is_err());
+        params.mime_type = Some("audio/webm".into());
+        assert_eq!(params.recording().unwrap().mime_type, "audio/webm");
+        params.mime_type = Some("video/avi".into());
+        assert!(params.recording().is_err());
+        params.language = Some("English".into());
+        assert!(params.validate().is_err());
+
+        let long = "a".repeat(MAX_SPEECH_CHARS + 1);
+        assert_eq!(spoken_text(&long).len(), MAX_SPEECH_CHARS);
+        assert_eq!(spoken_text(" Hi \n"), "Hi");
+    }
+}
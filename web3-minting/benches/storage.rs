@@ -0,0 +1,45 @@
+//! Benchmarks for metadata serialization and CID computation, the CPU-bound
+//! part of every `/mint` call.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use web3_minting::models::Metadata;
+use web3_minting::storage::compute_cid;
+
+fn sample_metadata(description_len: usize) -> Metadata {
+    Metadata {
+        name: "Voice note #42".to_string(),
+        description: Some("transcript ".repeat(description_len / 11 + 1)),
+        asset_url: Some("https://ipfs.io/ipfs/bafkreiexampleasset".to_string()),
+    }
+}
+
+fn bench_metadata(c: &mut Criterion) {
+    let mut group = c.benchmark_group("metadata");
+    for len in [256, 4096, 65536] {
+        let metadata = sample_metadata(len);
+        group.bench_with_input(BenchmarkId::new("serialize", len), &metadata, |b, m| {
+            b.iter(|| serde_json::to_vec(black_box(m)).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("serialize_and_cid", len),
+            &metadata,
+            |b, m| b.iter(|| compute_cid(&serde_json::to_vec(black_box(m)).unwrap())),
+        );
+    }
+    group.finish();
+}
+
+fn bench_cid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_cid");
+    for size in [1024, 64 * 1024, 1024 * 1024] {
+        let bytes = vec![0xabu8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &bytes, |b, bytes| {
+            b.iter(|| compute_cid(black_box(bytes)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_metadata, bench_cid);
+criterion_main!(benches);
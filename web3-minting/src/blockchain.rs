@@ -1,50 +1,272 @@
-use crate::models::MintResult;
+//! Minting on an EVM chain.
+//!
+//! With `BLOCKCHAIN_RPC` set, each mint calls `safeMint(to, uri)` on the
+//! ERC-721 contract at `CONTRACT_ADDRESS`, with the metadata URL as the token
+//! URI. The transaction is signed locally with `WALLET_PRIVATE_KEY`, sent with
+//! `eth_sendRawTransaction` and awaited until it is mined; the response
+//! carries its real hash and the token id from the contract's `Transfer`
+//! event. Mints without a `recipient` go to the signer's own address.
+//!
+//! Without `BLOCKCHAIN_RPC`, mints return random hashes and ids, for local
+//! development. Mints sent with `"chain": "solana"` go to
+//! [Solana](crate::solana) instead.
+//!
+//! # Environment Variables
+//!
+//! * `BLOCKCHAIN_RPC` - Optional. JSON-RPC endpoint of the chain
+//! * `CONTRACT_ADDRESS` - Required with `BLOCKCHAIN_RPC`. ERC-721 contract
+//!   whose `safeMint(address,string)` the signer may call
+//! * `WALLET_PRIVATE_KEY` - Required with `BLOCKCHAIN_RPC`. Hex key of the
+//!   signer, which pays for gas
+//! * `MINT_RECEIPT_TIMEOUT_SECS` - Optional. How long to wait for a sent
+//!   transaction to be mined (default: 120)
+
+use crate::config::ChainSettings;
+use crate::http_client::HttpClient;
+use crate::models::{Chain, MintResult};
+use crate::solana::SolanaMinter;
+use alloy::network::EthereumWallet;
+use alloy::primitives::Address;
+use alloy::providers::ProviderBuilder;
+use alloy::rpc::client::RpcClient;
+use alloy::signers::local::PrivateKeySigner;
+use alloy::transports::http::Http;
 use anyhow::{anyhow, Result};
-use reqwest::Client;
-use std::env;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
-/// Mint a token on-chain (or mock). Returns tx hash and optional token id.
-pub async fn mint_token(metadata_url: &str, recipient: &str) -> Result<MintResult> {
-    if let Ok(rpc) = env::var("BLOCKCHAIN_RPC") {
-        tracing::info!(rpc = %rpc, "calling configured blockchain RPC");
-        let client = Client::new();
-        // For simplicity we POST a JSON body {metadata_url, recipient}
-        let body = serde_json::json!({"metadata_url": metadata_url, "recipient": recipient});
-        let resp = client
-            .post(&rpc)
-            .json(&body)
-            .send()
+alloy::sol! {
+    #[sol(rpc)]
+    contract Erc721 {
+        function safeMint(address to, string uri) external;
+
+        event Transfer(address indexed from, address indexed to, uint256 indexed tokenId);
+    }
+}
+
+/// Held while a transaction is being sent, so concurrent mints from the same
+/// signer don't pick the same nonce.
+static SENDING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// The RPC accepted the mint but reported the transaction as reverted.
+#[derive(Debug, thiserror::Error)]
+#[error("transaction {tx_hash} reverted: {}", reason.as_deref().unwrap_or("no reason given"))]
+pub struct TransactionReverted {
+    pub tx_hash: String,
+    pub reason: Option<String>,
+}
+
+/// What an on-chain mint needs: the endpoint, the contract and the signer.
+#[derive(Debug, Clone)]
+pub struct EvmMinter {
+    rpc_url: reqwest::Url,
+    contract: Address,
+    signer: PrivateKeySigner,
+}
+
+impl EvmMinter {
+    /// Parses the chain settings; `None` when no RPC is configured, so mints
+    /// are mocked.
+    ///
+    /// Fails when the RPC is configured without a valid contract address or
+    /// signer key.
+    pub fn from_settings(settings: &ChainSettings) -> Result<Option<Self>> {
+        let Some(rpc_url) = &settings.rpc_url else {
+            return Ok(None);
+        };
+        let rpc_url = rpc_url
+            .parse()
+            .map_err(|e| anyhow!("BLOCKCHAIN_RPC {} is not a URL: {}", rpc_url, e))?;
+        let contract = settings
+            .contract_address
+            .as_deref()
+            .ok_or_else(|| anyhow!("CONTRACT_ADDRESS is required with BLOCKCHAIN_RPC"))?;
+        let contract = contract
+            .parse()
+            .map_err(|e| anyhow!("CONTRACT_ADDRESS {} is not an address: {}", contract, e))?;
+        let signer = settings
+            .signer_key
+            .as_deref()
+            .ok_or_else(|| anyhow!("WALLET_PRIVATE_KEY is required with BLOCKCHAIN_RPC"))?
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("WALLET_PRIVATE_KEY is not a valid private key"))?;
+        Ok(Some(Self {
+            rpc_url,
+            contract,
+            signer,
+        }))
+    }
+
+    /// Address the mints are sent from.
+    pub fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    /// Mints a token with `uri` to `recipient`, or to the signer, and waits
+    /// up to `settings.receipt_timeout` for it to be mined.
+    pub async fn mint(
+        &self,
+        client: &HttpClient,
+        settings: &ChainSettings,
+        uri: &str,
+        recipient: Option<&str>,
+    ) -> Result<MintResult> {
+        let to = match recipient {
+            Some(recipient) => recipient
+                .parse()
+                .map_err(|e| anyhow!("recipient {} is not an address: {}", recipient, e))?,
+            None => self.address(),
+        };
+        let transport = Http::with_client(client.deref().clone(), self.rpc_url.clone());
+        let provider = ProviderBuilder::new()
+            .wallet(EthereumWallet::from(self.signer.clone()))
+            .connect_client(RpcClient::new(transport, false));
+        let contract = Erc721::new(self.contract, &provider);
+
+        // The connection slot is only held while sending, not for the wait
+        // for the receipt, which can take minutes
+        let pending = {
+            let _permit = client.acquire(self.rpc_url.as_str()).await;
+            let _sending = SENDING.lock().await;
+            contract
+                .safeMint(to, uri.to_string())
+                .send()
+                .await
+                .map_err(|e| anyhow!("mint transaction failed: {}", e))?
+        };
+        let tx_hash = pending.tx_hash().to_string();
+        tracing::info!(tx_hash = %tx_hash, to = %to, "mint transaction sent");
+
+        let receipt = pending
+            .with_timeout(Some(settings.receipt_timeout))
+            .get_receipt()
             .await
-            .map_err(|e| anyhow!("rpc request failed: {}", e))?;
+            .map_err(|e| anyhow!("transaction {} was not mined: {}", tx_hash, e))?;
+        if !receipt.status() {
+            return Err(TransactionReverted {
+                tx_hash,
+                reason: None,
+            }
+            .into());
+        }
+        let token_id = receipt
+            .inner
+            .logs()
+            .iter()
+            .filter(|log| log.address() == self.contract)
+            .find_map(|log| log.log_decode::<Erc721::Transfer>().ok())
+            .map(|transfer| transfer.inner.data.tokenId.to_string());
+
+        Ok(MintResult {
+            tx_hash,
+            token_id,
+            mint_address: None,
+        })
+    }
+}
 
-        let status = resp.status();
-        if !status.is_success() {
-            let text = resp.text().await.unwrap_or_default();
-            return Err(anyhow!("rpc call failed: {} - {}", status, text));
+/// The minters of the chain settings in effect; `None` mocks a chain.
+#[derive(Debug, Clone, Default)]
+pub struct Minters {
+    pub evm: Option<EvmMinter>,
+    pub solana: Option<SolanaMinter>,
+}
+
+impl Minters {
+    /// Parses the signer key and reads the Solana keypair once, so mints
+    /// don't depend on them staying readable.
+    ///
+    /// Fails when either chain is configured but unusable.
+    pub fn from_settings(settings: &ChainSettings) -> Result<Self> {
+        Ok(Self {
+            evm: EvmMinter::from_settings(settings)?,
+            solana: SolanaMinter::from_settings(settings)?,
+        })
+    }
+}
+
+/// The current [`Minters`], shared between handlers and the reloader.
+#[derive(Debug, Clone, Default)]
+pub struct SharedMinters(Arc<RwLock<Arc<Minters>>>);
+
+impl SharedMinters {
+    /// The minters in effect right now.
+    pub fn current(&self) -> Arc<Minters> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replaces the minters for mints that start from now on.
+    pub fn replace(&self, minters: Minters) {
+        *self.0.write().unwrap() = Arc::new(minters);
+    }
+}
+
+/// Mint a token named `name` on `chain` with `minters` (or mock). Returns tx
+/// hash and optional token id, and the mint address on Solana.
+///
+/// Fails with [`TransactionReverted`] when an EVM transaction is mined but
+/// reverts.
+pub async fn mint_token(
+    client: &HttpClient,
+    settings: &ChainSettings,
+    minters: &Minters,
+    chain: Chain,
+    name: &str,
+    metadata_url: &str,
+    recipient: Option<&str>,
+) -> Result<MintResult> {
+    match chain {
+        Chain::Evm => {
+            if let Some(minter) = &minters.evm {
+                tracing::info!(rpc = %minter.rpc_url, contract = %minter.contract, "minting on-chain");
+                return minter.mint(client, settings, metadata_url, recipient).await;
+            }
+        }
+        Chain::Solana => {
+            if let Some(minter) = &minters.solana {
+                tracing::info!(payer = %minter.address(), "minting on solana");
+                return minter.mint(client, name, metadata_url, recipient).await;
+            }
         }
+    }
 
-        // Attempt to parse a tx hash and token id
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| anyhow!("failed to parse response: {}", e))?;
-        let tx_hash = json
-            .get("tx_hash")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| format!("0x{}", Uuid::new_v4().simple()));
-        let token_id = json
-            .get("token_id")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
-
-        Ok(MintResult { tx_hash, token_id })
-    } else {
-        // Mock path
-        let tx_hash = format!("0x{}", Uuid::new_v4().simple());
-        let token_id = Some(format!("{}", Uuid::new_v4().simple()));
-        tracing::warn!(tx_hash = %tx_hash, "BLOCKCHAIN_RPC not set - returning mock mint result");
-        Ok(MintResult { tx_hash, token_id })
+    // Mock path
+    let tx_hash = format!("0x{}", Uuid::new_v4().simple());
+    let token_id = Some(format!("{}", Uuid::new_v4().simple()));
+    let mint_address = token_id.clone().filter(|_| chain == Chain::Solana);
+    tracing::warn!(tx_hash = %tx_hash, ?chain, "no blockchain RPC configured - returning mock mint result");
+    Ok(MintResult {
+        tx_hash,
+        token_id,
+        mint_address,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpc_requires_contract_and_signer() {
+        let mut settings = ChainSettings::default();
+        assert!(EvmMinter::from_settings(&settings).unwrap().is_none());
+
+        settings.rpc_url = Some("http://127.0.0.1:8545".to_string());
+        assert!(EvmMinter::from_settings(&settings).is_err());
+        settings.contract_address = Some("0x5FbDB2315678afecb367f032d93F642f64180aa3".to_string());
+        assert!(EvmMinter::from_settings(&settings).is_err());
+        settings.signer_key = Some("not-a-key".to_string());
+        assert!(EvmMinter::from_settings(&settings).is_err());
+
+        // Anvil's first development account
+        settings.signer_key =
+            Some("0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80".to_string());
+        let minter = EvmMinter::from_settings(&settings).unwrap().unwrap();
+        assert_eq!(
+            minter.address().to_string(),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+        );
     }
 }
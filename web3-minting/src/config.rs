@@ -0,0 +1,520 @@
+//! Reloadable configuration.
+//!
+//! Chain, storage, load-shedding and error-reporting settings come from
+//! environment variables, optionally overridden by a TOML file named by
+//! `CONFIG_FILE`. Sending `SIGHUP` to the process, or calling
+//! `POST /admin/reload` with `Authorization: Bearer $ADMIN_TOKEN`, re-reads the
+//! file and swaps the new settings in without a restart. Handlers take a
+//! snapshot of the settings when a request starts, so in-flight mints and
+//! uploads finish against the endpoints they began with. An invalid file is
+//! rejected and the running settings are kept.
+//!
+//! # Example
+//!
+//! ```toml
+//! [chain]
+//! rpc_url = "https://sepolia.example.com"    # "" switches back to mock mints
+//! contract_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+//! receipt_timeout_secs = 120
+//! solana_rpc_url = "https://api.devnet.solana.com"
+//! solana_keypair = "/etc/web3-minting/payer.json"
+//!
+//! [storage]
+//! ipfs_url = "http://127.0.0.1:5001/api/v0/add"
+//! public_url = "https://mint.example.com"
+//! max_upload_bytes = 104857600
+//!
+//! [load_shed]
+//! max_in_flight = 64
+//!
+//! [error_reporting]
+//! webhook_url = "https://hooks.example.com/errors"
+//! ```
+//!
+//! The signer's `WALLET_PRIVATE_KEY` only comes from the environment, to keep
+//! it out of config files.
+
+use crate::blockchain::{Minters, SharedMinters};
+use crate::error_report::{ErrorReporter, ReportingConfig};
+use crate::load_shed::{LoadShedConfig, LoadShedder};
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Where mints are sent, see [`crate::blockchain`].
+#[derive(Clone)]
+pub struct ChainSettings {
+    /// Blockchain RPC endpoint (`BLOCKCHAIN_RPC`); `None` returns mock results
+    pub rpc_url: Option<String>,
+    /// ERC-721 contract minted from (`CONTRACT_ADDRESS`)
+    pub contract_address: Option<String>,
+    /// Hex key signing the mints (`WALLET_PRIVATE_KEY`)
+    pub signer_key: Option<String>,
+    /// How long to wait for a mint to be mined (`MINT_RECEIPT_TIMEOUT_SECS`,
+    /// default 120 s)
+    pub receipt_timeout: Duration,
+    /// Solana RPC endpoint (`SOLANA_RPC`); `None` returns mock results for
+    /// Solana mints
+    pub solana_rpc_url: Option<String>,
+    /// Keypair file of the Solana payer (`SOLANA_KEYPAIR`)
+    pub solana_keypair: Option<PathBuf>,
+}
+
+impl Default for ChainSettings {
+    fn default() -> Self {
+        Self {
+            rpc_url: None,
+            contract_address: None,
+            signer_key: None,
+            receipt_timeout: Duration::from_secs(120),
+            solana_rpc_url: None,
+            solana_keypair: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for ChainSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChainSettings")
+            .field("rpc_url", &self.rpc_url)
+            .field("contract_address", &self.contract_address)
+            .field(
+                "signer_key",
+                &self.signer_key.as_ref().map(|_| "[REDACTED]"),
+            )
+            .field("receipt_timeout", &self.receipt_timeout)
+            .field("solana_rpc_url", &self.solana_rpc_url)
+            .field("solana_keypair", &self.solana_keypair)
+            .finish()
+    }
+}
+
+/// Where metadata and assets are stored.
+#[derive(Debug, Clone)]
+pub struct StorageSettings {
+    /// IPFS add endpoint (`IPFS_URL`); `None` uses mock CIDs and local assets
+    pub ipfs_url: Option<String>,
+    /// Directory for locally stored assets (`ASSET_DIR`, default `assets`)
+    pub asset_dir: PathBuf,
+    /// Base URL this service is reached at, for links to locally stored assets
+    /// (`PUBLIC_URL`, default `http://localhost:8081`)
+    pub public_url: String,
+    /// Largest accepted asset upload (`MAX_UPLOAD_BYTES`, default 512 MiB)
+    pub max_upload_bytes: u64,
+    /// Write buffer for local asset storage (`UPLOAD_CHUNK_SIZE`, default 64 KiB)
+    pub upload_chunk_size: usize,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        Self {
+            ipfs_url: None,
+            asset_dir: PathBuf::from("assets"),
+            public_url: "http://localhost:8081".to_string(),
+            max_upload_bytes: 512 * 1024 * 1024,
+            upload_chunk_size: 64 * 1024,
+        }
+    }
+}
+
+/// The reloadable settings.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub chain: ChainSettings,
+    pub storage: StorageSettings,
+    pub load_shed: LoadShedConfig,
+    pub error_reporting: ReportingConfig,
+}
+
+/// Contents of the TOML config file. Every section and key is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub chain: ChainOverrides,
+    pub storage: StorageOverrides,
+    pub load_shed: LoadShedOverrides,
+    pub error_reporting: ReportingOverrides,
+}
+
+/// `[chain]` section. An empty `rpc_url` switches back to mock mints.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainOverrides {
+    pub rpc_url: Option<String>,
+    pub contract_address: Option<String>,
+    pub receipt_timeout_secs: Option<u64>,
+    pub solana_rpc_url: Option<String>,
+    pub solana_keypair: Option<PathBuf>,
+}
+
+/// `[storage]` section. An empty `ipfs_url` switches back to local storage.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageOverrides {
+    pub ipfs_url: Option<String>,
+    pub asset_dir: Option<PathBuf>,
+    pub public_url: Option<String>,
+    pub max_upload_bytes: Option<u64>,
+    pub upload_chunk_size: Option<usize>,
+}
+
+/// `[load_shed]` section, overriding the `LOAD_SHED_*` variables.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadShedOverrides {
+    pub max_in_flight: Option<usize>,
+    pub latency_ms: Option<u64>,
+    pub retry_after_secs: Option<u64>,
+}
+
+/// `[error_reporting]` section. An empty string turns a sink off.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportingOverrides {
+    pub sentry_dsn: Option<String>,
+    pub environment: Option<String>,
+    pub webhook_url: Option<String>,
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&v| v > 0)
+        .unwrap_or(default)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
+}
+
+impl Settings {
+    /// Settings from environment variables alone.
+    pub fn from_env() -> Self {
+        Self::resolve(FileConfig::default())
+    }
+
+    /// Loads settings from the environment, applying the file at `path` if given.
+    ///
+    /// Fails when the file can't be read.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let file = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+                toml::from_str(&text)
+                    .map_err(|e| anyhow!("invalid config {}: {}", path.display(), e))?
+            }
+            None => FileConfig::default(),
+        };
+        Ok(Self::resolve(file))
+    }
+
+    /// Applies `file` on top of the environment and defaults.
+    pub fn resolve(file: FileConfig) -> Self {
+        let var = |name: &str| std::env::var(name).ok();
+        let defaults = StorageSettings::default();
+
+        let chain = ChainSettings {
+            rpc_url: non_empty(file.chain.rpc_url.or_else(|| var("BLOCKCHAIN_RPC"))),
+            contract_address: non_empty(
+                file.chain
+                    .contract_address
+                    .or_else(|| var("CONTRACT_ADDRESS")),
+            ),
+            signer_key: non_empty(var("WALLET_PRIVATE_KEY")),
+            receipt_timeout: Duration::from_secs(
+                file.chain
+                    .receipt_timeout_secs
+                    .unwrap_or_else(|| env_u64("MINT_RECEIPT_TIMEOUT_SECS", 120)),
+            ),
+            solana_rpc_url: non_empty(file.chain.solana_rpc_url.or_else(|| var("SOLANA_RPC"))),
+            solana_keypair: file
+                .chain
+                .solana_keypair
+                .or_else(|| var("SOLANA_KEYPAIR").map(PathBuf::from))
+                .filter(|path| !path.as_os_str().is_empty()),
+        };
+        let storage = StorageSettings {
+            ipfs_url: non_empty(file.storage.ipfs_url.or_else(|| var("IPFS_URL"))),
+            asset_dir: file
+                .storage
+                .asset_dir
+                .or_else(|| var("ASSET_DIR").map(PathBuf::from))
+                .unwrap_or(defaults.asset_dir),
+            public_url: non_empty(file.storage.public_url.or_else(|| var("PUBLIC_URL")))
+                .unwrap_or(defaults.public_url),
+            max_upload_bytes: file
+                .storage
+                .max_upload_bytes
+                .unwrap_or_else(|| env_u64("MAX_UPLOAD_BYTES", defaults.max_upload_bytes)),
+            upload_chunk_size: file.storage.upload_chunk_size.unwrap_or_else(|| {
+                env_u64("UPLOAD_CHUNK_SIZE", defaults.upload_chunk_size as u64) as usize
+            }),
+        };
+
+        let env = LoadShedConfig::from_env();
+        let load_shed = LoadShedConfig {
+            max_in_flight: file.load_shed.max_in_flight.unwrap_or(env.max_in_flight),
+            latency_threshold: file
+                .load_shed
+                .latency_ms
+                .map(Duration::from_millis)
+                .unwrap_or(env.latency_threshold),
+            retry_after_secs: file
+                .load_shed
+                .retry_after_secs
+                .unwrap_or(env.retry_after_secs),
+        };
+
+        let reporting = file.error_reporting;
+        let error_reporting = ReportingConfig::new(
+            reporting
+                .sentry_dsn
+                .or_else(|| var("SENTRY_DSN"))
+                .as_deref(),
+            reporting.environment.or_else(|| var("SENTRY_ENVIRONMENT")),
+            reporting.webhook_url.or_else(|| var("ERROR_WEBHOOK_URL")),
+        );
+
+        Self {
+            chain,
+            storage,
+            load_shed,
+            error_reporting,
+        }
+    }
+}
+
+/// The current [`Settings`], shared between handlers and the reloader.
+#[derive(Debug, Clone)]
+pub struct SharedSettings(Arc<RwLock<Arc<Settings>>>);
+
+impl SharedSettings {
+    pub fn new(settings: Settings) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(settings))))
+    }
+
+    /// A snapshot of the settings in effect right now.
+    pub fn current(&self) -> Arc<Settings> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replaces the settings for requests that start from now on.
+    pub fn replace(&self, settings: Settings) {
+        *self.0.write().unwrap() = Arc::new(settings);
+    }
+}
+
+/// What a reload changed, returned by the admin endpoint.
+#[derive(Debug, Serialize)]
+pub struct ReloadSummary {
+    /// Whether mints go to a real RPC endpoint
+    pub chain_rpc: bool,
+    /// Whether Solana mints go to a real RPC endpoint
+    pub solana_rpc: bool,
+    /// Whether storage goes to IPFS
+    pub ipfs: bool,
+    /// In-flight request threshold now in effect
+    pub load_shed_max_in_flight: usize,
+    /// Whether any error-reporting sink is configured
+    pub error_reporting: bool,
+}
+
+/// Applies reloaded settings to the running service.
+#[derive(Clone)]
+pub struct Reloader {
+    path: Option<PathBuf>,
+    admin_token: Option<String>,
+    settings: SharedSettings,
+    minters: SharedMinters,
+    shedder: Arc<LoadShedder>,
+    reporter: ErrorReporter,
+}
+
+impl Reloader {
+    /// Creates a reloader for the given components.
+    ///
+    /// # Environment Variables
+    ///
+    /// * `CONFIG_FILE` - Optional. Path of the TOML config file to re-read
+    /// * `ADMIN_TOKEN` - Optional. Bearer token enabling `POST /admin/reload`
+    pub fn from_env(
+        settings: SharedSettings,
+        minters: SharedMinters,
+        shedder: Arc<LoadShedder>,
+        reporter: ErrorReporter,
+    ) -> Self {
+        Self {
+            path: std::env::var_os("CONFIG_FILE").map(PathBuf::from),
+            admin_token: non_empty(std::env::var("ADMIN_TOKEN").ok()),
+            settings,
+            minters,
+            shedder,
+            reporter,
+        }
+    }
+
+    /// The config file being watched, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Whether the admin reload endpoint should be served.
+    pub fn admin_enabled(&self) -> bool {
+        self.admin_token.is_some()
+    }
+
+    /// Swaps `settings` into the running components, with minters built
+    /// from its chain settings.
+    ///
+    /// Fails, changing nothing, when the chain settings don't make usable
+    /// [`Minters`].
+    pub fn apply(&self, settings: Settings) -> Result<ReloadSummary> {
+        let minters = Minters::from_settings(&settings.chain)?;
+        let summary = ReloadSummary {
+            chain_rpc: settings.chain.rpc_url.is_some(),
+            solana_rpc: settings.chain.solana_rpc_url.is_some(),
+            ipfs: settings.storage.ipfs_url.is_some(),
+            load_shed_max_in_flight: settings.load_shed.max_in_flight,
+            error_reporting: settings.error_reporting.has_sinks(),
+        };
+        self.shedder.set_config(settings.load_shed.clone());
+        self.reporter.reconfigure(settings.error_reporting.clone());
+        self.settings.replace(settings);
+        self.minters.replace(minters);
+        Ok(summary)
+    }
+
+    /// Re-reads the config file and applies it, keeping the running settings
+    /// if it is invalid.
+    pub fn reload(&self) -> Result<ReloadSummary> {
+        let settings = Settings::load(self.path())?;
+        let summary = self.apply(settings)?;
+        tracing::info!(?summary, "configuration reloaded");
+        Ok(summary)
+    }
+
+    /// Reloads the configuration every time the process receives `SIGHUP`.
+    #[cfg(unix)]
+    pub fn spawn_sighup_listener(self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot listen for SIGHUP, reload disabled");
+                return;
+            }
+        };
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                if let Err(e) = self.reload() {
+                    tracing::error!(error = %e, "configuration reload failed");
+                }
+            }
+        });
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(expected) = &self.admin_token else {
+            return false;
+        };
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // Compare without short-circuiting on the first differing byte
+        presented.len() == expected.len()
+            && presented
+                .bytes()
+                .zip(expected.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// `POST /admin/reload` - reloads the configuration on demand.
+///
+/// Requires `Authorization: Bearer $ADMIN_TOKEN`.
+pub async fn reload_handler(State(reloader): State<Reloader>, headers: HeaderMap) -> Response {
+    if !reloader.authorized(&headers) {
+        let body = serde_json::json!({ "error": "unauthorized" });
+        return (StatusCode::UNAUTHORIZED, Json(body)).into_response();
+    }
+    match reloader.reload() {
+        Ok(summary) => (StatusCode::OK, Json(summary)).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "configuration reload failed");
+            let body = serde_json::json!({ "error": e.to_string() });
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_overrides_environment() {
+        let file: FileConfig = toml::from_str(
+            r#"
+            [chain]
+            rpc_url = ""
+
+            [storage]
+            ipfs_url = "http://127.0.0.1:5001/api/v0/add"
+            max_upload_bytes = 1024
+            "#,
+        )
+        .unwrap();
+        let settings = Settings::resolve(file);
+
+        assert_eq!(settings.chain.rpc_url, None);
+        assert_eq!(
+            settings.storage.ipfs_url.as_deref(),
+            Some("http://127.0.0.1:5001/api/v0/add")
+        );
+        assert_eq!(settings.storage.max_upload_bytes, 1024);
+        assert_eq!(settings.storage.upload_chunk_size, 64 * 1024);
+    }
+
+    #[tokio::test]
+    async fn minters_are_built_on_apply_and_kept_on_failure() {
+        use solana_sdk::signature::{write_keypair_file, Keypair, Signer};
+
+        let settings = SharedSettings::new(Settings::from_env());
+        let minters = SharedMinters::default();
+        let shedder = Arc::new(LoadShedder::new(settings.current().load_shed.clone()));
+        let reporter = ErrorReporter::new("web3-minting", ReportingConfig::default());
+        let reloader = Reloader::from_env(settings, minters.clone(), shedder, reporter);
+
+        let payer = Keypair::new();
+        let path = std::env::temp_dir().join(format!("reload-{}.json", payer.pubkey()));
+        write_keypair_file(&payer, &path).unwrap();
+        let mut with_solana = Settings::from_env();
+        with_solana.chain.solana_rpc_url = Some("http://127.0.0.1:8899".to_string());
+        with_solana.chain.solana_keypair = Some(path.clone());
+        assert!(reloader.apply(with_solana).unwrap().solana_rpc);
+        // Mints keep the keypair read on apply even once the file is gone
+        std::fs::remove_file(&path).unwrap();
+        let current = minters.current();
+        assert_eq!(current.solana.as_ref().unwrap().address(), payer.pubkey());
+
+        let mut unreadable = Settings::from_env();
+        unreadable.chain.solana_rpc_url = Some("http://127.0.0.1:8899".to_string());
+        unreadable.chain.solana_keypair = Some(path);
+        assert!(reloader.apply(unreadable).is_err());
+        assert!(Arc::ptr_eq(&minters.current(), &current));
+    }
+}
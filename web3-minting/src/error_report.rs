@@ -0,0 +1,283 @@
+//! Optional error reporting to Sentry or a generic webhook.
+//!
+//! Panics, storage failures and failed or reverted mint transactions are
+//! forwarded as [`ErrorEvent`]s so operators hear about problems before users
+//! do. Delivery is best-effort and
+//! runs on a background task, so reporting never slows down or fails a request.
+//!
+//! # Environment Variables
+//!
+//! * `SENTRY_DSN` - Sentry project DSN; events go to its store endpoint
+//! * `SENTRY_ENVIRONMENT` - Environment name attached to events (default: production)
+//! * `ERROR_WEBHOOK_URL` - URL that receives every event as a JSON `POST`
+//!
+//! Reporting is disabled when neither `SENTRY_DSN` nor `ERROR_WEBHOOK_URL` is
+//! set. Both can be changed at runtime through a config reload.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// A failure worth telling an operator about.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorEvent {
+    /// Unique id of this event (32 hex digits)
+    pub event_id: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: f64,
+    /// Failure category, e.g. `panic` or `transaction_reverted`
+    pub kind: &'static str,
+    /// Human-readable description
+    pub message: String,
+    /// Id of the request that failed, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Additional structured context
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+}
+
+impl ErrorEvent {
+    /// Creates an event of the given kind, timestamped now.
+    pub fn new(kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            event_id: uuid::Uuid::new_v4().simple().to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            kind,
+            message: message.into(),
+            request_id: None,
+            details: Value::Null,
+        }
+    }
+
+    /// Attaches the id of the failing request.
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Attaches structured context.
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// Where a Sentry DSN says events should be sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentryDsn {
+    /// The project's store endpoint
+    pub store_url: String,
+    /// Public key used in the `X-Sentry-Auth` header
+    pub public_key: String,
+}
+
+impl SentryDsn {
+    /// Parses a DSN of the form `https://<key>@<host>[/<path>]/<project>`.
+    pub fn parse(dsn: &str) -> Option<Self> {
+        let (scheme, rest) = dsn.trim().split_once("://")?;
+        let (credentials, location) = rest.split_once('@')?;
+        let public_key = credentials.split(':').next()?.to_string();
+        let (prefix, project) = location.trim_end_matches('/').rsplit_once('/')?;
+        if public_key.is_empty() || project.is_empty() {
+            return None;
+        }
+        Some(Self {
+            store_url: format!("{}://{}/api/{}/store/", scheme, prefix, project),
+            public_key,
+        })
+    }
+}
+
+/// Where events are delivered.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReportingConfig {
+    /// Sentry project to send events to
+    pub sentry: Option<SentryDsn>,
+    /// Environment name attached to Sentry events
+    pub environment: String,
+    /// URL that receives every event as JSON
+    pub webhook_url: Option<String>,
+}
+
+impl ReportingConfig {
+    /// Reads `SENTRY_DSN`, `SENTRY_ENVIRONMENT` and `ERROR_WEBHOOK_URL`.
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("SENTRY_DSN").ok().as_deref(),
+            std::env::var("SENTRY_ENVIRONMENT").ok(),
+            std::env::var("ERROR_WEBHOOK_URL").ok(),
+        )
+    }
+
+    /// Builds a config from raw values; empty strings count as unset.
+    pub fn new(
+        sentry_dsn: Option<&str>,
+        environment: Option<String>,
+        webhook_url: Option<String>,
+    ) -> Self {
+        let sentry = sentry_dsn
+            .filter(|dsn| !dsn.trim().is_empty())
+            .and_then(|dsn| {
+                let parsed = SentryDsn::parse(dsn);
+                if parsed.is_none() {
+                    tracing::warn!("Ignoring invalid SENTRY_DSN");
+                }
+                parsed
+            });
+        Self {
+            sentry,
+            environment: environment
+                .filter(|e| !e.trim().is_empty())
+                .unwrap_or_else(|| "production".to_string()),
+            webhook_url: webhook_url.filter(|url| !url.trim().is_empty()),
+        }
+    }
+
+    /// Whether any sink is configured.
+    pub fn has_sinks(&self) -> bool {
+        self.sentry.is_some() || self.webhook_url.is_some()
+    }
+}
+
+/// Handle for reporting [`ErrorEvent`]s.
+///
+/// Cheap to clone; the default value is disabled and drops every event.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorReporter {
+    tx: Option<mpsc::UnboundedSender<ErrorEvent>>,
+    config: Arc<RwLock<ReportingConfig>>,
+}
+
+impl ErrorReporter {
+    /// Configures reporting from the environment and starts the delivery task.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn from_env(service: &'static str) -> Self {
+        Self::new(service, ReportingConfig::from_env())
+    }
+
+    /// Starts the delivery task with the given sinks.
+    ///
+    /// Must be called from within a Tokio runtime. The sinks can be changed
+    /// later with [`reconfigure`](Self::reconfigure).
+    pub fn new(service: &'static str, config: ReportingConfig) -> Self {
+        let config = Arc::new(RwLock::new(config));
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+        let (tx, mut rx) = mpsc::unbounded_channel::<ErrorEvent>();
+        let sinks = config.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let config = sinks.read().unwrap().clone();
+                if let Some(dsn) = &config.sentry {
+                    let auth = format!(
+                        "Sentry sentry_version=7, sentry_key={}, sentry_client={}/{}",
+                        dsn.public_key,
+                        service,
+                        env!("CARGO_PKG_VERSION")
+                    );
+                    let sent = client
+                        .post(&dsn.store_url)
+                        .header("X-Sentry-Auth", auth)
+                        .json(&sentry_payload(service, &config.environment, &event))
+                        .send()
+                        .await
+                        .and_then(|r| r.error_for_status());
+                    if let Err(e) = sent {
+                        tracing::warn!("Failed to send event to Sentry: {}", e);
+                    }
+                }
+                if let Some(url) = &config.webhook_url {
+                    let mut body = serde_json::to_value(&event).unwrap_or_default();
+                    body["service"] = json!(service);
+                    let sent = client
+                        .post(url)
+                        .json(&body)
+                        .send()
+                        .await
+                        .and_then(|r| r.error_for_status());
+                    if let Err(e) = sent {
+                        tracing::warn!("Failed to send event to error webhook: {}", e);
+                    }
+                }
+            }
+        });
+        Self {
+            tx: Some(tx),
+            config,
+        }
+    }
+
+    /// Replaces the sinks; events already queued go to the new sinks.
+    pub fn reconfigure(&self, config: ReportingConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Whether events are delivered anywhere.
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some() && self.config.read().unwrap().has_sinks()
+    }
+
+    /// Queues an event for delivery.
+    pub fn report(&self, event: ErrorEvent) {
+        if let Some(tx) = self.tx.as_ref().filter(|_| self.is_enabled()) {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// Reports panics before running the previously installed panic hook.
+    ///
+    /// Panics on the main thread may terminate the process before the event is
+    /// delivered; panics inside request handlers are reported reliably.
+    pub fn install_panic_hook(&self) {
+        if self.tx.is_none() {
+            return;
+        }
+        let reporter = self.clone();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".to_string());
+            let location = info
+                .location()
+                .map(|l| format!("{}:{}", l.file(), l.line()));
+            reporter.report(ErrorEvent::new("panic", message).with_details(json!({
+                "location": location,
+                "thread": std::thread::current().name(),
+            })));
+            previous(info);
+        }));
+    }
+}
+
+/// Builds a Sentry store API event.
+fn sentry_payload(service: &str, environment: &str, event: &ErrorEvent) -> Value {
+    json!({
+        "event_id": event.event_id,
+        "timestamp": event.timestamp,
+        "platform": "other",
+        "level": if event.kind == "panic" { "fatal" } else { "error" },
+        "logger": service,
+        "environment": environment,
+        "release": format!("{}@{}", service, env!("CARGO_PKG_VERSION")),
+        "message": { "formatted": event.message },
+        "tags": {
+            "service": service,
+            "kind": event.kind,
+            "request_id": event.request_id,
+        },
+        "extra": event.details,
+    })
+}
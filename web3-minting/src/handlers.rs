@@ -1,8 +1,39 @@
-use crate::models::{ErrorResponse, Metadata, MintRequest, MintResponse};
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use crate::blockchain::TransactionReverted;
+use crate::error_report::ErrorEvent;
+use crate::i18n::{Message, RequestLocale};
+use crate::models::{ErrorResponse, Metadata, MintRequest, MintResponse, UploadAssetQuery};
+use crate::storage::UploadTooLarge;
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
 
-pub async fn mint(Json(payload): Json<MintRequest>) -> impl IntoResponse {
+/// Header carrying the caller's request id, attached to reported errors.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Builds an error event, tagged with the caller's request id when present.
+fn error_event(headers: &HeaderMap, kind: &'static str, error: &anyhow::Error) -> ErrorEvent {
+    let event = ErrorEvent::new(kind, error.to_string());
+    match headers.get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()) {
+        Some(id) => event.with_request_id(id),
+        None => event,
+    }
+}
+
+pub async fn mint(
+    State(state): State<Arc<AppState>>,
+    RequestLocale(locale): RequestLocale,
+    headers: HeaderMap,
+    Json(payload): Json<MintRequest>,
+) -> impl IntoResponse {
     tracing::info!(request = ?payload, "/mint called");
+    let settings = state.settings.current();
+    let minters = state.minters.current();
 
     // Build metadata
     let metadata = Metadata {
@@ -12,30 +43,54 @@ pub async fn mint(Json(payload): Json<MintRequest>) -> impl IntoResponse {
     };
 
     // Upload metadata
-    let upload = match crate::storage::upload_metadata(&metadata).await {
-        Ok(u) => u,
-        Err(e) => {
-            tracing::error!(error = %e, "metadata upload failed");
-            let body = ErrorResponse {
-                error: format!("upload error: {}", e),
-            };
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
-        }
-    };
+    let upload =
+        match crate::storage::upload_metadata(&state.storage_client, &settings.storage, &metadata)
+            .await
+        {
+            Ok(u) => u,
+            Err(e) => {
+                tracing::error!(error = %e, "metadata upload failed");
+                state
+                    .reporter
+                    .report(error_event(&headers, "storage_failure", &e));
+                let body = ErrorResponse {
+                    error: Message::UploadFailed.format(locale, e),
+                };
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+            }
+        };
 
-    // Determine recipient
-    let recipient = payload
-        .recipient
-        .clone()
-        .unwrap_or_else(|| "default-recipient-address".to_string());
-
-    // Mint token
-    let mint = match crate::blockchain::mint_token(&upload.url, &recipient).await {
+    // Mint token, to the signer's own address without a recipient
+    let recipient = payload.recipient.as_deref();
+    let mint = match crate::blockchain::mint_token(
+        &state.rpc_client,
+        &settings.chain,
+        &minters,
+        payload.chain,
+        &payload.name,
+        &upload.url,
+        recipient,
+    )
+    .await
+    {
         Ok(m) => m,
         Err(e) => {
             tracing::error!(error = %e, "mint call failed");
+            let (kind, tx_hash) = match e.downcast_ref::<TransactionReverted>() {
+                Some(reverted) => ("transaction_reverted", Some(reverted.tx_hash.clone())),
+                None => ("mint_failure", None),
+            };
+            let details = serde_json::json!({
+                "chain": payload.chain,
+                "tx_hash": tx_hash,
+                "metadata_url": upload.url,
+                "recipient": recipient,
+            });
+            state
+                .reporter
+                .report(error_event(&headers, kind, &e).with_details(details));
             let body = ErrorResponse {
-                error: format!("mint error: {}", e),
+                error: Message::MintFailed.format(locale, e),
             };
             return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
         }
@@ -50,3 +105,84 @@ pub async fn mint(Json(payload): Json<MintRequest>) -> impl IntoResponse {
     tracing::info!(response = ?resp, "/mint completed");
     (StatusCode::OK, Json(resp)).into_response()
 }
+
+/// Upload a raw asset (image/audio) from the request body to storage.
+///
+/// The body is streamed straight to storage, so large audio files are never
+/// held in memory. Use the returned URL as `asset_url` in a `/mint` request.
+///
+/// Error messages follow the `?locale=` parameter or `Accept-Language` header.
+pub async fn upload_asset(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UploadAssetQuery>,
+    RequestLocale(locale): RequestLocale,
+    headers: HeaderMap,
+    body: Body,
+) -> impl IntoResponse {
+    let filename = query.filename.unwrap_or_else(|| "asset".to_string());
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    tracing::info!(filename = %filename, content_type = ?content_type, "/assets upload called");
+
+    let settings = state.settings.current();
+    let upload = crate::storage::upload_asset(
+        &state.storage_client,
+        &settings.storage,
+        body.into_data_stream(),
+        &filename,
+        content_type,
+    )
+    .await;
+    match upload {
+        Ok(upload) => {
+            tracing::info!(response = ?upload, "/assets upload completed");
+            (StatusCode::CREATED, Json(upload)).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "asset upload failed");
+            let status = if e.downcast_ref::<UploadTooLarge>().is_some() {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                state
+                    .reporter
+                    .report(error_event(&headers, "storage_failure", &e));
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            let body = ErrorResponse {
+                error: Message::UploadFailed.format(locale, e),
+            };
+            (status, Json(body)).into_response()
+        }
+    }
+}
+
+/// Serve an asset stored locally by [`upload_asset`], by its CID.
+///
+/// Only used without an IPFS endpoint, where upload URLs point here. The file
+/// is streamed, and cached for good since its name is its content hash.
+pub async fn get_asset(
+    State(state): State<Arc<AppState>>,
+    Path(cid): Path<String>,
+) -> impl IntoResponse {
+    let settings = state.settings.current();
+    let Some(path) = crate::storage::local_asset_path(&settings.storage, &cid) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return StatusCode::NOT_FOUND.into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, path = %path.display(), "cannot read asset");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let headers = [
+        (header::CONTENT_TYPE, "application/octet-stream"),
+        (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+    ];
+    let body = Body::from_stream(tokio_util::io::ReaderStream::new(file));
+    (headers, body).into_response()
+}
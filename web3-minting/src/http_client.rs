@@ -0,0 +1,238 @@
+//! Outbound HTTP client configuration.
+//!
+//! Storage and RPC calls each go through a pooled [`HttpClient`] whose
+//! timeouts, pooling, HTTP version and per-host connection cap are read from
+//! the environment, so a stalled RPC node fails fast instead of hanging mints.
+//!
+//! # Environment Variables
+//!
+//! All variables share a prefix (`STORAGE_HTTP` for IPFS/storage uploads,
+//! `RPC_HTTP` for blockchain RPC calls):
+//!
+//! * `<PREFIX>_CONNECT_TIMEOUT_MS` - TCP/TLS connect timeout
+//! * `<PREFIX>_TIMEOUT_MS` - Total request timeout (`0` disables it)
+//! * `<PREFIX>_READ_TIMEOUT_MS` - Max time between body reads (`0` disables it)
+//! * `<PREFIX>_POOL_IDLE_TIMEOUT_SECS` - How long idle connections are kept
+//! * `<PREFIX>_POOL_MAX_IDLE_PER_HOST` - Idle connections kept per host
+//! * `<PREFIX>_MAX_CONNECTIONS_PER_HOST` - Concurrent requests per host (`0` = unlimited)
+//! * `<PREFIX>_TCP_KEEPALIVE_SECS` - TCP keep-alive interval (`0` disables it)
+//! * `<PREFIX>_HTTP_VERSION` - `auto` (ALPN), `http1` or `http2` (prior knowledge)
+
+use reqwest::Client;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// HTTP protocol version preference for outbound connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Negotiate via ALPN, preferring HTTP/2 over TLS.
+    Auto,
+    /// Only use HTTP/1.1.
+    Http1,
+    /// Assume the server speaks HTTP/2 (prior knowledge).
+    Http2,
+}
+
+/// Tuning knobs for an outbound HTTP client.
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    /// Timeout for establishing a connection.
+    pub connect_timeout: Duration,
+    /// Total timeout for a request, including reading the body.
+    pub timeout: Option<Duration>,
+    /// Timeout between successive reads of the response.
+    pub read_timeout: Option<Duration>,
+    /// How long an idle pooled connection is kept open.
+    pub pool_idle_timeout: Duration,
+    /// Maximum idle connections kept per host.
+    pub pool_max_idle_per_host: usize,
+    /// Maximum concurrent requests per host; `None` means unlimited.
+    pub max_connections_per_host: Option<usize>,
+    /// TCP keep-alive interval.
+    pub tcp_keepalive: Option<Duration>,
+    /// Preferred HTTP version.
+    pub http_version: HttpVersion,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Some(Duration::from_secs(120)),
+            read_timeout: Some(Duration::from_secs(60)),
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 32,
+            max_connections_per_host: None,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            http_version: HttpVersion::Auto,
+        }
+    }
+}
+
+impl HttpClientConfig {
+    /// Reads `<prefix>_*` variables from the environment, falling back to `defaults`.
+    pub fn from_env(prefix: &str, defaults: Self) -> Self {
+        Self::from_lookup(prefix, defaults, |key| std::env::var(key).ok())
+    }
+
+    /// Like [`from_env`](Self::from_env), but reads values through `lookup`.
+    pub fn from_lookup(
+        prefix: &str,
+        defaults: Self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let number = |name: &str| -> Option<u64> {
+            let key = format!("{}_{}", prefix, name);
+            let value = lookup(&key)?;
+            match value.trim().parse() {
+                Ok(n) => Some(n),
+                Err(_) => {
+                    tracing::warn!("Ignoring invalid value for {}: {:?}", key, value);
+                    None
+                }
+            }
+        };
+        // `0` turns an optional setting off
+        let optional =
+            |name: &str, default: Option<Duration>, unit: fn(u64) -> Duration| match number(name) {
+                Some(0) => None,
+                Some(n) => Some(unit(n)),
+                None => default,
+            };
+
+        let http_version = match lookup(&format!("{}_HTTP_VERSION", prefix))
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("http1") | Some("1") | Some("1.1") => HttpVersion::Http1,
+            Some("http2") | Some("2") => HttpVersion::Http2,
+            Some("auto") => HttpVersion::Auto,
+            _ => defaults.http_version,
+        };
+
+        Self {
+            connect_timeout: number("CONNECT_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.connect_timeout),
+            timeout: optional("TIMEOUT_MS", defaults.timeout, Duration::from_millis),
+            read_timeout: optional(
+                "READ_TIMEOUT_MS",
+                defaults.read_timeout,
+                Duration::from_millis,
+            ),
+            pool_idle_timeout: number("POOL_IDLE_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.pool_idle_timeout),
+            pool_max_idle_per_host: number("POOL_MAX_IDLE_PER_HOST")
+                .map(|n| n as usize)
+                .unwrap_or(defaults.pool_max_idle_per_host),
+            max_connections_per_host: match number("MAX_CONNECTIONS_PER_HOST") {
+                Some(0) => None,
+                Some(n) => Some(n as usize),
+                None => defaults.max_connections_per_host,
+            },
+            tcp_keepalive: optional(
+                "TCP_KEEPALIVE_SECS",
+                defaults.tcp_keepalive,
+                Duration::from_secs,
+            ),
+            http_version,
+        }
+    }
+
+    /// Builds a client with these settings.
+    pub fn build(&self) -> reqwest::Result<HttpClient> {
+        let mut builder = Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(read_timeout) = self.read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+        builder = match self.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+
+        Ok(HttpClient {
+            client: builder.build()?,
+            host_limits: self
+                .max_connections_per_host
+                .map(|max| Arc::new(HostLimiter::new(max))),
+        })
+    }
+}
+
+/// A pooled `reqwest::Client` with an optional per-host concurrency cap.
+///
+/// Dereferences to [`reqwest::Client`]; callers that want the cap enforced
+/// hold the permit from [`acquire`](Self::acquire) until the response body
+/// has been read.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    host_limits: Option<Arc<HostLimiter>>,
+}
+
+impl HttpClient {
+    /// Waits for a connection slot to the host of `url`.
+    ///
+    /// Returns `None` when no per-host cap is configured.
+    pub async fn acquire(&self, url: &str) -> Option<OwnedSemaphorePermit> {
+        let limits = self.host_limits.as_ref()?;
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_default();
+        limits.semaphore(&host).acquire_owned().await.ok()
+    }
+}
+
+impl Deref for HttpClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl From<Client> for HttpClient {
+    /// Wraps an existing client without a per-host cap.
+    fn from(client: Client) -> Self {
+        Self {
+            client,
+            host_limits: None,
+        }
+    }
+}
+
+/// One semaphore per upstream host.
+struct HostLimiter {
+    max_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+    fn new(max_per_host: usize) -> Self {
+        Self {
+            max_per_host,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn semaphore(&self, host: &str) -> Arc<Semaphore> {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+            .clone()
+    }
+}
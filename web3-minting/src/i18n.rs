@@ -0,0 +1,159 @@
+//! Localized user-facing messages.
+//!
+//! Error strings returned by the HTTP handlers are looked up in a small
+//! compiled-in catalog keyed by [`Message`]. The client's locale is taken from
+//! the `?locale=` query parameter when present, otherwise negotiated from the
+//! `Accept-Language` header, and falls back to English.
+//!
+//! Supported locales: `en`, `es`, `fr`, `de`.
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, Uri},
+};
+use std::convert::Infallible;
+
+/// A locale with a translated message catalog.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    /// English (default)
+    #[default]
+    En,
+    /// Spanish
+    Es,
+    /// French
+    Fr,
+    /// German
+    De,
+}
+
+impl Locale {
+    /// Parses a language tag such as `es`, `fr-CA` or `de_DE`.
+    ///
+    /// Only the primary language subtag is considered. Returns `None` for
+    /// languages without a catalog.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?;
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "es" => Some(Locale::Es),
+            "fr" => Some(Locale::Fr),
+            "de" => Some(Locale::De),
+            _ => None,
+        }
+    }
+
+    /// The primary language subtag, e.g. `"es"`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+            Locale::Fr => "fr",
+            Locale::De => "de",
+        }
+    }
+
+    /// Picks the supported locale with the highest `q` weight from an
+    /// `Accept-Language` header value.
+    ///
+    /// ```
+    /// # use web3_minting::i18n::Locale;
+    /// let locale = Locale::from_accept_language("ja, fr-CH;q=0.9, de;q=0.7");
+    /// assert_eq!(locale, Some(Locale::Fr));
+    /// ```
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut best: Option<(Locale, f32)> = None;
+        for entry in header.split(',') {
+            let mut parts = entry.split(';');
+            let Some(locale) = parts.next().and_then(Locale::parse) else {
+                continue;
+            };
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            // Earlier entries win ties
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((locale, q));
+            }
+        }
+        best.map(|(locale, _)| locale)
+    }
+
+    /// Resolves the locale of an HTTP request from its `?locale=` query
+    /// parameter or `Accept-Language` header.
+    pub fn from_request(uri: &Uri, headers: &HeaderMap) -> Self {
+        let from_query = uri.query().and_then(|query| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.strip_prefix("locale="))
+                .find_map(Locale::parse)
+        });
+        from_query
+            .or_else(|| {
+                headers
+                    .get(header::ACCEPT_LANGUAGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(Locale::from_accept_language)
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Extractor for the locale negotiated by [`Locale::from_request`].
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLocale(pub Locale);
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestLocale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(RequestLocale(Locale::from_request(
+            &parts.uri,
+            &parts.headers,
+        )))
+    }
+}
+
+/// Keys into the message catalog.
+///
+/// Messages containing `{}` take one argument via [`Message::format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// Storing metadata or an asset failed; takes the underlying error
+    UploadFailed,
+    /// The mint transaction failed; takes the underlying error
+    MintFailed,
+    /// The request was shed under load
+    Overloaded,
+}
+
+impl Message {
+    /// The message text in `locale`.
+    pub fn text(self, locale: Locale) -> &'static str {
+        use Locale::*;
+        use Message::*;
+        match (self, locale) {
+            (UploadFailed, En) => "upload error: {}",
+            (UploadFailed, Es) => "error de subida: {}",
+            (UploadFailed, Fr) => "erreur de téléversement : {}",
+            (UploadFailed, De) => "Upload-Fehler: {}",
+
+            (MintFailed, En) => "mint error: {}",
+            (MintFailed, Es) => "error de acuñación: {}",
+            (MintFailed, Fr) => "erreur de frappe : {}",
+            (MintFailed, De) => "Mint-Fehler: {}",
+
+            (Overloaded, En) => "Server is overloaded, retry later",
+            (Overloaded, Es) => "El servidor está sobrecargado, inténtalo más tarde",
+            (Overloaded, Fr) => "Le serveur est surchargé, réessayez plus tard",
+            (Overloaded, De) => "Der Server ist überlastet, bitte später erneut versuchen",
+        }
+    }
+
+    /// The message text in `locale` with its `{}` placeholder replaced by `arg`.
+    pub fn format(self, locale: Locale, arg: impl std::fmt::Display) -> String {
+        self.text(locale).replacen("{}", &arg.to_string(), 1)
+    }
+}
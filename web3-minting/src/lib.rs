@@ -0,0 +1,73 @@
+//! Library half of the web3-minting service.
+//!
+//! `main.rs` wires these modules into the HTTP server; exposing them as a
+//! library lets the benchmarks under `benches/` drive them directly.
+
+pub mod blockchain;
+pub mod config;
+pub mod error_report;
+pub mod handlers;
+pub mod http_client;
+pub mod i18n;
+pub mod load_shed;
+pub mod models;
+pub mod solana;
+pub mod storage;
+
+use blockchain::SharedMinters;
+use config::{Settings, SharedSettings};
+use error_report::ErrorReporter;
+use http_client::{HttpClient, HttpClientConfig};
+use std::time::Duration;
+
+/// Application state shared across all request handlers.
+///
+/// Holds long-lived HTTP clients so storage uploads and RPC calls reuse pooled
+/// connections instead of opening a new one for every mint.
+#[derive(Clone)]
+pub struct AppState {
+    /// Client for IPFS/storage uploads. By default it has no overall request
+    /// timeout, because large assets can legitimately take minutes to stream;
+    /// a stalled transfer is caught by the read timeout instead.
+    pub storage_client: HttpClient,
+    /// Client for blockchain RPC calls.
+    pub rpc_client: HttpClient,
+    /// Forwards storage and mint failures to Sentry or an error webhook.
+    pub reporter: ErrorReporter,
+    /// Chain and storage endpoints, swapped on config reload.
+    pub settings: SharedSettings,
+    /// Minters built from the chain settings, swapped with them on reload.
+    pub minters: SharedMinters,
+}
+
+impl AppState {
+    /// Builds the shared clients from `STORAGE_HTTP_*` and `RPC_HTTP_*`
+    /// variables (see [`http_client`]), with defaults tuned for a handful of
+    /// long-lived upstream hosts.
+    ///
+    /// The remaining settings come from the environment alone (see [`config`]);
+    /// this starts the error-reporting task, so it must run inside the Tokio
+    /// runtime. Mints are mocked until [`config::Reloader::apply`] builds the
+    /// minters.
+    pub fn from_env() -> reqwest::Result<Self> {
+        let storage_defaults = HttpClientConfig {
+            timeout: None,
+            read_timeout: Some(Duration::from_secs(60)),
+            pool_max_idle_per_host: 16,
+            ..HttpClientConfig::default()
+        };
+        let rpc_defaults = HttpClientConfig {
+            connect_timeout: Duration::from_secs(5),
+            timeout: Some(Duration::from_secs(30)),
+            ..HttpClientConfig::default()
+        };
+        let settings = Settings::from_env();
+        Ok(Self {
+            storage_client: HttpClientConfig::from_env("STORAGE_HTTP", storage_defaults).build()?,
+            rpc_client: HttpClientConfig::from_env("RPC_HTTP", rpc_defaults).build()?,
+            reporter: ErrorReporter::new("web3-minting", settings.error_reporting.clone()),
+            settings: SharedSettings::new(settings),
+            minters: SharedMinters::default(),
+        })
+    }
+}
@@ -1,12 +1,15 @@
 use std::net::SocketAddr;
 
-mod blockchain;
-mod handlers;
-mod models;
-mod storage;
-
-use axum::{routing::post, Router};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use std::sync::Arc;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use web3_minting::config::{self, Reloader, Settings};
+use web3_minting::load_shed::{self, LoadShedder};
+use web3_minting::{handlers, AppState};
 
 #[tokio::main]
 async fn main() {
@@ -16,8 +19,52 @@ async fn main() {
         .with(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()))
         .init();
 
+    // Shared HTTP clients for storage and RPC calls
+    let state = Arc::new(AppState::from_env().expect("Failed to build HTTP clients"));
+
+    // Report panics when SENTRY_DSN or ERROR_WEBHOOK_URL is set
+    state.reporter.install_panic_hook();
+
+    // Shed low-priority (batch) mints when the service is saturated
+    let shedder = Arc::new(LoadShedder::new(state.settings.current().load_shed.clone()));
+
+    // Apply CONFIG_FILE now, and again on SIGHUP or POST /admin/reload
+    let reloader = Reloader::from_env(
+        state.settings.clone(),
+        state.minters.clone(),
+        shedder.clone(),
+        state.reporter.clone(),
+    );
+    let settings = Settings::load(reloader.path()).expect("Failed to load configuration");
+    reloader.apply(settings).expect("Invalid chain settings");
+    let minters = state.minters.current();
+    match &minters.evm {
+        Some(minter) => tracing::info!(signer = %minter.address(), "minting on-chain"),
+        None => tracing::warn!("BLOCKCHAIN_RPC not set - mints return mock results"),
+    }
+    match &minters.solana {
+        Some(minter) => tracing::info!(payer = %minter.address(), "minting on solana"),
+        None => tracing::warn!("SOLANA_RPC not set - Solana mints return mock results"),
+    }
+    let mut admin = Router::new();
+    if reloader.admin_enabled() {
+        admin = admin.route("/admin/reload", post(config::reload_handler));
+    }
+    let admin = admin.with_state(reloader.clone());
+    #[cfg(unix)]
+    reloader.spawn_sighup_listener();
+
     // Build our application with routes
-    let app = Router::new().route("/mint", post(handlers::mint));
+    let app = Router::new()
+        .route("/mint", post(handlers::mint))
+        .route("/assets", post(handlers::upload_asset))
+        .route("/assets/:cid", get(handlers::get_asset))
+        .with_state(state)
+        .merge(admin)
+        .layer(middleware::from_fn_with_state(
+            shedder,
+            load_shed::middleware,
+        ));
 
     // Run on 0.0.0.0:8081
     let addr = SocketAddr::from(([0, 0, 0, 0], 8081));
@@ -9,8 +9,29 @@ pub struct MintRequest {
     pub description: Option<String>,
     /// Link to uploaded asset (image/audio) on your storage (optional)
     pub asset_url: Option<String>,
-    /// Recipient address for token (optional; can be assigned server-side)
+    /// Recipient address for token (optional; defaults to the minting wallet)
     pub recipient: Option<String>,
+    /// Chain to mint on (default: `evm`)
+    #[serde(default)]
+    pub chain: Chain,
+}
+
+/// Chain a token is minted on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chain {
+    /// The ERC-721 contract at `CONTRACT_ADDRESS`, see [`crate::blockchain`]
+    #[default]
+    Evm,
+    /// A Metaplex NFT, see [`crate::solana`]
+    Solana,
+}
+
+/// Query parameters for `POST /assets`.
+#[derive(Debug, Deserialize)]
+pub struct UploadAssetQuery {
+    /// File name to record with the upload (defaults to "asset")
+    pub filename: Option<String>,
 }
 
 /// Internal metadata object that will be uploaded to storage (IPFS etc.)
@@ -31,10 +52,13 @@ pub struct UploadResult {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MintResult {
-    /// Blockchain transaction hash
+    /// Blockchain transaction hash, or signature on Solana
     pub tx_hash: String,
     /// Token ID minted (if available)
     pub token_id: Option<String>,
+    /// Address of the token's mint account, on Solana
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_address: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
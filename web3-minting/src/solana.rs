@@ -0,0 +1,252 @@
+//! Minting Metaplex NFTs on Solana.
+//!
+//! Mints sent with `"chain": "solana"` create a new mint account with 0
+//! decimals, mint its single token to the recipient's associated token
+//! account, and create its Token Metadata account, pointing at the metadata
+//! URL, and master edition, which caps the supply at one. It all happens in
+//! one transaction, paid for and signed by the keypair in `SOLANA_KEYPAIR` and
+//! by the new mint account, and awaited until confirmed. The response carries
+//! the transaction's signature as `tx_hash` and the mint account's address as
+//! `mint_address` and `token_id`. Mints without a `recipient` go to the payer.
+//!
+//! Without `SOLANA_RPC`, Solana mints return random signatures and addresses,
+//! for local development.
+//!
+//! # Environment Variables
+//!
+//! * `SOLANA_RPC` - Optional. JSON-RPC endpoint of the cluster, such as
+//!   `https://api.devnet.solana.com`
+//! * `SOLANA_KEYPAIR` - Required with `SOLANA_RPC`. Keypair file of the payer,
+//!   as written by `solana-keygen new`; read at startup and on reload
+
+use crate::config::ChainSettings;
+use crate::http_client::HttpClient;
+use crate::models::MintResult;
+use anyhow::{anyhow, Result};
+use mpl_token_metadata::accounts::{MasterEdition, Metadata};
+use mpl_token_metadata::instructions::{
+    CreateMasterEditionV3Builder, CreateMetadataAccountV3Builder,
+};
+use mpl_token_metadata::types::DataV2;
+use mpl_token_metadata::{MAX_NAME_LENGTH, MAX_URI_LENGTH};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use solana_system_interface::instruction as system_instruction;
+use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::instruction::create_associated_token_account;
+use spl_token::state::Mint;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a Solana mint needs: the endpoint and the payer.
+#[derive(Debug, Clone)]
+pub struct SolanaMinter {
+    rpc_url: String,
+    payer: Arc<Keypair>,
+}
+
+impl SolanaMinter {
+    /// Loads the payer's keypair; `None` when no Solana RPC is configured, so
+    /// Solana mints are mocked.
+    ///
+    /// Fails when the RPC is configured without a readable keypair file.
+    pub fn from_settings(settings: &ChainSettings) -> Result<Option<Self>> {
+        let Some(rpc_url) = &settings.solana_rpc_url else {
+            return Ok(None);
+        };
+        let path = settings
+            .solana_keypair
+            .as_deref()
+            .ok_or_else(|| anyhow!("SOLANA_KEYPAIR is required with SOLANA_RPC"))?;
+        let payer = read_keypair_file(path)
+            .map_err(|e| anyhow!("cannot read SOLANA_KEYPAIR {}: {}", path.display(), e))?;
+        Ok(Some(Self {
+            rpc_url: rpc_url.clone(),
+            payer: Arc::new(payer),
+        }))
+    }
+
+    /// Address that pays for the mints.
+    pub fn address(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
+    /// Mints an NFT named `name`, cut to what Token Metadata accepts, with
+    /// `uri` to `recipient`, or to the payer.
+    pub async fn mint(
+        &self,
+        client: &HttpClient,
+        name: &str,
+        uri: &str,
+        recipient: Option<&str>,
+    ) -> Result<MintResult> {
+        if uri.len() > MAX_URI_LENGTH {
+            return Err(anyhow!(
+                "metadata URL is limited to {} bytes on Solana, got {}",
+                MAX_URI_LENGTH,
+                uri.len()
+            ));
+        }
+        let payer = self.address();
+        let owner = match recipient {
+            Some(recipient) => recipient
+                .parse()
+                .map_err(|e| anyhow!("recipient {} is not a Solana address: {}", recipient, e))?,
+            None => payer,
+        };
+        let mint = Keypair::new();
+        let mint_address = mint.pubkey();
+        let (metadata, _) = Metadata::find_pda(&mint_address);
+        let (edition, _) = MasterEdition::find_pda(&mint_address);
+        let token_account = get_associated_token_address(&owner, &mint_address);
+
+        let rpc =
+            RpcClient::new_with_commitment(self.rpc_url.clone(), CommitmentConfig::confirmed());
+        // The connection slot is only held while sending, not for the wait
+        // for confirmation
+        let permit = client.acquire(&self.rpc_url).await;
+        let rent = rpc
+            .get_minimum_balance_for_rent_exemption(Mint::LEN)
+            .await
+            .map_err(|e| anyhow!("rpc request failed: {}", e))?;
+        let data = DataV2 {
+            name: truncate(name, MAX_NAME_LENGTH).to_string(),
+            symbol: String::new(),
+            uri: uri.to_string(),
+            seller_fee_basis_points: 0,
+            creators: None,
+            collection: None,
+            uses: None,
+        };
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &mint_address,
+                rent,
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::id(),
+                &mint_address,
+                &payer,
+                Some(&payer),
+                0,
+            )?,
+            create_associated_token_account(&payer, &owner, &mint_address, &spl_token::id()),
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint_address,
+                &token_account,
+                &payer,
+                &[],
+                1,
+            )?,
+            CreateMetadataAccountV3Builder::new()
+                .metadata(metadata)
+                .mint(mint_address)
+                .mint_authority(payer)
+                .payer(payer)
+                .update_authority(payer, true)
+                .data(data)
+                .is_mutable(true)
+                .instruction(),
+            CreateMasterEditionV3Builder::new()
+                .edition(edition)
+                .mint(mint_address)
+                .update_authority(payer)
+                .mint_authority(payer)
+                .payer(payer)
+                .metadata(metadata)
+                .max_supply(0)
+                .instruction(),
+        ];
+
+        let blockhash = rpc
+            .get_latest_blockhash()
+            .await
+            .map_err(|e| anyhow!("rpc request failed: {}", e))?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer),
+            &[self.payer.as_ref(), &mint],
+            blockhash,
+        );
+        let signature = rpc
+            .send_transaction(&transaction)
+            .await
+            .map_err(|e| anyhow!("mint transaction failed: {}", e))?;
+        drop(permit);
+        loop {
+            let status = rpc
+                .get_signature_status(&signature)
+                .await
+                .map_err(|e| anyhow!("rpc request failed: {}", e))?;
+            match status {
+                Some(Ok(())) => break,
+                Some(Err(e)) => {
+                    return Err(anyhow!("mint transaction {} failed: {}", signature, e))
+                }
+                None => {}
+            }
+            let live = rpc
+                .is_blockhash_valid(&blockhash, CommitmentConfig::processed())
+                .await
+                .map_err(|e| anyhow!("rpc request failed: {}", e))?;
+            if !live {
+                return Err(anyhow!(
+                    "mint transaction {} expired unconfirmed",
+                    signature
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        tracing::info!(signature = %signature, mint = %mint_address, to = %owner, "solana mint confirmed");
+
+        Ok(MintResult {
+            tx_hash: signature.to_string(),
+            token_id: Some(mint_address.to_string()),
+            mint_address: Some(mint_address.to_string()),
+        })
+    }
+}
+
+/// The longest prefix of `text` of at most `max` bytes.
+fn truncate(text: &str, max: usize) -> &str {
+    let mut end = text.len().min(max);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpc_requires_keypair_and_names_are_cut_to_fit() {
+        let mut settings = ChainSettings::default();
+        assert!(SolanaMinter::from_settings(&settings).unwrap().is_none());
+
+        settings.solana_rpc_url = Some("http://127.0.0.1:8899".to_string());
+        assert!(SolanaMinter::from_settings(&settings).is_err());
+        settings.solana_keypair = Some("does/not/exist.json".into());
+        assert!(SolanaMinter::from_settings(&settings).is_err());
+
+        let payer = Keypair::new();
+        let path = std::env::temp_dir().join(format!("payer-{}.json", payer.pubkey()));
+        solana_sdk::signature::write_keypair_file(&payer, &path).unwrap();
+        settings.solana_keypair = Some(path.clone());
+        let minter = SolanaMinter::from_settings(&settings).unwrap().unwrap();
+        assert_eq!(minter.address(), payer.pubkey());
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(truncate("Voice note", 32), "Voice note");
+        assert_eq!(truncate("ééé", 5), "éé");
+    }
+}
@@ -1,18 +1,38 @@
+use crate::config::StorageSettings;
+use crate::http_client::HttpClient;
 use crate::models::{Metadata, UploadResult};
 use anyhow::{anyhow, Result};
-use reqwest::Client;
-use std::env;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, BufWriter};
 use uuid::Uuid;
 
+/// Returned when a streamed upload exceeds `MAX_UPLOAD_BYTES`.
+#[derive(Debug, thiserror::Error)]
+#[error("upload exceeds the {limit} byte limit")]
+pub struct UploadTooLarge {
+    pub limit: u64,
+}
+
 /// Upload metadata to storage (IPFS or mock). Returns CID and a gateway URL.
-pub async fn upload_metadata(metadata: &Metadata) -> Result<UploadResult> {
-    // If IPFS_URL is set, attempt to POST the JSON there. Otherwise return a mock CID.
-    if let Ok(ipfs_url) = env::var("IPFS_URL") {
+///
+/// Without IPFS the mock CID is that of the metadata's JSON, so uploading the
+/// same metadata twice gives the same CID, as a real node would.
+pub async fn upload_metadata(
+    client: &HttpClient,
+    settings: &StorageSettings,
+    metadata: &Metadata,
+) -> Result<UploadResult> {
+    // If an IPFS endpoint is configured, attempt to POST the JSON there. Otherwise return a mock CID.
+    if let Some(ipfs_url) = &settings.ipfs_url {
         tracing::info!(ipfs_url = %ipfs_url, "using configured IPFS endpoint");
-        let client = Client::new();
         // We post the metadata as JSON and expect the remote to return some JSON containing a cid/hash.
+        let _permit = client.acquire(ipfs_url).await;
         let resp = client
-            .post(&ipfs_url)
+            .post(ipfs_url)
             .json(metadata)
             .send()
             .await
@@ -40,14 +60,194 @@ pub async fn upload_metadata(metadata: &Metadata) -> Result<UploadResult> {
         tracing::info!(cid = %cid, url = %url, "ipfs upload result");
         Ok(UploadResult { cid, url })
     } else {
-        // Mock path: deterministic-ish CID and gateway URL for local dev and testing.
-        let cid = format!("bafy{}", Uuid::new_v4().simple());
+        // Mock path: content-addressed CID and gateway URL for local dev and testing.
+        let bytes = serde_json::to_vec(metadata)?;
+        let cid = compute_cid(&bytes);
         let url = format!("https://ipfs.io/ipfs/{}", cid);
-        tracing::warn!(cid = %cid, "IPFS_URL not set - returning mock upload result");
+        tracing::warn!(cid = %cid, "no IPFS endpoint configured - returning mock upload result");
         Ok(UploadResult { cid, url })
     }
 }
 
+/// Upload an asset (image/audio) to storage, streaming it from `body`.
+///
+/// The asset is never buffered in full: with an IPFS endpoint configured it is
+/// forwarded to the node as a streamed multipart upload, otherwise it is
+/// written to the asset directory chunk by chunk while its CID is computed.
+/// Uploads larger than [`StorageSettings::max_upload_bytes`] fail with
+/// [`UploadTooLarge`].
+pub async fn upload_asset<S, E>(
+    client: &HttpClient,
+    settings: &StorageSettings,
+    body: S,
+    filename: &str,
+    content_type: Option<&str>,
+) -> Result<UploadResult>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let limit = settings.max_upload_bytes;
+
+    if let Some(ipfs_url) = &settings.ipfs_url {
+        tracing::info!(ipfs_url = %ipfs_url, filename, "streaming asset to IPFS");
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let exceeded_flag = exceeded.clone();
+        let mut total: u64 = 0;
+        let limited = body.map(move |chunk| {
+            let chunk = chunk.map_err(std::io::Error::other)?;
+            total += chunk.len() as u64;
+            if total > limit {
+                exceeded_flag.store(true, Ordering::Relaxed);
+                return Err(std::io::Error::other(UploadTooLarge { limit }));
+            }
+            Ok(chunk)
+        });
+
+        let mut part = reqwest::multipart::Part::stream(reqwest::Body::wrap_stream(limited))
+            .file_name(filename.to_string());
+        if let Some(content_type) = content_type {
+            part = part.mime_str(content_type)?;
+        }
+        let form = reqwest::multipart::Form::new().part("file", part);
+
+        let _permit = client.acquire(ipfs_url).await;
+        let resp = match client.post(ipfs_url).multipart(form).send().await {
+            Ok(resp) => resp,
+            Err(_) if exceeded.load(Ordering::Relaxed) => {
+                return Err(UploadTooLarge { limit }.into())
+            }
+            Err(e) => return Err(anyhow!("ipfs request failed: {}", e)),
+        };
+
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("ipfs upload failed: {} - {}", status, text));
+        }
+
+        let json: serde_json::Value = resp
+            .json()
+            .await
+            .map_err(|e| anyhow!("failed to parse response: {}", e))?;
+        let cid = json
+            .get("cid")
+            .or_else(|| json.get("Hash"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| anyhow!("ipfs response did not contain a CID"))?;
+
+        let url = format!("https://ipfs.io/ipfs/{}", cid);
+        tracing::info!(cid = %cid, url = %url, "ipfs asset upload result");
+        Ok(UploadResult { cid, url })
+    } else {
+        let dir = &settings.asset_dir;
+        tokio::fs::create_dir_all(dir).await?;
+        let chunk_size = settings.upload_chunk_size;
+
+        // Write under a temporary name; the final name is the CID, known only at the end.
+        let tmp_path = dir.join(format!(".upload-{}", Uuid::new_v4().simple()));
+        let result = write_hashed(body, &tmp_path, chunk_size, limit).await;
+        let digest = match result {
+            Ok(digest) => digest,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return Err(e);
+            }
+        };
+
+        let cid = cid_from_digest(&digest);
+        tokio::fs::rename(&tmp_path, dir.join(&cid)).await?;
+        let url = local_asset_url(settings, &cid);
+        tracing::warn!(cid = %cid, dir = %dir.display(), "no IPFS endpoint configured - stored asset locally");
+        Ok(UploadResult { cid, url })
+    }
+}
+
+/// URL of a locally stored asset, served by `GET /assets/{cid}`.
+pub fn local_asset_url(settings: &StorageSettings, cid: &str) -> String {
+    format!(
+        "{}/assets/{}",
+        settings.public_url.trim_end_matches('/'),
+        cid
+    )
+}
+
+/// Path of the locally stored asset `cid`; `None` unless `cid` looks like a
+/// CID this service writes, so requests can't reach other files.
+pub fn local_asset_path(settings: &StorageSettings, cid: &str) -> Option<std::path::PathBuf> {
+    let base32 = |c: char| c.is_ascii_lowercase() || ('2'..='7').contains(&c);
+    let valid = cid.len() > 1 && cid.starts_with('b') && cid.chars().all(base32);
+    valid.then(|| settings.asset_dir.join(cid))
+}
+
+/// Stream `body` into a file at `path`, returning the sha2-256 digest of its contents.
+async fn write_hashed<S, E>(
+    body: S,
+    path: &std::path::Path,
+    chunk_size: usize,
+    limit: u64,
+) -> Result<[u8; 32]>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let file = tokio::fs::File::create(path).await?;
+    let mut writer = BufWriter::with_capacity(chunk_size, file);
+    let mut hasher = Sha256::new();
+    let mut total: u64 = 0;
+
+    let mut body = Box::pin(body);
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        total += chunk.len() as u64;
+        if total > limit {
+            return Err(UploadTooLarge { limit }.into());
+        }
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await?;
+    }
+    writer.flush().await?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Compute the CIDv1 (raw codec, sha2-256) of `bytes`, base32-encoded.
+///
+/// This is the same identifier an IPFS node returns for a single-block upload
+/// with raw leaves, so mock uploads get stable, realistic CIDs.
+pub fn compute_cid(bytes: &[u8]) -> String {
+    cid_from_digest(&Sha256::digest(bytes).into())
+}
+
+/// Build a CIDv1 (raw codec) string from a sha2-256 digest.
+fn cid_from_digest(digest: &[u8; 32]) -> String {
+    // <cid-version=1><multicodec=raw><multihash=sha2-256><digest length=32><digest>
+    let mut cid = vec![0x01, 0x55, 0x12, 0x20];
+    cid.extend_from_slice(digest);
+    format!("b{}", base32_lower(&cid))
+}
+
+/// RFC 4648 base32, lowercase and unpadded (the multibase `b` alphabet).
+fn base32_lower(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -59,8 +259,81 @@ mod tests {
             description: Some("desc".to_string()),
             asset_url: Some("https://example.com/a.png".to_string()),
         };
-        let r = upload_metadata(&m).await.expect("upload should succeed");
+        let r = upload_metadata(
+            &reqwest::Client::new().into(),
+            &StorageSettings::default(),
+            &m,
+        )
+        .await
+        .expect("upload should succeed");
         assert!(r.cid.starts_with("bafy") || !r.cid.is_empty());
         assert!(r.url.contains(&r.cid));
     }
+
+    #[tokio::test]
+    async fn test_mock_metadata_cids_are_content_addressed() {
+        let client: HttpClient = reqwest::Client::new().into();
+        let settings = StorageSettings::default();
+        let m = |name: &str| Metadata {
+            name: name.to_string(),
+            description: None,
+            asset_url: None,
+        };
+        let first = upload_metadata(&client, &settings, &m("Punk"))
+            .await
+            .unwrap();
+        let again = upload_metadata(&client, &settings, &m("Punk"))
+            .await
+            .unwrap();
+        let other = upload_metadata(&client, &settings, &m("Ape"))
+            .await
+            .unwrap();
+
+        assert_eq!(first.cid, again.cid);
+        assert_ne!(first.cid, other.cid);
+        assert!(first.cid.starts_with("bafkrei"), "{}", first.cid);
+        assert_eq!(
+            first.cid,
+            compute_cid(&serde_json::to_vec(&m("Punk")).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_assets_are_linked_under_the_public_url() {
+        let settings = StorageSettings {
+            asset_dir: std::env::temp_dir().join(format!("assets-{}", Uuid::new_v4().simple())),
+            public_url: "https://mint.example.com/".to_string(),
+            ..StorageSettings::default()
+        };
+        let body = futures_util::stream::iter([Ok::<_, std::io::Error>(Bytes::from("hello"))]);
+        let r = upload_asset(
+            &reqwest::Client::new().into(),
+            &settings,
+            body,
+            "a.txt",
+            None,
+        )
+        .await
+        .expect("upload should succeed");
+        assert_eq!(
+            r.url,
+            format!("https://mint.example.com/assets/{}", compute_cid(b"hello"))
+        );
+
+        let path = local_asset_path(&settings, &r.cid).unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"hello");
+        for name in ["../secrets", "b..", ".upload-1", "bafy/x", "BAFY", ""] {
+            assert!(local_asset_path(&settings, name).is_none(), "{}", name);
+        }
+        std::fs::remove_dir_all(&settings.asset_dir).unwrap();
+    }
+
+    #[test]
+    fn test_compute_cid_matches_ipfs() {
+        // `echo -n hello | ipfs add --cid-version 1 --raw-leaves -n -q`
+        assert_eq!(
+            compute_cid(b"hello"),
+            "bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq"
+        );
+    }
 }